@@ -31,6 +31,12 @@ fn load_plugins_metadata() -> Vec<PluginMetadata> {
         plugin_relay::client::RelayClientPlugin,
         plugin_relay::server::RelayServerPlugin,
         plugin_opentelemetry::OpenTelemetryPlugin,
+        plugin_opentelemetry_input::OpenTelemetryInputPlugin,
+        plugin_parquet::ParquetPlugin,
+        plugin_sqlite::SqlitePlugin,
+        plugin_replay::ReplayPlugin,
+        plugin_jsonl::JsonLinesPlugin,
+        plugin_mqtt::MqttPlugin,
         plugin_aggregation::AggregationPlugin,
         plugin_energy_attribution::EnergyAttributionPlugin,
         plugin_energy_estimation_tdp::EnergyEstimationTdpPlugin,
@@ -41,16 +47,26 @@ fn load_plugins_metadata() -> Vec<PluginMetadata> {
         plugin_energy_to_carbon::EnergyToCarbonPlugin,
     ];
 
+    // plugins that are behind a cargo feature
+    #[cfg(feature = "kafka")]
+    plugins.extend(static_plugins![plugin_kafka::KafkaPlugin]);
+
     // plugins that only work on Linux
     #[cfg(target_os = "linux")]
     {
         plugins.extend(static_plugins![
             plugin_socket_control::SocketControlPlugin,
+            plugin_containers::ContainersPlugin,
             plugin_k8s::K8sPlugin,
             plugin_slurm::SlurmPlugin,
             plugin_oar::OarPlugin,
+            plugin_pbs::PbsPlugin,
+            plugin_htcondor::HtcondorPlugin,
             plugin_raw_cgroups::RawCgroupPlugin,
+            plugin_systemd::SystemdPlugin,
+            plugin_cpufreq::CpufreqPlugin,
             plugin_grace_hopper::GraceHopperPlugin,
+            plugin_hwmon::HwmonPlugin,
             plugin_rapl::RaplPlugin,
             plugin_perf::PerfPlugin,
             plugin_procfs::ProcfsPlugin,
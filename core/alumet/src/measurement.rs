@@ -413,6 +413,38 @@ impl From<&'static str> for AttributeValue {
     }
 }
 
+/// Serializes the value with its type: numbers, booleans and strings stay as they are, lists become sequences.
+#[cfg(feature = "serde")]
+impl serde::Serialize for AttributeValue {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            AttributeValue::F64(x) => serializer.serialize_f64(*x),
+            AttributeValue::U64(x) => serializer.serialize_u64(*x),
+            AttributeValue::Bool(x) => serializer.serialize_bool(*x),
+            AttributeValue::Str(str) => serializer.serialize_str(str),
+            AttributeValue::String(str) => serializer.serialize_str(str),
+            AttributeValue::ListU64(items) => serializer.collect_seq(items),
+        }
+    }
+}
+
+/// Converts the value to JSON, like its `Serialize` implementation does.
+///
+/// A non-finite `F64` becomes `null`.
+#[cfg(feature = "serde")]
+impl From<&AttributeValue> for serde_json::Value {
+    fn from(value: &AttributeValue) -> Self {
+        match value {
+            AttributeValue::F64(x) => serde_json::Value::from(*x),
+            AttributeValue::U64(x) => serde_json::Value::from(*x),
+            AttributeValue::Bool(x) => serde_json::Value::from(*x),
+            AttributeValue::Str(str) => serde_json::Value::from(*str),
+            AttributeValue::String(str) => serde_json::Value::from(str.as_str()),
+            AttributeValue::ListU64(items) => serde_json::Value::from(items.clone()),
+        }
+    }
+}
+
 /// A `MeasurementBuffer` stores measured data points.
 /// Unlike a [`MeasurementAccumulator`], the buffer allows to modify the measurements.
 #[derive(Clone, Debug)]
@@ -563,6 +595,28 @@ impl<'a> MeasurementAccumulator<'a> {
 mod tests {
     use super::*;
 
+    #[cfg(feature = "serde")]
+    mod attribute_value_json {
+        use super::*;
+
+        #[test]
+        fn to_json() {
+            let values = [
+                (AttributeValue::F64(0.5), serde_json::json!(0.5)),
+                (AttributeValue::F64(f64::NAN), serde_json::json!(null)),
+                (AttributeValue::U64(u64::MAX), serde_json::json!(u64::MAX)),
+                (AttributeValue::Bool(true), serde_json::json!(true)),
+                (AttributeValue::Str("a"), serde_json::json!("a")),
+                (AttributeValue::String(String::from("b")), serde_json::json!("b")),
+                (AttributeValue::ListU64(vec![1, 2]), serde_json::json!([1, 2])),
+            ];
+            for (value, expected) in values {
+                assert_eq!(serde_json::Value::from(&value), expected);
+                assert_eq!(serde_json::to_value(&value).unwrap(), expected);
+            }
+        }
+    }
+
     mod wrapped_measurement_value {
         use super::*;
 
@@ -54,6 +54,8 @@ pub enum Resource {
     Dram { pkg_id: u32 },
     /// A dedicated GPU.
     Gpu { bus_id: StrCow },
+    /// A block device, like a disk or a partition, identified by its kernel name (e.g. `sda` or `nvme0n1p1`).
+    BlockDevice { name: StrCow },
     /// A custom resource.
     Custom { kind: StrCow, id: StrCow },
 }
@@ -99,6 +101,7 @@ impl Resource {
             Resource::CpuCore { .. } => "cpu_core",
             Resource::Dram { .. } => "dram",
             Resource::Gpu { .. } => "gpu",
+            Resource::BlockDevice { .. } => "block_device",
             Resource::Custom { kind, id: _ } => kind,
         }
     }
@@ -117,6 +120,7 @@ impl Resource {
             Resource::CpuCore { id } => LazyDisplayable::U32(*id),
             Resource::Dram { pkg_id } => LazyDisplayable::U32(*pkg_id),
             Resource::Gpu { bus_id } => LazyDisplayable::Str(bus_id),
+            Resource::BlockDevice { name } => LazyDisplayable::Str(name),
             Resource::Custom { kind: _, id } => LazyDisplayable::Str(id),
         }
     }
@@ -148,6 +152,7 @@ impl Resource {
                     Ok(Resource::Dram { pkg_id })
                 }
                 "gpu" => Ok(Resource::Gpu { bus_id: id }),
+                "block_device" => Ok(Resource::BlockDevice { name: id }),
                 _ => Ok(Resource::Custom { kind, id }),
             },
             r => Ok(r),
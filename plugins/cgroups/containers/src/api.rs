@@ -0,0 +1,341 @@
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    time::Duration,
+};
+
+use anyhow::{Context, anyhow};
+
+/// A client that asks a container runtime for informations about its containers.
+pub trait RuntimeClient: Send + Sync {
+    /// Returns `None` if the container does not exist.
+    fn inspect_container(&self, id: &str) -> anyhow::Result<Option<ContainerInfos>>;
+}
+
+/// Client of the Docker Engine API, over a Unix socket (limited capabilities, just what we need).
+///
+/// Podman exposes a compatible API, which is also supported.
+#[derive(Debug, Clone)]
+pub struct EngineClient {
+    socket: PathBuf,
+    timeout: Duration,
+}
+
+/// Relevant informations about a container.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ContainerInfos {
+    pub name: String,
+    pub image: String,
+    pub labels: BTreeMap<String, String>,
+}
+
+/// Labels that contain the name of the Compose project, in order of preference.
+const COMPOSE_PROJECT_LABELS: [&str; 2] = ["com.docker.compose.project", "io.podman.compose.project"];
+
+/// Encoding/decoding of the Engine API responses.
+/// Fields that we don't need are not included, serde will skip them.
+mod model {
+    use std::collections::BTreeMap;
+
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct ContainerInspect {
+        pub name: String,
+        pub config: ContainerConfig,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct ContainerConfig {
+        pub image: String,
+        pub labels: Option<BTreeMap<String, String>>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct ErrorResponse {
+        pub message: String,
+    }
+}
+
+impl From<model::ContainerInspect> for ContainerInfos {
+    fn from(container: model::ContainerInspect) -> Self {
+        ContainerInfos {
+            // the API returns the name with a leading slash
+            name: container.name.trim_start_matches('/').to_owned(),
+            image: container.config.image,
+            labels: container.config.labels.unwrap_or_default(),
+        }
+    }
+}
+
+impl ContainerInfos {
+    /// Returns the name of the Compose project that started the container, if any.
+    pub fn compose_project(&self) -> Option<&str> {
+        COMPOSE_PROJECT_LABELS
+            .iter()
+            .find_map(|label| self.labels.get(*label))
+            .map(String::as_str)
+    }
+}
+
+impl EngineClient {
+    pub fn new(socket: impl Into<PathBuf>, timeout: Duration) -> Self {
+        Self {
+            socket: socket.into(),
+            timeout,
+        }
+    }
+
+    /// Asks the runtime for informations about a container.
+    ///
+    /// Returns `None` if the container does not exist.
+    pub fn inspect_container(&self, id: &str) -> anyhow::Result<Option<ContainerInfos>> {
+        let (status, body) = self.get(&format!("/containers/{id}/json"))?;
+        match status {
+            200 => {
+                let container: model::ContainerInspect =
+                    serde_json::from_slice(&body).context("failed to parse json response")?;
+                Ok(Some(container.into()))
+            }
+            404 => Ok(None),
+            _ => {
+                let message = serde_json::from_slice::<model::ErrorResponse>(&body)
+                    .map(|e| e.message)
+                    .unwrap_or_else(|_| String::from_utf8_lossy(&body).into_owned());
+                Err(anyhow!("unexpected status {status}: {message}"))
+            }
+        }
+    }
+
+    /// Sends a `GET` request and returns the status code and the body of the response.
+    ///
+    /// We use HTTP/1.0 so that the server closes the connection after the response,
+    /// which allows to read the response until EOF.
+    fn get(&self, route: &str) -> anyhow::Result<(u16, Vec<u8>)> {
+        let mut stream = UnixStream::connect(&self.socket)
+            .with_context(|| format!("failed to connect to {}", self.socket.display()))?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+
+        let request = format!("GET {route} HTTP/1.0\r\nHost: localhost\r\nAccept: application/json\r\n\r\n");
+        stream
+            .write_all(request.as_bytes())
+            .context("failed to send http request")?;
+
+        let mut response = Vec::new();
+        stream
+            .read_to_end(&mut response)
+            .context("failed to read http response")?;
+        log::trace!("response: {}", String::from_utf8_lossy(&response));
+        parse_http_response(&response)
+    }
+}
+
+impl RuntimeClient for EngineClient {
+    fn inspect_container(&self, id: &str) -> anyhow::Result<Option<ContainerInfos>> {
+        EngineClient::inspect_container(self, id)
+    }
+}
+
+impl RuntimeClient for crate::containerd::ContainerdClient {
+    fn inspect_container(&self, id: &str) -> anyhow::Result<Option<ContainerInfos>> {
+        crate::containerd::ContainerdClient::inspect_container(self, id)
+    }
+}
+
+/// Parses a raw HTTP response into a status code and a body.
+fn parse_http_response(response: &[u8]) -> anyhow::Result<(u16, Vec<u8>)> {
+    let header_end = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| anyhow!("incomplete http response"))?;
+    let head = std::str::from_utf8(&response[..header_end]).context("invalid http headers")?;
+    let body = &response[header_end + 4..];
+
+    let mut lines = head.split("\r\n");
+    let status_line = lines.next().unwrap_or_default();
+    let status: u16 = status_line
+        .split_ascii_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| anyhow!("invalid http status line: {status_line}"))?;
+
+    let chunked = lines.any(|line| {
+        line.split_once(':').is_some_and(|(name, value)| {
+            name.trim().eq_ignore_ascii_case("transfer-encoding") && value.trim().eq_ignore_ascii_case("chunked")
+        })
+    });
+    let body = if chunked { decode_chunked(body)? } else { body.to_vec() };
+    Ok((status, body))
+}
+
+/// Decodes a body that uses the "chunked" transfer encoding.
+fn decode_chunked(mut body: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut res = Vec::new();
+    loop {
+        let line_end = body
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or_else(|| anyhow!("invalid chunk"))?;
+        let size = std::str::from_utf8(&body[..line_end])?;
+        // ignore the chunk extensions
+        let size = size.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16).with_context(|| format!("invalid chunk size {size:?}"))?;
+        if size == 0 {
+            return Ok(res);
+        }
+        let chunk = body
+            .get(line_end + 2..line_end + 2 + size)
+            .ok_or_else(|| anyhow!("truncated chunk"))?;
+        res.extend_from_slice(chunk);
+        body = body.get(line_end + 2 + size + 2..).unwrap_or_default();
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        os::unix::net::UnixListener,
+        thread::JoinHandle,
+    };
+
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// Starts a fake container runtime that serves the given responses, in order, one per connection.
+    ///
+    /// Returns the requested routes when all the responses have been sent.
+    pub fn fake_engine(responses: Vec<String>) -> (TempDir, PathBuf, JoinHandle<Vec<String>>) {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("engine.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut routes = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request_line = String::new();
+                let mut reader = BufReader::new(&mut stream);
+                reader.read_line(&mut request_line).unwrap();
+                // consume the headers
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                routes.push(request_line.split_ascii_whitespace().nth(1).unwrap().to_owned());
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            routes
+        });
+        (dir, socket, handle)
+    }
+
+    pub fn json_response(status: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.0 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    pub const ID: &str = "4d8b5ad4fb2e0d90e1d02065f32d849621048864d98b5ad4fb2e0d90e1d02065";
+
+    pub const INSPECT_WEB: &str = r#"{
+        "Id": "4d8b5ad4fb2e0d90e1d02065f32d849621048864d98b5ad4fb2e0d90e1d02065",
+        "Name": "/shop-web-1",
+        "State": {"Status": "running", "Pid": 1234},
+        "Config": {
+            "Hostname": "4d8b5ad4fb2e",
+            "Image": "nginx:1.27",
+            "Labels": {
+                "com.docker.compose.project": "shop",
+                "com.docker.compose.service": "web"
+            }
+        }
+    }"#;
+
+    #[test]
+    fn inspect() {
+        let (_dir, socket, server) = fake_engine(vec![
+            json_response("200 OK", INSPECT_WEB),
+            json_response("404 Not Found", r#"{"message":"No such container: abcd"}"#),
+            json_response("500 Internal Server Error", r#"{"message":"boom"}"#),
+        ]);
+        let client = EngineClient::new(socket, Duration::from_secs(1));
+
+        let infos = client
+            .inspect_container(ID)
+            .unwrap()
+            .expect("the container should exist");
+        assert_eq!(infos.name, "shop-web-1");
+        assert_eq!(infos.image, "nginx:1.27");
+        assert_eq!(infos.compose_project(), Some("shop"));
+        assert_eq!(infos.labels.get("com.docker.compose.service").unwrap(), "web");
+
+        assert_eq!(client.inspect_container("abcd").unwrap(), None);
+
+        let err = client.inspect_container("abcd").unwrap_err();
+        assert_eq!(err.to_string(), "unexpected status 500: boom");
+
+        let routes = server.join().unwrap();
+        assert_eq!(
+            routes,
+            vec![
+                format!("/containers/{ID}/json"),
+                String::from("/containers/abcd/json"),
+                String::from("/containers/abcd/json"),
+            ]
+        );
+    }
+
+    #[test]
+    fn inspect_without_labels() {
+        let (_dir, socket, server) = fake_engine(vec![json_response(
+            "200 OK",
+            r#"{"Name": "/db", "Config": {"Image": "postgres", "Labels": null}}"#,
+        )]);
+        let client = EngineClient::new(socket, Duration::from_secs(1));
+        let infos = client.inspect_container(ID).unwrap().unwrap();
+        assert_eq!(
+            infos,
+            ContainerInfos {
+                name: String::from("db"),
+                image: String::from("postgres"),
+                labels: BTreeMap::new(),
+            }
+        );
+        assert_eq!(infos.compose_project(), None);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn inspect_no_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = EngineClient::new(dir.path().join("missing.sock"), Duration::from_secs(1));
+        client.inspect_container(ID).expect_err("should fail");
+    }
+
+    #[test]
+    fn parse_response() {
+        let (status, body) = parse_http_response(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}").unwrap();
+        assert_eq!(status, 200);
+        assert_eq!(body, b"{}");
+
+        let (status, body) = parse_http_response(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4\r\n{\"a\"\r\n5;ext=1\r\n: 12}\r\n0\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(status, 200);
+        assert_eq!(body, b"{\"a\": 12}");
+
+        parse_http_response(b"HTTP/1.1 200 OK\r\nContent-Length: 2").expect_err("no end of headers");
+        parse_http_response(b"garbage\r\n\r\n").expect_err("no status");
+        parse_http_response(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nff\r\n{}")
+            .expect_err("truncated chunk");
+    }
+}
@@ -0,0 +1,325 @@
+//! Client of the containerd API (limited capabilities, just what we need).
+//!
+//! Unlike Docker and Podman, containerd only exposes a gRPC API.
+
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::{Context, anyhow};
+use hyper_util::rt::TokioIo;
+use tokio::net::UnixStream;
+use tonic::{
+    Code, Request,
+    codegen::http::uri::PathAndQuery,
+    metadata::MetadataValue,
+    transport::{Channel, Endpoint},
+};
+use tonic_prost::ProstCodec;
+
+use crate::api::ContainerInfos;
+
+/// gRPC route of `Containers.Get`.
+const GET_CONTAINER_ROUTE: &str = "/containerd.services.containers.v1.Containers/Get";
+
+/// gRPC metadata that contains the namespace of the containers.
+const NAMESPACE_HEADER: &str = "containerd-namespace";
+
+/// Labels that contain the name of the container, in order of preference.
+const NAME_LABELS: [&str; 2] = ["nerdctl/name", "io.kubernetes.container.name"];
+
+/// Messages of the containerd API (see `api/services/containers/v1/containers.proto` in containerd).
+/// Fields that we don't need are not included, prost will skip them.
+pub(crate) mod model {
+    use std::collections::HashMap;
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Container {
+        #[prost(string, tag = "1")]
+        pub id: String,
+        #[prost(map = "string, string", tag = "2")]
+        pub labels: HashMap<String, String>,
+        #[prost(string, tag = "3")]
+        pub image: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct GetContainerRequest {
+        #[prost(string, tag = "1")]
+        pub id: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct GetContainerResponse {
+        #[prost(message, optional, tag = "1")]
+        pub container: Option<Container>,
+    }
+}
+
+impl From<model::Container> for ContainerInfos {
+    fn from(container: model::Container) -> Self {
+        let name = NAME_LABELS
+            .iter()
+            .find_map(|label| container.labels.get(*label))
+            .cloned()
+            .unwrap_or(container.id);
+        ContainerInfos {
+            name,
+            image: container.image,
+            labels: container.labels.into_iter().collect(),
+        }
+    }
+}
+
+/// Client of the containerd API, over a Unix socket.
+///
+/// The containers of containerd belong to namespaces, such as `default` for nerdctl and `k8s.io` for Kubernetes.
+/// The cgroup of a container does not tell its namespace: the client looks for it in each namespace, in order.
+#[derive(Debug, Clone)]
+pub struct ContainerdClient {
+    socket: PathBuf,
+    timeout: Duration,
+    namespaces: Vec<String>,
+}
+
+impl ContainerdClient {
+    pub fn new(socket: impl Into<PathBuf>, timeout: Duration, namespaces: Vec<String>) -> Self {
+        Self {
+            socket: socket.into(),
+            timeout,
+            namespaces,
+        }
+    }
+
+    /// Asks containerd for informations about a container.
+    ///
+    /// Returns `None` if the container does not exist in any namespace.
+    pub fn inspect_container(&self, id: &str) -> anyhow::Result<Option<ContainerInfos>> {
+        // The registry is used by a blocking thread: run the request on a small runtime.
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("failed to create a runtime for the containerd client")?;
+        rt.block_on(async {
+            tokio::time::timeout(self.timeout, self.find_container(id))
+                .await
+                .map_err(|_| anyhow!("no response from containerd after {:?}", self.timeout))?
+        })
+    }
+
+    async fn find_container(&self, id: &str) -> anyhow::Result<Option<ContainerInfos>> {
+        let channel = self.connect().await?;
+        let mut grpc = tonic::client::Grpc::new(channel);
+        for namespace in &self.namespaces {
+            grpc.ready().await.context("containerd is not ready")?;
+            let mut request = Request::new(model::GetContainerRequest { id: id.to_owned() });
+            let namespace_value = MetadataValue::try_from(namespace.as_str())
+                .with_context(|| format!("invalid containerd namespace {namespace:?}"))?;
+            request.metadata_mut().insert(NAMESPACE_HEADER, namespace_value);
+
+            let response = grpc
+                .unary::<_, model::GetContainerResponse, _>(
+                    request,
+                    PathAndQuery::from_static(GET_CONTAINER_ROUTE),
+                    ProstCodec::default(),
+                )
+                .await;
+            match response {
+                Ok(response) => return Ok(response.into_inner().container.map(ContainerInfos::from)),
+                Err(status) if status.code() == Code::NotFound => continue,
+                Err(status) => {
+                    return Err(anyhow!(
+                        "unexpected status {:?} in namespace {namespace}: {}",
+                        status.code(),
+                        status.message()
+                    ));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Opens a gRPC channel to the Unix socket of containerd.
+    async fn connect(&self) -> anyhow::Result<Channel> {
+        let socket = self.socket.clone();
+        // The URI is required by tonic but not used: the connector always connects to the socket.
+        Endpoint::from_static("http://[::]:0")
+            .connect_with_connector(tower::service_fn(move |_| {
+                let socket = socket.clone();
+                async move { Ok::<_, std::io::Error>(TokioIo::new(UnixStream::connect(socket).await?)) }
+            }))
+            .await
+            .with_context(|| format!("failed to connect to {}", self.socket.display()))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        collections::HashMap,
+        convert::Infallible,
+        future::Future,
+        pin::Pin,
+        sync::Arc,
+        task::{Context, Poll},
+    };
+
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+    use tokio_stream::wrappers::UnixListenerStream;
+    use tonic::{
+        Response, Status,
+        body::Body,
+        codegen::http,
+        server::{NamedService, UnaryService},
+        transport::Server,
+    };
+
+    use super::*;
+    use crate::api::tests::ID;
+
+    type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+    /// The containers of the fake containerd, by (namespace, id).
+    type Containers = Arc<HashMap<(String, String), model::Container>>;
+
+    /// Implementation of `Containers.Get` for the fake containerd.
+    #[derive(Clone)]
+    struct FakeContainers(Containers);
+
+    impl NamedService for FakeContainers {
+        const NAME: &'static str = "containerd.services.containers.v1.Containers";
+    }
+
+    impl tower::Service<http::Request<Body>> for FakeContainers {
+        type Response = http::Response<Body>;
+        type Error = Infallible;
+        type Future = BoxFuture<Result<Self::Response, Infallible>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: http::Request<Body>) -> Self::Future {
+            let containers = self.0.clone();
+            Box::pin(async move {
+                assert_eq!(req.uri().path(), GET_CONTAINER_ROUTE);
+                let mut grpc = tonic::server::Grpc::new(ProstCodec::default());
+                Ok(grpc.unary(GetContainer(containers), req).await)
+            })
+        }
+    }
+
+    struct GetContainer(Containers);
+
+    impl UnaryService<model::GetContainerRequest> for GetContainer {
+        type Response = model::GetContainerResponse;
+        type Future = BoxFuture<Result<Response<Self::Response>, Status>>;
+
+        fn call(&mut self, request: Request<model::GetContainerRequest>) -> Self::Future {
+            let namespace = request
+                .metadata()
+                .get(NAMESPACE_HEADER)
+                .and_then(|ns| ns.to_str().ok())
+                .unwrap_or_default()
+                .to_owned();
+            let id = request.into_inner().id;
+            let res = match self.0.get(&(namespace.clone(), id.clone())) {
+                Some(container) => Ok(Response::new(model::GetContainerResponse {
+                    container: Some(container.clone()),
+                })),
+                None if id == "boom" => Err(Status::internal("boom")),
+                None => Err(Status::not_found(format!(
+                    "container \"{id}\" in namespace \"{namespace}\": not found"
+                ))),
+            };
+            Box::pin(async move { res })
+        }
+    }
+
+    /// Starts a fake containerd that knows the given containers, by namespace.
+    ///
+    /// The server runs until the returned runtime is dropped.
+    pub fn fake_containerd(containers: Vec<(&str, model::Container)>) -> (TempDir, PathBuf, tokio::runtime::Runtime) {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("containerd.sock");
+        let containers: Containers = Arc::new(
+            containers
+                .into_iter()
+                .map(|(ns, c)| ((ns.to_owned(), c.id.clone()), c))
+                .collect(),
+        );
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        let listener = rt.block_on(async { tokio::net::UnixListener::bind(&socket) }).unwrap();
+        rt.spawn(
+            Server::builder()
+                .add_service(FakeContainers(containers))
+                .serve_with_incoming(UnixListenerStream::new(listener)),
+        );
+        (dir, socket, rt)
+    }
+
+    pub fn nerdctl_container() -> model::Container {
+        model::Container {
+            id: ID.to_owned(),
+            labels: HashMap::from([
+                (String::from("nerdctl/name"), String::from("shop-web-1")),
+                (String::from("com.docker.compose.project"), String::from("shop")),
+            ]),
+            image: String::from("docker.io/library/nginx:1.27"),
+        }
+    }
+
+    #[test]
+    fn inspect() {
+        let (_dir, socket, _server) = fake_containerd(vec![("default", nerdctl_container())]);
+        let client = ContainerdClient::new(
+            socket,
+            Duration::from_secs(5),
+            vec![String::from("k8s.io"), String::from("default")],
+        );
+
+        let infos = client
+            .inspect_container(ID)
+            .unwrap()
+            .expect("the container should exist");
+        assert_eq!(infos.name, "shop-web-1");
+        assert_eq!(infos.image, "docker.io/library/nginx:1.27");
+        assert_eq!(infos.compose_project(), Some("shop"));
+
+        assert_eq!(client.inspect_container("abcd").unwrap(), None);
+
+        let err = client.inspect_container("boom").unwrap_err();
+        assert_eq!(err.to_string(), "unexpected status Internal in namespace k8s.io: boom");
+    }
+
+    #[test]
+    fn infos_without_name() {
+        let container = model::Container {
+            id: ID.to_owned(),
+            labels: HashMap::new(),
+            image: String::from("postgres"),
+        };
+        assert_eq!(
+            ContainerInfos::from(container),
+            ContainerInfos {
+                name: ID.to_owned(),
+                image: String::from("postgres"),
+                labels: Default::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn inspect_no_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = ContainerdClient::new(
+            dir.path().join("missing.sock"),
+            Duration::from_secs(1),
+            vec![String::from("default")],
+        );
+        client.inspect_container(ID).expect_err("should fail");
+    }
+}
@@ -0,0 +1,221 @@
+use std::{path::PathBuf, time::Duration};
+
+use alumet::{
+    pipeline::elements::source::trigger::TriggerSpec,
+    plugin::rust::{AlumetPlugin, deserialize_config, serialize_config},
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{api::EngineClient, containerd::ContainerdClient, registry::ContainerRegistry, runtime::Runtime};
+use source::SourceSetup;
+use util_cgroups_plugins::{
+    cgroup_events::{CgroupReactor, ReactorCallbacks, ReactorConfig},
+    job_annotation_transform::{
+        CachedCgroupHierarchy, JobAnnotationTransform, OptionalSharedHierarchy, SharedCgroupHierarchy,
+    },
+    metrics::Metrics,
+};
+
+mod api;
+mod containerd;
+mod registry;
+mod runtime;
+mod source;
+
+pub struct ContainersPlugin {
+    config: Config,
+    starting_state: Option<StartingState>,
+    reactor: Option<CgroupReactor>,
+}
+
+impl AlumetPlugin for ContainersPlugin {
+    fn name() -> &'static str {
+        "containers"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn init(config: alumet::plugin::ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(Self {
+            config,
+            starting_state: None,
+            reactor: None,
+        }))
+    }
+
+    fn default_config() -> anyhow::Result<Option<alumet::plugin::ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn start(&mut self, alumet: &mut alumet::plugin::AlumetPluginStart) -> anyhow::Result<()> {
+        let metrics = Metrics::create(alumet)?.with_memory_stat_keys(self.config.memory_stat_keys.clone());
+        let reactor_config = ReactorConfig::default();
+        let mut shared_hierarchy = OptionalSharedHierarchy::default();
+
+        // prepare the links to the container runtimes
+        let mut registry = ContainerRegistry::new(self.config.attach_labels);
+        let sockets = [
+            (Runtime::Docker, &self.config.docker_socket),
+            (Runtime::Podman, &self.config.podman_socket),
+        ];
+        for (runtime, socket) in sockets {
+            if let Some(socket) = socket {
+                if !socket.exists() {
+                    // The runtime may be started after Alumet, it's not an error.
+                    log::warn!(
+                        "The {runtime} socket {} does not exist (yet?): the {runtime} containers will only be identified by their id until it is created.",
+                        socket.display()
+                    );
+                }
+                registry = registry.with_client(runtime, EngineClient::new(socket, self.config.api_timeout));
+            }
+        }
+        if let Some(socket) = &self.config.containerd_socket {
+            if !socket.exists() {
+                log::warn!(
+                    "The containerd socket {} does not exist (yet?): the containerd containers will only be identified by their id until it is created.",
+                    socket.display()
+                );
+            }
+            let client = ContainerdClient::new(
+                socket,
+                self.config.api_timeout,
+                self.config.containerd_namespaces.clone(),
+            );
+            registry = registry.with_client(Runtime::Containerd, client);
+        }
+
+        // If enabled, create the annotation transform.
+        if self.config.annotate_foreign_measurements {
+            let shared = SharedCgroupHierarchy::default();
+            shared_hierarchy.enable(shared.clone());
+
+            let transform = JobAnnotationTransform {
+                // the registry is filled by the reactor, when the cgroups are created
+                tagger: registry.cache_only(),
+                cgroup_v2_hierarchy: CachedCgroupHierarchy::new(shared),
+            };
+            alumet.add_transform("containers-annotation", Box::new(transform))?;
+        }
+
+        // store the state for later, because we cannot set up everything now
+        let starting_state = StartingState {
+            metrics,
+            reactor_config,
+            registry,
+            shared_hierarchy,
+        };
+        self.starting_state = Some(starting_state);
+        Ok(())
+    }
+
+    fn post_pipeline_start(&mut self, alumet: &mut alumet::plugin::AlumetPostStart) -> anyhow::Result<()> {
+        // continue from the state that has been prepared in `start`
+        let s = self.starting_state.take().unwrap();
+
+        let trigger = TriggerSpec::at_interval(self.config.poll_interval);
+        let probe_setup = SourceSetup {
+            trigger,
+            containers: s.registry.clone(),
+        };
+
+        let reactor = CgroupReactor::new(
+            s.reactor_config,
+            s.metrics,
+            ReactorCallbacks {
+                probe_setup,
+                on_removal: s.registry,
+                on_fs_mount: s.shared_hierarchy,
+            },
+            alumet.pipeline_control(),
+        )
+        .context("failed to init CgroupReactor")?;
+
+        self.reactor = Some(reactor);
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        if let Some(reactor) = self.reactor.take() {
+            drop(reactor);
+        }
+        Ok(())
+    }
+}
+
+struct StartingState {
+    metrics: Metrics,
+    reactor_config: ReactorConfig,
+    registry: ContainerRegistry,
+    shared_hierarchy: OptionalSharedHierarchy,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Config {
+    #[serde(with = "humantime_serde")]
+    pub poll_interval: Duration,
+
+    /// Path to the Unix socket of the Docker Engine API.
+    /// If not set, the Docker containers are only identified by their id.
+    pub docker_socket: Option<PathBuf>,
+
+    /// Path to the Unix socket of the Podman API (Docker-compatible).
+    /// If not set, the Podman containers are only identified by their id.
+    pub podman_socket: Option<PathBuf>,
+
+    /// Path to the Unix socket of the containerd API (gRPC).
+    /// If not set, the containerd containers are only identified by their id.
+    pub containerd_socket: Option<PathBuf>,
+
+    /// The containerd namespaces in which the containers are looked for, in order.
+    /// The default value is `["default", "k8s.io"]` (the namespaces of nerdctl and of the CRI plugin).
+    #[serde(default = "default_containerd_namespaces")]
+    pub containerd_namespaces: Vec<String>,
+
+    /// Maximum time to wait for an answer of a container runtime.
+    #[serde(with = "humantime_serde")]
+    pub api_timeout: Duration,
+
+    /// If `true`, adds the labels of the containers to the measurements, as `label.<key>` attributes.
+    pub attach_labels: bool,
+
+    /// If `true`, adds attributes like `container_name` to the measurements produced by other plugins.
+    /// The default value is `false`.
+    ///
+    /// The measurements must have the `cgroup` resource consumer, and **cgroup v2** must be used on the node.
+    #[serde(default)]
+    pub annotate_foreign_measurements: bool,
+
+    /// Additional keys of `memory.stat` to measure, like `file_mapped` or `pgmajfault`.
+    /// The default value is empty.
+    ///
+    /// With **cgroup v1**, use the keys of cgroup v1, like `total_mapped_file` or `total_pgmajfault`.
+    #[serde(default)]
+    pub memory_stat_keys: Vec<String>,
+}
+
+#[cfg_attr(tarpaulin, ignore)]
+fn default_containerd_namespaces() -> Vec<String> {
+    vec![String::from("default"), String::from("k8s.io")]
+}
+
+impl Default for Config {
+    #[cfg_attr(tarpaulin, ignore)]
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(5),
+            docker_socket: Some(PathBuf::from("/var/run/docker.sock")),
+            podman_socket: Some(PathBuf::from("/run/podman/podman.sock")),
+            containerd_socket: Some(PathBuf::from("/run/containerd/containerd.sock")),
+            containerd_namespaces: default_containerd_namespaces(),
+            api_timeout: Duration::from_secs(1),
+            attach_labels: true,
+            annotate_foreign_measurements: false,
+            memory_stat_keys: Vec::new(),
+        }
+    }
+}
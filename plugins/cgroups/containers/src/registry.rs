@@ -0,0 +1,330 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use alumet::measurement::AttributeValue;
+use rustc_hash::FxHashMap;
+use util_cgroups::Cgroup;
+use util_cgroups_plugins::{cgroup_events::CgroupRemovalCallback, job_annotation_transform::JobTagger};
+
+use crate::{
+    api::{ContainerInfos, RuntimeClient},
+    runtime::{ContainerRef, Runtime, find_container_in_cgroup},
+};
+
+/// How long a failed lookup is remembered, so that the runtime is not queried again for the same container.
+const NEGATIVE_CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// Container registry: finds the metadata of the containers and keeps them in cache.
+///
+/// The registry can be cloned, the clones share the same cache.
+#[derive(Clone)]
+pub struct ContainerRegistry {
+    clients: FxHashMap<Runtime, Arc<dyn RuntimeClient>>,
+    attach_labels: bool,
+    cache: Arc<Mutex<Cache>>,
+}
+
+#[derive(Default)]
+struct Cache {
+    /// Container infos, by container id.
+    containers: FxHashMap<String, ContainerInfos>,
+    /// Time of the failed lookups, by container id.
+    failures: FxHashMap<String, Instant>,
+}
+
+impl ContainerRegistry {
+    pub fn new(attach_labels: bool) -> Self {
+        Self {
+            clients: FxHashMap::default(),
+            attach_labels,
+            cache: Default::default(),
+        }
+    }
+
+    /// Returns a registry that shares the cache of this one, but never queries the container runtimes.
+    ///
+    /// Use it where a query would block too much, like in a transform.
+    pub fn cache_only(&self) -> Self {
+        Self {
+            clients: FxHashMap::default(),
+            attach_labels: self.attach_labels,
+            cache: self.cache.clone(),
+        }
+    }
+
+    /// Uses `client` to get the metadata of the containers managed by `runtime`.
+    pub fn with_client(mut self, runtime: Runtime, client: impl RuntimeClient + 'static) -> Self {
+        self.clients.insert(runtime, Arc::new(client));
+        self
+    }
+
+    /// Returns the infos about a container, or `None` if they are not available.
+    pub fn get(&mut self, container: &ContainerRef) -> anyhow::Result<Option<ContainerInfos>> {
+        self.get_at(container, Instant::now())
+    }
+
+    fn get_at(&mut self, container: &ContainerRef, now: Instant) -> anyhow::Result<Option<ContainerInfos>> {
+        {
+            let mut cache = self.cache.lock().unwrap();
+            if let Some(infos) = cache.containers.get(&container.id) {
+                return Ok(Some(infos.to_owned()));
+            }
+            match cache.failures.get(&container.id) {
+                Some(t) if now.duration_since(*t) < NEGATIVE_CACHE_TTL => return Ok(None),
+                Some(_) => {
+                    cache.failures.remove(&container.id);
+                }
+                None => (),
+            }
+        }
+
+        // Without a client, we cannot know more than the container id.
+        let Some(client) = self.clients.get(&container.runtime) else {
+            return Ok(None);
+        };
+
+        // We have no info about this container, ask the runtime.
+        // If it does not know the container, it must have been deleted in the meantime => return None.
+        // The lock is not held during the request, so that other threads are not blocked.
+        let res = client.inspect_container(&container.id);
+        let mut cache = self.cache.lock().unwrap();
+        match &res {
+            Ok(Some(infos)) => {
+                cache.containers.insert(container.id.clone(), infos.clone());
+            }
+            Ok(None) | Err(_) => {
+                cache.failures.insert(container.id.clone(), now);
+            }
+        }
+        res
+    }
+
+    fn attributes(&self, container: ContainerRef, infos: Option<ContainerInfos>) -> Vec<(String, AttributeValue)> {
+        let mut attrs = vec![
+            ("container_id".into(), AttributeValue::String(container.id)),
+            (
+                "container_runtime".into(),
+                AttributeValue::Str(container.runtime.as_str()),
+            ),
+        ];
+        if let Some(infos) = infos {
+            if let Some(project) = infos.compose_project() {
+                attrs.push(("compose_project".into(), AttributeValue::String(project.to_owned())));
+            }
+            attrs.push(("container_name".into(), AttributeValue::String(infos.name)));
+            attrs.push(("container_image".into(), AttributeValue::String(infos.image)));
+            if self.attach_labels {
+                for (key, value) in infos.labels {
+                    attrs.push((format!("label.{key}"), AttributeValue::String(value)));
+                }
+            }
+        }
+        attrs
+    }
+}
+
+impl JobTagger for ContainerRegistry {
+    fn attributes_for_cgroup(&mut self, cgroup: &Cgroup) -> Vec<(String, AttributeValue)> {
+        let Some(container) = find_container_in_cgroup(cgroup.fs_path()) else {
+            return Vec::new();
+        };
+        let infos = self
+            .get(&container)
+            .inspect_err(|e| {
+                log::error!(
+                    "failed to get infos for {} container {}: {e:#}",
+                    container.runtime,
+                    container.id
+                )
+            })
+            .ok()
+            .flatten();
+        self.attributes(container, infos)
+    }
+}
+
+impl CgroupRemovalCallback for ContainerRegistry {
+    fn on_cgroups_removed(&mut self, cgroups: Vec<Cgroup>) -> anyhow::Result<()> {
+        let mut cache = self.cache.lock().unwrap();
+        for cgroup in cgroups {
+            if let Some(container) = find_container_in_cgroup(cgroup.fs_path()) {
+                cache.containers.remove(&container.id);
+                cache.failures.remove(&container.id);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+    use util_cgroups::{CgroupHierarchy, CgroupVersion};
+
+    use super::*;
+    use crate::{
+        api::{
+            EngineClient,
+            tests::{ID, INSPECT_WEB, fake_engine, json_response},
+        },
+        containerd::{
+            ContainerdClient,
+            tests::{fake_containerd, nerdctl_container},
+        },
+    };
+
+    fn attr_keys(attrs: &[(String, AttributeValue)]) -> Vec<&str> {
+        attrs.iter().map(|(k, _)| k.as_str()).collect()
+    }
+
+    #[test]
+    fn attributes_and_cache() {
+        // only one response: the second call must hit the cache
+        let (_dir, socket, server) = fake_engine(vec![json_response("200 OK", INSPECT_WEB)]);
+        let mut registry = ContainerRegistry::new(true)
+            .with_client(Runtime::Docker, EngineClient::new(socket, Duration::from_secs(1)));
+
+        let hierarchy = CgroupHierarchy::manually_unchecked("/sys/fs/cgroup", CgroupVersion::V2, vec!["cpu"]);
+        let cgroup = Cgroup::from_cgroup_path(&hierarchy, format!("/system.slice/docker-{ID}.scope"));
+
+        let attrs = registry.attributes_for_cgroup(&cgroup);
+        assert_eq!(
+            attr_keys(&attrs),
+            vec![
+                "container_id",
+                "container_runtime",
+                "compose_project",
+                "container_name",
+                "container_image",
+                "label.com.docker.compose.project",
+                "label.com.docker.compose.service",
+            ]
+        );
+        assert_eq!(attrs[3].1, AttributeValue::String(String::from("shop-web-1")));
+
+        let mut clone = registry.clone();
+        assert_eq!(clone.attributes_for_cgroup(&cgroup), attrs);
+        server.join().unwrap();
+
+        // the removal of the cgroup evicts the container from the cache, for all the clones
+        clone.on_cgroups_removed(vec![cgroup]).unwrap();
+        assert!(registry.cache.lock().unwrap().containers.is_empty());
+    }
+
+    #[test]
+    fn cache_only() {
+        let (_dir, socket, server) = fake_engine(vec![json_response("200 OK", INSPECT_WEB)]);
+        let mut registry = ContainerRegistry::new(false)
+            .with_client(Runtime::Docker, EngineClient::new(socket, Duration::from_secs(1)));
+        let mut cache_only = registry.cache_only();
+
+        let hierarchy = CgroupHierarchy::manually_unchecked("/sys/fs/cgroup", CgroupVersion::V2, vec!["cpu"]);
+        let cgroup = Cgroup::from_cgroup_path(&hierarchy, format!("/system.slice/docker-{ID}.scope"));
+
+        // not in the cache yet: the runtime is not queried
+        let attrs = cache_only.attributes_for_cgroup(&cgroup);
+        assert_eq!(attr_keys(&attrs), vec!["container_id", "container_runtime"]);
+
+        // the cache is filled by the registry, and shared
+        registry.attributes_for_cgroup(&cgroup);
+        server.join().unwrap();
+        let attrs = cache_only.attributes_for_cgroup(&cgroup);
+        assert_eq!(
+            attr_keys(&attrs),
+            vec![
+                "container_id",
+                "container_runtime",
+                "compose_project",
+                "container_name",
+                "container_image"
+            ]
+        );
+    }
+
+    #[test]
+    fn attributes_containerd() {
+        let (_dir, socket, _server) = fake_containerd(vec![("default", nerdctl_container())]);
+        let client = ContainerdClient::new(socket, Duration::from_secs(5), vec![String::from("default")]);
+        let mut registry = ContainerRegistry::new(false).with_client(Runtime::Containerd, client);
+        let hierarchy = CgroupHierarchy::manually_unchecked("/sys/fs/cgroup", CgroupVersion::V2, vec!["cpu"]);
+
+        let cgroup = Cgroup::from_cgroup_path(&hierarchy, format!("/system.slice/nerdctl-{ID}.scope"));
+        let attrs = registry.attributes_for_cgroup(&cgroup);
+        assert_eq!(
+            attr_keys(&attrs),
+            vec![
+                "container_id",
+                "container_runtime",
+                "compose_project",
+                "container_name",
+                "container_image"
+            ]
+        );
+        assert_eq!(attrs[1].1, AttributeValue::Str("containerd"));
+        assert_eq!(attrs[3].1, AttributeValue::String(String::from("shop-web-1")));
+    }
+
+    #[test]
+    fn attributes_without_client() {
+        let mut registry = ContainerRegistry::new(true);
+        let hierarchy = CgroupHierarchy::manually_unchecked("/sys/fs/cgroup", CgroupVersion::V2, vec!["cpu"]);
+
+        let cgroup = Cgroup::from_cgroup_path(&hierarchy, format!("/system.slice/docker-{ID}.scope"));
+        let attrs = registry.attributes_for_cgroup(&cgroup);
+        assert_eq!(
+            attrs,
+            vec![
+                (String::from("container_id"), AttributeValue::String(ID.to_owned())),
+                (String::from("container_runtime"), AttributeValue::Str("docker")),
+            ]
+        );
+
+        // not a container
+        let cgroup = Cgroup::from_cgroup_path(&hierarchy, String::from("/system.slice/docker.service"));
+        assert!(registry.attributes_for_cgroup(&cgroup).is_empty());
+    }
+
+    #[test]
+    fn attributes_runtime_unavailable() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = EngineClient::new(dir.path().join("missing.sock"), Duration::from_secs(1));
+        let mut registry = ContainerRegistry::new(false).with_client(Runtime::Podman, client);
+        let hierarchy = CgroupHierarchy::manually_unchecked("/sys/fs/cgroup", CgroupVersion::V2, vec!["cpu"]);
+
+        // the container is still identified
+        let cgroup = Cgroup::from_cgroup_path(&hierarchy, format!("/machine.slice/libpod-{ID}.scope"));
+        let attrs = registry.attributes_for_cgroup(&cgroup);
+        assert_eq!(attr_keys(&attrs), vec!["container_id", "container_runtime"]);
+    }
+
+    #[test]
+    fn failures_are_cached() {
+        // only one response: the other lookups must not reach the runtime
+        let (_dir, socket, server) = fake_engine(vec![json_response(
+            "404 Not Found",
+            r#"{"message": "no such container"}"#,
+        )]);
+        let mut registry = ContainerRegistry::new(false)
+            .with_client(Runtime::Docker, EngineClient::new(socket, Duration::from_secs(1)));
+        let container = ContainerRef {
+            runtime: Runtime::Docker,
+            id: ID.to_owned(),
+        };
+
+        let t0 = Instant::now();
+        assert_eq!(registry.get_at(&container, t0).unwrap(), None);
+        server.join().unwrap();
+        assert_eq!(registry.get_at(&container, t0 + Duration::from_secs(1)).unwrap(), None);
+
+        // after the TTL, the runtime is queried again
+        let (_dir, socket, server) = fake_engine(vec![json_response("200 OK", INSPECT_WEB)]);
+        registry = registry.with_client(Runtime::Docker, EngineClient::new(socket, Duration::from_secs(1)));
+        let infos = registry.get_at(&container, t0 + NEGATIVE_CACHE_TTL).unwrap();
+        server.join().unwrap();
+        assert_eq!(infos.unwrap().name, "shop-web-1");
+    }
+}
@@ -0,0 +1,191 @@
+use std::{fmt, path::Path};
+
+/// A container runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Runtime {
+    Docker,
+    Podman,
+    Containerd,
+}
+
+/// A container, identified by its runtime and its full id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerRef {
+    pub runtime: Runtime,
+    /// The full container id, 64 hexadecimal characters.
+    pub id: String,
+}
+
+impl Runtime {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Runtime::Docker => "docker",
+            Runtime::Podman => "podman",
+            Runtime::Containerd => "containerd",
+        }
+    }
+}
+
+impl fmt::Display for Runtime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Finds the container that corresponds to a cgroup path (in the sysfs).
+///
+/// # Expected format
+///
+/// The supported formats are:
+/// - `…/docker-{id}.scope` (Docker with the systemd cgroup driver)
+/// - `…/docker/{id}` (Docker with the cgroupfs cgroup driver)
+/// - `…/libpod-{id}.scope` (Podman with the systemd cgroup manager, rootful or rootless)
+/// - `…/libpod_parent/libpod-{id}` (Podman with the cgroupfs cgroup manager)
+/// - `…/nerdctl-{id}.scope` (containerd, started by nerdctl with the systemd cgroup manager)
+/// - `…/cri-containerd-{id}.scope` (containerd, started through its CRI plugin with the systemd cgroup driver)
+///
+/// # Exclusions
+///
+/// Returns `None` for the cgroups of the Podman monitor (`libpod-conmon-{id}.scope`),
+/// and for the containers of Kubernetes pods (paths that contain `kubepods`), which are handled by the `k8s` plugin.
+pub fn find_container_in_cgroup(cgroup_fs_path: &Path) -> Option<ContainerRef> {
+    if cgroup_fs_path.to_str()?.contains("kubepods") {
+        return None;
+    }
+    let cgroup_name = cgroup_fs_path.file_name()?.to_str()?;
+    let parent_name = cgroup_fs_path
+        .parent()
+        .and_then(|p| p.file_name())
+        .and_then(|n| n.to_str());
+
+    let (runtime, id) = if let Some(scope) = cgroup_name.strip_suffix(".scope") {
+        let (prefix, id) = scope.rsplit_once('-')?;
+        let runtime = match prefix {
+            "docker" => Runtime::Docker,
+            "libpod" => Runtime::Podman,
+            "nerdctl" | "cri-containerd" => Runtime::Containerd,
+            _ => return None,
+        };
+        (runtime, id)
+    } else {
+        match parent_name {
+            Some("docker") => (Runtime::Docker, cgroup_name),
+            Some("libpod_parent") => (Runtime::Podman, cgroup_name.strip_prefix("libpod-")?),
+            _ => return None,
+        }
+    };
+
+    if !is_container_id(id) {
+        return None;
+    }
+    Some(ContainerRef {
+        runtime,
+        id: id.to_owned(),
+    })
+}
+
+/// Checks that `id` looks like a full container id.
+fn is_container_id(id: &str) -> bool {
+    id.len() == 64 && id.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    const ID: &str = "4d8b5ad4fb2e0d90e1d02065f32d849621048864d98b5ad4fb2e0d90e1d02065";
+
+    fn find(path: &str) -> Option<ContainerRef> {
+        find_container_in_cgroup(&PathBuf::from(path))
+    }
+
+    fn container(runtime: Runtime) -> Option<ContainerRef> {
+        Some(ContainerRef {
+            runtime,
+            id: ID.to_owned(),
+        })
+    }
+
+    #[test]
+    fn docker() {
+        assert_eq!(
+            find(&format!("/sys/fs/cgroup/system.slice/docker-{ID}.scope")),
+            container(Runtime::Docker)
+        );
+        assert_eq!(find(&format!("/sys/fs/cgroup/docker/{ID}")), container(Runtime::Docker));
+        assert_eq!(find("/sys/fs/cgroup/system.slice/docker.service"), None);
+        assert_eq!(find("/sys/fs/cgroup/docker"), None);
+    }
+
+    #[test]
+    fn podman() {
+        assert_eq!(
+            find(&format!("/sys/fs/cgroup/machine.slice/libpod-{ID}.scope")),
+            container(Runtime::Podman)
+        );
+        assert_eq!(
+            find(&format!(
+                "/sys/fs/cgroup/user.slice/user-1000.slice/user@1000.service/user.slice/libpod-{ID}.scope"
+            )),
+            container(Runtime::Podman)
+        );
+        assert_eq!(
+            find(&format!("/sys/fs/cgroup/libpod_parent/libpod-{ID}")),
+            container(Runtime::Podman)
+        );
+        assert_eq!(
+            find(&format!("/sys/fs/cgroup/machine.slice/libpod-conmon-{ID}.scope")),
+            None
+        );
+    }
+
+    #[test]
+    fn containerd() {
+        assert_eq!(
+            find(&format!("/sys/fs/cgroup/system.slice/nerdctl-{ID}.scope")),
+            container(Runtime::Containerd)
+        );
+        assert_eq!(
+            find(&format!("/sys/fs/cgroup/system.slice/cri-containerd-{ID}.scope")),
+            container(Runtime::Containerd)
+        );
+        assert_eq!(find("/sys/fs/cgroup/system.slice/containerd.service"), None);
+        // containers of K8S pods
+        assert_eq!(
+            find(&format!(
+                "/sys/fs/cgroup/kubepods.slice/kubepods-besteffort.slice/kubepods-besteffort-pod5f32d849_6210_4886_a48d_e0d90e1d0206.slice/cri-containerd-{ID}.scope"
+            )),
+            None
+        );
+    }
+
+    #[test]
+    fn excluded() {
+        // containers of K8S pods
+        assert_eq!(
+            find(&format!(
+                "/sys/fs/cgroup/kubepods.slice/kubepods-besteffort.slice/kubepods-besteffort-pod5f32d849_6210_4886_a48d_e0d90e1d0206.slice/docker-{ID}.scope"
+            )),
+            None
+        );
+        // invalid ids
+        assert_eq!(find("/sys/fs/cgroup/system.slice/docker-1234.scope"), None);
+        assert_eq!(
+            find(&format!(
+                "/sys/fs/cgroup/system.slice/docker-{}.scope",
+                ID.to_uppercase()
+            )),
+            None
+        );
+        // other cgroups
+        assert_eq!(
+            find("/sys/fs/cgroup/user.slice/user-1000.slice/user@1000.service/app.slice/app-org.gnome.Terminal.slice"),
+            None
+        );
+        assert_eq!(find("/sys/fs/cgroup/system.slice/run-r0123.scope"), None);
+    }
+}
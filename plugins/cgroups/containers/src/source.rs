@@ -0,0 +1,40 @@
+use alumet::pipeline::elements::source::trigger::TriggerSpec;
+
+use util_cgroups::Cgroup;
+use util_cgroups_plugins::{
+    cgroup_events::{CgroupSetupCallback, ProbeSetup, SourceSettings},
+    job_annotation_transform::JobTagger,
+    metrics::{AugmentedMetrics, Metrics},
+};
+
+use crate::{registry::ContainerRegistry, runtime::find_container_in_cgroup};
+
+#[derive(Clone)]
+pub struct SourceSetup {
+    pub trigger: TriggerSpec,
+    pub containers: ContainerRegistry,
+}
+
+impl CgroupSetupCallback for SourceSetup {
+    fn setup_new_probe(&mut self, cgroup: &Cgroup, metrics: &Metrics) -> Option<ProbeSetup> {
+        // If we cannot find a container id, this is NOT a container
+        let container = find_container_in_cgroup(cgroup.fs_path())?;
+
+        // Retrieves associated attributes
+        let attrs = self.containers.attributes_for_cgroup(cgroup);
+        let metrics = AugmentedMetrics::with_common_attr_vec(metrics, attrs);
+
+        // setup the trigger according to the plugin's config
+        let trigger = self.trigger.clone();
+
+        // use the runtime and the container id as the source name, it does not depend on the cgroup driver
+        let name = format!("{}-{}", container.runtime, container.id);
+
+        // ready!
+        let source_settings = SourceSettings { name, trigger };
+        Some(ProbeSetup {
+            metrics,
+            source_settings,
+        })
+    }
+}
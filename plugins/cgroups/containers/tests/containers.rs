@@ -0,0 +1,145 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    os::unix::net::UnixListener,
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use alumet::{
+    agent::{
+        self,
+        plugin::{PluginInfo, PluginSet},
+    },
+    pipeline::{
+        control::request::{self, ElementListFilter},
+        naming::ElementKind,
+    },
+    plugin::PluginMetadata,
+};
+use anyhow::Context;
+use plugin_containers::ContainersPlugin;
+use util_cgroups::hierarchy::find_user_app_slice;
+
+const SYSFS_CGROUP: &str = "/sys/fs/cgroup";
+const TIMEOUT: Duration = Duration::from_secs(1);
+const TOLERANCE: Duration = Duration::from_millis(500);
+const CONTAINER_ID: &str = "0b506dc87ee462c880d3e41d0dacd0c00b506dc87ee462c880d3e41d0dacd0c0";
+
+#[test]
+fn test_docker_cgroupv2() -> anyhow::Result<()> {
+    if std::env::var_os("SKIP_CGROUPFS_TESTS").is_some() {
+        println!("skipped because SKIP_CGROUPFS_TESTS is set");
+        return Ok(());
+    }
+
+    let _ = env_logger::Builder::from_default_env().try_init();
+
+    // find where we can create actual cgroups
+    let app_slice = find_user_app_slice(Path::new(SYSFS_CGROUP))?;
+
+    // prepare fake docker api
+    let socket_dir = tempfile::tempdir()?;
+    let socket_path = socket_dir.path().join("docker.sock");
+    let api_hits = fake_docker_api(UnixListener::bind(&socket_path)?);
+    let socket_path = socket_path.to_str().unwrap();
+
+    // load plugins
+    let mut plugins = PluginSet::new();
+    plugins.add_plugin(PluginInfo {
+        metadata: PluginMetadata::from_static::<ContainersPlugin>(),
+        enabled: true,
+        config: Some(
+            toml::from_str(&format!(
+                r#"
+                    poll_interval = "1s"
+                    docker_socket = "{socket_path}"
+                    api_timeout = "1s"
+                    attach_labels = true
+                "#
+            ))
+            .unwrap(),
+        ),
+    });
+
+    // start the measurement pipeline, without the container's cgroup
+    let agent = agent::Builder::new(plugins).build_and_start()?;
+    std::thread::sleep(TOLERANCE);
+
+    // create the cgroups
+    let cgroup_dir_parent =
+        tempfile::tempdir_in(&app_slice).with_context(|| format!("failed to create cgroup in {app_slice:?}"))?;
+    let cgroup_dir_container = cgroup_dir_parent.path().join(format!("docker-{CONTAINER_ID}.scope"));
+    std::fs::create_dir_all(&cgroup_dir_container)?;
+
+    let source_name = &format!("docker-{CONTAINER_ID}");
+    log::info!("cgroup created at {:?}", cgroup_dir_container);
+    log::info!("source name: {source_name}");
+
+    // expect the source to be created quickly after that
+    std::thread::sleep(TOLERANCE);
+    let handle = agent.pipeline.control_handle();
+    let rt = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let elements = rt.block_on(
+        handle.send_wait(
+            request::list_elements(
+                ElementListFilter::kind(ElementKind::Source)
+                    .plugin("containers")
+                    .name(source_name),
+            ),
+            TIMEOUT,
+        ),
+    )?;
+    let all_sources = rt.block_on(handle.send_wait(
+        request::list_elements(ElementListFilter::kind(ElementKind::Source)),
+        TIMEOUT,
+    ))?;
+    assert!(
+        !elements.is_empty(),
+        "source not found: {source_name}, all sources: {all_sources:?}"
+    );
+
+    // check that the API has been called once
+    assert_eq!(api_hits.load(Ordering::Relaxed), 1);
+
+    // stop the pipeline and wait for it to terminate
+    handle.shutdown();
+    agent.wait_for_shutdown(TIMEOUT).context("error in shutdown")?;
+    Ok(())
+}
+
+/// Answers every request with the infos of the same container, and counts the requests.
+fn fake_docker_api(listener: UnixListener) -> Arc<AtomicUsize> {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let hits_server = hits.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(&mut stream);
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            hits_server.fetch_add(1, Ordering::Relaxed);
+            let body = serde_json::json!({
+                "Id": CONTAINER_ID,
+                "Name": "/web",
+                "Config": {
+                    "Image": "nginx:1.27",
+                    "Labels": {"com.docker.compose.project": "shop"}
+                }
+            })
+            .to_string();
+            write!(
+                stream,
+                "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+        }
+    });
+    hits
+}
@@ -0,0 +1,236 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use alumet::measurement::AttributeValue;
+use rustc_hash::FxHashMap;
+use util_cgroups::Cgroup;
+use util_cgroups_plugins::{
+    cgroup_events::CgroupRemovalCallback, job_annotation_transform::JobTagger, regex::RegexAttributesExtrator,
+};
+
+use crate::job_ad::JobAdLocator;
+
+/// Default name of the parent cgroup of the slots (configuration knob `BASE_CGROUP` of HTCondor).
+pub const DEFAULT_BASE_CGROUP: &str = "htcondor";
+
+/// Attributes of a slot cgroup.
+type Attributes = Vec<(String, AttributeValue)>;
+
+/// Builds the regex that matches the cgroups of the HTCondor slots.
+///
+/// The starter of each job creates a cgroup named after the execute directory and the slot, for instance
+/// `/htcondor/condor_var_lib_condor_execute_slot1_1@node1` for the dynamic slot `slot1_1`
+/// and the execute directory `/var/lib/condor/execute`.
+/// With systemd, the base cgroup is inside of the cgroup of the service, like `/system.slice/condor.service/htcondor`.
+fn slot_regex(base_cgroup: &str) -> String {
+    format!(
+        r"/{}/condor_(?:[^/]*_)?(?<slot>slot(?<slot_id__u64>[0-9]+)(?:_(?<dynamic_slot_id__u64>[0-9]+))?)@[^/]+$",
+        regex::escape(base_cgroup)
+    )
+}
+
+pub fn find_key_in_attrs(key: &str, attrs: &[(String, AttributeValue)]) -> Option<String> {
+    attrs.iter().find(|(k, _)| k == key).map(|(_, v)| match v {
+        AttributeValue::String(value) => value.clone(),
+        _ => unreachable!("key: {} not found", key),
+    })
+}
+
+/// Finds the slot and the job of the cgroups, and keeps their attributes in cache.
+///
+/// The tagger can be cloned, the clones share the same cache.
+#[derive(Clone)]
+pub struct HtcondorTagger {
+    extractor: RegexAttributesExtrator,
+    /// Reads the job ad of the slots, if enabled.
+    job_ads: Option<JobAdLocator>,
+    /// Cache of attributes, by canonical cgroup path.
+    ///
+    /// A slot is only cached when its attributes are complete, that is, when its job ad has been read.
+    slots: Arc<Mutex<FxHashMap<String, Attributes>>>,
+}
+
+impl HtcondorTagger {
+    pub fn new(base_cgroup: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            extractor: RegexAttributesExtrator::new(&slot_regex(base_cgroup))?,
+            job_ads: None,
+            slots: Default::default(),
+        })
+    }
+
+    pub fn with_job_ads(mut self, locator: JobAdLocator) -> Self {
+        self.job_ads = Some(locator);
+        self
+    }
+
+    /// Returns `true` if the attributes of the cgroup, identified by its canonical path, are complete.
+    ///
+    /// They are not complete if the job has not been started in the slot yet.
+    pub fn is_complete(&self, path: &str) -> bool {
+        self.slots.lock().unwrap().contains_key(path)
+    }
+
+    /// Returns the attributes of the cgroup, identified by its canonical path, if they are complete.
+    pub fn complete_attributes(&self, path: &str) -> Option<Attributes> {
+        self.slots.lock().unwrap().get(path).cloned()
+    }
+
+    /// Returns the attributes of the cgroup with the given canonical path and filesystem path.
+    ///
+    /// Unlike [`JobTagger::attributes_for_cgroup`], this does not need the hierarchy of the cgroup.
+    pub fn attributes_for_path(&mut self, path: &str, fs_path: &Path) -> Attributes {
+        if let Some(attrs) = self.slots.lock().unwrap().get(path) {
+            return attrs.clone();
+        }
+
+        // extracts attributes "slot", "slot_id" and "dynamic_slot_id"
+        let mut attrs = self
+            .extractor
+            .extract(path)
+            .expect("bad regex: it should only match if the input can be parsed into the specified types");
+        if attrs.is_empty() {
+            // not a slot
+            return attrs;
+        }
+
+        if let Some(locator) = &self.job_ads {
+            match locator.find(fs_path) {
+                Ok(Some(ad)) => attrs.extend(ad.attributes()),
+                // The job has not been started yet, don't cache the attributes to try again later.
+                Ok(None) => return attrs,
+                Err(e) => log::warn!("failed to read the job ad of {fs_path:?}: {e}"),
+            }
+        }
+        self.slots.lock().unwrap().insert(path.to_owned(), attrs.clone());
+        attrs
+    }
+}
+
+impl JobTagger for HtcondorTagger {
+    fn attributes_for_cgroup(&mut self, cgroup: &Cgroup) -> Vec<(String, AttributeValue)> {
+        self.attributes_for_path(cgroup.canonical_path(), cgroup.fs_path())
+    }
+}
+
+impl CgroupRemovalCallback for HtcondorTagger {
+    fn on_cgroups_removed(&mut self, cgroups: Vec<Cgroup>) -> anyhow::Result<()> {
+        // The slots are reused by other jobs, with a new cgroup.
+        let mut slots = self.slots.lock().unwrap();
+        for cgroup in cgroups {
+            slots.remove(cgroup.canonical_path());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use util_cgroups::{CgroupHierarchy, CgroupVersion};
+
+    use super::*;
+    use crate::job_ad::tests::{JOB_AD, fake_job};
+
+    fn extract(tagger: &HtcondorTagger, path: &str) -> Vec<(String, AttributeValue)> {
+        tagger.extractor.extract(path).unwrap()
+    }
+
+    #[test]
+    fn slot_cgroups() {
+        let tagger = HtcondorTagger::new(DEFAULT_BASE_CGROUP).unwrap();
+        assert_eq!(
+            extract(
+                &tagger,
+                "/htcondor/condor_var_lib_condor_execute_slot1_12@node1.example.org"
+            ),
+            vec![
+                (String::from("slot"), AttributeValue::String(String::from("slot1_12"))),
+                (String::from("slot_id"), AttributeValue::U64(1)),
+                (String::from("dynamic_slot_id"), AttributeValue::U64(12)),
+            ]
+        );
+        // static slot, cgroup v2 with systemd
+        assert_eq!(
+            extract(
+                &tagger,
+                "/system.slice/condor.service/htcondor/condor_var_lib_condor_execute_slot2@node1"
+            ),
+            vec![
+                (String::from("slot"), AttributeValue::String(String::from("slot2"))),
+                (String::from("slot_id"), AttributeValue::U64(2)),
+            ]
+        );
+        // the execute directory can contain "slot"
+        assert_eq!(
+            extract(&tagger, "/htcondor/condor_scratch_slot_dirs_slot3_1@node1"),
+            vec![
+                (String::from("slot"), AttributeValue::String(String::from("slot3_1"))),
+                (String::from("slot_id"), AttributeValue::U64(3)),
+                (String::from("dynamic_slot_id"), AttributeValue::U64(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn not_slots() {
+        let tagger = HtcondorTagger::new(DEFAULT_BASE_CGROUP).unwrap();
+        assert!(extract(&tagger, "/htcondor").is_empty());
+        assert!(extract(&tagger, "/system.slice/condor.service").is_empty());
+        assert!(extract(&tagger, "/htcondor/condor_var_lib_condor_execute_slot1_1@node1/sub").is_empty());
+        assert!(extract(&tagger, "/other/condor_var_lib_condor_execute_slot1_1@node1").is_empty());
+        assert!(extract(&tagger, "/pbs_jobs.service/jobid/1234.server").is_empty());
+
+        let tagger = HtcondorTagger::new("condor_jobs").unwrap();
+        assert!(extract(&tagger, "/htcondor/condor_var_lib_condor_execute_slot1_1@node1").is_empty());
+        assert!(!extract(&tagger, "/condor_jobs/condor_var_lib_condor_execute_slot1_1@node1").is_empty());
+    }
+
+    #[test]
+    fn job_ad_and_cache() {
+        let root = tempfile::tempdir().unwrap();
+        let hierarchy = CgroupHierarchy::manually_unchecked(root.path().join("cgroup"), CgroupVersion::V2, vec!["cpu"]);
+        let mut tagger = HtcondorTagger::new(DEFAULT_BASE_CGROUP)
+            .unwrap()
+            .with_job_ads(JobAdLocator::new(root.path().join("proc")));
+
+        // the job has not been started yet
+        let path = "/htcondor/condor_var_lib_condor_execute_slot1_1@node1";
+        fake_job(root.path(), path, 1200, None);
+        let cgroup = Cgroup::from_cgroup_path(&hierarchy, String::from(path));
+        let attrs = tagger.attributes_for_cgroup(&cgroup);
+        assert_eq!(find_key_in_attrs("slot", &attrs), Some(String::from("slot1_1")));
+        assert_eq!(find_key_in_attrs("job_id", &attrs), None);
+        assert!(!tagger.is_complete(cgroup.canonical_path()));
+
+        // the job has been started
+        fake_job(root.path(), path, 1200, Some(JOB_AD));
+        let attrs = tagger.attributes_for_cgroup(&cgroup);
+        assert_eq!(find_key_in_attrs("job_id", &attrs), Some(String::from("4217.3")));
+        assert_eq!(find_key_in_attrs("owner", &attrs), Some(String::from("alice")));
+        assert!(tagger.is_complete(cgroup.canonical_path()));
+        assert_eq!(tagger.clone().attributes_for_cgroup(&cgroup), attrs);
+
+        // the removal of the cgroup clears the cache, for all the clones
+        tagger.clone().on_cgroups_removed(vec![cgroup.clone()]).unwrap();
+        assert!(!tagger.is_complete(cgroup.canonical_path()));
+
+        // not a slot
+        let cgroup = Cgroup::from_cgroup_path(&hierarchy, String::from("/system.slice/condor.service"));
+        assert!(tagger.attributes_for_cgroup(&cgroup).is_empty());
+    }
+
+    #[test]
+    fn without_job_ads() {
+        let hierarchy = CgroupHierarchy::manually_unchecked("/sys/fs/cgroup", CgroupVersion::V2, vec!["cpu"]);
+        let mut tagger = HtcondorTagger::new(DEFAULT_BASE_CGROUP).unwrap();
+        let cgroup = Cgroup::from_cgroup_path(
+            &hierarchy,
+            String::from("/htcondor/condor_var_lib_condor_execute_slot1_1@node1"),
+        );
+        assert_eq!(tagger.attributes_for_cgroup(&cgroup).len(), 3);
+        assert!(tagger.is_complete(cgroup.canonical_path()));
+    }
+}
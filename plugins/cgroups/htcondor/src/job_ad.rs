@@ -0,0 +1,218 @@
+use std::{
+    ffi::OsStr,
+    fs, io,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use alumet::measurement::AttributeValue;
+
+/// Environment variable in which the starter gives the path of the job ad to the job.
+const JOB_AD_ENV_VAR: &[u8] = b"_CONDOR_JOB_AD=";
+
+/// Information about an HTCondor job, which is not available in the cgroup path.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct JobAd {
+    pub cluster_id: Option<u64>,
+    pub proc_id: Option<u64>,
+    pub owner: Option<String>,
+    pub accounting_group: Option<String>,
+}
+
+impl JobAd {
+    /// Parses a job ClassAd in the "long" format, which contains one `Attribute = Value` per line.
+    ///
+    /// The names of the attributes are case-insensitive.
+    pub fn parse(content: &str) -> JobAd {
+        let mut ad = JobAd::default();
+        for (name, value) in content.lines().filter_map(|line| line.split_once('=')) {
+            let name = name.trim();
+            // string values are quoted
+            let value = value.trim().trim_matches('"');
+            if name.eq_ignore_ascii_case("ClusterId") {
+                ad.cluster_id = value.parse().ok();
+            } else if name.eq_ignore_ascii_case("ProcId") {
+                ad.proc_id = value.parse().ok();
+            } else if name.eq_ignore_ascii_case("Owner") {
+                ad.owner = Some(value.to_owned());
+            } else if name.eq_ignore_ascii_case("AcctGroup") {
+                ad.accounting_group = Some(value.to_owned());
+            }
+        }
+        ad
+    }
+
+    pub fn attributes(&self) -> Vec<(String, AttributeValue)> {
+        let mut attrs = Vec::with_capacity(5);
+        if let (Some(cluster), Some(proc)) = (self.cluster_id, self.proc_id) {
+            // The usual notation of the job ids. Unlike the other job plugins, `job_id` is not a number here,
+            // the numeric parts are given in `cluster_id` and `proc_id`.
+            attrs.push(("job_id".into(), AttributeValue::String(format!("{cluster}.{proc}"))));
+        }
+        if let Some(cluster) = self.cluster_id {
+            attrs.push(("cluster_id".into(), AttributeValue::U64(cluster)));
+        }
+        if let Some(proc) = self.proc_id {
+            attrs.push(("proc_id".into(), AttributeValue::U64(proc)));
+        }
+        if let Some(owner) = &self.owner {
+            attrs.push(("owner".into(), AttributeValue::String(owner.clone())));
+        }
+        if let Some(group) = &self.accounting_group {
+            attrs.push(("accounting_group".into(), AttributeValue::String(group.clone())));
+        }
+        attrs
+    }
+}
+
+/// Finds the job ad of the job that runs in a slot cgroup.
+#[derive(Debug, Clone)]
+pub struct JobAdLocator {
+    proc_root: PathBuf,
+}
+
+impl JobAdLocator {
+    pub fn new(proc_root: impl Into<PathBuf>) -> Self {
+        Self {
+            proc_root: proc_root.into(),
+        }
+    }
+
+    /// Looks for the job ad in the environment of the processes of the cgroup.
+    ///
+    /// Returns `Ok(None)` if no process of the cgroup has a job ad, which happens before the job has been started.
+    pub fn find(&self, cgroup_fs_path: &Path) -> io::Result<Option<JobAd>> {
+        let procs = fs::read_to_string(cgroup_fs_path.join("cgroup.procs"))?;
+        for pid in procs.lines().map(str::trim).filter(|pid| !pid.is_empty()) {
+            let proc_dir = self.proc_root.join(pid);
+            let environ = match fs::read(proc_dir.join("environ")) {
+                Ok(environ) => environ,
+                // the process has exited in the meantime
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            let Some(ad_path) = environ
+                .split(|b| *b == 0)
+                .find_map(|var| var.strip_prefix(JOB_AD_ENV_VAR))
+            else {
+                continue;
+            };
+            // The path is relative to the root of the process, which is different for the jobs that run in containers.
+            let ad_path = Path::new(OsStr::from_bytes(ad_path));
+            let ad_path = proc_dir.join("root").join(ad_path.strip_prefix("/").unwrap_or(ad_path));
+            let content = fs::read_to_string(ad_path)?;
+            return Ok(Some(JobAd::parse(&content)));
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    pub const JOB_AD: &str = r#"Args = "--epochs 10"
+ClusterId = 4217
+Cmd = "/home/alice/train.sh"
+GlobalJobId = "submit.example.org#4217.3#1760000000"
+JobUniverse = 5
+Owner = "alice"
+AcctGroup = "physics"
+ProcId = 3
+RequestCpus = 4
+"#;
+
+    /// Creates a fake cgroup and a fake procfs, with two processes: `pid` runs the job (if there is a job ad)
+    /// and `pid + 1` does not.
+    pub fn fake_job(root: &Path, cgroup_path: &str, pid: u32, job_ad: Option<&str>) -> PathBuf {
+        let cgroup_dir = root.join("cgroup").join(cgroup_path.trim_start_matches('/'));
+        fs::create_dir_all(&cgroup_dir).unwrap();
+        fs::write(cgroup_dir.join("cgroup.procs"), format!("{}\n{pid}\n", pid + 1)).unwrap();
+
+        let proc_dir = root.join("proc");
+        let other_dir = proc_dir.join((pid + 1).to_string());
+        fs::create_dir_all(&other_dir).unwrap();
+        fs::write(other_dir.join("environ"), b"PATH=/usr/bin\0HOME=/\0").unwrap();
+        if let Some(job_ad) = job_ad {
+            let job_dir = proc_dir.join(pid.to_string());
+            let scratch_dir = format!("/var/lib/condor/execute/dir_{pid}");
+            fs::create_dir_all(job_dir.join(format!("root{scratch_dir}"))).unwrap();
+            fs::write(job_dir.join(format!("root{scratch_dir}/.job.ad")), job_ad).unwrap();
+            let environ = format!("_CONDOR_SCRATCH_DIR={scratch_dir}\0_CONDOR_JOB_AD={scratch_dir}/.job.ad\0");
+            fs::write(job_dir.join("environ"), environ).unwrap();
+        }
+        cgroup_dir
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            JobAd::parse(JOB_AD),
+            JobAd {
+                cluster_id: Some(4217),
+                proc_id: Some(3),
+                owner: Some(String::from("alice")),
+                accounting_group: Some(String::from("physics")),
+            }
+        );
+        assert_eq!(
+            JobAd::parse("clusterid = 12\nPROCID = 0"),
+            JobAd {
+                cluster_id: Some(12),
+                proc_id: Some(0),
+                ..Default::default()
+            }
+        );
+        assert_eq!(JobAd::parse(""), JobAd::default());
+    }
+
+    #[test]
+    fn attributes() {
+        let ad = JobAd::parse(JOB_AD);
+        assert_eq!(
+            ad.attributes(),
+            vec![
+                (String::from("job_id"), AttributeValue::String(String::from("4217.3"))),
+                (String::from("cluster_id"), AttributeValue::U64(4217)),
+                (String::from("proc_id"), AttributeValue::U64(3)),
+                (String::from("owner"), AttributeValue::String(String::from("alice"))),
+                (
+                    String::from("accounting_group"),
+                    AttributeValue::String(String::from("physics"))
+                ),
+            ]
+        );
+        assert!(JobAd::default().attributes().is_empty());
+    }
+
+    #[test]
+    fn locate() {
+        let root = tempfile::tempdir().unwrap();
+        let locator = JobAdLocator::new(root.path().join("proc"));
+
+        let cgroup_dir = fake_job(
+            root.path(),
+            "/htcondor/condor_var_lib_condor_execute_slot1_1@node1",
+            1200,
+            Some(JOB_AD),
+        );
+        let ad = locator.find(&cgroup_dir).unwrap().unwrap();
+        assert_eq!(ad.cluster_id, Some(4217));
+
+        // the job has not started yet
+        let cgroup_dir = fake_job(
+            root.path(),
+            "/htcondor/condor_var_lib_condor_execute_slot1_2@node1",
+            1300,
+            None,
+        );
+        assert_eq!(locator.find(&cgroup_dir).unwrap(), None);
+
+        // the cgroup does not exist
+        locator
+            .find(&root.path().join("cgroup/htcondor/missing"))
+            .expect_err("cgroup.procs does not exist");
+    }
+}
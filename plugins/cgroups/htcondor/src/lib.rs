@@ -0,0 +1,237 @@
+use std::time::Duration;
+
+use alumet::plugin::{
+    AlumetPluginStart, AlumetPostStart, ConfigTable,
+    rust::{AlumetPlugin, deserialize_config, serialize_config},
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use util_cgroups_plugins::{
+    cgroup_events::{CgroupReactor, ReactorCallbacks, ReactorConfig},
+    job_annotation_transform::{
+        CachedCgroupHierarchy, JobAnnotationTransform, OptionalSharedHierarchy, SharedCgroupHierarchy,
+    },
+    metrics::Metrics,
+};
+
+use crate::{
+    attr::{DEFAULT_BASE_CGROUP, HtcondorTagger},
+    job_ad::JobAdLocator,
+    transform::JobAdAttacher,
+};
+
+mod attr;
+mod job_ad;
+mod source;
+mod transform;
+
+/// Mount point of procfs, which contains the environment of the jobs.
+const PROC_ROOT: &str = "/proc";
+
+/// Gathers metrics for HTCondor jobs.
+///
+/// Supports HTCondor on cgroup v1 or cgroup v2.
+pub struct HtcondorPlugin {
+    pub config: Option<Config>,
+    /// Intermediary state for startup.
+    pub starting_state: Option<StartingState>,
+    /// The reactor that is running in the background. Dropping it will stop it.
+    pub reactor: Option<CgroupReactor>,
+}
+
+impl HtcondorPlugin {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config: Some(config),
+            reactor: None,
+            starting_state: None,
+        }
+    }
+}
+
+impl AlumetPlugin for HtcondorPlugin {
+    fn name() -> &'static str {
+        "htcondor"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config: Config = deserialize_config(config)?;
+        Ok(Box::new(Self::new(config)))
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        let config = serialize_config(Config::default())?;
+        Ok(Some(config))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let config = self.config.take().unwrap();
+
+        let mut tagger = HtcondorTagger::new(&config.base_cgroup).context("invalid base_cgroup in the config")?;
+        if config.read_job_ads {
+            tagger = tagger.with_job_ads(JobAdLocator::new(PROC_ROOT));
+        }
+        let mut shared_hierarchy = OptionalSharedHierarchy::default();
+
+        // The job ads are read in the background: add their attributes to the measurements of the slots.
+        if config.read_job_ads {
+            let transform = JobAdAttacher::new(tagger.clone());
+            alumet.add_transform("htcondor-job-ads", Box::new(transform))?;
+        }
+
+        // If enabled, create the annotation transform.
+        if config.annotate_foreign_measurements {
+            let shared = SharedCgroupHierarchy::default();
+            shared_hierarchy.enable(shared.clone());
+
+            let transform = JobAnnotationTransform {
+                tagger: tagger.clone(),
+                cgroup_v2_hierarchy: CachedCgroupHierarchy::new(shared),
+            };
+            alumet.add_transform("htcondor-annotation", Box::new(transform))?;
+        }
+
+        // Prepare for cgroup detection.
+        let starting_state = StartingState {
+            metrics: Metrics::create(alumet)?.with_memory_stat_keys(config.memory_stat_keys.clone()),
+            reactor_config: ReactorConfig {
+                v1_refresh_interval: config.cgroupv1_refresh_interval,
+                add_source_in_pause_state: config.add_source_in_pause_state,
+                ..Default::default()
+            },
+            source_setup: source::SlotSourceSetup::new(&config, tagger.clone()),
+            tagger,
+            shared_hierarchy,
+        };
+        self.starting_state = Some(starting_state);
+        Ok(())
+    }
+
+    fn post_pipeline_start(&mut self, alumet: &mut AlumetPostStart) -> anyhow::Result<()> {
+        let s = self.starting_state.take().unwrap();
+
+        let reactor = CgroupReactor::new(
+            s.reactor_config,
+            s.metrics,
+            ReactorCallbacks {
+                probe_setup: s.source_setup,
+                on_removal: s.tagger,
+                on_fs_mount: s.shared_hierarchy,
+            },
+            alumet.pipeline_control(),
+        )
+        .context("failed to init CgroupReactor")?;
+        self.reactor = Some(reactor);
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        if let Some(reactor) = self.reactor.take() {
+            drop(reactor);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Config {
+    /// Interval between two measurements.
+    #[serde(with = "humantime_serde")]
+    pub poll_interval: Duration,
+
+    /// Interval between two scans of the cgroup v1 hierarchies.
+    /// Only used as a fallback, when a cgroup v1 hierarchy cannot be watched with inotify.
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    pub cgroupv1_refresh_interval: Option<Duration>,
+
+    /// Name of the parent cgroup of the slots, as configured in the `BASE_CGROUP` knob of HTCondor.
+    /// The default value is `htcondor`.
+    #[serde(default = "default_base_cgroup")]
+    pub base_cgroup: String,
+
+    /// Only monitor the cgroups related to HTCondor jobs.
+    pub ignore_non_jobs: bool,
+
+    /// If `true`, reads the job ad of each slot to add the id and the owner of its job to the measurements.
+    /// The default value is `true`.
+    ///
+    /// The job ad is found through the environment of the processes of the job, which requires root privileges.
+    #[serde(default = "default_true")]
+    pub read_job_ads: bool,
+
+    /// Maximum time to wait for the job to start in a new slot cgroup, when `read_job_ads` is enabled.
+    /// The default value is 2 seconds.
+    ///
+    /// The job is waited for in the background: the measurements of the slot get the attributes of the job
+    /// once it has been found.
+    #[serde(with = "humantime_serde", default = "default_job_ad_timeout")]
+    pub job_ad_timeout: Duration,
+
+    /// If `true`, the HTCondor sources will be started in pause state.
+    /// The default value is `false`.
+    ///
+    /// This behavior is necessary to have fine-grained control over which cgroup to monitor,
+    /// with a control plugin that manages the state of the sources.
+    #[serde(default)]
+    pub add_source_in_pause_state: bool,
+
+    /// If `true`, adds attributes like `job_id` to the measurements produced by other plugins.
+    /// The default value is `false`.
+    ///
+    /// The measurements must have the `cgroup` resource consumer, and **cgroup v2** must be used on the node.
+    #[serde(default)]
+    pub annotate_foreign_measurements: bool,
+
+    /// Additional keys of `memory.stat` to measure, like `file_mapped` or `pgmajfault`.
+    /// The default value is empty.
+    ///
+    /// With **cgroup v1**, use the keys of cgroup v1, like `total_mapped_file` or `total_pgmajfault`.
+    #[serde(default)]
+    pub memory_stat_keys: Vec<String>,
+}
+
+#[cfg_attr(tarpaulin, ignore)]
+fn default_base_cgroup() -> String {
+    String::from(DEFAULT_BASE_CGROUP)
+}
+
+#[cfg_attr(tarpaulin, ignore)]
+fn default_true() -> bool {
+    true
+}
+
+#[cfg_attr(tarpaulin, ignore)]
+fn default_job_ad_timeout() -> Duration {
+    Duration::from_secs(2)
+}
+
+impl Default for Config {
+    #[cfg_attr(tarpaulin, ignore)]
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(1),
+            cgroupv1_refresh_interval: None,
+            base_cgroup: default_base_cgroup(),
+            ignore_non_jobs: true,
+            read_job_ads: true,
+            job_ad_timeout: default_job_ad_timeout(),
+            add_source_in_pause_state: false,
+            annotate_foreign_measurements: false,
+            memory_stat_keys: Vec::new(),
+        }
+    }
+}
+
+pub struct StartingState {
+    metrics: Metrics,
+    reactor_config: ReactorConfig,
+    source_setup: source::SlotSourceSetup,
+    tagger: HtcondorTagger,
+    shared_hierarchy: OptionalSharedHierarchy,
+}
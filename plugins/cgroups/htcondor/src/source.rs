@@ -0,0 +1,99 @@
+use std::time::{Duration, Instant};
+
+use alumet::pipeline::elements::source::trigger::TriggerSpec;
+use util_cgroups::Cgroup;
+
+use crate::{
+    Config,
+    attr::{HtcondorTagger, find_key_in_attrs},
+};
+use util_cgroups_plugins::{
+    cgroup_events::{CgroupSetupCallback, ProbeSetup, SourceSettings},
+    job_annotation_transform::JobTagger,
+    metrics::{AugmentedMetrics, Metrics},
+};
+
+/// Interval between two attempts to read the job ad of a new slot.
+const JOB_AD_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone)]
+pub struct SlotSourceSetup {
+    tagger: HtcondorTagger,
+    trigger: TriggerSpec,
+    ignore_non_jobs: bool,
+    job_ad_timeout: Duration,
+}
+
+impl SlotSourceSetup {
+    pub fn new(config: &Config, tagger: HtcondorTagger) -> Self {
+        Self {
+            tagger,
+            trigger: TriggerSpec::at_interval(config.poll_interval),
+            ignore_non_jobs: config.ignore_non_jobs,
+            job_ad_timeout: config.job_ad_timeout,
+        }
+    }
+
+    /// Waits for the job of a slot cgroup in the background.
+    ///
+    /// The starter creates the cgroup before starting the job in it. Once the job ad has been read, the attributes
+    /// of the job are in the cache of the tagger, and the [`JobAdAttacher`](crate::transform::JobAdAttacher) adds
+    /// them to the measurements of the slot.
+    /// The reactor thread must not be blocked in the meantime, because it creates the sources of all the cgroups.
+    fn wait_for_job(&self, cgroup: &Cgroup) {
+        let mut tagger = self.tagger.clone();
+        let path = cgroup.canonical_path().to_owned();
+        let fs_path = cgroup.fs_path().to_owned();
+        let timeout = self.job_ad_timeout;
+        let res = std::thread::Builder::new()
+            .name(String::from("htcondor-job-ad"))
+            .spawn(move || {
+                let deadline = Instant::now() + timeout;
+                while Instant::now() < deadline {
+                    std::thread::sleep(JOB_AD_RETRY_INTERVAL);
+                    if !fs_path.exists() {
+                        // the slot has already been removed
+                        return;
+                    }
+                    tagger.attributes_for_path(&path, &fs_path);
+                    if tagger.is_complete(&path) {
+                        return;
+                    }
+                }
+                log::warn!(
+                    "no job found in slot cgroup {path} after {timeout:?}, its measurements will not have the attributes of the job"
+                );
+            });
+        if let Err(e) = res {
+            log::error!(
+                "failed to spawn a thread to wait for the job of {}: {e}",
+                cgroup.canonical_path()
+            );
+        }
+    }
+}
+
+impl CgroupSetupCallback for SlotSourceSetup {
+    fn setup_new_probe(&mut self, cgroup: &Cgroup, metrics: &Metrics) -> Option<ProbeSetup> {
+        let attrs = self.tagger.attributes_for_cgroup(cgroup);
+        let (name, attrs) = match find_key_in_attrs("slot", &attrs) {
+            // use the slot name as the source name, there is only one job at a time in a slot
+            Some(slot) => {
+                if !self.tagger.is_complete(cgroup.canonical_path()) {
+                    self.wait_for_job(cgroup);
+                }
+                (slot, attrs)
+            }
+            None if self.ignore_non_jobs => return None,
+            None => (format!("cgroup {}", cgroup.unique_name()), attrs),
+        };
+
+        let trigger = self.trigger.clone();
+        let source_settings = SourceSettings { name, trigger };
+        let metrics = AugmentedMetrics::with_common_attr_vec(metrics, attrs);
+        Some(ProbeSetup {
+            metrics,
+            source_settings,
+        })
+    }
+}
@@ -0,0 +1,121 @@
+use alumet::{
+    measurement::MeasurementBuffer,
+    pipeline::{
+        Transform,
+        elements::{error::TransformError, transform::TransformContext},
+    },
+    resources::ResourceConsumer,
+};
+
+use crate::attr::HtcondorTagger;
+
+/// Adds the attributes of the jobs to the measurements of their slots.
+///
+/// The source of a slot is created as soon as its cgroup appears, before the job is started in it.
+/// The job ad is then read in the background, and its attributes are added here to the measurements
+/// that don't have them yet.
+pub struct JobAdAttacher {
+    tagger: HtcondorTagger,
+}
+
+impl JobAdAttacher {
+    pub fn new(tagger: HtcondorTagger) -> Self {
+        Self { tagger }
+    }
+}
+
+impl Transform for JobAdAttacher {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, _ctx: &TransformContext) -> Result<(), TransformError> {
+        self.attach(measurements);
+        Ok(())
+    }
+}
+
+impl JobAdAttacher {
+    fn attach(&self, measurements: &mut MeasurementBuffer) {
+        for m in measurements.iter_mut() {
+            if let ResourceConsumer::ControlGroup { path } = &m.consumer
+                && m.attributes_keys().any(|k| k == "slot")
+                && !m.attributes_keys().any(|k| k == "job_id")
+                && let Some(attrs) = self.tagger.complete_attributes(path)
+            {
+                for (key, value) in attrs {
+                    if !m.attributes_keys().any(|k| k == key) {
+                        m.add_attr(key, value);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alumet::{
+        measurement::{AttributeValue, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        resources::Resource,
+    };
+    use pretty_assertions::assert_eq;
+    use util_cgroups::{Cgroup, CgroupHierarchy, CgroupVersion};
+    use util_cgroups_plugins::job_annotation_transform::JobTagger;
+
+    use super::*;
+    use crate::{
+        attr::DEFAULT_BASE_CGROUP,
+        job_ad::{
+            JobAdLocator,
+            tests::{JOB_AD, fake_job},
+        },
+    };
+
+    #[test]
+    fn attach_job_ad() {
+        let root = tempfile::tempdir().unwrap();
+        let hierarchy = CgroupHierarchy::manually_unchecked(root.path().join("cgroup"), CgroupVersion::V2, vec!["cpu"]);
+        let mut tagger = HtcondorTagger::new(DEFAULT_BASE_CGROUP)
+            .unwrap()
+            .with_job_ads(JobAdLocator::new(root.path().join("proc")));
+        let attacher = JobAdAttacher::new(tagger.clone());
+
+        // the source of the slot is created before the job is started
+        let path = "/htcondor/condor_var_lib_condor_execute_slot1_1@node1";
+        fake_job(root.path(), path, 1200, None);
+        let cgroup = Cgroup::from_cgroup_path(&hierarchy, String::from(path));
+        let slot_attrs = tagger.attributes_for_cgroup(&cgroup);
+
+        let point = || {
+            MeasurementPoint::new_untyped(
+                Timestamp::now(),
+                RawMetricId::from_u64(0),
+                Resource::LocalMachine,
+                ResourceConsumer::ControlGroup { path: path.into() },
+                WrappedMeasurementValue::U64(1),
+            )
+            .with_attr_vec(slot_attrs.clone())
+        };
+        let attributes = |buf: &MeasurementBuffer| -> Vec<(String, AttributeValue)> {
+            let m = buf.iter().next().unwrap();
+            m.attributes().map(|(k, v)| (k.to_owned(), v.clone())).collect()
+        };
+
+        let mut buf = MeasurementBuffer::new();
+        buf.push(point());
+        attacher.attach(&mut buf);
+        assert_eq!(attributes(&buf), slot_attrs);
+
+        // the job ad has been read in the background
+        fake_job(root.path(), path, 1200, Some(JOB_AD));
+        let complete_attrs = tagger.attributes_for_cgroup(&cgroup);
+        assert!(complete_attrs.len() > slot_attrs.len());
+
+        let mut buf = MeasurementBuffer::new();
+        buf.push(point());
+        attacher.attach(&mut buf);
+        assert_eq!(attributes(&buf), complete_attrs);
+
+        // the measurements that already have the job are not modified
+        attacher.attach(&mut buf);
+        assert_eq!(attributes(&buf), complete_attrs);
+    }
+}
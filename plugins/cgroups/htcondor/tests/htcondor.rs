@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+use alumet::plugin::rust::{AlumetPlugin, deserialize_config, serialize_config};
+use plugin_htcondor::{Config, HtcondorPlugin};
+
+#[test]
+fn test_default_config() {
+    let config_table = HtcondorPlugin::default_config().unwrap().unwrap();
+    let config: Config = deserialize_config(config_table).expect("failed to deserialize the default config");
+
+    assert!(config.ignore_non_jobs);
+    assert!(config.read_job_ads);
+    assert_eq!(config.base_cgroup, "htcondor");
+    assert_eq!(config.job_ad_timeout, Duration::from_secs(2));
+}
+
+#[test]
+fn test_init() -> anyhow::Result<()> {
+    let config_table = serialize_config(Config::default())?;
+    let plugin = HtcondorPlugin::init(config_table)?;
+    assert!(plugin.reactor.is_none());
+    assert!(plugin.starting_state.is_none());
+    Ok(())
+}
+
+#[test]
+fn test_optional_config() -> anyhow::Result<()> {
+    // the options that have a default value can be omitted
+    let mut config_table = serialize_config(Config::default())?;
+    for key in ["base_cgroup", "read_job_ads", "job_ad_timeout", "memory_stat_keys"] {
+        config_table.0.remove(key);
+    }
+    HtcondorPlugin::init(config_table)?;
+    Ok(())
+}
+
+#[test]
+fn test_stop() {
+    let mut plugin = HtcondorPlugin::new(Config::default());
+    plugin.stop().expect("stop should complete without errors");
+}
@@ -10,24 +10,29 @@ use serde::{Deserialize, Serialize};
 use crate::{
     pods::{ApiClient, AutoNodePodRegistry},
     token::{Token, TokenRetrievalConfig},
+    watch::PodWatcher,
 };
 use source::SourceSetup;
 use util_cgroups_plugins::{
+    aggregation::{AggregationConfig, CgroupAggregation, setup_aggregation},
     cgroup_events::{CgroupReactor, NoCallback, ReactorCallbacks, ReactorConfig},
     job_annotation_transform::{
         CachedCgroupHierarchy, JobAnnotationTransform, OptionalSharedHierarchy, SharedCgroupHierarchy,
     },
+    job_summary::{JobSummaries, JobSummaryConfig, setup_job_summaries},
     metrics::Metrics,
 };
 
 mod pods;
 mod source;
 mod token;
+mod watch;
 
 pub struct K8sPlugin {
     config: Config,
     starting_state: Option<StartingState>,
     reactor: Option<CgroupReactor>,
+    pod_watcher: Option<PodWatcher>,
 }
 
 impl AlumetPlugin for K8sPlugin {
@@ -45,6 +50,7 @@ impl AlumetPlugin for K8sPlugin {
             config,
             starting_state: None,
             reactor: None,
+            pod_watcher: None,
         }))
     }
 
@@ -53,7 +59,7 @@ impl AlumetPlugin for K8sPlugin {
     }
 
     fn start(&mut self, alumet: &mut alumet::plugin::AlumetPluginStart) -> anyhow::Result<()> {
-        let metrics = Metrics::create(alumet)?;
+        let metrics = Metrics::create(alumet)?.with_memory_stat_keys(self.config.memory_stat_keys.clone());
         let reactor_config = ReactorConfig::default();
         let mut shared_hierarchy = OptionalSharedHierarchy::default();
 
@@ -61,13 +67,20 @@ impl AlumetPlugin for K8sPlugin {
         let node = self.config.k8s_node_name();
         let api_token = Token::new(self.config.token_retrieval.clone().into());
         let api_client = ApiClient::new(&self.config.k8s_api_url, api_token)
-            .context("failed to create http client for communicating with the K8S API")?;
-        let mut pod_registry = AutoNodePodRegistry::new(node, api_client);
-        pod_registry
-            .refresh()
+            .context("failed to create http client for communicating with the K8S API")?
+            .with_selected_metadata(self.config.pod_labels.clone(), self.config.pod_annotations.clone());
+        let mut pod_registry = AutoNodePodRegistry::new(node, api_client).resolve_owners(self.config.resolve_owners);
+        let resource_version = pod_registry
+            .relist()
             .context("failed to list pods with the K8S API, are the url and token correct?")?;
         log::info!("List of pods refreshed.");
 
+        // Keep the list up to date with the watch API, instead of listing the pods each time an unknown pod appears.
+        if self.config.watch_pods {
+            let watcher = PodWatcher::spawn(pod_registry.clone(), resource_version)?;
+            self.pod_watcher = Some(watcher);
+        }
+
         // If enabled, create the annotation transform.
         if self.config.annotate_foreign_measurements {
             let shared = SharedCgroupHierarchy::default();
@@ -80,11 +93,25 @@ impl AlumetPlugin for K8sPlugin {
             alumet.add_transform("k8s-annotation", Box::new(transform))?;
         }
 
+        // If enabled, summarize the consumption of each pod when it ends.
+        let job_summaries = match self.config.job_summary.clone() {
+            Some(summary_config) => Some(setup_job_summaries(alumet, summary_config, &metrics)?),
+            None => None,
+        };
+
+        // If enabled, aggregate the measurements of the pods, for instance by namespace.
+        let aggregation = match self.config.aggregation.clone() {
+            Some(aggregation_config) => Some(setup_aggregation(alumet, aggregation_config, &metrics)?),
+            None => None,
+        };
+
         // store the state for later, because we cannot set up everything now
         let starting_state = StartingState {
             metrics,
             reactor_config,
             pod_registry,
+            job_summaries,
+            aggregation,
         };
         self.starting_state = Some(starting_state);
         Ok(())
@@ -98,6 +125,8 @@ impl AlumetPlugin for K8sPlugin {
         let probe_setup = SourceSetup {
             trigger,
             k8s_pods: s.pod_registry,
+            monitor_containers: self.config.monitor_containers,
+            job_summaries: s.job_summaries.clone(),
         };
 
         let reactor = CgroupReactor::new(
@@ -105,7 +134,7 @@ impl AlumetPlugin for K8sPlugin {
             s.metrics,
             ReactorCallbacks {
                 probe_setup,
-                on_removal: NoCallback,
+                on_removal: (s.job_summaries, s.aggregation),
                 on_fs_mount: NoCallback,
             },
             alumet.pipeline_control(),
@@ -118,6 +147,7 @@ impl AlumetPlugin for K8sPlugin {
 
     fn stop(&mut self) -> anyhow::Result<()> {
         drop(self.reactor.take().unwrap());
+        drop(self.pod_watcher.take());
         Ok(())
     }
 }
@@ -126,6 +156,8 @@ struct StartingState {
     metrics: Metrics,
     reactor_config: ReactorConfig,
     pod_registry: AutoNodePodRegistry,
+    job_summaries: Option<JobSummaries>,
+    aggregation: Option<CgroupAggregation>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -139,12 +171,54 @@ pub struct Config {
 
     #[serde(with = "humantime_serde")]
     pub poll_interval: Duration,
+    /// If `true`, keeps the list of pods up to date with the watch API of K8S.
+    /// If `false`, lists the pods each time that an unknown pod appears.
+    /// The default value is `true`.
+    #[serde(default = "default_true")]
+    pub watch_pods: bool,
+    /// Keys of the pod labels to add as attributes, like `app.kubernetes.io/name`.
+    /// Each label becomes an attribute `label.<key>`. The default value is empty.
+    #[serde(default)]
+    pub pod_labels: Vec<String>,
+    /// Keys of the pod annotations to add as attributes.
+    /// Each annotation becomes an attribute `annotation.<key>`. The default value is empty.
+    #[serde(default)]
+    pub pod_annotations: Vec<String>,
+    /// If `true`, finds the workload that owns each pod, like a Deployment, through its ReplicaSet.
+    /// This requires the permission to get the ReplicaSets.
+    /// The default value is `true`.
+    #[serde(default = "default_true")]
+    pub resolve_owners: bool,
+    /// If `true`, measures each container of the pods, in addition to the pods.
+    /// The default value is `false`.
+    #[serde(default)]
+    pub monitor_containers: bool,
     /// If `true`, adds attributes like `job_id` to the measurements produced by other plugins.
     /// The default value is `false`.
     ///
     /// The measurements must have the `cgroup` resource consumer, and **cgroup v2** must be used on the node.
     #[serde(default)]
     pub annotate_foreign_measurements: bool,
+    /// Additional keys of `memory.stat` to measure, like `file_mapped` or `pgmajfault`.
+    /// The default value is empty.
+    ///
+    /// With **cgroup v1**, use the keys of cgroup v1, like `total_mapped_file` or `total_pgmajfault`.
+    #[serde(default)]
+    pub memory_stat_keys: Vec<String>,
+    /// If set, emits a summary of the consumption of each pod (energy, CPU time, peak memory) when it ends.
+    /// The default value is `None` (disabled).
+    #[serde(default)]
+    pub job_summary: Option<JobSummaryConfig>,
+    /// If set, aggregates the measurements of the pods by the given attributes, like `namespace`,
+    /// with `inclusive` and `exclusive` values.
+    /// The default value is `None` (disabled).
+    #[serde(default)]
+    pub aggregation: Option<AggregationConfig>,
+}
+
+#[cfg_attr(tarpaulin, ignore)]
+fn default_true() -> bool {
+    true
 }
 
 #[cfg_attr(tarpaulin, ignore)]
@@ -160,7 +234,15 @@ impl Default for Config {
             k8s_api_url: default_k8s_api_url(),
             token_retrieval: TokenRetrievalConfig::Simple(token::SimpleRetrievalMethod::Auto),
             poll_interval: Duration::from_secs(5),
+            watch_pods: true,
+            pod_labels: Vec::new(),
+            pod_annotations: Vec::new(),
+            resolve_owners: true,
+            monitor_containers: false,
             annotate_foreign_measurements: false,
+            memory_stat_keys: Vec::new(),
+            job_summary: None,
+            aggregation: None,
         }
     }
 }
@@ -1,19 +1,40 @@
-use std::path::Path;
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader},
+    ops::ControlFlow,
+    path::Path,
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, RecvTimeoutError},
+    },
+    time::Duration,
+};
 
 use alumet::measurement::AttributeValue;
-use anyhow::Context;
+use anyhow::{Context, anyhow};
 use rustc_hash::FxHashMap;
 use util_cgroups_plugins::job_annotation_transform::JobTagger;
 
 use super::token::Token;
 use api::PodList;
 
+/// Additional time given to the server to close a watch, after the `timeoutSeconds` that we have requested.
+const WATCH_GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+/// How often a watch checks whether it must stop, while it waits for the next event.
+const STOP_CHECK_PERIOD: Duration = Duration::from_millis(100);
+
 /// Kubernetes API client (limited capabilities, just what we need).
 #[derive(Clone)]
 pub struct ApiClient {
     client: reqwest::blocking::Client,
     auth_token: Token,
+    k8s_api_url: String,
     k8s_api_pods_route: String,
+    /// Keys of the labels to keep in [`PodInfos`].
+    selected_labels: Vec<String>,
+    /// Keys of the annotations to keep in [`PodInfos`].
+    selected_annotations: Vec<String>,
 }
 
 /// Relevant informations about a pod.
@@ -23,31 +44,147 @@ pub struct PodInfos {
     pub name: String,
     pub namespace: String,
     pub node: String,
+    /// The labels of the pod that have been selected in the [`ApiClient`].
+    pub labels: BTreeMap<String, String>,
+    /// The annotations of the pod that have been selected in the [`ApiClient`].
+    pub annotations: BTreeMap<String, String>,
+    /// The object that manages the pod, like a ReplicaSet or a StatefulSet.
+    pub controller: Option<OwnerRef>,
+    /// The names of the containers of the pod, by container id (without the `runtime://` prefix).
+    pub containers: FxHashMap<String, String>,
+}
+
+/// Reference to a K8S object that owns another object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnerRef {
+    pub kind: String,
+    pub name: String,
+}
+
+/// The pods returned by the API, at a given version of the cluster state.
+pub struct PodListing {
+    /// Version from which the changes can be watched, see [`ApiClient::watch_pods`].
+    pub resource_version: Option<String>,
+    pub pods: Vec<PodInfos>,
+}
+
+/// A change of a pod, notified by the watch API.
+#[derive(Debug)]
+pub enum PodEvent {
+    /// The pod has been added or modified.
+    Upsert(PodInfos),
+    /// The pod with this uid has been deleted.
+    Delete(String),
+}
+
+/// Why a watch has ended.
+#[derive(Debug, PartialEq, Eq)]
+pub enum WatchEnd {
+    /// The server has closed the watch, which can be resumed from this version.
+    Closed { resource_version: String },
+    /// The version is too old (HTTP 410 Gone), the pods must be listed again.
+    Expired,
+    /// The watch has been interrupted by the callback.
+    Stopped,
 }
 
 /// Automatically-refreshed pod registry: keep track of the pods on a given node.
+///
+/// The registry can be cloned, the clones share the same state.
 #[derive(Clone)]
 pub struct AutoNodePodRegistry {
     client: ApiClient,
     node: String,
+    resolve_owners: bool,
+    state: Arc<Mutex<RegistryState>>,
+}
+
+#[derive(Default)]
+struct RegistryState {
     // TODO use uuid instead of string to reduce memory consumption
     pods: FxHashMap<String, PodInfos>,
+    /// The owner of each ReplicaSet (usually a Deployment), by (namespace, name).
+    ///
+    /// Only the ReplicaSets that control a pod of the registry are kept.
+    replicaset_owners: FxHashMap<(String, String), Option<OwnerRef>>,
+    /// The containers that we have looked for without finding them, like the sandboxes of the pods.
+    /// The value is the uid of the pod of the container.
+    unknown_containers: FxHashMap<String, String>,
+    /// If true, the pods are kept up to date by a [`PodWatcher`](crate::watch::PodWatcher).
+    watched: bool,
+}
+
+impl RegistryState {
+    /// Forgets what we know about the pods that have been deleted, and about their ReplicaSets.
+    fn evict_deleted_pods(&mut self) {
+        let pods = &self.pods;
+        self.unknown_containers.retain(|id, pod_uid| {
+            // The container may have appeared in the status of its pod.
+            pods.get(pod_uid).is_some_and(|p| !p.containers.contains_key(id))
+        });
+        self.replicaset_owners.retain(|(namespace, name), _| {
+            pods.values().any(|p| {
+                p.namespace == *namespace
+                    && p.controller
+                        .as_ref()
+                        .is_some_and(|c| c.kind == "ReplicaSet" && c.name == *name)
+            })
+        });
+    }
+
+    fn apply(&mut self, event: PodEvent, node: &str) {
+        match event {
+            PodEvent::Upsert(pod) if pod.node == node => {
+                for id in pod.containers.keys() {
+                    self.unknown_containers.remove(id);
+                }
+                self.pods.insert(pod.uid.clone(), pod);
+            }
+            PodEvent::Upsert(pod) => {
+                // the pod has moved to another node (should not happen)
+                self.pods.remove(&pod.uid);
+                self.evict_deleted_pods();
+            }
+            PodEvent::Delete(uid) => {
+                self.pods.remove(&uid);
+                self.evict_deleted_pods();
+            }
+        }
+    }
+}
+
+/// Message sent by the thread that reads a watch request.
+enum WatchMessage {
+    Event(PodEvent),
+    End(anyhow::Result<WatchEnd>),
 }
 
 /// Encoding/decoding of the K8S API responses.
 /// Fields that we don't need are not included, serde will skip them.
 mod api {
+    use std::collections::BTreeMap;
+
     use serde::Deserialize;
 
     #[derive(Deserialize)]
     pub struct PodList {
+        #[serde(default)]
+        pub metadata: ListMeta,
         pub items: Vec<Pod>,
     }
 
+    #[derive(Deserialize, Default)]
+    pub struct ListMeta {
+        #[serde(rename = "resourceVersion")]
+        pub resource_version: Option<String>,
+    }
+
     #[derive(Deserialize)]
     pub struct Pod {
         pub metadata: ObjectMeta,
         pub spec: PodSpec,
+        #[serde(default)]
+        pub status: PodStatus,
     }
 
     #[derive(Deserialize)]
@@ -55,6 +192,20 @@ mod api {
         pub name: String,
         pub namespace: String,
         pub uid: String,
+        #[serde(default)]
+        pub labels: BTreeMap<String, String>,
+        #[serde(default)]
+        pub annotations: BTreeMap<String, String>,
+        #[serde(default, rename = "ownerReferences")]
+        pub owner_references: Vec<OwnerReference>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct OwnerReference {
+        pub kind: String,
+        pub name: String,
+        #[serde(default)]
+        pub controller: bool,
     }
 
     #[derive(Deserialize)]
@@ -62,15 +213,80 @@ mod api {
         #[serde(rename = "nodeName")]
         pub node_name: String,
     }
+
+    #[derive(Deserialize, Default)]
+    pub struct PodStatus {
+        #[serde(default, rename = "containerStatuses")]
+        pub container_statuses: Vec<ContainerStatus>,
+        #[serde(default, rename = "initContainerStatuses")]
+        pub init_container_statuses: Vec<ContainerStatus>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct ContainerStatus {
+        pub name: String,
+        #[serde(rename = "containerID")]
+        pub container_id: Option<String>,
+    }
+
+    /// Any object, for which we only need the owner references.
+    #[derive(Deserialize)]
+    pub struct OwnedObject {
+        pub metadata: OwnedObjectMeta,
+    }
+
+    #[derive(Deserialize)]
+    pub struct OwnedObjectMeta {
+        #[serde(default, rename = "ownerReferences")]
+        pub owner_references: Vec<OwnerReference>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct WatchEvent {
+        #[serde(rename = "type")]
+        pub event_type: String,
+        pub object: serde_json::Value,
+    }
+
+    /// Returns the owner reference that has `controller: true`, if any.
+    pub fn controller(owner_references: Vec<OwnerReference>) -> Option<super::OwnerRef> {
+        owner_references
+            .into_iter()
+            .find(|owner| owner.controller)
+            .map(|owner| super::OwnerRef {
+                kind: owner.kind,
+                name: owner.name,
+            })
+    }
 }
 
-impl From<api::Pod> for PodInfos {
-    fn from(pod: api::Pod) -> Self {
+impl PodInfos {
+    fn from_api(pod: api::Pod, selected_labels: &[String], selected_annotations: &[String]) -> Self {
+        fn select(map: BTreeMap<String, String>, keys: &[String]) -> BTreeMap<String, String> {
+            map.into_iter().filter(|(k, _)| keys.contains(k)).collect()
+        }
+
+        let containers = pod
+            .status
+            .container_statuses
+            .into_iter()
+            .chain(pod.status.init_container_statuses)
+            .filter_map(|c| {
+                // "containerd://{id}", "cri-o://{id}"...
+                let id = c.container_id?.split_once("://")?.1.to_owned();
+                Some((id, c.name))
+            })
+            .collect();
+
         PodInfos {
             uid: pod.metadata.uid,
             name: pod.metadata.name,
             namespace: pod.metadata.namespace,
             node: pod.spec.node_name,
+            labels: select(pod.metadata.labels, selected_labels),
+            annotations: select(pod.metadata.annotations, selected_annotations),
+            controller: api::controller(pod.metadata.owner_references),
+            containers,
         }
     }
 }
@@ -87,11 +303,25 @@ impl ApiClient {
         Ok(Self {
             auth_token,
             client,
+            k8s_api_url: k8s_api_url.to_owned(),
             k8s_api_pods_route,
+            selected_labels: Vec::new(),
+            selected_annotations: Vec::new(),
         })
     }
 
-    pub fn list_pods(&self, node: Option<&str>) -> anyhow::Result<impl Iterator<Item = PodInfos>> {
+    /// Keeps the given labels and annotations in the [`PodInfos`] returned by this client.
+    pub fn with_selected_metadata(mut self, labels: Vec<String>, annotations: Vec<String>) -> Self {
+        self.selected_labels = labels;
+        self.selected_annotations = annotations;
+        self
+    }
+
+    fn pod_infos(&self, pod: api::Pod) -> PodInfos {
+        PodInfos::from_api(pod, &self.selected_labels, &self.selected_annotations)
+    }
+
+    pub fn list_pods_with_version(&self, node: Option<&str>) -> anyhow::Result<PodListing> {
         // get the auth token, refreshed if needed
         let token = self.auth_token.get_value().context("failed to get auth token")?;
 
@@ -107,8 +337,111 @@ impl ApiClient {
         let pods: PodList = response.json().context("failed to parse json response")?;
 
         // turn the response into the format we want
-        let pods = pods.items.into_iter().map(PodInfos::from);
-        Ok(pods)
+        Ok(PodListing {
+            resource_version: pods.metadata.resource_version,
+            pods: pods.items.into_iter().map(|p| self.pod_infos(p)).collect(),
+        })
+    }
+
+    /// Watches the changes of the pods, starting from `resource_version`, and calls `on_event` for each change.
+    ///
+    /// The server closes the watch after `timeout`.
+    /// See <https://kubernetes.io/docs/reference/using-api/api-concepts/#efficient-detection-of-changes>.
+    pub fn watch_pods(
+        &self,
+        node: Option<&str>,
+        resource_version: &str,
+        timeout: Duration,
+        mut on_event: impl FnMut(PodEvent) -> ControlFlow<()>,
+    ) -> anyhow::Result<WatchEnd> {
+        let token = self.auth_token.get_value().context("failed to get auth token")?;
+
+        let mut req = self
+            .client
+            .get(&self.k8s_api_pods_route)
+            .bearer_auth(token)
+            .query(&[
+                ("watch", "true"),
+                ("allowWatchBookmarks", "true"),
+                ("resourceVersion", resource_version),
+            ])
+            .query(&[("timeoutSeconds", timeout.as_secs())])
+            .timeout(timeout + WATCH_GRACE_PERIOD);
+        if let Some(node) = node {
+            req = req.query(&[("fieldSelector", format!("spec.nodeName={}", node))]);
+        }
+
+        let response = req.send().context("failed to send http request")?;
+        match response.status() {
+            reqwest::StatusCode::GONE => return Ok(WatchEnd::Expired),
+            status if !status.is_success() => return Err(anyhow!("unexpected status {status}")),
+            _ => (),
+        }
+
+        // The response is a stream of events, one JSON object per line.
+        let mut resource_version = resource_version.to_owned();
+        for line in BufReader::new(response).lines() {
+            let line = line.context("failed to read watch event")?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event: api::WatchEvent = serde_json::from_str(&line).context("failed to parse watch event")?;
+            log::trace!("watch event: {}", event.event_type);
+            if event.event_type == "ERROR" {
+                // The object is a Status, 410 means that the resource version is too old.
+                return match event.object.get("code").and_then(|c| c.as_u64()) {
+                    Some(410) => Ok(WatchEnd::Expired),
+                    _ => Err(anyhow!("error in watch: {}", event.object)),
+                };
+            }
+
+            if let Some(version) = event
+                .object
+                .pointer("/metadata/resourceVersion")
+                .and_then(|v| v.as_str())
+            {
+                resource_version = version.to_owned();
+            }
+            let pod_event = match event.event_type.as_str() {
+                "ADDED" | "MODIFIED" => {
+                    let pod: api::Pod = serde_json::from_value(event.object).context("invalid pod in watch event")?;
+                    PodEvent::Upsert(self.pod_infos(pod))
+                }
+                "DELETED" => {
+                    let pod: api::Pod = serde_json::from_value(event.object).context("invalid pod in watch event")?;
+                    PodEvent::Delete(pod.metadata.uid)
+                }
+                // BOOKMARK only updates the resource version
+                _ => continue,
+            };
+            if on_event(pod_event).is_break() {
+                return Ok(WatchEnd::Stopped);
+            }
+        }
+        Ok(WatchEnd::Closed { resource_version })
+    }
+
+    /// Returns the controller of a ReplicaSet, or `None` if the ReplicaSet has no controller or does not exist.
+    pub fn replicaset_controller(&self, namespace: &str, name: &str) -> anyhow::Result<Option<OwnerRef>> {
+        let token = self.auth_token.get_value().context("failed to get auth token")?;
+        let url = format!(
+            "{}/apis/apps/v1/namespaces/{namespace}/replicasets/{name}",
+            self.k8s_api_url
+        );
+        let response = self
+            .client
+            .get(url)
+            .bearer_auth(token)
+            .send()
+            .context("failed to send http request")?;
+        match response.status() {
+            reqwest::StatusCode::NOT_FOUND => Ok(None),
+            status if !status.is_success() => Err(anyhow!("unexpected status {status}")),
+            _ => {
+                let replicaset: api::OwnedObject = response.json().context("failed to parse json response")?;
+                Ok(api::controller(replicaset.metadata.owner_references))
+            }
+        }
     }
 }
 
@@ -117,36 +450,185 @@ impl AutoNodePodRegistry {
         Self {
             client: k8s_api_client,
             node,
-            pods: Default::default(),
+            resolve_owners: true,
+            state: Default::default(),
         }
     }
 
+    /// Enables or disables the resolution of the ReplicaSets to their Deployment.
+    pub fn resolve_owners(mut self, enabled: bool) -> Self {
+        self.resolve_owners = enabled;
+        self
+    }
+
+    /// Tells the registry whether the pods are kept up to date by a [`PodWatcher`](crate::watch::PodWatcher).
+    ///
+    /// When they are, the registry does not list the pods again when it does not find a pod or a container.
+    pub fn set_watched(&self, watched: bool) {
+        self.state.lock().unwrap().watched = watched;
+    }
+
     pub fn refresh(&mut self) -> anyhow::Result<()> {
-        let all_pods = self
+        self.relist().map(|_| ())
+    }
+
+    /// Lists all the pods of the node and replaces the state of the registry.
+    ///
+    /// Returns the version from which the changes can be watched.
+    pub fn relist(&mut self) -> anyhow::Result<Option<String>> {
+        let listing = self
             .client
-            .list_pods(Some(&self.node))
+            .list_pods_with_version(Some(&self.node))
             .with_context(|| format!("failed to list K8S pods on node {}", self.node))?;
-        self.pods = all_pods
+        let pods = listing
+            .pods
+            .into_iter()
             .filter(|p| p.node == self.node)
             .map(|p| (p.uid.clone(), p))
             .collect();
-        Ok(())
+        let mut state = self.state.lock().unwrap();
+        state.pods = pods;
+        state.evict_deleted_pods();
+        Ok(listing.resource_version)
+    }
+
+    /// Watches the pods of the node from the given version and applies the changes to the registry,
+    /// until the watch ends or `stop` returns `true`.
+    pub fn watch(
+        &mut self,
+        resource_version: &str,
+        timeout: Duration,
+        stop: impl Fn() -> bool,
+    ) -> anyhow::Result<WatchEnd> {
+        // The request blocks until the next event, so it is read by another thread: this allows to stop
+        // without waiting for the server. That thread ends at the next event, or at the end of the request.
+        let (tx, rx) = mpsc::channel();
+        let client = self.client.clone();
+        let node = self.node.clone();
+        let version = resource_version.to_owned();
+        std::thread::Builder::new()
+            .name(String::from("k8s-watch-request"))
+            .spawn(move || {
+                let end = client.watch_pods(Some(&node), &version, timeout, |event| {
+                    match tx.send(WatchMessage::Event(event)) {
+                        Ok(()) => ControlFlow::Continue(()),
+                        Err(_) => ControlFlow::Break(()),
+                    }
+                });
+                let _ = tx.send(WatchMessage::End(end));
+            })
+            .context("failed to spawn the K8S watch request thread")?;
+
+        loop {
+            match rx.recv_timeout(STOP_CHECK_PERIOD) {
+                Ok(WatchMessage::Event(event)) => {
+                    self.state.lock().unwrap().apply(event, &self.node);
+                    if stop() {
+                        return Ok(WatchEnd::Stopped);
+                    }
+                }
+                Ok(WatchMessage::End(end)) => {
+                    return end.with_context(|| format!("failed to watch K8S pods on node {}", self.node));
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    if stop() {
+                        return Ok(WatchEnd::Stopped);
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(anyhow!("the K8S watch request thread has stopped unexpectedly"));
+                }
+            }
+        }
     }
 
     pub fn get(&mut self, pod_uid: &str) -> anyhow::Result<Option<PodInfos>> {
-        if let Some(infos) = self.pods.get(pod_uid) {
-            return Ok(Some(infos.to_owned()));
+        {
+            let state = self.state.lock().unwrap();
+            if let Some(infos) = state.pods.get(pod_uid) {
+                return Ok(Some(infos.to_owned()));
+            }
+            if state.watched {
+                // The watch notifies the new pods as soon as they are scheduled on the node,
+                // before their cgroups are created: the pod must have been deleted.
+                return Ok(None);
+            }
         }
 
         // We have no info about this pod, ask the K8S API.
         self.refresh()?;
 
         // Is the pod here? If not, it must have been deleted in the meantime => return None.
-        match self.pods.get(pod_uid) {
+        match self.state.lock().unwrap().pods.get(pod_uid) {
             Some(infos) => Ok(Some(infos.to_owned())),
             None => Ok(None),
         }
     }
+
+    /// Returns the name of a container of the pod.
+    ///
+    /// The status of the pod may not contain the container yet. Without a watch, the K8S API is then asked again,
+    /// once per container.
+    /// Returns `None` for the containers that are not in the pod's status, like the sandbox (a.k.a. "pause" container).
+    fn container_name(&mut self, pod: &PodInfos, container_id: &str) -> Option<String> {
+        if let Some(name) = pod.containers.get(container_id) {
+            return Some(name.to_owned());
+        }
+        let find = |state: &RegistryState| {
+            state
+                .pods
+                .get(&pod.uid)
+                .and_then(|p| p.containers.get(container_id))
+                .cloned()
+        };
+        {
+            let state = self.state.lock().unwrap();
+            if state.watched || state.unknown_containers.contains_key(container_id) {
+                return find(&state);
+            }
+        }
+        if let Err(e) = self.refresh() {
+            log::error!("failed to refresh the K8S pods: {e:#}");
+        }
+        let mut state = self.state.lock().unwrap();
+        let name = find(&state);
+        if name.is_none() && state.pods.contains_key(&pod.uid) {
+            state
+                .unknown_containers
+                .insert(container_id.to_owned(), pod.uid.clone());
+        }
+        name
+    }
+
+    /// Returns the workload that owns the pod, like a Deployment or a StatefulSet.
+    ///
+    /// The pods of a Deployment are controlled by a ReplicaSet, which is controlled by the Deployment:
+    /// if owner resolution is enabled, we ask the K8S API for the controller of the ReplicaSet.
+    fn workload(&mut self, pod: &PodInfos) -> Option<OwnerRef> {
+        let controller = pod.controller.clone()?;
+        if controller.kind != "ReplicaSet" || !self.resolve_owners {
+            return Some(controller);
+        }
+
+        let key = (pod.namespace.clone(), controller.name.clone());
+        if let Some(owner) = self.state.lock().unwrap().replicaset_owners.get(&key) {
+            return Some(owner.clone().unwrap_or(controller));
+        }
+        match self.client.replicaset_controller(&pod.namespace, &controller.name) {
+            Ok(owner) => {
+                self.state.lock().unwrap().replicaset_owners.insert(key, owner.clone());
+                Some(owner.unwrap_or(controller))
+            }
+            Err(e) => {
+                log::warn!(
+                    "failed to get the owner of ReplicaSet {}/{}, is the service account allowed to get the ReplicaSets? {e:#}",
+                    pod.namespace,
+                    controller.name
+                );
+                Some(controller)
+            }
+        }
+    }
 }
 
 /// Extracts the uid from a cgroup path (in the sysfs).
@@ -162,6 +644,7 @@ impl AutoNodePodRegistry {
 /// Returns `None` for cgroups that correspond to individual containers in a pod.
 /// That is, `…/kubepods-burstable-pod{pod_uid}.slice/crio-{container_id}.scope` returns `None`,
 /// while `…/kubepods-burstable-pod{pod_uid}.slice` returns `Some(pod_uid)`.
+/// Use [`extract_container_from_cgroup`] for the containers.
 pub fn extract_pod_uid_from_cgroup(cgroup_fs_path: &Path) -> Option<String> {
     let cgroup_name = cgroup_fs_path.file_name()?.to_str()?;
     let (_prefix, suffix) = cgroup_name.rsplit_once("pod")?;
@@ -177,25 +660,66 @@ pub fn extract_pod_uid_from_cgroup(cgroup_fs_path: &Path) -> Option<String> {
     Some(uid.to_owned())
 }
 
+/// Extracts the pod uid and the container id from the cgroup path of a container (in the sysfs).
+///
+/// # Expected format
+///
+/// `…/kubepods-burstable-pod{pod_uid}.slice/{runtime}-{container_id}.scope`,
+/// where `runtime` is `cri-containerd`, `crio` or `docker` (with cri-dockerd).
+pub fn extract_container_from_cgroup(cgroup_fs_path: &Path) -> Option<(String, String)> {
+    let cgroup_name = cgroup_fs_path.file_name()?.to_str()?;
+    let (runtime, container_id) = cgroup_name.strip_suffix(".scope")?.rsplit_once('-')?;
+    if !matches!(runtime, "cri-containerd" | "crio" | "docker") {
+        // this excludes the monitor of CRI-O, "crio-conmon-{container_id}.scope"
+        return None;
+    }
+    let pod_uid = extract_pod_uid_from_cgroup(cgroup_fs_path.parent()?)?;
+    Some((pod_uid, container_id.to_owned()))
+}
+
 impl JobTagger for AutoNodePodRegistry {
     fn attributes_for_cgroup(&mut self, cgroup: &util_cgroups::Cgroup) -> Vec<(String, AttributeValue)> {
-        let Some(pod_uid) = extract_pod_uid_from_cgroup(cgroup.fs_path()) else {
-            return Vec::new();
+        let (pod_uid, container_id) = match extract_container_from_cgroup(cgroup.fs_path()) {
+            Some((pod_uid, container_id)) => (pod_uid, Some(container_id)),
+            None => match extract_pod_uid_from_cgroup(cgroup.fs_path()) {
+                Some(pod_uid) => (pod_uid, None),
+                None => return Vec::new(),
+            },
         };
-        let attrs = self
+        let Some(pod_infos) = self
             .get(&pod_uid)
             .inspect_err(|e| log::error!("failed to get K8S pod infos for pod {pod_uid}: {e:#}"))
             .ok()
             .flatten()
-            .map(|pod_infos| {
-                vec![
-                    ("uid".into(), AttributeValue::String(pod_uid)),
-                    ("name".into(), AttributeValue::String(pod_infos.name)),
-                    ("namespace".into(), AttributeValue::String(pod_infos.namespace)),
-                    ("node".into(), AttributeValue::String(pod_infos.node)),
-                ]
-            })
-            .unwrap_or_default();
+        else {
+            return Vec::new();
+        };
+
+        let workload = self.workload(&pod_infos);
+        let container_name = container_id.as_ref().and_then(|id| self.container_name(&pod_infos, id));
+
+        let mut attrs = vec![
+            ("uid".into(), AttributeValue::String(pod_uid)),
+            ("name".into(), AttributeValue::String(pod_infos.name)),
+            ("namespace".into(), AttributeValue::String(pod_infos.namespace)),
+            ("node".into(), AttributeValue::String(pod_infos.node)),
+        ];
+        if let Some(owner) = workload {
+            attrs.push(("owner_kind".into(), AttributeValue::String(owner.kind)));
+            attrs.push(("owner_name".into(), AttributeValue::String(owner.name)));
+        }
+        for (key, value) in pod_infos.labels {
+            attrs.push((format!("label.{key}"), AttributeValue::String(value)));
+        }
+        for (key, value) in pod_infos.annotations {
+            attrs.push((format!("annotation.{key}"), AttributeValue::String(value)));
+        }
+        if let Some(container_id) = container_id {
+            attrs.push(("container_id".into(), AttributeValue::String(container_id)));
+            if let Some(name) = container_name {
+                attrs.push(("container".into(), AttributeValue::String(name)));
+            }
+        }
         attrs
     }
 }
@@ -205,9 +729,10 @@ mod tests {
     use pretty_assertions::assert_eq;
     use std::path::PathBuf;
 
-    use mockito::{Server, ServerGuard};
+    use mockito::{Matcher, Server, ServerGuard};
     use serde_json::json;
     use tempfile::tempdir;
+    use util_cgroups::{Cgroup, CgroupHierarchy, CgroupVersion};
 
     use super::*;
 
@@ -248,7 +773,12 @@ mod tests {
     ) -> anyhow::Result<FxHashMap<String, PodInfos>> {
         let k8s_api_url = server.url();
         let client = ApiClient::new(&k8s_api_url, auth_token)?;
-        let result: FxHashMap<_, _> = client.list_pods(Some(node))?.map(|p| (p.uid.clone(), p)).collect();
+        let result: FxHashMap<_, _> = client
+            .list_pods_with_version(Some(node))?
+            .pods
+            .into_iter()
+            .map(|p| (p.uid.clone(), p))
+            .collect();
         Ok(result)
     }
 
@@ -373,13 +903,13 @@ mod tests {
         let k8s_api_url = server.url();
         let k8s_api_client = ApiClient::new(&k8s_api_url, auth_token).unwrap();
         let mut registry = AutoNodePodRegistry::new(node.to_owned(), k8s_api_client);
-        assert!(registry.pods.is_empty());
+        assert!(registry.state.lock().unwrap().pods.is_empty());
 
         // This is the only request we've got
         registry.refresh().expect("refresh should work");
         mock.assert();
 
-        println!("refreshed: {:?}", registry.pods);
+        println!("refreshed: {:?}", registry.state.lock().unwrap().pods);
 
         // These should NOT generate more requests, because we've already got the pod infos
         let pod_infos_5f32 = registry.get("5f32d849-6210-4886-a48d-e0d90e1d0206").unwrap().unwrap();
@@ -438,9 +968,9 @@ mod tests {
         let k8s_api_url = server.url();
         let k8s_api_client = ApiClient::new(&k8s_api_url, auth_token).unwrap();
         let mut registry = AutoNodePodRegistry::new(node.to_owned(), k8s_api_client);
-        assert!(registry.pods.is_empty());
+        assert!(registry.state.lock().unwrap().pods.is_empty());
 
-        println!("refreshed: {:?}", registry.pods);
+        println!("refreshed: {:?}", registry.state.lock().unwrap().pods);
 
         // These should generate TWO requests
         let pod_infos_5f32 = registry
@@ -563,4 +1093,316 @@ mod tests {
         let result = get_node_pod_infos(&server, auth_token, node).unwrap();
         assert!(result.is_empty());
     }
+
+    #[test]
+    fn test_extract_container() {
+        let pod = "/sys/fs/cgroup/kubepods.slice/kubepods-besteffort.slice/kubepods-besteffort-pod5f32d849_6210_4886_a48d_e0d90e1d0206.slice";
+        let uid = String::from("5f32d849-6210-4886-a48d-e0d90e1d0206");
+        assert_eq!(
+            extract_container_from_cgroup(&PathBuf::from(format!("{pod}/cri-containerd-4a7f1c2b.scope"))),
+            Some((uid.clone(), String::from("4a7f1c2b")))
+        );
+        assert_eq!(
+            extract_container_from_cgroup(&PathBuf::from(format!("{pod}/crio-85b951fd.scope"))),
+            Some((uid, String::from("85b951fd")))
+        );
+        assert_eq!(
+            extract_container_from_cgroup(&PathBuf::from(format!("{pod}/crio-conmon-85b951fd.scope"))),
+            None
+        );
+        assert_eq!(extract_container_from_cgroup(&PathBuf::from(pod)), None);
+        assert_eq!(
+            extract_container_from_cgroup(&PathBuf::from("/sys/fs/cgroup/system.slice/docker-4a7f1c2b.scope")),
+            None
+        );
+    }
+
+    fn token_file(root: &Path) -> Token {
+        let dir = root.join("run/secrets/kubernetes.io/serviceaccount/");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("token_4");
+        std::fs::write(&path, TOKEN_CONTENT).unwrap();
+        Token::with_file(path.to_str().unwrap().to_owned())
+    }
+
+    fn pod_json(uid: &str, name: &str, version: &str) -> serde_json::Value {
+        json!({
+            "metadata": {
+                "name": name,
+                "namespace": "default",
+                "uid": uid,
+                "resourceVersion": version,
+                "labels": {
+                    "app": "web",
+                    "pod-template-hash": "7d9c8b6f5"
+                },
+                "annotations": {
+                    "team": "green"
+                },
+                "ownerReferences": [
+                    {"apiVersion": "apps/v1", "kind": "ReplicaSet", "name": "web-7d9c8b6f5", "controller": true}
+                ]
+            },
+            "spec": {
+                "nodeName": "node1"
+            },
+            "status": {
+                "containerStatuses": [
+                    {"name": "nginx", "containerID": "containerd://4a7f1c2b"}
+                ],
+                "initContainerStatuses": [
+                    {"name": "init-config", "containerID": "containerd://9e8d7c6b"}
+                ]
+            }
+        })
+    }
+
+    #[test]
+    fn test_pod_metadata() {
+        let tempdir = tempdir().unwrap();
+        let auth_token = token_file(tempdir.path());
+
+        let mut server = Server::new();
+        let mock = server
+            .mock("GET", "/api/v1/pods?fieldSelector=spec.nodeName%3Dnode1")
+            .with_status(200)
+            .with_header("Content-Type", "application/json")
+            .with_body(
+                json!({
+                    "metadata": {"resourceVersion": "100"},
+                    "items": [pod_json("5f32d849-6210-4886-a48d-e0d90e1d0206", "pod1", "99")]
+                })
+                .to_string(),
+            )
+            .expect(1)
+            .create();
+
+        let client = ApiClient::new(&server.url(), auth_token)
+            .unwrap()
+            .with_selected_metadata(vec![String::from("app")], vec![String::from("team")]);
+        let listing = client.list_pods_with_version(Some("node1")).unwrap();
+        mock.assert();
+
+        assert_eq!(listing.resource_version.as_deref(), Some("100"));
+        let pod = &listing.pods[0];
+        assert_eq!(pod.labels, BTreeMap::from([(String::from("app"), String::from("web"))]));
+        assert_eq!(
+            pod.annotations,
+            BTreeMap::from([(String::from("team"), String::from("green"))])
+        );
+        assert_eq!(
+            pod.controller,
+            Some(OwnerRef {
+                kind: String::from("ReplicaSet"),
+                name: String::from("web-7d9c8b6f5"),
+            })
+        );
+        assert_eq!(pod.containers.get("4a7f1c2b").map(String::as_str), Some("nginx"));
+        assert_eq!(pod.containers.get("9e8d7c6b").map(String::as_str), Some("init-config"));
+    }
+
+    #[test]
+    fn test_watch() {
+        let tempdir = tempdir().unwrap();
+        let auth_token = token_file(tempdir.path());
+
+        let uid1 = "5f32d849-6210-4886-a48d-e0d90e1d0206";
+        let uid2 = "5fffd849-6210-4886-aaaa-e0d90e1d0206";
+        let events = [
+            json!({"type": "ADDED", "object": pod_json(uid2, "pod2", "101")}),
+            json!({"type": "BOOKMARK", "object": {"kind": "Pod", "metadata": {"resourceVersion": "102"}}}),
+            json!({"type": "DELETED", "object": pod_json(uid1, "pod1", "103")}),
+        ];
+        let body: String = events.iter().map(|e| format!("{e}\n")).collect();
+
+        let mut server = Server::new();
+        let list_mock = server
+            .mock("GET", "/api/v1/pods?fieldSelector=spec.nodeName%3Dnode1")
+            .with_status(200)
+            .with_header("Content-Type", "application/json")
+            .with_body(
+                json!({
+                    "metadata": {"resourceVersion": "100"},
+                    "items": [pod_json(uid1, "pod1", "99")]
+                })
+                .to_string(),
+            )
+            .expect(1)
+            .create();
+        let watch_mock = server
+            .mock("GET", "/api/v1/pods")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("watch".into(), "true".into()),
+                Matcher::UrlEncoded("resourceVersion".into(), "100".into()),
+                Matcher::UrlEncoded("fieldSelector".into(), "spec.nodeName=node1".into()),
+            ]))
+            .with_status(200)
+            .with_header("Content-Type", "application/json")
+            .with_body(body)
+            .expect(1)
+            .create();
+        let expired_mock = server
+            .mock("GET", "/api/v1/pods")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("watch".into(), "true".into()),
+                Matcher::UrlEncoded("resourceVersion".into(), "103".into()),
+            ]))
+            .with_status(200)
+            .with_header("Content-Type", "application/json")
+            .with_body(
+                json!({
+                    "type": "ERROR",
+                    "object": {"kind": "Status", "code": 410, "reason": "Expired"}
+                })
+                .to_string(),
+            )
+            .expect(1)
+            .create();
+
+        let client = ApiClient::new(&server.url(), auth_token).unwrap();
+        let mut registry = AutoNodePodRegistry::new(String::from("node1"), client);
+        let version = registry.relist().unwrap().unwrap();
+        assert_eq!(version, "100");
+        list_mock.assert();
+
+        let end = registry.watch(&version, Duration::from_secs(10), || false).unwrap();
+        watch_mock.assert();
+        assert_eq!(
+            end,
+            WatchEnd::Closed {
+                resource_version: String::from("103")
+            }
+        );
+        {
+            let state = registry.state.lock().unwrap();
+            assert!(!state.pods.contains_key(uid1));
+            assert_eq!(state.pods.get(uid2).unwrap().name, "pod2");
+        }
+
+        let end = registry.watch("103", Duration::from_secs(10), || false).unwrap();
+        expired_mock.assert();
+        assert_eq!(end, WatchEnd::Expired);
+    }
+
+    #[test]
+    fn test_owner_and_container_attributes() {
+        let tempdir = tempdir().unwrap();
+        let auth_token = token_file(tempdir.path());
+
+        let uid = "5f32d849-6210-4886-a48d-e0d90e1d0206";
+        let mut server = Server::new();
+        let list_mock = server
+            .mock("GET", "/api/v1/pods?fieldSelector=spec.nodeName%3Dnode1")
+            .with_status(200)
+            .with_header("Content-Type", "application/json")
+            .with_body(json!({"items": [pod_json(uid, "pod1", "99")]}).to_string())
+            .expect(3)
+            .create();
+        let replicaset_mock = server
+            .mock("GET", "/apis/apps/v1/namespaces/default/replicasets/web-7d9c8b6f5")
+            .with_status(200)
+            .with_header("Content-Type", "application/json")
+            .with_body(
+                json!({
+                    "metadata": {
+                        "name": "web-7d9c8b6f5",
+                        "ownerReferences": [
+                            {"apiVersion": "apps/v1", "kind": "Deployment", "name": "web", "controller": true}
+                        ]
+                    }
+                })
+                .to_string(),
+            )
+            .expect(1)
+            .create();
+
+        let client = ApiClient::new(&server.url(), auth_token)
+            .unwrap()
+            .with_selected_metadata(vec![String::from("app")], Vec::new());
+        let mut registry = AutoNodePodRegistry::new(String::from("node1"), client);
+        registry.refresh().unwrap();
+
+        let root = tempdir.path().join("cgroup");
+        let hierarchy = CgroupHierarchy::manually_unchecked(&root, CgroupVersion::V2, vec!["cpu"]);
+        let pod_path =
+            "/kubepods.slice/kubepods-burstable.slice/kubepods-burstable-pod5f32d849_6210_4886_a48d_e0d90e1d0206.slice";
+        let attr = |key: &str, value: &str| (key.to_owned(), AttributeValue::String(value.to_owned()));
+
+        let pod_cgroup = Cgroup::from_cgroup_path(&hierarchy, pod_path.to_owned());
+        let pod_attrs = vec![
+            attr("uid", uid),
+            attr("name", "pod1"),
+            attr("namespace", "default"),
+            attr("node", "node1"),
+            attr("owner_kind", "Deployment"),
+            attr("owner_name", "web"),
+            attr("label.app", "web"),
+        ];
+        assert_eq!(registry.attributes_for_cgroup(&pod_cgroup), pod_attrs);
+
+        let container_cgroup =
+            Cgroup::from_cgroup_path(&hierarchy, format!("{pod_path}/cri-containerd-4a7f1c2b.scope"));
+        let mut container_attrs = pod_attrs.clone();
+        container_attrs.push(attr("container_id", "4a7f1c2b"));
+        container_attrs.push(attr("container", "nginx"));
+        assert_eq!(registry.attributes_for_cgroup(&container_cgroup), container_attrs);
+
+        // The sandbox is not in the status of the pod: it is looked for once, then remembered as unknown.
+        let sandbox_cgroup = Cgroup::from_cgroup_path(&hierarchy, format!("{pod_path}/cri-containerd-0b1c2d3e.scope"));
+        let mut sandbox_attrs = pod_attrs.clone();
+        sandbox_attrs.push(attr("container_id", "0b1c2d3e"));
+        assert_eq!(registry.attributes_for_cgroup(&sandbox_cgroup), sandbox_attrs);
+        assert_eq!(registry.attributes_for_cgroup(&sandbox_cgroup), sandbox_attrs);
+
+        // Listing the pods again does not forget the containers that are still missing from their pod.
+        registry.relist().unwrap();
+        assert!(
+            registry
+                .state
+                .lock()
+                .unwrap()
+                .unknown_containers
+                .contains_key("0b1c2d3e")
+        );
+
+        // With a watch, the misses don't list the pods again.
+        registry.set_watched(true);
+        assert!(registry.get("5fffd849-6210-4886-aaaa-e0d90e1d0206").unwrap().is_none());
+        let new_cgroup = Cgroup::from_cgroup_path(&hierarchy, format!("{pod_path}/cri-containerd-7f6e5d4c.scope"));
+        let mut new_attrs = pod_attrs.clone();
+        new_attrs.push(attr("container_id", "7f6e5d4c"));
+        assert_eq!(registry.attributes_for_cgroup(&new_cgroup), new_attrs);
+
+        // the owner of the ReplicaSet is in cache
+        list_mock.assert();
+        replicaset_mock.assert();
+
+        // Deleting the pod forgets its containers and its ReplicaSet.
+        let mut state = registry.state.lock().unwrap();
+        state.apply(PodEvent::Delete(uid.to_owned()), "node1");
+        assert!(state.unknown_containers.is_empty());
+        assert!(state.replicaset_owners.is_empty());
+    }
+
+    #[test]
+    fn test_watcher_not_synced() {
+        let tempdir = tempdir().unwrap();
+        let auth_token = token_file(tempdir.path());
+
+        let mut server = Server::new();
+        let _list_mock = server
+            .mock("GET", "/api/v1/pods?fieldSelector=spec.nodeName%3Dnode1")
+            .with_status(500)
+            .create();
+
+        let client = ApiClient::new(&server.url(), auth_token).unwrap();
+        let mut registry = AutoNodePodRegistry::new(String::from("node1"), client);
+
+        // The watcher cannot list the pods: the registry must not assume that the unknown pods have been deleted,
+        // and ask the API instead.
+        let watcher = crate::watch::PodWatcher::spawn(registry.clone(), None).unwrap();
+        assert!(!registry.state.lock().unwrap().watched);
+        assert!(registry.get("5f32d849-6210-4886-a48d-e0d90e1d0206").is_err());
+        drop(watcher);
+    }
 }
@@ -3,13 +3,20 @@ use alumet::pipeline::elements::source::trigger::TriggerSpec;
 use util_cgroups_plugins::{
     cgroup_events::{CgroupSetupCallback, ProbeSetup, SourceSettings},
     job_annotation_transform::JobTagger,
+    job_summary::JobSummaries,
     metrics::{AugmentedMetrics, Metrics},
 };
 
+use super::pods::extract_container_from_cgroup;
+
 #[derive(Clone)]
 pub struct SourceSetup {
     pub trigger: TriggerSpec,
     pub k8s_pods: super::pods::AutoNodePodRegistry,
+    /// Measure the containers of the pods, not only the pods.
+    pub monitor_containers: bool,
+    /// Summarize the consumption of the pods when they end, if enabled.
+    pub job_summaries: Option<JobSummaries>,
 }
 
 impl CgroupSetupCallback for SourceSetup {
@@ -18,6 +25,11 @@ impl CgroupSetupCallback for SourceSetup {
         cgroup: &util_cgroups::Cgroup,
         metrics: &Metrics,
     ) -> Option<util_cgroups_plugins::cgroup_events::ProbeSetup> {
+        let is_container = extract_container_from_cgroup(cgroup.fs_path()).is_some();
+        if !self.monitor_containers && is_container {
+            return None;
+        }
+
         // Retrieves associated attributes
         let attrs = self.k8s_pods.attributes_for_cgroup(cgroup);
 
@@ -26,12 +38,17 @@ impl CgroupSetupCallback for SourceSetup {
             return None;
         }
 
+        // Only summarize the pods: the consumption of the containers is included in the one of their pod.
+        if let (Some(summaries), false) = (&self.job_summaries, is_container) {
+            summaries.register(cgroup, attrs.clone());
+        }
+
         let metrics = AugmentedMetrics::with_common_attr_vec(metrics, attrs);
 
         // setup the trigger according to the plugin's config
         let trigger = self.trigger.clone();
 
-        // use the cgroup's "file stem" as the source name (it contains the pod uid)
+        // use the cgroup's "file stem" as the source name (it contains the pod uid or the container id)
         let name = cgroup.fs_path().file_stem().unwrap().to_str().unwrap().to_string();
 
         // ready!
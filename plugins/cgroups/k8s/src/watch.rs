@@ -0,0 +1,110 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use anyhow::Context;
+
+use crate::pods::{AutoNodePodRegistry, WatchEnd};
+
+/// How long each watch request lasts, before being renewed.
+const WATCH_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Delay before retrying after an error.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Background thread that keeps the registry up to date with the watch API.
+///
+/// The thread is stopped, and joined, when the `PodWatcher` is dropped.
+/// While it is in sync with the API, the registry relies on it instead of listing the pods on each miss.
+/// When the watch fails or expires, the registry falls back to listing the pods until the watch is resumed.
+pub struct PodWatcher {
+    stopping: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    registry: AutoNodePodRegistry,
+}
+
+impl PodWatcher {
+    /// Starts watching the changes of the pods, from the given version of the registry's state.
+    pub fn spawn(registry: AutoNodePodRegistry, resource_version: Option<String>) -> anyhow::Result<Self> {
+        // Without a version, the registry is not in sync until the watch thread lists the pods.
+        registry.set_watched(resource_version.is_some());
+        let stopping = Arc::new(AtomicBool::new(false));
+        let thread = std::thread::Builder::new()
+            .name(String::from("k8s-pod-watcher"))
+            .spawn({
+                let stopping = stopping.clone();
+                let registry = registry.clone();
+                move || run_watch_loop(registry, resource_version, stopping)
+            })
+            .context("failed to spawn the K8S watcher thread")?;
+        Ok(Self {
+            stopping,
+            thread: Some(thread),
+            registry,
+        })
+    }
+}
+
+impl Drop for PodWatcher {
+    fn drop(&mut self) {
+        // The watch checks the flag periodically, and the retry delay is interrupted by `unpark`.
+        self.stopping.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            if thread.join().is_err() {
+                log::error!("the K8S watcher thread has panicked");
+            }
+        }
+        self.registry.set_watched(false);
+    }
+}
+
+fn run_watch_loop(mut registry: AutoNodePodRegistry, mut resource_version: Option<String>, stopping: Arc<AtomicBool>) {
+    let is_stopping = || stopping.load(Ordering::Relaxed);
+    while !is_stopping() {
+        // Without a version to start from, we need to list the pods again.
+        let version = match resource_version.take() {
+            Some(v) => v,
+            None => match registry.relist() {
+                Ok(Some(v)) => {
+                    registry.set_watched(true);
+                    v
+                }
+                Ok(None) => {
+                    log::warn!("the K8S API did not return a resourceVersion, retrying in {RETRY_DELAY:?}");
+                    std::thread::park_timeout(RETRY_DELAY);
+                    continue;
+                }
+                Err(e) => {
+                    log::error!("{e:#}, retrying in {RETRY_DELAY:?}");
+                    std::thread::park_timeout(RETRY_DELAY);
+                    continue;
+                }
+            },
+        };
+
+        match registry.watch(&version, WATCH_TIMEOUT, is_stopping) {
+            Ok(WatchEnd::Closed { resource_version: v }) => {
+                // normal end of the request, resume from the last known version
+                resource_version = Some(v);
+            }
+            Ok(WatchEnd::Expired) => {
+                log::debug!("resourceVersion {version} has expired, listing the pods again");
+                registry.set_watched(false);
+            }
+            Ok(WatchEnd::Stopped) => break,
+            Err(e) => {
+                log::warn!("{e:#}, retrying in {RETRY_DELAY:?}");
+                // Some events may have been missed, list the pods again.
+                // Until then, the registry must ask the API when it does not find a pod.
+                registry.set_watched(false);
+                std::thread::park_timeout(RETRY_DELAY);
+            }
+        }
+    }
+}
@@ -56,6 +56,7 @@ fn test_k8s_cgroupv2() -> anyhow::Result<()> {
                     k8s_api_url = "{mock_server_url}"
                     k8s_node = "{POD_NODE}"
                     token_retrieval.file = "{token_file_path}"
+                    watch_pods = false
                 "#
             ))
             .unwrap(),
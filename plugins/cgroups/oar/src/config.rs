@@ -1,6 +1,7 @@
 use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
+use util_cgroups_plugins::{aggregation::AggregationConfig, job_summary::JobSummaryConfig};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
@@ -14,6 +15,21 @@ pub struct Config {
     /// The measurements must have the `cgroup` resource consumer, and **cgroup v2** must be used on the node.
     #[serde(default)]
     pub annotate_foreign_measurements: bool,
+    /// Additional keys of `memory.stat` to measure, like `file_mapped` or `pgmajfault`.
+    /// The default value is empty.
+    ///
+    /// With **cgroup v1**, use the keys of cgroup v1, like `total_mapped_file` or `total_pgmajfault`.
+    #[serde(default)]
+    pub memory_stat_keys: Vec<String>,
+    /// If set, emits a summary of the consumption of each job (energy, CPU time, peak memory) when it ends.
+    /// The default value is `None` (disabled).
+    #[serde(default)]
+    pub job_summary: Option<JobSummaryConfig>,
+    /// If set, aggregates the measurements of the cgroups by the given attributes, like `user_id`,
+    /// with `inclusive` and `exclusive` values.
+    /// The default value is `None` (disabled).
+    #[serde(default)]
+    pub aggregation: Option<AggregationConfig>,
 }
 
 impl Default for Config {
@@ -24,6 +40,9 @@ impl Default for Config {
             poll_interval: Duration::from_secs(1),
             jobs_only: true,
             annotate_foreign_measurements: false,
+            memory_stat_keys: Vec::new(),
+            job_summary: None,
+            aggregation: None,
         }
     }
 }
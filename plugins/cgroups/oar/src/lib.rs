@@ -10,10 +10,12 @@ use crate::{
     transform::JobInfoAttacher,
 };
 use util_cgroups_plugins::{
+    aggregation::{CgroupAggregation, setup_aggregation},
     cgroup_events::{CgroupReactor, NoCallback, ReactorCallbacks, ReactorConfig},
     job_annotation_transform::{
         CachedCgroupHierarchy, JobAnnotationTransform, OptionalSharedHierarchy, SharedCgroupHierarchy,
     },
+    job_summary::{JobSummaries, setup_job_summaries},
     metrics::Metrics,
 };
 
@@ -80,12 +82,28 @@ impl AlumetPlugin for OarPlugin {
             alumet.add_transform("oar-annotation", Box::new(transform))?;
         }
 
+        let metrics = Metrics::create(alumet)?.with_memory_stat_keys(config.memory_stat_keys.clone());
+
+        // If enabled, summarize the consumption of each job when it ends.
+        let job_summaries = match config.job_summary.clone() {
+            Some(summary_config) => Some(setup_job_summaries(alumet, summary_config, &metrics)?),
+            None => None,
+        };
+
+        // If enabled, aggregate the measurements of the cgroups, for instance by user.
+        let aggregation = match config.aggregation.clone() {
+            Some(aggregation_config) => Some(setup_aggregation(alumet, aggregation_config, &metrics)?),
+            None => None,
+        };
+
         // Prepare for cgroup detection.
         let starting_state = StartingState {
-            metrics: Metrics::create(alumet)?,
+            metrics,
             reactor_config: ReactorConfig::default(),
             job_cleaner: JobCleaner::with_version(&tracker, config.oar_version)?,
-            source_setup: source::JobSourceSetup::new(config, tracker.clone(), tagger)?,
+            source_setup: source::JobSourceSetup::new(config, tracker.clone(), tagger, job_summaries.clone())?,
+            job_summaries,
+            aggregation,
         };
         self.starting_state = Some(starting_state);
 
@@ -103,7 +121,7 @@ impl AlumetPlugin for OarPlugin {
             s.metrics,
             ReactorCallbacks {
                 probe_setup: s.source_setup,
-                on_removal: s.job_cleaner,
+                on_removal: (s.job_cleaner, (s.job_summaries, s.aggregation)),
                 on_fs_mount: NoCallback,
             },
             alumet.pipeline_control(),
@@ -126,4 +144,6 @@ struct StartingState {
     reactor_config: ReactorConfig,
     source_setup: source::JobSourceSetup,
     job_cleaner: JobCleaner,
+    job_summaries: Option<JobSummaries>,
+    aggregation: Option<CgroupAggregation>,
 }
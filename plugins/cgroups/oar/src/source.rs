@@ -12,6 +12,7 @@ use crate::{
 use util_cgroups_plugins::{
     cgroup_events::{CgroupSetupCallback, ProbeSetup, SourceSettings},
     job_annotation_transform::JobTagger,
+    job_summary::JobSummaries,
     metrics::{AugmentedMetrics, Metrics},
     regex::RegexAttributesExtrator,
 };
@@ -23,10 +24,16 @@ pub struct JobSourceSetup {
     trigger: TriggerSpec,
     tracker: JobTracker,
     jobs_only: bool,
+    job_summaries: Option<JobSummaries>,
 }
 
 impl JobSourceSetup {
-    pub fn new(config: super::config::Config, tracker: JobTracker, tagger: OarJobTagger) -> anyhow::Result<Self> {
+    pub fn new(
+        config: super::config::Config,
+        tracker: JobTracker,
+        tagger: OarJobTagger,
+        job_summaries: Option<JobSummaries>,
+    ) -> anyhow::Result<Self> {
         let trigger = TriggerSpec::at_interval(config.poll_interval);
         match config.oar_version {
             OarVersion::Oar2 => Ok(Self {
@@ -35,6 +42,7 @@ impl JobSourceSetup {
                 trigger,
                 tracker,
                 jobs_only: config.jobs_only,
+                job_summaries,
             }),
             OarVersion::Oar3 => Ok(Self {
                 tagger,
@@ -42,6 +50,7 @@ impl JobSourceSetup {
                 trigger,
                 tracker,
                 jobs_only: config.jobs_only,
+                job_summaries,
             }),
         }
     }
@@ -67,6 +76,9 @@ impl CgroupSetupCallback for JobSourceSetup {
             // add to job tracker
             let job_id = find_jobid_in_attrs(&attrs).expect("job_id should be set");
             self.tracker.add(job_id);
+            if let Some(summaries) = &self.job_summaries {
+                summaries.register(cgroup, attrs.clone());
+            }
 
             // give a nice name
             name = format!(
@@ -0,0 +1,155 @@
+use alumet::measurement::AttributeValue;
+use util_cgroups::Cgroup;
+use util_cgroups_plugins::job_annotation_transform::JobTagger;
+use util_cgroups_plugins::regex::RegexAttributesExtrator;
+
+/// Default name of the parent cgroup of the PBS jobs (option `cgroup_prefix` of the cgroups hook).
+pub const DEFAULT_CGROUP_PREFIX: &str = "pbs_jobs";
+
+/// Builds the regex that matches the cgroups of the PBS jobs.
+///
+/// The cgroups hook of PBS creates one cgroup per job, named after the full id of the job, for instance:
+/// - `/pbs_jobs.service/jobid/1234.server` (with systemd)
+/// - `/pbs_jobs/1234.server` (without systemd)
+///
+/// The id of a subjob of a job array contains the index of the subjob: `1234[5].server`.
+fn job_regex(cgroup_prefix: &str) -> String {
+    format!(
+        r"/{}(?:\.service/jobid)?/(?<pbs_job_id>(?<job_id__u64>[0-9]+)(?:\[(?<array_index__u64>[0-9]+)\])?(?:\.(?<server>[^/]+))?)(?<remaining>/.*)?$",
+        regex::escape(cgroup_prefix)
+    )
+}
+
+pub fn find_key_in_attrs(key: &str, attrs: &[(String, AttributeValue)]) -> Option<String> {
+    attrs.iter().find(|(k, _)| k == key).map(|(_, v)| match v {
+        AttributeValue::String(value) => value.clone(),
+        _ => unreachable!("key: {} not found", key),
+    })
+}
+
+#[derive(Clone)]
+pub struct PbsJobTagger {
+    extractor: RegexAttributesExtrator,
+}
+
+impl PbsJobTagger {
+    pub fn new(cgroup_prefix: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            extractor: RegexAttributesExtrator::new(&job_regex(cgroup_prefix))?,
+        })
+    }
+
+    /// Extracts the attributes of the job from the canonical path of a cgroup.
+    ///
+    /// Returns the attributes, and `true` if the cgroup is the cgroup of the job itself,
+    /// `false` if it is a sub-cgroup of the job (or not related to a job at all).
+    pub(crate) fn extract(&self, canonical_path: &str) -> (Vec<(String, AttributeValue)>, bool) {
+        let mut attrs = self
+            .extractor
+            .extract(canonical_path)
+            .expect("bad regex: it should only match if the input can be parsed into the specified types");
+        let remaining = attrs
+            .iter()
+            .position(|(key, _)| key == "remaining")
+            .map(|pos| attrs.remove(pos));
+        let is_job = !attrs.is_empty() && remaining.is_none();
+        (attrs, is_job)
+    }
+}
+
+impl JobTagger for PbsJobTagger {
+    fn attributes_for_cgroup(&mut self, cgroup: &Cgroup) -> Vec<(String, AttributeValue)> {
+        // The sub-cgroups of a job get the attributes of the job.
+        self.extract(cgroup.canonical_path()).0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn job_attrs(
+        pbs_job_id: &str,
+        job_id: u64,
+        array_index: Option<u64>,
+        server: Option<&str>,
+    ) -> Vec<(String, AttributeValue)> {
+        let mut attrs = vec![
+            (
+                String::from("pbs_job_id"),
+                AttributeValue::String(pbs_job_id.to_owned()),
+            ),
+            (String::from("job_id"), AttributeValue::U64(job_id)),
+        ];
+        if let Some(index) = array_index {
+            attrs.push((String::from("array_index"), AttributeValue::U64(index)));
+        }
+        if let Some(server) = server {
+            attrs.push((String::from("server"), AttributeValue::String(server.to_owned())));
+        }
+        attrs
+    }
+
+    #[test]
+    fn job_cgroups() {
+        let tagger = PbsJobTagger::new(DEFAULT_CGROUP_PREFIX).unwrap();
+        assert_eq!(
+            tagger.extract("/pbs_jobs.service/jobid/1234.pbs-server"),
+            (job_attrs("1234.pbs-server", 1234, None, Some("pbs-server")), true)
+        );
+        assert_eq!(
+            tagger.extract("/pbs_jobs/1234.pbs-server"),
+            (job_attrs("1234.pbs-server", 1234, None, Some("pbs-server")), true)
+        );
+        assert_eq!(
+            tagger.extract("/pbs_jobs.service/jobid/1234[5].head.cluster.org"),
+            (
+                job_attrs("1234[5].head.cluster.org", 1234, Some(5), Some("head.cluster.org")),
+                true
+            )
+        );
+        assert_eq!(
+            tagger.extract("/pbs_jobs.service/jobid/42"),
+            (job_attrs("42", 42, None, None), true)
+        );
+    }
+
+    #[test]
+    fn sub_cgroups() {
+        let tagger = PbsJobTagger::new(DEFAULT_CGROUP_PREFIX).unwrap();
+        assert_eq!(
+            tagger.extract("/pbs_jobs.service/jobid/1234.srv/tasks/1"),
+            (job_attrs("1234.srv", 1234, None, Some("srv")), false)
+        );
+    }
+
+    #[test]
+    fn not_jobs() {
+        let tagger = PbsJobTagger::new(DEFAULT_CGROUP_PREFIX).unwrap();
+        assert_eq!(tagger.extract("/pbs_jobs.service"), (vec![], false));
+        assert_eq!(tagger.extract("/pbs_jobs.service/jobid"), (vec![], false));
+        assert_eq!(tagger.extract("/system.slice/pbs.service"), (vec![], false));
+        assert_eq!(tagger.extract("/slurmstepd.scope/job_12"), (vec![], false));
+        assert_eq!(tagger.extract("/"), (vec![], false));
+    }
+
+    #[test]
+    fn custom_prefix() {
+        let tagger = PbsJobTagger::new("pbspro").unwrap();
+        assert_eq!(
+            tagger.extract("/pbspro/7.srv"),
+            (job_attrs("7.srv", 7, None, Some("srv")), true)
+        );
+        assert_eq!(tagger.extract("/pbs_jobs/7.srv"), (vec![], false));
+    }
+
+    #[test]
+    fn attrs_lookup() {
+        let attrs = job_attrs("1234[5].srv", 1234, Some(5), Some("srv"));
+        assert_eq!(find_key_in_attrs("server", &attrs), Some(String::from("srv")));
+        assert_eq!(find_key_in_attrs("queue", &attrs), None);
+        assert_eq!(find_key_in_attrs("server", &[]), None);
+    }
+}
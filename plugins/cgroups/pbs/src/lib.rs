@@ -0,0 +1,190 @@
+use std::time::Duration;
+
+use alumet::plugin::{
+    AlumetPluginStart, AlumetPostStart, ConfigTable,
+    rust::{AlumetPlugin, deserialize_config, serialize_config},
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use util_cgroups_plugins::{
+    cgroup_events::{CgroupReactor, NoCallback, ReactorCallbacks, ReactorConfig},
+    job_annotation_transform::{
+        CachedCgroupHierarchy, JobAnnotationTransform, OptionalSharedHierarchy, SharedCgroupHierarchy,
+    },
+    metrics::Metrics,
+};
+
+use crate::attr::{DEFAULT_CGROUP_PREFIX, PbsJobTagger};
+
+mod attr;
+mod source;
+
+/// Gathers metrics for PBS (PBS Pro, OpenPBS) jobs.
+///
+/// Supports PBS on cgroup v1 or cgroup v2, with the cgroups hook enabled.
+pub struct PbsPlugin {
+    pub config: Option<Config>,
+    /// Intermediary state for startup.
+    pub starting_state: Option<StartingState>,
+    /// The reactor that is running in the background. Dropping it will stop it.
+    pub reactor: Option<CgroupReactor>,
+}
+
+impl PbsPlugin {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config: Some(config),
+            reactor: None,
+            starting_state: None,
+        }
+    }
+}
+
+impl AlumetPlugin for PbsPlugin {
+    fn name() -> &'static str {
+        "pbs"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config: Config = deserialize_config(config)?;
+        Ok(Box::new(Self::new(config)))
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        let config = serialize_config(Config::default())?;
+        Ok(Some(config))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let config = self.config.take().unwrap();
+
+        let tagger = PbsJobTagger::new(&config.cgroup_prefix).context("invalid cgroup_prefix in the config")?;
+        let mut shared_hierarchy = OptionalSharedHierarchy::default();
+
+        // If enabled, create the annotation transform.
+        if config.annotate_foreign_measurements {
+            let shared = SharedCgroupHierarchy::default();
+            shared_hierarchy.enable(shared.clone());
+
+            let transform = JobAnnotationTransform {
+                tagger: tagger.clone(),
+                cgroup_v2_hierarchy: CachedCgroupHierarchy::new(shared),
+            };
+            alumet.add_transform("pbs-annotation", Box::new(transform))?;
+        }
+
+        // Prepare for cgroup detection.
+        let starting_state = StartingState {
+            metrics: Metrics::create(alumet)?.with_memory_stat_keys(config.memory_stat_keys.clone()),
+            reactor_config: ReactorConfig {
+                v1_refresh_interval: config.cgroupv1_refresh_interval,
+                add_source_in_pause_state: config.add_source_in_pause_state,
+                ..Default::default()
+            },
+            source_setup: source::JobSourceSetup::new(&config, tagger),
+            shared_hierarchy,
+        };
+        self.starting_state = Some(starting_state);
+        Ok(())
+    }
+
+    fn post_pipeline_start(&mut self, alumet: &mut AlumetPostStart) -> anyhow::Result<()> {
+        let s = self.starting_state.take().unwrap();
+
+        let reactor = CgroupReactor::new(
+            s.reactor_config,
+            s.metrics,
+            ReactorCallbacks {
+                probe_setup: s.source_setup,
+                on_removal: NoCallback,
+                on_fs_mount: s.shared_hierarchy,
+            },
+            alumet.pipeline_control(),
+        )
+        .context("failed to init CgroupReactor")?;
+        self.reactor = Some(reactor);
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        if let Some(reactor) = self.reactor.take() {
+            drop(reactor);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Config {
+    /// Interval between two measurements.
+    #[serde(with = "humantime_serde")]
+    pub poll_interval: Duration,
+
+    /// Interval between two scans of the cgroup v1 hierarchies.
+    /// Only used as a fallback, when a cgroup v1 hierarchy cannot be watched with inotify.
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    pub cgroupv1_refresh_interval: Option<Duration>,
+
+    /// Name of the parent cgroup of the jobs, as configured in the `cgroup_prefix` option of the PBS cgroups hook.
+    /// The default value is `pbs_jobs`.
+    #[serde(default = "default_cgroup_prefix")]
+    pub cgroup_prefix: String,
+
+    /// Only monitor the cgroups related to PBS jobs.
+    pub ignore_non_jobs: bool,
+
+    /// If `true`, the PBS sources will be started in pause state.
+    /// The default value is `false`.
+    ///
+    /// This behavior is necessary to have fine-grained control over which cgroup to monitor,
+    /// with a control plugin that manages the state of the sources.
+    #[serde(default)]
+    pub add_source_in_pause_state: bool,
+
+    /// If `true`, adds attributes like `job_id` to the measurements produced by other plugins.
+    /// The default value is `false`.
+    ///
+    /// The measurements must have the `cgroup` resource consumer, and **cgroup v2** must be used on the node.
+    #[serde(default)]
+    pub annotate_foreign_measurements: bool,
+
+    /// Additional keys of `memory.stat` to measure, like `file_mapped` or `pgmajfault`.
+    /// The default value is empty.
+    ///
+    /// With **cgroup v1**, use the keys of cgroup v1, like `total_mapped_file` or `total_pgmajfault`.
+    #[serde(default)]
+    pub memory_stat_keys: Vec<String>,
+}
+
+#[cfg_attr(tarpaulin, ignore)]
+fn default_cgroup_prefix() -> String {
+    String::from(DEFAULT_CGROUP_PREFIX)
+}
+
+impl Default for Config {
+    #[cfg_attr(tarpaulin, ignore)]
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(1),
+            cgroupv1_refresh_interval: None,
+            cgroup_prefix: default_cgroup_prefix(),
+            ignore_non_jobs: true,
+            add_source_in_pause_state: false,
+            annotate_foreign_measurements: false,
+            memory_stat_keys: Vec::new(),
+        }
+    }
+}
+
+pub struct StartingState {
+    metrics: Metrics,
+    reactor_config: ReactorConfig,
+    source_setup: source::JobSourceSetup,
+    shared_hierarchy: OptionalSharedHierarchy,
+}
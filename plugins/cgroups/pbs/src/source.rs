@@ -0,0 +1,55 @@
+use alumet::pipeline::elements::source::trigger::TriggerSpec;
+use util_cgroups::Cgroup;
+
+use crate::{
+    Config,
+    attr::{PbsJobTagger, find_key_in_attrs},
+};
+use util_cgroups_plugins::{
+    cgroup_events::{CgroupSetupCallback, ProbeSetup, SourceSettings},
+    metrics::{AugmentedMetrics, Metrics},
+};
+
+#[derive(Clone)]
+pub struct JobSourceSetup {
+    tagger: PbsJobTagger,
+    trigger: TriggerSpec,
+    ignore_non_jobs: bool,
+}
+
+impl JobSourceSetup {
+    pub fn new(config: &Config, tagger: PbsJobTagger) -> Self {
+        Self {
+            tagger,
+            trigger: TriggerSpec::at_interval(config.poll_interval),
+            ignore_non_jobs: config.ignore_non_jobs,
+        }
+    }
+}
+
+impl CgroupSetupCallback for JobSourceSetup {
+    fn setup_new_probe(&mut self, cgroup: &Cgroup, metrics: &Metrics) -> Option<ProbeSetup> {
+        // extracts attributes "pbs_job_id", "job_id", "array_index" and "server"
+        let (attrs, is_job) = self.tagger.extract(cgroup.canonical_path());
+
+        let name = match find_key_in_attrs("pbs_job_id", &attrs) {
+            // use the full job id as the source name, it is unique on the node
+            Some(pbs_job_id) if is_job => pbs_job_id,
+            Some(_) => {
+                // the consumption of the sub-cgroups is included in the one of the job
+                log::debug!("ignoring sub-cgroup of PBS job: {}", cgroup.canonical_path());
+                return None;
+            }
+            None if self.ignore_non_jobs => return None,
+            None => format!("cgroup {}", cgroup.unique_name()),
+        };
+
+        let trigger = self.trigger.clone();
+        let source_settings = SourceSettings { name, trigger };
+        let metrics = AugmentedMetrics::with_common_attr_vec(metrics, attrs);
+        Some(ProbeSetup {
+            metrics,
+            source_settings,
+        })
+    }
+}
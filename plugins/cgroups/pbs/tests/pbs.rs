@@ -0,0 +1,36 @@
+use std::time::Duration;
+
+use alumet::plugin::rust::{AlumetPlugin, deserialize_config, serialize_config};
+use plugin_pbs::{Config, PbsPlugin};
+
+#[test]
+fn test_default_config() {
+    let config_table = PbsPlugin::default_config().unwrap().unwrap();
+    let config: Config = deserialize_config(config_table).expect("failed to deserialize the default config");
+
+    assert!(config.ignore_non_jobs);
+    assert_eq!(config.cgroup_prefix, "pbs_jobs");
+    assert_eq!(config.poll_interval, Duration::from_secs(1));
+}
+
+#[test]
+fn test_init() -> anyhow::Result<()> {
+    let config_table = serialize_config(Config::default())?;
+    let plugin = PbsPlugin::init(config_table)?;
+    assert!(plugin.reactor.is_none());
+    assert!(plugin.starting_state.is_none());
+    Ok(())
+}
+
+#[test]
+fn test_invalid_config() {
+    let mut config_table = serialize_config(Config::default()).unwrap();
+    config_table.0.remove("poll_interval");
+    assert!(PbsPlugin::init(config_table).is_err());
+}
+
+#[test]
+fn test_stop() {
+    let mut plugin = PbsPlugin::new(Config::default());
+    plugin.stop().expect("stop should complete without errors");
+}
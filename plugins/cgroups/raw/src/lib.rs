@@ -48,7 +48,7 @@ impl AlumetPlugin for RawCgroupPlugin {
     }
 
     fn start(&mut self, alumet: &mut alumet::plugin::AlumetPluginStart) -> anyhow::Result<()> {
-        let metrics = Metrics::create(alumet)?;
+        let metrics = Metrics::create(alumet)?.with_memory_stat_keys(self.config.memory_stat_keys.clone());
         let reactor_config = ReactorConfig::default();
         let starting_state = StartingState {
             metrics,
@@ -93,6 +93,10 @@ struct StartingState {
 pub struct Config {
     #[serde(with = "humantime_serde")]
     pub poll_interval: Duration,
+    /// Additional keys of `memory.stat` to measure, like `file_mapped` or `pgmajfault`.
+    /// The default value is empty.
+    #[serde(default)]
+    pub memory_stat_keys: Vec<String>,
 }
 
 impl Default for Config {
@@ -100,6 +104,7 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             poll_interval: Duration::from_secs(5),
+            memory_stat_keys: Vec::new(),
         }
     }
 }
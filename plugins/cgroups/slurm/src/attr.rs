@@ -1,8 +1,11 @@
 use alumet::measurement::AttributeValue;
 use util_cgroups::Cgroup;
+use util_cgroups_plugins::cgroup_events::CgroupRemovalCallback;
 use util_cgroups_plugins::job_annotation_transform::JobTagger;
 use util_cgroups_plugins::regex::RegexAttributesExtrator;
 
+use crate::metadata::JobMetadataProvider;
+
 pub const JOB_REGEX_SLURM1: &str = "/slurm/uid_(?<user_id__u64>[0-9]+)/job_(?<job_id__u64>[0-9]+)";
 pub const JOB_REGEX_SLURM2: &str = "/slurmstepd.scope/job_(?<job_id__u64>[0-9]+)(?<remaining>(/.*)?)";
 
@@ -65,6 +68,8 @@ fn extract_values(path: &str) -> Option<JobDetails> {
 pub struct SlurmJobTagger {
     extractor_v1: RegexAttributesExtrator,
     extractor_v2: RegexAttributesExtrator,
+    /// Adds the metadata of the jobs (user, account, partition...), if enabled.
+    metadata: Option<JobMetadataProvider>,
 }
 
 impl SlurmJobTagger {
@@ -72,21 +77,38 @@ impl SlurmJobTagger {
         Ok(Self {
             extractor_v1: RegexAttributesExtrator::new(JOB_REGEX_SLURM1)?,
             extractor_v2: RegexAttributesExtrator::new(JOB_REGEX_SLURM2)?,
+            metadata: None,
         })
     }
-}
 
-impl JobTagger for SlurmJobTagger {
-    fn attributes_for_cgroup(&mut self, cgroup: &Cgroup) -> Vec<(String, AttributeValue)> {
-        // extracts attributes "job_id" and ("user" or "user_id")
+    pub fn with_metadata(mut self, provider: JobMetadataProvider) -> Self {
+        self.metadata = Some(provider);
+        self
+    }
+
+    /// Returns a tagger that shares the metadata cache of this one, but never runs `scontrol`.
+    pub fn cache_only(&self) -> Self {
+        Self {
+            metadata: self.metadata.as_ref().map(JobMetadataProvider::cache_only),
+            ..self.clone()
+        }
+    }
+
+    fn extract(&self, cgroup: &Cgroup) -> Vec<(String, AttributeValue)> {
         let extractor = match cgroup.hierarchy().version() {
             util_cgroups::CgroupVersion::V1 => &self.extractor_v1,
             util_cgroups::CgroupVersion::V2 => &self.extractor_v2,
         };
-
-        let mut attrs = extractor
+        extractor
             .extract(cgroup.canonical_path())
-            .expect("bad regex: it should only match if the input can be parsed into the specified types");
+            .expect("bad regex: it should only match if the input can be parsed into the specified types")
+    }
+}
+
+impl JobTagger for SlurmJobTagger {
+    fn attributes_for_cgroup(&mut self, cgroup: &Cgroup) -> Vec<(String, AttributeValue)> {
+        // extracts attributes "job_id" and ("user" or "user_id")
+        let mut attrs = self.extract(cgroup);
 
         // Retrieve remaining
         let remaining = attrs
@@ -108,10 +130,34 @@ impl JobTagger for SlurmJobTagger {
                 attrs.push(("task".to_string(), AttributeValue::String(task)));
             }
         }
+        if let (Some(provider), Some(job_id)) = (&self.metadata, find_jobid_in_attrs(&attrs))
+            && let Some(metadata) = provider.get(job_id)
+        {
+            attrs.extend(metadata.attributes());
+        }
         attrs
     }
 }
 
+impl CgroupRemovalCallback for SlurmJobTagger {
+    fn on_cgroups_removed(&mut self, cgroups: Vec<Cgroup>) -> anyhow::Result<()> {
+        let Some(provider) = &self.metadata else {
+            return Ok(());
+        };
+        for cgroup in cgroups {
+            // The cgroup of the job itself is removed at the end of the job, after the cgroups of the steps.
+            let attrs = self.extract(&cgroup);
+            let is_job = attrs
+                .iter()
+                .all(|(k, v)| k != "remaining" || matches!(v, AttributeValue::String(s) if s.is_empty()));
+            if let (Some(job_id), true) = (find_jobid_in_attrs(&attrs), is_job) {
+                provider.evict(job_id);
+            }
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::attr::*;
@@ -271,4 +317,62 @@ mod tests {
         assert_eq!(ret_unwraped.sub_step.unwrap(), "Iowa".to_string());
         assert_eq!(ret_unwraped.task.unwrap(), "2001".to_string());
     }
+
+    #[test]
+    fn test_tagger_with_metadata() {
+        use std::sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        };
+
+        use util_cgroups::{CgroupHierarchy, CgroupVersion};
+        use util_cgroups_plugins::cgroup_events::CgroupRemovalCallback;
+
+        use crate::metadata::{
+            JobMetadataProvider,
+            tests::{SCONTROL_OUTPUT, StubRunner, wait_for_job},
+        };
+
+        let runner = Arc::new(StubRunner {
+            output: Ok(SCONTROL_OUTPUT.to_owned()),
+            calls: AtomicUsize::new(0),
+        });
+        let provider = JobMetadataProvider::new(runner.clone(), String::from("scontrol"));
+        let mut tagger = SlurmJobTagger::new().unwrap().with_metadata(provider.clone());
+
+        let hierarchy = CgroupHierarchy::manually_unchecked("/sys/fs/cgroup", CgroupVersion::V2, vec!["cpu"]);
+        let job = Cgroup::from_cgroup_path(&hierarchy, String::from("/system.slice/slurmstepd.scope/job_10707"));
+        let task = Cgroup::from_cgroup_path(
+            &hierarchy,
+            String::from("/system.slice/slurmstepd.scope/job_10707/step_0/user/task_0"),
+        );
+
+        // the metadata is obtained in the background
+        let attrs = tagger.attributes_for_cgroup(&task);
+        assert_eq!(find_jobid_in_attrs(&attrs), Some(10707));
+        assert_eq!(find_key_in_attrs("task", &attrs), Some(String::from("0")));
+        assert_eq!(find_key_in_attrs("account", &attrs), None);
+        wait_for_job(&provider, 10707);
+
+        let attrs = tagger.attributes_for_cgroup(&task);
+        assert_eq!(find_key_in_attrs("account", &attrs), Some(String::from("physics")));
+        assert_eq!(find_key_in_attrs("job_name", &attrs), Some(String::from("train model")));
+        assert!(attrs.contains(&(String::from("requested_gpus"), AttributeValue::U64(2))));
+
+        // the metadata is shared by the job and its steps
+        let attrs = tagger.attributes_for_cgroup(&job);
+        assert_eq!(find_key_in_attrs("user", &attrs), Some(String::from("alice")));
+        assert_eq!(runner.calls.load(Ordering::Relaxed), 1);
+
+        // the end of a step does not evict the job, the end of the job does
+        tagger.on_cgroups_removed(vec![task]).unwrap();
+        tagger.attributes_for_cgroup(&job);
+        assert_eq!(runner.calls.load(Ordering::Relaxed), 1);
+        tagger.on_cgroups_removed(vec![job.clone()]).unwrap();
+        let attrs = tagger.cache_only().attributes_for_cgroup(&job);
+        assert_eq!(find_key_in_attrs("user", &attrs), None);
+        assert_eq!(runner.calls.load(Ordering::Relaxed), 1);
+        wait_for_job(&provider, 10707);
+        assert_eq!(runner.calls.load(Ordering::Relaxed), 2);
+    }
 }
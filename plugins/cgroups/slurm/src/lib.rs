@@ -1,4 +1,4 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 use alumet::plugin::{
     AlumetPluginStart, AlumetPostStart, ConfigTable,
@@ -8,17 +8,25 @@ use anyhow::Context;
 use serde::{Deserialize, Serialize};
 
 use util_cgroups_plugins::{
-    cgroup_events::{CgroupReactor, NoCallback, ReactorCallbacks, ReactorConfig},
+    aggregation::{AggregationConfig, CgroupAggregation, setup_aggregation},
+    cgroup_events::{CgroupReactor, ReactorCallbacks, ReactorConfig},
     job_annotation_transform::{
         CachedCgroupHierarchy, JobAnnotationTransform, OptionalSharedHierarchy, SharedCgroupHierarchy,
     },
+    job_summary::{JobSummaries, JobSummaryConfig, setup_job_summaries},
     metrics::Metrics,
 };
 
-use crate::attr::SlurmJobTagger;
+use crate::{
+    attr::SlurmJobTagger,
+    metadata::{JobMetadataProvider, ProcessRunner},
+    transform::JobMetadataAttacher,
+};
 
 mod attr;
+mod metadata;
 mod source;
+mod transform;
 
 /// Gathers metrics for slurm jobs.
 ///
@@ -63,7 +71,18 @@ impl AlumetPlugin for SlurmPlugin {
     fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
         let config = self.config.take().unwrap();
 
-        let tagger = SlurmJobTagger::new()?;
+        let mut tagger = SlurmJobTagger::new()?;
+        if config.job_metadata {
+            let runner = ProcessRunner {
+                timeout: config.scontrol_timeout,
+            };
+            let provider = JobMetadataProvider::new(Arc::new(runner), config.scontrol_path.clone());
+            tagger = tagger.with_metadata(provider.clone());
+
+            // The metadata is obtained in the background: add it to the measurements of the jobs once it is available.
+            let transform = JobMetadataAttacher::new(provider);
+            alumet.add_transform("slurm/metadata", Box::new(transform))?;
+        }
         let mut shared_hierarchy = OptionalSharedHierarchy::default();
 
         // If enabled, create the annotation transform.
@@ -72,20 +91,39 @@ impl AlumetPlugin for SlurmPlugin {
             shared_hierarchy.enable(shared.clone());
 
             let transform = JobAnnotationTransform {
-                tagger: tagger.clone(),
+                // the metadata of the jobs is obtained by the reactor, when the cgroups are created
+                tagger: tagger.cache_only(),
                 cgroup_v2_hierarchy: CachedCgroupHierarchy::new(shared),
             };
             alumet.add_transform("slurm/annotation", Box::new(transform))?;
         }
 
+        let metrics = Metrics::create(alumet)?.with_memory_stat_keys(config.memory_stat_keys.clone());
+
+        // If enabled, summarize the consumption of each job when it ends.
+        let job_summaries = match config.job_summary.clone() {
+            Some(summary_config) => Some(setup_job_summaries(alumet, summary_config, &metrics)?),
+            None => None,
+        };
+
+        // If enabled, aggregate the measurements of the cgroups of each job (e.g. its steps).
+        let aggregation = match config.aggregation.clone() {
+            Some(aggregation_config) => Some(setup_aggregation(alumet, aggregation_config, &metrics)?),
+            None => None,
+        };
+
         // Prepare for cgroup detection.
         let starting_state = StartingState {
-            metrics: Metrics::create(alumet)?,
+            metrics,
             reactor_config: ReactorConfig {
+                v1_refresh_interval: config.cgroupv1_refresh_interval,
                 add_source_in_pause_state: config.add_source_in_pause_state,
                 ..Default::default()
             },
-            source_setup: source::JobSourceSetup::new(config, tagger)?,
+            source_setup: source::JobSourceSetup::new(config, tagger.clone(), job_summaries.clone())?,
+            tagger,
+            job_summaries,
+            aggregation,
             shared_hierarchy,
         };
         self.starting_state = Some(starting_state);
@@ -100,7 +138,7 @@ impl AlumetPlugin for SlurmPlugin {
             s.metrics,
             ReactorCallbacks {
                 probe_setup: s.source_setup,
-                on_removal: NoCallback,
+                on_removal: (s.tagger, (s.job_summaries, s.aggregation)),
                 on_fs_mount: s.shared_hierarchy,
             },
             alumet.pipeline_control(),
@@ -125,7 +163,7 @@ pub struct Config {
     pub poll_interval: Duration,
 
     /// Interval between two scans of the cgroup v1 hierarchies.
-    /// Only applies to cgroup v1 hierarchies (cgroupv2 supports inotify).
+    /// Only used as a fallback, when a cgroup v1 hierarchy cannot be watched with inotify.
     #[serde(default)]
     #[serde(with = "humantime_serde")]
     pub cgroupv1_refresh_interval: Option<Duration>,
@@ -150,6 +188,52 @@ pub struct Config {
     /// The measurements must have the `cgroup` resource consumer, and **cgroup v2** must be used on the node.
     #[serde(default)]
     pub annotate_foreign_measurements: bool,
+
+    /// Additional keys of `memory.stat` to measure, like `file_mapped` or `pgmajfault`.
+    /// The default value is empty.
+    ///
+    /// With **cgroup v1**, use the keys of cgroup v1, like `total_mapped_file` or `total_pgmajfault`.
+    #[serde(default)]
+    pub memory_stat_keys: Vec<String>,
+
+    /// If `true`, adds the metadata of the jobs to the measurements: user, account, partition, QOS,
+    /// job name and requested resources.
+    /// The default value is `false`.
+    ///
+    /// The metadata is obtained in the background with `scontrol show job` and kept in cache until the end of the job.
+    /// The measurements taken before it is available don't have it.
+    #[serde(default)]
+    pub job_metadata: bool,
+
+    /// Path to the `scontrol` command, used when `job_metadata` is enabled.
+    #[serde(default = "default_scontrol_path")]
+    pub scontrol_path: String,
+
+    /// Maximum time to wait for `scontrol`, used when `job_metadata` is enabled.
+    /// The default value is 10 seconds.
+    #[serde(with = "humantime_serde", default = "default_scontrol_timeout")]
+    pub scontrol_timeout: Duration,
+
+    /// If set, emits a summary of the consumption of each job (energy, CPU time, peak memory) when it ends.
+    /// The default value is `None` (disabled).
+    #[serde(default)]
+    pub job_summary: Option<JobSummaryConfig>,
+
+    /// If set, aggregates the measurements of the cgroups of each job, with `inclusive` and `exclusive` values.
+    /// This is useful when the steps are monitored (see `jobs_monitoring_level`).
+    /// The default value is `None` (disabled).
+    #[serde(default)]
+    pub aggregation: Option<AggregationConfig>,
+}
+
+#[cfg_attr(tarpaulin, ignore)]
+fn default_scontrol_path() -> String {
+    String::from("scontrol")
+}
+
+#[cfg_attr(tarpaulin, ignore)]
+fn default_scontrol_timeout() -> Duration {
+    Duration::from_secs(10)
 }
 
 impl Default for Config {
@@ -162,6 +246,12 @@ impl Default for Config {
             jobs_monitoring_level: JobMonitoringLevel::Job,
             add_source_in_pause_state: false,
             annotate_foreign_measurements: false,
+            memory_stat_keys: Vec::new(),
+            job_metadata: false,
+            scontrol_path: default_scontrol_path(),
+            scontrol_timeout: default_scontrol_timeout(),
+            job_summary: None,
+            aggregation: None,
         }
     }
 }
@@ -170,6 +260,9 @@ pub struct StartingState {
     metrics: Metrics,
     reactor_config: ReactorConfig,
     source_setup: source::JobSourceSetup,
+    tagger: SlurmJobTagger,
+    job_summaries: Option<JobSummaries>,
+    aggregation: Option<CgroupAggregation>,
     shared_hierarchy: OptionalSharedHierarchy,
 }
 
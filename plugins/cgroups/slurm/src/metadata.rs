@@ -0,0 +1,482 @@
+use std::{
+    io::Read,
+    process::{Command, Stdio},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use alumet::measurement::AttributeValue;
+use anyhow::{Context, anyhow};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// How long a failed lookup is remembered, so that `scontrol` is not run again for the same job.
+const NEGATIVE_CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// How often [`ProcessRunner`] checks whether the command has exited.
+const EXIT_CHECK_PERIOD: Duration = Duration::from_millis(10);
+
+/// Runs an external command and returns its standard output.
+///
+/// This trait allows to replace `scontrol` in the tests.
+pub trait CommandRunner: Send + Sync {
+    fn run(&self, program: &str, args: &[&str]) -> anyhow::Result<String>;
+}
+
+/// Runs the commands in child processes, and kills them if they take too long.
+pub struct ProcessRunner {
+    pub timeout: Duration,
+}
+
+impl CommandRunner for ProcessRunner {
+    fn run(&self, program: &str, args: &[&str]) -> anyhow::Result<String> {
+        log::debug!("running: {program} {}", args.join(" "));
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("failed to run {program}"))?;
+
+        // The output of scontrol is small enough to fit in the pipes: it can be read after the exit.
+        let deadline = Instant::now() + self.timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(anyhow!("{program} did not finish in {:?}", self.timeout));
+            }
+            std::thread::sleep(EXIT_CHECK_PERIOD);
+        };
+
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        if let Some(mut out) = child.stdout.take() {
+            out.read_to_end(&mut stdout)?;
+        }
+        if let Some(mut err) = child.stderr.take() {
+            err.read_to_end(&mut stderr)?;
+        }
+        if !status.success() {
+            let error_message = String::from_utf8_lossy(&stderr).into_owned();
+            return Err(anyhow!("{program} failed with {status}").context(error_message));
+        }
+        String::from_utf8(stdout).with_context(|| format!("{program} produced an invalid output"))
+    }
+}
+
+/// Information about a Slurm job, which is not available in the cgroup path.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct JobMetadata {
+    pub user: Option<String>,
+    pub account: Option<String>,
+    pub partition: Option<String>,
+    pub qos: Option<String>,
+    pub job_name: Option<String>,
+    pub requested_cpus: Option<u64>,
+    pub requested_nodes: Option<u64>,
+    /// Requested memory, in bytes.
+    pub requested_memory: Option<u64>,
+    pub requested_gpus: Option<u64>,
+}
+
+impl JobMetadata {
+    /// Parses the output of `scontrol show job --oneliner <job_id>`.
+    ///
+    /// The output is made of `Key=Value` pairs separated by spaces, for instance:
+    /// `JobId=10707 JobName=train UserId=alice(1000) Account=physics QOS=normal Partition=gpu ReqTRES=cpu=8,mem=16G,node=1,gres/gpu=2`.
+    /// Some values, like the job name, can contain spaces.
+    pub fn from_scontrol_output(output: &str) -> JobMetadata {
+        let fields = parse_key_values(output.trim());
+        let get = |key: &str| {
+            fields
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.as_str())
+                .filter(|v| !v.is_empty() && *v != "(null)")
+        };
+
+        let mut metadata = JobMetadata {
+            // "alice(1000)" => "alice"
+            user: get("UserId").map(|u| u.split_once('(').map_or(u, |(name, _)| name).to_owned()),
+            account: get("Account").map(str::to_owned),
+            partition: get("Partition").map(str::to_owned),
+            qos: get("QOS").map(str::to_owned),
+            job_name: get("JobName").map(str::to_owned),
+            requested_cpus: get("NumCPUs").and_then(|n| n.parse().ok()),
+            requested_nodes: get("NumNodes").and_then(|n| n.split('-').next()?.parse().ok()),
+            requested_memory: None,
+            requested_gpus: None,
+        };
+
+        // ReqTRES is more precise than NumCPUs and NumNodes, and contains the memory and the GPUs
+        for (resource, value) in get("ReqTRES")
+            .into_iter()
+            .flat_map(|tres| tres.split(','))
+            .filter_map(|r| r.split_once('='))
+        {
+            match resource {
+                "cpu" => metadata.requested_cpus = value.parse().ok().or(metadata.requested_cpus),
+                "node" => metadata.requested_nodes = value.parse().ok().or(metadata.requested_nodes),
+                "mem" => metadata.requested_memory = parse_memory(value),
+                "gres/gpu" => metadata.requested_gpus = value.parse().ok(),
+                _ => (),
+            }
+        }
+        metadata
+    }
+
+    pub fn attributes(&self) -> Vec<(String, AttributeValue)> {
+        let strings = [
+            ("user", &self.user),
+            ("account", &self.account),
+            ("partition", &self.partition),
+            ("qos", &self.qos),
+            ("job_name", &self.job_name),
+        ];
+        let numbers = [
+            ("requested_cpus", self.requested_cpus),
+            ("requested_nodes", self.requested_nodes),
+            ("requested_memory", self.requested_memory),
+            ("requested_gpus", self.requested_gpus),
+        ];
+        let strings = strings
+            .into_iter()
+            .filter_map(|(k, v)| Some((k.to_owned(), AttributeValue::String(v.clone()?))));
+        let numbers = numbers
+            .into_iter()
+            .filter_map(|(k, v)| Some((k.to_owned(), AttributeValue::U64(v?))));
+        strings.chain(numbers).collect()
+    }
+}
+
+/// Splits a line of `Key=Value` pairs.
+///
+/// A word that does not start with `Key=` is part of the previous value.
+fn parse_key_values(line: &str) -> Vec<(&str, String)> {
+    let is_key = |k: &str| {
+        k.starts_with(|c: char| c.is_ascii_alphabetic())
+            && k.chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | ':' | '/'))
+    };
+
+    let mut fields: Vec<(&str, String)> = Vec::new();
+    for word in line.split(' ').filter(|w| !w.is_empty()) {
+        match word.split_once('=') {
+            Some((key, value)) if is_key(key) => fields.push((key, value.to_owned())),
+            _ => {
+                if let Some((_, value)) = fields.last_mut() {
+                    value.push(' ');
+                    value.push_str(word);
+                }
+            }
+        }
+    }
+    fields
+}
+
+/// Parses an amount of memory in the format of Slurm, like `16G`, and returns it in bytes.
+///
+/// Without suffix, the unit is the megabyte (MiB).
+fn parse_memory(value: &str) -> Option<u64> {
+    let (number, multiplier) = match value.char_indices().last()? {
+        (i, 'K') => (&value[..i], 1u64 << 10),
+        (i, 'M') => (&value[..i], 1 << 20),
+        (i, 'G') => (&value[..i], 1 << 30),
+        (i, 'T') => (&value[..i], 1 << 40),
+        (i, 'P') => (&value[..i], 1 << 50),
+        _ => (value, 1u64 << 20),
+    };
+    // the value can be fractional, like "1.50G"
+    let number: f64 = number.parse().ok()?;
+    Some((number * multiplier as f64) as u64)
+}
+
+/// Obtains the metadata of the jobs with `scontrol` and keeps it in cache.
+///
+/// `scontrol` is run in the background, because it can take some time (it asks the Slurm controller).
+/// Until it has finished, the metadata of the job is not available.
+///
+/// The provider can be cloned, the clones share the same cache.
+#[derive(Clone)]
+pub struct JobMetadataProvider {
+    /// Runs `scontrol`, or `None` if the provider only reads the cache (see [`Self::cache_only`]).
+    runner: Option<Arc<dyn CommandRunner>>,
+    scontrol_path: String,
+    cache: Arc<Mutex<Cache>>,
+}
+
+#[derive(Default)]
+struct Cache {
+    /// Metadata, by job id.
+    jobs: FxHashMap<u64, JobMetadata>,
+    /// Time of the failed lookups, by job id.
+    ///
+    /// The provider is called for every new cgroup of the job: we don't try again immediately.
+    failures: FxHashMap<u64, Instant>,
+    /// The jobs whose metadata is being obtained in the background.
+    pending: FxHashSet<u64>,
+}
+
+impl JobMetadataProvider {
+    pub fn new(runner: Arc<dyn CommandRunner>, scontrol_path: String) -> Self {
+        Self {
+            runner: Some(runner),
+            scontrol_path,
+            cache: Default::default(),
+        }
+    }
+
+    /// Returns a provider that shares the cache of this one, but never runs `scontrol`.
+    ///
+    /// Use it where the jobs are not discovered, like in a transform.
+    pub fn cache_only(&self) -> Self {
+        Self {
+            runner: None,
+            scontrol_path: self.scontrol_path.clone(),
+            cache: self.cache.clone(),
+        }
+    }
+
+    /// Returns the metadata of the job if it is in cache.
+    ///
+    /// Otherwise, starts obtaining it in the background (unless the provider is cache-only) and returns `None`.
+    pub fn get(&self, job_id: u64) -> Option<JobMetadata> {
+        let runner = {
+            let mut cache = self.cache.lock().unwrap();
+            if let Some(metadata) = cache.jobs.get(&job_id) {
+                return Some(metadata.clone());
+            }
+            let Some(runner) = &self.runner else {
+                return None;
+            };
+            match cache.failures.get(&job_id) {
+                Some(t) if t.elapsed() < NEGATIVE_CACHE_TTL => return None,
+                Some(_) => {
+                    cache.failures.remove(&job_id);
+                }
+                None => (),
+            }
+            if !cache.pending.insert(job_id) {
+                // already in progress
+                return None;
+            }
+            runner.clone()
+        };
+
+        let scontrol_path = self.scontrol_path.clone();
+        let cache = self.cache.clone();
+        let res = std::thread::Builder::new()
+            .name(String::from("slurm-scontrol"))
+            .spawn(move || fetch(runner.as_ref(), &scontrol_path, &cache, job_id));
+        if let Err(e) = res {
+            log::error!("failed to spawn a thread to get the metadata of Slurm job {job_id}: {e}");
+            let mut cache = self.cache.lock().unwrap();
+            cache.pending.remove(&job_id);
+            cache.failures.insert(job_id, Instant::now());
+        }
+        None
+    }
+
+    /// Removes a job from the cache, when it has ended.
+    pub fn evict(&self, job_id: u64) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.jobs.remove(&job_id);
+        cache.failures.remove(&job_id);
+        // if scontrol is still running, its result will be ignored
+        cache.pending.remove(&job_id);
+    }
+}
+
+/// Runs `scontrol` to obtain the metadata of a job, and puts the result in the cache.
+fn fetch(runner: &dyn CommandRunner, scontrol_path: &str, cache: &Mutex<Cache>, job_id: u64) {
+    let job_id_str = job_id.to_string();
+    let res = runner.run(scontrol_path, &["show", "job", "--oneliner", &job_id_str]);
+    let mut cache = cache.lock().unwrap();
+    if !cache.pending.remove(&job_id) {
+        // the job has ended in the meantime
+        return;
+    }
+    match res {
+        Ok(output) => {
+            cache.jobs.insert(job_id, JobMetadata::from_scontrol_output(&output));
+        }
+        Err(e) => {
+            log::warn!("failed to get the metadata of Slurm job {job_id}: {e:#}");
+            cache.failures.insert(job_id, Instant::now());
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    pub const SCONTROL_OUTPUT: &str = "JobId=10707 JobName=train model UserId=alice(1000) GroupId=alice(1000) MCS_label=N/A Priority=4294901758 Nice=0 Account=physics QOS=normal JobState=RUNNING Reason=None Dependency=(null) Partition=gpu AllocNode:Sid=login1:12345 NodeList=node[1-2] NumNodes=2 NumCPUs=8 NumTasks=8 CPUs/Task=1 ReqB:S:C:T=0:0:*:* ReqTRES=cpu=8,mem=16G,node=2,billing=8,gres/gpu=2 AllocTRES=cpu=8,mem=16G,node=2,billing=8,gres/gpu=2 Command=/home/alice/train.sh WorkDir=/home/alice\n";
+
+    /// Returns a fixed output and counts the calls.
+    pub struct StubRunner {
+        pub output: anyhow::Result<String>,
+        pub calls: AtomicUsize,
+    }
+
+    impl CommandRunner for StubRunner {
+        fn run(&self, program: &str, args: &[&str]) -> anyhow::Result<String> {
+            assert_eq!(program, "scontrol");
+            assert_eq!(&args[..3], &["show", "job", "--oneliner"]);
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            match &self.output {
+                Ok(output) => Ok(output.clone()),
+                Err(e) => Err(anyhow!("{e}")),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_scontrol() {
+        let metadata = JobMetadata::from_scontrol_output(SCONTROL_OUTPUT);
+        assert_eq!(
+            metadata,
+            JobMetadata {
+                user: Some(String::from("alice")),
+                account: Some(String::from("physics")),
+                partition: Some(String::from("gpu")),
+                qos: Some(String::from("normal")),
+                job_name: Some(String::from("train model")),
+                requested_cpus: Some(8),
+                requested_nodes: Some(2),
+                requested_memory: Some(16 << 30),
+                requested_gpus: Some(2),
+            }
+        );
+
+        // without ReqTRES
+        let metadata = JobMetadata::from_scontrol_output("JobId=12 JobName=x Account=(null) NumNodes=1-2 NumCPUs=4");
+        assert_eq!(
+            metadata,
+            JobMetadata {
+                job_name: Some(String::from("x")),
+                requested_cpus: Some(4),
+                requested_nodes: Some(1),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn memory() {
+        assert_eq!(parse_memory("16G"), Some(16 << 30));
+        assert_eq!(parse_memory("512M"), Some(512 << 20));
+        assert_eq!(parse_memory("1.5T"), Some(3 << 39));
+        assert_eq!(parse_memory("2000"), Some(2000 << 20));
+        assert_eq!(parse_memory("lots"), None);
+        assert_eq!(parse_memory(""), None);
+    }
+
+    #[test]
+    fn attributes() {
+        let metadata = JobMetadata {
+            user: Some(String::from("alice")),
+            partition: Some(String::from("gpu")),
+            requested_cpus: Some(8),
+            ..Default::default()
+        };
+        assert_eq!(
+            metadata.attributes(),
+            vec![
+                (String::from("user"), AttributeValue::String(String::from("alice"))),
+                (String::from("partition"), AttributeValue::String(String::from("gpu"))),
+                (String::from("requested_cpus"), AttributeValue::U64(8)),
+            ]
+        );
+    }
+
+    /// Calls `get` until the metadata of the job is available, which happens after `scontrol` has run in the background.
+    pub fn wait_for_job(provider: &JobMetadataProvider, job_id: u64) -> JobMetadata {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Some(metadata) = provider.get(job_id) {
+                return metadata;
+            }
+            assert!(
+                Instant::now() < deadline,
+                "the metadata of job {job_id} is not available"
+            );
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    /// Waits for the background `scontrol` of the job to end.
+    fn wait_for_fetch(provider: &JobMetadataProvider, job_id: u64) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while provider.cache.lock().unwrap().pending.contains(&job_id) {
+            assert!(Instant::now() < deadline, "scontrol is still running for job {job_id}");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn provider_cache() {
+        let runner = Arc::new(StubRunner {
+            output: Ok(SCONTROL_OUTPUT.to_owned()),
+            calls: AtomicUsize::new(0),
+        });
+        let provider = JobMetadataProvider::new(runner.clone(), String::from("scontrol"));
+        let cache_only = provider.cache_only();
+        assert_eq!(cache_only.get(10707), None);
+        assert_eq!(runner.calls.load(Ordering::Relaxed), 0);
+
+        let metadata = wait_for_job(&provider, 10707);
+        assert_eq!(metadata.account.as_deref(), Some("physics"));
+        assert_eq!(provider.clone().get(10707), Some(metadata.clone()));
+        assert_eq!(cache_only.get(10707), Some(metadata));
+        assert_eq!(runner.calls.load(Ordering::Relaxed), 1);
+
+        provider.evict(10707);
+        assert_eq!(cache_only.get(10707), None);
+        wait_for_job(&provider, 10707);
+        assert_eq!(runner.calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn provider_failure() {
+        let runner = Arc::new(StubRunner {
+            output: Err(anyhow!("slurm_load_jobs error: Invalid job id specified")),
+            calls: AtomicUsize::new(0),
+        });
+        let provider = JobMetadataProvider::new(runner.clone(), String::from("scontrol"));
+        assert_eq!(provider.get(1), None);
+        wait_for_fetch(&provider, 1);
+        assert_eq!(provider.get(1), None);
+        // the failure is cached
+        assert_eq!(runner.calls.load(Ordering::Relaxed), 1);
+
+        // until it expires
+        let failed_at = Instant::now().checked_sub(NEGATIVE_CACHE_TTL).unwrap();
+        provider.cache.lock().unwrap().failures.insert(1, failed_at);
+        assert_eq!(provider.get(1), None);
+        wait_for_fetch(&provider, 1);
+        assert_eq!(runner.calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn process_timeout() {
+        let runner = ProcessRunner {
+            timeout: Duration::from_millis(100),
+        };
+        assert_eq!(runner.run("echo", &["JobId=1"]).unwrap(), "JobId=1\n");
+        assert!(runner.run("false", &[]).is_err());
+
+        let start = Instant::now();
+        let err = runner.run("sleep", &["10"]).unwrap_err();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(err.to_string().contains("did not finish"), "unexpected error {err}");
+    }
+}
@@ -8,6 +8,7 @@ use crate::{
 use util_cgroups_plugins::{
     cgroup_events::{CgroupSetupCallback, ProbeSetup, SourceSettings},
     job_annotation_transform::JobTagger,
+    job_summary::JobSummaries,
     metrics::{AugmentedMetrics, Metrics},
 };
 
@@ -17,10 +18,11 @@ pub struct JobSourceSetup {
     trigger: TriggerSpec,
     ignore_non_jobs: bool,
     jobs_monitoring_level: JobMonitoringLevel,
+    job_summaries: Option<JobSummaries>,
 }
 
 impl JobSourceSetup {
-    pub fn new(config: Config, tagger: SlurmJobTagger) -> anyhow::Result<Self> {
+    pub fn new(config: Config, tagger: SlurmJobTagger, job_summaries: Option<JobSummaries>) -> anyhow::Result<Self> {
         let trigger = TriggerSpec::at_interval(config.poll_interval);
 
         Ok(Self {
@@ -28,6 +30,7 @@ impl JobSourceSetup {
             trigger,
             ignore_non_jobs: config.ignore_non_jobs,
             jobs_monitoring_level: config.jobs_monitoring_level,
+            job_summaries,
         })
     }
 }
@@ -86,6 +89,11 @@ impl CgroupSetupCallback for JobSourceSetup {
             None => return None,
         };
 
+        // Only summarize the jobs: the consumption of the steps is included in the one of their job.
+        if let (Some(summaries), Some(_), None) = (&self.job_summaries, job_id, &step_id) {
+            summaries.register(cgroup, attrs.clone());
+        }
+
         let trigger = self.trigger.clone();
         let source_settings = SourceSettings { name, trigger };
         let metrics = AugmentedMetrics::with_common_attr_vec(metrics, attrs);
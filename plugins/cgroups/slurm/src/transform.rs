@@ -0,0 +1,120 @@
+use alumet::{
+    measurement::{AttributeValue, MeasurementBuffer},
+    pipeline::{
+        Transform,
+        elements::{error::TransformError, transform::TransformContext},
+    },
+};
+
+use crate::metadata::JobMetadataProvider;
+
+/// Adds the metadata of the jobs to their measurements.
+///
+/// The source of a job is created as soon as its cgroup appears, and the metadata is obtained in the background
+/// with `scontrol`. Once it is in cache, it is added here to the measurements that don't have it yet.
+pub struct JobMetadataAttacher {
+    provider: JobMetadataProvider,
+}
+
+impl JobMetadataAttacher {
+    pub fn new(provider: JobMetadataProvider) -> Self {
+        Self {
+            provider: provider.cache_only(),
+        }
+    }
+}
+
+impl Transform for JobMetadataAttacher {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, _ctx: &TransformContext) -> Result<(), TransformError> {
+        self.attach(measurements);
+        Ok(())
+    }
+}
+
+impl JobMetadataAttacher {
+    fn attach(&self, measurements: &mut MeasurementBuffer) {
+        for m in measurements.iter_mut() {
+            let job_id = m.attributes().find_map(|(k, v)| match (k, v) {
+                ("job_id", AttributeValue::U64(id)) => Some(*id),
+                _ => None,
+            });
+            if let Some(job_id) = job_id
+                && let Some(metadata) = self.provider.get(job_id)
+            {
+                for (key, value) in metadata.attributes() {
+                    if !m.attributes_keys().any(|k| k == key) {
+                        m.add_attr(key, value);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, atomic::AtomicUsize};
+
+    use alumet::{
+        measurement::{MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        resources::{Resource, ResourceConsumer},
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::metadata::tests::{SCONTROL_OUTPUT, StubRunner, wait_for_job};
+
+    #[test]
+    fn attach_metadata() {
+        let runner = Arc::new(StubRunner {
+            output: Ok(SCONTROL_OUTPUT.to_owned()),
+            calls: AtomicUsize::new(0),
+        });
+        let provider = JobMetadataProvider::new(runner, String::from("scontrol"));
+        let attacher = JobMetadataAttacher::new(provider.clone());
+
+        let point = |job_id: u64| {
+            MeasurementPoint::new_untyped(
+                Timestamp::now(),
+                RawMetricId::from_u64(0),
+                Resource::LocalMachine,
+                ResourceConsumer::ControlGroup {
+                    path: "/system.slice/slurmstepd.scope/job_10707".into(),
+                },
+                WrappedMeasurementValue::U64(1),
+            )
+            .with_attr("job_id", job_id)
+        };
+        let keys = |buf: &MeasurementBuffer| -> Vec<String> {
+            let m = buf.iter().next().unwrap();
+            m.attributes_keys().map(str::to_owned).collect()
+        };
+
+        // the metadata is not in cache yet, and the transform does not run scontrol
+        let mut buf = MeasurementBuffer::new();
+        buf.push(point(10707));
+        attacher.attach(&mut buf);
+        assert_eq!(keys(&buf), vec!["job_id"]);
+
+        // the metadata has been obtained in the background
+        let metadata = wait_for_job(&provider, 10707);
+        let mut expected = vec![String::from("job_id")];
+        expected.extend(metadata.attributes().into_iter().map(|(k, _)| k));
+
+        let mut buf = MeasurementBuffer::new();
+        buf.push(point(10707));
+        attacher.attach(&mut buf);
+        assert_eq!(keys(&buf), expected);
+
+        // the measurements that already have the metadata are not modified
+        attacher.attach(&mut buf);
+        assert_eq!(keys(&buf), expected);
+
+        // other jobs are not modified
+        let mut buf = MeasurementBuffer::new();
+        buf.push(point(1));
+        attacher.attach(&mut buf);
+        assert_eq!(keys(&buf), vec!["job_id"]);
+    }
+}
@@ -0,0 +1,109 @@
+use anyhow::Context;
+use regex::Regex;
+
+use crate::unit::{SystemdUnit, UnitKind};
+
+/// Chooses the units to monitor, based on glob patterns.
+#[derive(Debug, Clone)]
+pub struct UnitFilter {
+    units: Option<Regex>,
+    exclude_units: Option<Regex>,
+    slices: Option<Regex>,
+}
+
+impl UnitFilter {
+    /// Creates a new filter.
+    ///
+    /// - The services and scopes that match one of the `units` and none of the `exclude_units` are accepted.
+    /// - The slices that match one of the `slices` are accepted.
+    pub fn new(units: &[String], exclude_units: &[String], slices: &[String]) -> anyhow::Result<Self> {
+        Ok(Self {
+            units: globs_to_regex(units).context("invalid units")?,
+            exclude_units: globs_to_regex(exclude_units).context("invalid exclude_units")?,
+            slices: globs_to_regex(slices).context("invalid slices")?,
+        })
+    }
+
+    pub fn accepts(&self, unit: &SystemdUnit) -> bool {
+        let matches = |regex: &Option<Regex>| regex.as_ref().is_some_and(|r| r.is_match(&unit.name));
+        match unit.kind {
+            UnitKind::Service | UnitKind::Scope => matches(&self.units) && !matches(&self.exclude_units),
+            UnitKind::Slice => matches(&self.slices),
+        }
+    }
+}
+
+/// Turns a list of glob patterns into a single regex that matches a whole unit name.
+///
+/// In the patterns, `*` matches any sequence of characters and `?` matches exactly one character.
+/// Returns `None` if the list is empty.
+fn globs_to_regex(globs: &[String]) -> anyhow::Result<Option<Regex>> {
+    if globs.is_empty() {
+        return Ok(None);
+    }
+    let alternatives: Vec<String> = globs
+        .iter()
+        .map(|glob| {
+            glob.split('*')
+                .map(|part| part.split('?').map(regex::escape).collect::<Vec<_>>().join("."))
+                .collect::<Vec<_>>()
+                .join(".*")
+        })
+        .collect();
+    let regex = format!("^(?:{})$", alternatives.join("|"));
+    let regex = Regex::new(&regex).with_context(|| format!("failed to build regex from globs {globs:?}"))?;
+    Ok(Some(regex))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(name: &str) -> SystemdUnit {
+        SystemdUnit::from_cgroup_path(&format!("/system.slice/{name}")).unwrap()
+    }
+
+    fn strings(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn globs() {
+        let regex = globs_to_regex(&strings(&["*.service", "session-?.scope"]))
+            .unwrap()
+            .unwrap();
+        assert!(regex.is_match("nginx.service"));
+        assert!(regex.is_match("getty@tty1.service"));
+        assert!(regex.is_match("session-2.scope"));
+        assert!(!regex.is_match("session-12.scope"));
+        assert!(!regex.is_match("nginx.service.bak"));
+        // the dot is not a regex wildcard
+        assert!(!regex.is_match("nginx-service"));
+
+        let regex = globs_to_regex(&strings(&["postgresql@1[4-6]*"])).unwrap().unwrap();
+        assert!(regex.is_match("postgresql@1[4-6]-main.service"));
+        assert!(!regex.is_match("postgresql@14-main.service"));
+
+        assert!(globs_to_regex(&[]).unwrap().is_none());
+    }
+
+    #[test]
+    fn filter() {
+        let filter = UnitFilter::new(
+            &strings(&["*.service", "*.scope"]),
+            &strings(&["systemd-*"]),
+            &strings(&["user-*.slice"]),
+        )
+        .unwrap();
+        assert!(filter.accepts(&unit("nginx.service")));
+        assert!(filter.accepts(&unit("session-2.scope")));
+        assert!(!filter.accepts(&unit("systemd-journald.service")));
+        assert!(filter.accepts(&unit("user-1000.slice")));
+        assert!(!filter.accepts(&unit("machine.slice")));
+
+        // slices are not monitored by default
+        let filter = UnitFilter::new(&strings(&["*"]), &[], &[]).unwrap();
+        assert!(filter.accepts(&unit("nginx.service")));
+        assert!(!filter.accepts(&unit("user-1000.slice")));
+    }
+}
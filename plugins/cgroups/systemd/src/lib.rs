@@ -0,0 +1,177 @@
+use std::time::Duration;
+
+use alumet::{
+    pipeline::elements::source::trigger::TriggerSpec,
+    plugin::rust::{AlumetPlugin, deserialize_config, serialize_config},
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{filter::UnitFilter, tagger::UnitTagger};
+use source::SourceSetup;
+use util_cgroups_plugins::{
+    cgroup_events::{CgroupReactor, ReactorCallbacks, ReactorConfig},
+    job_annotation_transform::{
+        CachedCgroupHierarchy, JobAnnotationTransform, OptionalSharedHierarchy, SharedCgroupHierarchy,
+    },
+    metrics::Metrics,
+};
+
+mod filter;
+mod source;
+mod tagger;
+mod unit;
+
+pub struct SystemdPlugin {
+    config: Config,
+    starting_state: Option<StartingState>,
+    reactor: Option<CgroupReactor>,
+}
+
+impl AlumetPlugin for SystemdPlugin {
+    fn name() -> &'static str {
+        "systemd"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn init(config: alumet::plugin::ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(Self {
+            config,
+            starting_state: None,
+            reactor: None,
+        }))
+    }
+
+    fn default_config() -> anyhow::Result<Option<alumet::plugin::ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn start(&mut self, alumet: &mut alumet::plugin::AlumetPluginStart) -> anyhow::Result<()> {
+        let metrics = Metrics::create(alumet)?.with_memory_stat_keys(self.config.memory_stat_keys.clone());
+        let reactor_config = ReactorConfig::default();
+        let mut shared_hierarchy = OptionalSharedHierarchy::default();
+
+        let filter = UnitFilter::new(&self.config.units, &self.config.exclude_units, &self.config.slices)
+            .context("invalid unit filter in the config")?;
+        let tagger = UnitTagger::default();
+
+        // If enabled, create the annotation transform.
+        if self.config.annotate_foreign_measurements {
+            let shared = SharedCgroupHierarchy::default();
+            shared_hierarchy.enable(shared.clone());
+
+            let transform = JobAnnotationTransform {
+                tagger: tagger.clone(),
+                cgroup_v2_hierarchy: CachedCgroupHierarchy::new(shared),
+            };
+            alumet.add_transform("systemd-annotation", Box::new(transform))?;
+        }
+
+        // store the state for later, because we cannot set up everything now
+        let starting_state = StartingState {
+            metrics,
+            reactor_config,
+            filter,
+            tagger,
+            shared_hierarchy,
+        };
+        self.starting_state = Some(starting_state);
+        Ok(())
+    }
+
+    fn post_pipeline_start(&mut self, alumet: &mut alumet::plugin::AlumetPostStart) -> anyhow::Result<()> {
+        // continue from the state that has been prepared in `start`
+        let s = self.starting_state.take().unwrap();
+
+        let trigger = TriggerSpec::at_interval(self.config.poll_interval);
+        let probe_setup = SourceSetup {
+            trigger,
+            filter: s.filter,
+            tagger: s.tagger.clone(),
+        };
+
+        let reactor = CgroupReactor::new(
+            s.reactor_config,
+            s.metrics,
+            ReactorCallbacks {
+                probe_setup,
+                on_removal: s.tagger,
+                on_fs_mount: s.shared_hierarchy,
+            },
+            alumet.pipeline_control(),
+        )
+        .context("failed to init CgroupReactor")?;
+
+        self.reactor = Some(reactor);
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        if let Some(reactor) = self.reactor.take() {
+            drop(reactor);
+        }
+        Ok(())
+    }
+}
+
+struct StartingState {
+    metrics: Metrics,
+    reactor_config: ReactorConfig,
+    filter: UnitFilter,
+    tagger: UnitTagger,
+    shared_hierarchy: OptionalSharedHierarchy,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Config {
+    #[serde(with = "humantime_serde")]
+    pub poll_interval: Duration,
+
+    /// Glob patterns of the services and scopes to monitor, like `nginx.service` or `*.service`.
+    /// `*` matches any sequence of characters and `?` matches exactly one character.
+    pub units: Vec<String>,
+
+    /// Glob patterns of the services and scopes to ignore, even if they match `units`.
+    #[serde(default)]
+    pub exclude_units: Vec<String>,
+
+    /// Glob patterns of the slices to monitor as a whole, like `user-*.slice`.
+    /// The default value is empty.
+    ///
+    /// The measurements of a slice include all the units of the slice, because the kernel
+    /// aggregates the statistics of the child cgroups.
+    #[serde(default)]
+    pub slices: Vec<String>,
+
+    /// If `true`, adds attributes like `unit` to the measurements produced by other plugins.
+    /// The default value is `false`.
+    ///
+    /// The measurements must have the `cgroup` resource consumer, and **cgroup v2** must be used on the node.
+    #[serde(default)]
+    pub annotate_foreign_measurements: bool,
+
+    /// Additional keys of `memory.stat` to measure, like `file_mapped` or `pgmajfault`.
+    /// The default value is empty.
+    ///
+    /// With **cgroup v1**, use the keys of cgroup v1, like `total_mapped_file` or `total_pgmajfault`.
+    #[serde(default)]
+    pub memory_stat_keys: Vec<String>,
+}
+
+impl Default for Config {
+    #[cfg_attr(tarpaulin, ignore)]
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(5),
+            units: vec![String::from("*.service")],
+            exclude_units: Vec::new(),
+            slices: Vec::new(),
+            annotate_foreign_measurements: false,
+            memory_stat_keys: Vec::new(),
+        }
+    }
+}
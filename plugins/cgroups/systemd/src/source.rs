@@ -0,0 +1,45 @@
+use alumet::pipeline::elements::source::trigger::TriggerSpec;
+
+use util_cgroups::Cgroup;
+use util_cgroups_plugins::{
+    cgroup_events::{CgroupSetupCallback, ProbeSetup, SourceSettings},
+    job_annotation_transform::JobTagger,
+    metrics::{AugmentedMetrics, Metrics},
+};
+
+use crate::{filter::UnitFilter, tagger::UnitTagger, unit::SystemdUnit};
+
+#[derive(Clone)]
+pub struct SourceSetup {
+    pub trigger: TriggerSpec,
+    pub filter: UnitFilter,
+    pub tagger: UnitTagger,
+}
+
+impl CgroupSetupCallback for SourceSetup {
+    fn setup_new_probe(&mut self, cgroup: &Cgroup, metrics: &Metrics) -> Option<ProbeSetup> {
+        // If this is not a unit, or if the unit has not been selected in the config, don't monitor the cgroup
+        let unit = SystemdUnit::from_cgroup_path(cgroup.canonical_path())?;
+        if !self.filter.accepts(&unit) {
+            log::debug!("ignoring unit {} (filtered out)", unit.name);
+            return None;
+        }
+
+        // Retrieves associated attributes
+        let attrs = self.tagger.attributes_for_cgroup(cgroup);
+        let metrics = AugmentedMetrics::with_common_attr_vec(metrics, attrs);
+
+        // setup the trigger according to the plugin's config
+        let trigger = self.trigger.clone();
+
+        // use the cgroup name as the source name: the unit name is not unique, because of the user managers
+        let name = cgroup.unique_name().to_string();
+
+        // ready!
+        let source_settings = SourceSettings { name, trigger };
+        Some(ProbeSetup {
+            metrics,
+            source_settings,
+        })
+    }
+}
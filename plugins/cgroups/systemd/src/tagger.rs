@@ -0,0 +1,109 @@
+use std::sync::{Arc, Mutex};
+
+use alumet::measurement::AttributeValue;
+use rustc_hash::FxHashMap;
+use util_cgroups::Cgroup;
+use util_cgroups_plugins::{cgroup_events::CgroupRemovalCallback, job_annotation_transform::JobTagger};
+
+use crate::unit::{SystemdUnit, read_invocation_id};
+
+/// Attributes of a cgroup.
+type Attributes = Vec<(String, AttributeValue)>;
+
+/// Finds the systemd unit of the cgroups and keeps their attributes in cache.
+///
+/// The tagger can be cloned, the clones share the same cache.
+#[derive(Clone, Default)]
+pub struct UnitTagger {
+    /// Cache of attributes, by canonical cgroup path.
+    ///
+    /// The invocation id changes when the unit is restarted, but the cgroup is deleted and created again,
+    /// which clears the cache.
+    units: Arc<Mutex<FxHashMap<String, Attributes>>>,
+}
+
+impl UnitTagger {
+    fn attributes(cgroup: &Cgroup, unit: SystemdUnit) -> Vec<(String, AttributeValue)> {
+        let mut attrs = vec![
+            ("unit".into(), AttributeValue::String(unit.name)),
+            ("unit_type".into(), AttributeValue::Str(unit.kind.as_str())),
+            ("slice".into(), AttributeValue::String(unit.slice)),
+        ];
+        match read_invocation_id(cgroup.fs_path()) {
+            Ok(Some(id)) => attrs.push(("invocation_id".into(), AttributeValue::String(id))),
+            Ok(None) => (),
+            Err(e) => log::warn!("failed to read the invocation id of {:?}: {e}", cgroup.fs_path()),
+        }
+        attrs
+    }
+}
+
+impl JobTagger for UnitTagger {
+    fn attributes_for_cgroup(&mut self, cgroup: &Cgroup) -> Vec<(String, AttributeValue)> {
+        let path = cgroup.canonical_path();
+        if let Some(attrs) = self.units.lock().unwrap().get(path) {
+            return attrs.clone();
+        }
+        let Some(unit) = SystemdUnit::from_cgroup_path(path) else {
+            return Vec::new();
+        };
+        let attrs = Self::attributes(cgroup, unit);
+        self.units.lock().unwrap().insert(path.to_owned(), attrs.clone());
+        attrs
+    }
+}
+
+impl CgroupRemovalCallback for UnitTagger {
+    fn on_cgroups_removed(&mut self, cgroups: Vec<Cgroup>) -> anyhow::Result<()> {
+        let mut units = self.units.lock().unwrap();
+        for cgroup in cgroups {
+            units.remove(cgroup.canonical_path());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use util_cgroups::{CgroupHierarchy, CgroupVersion};
+
+    use super::*;
+
+    #[test]
+    fn attributes_and_cache() {
+        let root = tempfile::tempdir().unwrap();
+        let hierarchy = CgroupHierarchy::manually_unchecked(root.path(), CgroupVersion::V2, vec!["cpu"]);
+        let cgroup = Cgroup::from_cgroup_path(&hierarchy, String::from("/system.slice/nginx.service"));
+        std::fs::create_dir_all(cgroup.fs_path()).unwrap();
+
+        let mut tagger = UnitTagger::default();
+        let attrs = tagger.attributes_for_cgroup(&cgroup);
+        assert_eq!(
+            attrs,
+            vec![
+                (
+                    String::from("unit"),
+                    AttributeValue::String(String::from("nginx.service"))
+                ),
+                (String::from("unit_type"), AttributeValue::Str("service")),
+                (
+                    String::from("slice"),
+                    AttributeValue::String(String::from("system.slice"))
+                ),
+            ]
+        );
+
+        let mut clone = tagger.clone();
+        assert_eq!(clone.attributes_for_cgroup(&cgroup), attrs);
+        assert_eq!(tagger.units.lock().unwrap().len(), 1);
+
+        // the removal of the cgroup clears the cache, for all the clones
+        clone.on_cgroups_removed(vec![cgroup]).unwrap();
+        assert!(tagger.units.lock().unwrap().is_empty());
+
+        // not a unit
+        let cgroup = Cgroup::from_cgroup_path(&hierarchy, String::from("/system.slice/nginx.service/worker"));
+        assert!(tagger.attributes_for_cgroup(&cgroup).is_empty());
+    }
+}
@@ -0,0 +1,197 @@
+use std::{ffi::CString, io, os::unix::ffi::OsStrExt, path::Path};
+
+/// The root slice, which contains all the other units.
+pub const ROOT_SLICE: &str = "-.slice";
+
+/// Extended attributes in which systemd stores the invocation id of a unit, on the unit's cgroup.
+///
+/// The system manager uses `trusted.`, which requires `CAP_SYS_ADMIN` to read.
+/// The user managers use `user.`.
+const INVOCATION_ID_XATTRS: [&str; 2] = ["trusted.invocation_id", "user.invocation_id"];
+
+/// A systemd unit that has its own cgroup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemdUnit {
+    /// Full name of the unit, like `nginx.service`.
+    pub name: String,
+    pub kind: UnitKind,
+    /// The slice that contains the unit, like `system.slice`.
+    pub slice: String,
+}
+
+/// The types of units that have a cgroup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnitKind {
+    Service,
+    Scope,
+    Slice,
+}
+
+impl UnitKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UnitKind::Service => "service",
+            UnitKind::Scope => "scope",
+            UnitKind::Slice => "slice",
+        }
+    }
+}
+
+impl SystemdUnit {
+    /// Finds the unit that corresponds to a cgroup, from the canonical path of the cgroup.
+    ///
+    /// # Expected format
+    ///
+    /// The last component of the path must be the name of a unit, for instance:
+    /// - `/system.slice/nginx.service`
+    /// - `/user.slice/user-1000.slice/user@1000.service/app.slice/app-firefox.scope`
+    ///
+    /// Returns `None` for the cgroups that are not units, like the sub-cgroups that a service creates for itself
+    /// (`/system.slice/docker.service/payload`) and the root cgroup.
+    pub fn from_cgroup_path(canonical_path: &str) -> Option<SystemdUnit> {
+        let mut components = canonical_path.rsplit('/').filter(|c| !c.is_empty());
+        let name = components.next()?;
+        let (_, suffix) = name.rsplit_once('.')?;
+        let kind = match suffix {
+            "service" => UnitKind::Service,
+            "scope" => UnitKind::Scope,
+            "slice" => UnitKind::Slice,
+            _ => return None,
+        };
+        // The slice is the nearest ancestor that is a slice.
+        // Most of the time, this is the parent, except for the units of the user managers (`user@1000.service`).
+        let slice = components
+            .find(|c| c.ends_with(".slice"))
+            .unwrap_or(ROOT_SLICE)
+            .to_owned();
+        Some(SystemdUnit {
+            name: name.to_owned(),
+            kind,
+            slice,
+        })
+    }
+}
+
+/// Reads the invocation id of the unit that owns a cgroup, from the extended attributes of the cgroup.
+///
+/// Each time a unit is started, it gets a new invocation id.
+/// Returns `Ok(None)` if the cgroup has no invocation id, or if we are not allowed to read it.
+pub fn read_invocation_id(cgroup_fs_path: &Path) -> io::Result<Option<String>> {
+    let path = CString::new(cgroup_fs_path.as_os_str().as_bytes())?;
+    for xattr in INVOCATION_ID_XATTRS {
+        let name = CString::new(xattr).unwrap();
+        // The id is 16 bytes long, encoded in hexadecimal by systemd, but older versions stored the raw bytes.
+        let mut buf = [0u8; 64];
+        // SAFETY: the path and the name are valid C strings, and the size of the buffer is correct.
+        let res = unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), buf.as_mut_ptr().cast(), buf.len()) };
+        if res < 0 {
+            let err = io::Error::last_os_error();
+            match err.raw_os_error() {
+                // no such attribute, or not allowed to read it: try the next one
+                Some(libc::ENODATA) | Some(libc::EPERM) | Some(libc::EACCES) => continue,
+                // extended attributes not supported
+                Some(libc::ENOTSUP) => return Ok(None),
+                _ => return Err(err),
+            }
+        }
+        return Ok(parse_invocation_id(&buf[..res as usize]));
+    }
+    Ok(None)
+}
+
+/// Parses the value of the invocation id attribute, and returns it in hexadecimal.
+fn parse_invocation_id(value: &[u8]) -> Option<String> {
+    match value.len() {
+        16 => Some(value.iter().map(|b| format!("{b:02x}")).collect()),
+        32 if value.iter().all(u8::is_ascii_hexdigit) => Some(String::from_utf8_lossy(value).to_ascii_lowercase()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn unit(name: &str, kind: UnitKind, slice: &str) -> Option<SystemdUnit> {
+        Some(SystemdUnit {
+            name: name.to_owned(),
+            kind,
+            slice: slice.to_owned(),
+        })
+    }
+
+    #[test]
+    fn units_from_path() {
+        assert_eq!(
+            SystemdUnit::from_cgroup_path("/system.slice/nginx.service"),
+            unit("nginx.service", UnitKind::Service, "system.slice")
+        );
+        assert_eq!(
+            SystemdUnit::from_cgroup_path("/system.slice/system-getty.slice/getty@tty1.service"),
+            unit("getty@tty1.service", UnitKind::Service, "system-getty.slice")
+        );
+        assert_eq!(
+            SystemdUnit::from_cgroup_path("/user.slice/user-1000.slice/session-2.scope"),
+            unit("session-2.scope", UnitKind::Scope, "user-1000.slice")
+        );
+        assert_eq!(
+            SystemdUnit::from_cgroup_path("/user.slice/user-1000.slice/user@1000.service"),
+            unit("user@1000.service", UnitKind::Service, "user-1000.slice")
+        );
+        assert_eq!(
+            SystemdUnit::from_cgroup_path("/user.slice/user-1000.slice/user@1000.service/app.slice/app-firefox.scope"),
+            unit("app-firefox.scope", UnitKind::Scope, "app.slice")
+        );
+        // the units of the user managers can be outside of a slice
+        assert_eq!(
+            SystemdUnit::from_cgroup_path("/user.slice/user-1000.slice/user@1000.service/init.scope"),
+            unit("init.scope", UnitKind::Scope, "user-1000.slice")
+        );
+        assert_eq!(
+            SystemdUnit::from_cgroup_path("/system.slice"),
+            unit("system.slice", UnitKind::Slice, ROOT_SLICE)
+        );
+        assert_eq!(
+            SystemdUnit::from_cgroup_path("/init.scope"),
+            unit("init.scope", UnitKind::Scope, ROOT_SLICE)
+        );
+    }
+
+    #[test]
+    fn not_units() {
+        assert_eq!(SystemdUnit::from_cgroup_path("/"), None);
+        assert_eq!(SystemdUnit::from_cgroup_path(""), None);
+        assert_eq!(
+            SystemdUnit::from_cgroup_path("/system.slice/docker.service/payload"),
+            None
+        );
+        assert_eq!(SystemdUnit::from_cgroup_path("/oar/user_1234"), None);
+        assert_eq!(SystemdUnit::from_cgroup_path("/system.slice/app.mount"), None);
+    }
+
+    #[test]
+    fn invocation_id() {
+        assert_eq!(
+            parse_invocation_id(b"8A1b2c3d4e5f60718293a4b5c6d7e8f9"),
+            Some(String::from("8a1b2c3d4e5f60718293a4b5c6d7e8f9"))
+        );
+        assert_eq!(
+            parse_invocation_id(&[
+                0x8a, 0x1b, 0x2c, 0x3d, 0x4e, 0x5f, 0x60, 0x71, 0x82, 0x93, 0xa4, 0xb5, 0xc6, 0xd7, 0xe8, 0xf9
+            ]),
+            Some(String::from("8a1b2c3d4e5f60718293a4b5c6d7e8f9"))
+        );
+        assert_eq!(parse_invocation_id(b"not an id"), None);
+        assert_eq!(parse_invocation_id(b""), None);
+    }
+
+    #[test]
+    fn no_invocation_id() {
+        let dir = tempfile::tempdir().unwrap();
+        // a regular directory has no invocation id (and tmpfs may not support extended attributes at all)
+        assert_eq!(read_invocation_id(dir.path()).unwrap(), None);
+        read_invocation_id(&dir.path().join("missing")).expect_err("the directory does not exist");
+    }
+}
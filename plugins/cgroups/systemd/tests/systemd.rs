@@ -0,0 +1,100 @@
+use std::{path::Path, time::Duration};
+
+use alumet::{
+    agent::{
+        self,
+        plugin::{PluginInfo, PluginSet},
+    },
+    pipeline::{
+        control::request::{self, ElementListFilter},
+        naming::ElementKind,
+    },
+    plugin::PluginMetadata,
+};
+use anyhow::Context;
+use plugin_systemd::{Config, SystemdPlugin};
+use util_cgroups::{CgroupHierarchy, CgroupVersion, hierarchy::find_user_app_slice};
+
+const SYSFS_CGROUP: &str = "/sys/fs/cgroup";
+const TIMEOUT: Duration = Duration::from_secs(1);
+const TOLERANCE: Duration = Duration::from_millis(500);
+
+#[test]
+fn test_systemd_cgroupv2() -> anyhow::Result<()> {
+    if std::env::var_os("SKIP_CGROUPFS_TESTS").is_some() {
+        println!("skipped because SKIP_CGROUPFS_TESTS is set");
+        return Ok(());
+    }
+
+    let _ = env_logger::Builder::from_default_env().try_init();
+
+    let app_slice = find_user_app_slice(Path::new(SYSFS_CGROUP))?;
+
+    let mut plugins = PluginSet::new();
+    plugins.add_plugin(PluginInfo {
+        metadata: PluginMetadata::from_static::<SystemdPlugin>(),
+        enabled: true,
+        config: Some(config_to_toml_table(&Config {
+            poll_interval: Duration::from_secs(1),
+            units: vec![String::from("alumet-test-*.scope")],
+            ..Default::default()
+        })),
+    });
+
+    // start the measurement pipeline, without our test units
+    let agent = agent::Builder::new(plugins).build_and_start()?;
+
+    // create the cgroups: one that matches the filter, one that does not
+    let selected_dir = tempfile::Builder::new()
+        .prefix("alumet-test-")
+        .suffix(".scope")
+        .tempdir_in(&app_slice)
+        .with_context(|| format!("failed to create cgroup in {app_slice:?}"))?;
+    let ignored_dir = tempfile::Builder::new()
+        .prefix("alumet-ignored-")
+        .suffix(".scope")
+        .tempdir_in(&app_slice)
+        .with_context(|| format!("failed to create cgroup in {app_slice:?}"))?;
+    let cgroup_hierarchy = CgroupHierarchy::manually_unchecked(SYSFS_CGROUP, CgroupVersion::V2, vec!["cpu"]);
+    let selected_source = cgroup_hierarchy.cgroup_path_from_fs(selected_dir.path()).unwrap();
+    let ignored_source = cgroup_hierarchy.cgroup_path_from_fs(ignored_dir.path()).unwrap();
+    log::info!(
+        "cgroups created at {:?} and {:?}",
+        selected_dir.path(),
+        ignored_dir.path()
+    );
+
+    // expect the source to be created quickly after that
+    std::thread::sleep(TOLERANCE);
+    let handle = agent.pipeline.control_handle();
+    let rt = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let list_sources = |name: &str| {
+        rt.block_on(
+            handle.send_wait(
+                request::list_elements(
+                    ElementListFilter::kind(ElementKind::Source)
+                        .plugin("systemd")
+                        .name(name),
+                ),
+                TIMEOUT,
+            ),
+        )
+    };
+    assert!(
+        !list_sources(&selected_source)?.is_empty(),
+        "source not found: {selected_source}"
+    );
+    assert!(
+        list_sources(&ignored_source)?.is_empty(),
+        "the unit should have been filtered out: {ignored_source}"
+    );
+
+    // stop the pipeline and wait for it to terminate
+    handle.shutdown();
+    agent.wait_for_shutdown(TIMEOUT)?;
+    Ok(())
+}
+
+fn config_to_toml_table(config: &Config) -> toml::Table {
+    toml::Value::try_from(config).unwrap().as_table().unwrap().to_owned()
+}
@@ -0,0 +1,631 @@
+//! Aggregation of the cgroup measurements per subtree of the cgroup hierarchy.
+//!
+//! The value measured on a cgroup includes the resources consumed by all its descendants:
+//! adding the measurements of a cgroup and of its children counts the children twice.
+//! The aggregation groups the cgroups by the value of some attributes (for instance, the cgroups of a Slurm job
+//! by `job_id`) and computes, at regular intervals:
+//! - the **inclusive** value of the subtrees of the group that are not measured directly,
+//!   by adding the values of their topmost measured cgroups,
+//! - the **exclusive** value of the measured cgroups, that is, their value minus the value of their topmost
+//!   measured descendants.
+//!
+//! The aggregation is made of three parts:
+//! - an [`AggregationTransform`], which accumulates the measurements of the cgroups and marks them as `inclusive`,
+//! - a [`CgroupAggregation`] handle, which forgets the cgroups when they are removed,
+//! - an [`AggregationSource`], which emits the aggregated measurements.
+//!
+//! Only the additive metrics are aggregated: the CPU time, the I/O, the memory and the number of processes.
+
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use alumet::{
+    measurement::{
+        AttributeValue, MeasurementAccumulator, MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue,
+    },
+    metrics::{RawMetricId, def::MetricId},
+    pipeline::{
+        Source, Transform,
+        elements::{
+            error::{PollError, TransformError},
+            source::trigger::TriggerSpec,
+            transform::TransformContext,
+        },
+    },
+    plugin::AlumetPluginStart,
+    resources::{Resource, ResourceConsumer},
+};
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use util_cgroups::{
+    Cgroup,
+    hierarchy::{ancestor_at_depth, common_ancestor, is_in_subtree, path_depth},
+};
+
+use crate::{cgroup_events::CgroupRemovalCallback, metrics::Metrics};
+
+/// Attribute that tells whether a measurement includes the resources consumed by the descendants of its cgroup.
+pub const ACCOUNTING_ATTRIBUTE: &str = "accounting";
+
+/// Attributes that distinguish the series of a metric on the same cgroup.
+const SERIES_ATTRIBUTES: [&str; 4] = ["kind", "direction", "key", "event"];
+
+/// Configuration of the hierarchical aggregation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AggregationConfig {
+    /// Attributes that define the groups of cgroups to aggregate together, such as `job_id`.
+    /// The measurements that lack one of these attributes are not aggregated.
+    pub group_by: Vec<String>,
+
+    /// Number of levels, below the top of each group, that get their own aggregated measurements.
+    /// With `0`, only the top of the group (the deepest common ancestor of its cgroups) is aggregated.
+    #[serde(default)]
+    pub depth: usize,
+
+    /// Interval between two aggregated measurements.
+    #[serde(with = "humantime_serde", default = "default_interval")]
+    pub interval: Duration,
+}
+
+#[cfg_attr(tarpaulin, ignore)]
+fn default_interval() -> Duration {
+    Duration::from_secs(5)
+}
+
+/// Creates the transform and the source of the aggregation, and returns the handle that must be notified
+/// of the removal of the cgroups.
+///
+/// The transform only sees the measurements of the plugins that are enabled **before** the current plugin.
+pub fn setup_aggregation(
+    alumet: &mut AlumetPluginStart,
+    config: AggregationConfig,
+    metrics: &Metrics,
+) -> anyhow::Result<CgroupAggregation> {
+    if config.group_by.is_empty() {
+        anyhow::bail!("invalid aggregation config: group_by must not be empty");
+    }
+    let aggregation = CgroupAggregation::default();
+
+    let m = metrics;
+    let deltas = [
+        m.cpu_time_delta,
+        m.disk_bytes,
+        m.disk_operations,
+        m.memory_events,
+        m.memory_stat_events,
+    ];
+    let gauges = [
+        m.memory_usage,
+        m.memory_anonymous,
+        m.memory_file,
+        m.memory_kernel_stack,
+        m.memory_pagetables,
+        m.memory_stat,
+        m.pids,
+    ];
+    let mut metric_kinds = FxHashMap::default();
+    metric_kinds.extend(deltas.iter().map(|id| (id.untyped_id(), MetricKind::Delta)));
+    metric_kinds.extend(gauges.iter().map(|id| (id.untyped_id(), MetricKind::Gauge)));
+
+    let transform = AggregationTransform {
+        aggregation: aggregation.clone(),
+        group_by: config.group_by.clone(),
+        metrics: metric_kinds,
+    };
+    alumet.add_transform("cgroup-aggregation", Box::new(transform))?;
+
+    let source = AggregationSource {
+        aggregation: aggregation.clone(),
+        group_by: config.group_by,
+        depth: config.depth,
+    };
+    alumet.add_source(
+        "cgroup-aggregation",
+        Box::new(source),
+        TriggerSpec::at_interval(config.interval),
+    )?;
+    Ok(aggregation)
+}
+
+/// Returns `true` if the measurement excludes the resources consumed by the descendants of its cgroup.
+pub fn is_exclusive(m: &MeasurementPoint) -> bool {
+    m.attributes().any(|(k, v)| {
+        k == ACCOUNTING_ATTRIBUTE
+            && match v {
+                AttributeValue::Str(s) => *s == "exclusive",
+                AttributeValue::String(s) => s == "exclusive",
+                _ => false,
+            }
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MetricKind {
+    /// Increase since the previous measurement: the values are added over the interval.
+    Delta,
+    /// Instantaneous value: only the last one is kept.
+    Gauge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Accounting {
+    Inclusive,
+    Exclusive,
+}
+
+impl Accounting {
+    fn as_str(self) -> &'static str {
+        match self {
+            Accounting::Inclusive => "inclusive",
+            Accounting::Exclusive => "exclusive",
+        }
+    }
+}
+
+/// Identifies a series of measurements of a cgroup.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SeriesKey {
+    metric: RawMetricId,
+    resource: Resource,
+    attributes: Vec<(String, AttributeValue)>,
+}
+
+/// Values of a measured cgroup during the current interval.
+#[derive(Default)]
+struct MemberValues {
+    /// Sum of the deltas since the beginning of the interval.
+    deltas: FxHashMap<SeriesKey, u64>,
+    /// Last value of the gauges.
+    gauges: FxHashMap<SeriesKey, u64>,
+    /// The cgroup has been removed: its deltas still count for the current interval, then it is forgotten.
+    removed: bool,
+}
+
+impl MemberValues {
+    fn get(&self, series: &SeriesKey) -> Option<u64> {
+        self.deltas.get(series).or_else(|| self.gauges.get(series)).copied()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&SeriesKey, u64)> {
+        self.deltas.iter().chain(self.gauges.iter()).map(|(k, v)| (k, *v))
+    }
+}
+
+#[derive(Default)]
+struct AggregationState {
+    /// Measured cgroups, by values of the `group_by` attributes, then by cgroup path.
+    groups: FxHashMap<Vec<AttributeValue>, FxHashMap<String, MemberValues>>,
+}
+
+impl AggregationState {
+    fn remove(&mut self, cgroup_path: &str) {
+        for members in self.groups.values_mut() {
+            if let Some(member) = members.get_mut(cgroup_path) {
+                // The gauges of a removed cgroup are obsolete, unlike the deltas that it has accumulated.
+                member.gauges.clear();
+                member.removed = true;
+            }
+        }
+    }
+
+    /// Starts a new interval: resets the deltas and forgets the removed cgroups.
+    fn end_interval(&mut self) {
+        for members in self.groups.values_mut() {
+            members.retain(|_, m| !m.removed);
+            for member in members.values_mut() {
+                member.deltas.clear();
+            }
+        }
+        self.groups.retain(|_, members| !members.is_empty());
+    }
+}
+
+/// Keeps track of the measured cgroups of each group.
+///
+/// The handle can be cloned, the clones share the same state.
+#[derive(Clone, Default)]
+pub struct CgroupAggregation {
+    state: Arc<Mutex<AggregationState>>,
+}
+
+impl CgroupRemovalCallback for CgroupAggregation {
+    fn on_cgroups_removed(&mut self, cgroups: Vec<Cgroup>) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        for cgroup in cgroups {
+            state.remove(cgroup.canonical_path());
+        }
+        Ok(())
+    }
+}
+
+/// Accumulates the measurements of the cgroups and marks them as `inclusive`.
+pub struct AggregationTransform {
+    aggregation: CgroupAggregation,
+    group_by: Vec<String>,
+    metrics: FxHashMap<RawMetricId, MetricKind>,
+}
+
+impl AggregationTransform {
+    fn accumulate(&self, measurements: &mut MeasurementBuffer) {
+        let mut state = self.aggregation.state.lock().unwrap();
+        for m in measurements.iter_mut() {
+            let Some(kind) = self.metrics.get(&m.metric) else {
+                continue;
+            };
+            let ResourceConsumer::ControlGroup { path } = &m.consumer else {
+                continue;
+            };
+            // Skip the measurements that have already been aggregated.
+            if m.attributes().any(|(k, _)| k == ACCOUNTING_ATTRIBUTE) {
+                continue;
+            }
+            let Some(group) = self.group_of(m) else {
+                continue;
+            };
+            let series = SeriesKey {
+                metric: m.metric,
+                resource: m.resource.clone(),
+                attributes: series_attributes(m),
+            };
+            let member = state
+                .groups
+                .entry(group)
+                .or_default()
+                .entry(path.to_string())
+                .or_default();
+            let value = m.value.as_u64();
+            match kind {
+                MetricKind::Delta => *member.deltas.entry(series).or_default() += value,
+                MetricKind::Gauge if !member.removed => {
+                    member.gauges.insert(series, value);
+                }
+                MetricKind::Gauge => (),
+            }
+            m.add_attr(ACCOUNTING_ATTRIBUTE, Accounting::Inclusive.as_str());
+        }
+    }
+
+    /// Returns the values of the `group_by` attributes, or `None` if one of them is missing.
+    fn group_of(&self, m: &MeasurementPoint) -> Option<Vec<AttributeValue>> {
+        self.group_by
+            .iter()
+            .map(|name| {
+                m.attributes()
+                    .find(|(k, _)| *k == name.as_str())
+                    .map(|(_, v)| v.clone())
+            })
+            .collect()
+    }
+}
+
+impl Transform for AggregationTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, _ctx: &TransformContext) -> Result<(), TransformError> {
+        self.accumulate(measurements);
+        Ok(())
+    }
+
+    fn finish(&mut self, _ctx: &TransformContext) -> Result<(), TransformError> {
+        Ok(())
+    }
+}
+
+fn series_attributes(m: &MeasurementPoint) -> Vec<(String, AttributeValue)> {
+    m.attributes()
+        .filter(|(k, _)| SERIES_ATTRIBUTES.contains(k))
+        .map(|(k, v)| (k.to_owned(), v.clone()))
+        .collect()
+}
+
+/// Emits the aggregated measurements of each group, at regular intervals.
+pub struct AggregationSource {
+    aggregation: CgroupAggregation,
+    group_by: Vec<String>,
+    depth: usize,
+}
+
+impl Source for AggregationSource {
+    fn poll(&mut self, measurements: &mut MeasurementAccumulator, t: Timestamp) -> Result<(), PollError> {
+        let mut state = self.aggregation.state.lock().unwrap();
+        for (group, members) in &state.groups {
+            let group_attrs: Vec<(String, AttributeValue)> =
+                self.group_by.iter().cloned().zip(group.iter().cloned()).collect();
+            for aggregated in aggregate_group(members, self.depth) {
+                let consumer = ResourceConsumer::ControlGroup {
+                    path: aggregated.cgroup.into(),
+                };
+                let point = MeasurementPoint::new_untyped(
+                    t,
+                    aggregated.series.metric,
+                    aggregated.series.resource,
+                    consumer,
+                    WrappedMeasurementValue::U64(aggregated.value),
+                )
+                .with_attr_slice(&aggregated.series.attributes)
+                .with_attr_slice(&group_attrs)
+                .with_attr(ACCOUNTING_ATTRIBUTE, aggregated.accounting.as_str());
+                measurements.push(point);
+            }
+        }
+        state.end_interval();
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct AggregatedValue {
+    cgroup: String,
+    series: SeriesKey,
+    accounting: Accounting,
+    value: u64,
+}
+
+/// Computes the aggregated values of a group of cgroups.
+///
+/// The subtrees that are aggregated are rooted at the top of the group (the deepest common ancestor of its cgroups)
+/// and at its descendants, up to `depth` levels below it, that contain measured cgroups.
+/// A measured subtree gets its exclusive values (its inclusive values are the raw measurements),
+/// the other ones get their inclusive values.
+fn aggregate_group(members: &FxHashMap<String, MemberValues>, depth: usize) -> Vec<AggregatedValue> {
+    let Some(top) = members.keys().map(String::as_str).reduce(common_ancestor) else {
+        return Vec::new();
+    };
+    let top_depth = path_depth(top);
+    let mut subtrees = BTreeSet::from([top]);
+    for path in members.keys() {
+        let max_depth = (top_depth + depth).min(path_depth(path));
+        for d in (top_depth + 1)..=max_depth {
+            subtrees.insert(ancestor_at_depth(path, d));
+        }
+    }
+
+    let mut res = Vec::new();
+    for subtree in subtrees {
+        let below = topmost_below(members, subtree);
+        match members.get(subtree) {
+            Some(values) => {
+                for (series, value) in values.iter() {
+                    let below_sum: u64 = below.iter().filter_map(|p| members[*p].get(series)).sum();
+                    res.push(AggregatedValue {
+                        cgroup: subtree.to_owned(),
+                        series: series.clone(),
+                        accounting: Accounting::Exclusive,
+                        // The descendants are not measured at the exact same time as their parent.
+                        value: value.saturating_sub(below_sum),
+                    });
+                }
+            }
+            None => {
+                let mut sums: FxHashMap<&SeriesKey, u64> = FxHashMap::default();
+                for path in below {
+                    for (series, value) in members[path].iter() {
+                        *sums.entry(series).or_default() += value;
+                    }
+                }
+                res.extend(sums.into_iter().map(|(series, value)| AggregatedValue {
+                    cgroup: subtree.to_owned(),
+                    series: series.clone(),
+                    accounting: Accounting::Inclusive,
+                    value,
+                }));
+            }
+        }
+    }
+    res
+}
+
+/// Returns the measured cgroups that are strictly below `subtree`, and that have no measured ancestor below `subtree`.
+fn topmost_below<'a>(members: &'a FxHashMap<String, MemberValues>, subtree: &str) -> Vec<&'a str> {
+    let below: Vec<&str> = members
+        .keys()
+        .map(String::as_str)
+        .filter(|p| *p != subtree && is_in_subtree(subtree, p))
+        .collect();
+    below
+        .iter()
+        .copied()
+        .filter(|p| !below.iter().any(|q| q != p && is_in_subtree(q, p)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    const CPU: u64 = 0;
+    const MEMORY: u64 = 1;
+    const OTHER: u64 = 2;
+
+    fn transform(group_by: &[&str]) -> AggregationTransform {
+        let mut metrics = FxHashMap::default();
+        metrics.insert(RawMetricId::from_u64(CPU), MetricKind::Delta);
+        metrics.insert(RawMetricId::from_u64(MEMORY), MetricKind::Gauge);
+        AggregationTransform {
+            aggregation: CgroupAggregation::default(),
+            group_by: group_by.iter().map(|s| s.to_string()).collect(),
+            metrics,
+        }
+    }
+
+    fn point(metric: u64, path: &str, job: u64, value: u64) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::from_unix_timestamp(1000, 0),
+            RawMetricId::from_u64(metric),
+            Resource::LocalMachine,
+            ResourceConsumer::ControlGroup {
+                path: path.to_owned().into(),
+            },
+            WrappedMeasurementValue::U64(value),
+        )
+        .with_attr("job_id", job)
+    }
+
+    fn series(metric: u64) -> SeriesKey {
+        SeriesKey {
+            metric: RawMetricId::from_u64(metric),
+            resource: Resource::LocalMachine,
+            attributes: Vec::new(),
+        }
+    }
+
+    /// Returns the aggregated value of the given subtree, metric and accounting.
+    fn find(values: &[AggregatedValue], cgroup: &str, metric: u64, accounting: Accounting) -> Option<u64> {
+        let metric = RawMetricId::from_u64(metric);
+        values
+            .iter()
+            .find(|v| v.cgroup == cgroup && v.series.metric == metric && v.accounting == accounting)
+            .map(|v| v.value)
+    }
+
+    fn aggregate(transform: &AggregationTransform, depth: usize) -> Vec<AggregatedValue> {
+        let state = transform.aggregation.state.lock().unwrap();
+        let members = state.groups.get(&vec![AttributeValue::U64(7)]).unwrap();
+        aggregate_group(members, depth)
+    }
+
+    #[test]
+    fn steps_of_a_job() {
+        let job = "/slurm/job_7";
+        let step0 = "/slurm/job_7/step_0";
+        let step1 = "/slurm/job_7/step_1";
+        let step1_task = "/slurm/job_7/step_1/task_0";
+        let transform = transform(&["job_id"]);
+
+        let mut buf = MeasurementBuffer::new();
+        buf.push(point(CPU, step0, 7, 100));
+        buf.push(point(CPU, step0, 7, 50));
+        buf.push(point(CPU, step1, 7, 40));
+        buf.push(point(CPU, step1_task, 7, 30));
+        buf.push(point(MEMORY, step0, 7, 1000));
+        buf.push(point(MEMORY, step0, 7, 2000));
+        buf.push(point(MEMORY, step1, 7, 500));
+        buf.push(point(MEMORY, step1_task, 7, 400));
+        // not aggregated
+        buf.push(point(OTHER, step0, 7, 1));
+        transform.accumulate(&mut buf);
+
+        // the measurements of the groups are marked as inclusive
+        let n_inclusive = buf
+            .iter()
+            .filter(|m| m.attributes().any(|(k, _)| k == ACCOUNTING_ATTRIBUTE))
+            .count();
+        assert_eq!(n_inclusive, 8);
+
+        // depth 0: only the job, which is not measured
+        let values = aggregate(&transform, 0);
+        assert_eq!(find(&values, job, CPU, Accounting::Inclusive), Some(190));
+        assert_eq!(find(&values, job, MEMORY, Accounting::Inclusive), Some(2500));
+        assert_eq!(values.len(), 2);
+
+        // depth 1: the steps, which are measured
+        let values = aggregate(&transform, 1);
+        assert_eq!(find(&values, job, CPU, Accounting::Inclusive), Some(190));
+        assert_eq!(find(&values, step0, CPU, Accounting::Exclusive), Some(150));
+        assert_eq!(find(&values, step1, CPU, Accounting::Exclusive), Some(10));
+        assert_eq!(find(&values, step1, MEMORY, Accounting::Exclusive), Some(100));
+        assert_eq!(find(&values, step1_task, CPU, Accounting::Exclusive), None);
+        assert_eq!(find(&values, step0, CPU, Accounting::Inclusive), None);
+    }
+
+    #[test]
+    fn measured_top() {
+        let job = "/oar.slice/job_7";
+        let child = "/oar.slice/job_7/child";
+        let transform = transform(&["job_id"]);
+
+        let mut buf = MeasurementBuffer::new();
+        buf.push(point(CPU, job, 7, 100));
+        buf.push(point(CPU, child, 7, 120));
+        transform.accumulate(&mut buf);
+
+        // the child is measured a bit later than its parent: saturate at zero
+        let values = aggregate(&transform, 0);
+        assert_eq!(values.len(), 1);
+        assert_eq!(find(&values, job, CPU, Accounting::Exclusive), Some(0));
+
+        // the aggregated measurements are not accumulated again
+        let mut buf = MeasurementBuffer::new();
+        buf.push(point(CPU, job, 7, 1).with_attr(ACCOUNTING_ATTRIBUTE, "exclusive"));
+        transform.accumulate(&mut buf);
+        let values = aggregate(&transform, 0);
+        assert_eq!(find(&values, job, CPU, Accounting::Exclusive), Some(0));
+        assert!(is_exclusive(buf.iter().next().unwrap()));
+    }
+
+    #[test]
+    fn removed_during_interval() {
+        let job = "/slurm/job_7";
+        let step0 = "/slurm/job_7/step_0";
+        let step1 = "/slurm/job_7/step_1";
+        let transform = transform(&["job_id"]);
+        let aggregation = transform.aggregation.clone();
+
+        let mut buf = MeasurementBuffer::new();
+        buf.push(point(CPU, step0, 7, 100));
+        buf.push(point(MEMORY, step0, 7, 1000));
+        buf.push(point(CPU, step1, 7, 10));
+        buf.push(point(MEMORY, step1, 7, 20));
+        transform.accumulate(&mut buf);
+        aggregation.state.lock().unwrap().remove(step0);
+
+        // late measurement of the removed cgroup: the delta counts, not the gauge
+        let mut buf = MeasurementBuffer::new();
+        buf.push(point(CPU, step0, 7, 5));
+        buf.push(point(MEMORY, step0, 7, 3000));
+        transform.accumulate(&mut buf);
+
+        let values = aggregate(&transform, 0);
+        assert_eq!(find(&values, job, CPU, Accounting::Inclusive), Some(115));
+        assert_eq!(find(&values, job, MEMORY, Accounting::Inclusive), Some(20));
+
+        // next interval: the removed cgroup is forgotten, the gauges are kept
+        aggregation.state.lock().unwrap().end_interval();
+        let values = aggregate(&transform, 1);
+        assert_eq!(find(&values, job, CPU, Accounting::Inclusive), None);
+        assert_eq!(find(&values, step1, MEMORY, Accounting::Exclusive), Some(20));
+        assert_eq!(find(&values, step0, MEMORY, Accounting::Exclusive), None);
+
+        // a new cgroup appears
+        let mut buf = MeasurementBuffer::new();
+        buf.push(point(CPU, step0, 7, 8));
+        transform.accumulate(&mut buf);
+        let values = aggregate(&transform, 0);
+        assert_eq!(find(&values, job, CPU, Accounting::Inclusive), Some(8));
+
+        // everything is removed: the group disappears
+        let mut state = aggregation.state.lock().unwrap();
+        state.remove(step0);
+        state.remove(step1);
+        state.end_interval();
+        assert!(state.groups.is_empty());
+    }
+
+    #[test]
+    fn series_are_separated() {
+        let transform = transform(&["job_id"]);
+        let mut buf = MeasurementBuffer::new();
+        buf.push(point(CPU, "/j/a", 7, 100).with_attr("kind", "total"));
+        buf.push(point(CPU, "/j/a", 7, 60).with_attr("kind", "user"));
+        buf.push(
+            point(CPU, "/j/b", 7, 10)
+                .with_attr("kind", "total")
+                .with_attr("step", 1_u64),
+        );
+        // another group
+        buf.push(point(CPU, "/j/c", 8, 1000).with_attr("kind", "total"));
+        transform.accumulate(&mut buf);
+
+        let values = aggregate(&transform, 0);
+        let mut total = series(CPU);
+        total
+            .attributes
+            .push((String::from("kind"), AttributeValue::Str("total")));
+        let total = values.iter().find(|v| v.series == total).unwrap();
+        assert_eq!((total.cgroup.as_str(), total.value), ("/j", 110));
+        assert_eq!(values.len(), 2);
+    }
+}
@@ -50,6 +50,25 @@ impl CgroupFsMountCallback for NoCallback {
     }
 }
 
+/// An optional callback, which does nothing if `None`.
+impl<R: CgroupRemovalCallback> CgroupRemovalCallback for Option<R> {
+    fn on_cgroups_removed(&mut self, cgroups: Vec<Cgroup>) -> anyhow::Result<()> {
+        match self {
+            Some(callback) => callback.on_cgroups_removed(cgroups),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Two callbacks, called one after the other (even if the first one fails).
+impl<A: CgroupRemovalCallback, B: CgroupRemovalCallback> CgroupRemovalCallback for (A, B) {
+    fn on_cgroups_removed(&mut self, cgroups: Vec<Cgroup>) -> anyhow::Result<()> {
+        let first = self.0.on_cgroups_removed(cgroups.clone());
+        let second = self.1.on_cgroups_removed(cgroups);
+        first.and(second)
+    }
+}
+
 /// Settings to apply to the cgroup probe.
 pub struct ProbeSetup {
     pub metrics: AugmentedMetrics,
@@ -81,7 +100,8 @@ pub struct ReactorConfig {
 
     /// Interval between two scans of the cgroup v1 hierarchies.
     ///
-    /// The cgroup v1 filesystem does not support notifications (inotify), we must rely on manually inspecting the cgroups at regular intervals.
+    /// The cgroup v1 hierarchies are watched with inotify, like cgroup v2. The scans at regular intervals
+    /// are only used as a fallback, when inotify cannot be used on a hierarchy.
     /// If `None` (the default), uses the default value of [`detect::Config`].
     pub v1_refresh_interval: Option<Duration>,
 
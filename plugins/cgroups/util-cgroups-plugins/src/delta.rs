@@ -5,6 +5,9 @@ pub struct CpuDeltaCounters {
     pub usage: CounterDiff,
     pub user: CounterDiff,
     pub system: CounterDiff,
+    pub nr_periods: CounterDiff,
+    pub nr_throttled: CounterDiff,
+    pub throttled: CounterDiff,
 }
 
 impl CpuDeltaCounters {
@@ -12,6 +15,9 @@ impl CpuDeltaCounters {
         self.usage.reset();
         self.user.reset();
         self.system.reset();
+        self.nr_periods.reset();
+        self.nr_throttled.reset();
+        self.throttled.reset();
     }
 }
 
@@ -21,6 +27,82 @@ impl Default for CpuDeltaCounters {
             usage: CounterDiff::with_max_value(u64::MAX),
             user: CounterDiff::with_max_value(u64::MAX),
             system: CounterDiff::with_max_value(u64::MAX),
+            nr_periods: CounterDiff::with_max_value(u64::MAX),
+            nr_throttled: CounterDiff::with_max_value(u64::MAX),
+            throttled: CounterDiff::with_max_value(u64::MAX),
+        }
+    }
+}
+
+/// CounterDiff to compute the delta of the total stall times of a PSI file.
+pub struct PressureDeltaCounters {
+    pub some: CounterDiff,
+    pub full: CounterDiff,
+}
+
+impl PressureDeltaCounters {
+    pub fn reset(&mut self) {
+        self.some.reset();
+        self.full.reset();
+    }
+}
+
+impl Default for PressureDeltaCounters {
+    fn default() -> Self {
+        Self {
+            some: CounterDiff::with_max_value(u64::MAX),
+            full: CounterDiff::with_max_value(u64::MAX),
+        }
+    }
+}
+
+/// CounterDiff to compute the delta of the counters of `memory.events`.
+pub struct MemoryEventsDeltaCounters {
+    pub high: CounterDiff,
+    pub max: CounterDiff,
+    pub oom: CounterDiff,
+    pub oom_kill: CounterDiff,
+}
+
+impl MemoryEventsDeltaCounters {
+    pub fn reset(&mut self) {
+        self.high.reset();
+        self.max.reset();
+        self.oom.reset();
+        self.oom_kill.reset();
+    }
+}
+
+impl Default for MemoryEventsDeltaCounters {
+    fn default() -> Self {
+        Self {
+            high: CounterDiff::with_max_value(u64::MAX),
+            max: CounterDiff::with_max_value(u64::MAX),
+            oom: CounterDiff::with_max_value(u64::MAX),
+            oom_kill: CounterDiff::with_max_value(u64::MAX),
+        }
+    }
+}
+
+/// CounterDiff to compute the delta of the I/O statistics of a block device.
+pub struct IoDeltaCounters {
+    pub rbytes: CounterDiff,
+    pub wbytes: CounterDiff,
+    pub dbytes: CounterDiff,
+    pub rios: CounterDiff,
+    pub wios: CounterDiff,
+    pub dios: CounterDiff,
+}
+
+impl Default for IoDeltaCounters {
+    fn default() -> Self {
+        Self {
+            rbytes: CounterDiff::with_max_value(u64::MAX),
+            wbytes: CounterDiff::with_max_value(u64::MAX),
+            dbytes: CounterDiff::with_max_value(u64::MAX),
+            rios: CounterDiff::with_max_value(u64::MAX),
+            wios: CounterDiff::with_max_value(u64::MAX),
+            dios: CounterDiff::with_max_value(u64::MAX),
         }
     }
 }
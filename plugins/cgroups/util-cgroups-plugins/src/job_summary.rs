@@ -0,0 +1,537 @@
+//! Summary of the resources consumed by each job, emitted when the job ends.
+//!
+//! The summary is made of three parts:
+//! - a [`JobSummaryTransform`], which accumulates the energy, CPU time and peak memory of the jobs,
+//! - a [`JobSummaries`] handle, which registers the jobs and finalizes their summary when their cgroup is removed,
+//! - a [`JobSummarySource`], which emits the summaries as measurement points.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use alumet::{
+    measurement::{
+        AttributeValue, MeasurementAccumulator, MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue,
+    },
+    metrics::{RawMetricId, TypedMetricId, def::MetricId},
+    pipeline::{
+        Source, Transform,
+        elements::{
+            error::{PollError, TransformError},
+            source::trigger::TriggerSpec,
+            transform::TransformContext,
+        },
+    },
+    plugin::AlumetPluginStart,
+    resources::{Resource, ResourceConsumer},
+    units::{PrefixedUnit, Unit},
+};
+use anyhow::Context;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use util_cgroups::Cgroup;
+
+use crate::{aggregation::is_exclusive, cgroup_events::CgroupRemovalCallback, metrics::Metrics};
+
+/// Configuration of the job summaries.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobSummaryConfig {
+    /// Names of the metrics that measure the energy attributed to the jobs, in Joules.
+    /// They are usually produced by the `energy-attribution` plugin.
+    #[serde(default = "default_energy_metrics")]
+    pub energy_metrics: Vec<String>,
+
+    /// If set, a JSON record is written in this directory at the end of each job.
+    #[serde(default)]
+    pub spool_dir: Option<PathBuf>,
+
+    /// Interval between two checks for finished jobs.
+    #[serde(with = "humantime_serde", default = "default_flush_interval")]
+    pub flush_interval: Duration,
+}
+
+#[cfg_attr(tarpaulin, ignore)]
+fn default_energy_metrics() -> Vec<String> {
+    vec![String::from("attributed_energy")]
+}
+
+#[cfg_attr(tarpaulin, ignore)]
+fn default_flush_interval() -> Duration {
+    Duration::from_secs(1)
+}
+
+impl Default for JobSummaryConfig {
+    #[cfg_attr(tarpaulin, ignore)]
+    fn default() -> Self {
+        Self {
+            energy_metrics: default_energy_metrics(),
+            spool_dir: None,
+            flush_interval: default_flush_interval(),
+        }
+    }
+}
+
+/// Metrics of the job summaries.
+#[derive(Clone)]
+pub struct JobSummaryMetrics {
+    pub total_energy: TypedMetricId<f64>,
+    pub duration: TypedMetricId<f64>,
+    pub average_power: TypedMetricId<f64>,
+    pub total_cpu_time: TypedMetricId<u64>,
+    pub peak_memory: TypedMetricId<u64>,
+}
+
+impl JobSummaryMetrics {
+    pub fn create(alumet: &mut AlumetPluginStart) -> anyhow::Result<Self> {
+        Ok(Self {
+            total_energy: alumet.create_metric(
+                "job_total_energy",
+                Unit::Joule,
+                "Energy attributed to the job during its whole execution",
+            )?,
+            duration: alumet.create_metric("job_duration", Unit::Second, "Duration of the job")?,
+            average_power: alumet.create_metric(
+                "job_average_power",
+                Unit::Watt,
+                "Average power of the job: job_total_energy / job_duration",
+            )?,
+            total_cpu_time: alumet.create_metric(
+                "job_total_cpu_time",
+                PrefixedUnit::nano(Unit::Second),
+                "Time spent by the job on the CPU during its whole execution",
+            )?,
+            peak_memory: alumet.create_metric("job_peak_memory", Unit::Byte, "Maximum memory usage of the job")?,
+        })
+    }
+}
+
+/// Creates the transform and the source of the job summaries, and returns the handle that registers the jobs.
+///
+/// The transform only sees the measurements of the plugins that are enabled **before** the current plugin.
+pub fn setup_job_summaries(
+    alumet: &mut AlumetPluginStart,
+    config: JobSummaryConfig,
+    metrics: &Metrics,
+) -> anyhow::Result<JobSummaries> {
+    let summary_metrics = JobSummaryMetrics::create(alumet)?;
+    let summaries = JobSummaries::new(config.spool_dir);
+
+    let cpu_time_delta = metrics.cpu_time_delta.untyped_id();
+    let memory_usage = metrics.memory_usage.untyped_id();
+    let energy_metrics = config.energy_metrics;
+    let transform_summaries = summaries.clone();
+    alumet.add_transform_builder("job-summary", move |ctx| {
+        let mut energy_ids = Vec::with_capacity(energy_metrics.len());
+        for name in &energy_metrics {
+            match ctx.metric_by_name(name) {
+                Some((id, _)) => energy_ids.push(id),
+                None => log::warn!("energy metric not found: {name}, it will not be included in the job summaries"),
+            }
+        }
+        let transform = JobSummaryTransform {
+            summaries: transform_summaries,
+            energy_metrics: energy_ids,
+            cpu_time_delta,
+            memory_usage,
+        };
+        Ok(Box::new(transform))
+    })?;
+
+    let source = JobSummarySource {
+        summaries: summaries.clone(),
+        metrics: summary_metrics,
+    };
+    alumet.add_source(
+        "job-summary",
+        Box::new(source),
+        TriggerSpec::at_interval(config.flush_interval),
+    )?;
+    Ok(summaries)
+}
+
+/// Resources consumed by a running job.
+#[derive(Clone)]
+struct JobUsage {
+    attributes: Vec<(String, AttributeValue)>,
+    start: Timestamp,
+    /// `None` if no energy measurement has been received.
+    energy: Option<f64>,
+    cpu_time: u64,
+    peak_memory: u64,
+}
+
+/// Resources consumed by a job during its whole execution.
+#[derive(Clone)]
+pub struct JobSummary {
+    /// Path of the job's cgroup.
+    pub cgroup: String,
+    pub attributes: Vec<(String, AttributeValue)>,
+    pub start: Timestamp,
+    pub end: Timestamp,
+    /// Total energy in Joules, `None` if no energy measurement has been received.
+    pub energy: Option<f64>,
+    /// Total CPU time in nanoseconds.
+    pub cpu_time: u64,
+    /// Peak memory in bytes.
+    pub peak_memory: u64,
+}
+
+impl JobSummary {
+    pub fn duration(&self) -> Duration {
+        self.end.duration_since(self.start).unwrap_or_default()
+    }
+
+    /// Average power in Watts, if the energy and the duration are known.
+    pub fn average_power(&self) -> Option<f64> {
+        let duration = self.duration().as_secs_f64();
+        match self.energy {
+            Some(energy) if duration > 0.0 => Some(energy / duration),
+            _ => None,
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let unix = |t: Timestamp| {
+            let (secs, nanos) = t.to_unix_timestamp();
+            secs as f64 + nanos as f64 / 1e9
+        };
+        let attributes: serde_json::Map<String, serde_json::Value> = self
+            .attributes
+            .iter()
+            .map(|(k, v)| (k.clone(), serde_json::Value::from(v)))
+            .collect();
+        serde_json::json!({
+            "cgroup": self.cgroup,
+            "attributes": attributes,
+            "start": unix(self.start),
+            "end": unix(self.end),
+            "duration_s": self.duration().as_secs_f64(),
+            "energy_j": self.energy,
+            "average_power_w": self.average_power(),
+            "cpu_time_ns": self.cpu_time,
+            "peak_memory_bytes": self.peak_memory,
+        })
+    }
+
+    /// Writes the summary in a JSON file of the spool directory, and returns the path of the file.
+    ///
+    /// The file is written under a temporary name, then renamed, so that a reader never sees an incomplete record.
+    fn write_to_spool(&self, spool_dir: &Path) -> anyhow::Result<PathBuf> {
+        let name = self.cgroup.trim_matches('/').replace('/', "_");
+        let path = spool_dir.join(format!("{name}.json"));
+        let tmp_path = spool_dir.join(format!(".{name}.json.tmp"));
+        let content = serde_json::to_vec(&self.to_json())?;
+        std::fs::write(&tmp_path, content).with_context(|| format!("failed to write {tmp_path:?}"))?;
+        std::fs::rename(&tmp_path, &path).with_context(|| format!("failed to rename {tmp_path:?} to {path:?}"))?;
+        Ok(path)
+    }
+}
+
+#[derive(Default)]
+struct SummaryState {
+    /// Running jobs, by cgroup path.
+    running: FxHashMap<String, JobUsage>,
+    /// Finished jobs, waiting to be emitted by the source.
+    finished: Vec<JobSummary>,
+}
+
+/// Keeps track of the resources consumed by the jobs.
+///
+/// The handle can be cloned, the clones share the same state.
+#[derive(Clone)]
+pub struct JobSummaries {
+    state: Arc<Mutex<SummaryState>>,
+    spool_dir: Option<PathBuf>,
+}
+
+impl JobSummaries {
+    pub fn new(spool_dir: Option<PathBuf>) -> Self {
+        Self {
+            state: Default::default(),
+            spool_dir,
+        }
+    }
+
+    /// Starts to accumulate the resources consumed by a job, identified by its cgroup.
+    ///
+    /// The attributes are copied to the summary.
+    pub fn register(&self, cgroup: &Cgroup, attributes: Vec<(String, AttributeValue)>) {
+        self.register_at(cgroup.canonical_path().to_owned(), attributes, Timestamp::now());
+    }
+
+    fn register_at(&self, cgroup_path: String, attributes: Vec<(String, AttributeValue)>, start: Timestamp) {
+        let usage = JobUsage {
+            attributes,
+            start,
+            energy: None,
+            cpu_time: 0,
+            peak_memory: 0,
+        };
+        self.state.lock().unwrap().running.entry(cgroup_path).or_insert(usage);
+    }
+
+    /// Finalizes the summary of a job, if it has been registered.
+    fn finish_at(&self, cgroup_path: &str, end: Timestamp) {
+        let Some(usage) = self.state.lock().unwrap().running.remove(cgroup_path) else {
+            return;
+        };
+        let summary = JobSummary {
+            cgroup: cgroup_path.to_owned(),
+            attributes: usage.attributes,
+            start: usage.start,
+            end,
+            energy: usage.energy,
+            cpu_time: usage.cpu_time,
+            peak_memory: usage.peak_memory,
+        };
+        if let Some(spool_dir) = &self.spool_dir {
+            match summary.write_to_spool(spool_dir) {
+                Ok(path) => log::debug!("job summary written to {path:?}"),
+                Err(e) => log::error!("failed to write the summary of {cgroup_path}: {e:#}"),
+            }
+        }
+        self.state.lock().unwrap().finished.push(summary);
+    }
+
+    fn take_finished(&self) -> Vec<JobSummary> {
+        std::mem::take(&mut self.state.lock().unwrap().finished)
+    }
+}
+
+impl CgroupRemovalCallback for JobSummaries {
+    fn on_cgroups_removed(&mut self, cgroups: Vec<Cgroup>) -> anyhow::Result<()> {
+        let end = Timestamp::now();
+        for cgroup in cgroups {
+            self.finish_at(cgroup.canonical_path(), end);
+        }
+        Ok(())
+    }
+}
+
+/// Accumulates the energy, CPU time and memory usage of the registered jobs.
+pub struct JobSummaryTransform {
+    summaries: JobSummaries,
+    energy_metrics: Vec<RawMetricId>,
+    cpu_time_delta: RawMetricId,
+    memory_usage: RawMetricId,
+}
+
+impl JobSummaryTransform {
+    fn accumulate(&self, measurements: &MeasurementBuffer) {
+        let mut state = self.summaries.state.lock().unwrap();
+        if state.running.is_empty() {
+            return;
+        }
+        for m in measurements.iter() {
+            let ResourceConsumer::ControlGroup { path } = &m.consumer else {
+                continue;
+            };
+            let Some(usage) = state.running.get_mut(path.as_ref()) else {
+                continue;
+            };
+            // The summary includes the descendants: the exclusive values of the aggregation are already part of it.
+            if is_exclusive(m) {
+                continue;
+            }
+            if self.energy_metrics.contains(&m.metric) {
+                *usage.energy.get_or_insert(0.0) += m.value.as_f64();
+            } else if m.metric == self.cpu_time_delta && is_total_cpu_time(m) {
+                usage.cpu_time += m.value.as_u64();
+            } else if m.metric == self.memory_usage {
+                usage.peak_memory = usage.peak_memory.max(m.value.as_u64());
+            }
+        }
+    }
+}
+
+impl Transform for JobSummaryTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, _ctx: &TransformContext) -> Result<(), TransformError> {
+        self.accumulate(measurements);
+        Ok(())
+    }
+
+    fn finish(&mut self, _ctx: &TransformContext) -> Result<(), TransformError> {
+        Ok(())
+    }
+}
+
+/// `cpu_time_delta` is split by `kind`: only keep the total, to avoid counting the time twice.
+fn is_total_cpu_time(m: &MeasurementPoint) -> bool {
+    m.attributes().any(|(k, v)| {
+        k == "kind"
+            && match v {
+                AttributeValue::Str(s) => *s == "total",
+                AttributeValue::String(s) => s == "total",
+                _ => false,
+            }
+    })
+}
+
+/// Emits the summaries of the jobs that have finished.
+pub struct JobSummarySource {
+    summaries: JobSummaries,
+    metrics: JobSummaryMetrics,
+}
+
+impl Source for JobSummarySource {
+    fn poll(&mut self, measurements: &mut MeasurementAccumulator, _t: Timestamp) -> Result<(), PollError> {
+        for summary in self.summaries.take_finished() {
+            push_summary(measurements, &self.metrics, summary);
+        }
+        Ok(())
+    }
+}
+
+fn push_summary(measurements: &mut MeasurementAccumulator, metrics: &JobSummaryMetrics, summary: JobSummary) {
+    let consumer = ResourceConsumer::ControlGroup {
+        path: summary.cgroup.clone().into(),
+    };
+    let t = summary.end;
+    let point = |metric, value: WrappedMeasurementValue| {
+        MeasurementPoint::new_untyped(t, metric, Resource::LocalMachine, consumer.clone(), value)
+            .with_attr_slice(&summary.attributes)
+    };
+
+    let m = metrics;
+    measurements.push(point(
+        m.duration.untyped_id(),
+        WrappedMeasurementValue::F64(summary.duration().as_secs_f64()),
+    ));
+    measurements.push(point(
+        m.total_cpu_time.untyped_id(),
+        WrappedMeasurementValue::U64(summary.cpu_time),
+    ));
+    measurements.push(point(
+        m.peak_memory.untyped_id(),
+        WrappedMeasurementValue::U64(summary.peak_memory),
+    ));
+    if let Some(energy) = summary.energy {
+        measurements.push(point(m.total_energy.untyped_id(), WrappedMeasurementValue::F64(energy)));
+    }
+    if let Some(power) = summary.average_power() {
+        measurements.push(point(m.average_power.untyped_id(), WrappedMeasurementValue::F64(power)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn summary_values() {
+        let start = Timestamp::from_unix_timestamp(1000, 0);
+        let summary = JobSummary {
+            cgroup: String::from("/system.slice/slurmstepd.scope/job_12"),
+            attributes: vec![(String::from("job_id"), AttributeValue::U64(12))],
+            start,
+            end: start + Duration::from_secs(10),
+            energy: Some(500.0),
+            cpu_time: 8_000_000_000,
+            peak_memory: 1 << 30,
+        };
+        assert_eq!(summary.duration(), Duration::from_secs(10));
+        assert_eq!(summary.average_power(), Some(50.0));
+
+        let json = summary.to_json();
+        assert_eq!(json["attributes"]["job_id"], 12);
+        assert_eq!(json["energy_j"], 500.0);
+        assert_eq!(json["average_power_w"], 50.0);
+        assert_eq!(json["start"], 1000.0);
+
+        let no_energy = JobSummary {
+            energy: None,
+            ..summary
+        };
+        assert_eq!(no_energy.average_power(), None);
+        assert!(no_energy.to_json()["energy_j"].is_null());
+    }
+
+    #[test]
+    fn spool() {
+        let dir = tempfile::tempdir().unwrap();
+        let summaries = JobSummaries::new(Some(dir.path().to_owned()));
+        let start = Timestamp::from_unix_timestamp(1000, 0);
+        summaries.register_at(
+            String::from("/system.slice/slurmstepd.scope/job_12"),
+            vec![(String::from("job_id"), AttributeValue::U64(12))],
+            start,
+        );
+
+        // not registered: ignored
+        summaries.finish_at("/system.slice/other.service", start);
+        assert!(summaries.take_finished().is_empty());
+
+        summaries.finish_at("/system.slice/slurmstepd.scope/job_12", start + Duration::from_secs(2));
+        let finished = summaries.take_finished();
+        assert_eq!(finished.len(), 1);
+        assert_eq!(finished[0].duration(), Duration::from_secs(2));
+        assert!(summaries.take_finished().is_empty());
+
+        let files: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        assert_eq!(files, vec![String::from("system.slice_slurmstepd.scope_job_12.json")]);
+        let content = std::fs::read_to_string(dir.path().join(&files[0])).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(json["cgroup"], "/system.slice/slurmstepd.scope/job_12");
+        assert_eq!(json["duration_s"], 2.0);
+    }
+
+    #[test]
+    fn accumulation() {
+        let energy = RawMetricId::from_u64(0);
+        let cpu_time_delta = RawMetricId::from_u64(1);
+        let memory_usage = RawMetricId::from_u64(2);
+        let other = RawMetricId::from_u64(3);
+
+        let summaries = JobSummaries::new(None);
+        let job = "/oar.slice/oar-u1000.slice/oar-u1000-j7.slice";
+        summaries.register_at(job.to_owned(), Vec::new(), Timestamp::now());
+        let transform = JobSummaryTransform {
+            summaries: summaries.clone(),
+            energy_metrics: vec![energy],
+            cpu_time_delta,
+            memory_usage,
+        };
+
+        let t = Timestamp::now();
+        let point = |metric, path: &str, value| {
+            MeasurementPoint::new_untyped(
+                t,
+                metric,
+                Resource::LocalMachine,
+                ResourceConsumer::ControlGroup {
+                    path: path.to_owned().into(),
+                },
+                value,
+            )
+        };
+        let mut buf = MeasurementBuffer::new();
+        buf.push(point(energy, job, WrappedMeasurementValue::F64(10.0)));
+        buf.push(point(energy, job, WrappedMeasurementValue::F64(5.5)));
+        buf.push(point(cpu_time_delta, job, WrappedMeasurementValue::U64(100)).with_attr("kind", "total"));
+        buf.push(point(cpu_time_delta, job, WrappedMeasurementValue::U64(70)).with_attr("kind", "user"));
+        buf.push(point(memory_usage, job, WrappedMeasurementValue::U64(2048)));
+        buf.push(point(memory_usage, job, WrappedMeasurementValue::U64(1024)));
+        buf.push(point(other, job, WrappedMeasurementValue::U64(999)));
+        buf.push(
+            point(cpu_time_delta, job, WrappedMeasurementValue::U64(30))
+                .with_attr("kind", "total")
+                .with_attr("accounting", "exclusive"),
+        );
+        // not a job
+        buf.push(point(energy, "/system.slice", WrappedMeasurementValue::F64(1000.0)));
+        transform.accumulate(&buf);
+
+        summaries.finish_at(job, Timestamp::now());
+        let summary = summaries.take_finished().pop().unwrap();
+        assert_eq!(summary.energy, Some(15.5));
+        assert_eq!(summary.cpu_time, 100);
+        assert_eq!(summary.peak_memory, 2048);
+    }
+}
@@ -5,9 +5,11 @@ pub mod v1;
 /// Probe for cgroups v2.
 pub mod v2;
 
+pub mod aggregation;
 mod cpus;
 pub mod delta;
 pub mod job_annotation_transform;
+pub mod job_summary;
 pub mod metrics;
 pub mod regex;
 mod self_stop;
@@ -22,6 +22,31 @@ pub struct Metrics {
     pub memory_kernel_stack: TypedMetricId<u64>,
     /// Memory used to manage correspondence between virtual and physical addresses.
     pub memory_pagetables: TypedMetricId<u64>,
+    /// Share of time in which the tasks of the cgroup were stalled on a resource (PSI).
+    pub pressure_stall_avg: TypedMetricId<f64>,
+    /// Time in which the tasks of the cgroup were stalled on a resource since last measurement (PSI).
+    pub pressure_stall_time_delta: TypedMetricId<u64>,
+    /// Bytes read, written or discarded by the cgroup on a block device since last measurement.
+    pub disk_bytes: TypedMetricId<u64>,
+    /// I/O operations done by the cgroup on a block device since last measurement.
+    pub disk_operations: TypedMetricId<u64>,
+    /// Number of enforcement periods of the CPU bandwidth limit since last measurement.
+    pub cpu_periods: TypedMetricId<u64>,
+    /// Number of periods in which the cgroup was throttled since last measurement.
+    pub cpu_throttled_periods: TypedMetricId<u64>,
+    /// Time during which the cgroup was throttled since last measurement.
+    pub cpu_throttled_time: TypedMetricId<u64>,
+    /// Number of processes in the cgroup.
+    pub pids: TypedMetricId<u64>,
+    /// Number of memory events (reaching `memory.high`, `memory.max`, OOM) since last measurement.
+    pub memory_events: TypedMetricId<u64>,
+    /// Value of a key of `memory.stat` that is an amount of memory.
+    pub memory_stat: TypedMetricId<u64>,
+    /// Increase of a key of `memory.stat` that is an event counter, since last measurement.
+    pub memory_stat_events: TypedMetricId<u64>,
+
+    /// Additional keys of `memory.stat` to measure.
+    pub memory_stat_keys: Vec<String>,
 }
 
 /// Used by probes to configure how cgroup measurements will be mapped to Alumet measurement points.
@@ -61,6 +86,31 @@ pub struct AugmentedMetrics {
     pub memory_kernel_stack: AugmentedMetric<u64>,
     /// Memory used to manage correspondence between virtual and physical addresses.
     pub memory_pagetables: AugmentedMetric<u64>,
+    /// Share of time in which the tasks of the cgroup were stalled on a resource (PSI).
+    pub pressure_stall_avg: AugmentedMetric<f64>,
+    /// Time in which the tasks of the cgroup were stalled on a resource since last measurement (PSI).
+    pub pressure_stall_time_delta: AugmentedMetric<u64>,
+    /// Bytes read, written or discarded by the cgroup on a block device since last measurement.
+    pub disk_bytes: AugmentedMetric<u64>,
+    /// I/O operations done by the cgroup on a block device since last measurement.
+    pub disk_operations: AugmentedMetric<u64>,
+    /// Number of enforcement periods of the CPU bandwidth limit since last measurement.
+    pub cpu_periods: AugmentedMetric<u64>,
+    /// Number of periods in which the cgroup was throttled since last measurement.
+    pub cpu_throttled_periods: AugmentedMetric<u64>,
+    /// Time during which the cgroup was throttled since last measurement.
+    pub cpu_throttled_time: AugmentedMetric<u64>,
+    /// Number of processes in the cgroup.
+    pub pids: AugmentedMetric<u64>,
+    /// Number of memory events (reaching `memory.high`, `memory.max`, OOM) since last measurement.
+    pub memory_events: AugmentedMetric<u64>,
+    /// Value of a key of `memory.stat` that is an amount of memory.
+    pub memory_stat: AugmentedMetric<u64>,
+    /// Increase of a key of `memory.stat` that is an event counter, since last measurement.
+    pub memory_stat_events: AugmentedMetric<u64>,
+
+    /// Additional keys of `memory.stat` to measure.
+    pub memory_stat_keys: Vec<String>,
 
     /// Common attributes, added to the points of all metrics.
     pub common_attrs: Vec<(String, AttributeValue)>,
@@ -104,6 +154,61 @@ impl Metrics {
             Unit::Byte,
             "Amount of memory allocated for page tables (which map virtual addresses to physical addresses).",
         )?;
+        let pressure_stall_avg = alumet.create_metric::<f64>(
+            "pressure_stall_avg",
+            Unit::Percent,
+            "Share of time in which some (or all) tasks were stalled on a resource, averaged over a time window",
+        )?;
+        let pressure_stall_time_delta = alumet.create_metric::<u64>(
+            "pressure_stall_time_delta",
+            PrefixedUnit::micro(Unit::Second),
+            "Time in which some (or all) tasks were stalled on a resource since the previous measurement",
+        )?;
+        let disk_bytes = alumet.create_metric::<u64>(
+            "disk_bytes",
+            Unit::Byte,
+            "Number of bytes read or written on a block device since the previous measurement",
+        )?;
+        let disk_operations = alumet.create_metric::<u64>(
+            "disk_operations",
+            Unit::Unity,
+            "Number of read or write operations completed on a block device since the previous measurement",
+        )?;
+        let cpu_periods = alumet.create_metric::<u64>(
+            "cgroup_cpu_periods",
+            Unit::Unity,
+            "Number of enforcement periods of the CPU bandwidth limit (cpu.max) that elapsed since the previous measurement",
+        )?;
+        let cpu_throttled_periods = alumet.create_metric::<u64>(
+            "cgroup_cpu_throttled_periods",
+            Unit::Unity,
+            "Number of periods in which the cgroup was throttled because it used its whole CPU quota, since the previous measurement",
+        )?;
+        let cpu_throttled_time = alumet.create_metric::<u64>(
+            "cgroup_cpu_throttled_time",
+            PrefixedUnit::micro(Unit::Second),
+            "Time during which the cgroup was throttled because it used its whole CPU quota, since the previous measurement",
+        )?;
+        let pids = alumet.create_metric::<u64>(
+            "cgroup_pids",
+            Unit::Unity,
+            "Number of processes in the cgroup and its descendants",
+        )?;
+        let memory_events = alumet.create_metric::<u64>(
+            "cgroup_memory_events",
+            Unit::Unity,
+            "Number of times the cgroup reached a memory limit or ran out of memory, since the previous measurement",
+        )?;
+        let memory_stat = alumet.create_metric::<u64>(
+            "cgroup_memory_stat",
+            Unit::Byte,
+            "Amount of memory reported by a key of memory.stat",
+        )?;
+        let memory_stat_events = alumet.create_metric::<u64>(
+            "cgroup_memory_stat_events",
+            Unit::Unity,
+            "Number of memory events reported by a key of memory.stat (like page faults), since the previous measurement",
+        )?;
         Ok(Self {
             cpu_time_delta,
             cpu_percent,
@@ -112,8 +217,41 @@ impl Metrics {
             memory_file,
             memory_kernel_stack,
             memory_pagetables,
+            pressure_stall_avg,
+            pressure_stall_time_delta,
+            disk_bytes,
+            disk_operations,
+            cpu_periods,
+            cpu_throttled_periods,
+            cpu_throttled_time,
+            pids,
+            memory_events,
+            memory_stat,
+            memory_stat_events,
+            memory_stat_keys: Vec::new(),
         })
     }
+
+    /// Measures additional keys of `memory.stat`, like `shmem` or `pgmajfault`.
+    ///
+    /// The amounts of memory are reported with the metric `cgroup_memory_stat`,
+    /// and the event counters (like `pgmajfault`) with `cgroup_memory_stat_events`.
+    pub fn with_memory_stat_keys(mut self, keys: Vec<String>) -> Self {
+        self.memory_stat_keys = keys;
+        self
+    }
+}
+
+/// Returns `true` if the key of `memory.stat` is an event counter, `false` if it is an amount of memory.
+///
+/// The hierarchical keys of cgroup v1, like `total_pgfault`, are supported.
+///
+/// See <https://docs.kernel.org/admin-guide/cgroup-v2.html#memory-interface-files>
+/// and <https://docs.kernel.org/admin-guide/cgroup-v1/memory.html#stat-file>.
+pub fn is_memory_stat_event(key: &str) -> bool {
+    const EVENT_PREFIXES: [&str; 8] = ["pg", "pswp", "workingset_", "thp_", "zswp", "swpin", "swpout", "numa_"];
+    let key = key.strip_prefix("total_").unwrap_or(key);
+    EVENT_PREFIXES.iter().any(|prefix| key.starts_with(prefix))
 }
 
 impl AugmentedMetrics {
@@ -126,6 +264,18 @@ impl AugmentedMetrics {
             memory_file: AugmentedMetric::simple(metrics.memory_file),
             memory_kernel_stack: AugmentedMetric::simple(metrics.memory_kernel_stack),
             memory_pagetables: AugmentedMetric::simple(metrics.memory_pagetables),
+            pressure_stall_avg: AugmentedMetric::simple(metrics.pressure_stall_avg),
+            pressure_stall_time_delta: AugmentedMetric::simple(metrics.pressure_stall_time_delta),
+            disk_bytes: AugmentedMetric::simple(metrics.disk_bytes),
+            disk_operations: AugmentedMetric::simple(metrics.disk_operations),
+            cpu_periods: AugmentedMetric::simple(metrics.cpu_periods),
+            cpu_throttled_periods: AugmentedMetric::simple(metrics.cpu_throttled_periods),
+            cpu_throttled_time: AugmentedMetric::simple(metrics.cpu_throttled_time),
+            pids: AugmentedMetric::simple(metrics.pids),
+            memory_events: AugmentedMetric::simple(metrics.memory_events),
+            memory_stat: AugmentedMetric::simple(metrics.memory_stat),
+            memory_stat_events: AugmentedMetric::simple(metrics.memory_stat_events),
+            memory_stat_keys: metrics.memory_stat_keys.clone(),
             common_attrs: Vec::new(),
         }
     }
@@ -152,6 +302,18 @@ impl AugmentedMetrics {
             memory_file: AugmentedMetric::simple(metrics.memory_file),
             memory_kernel_stack: AugmentedMetric::simple(metrics.memory_kernel_stack),
             memory_pagetables: AugmentedMetric::simple(metrics.memory_pagetables),
+            pressure_stall_avg: AugmentedMetric::simple(metrics.pressure_stall_avg),
+            pressure_stall_time_delta: AugmentedMetric::simple(metrics.pressure_stall_time_delta),
+            disk_bytes: AugmentedMetric::simple(metrics.disk_bytes),
+            disk_operations: AugmentedMetric::simple(metrics.disk_operations),
+            cpu_periods: AugmentedMetric::simple(metrics.cpu_periods),
+            cpu_throttled_periods: AugmentedMetric::simple(metrics.cpu_throttled_periods),
+            cpu_throttled_time: AugmentedMetric::simple(metrics.cpu_throttled_time),
+            pids: AugmentedMetric::simple(metrics.pids),
+            memory_events: AugmentedMetric::simple(metrics.memory_events),
+            memory_stat: AugmentedMetric::simple(metrics.memory_stat),
+            memory_stat_events: AugmentedMetric::simple(metrics.memory_stat_events),
+            memory_stat_keys: metrics.memory_stat_keys.clone(),
             common_attrs,
         }
     }
@@ -161,6 +323,38 @@ impl AugmentedMetrics {
 mod tests {
     use super::*;
 
+    #[test]
+    fn memory_stat_events() {
+        for key in [
+            "pgfault",
+            "pgmajfault",
+            "pswpin",
+            "workingset_refault_anon",
+            "thp_fault_alloc",
+            "zswpout",
+            // cgroup v1
+            "pgpgin",
+            "total_pgmajfault",
+        ] {
+            assert!(is_memory_stat_event(key), "{key} should be an event");
+        }
+        for key in [
+            "anon",
+            "file",
+            "shmem",
+            "pagetables",
+            "zswapped",
+            "swapcached",
+            "slab_reclaimable",
+            // cgroup v1
+            "rss",
+            "total_cache",
+            "total_mapped_file",
+        ] {
+            assert!(!is_memory_stat_event(key), "{key} should be an amount");
+        }
+    }
+
     #[test]
     fn augmented_metrics() {
         // just test that this compiles
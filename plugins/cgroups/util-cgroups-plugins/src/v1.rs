@@ -1,21 +1,42 @@
+use std::collections::HashMap;
+
 use alumet::{
     measurement::{MeasurementAccumulator, MeasurementPoint, MeasurementType, Timestamp},
     pipeline::{Source, elements::error::PollError},
+    plugin::util::CounterDiff,
     resources::{Resource, ResourceConsumer},
 };
-use util_cgroups::{Cgroup, measure::v1::V1Collector};
+use util_cgroups::{
+    Cgroup,
+    measure::{
+        v1::{V1Collector, V1CollectorSettings},
+        v2::io::BlockDeviceNames,
+    },
+};
 
 use super::{
-    delta::CpuDeltaCounters, metrics::AugmentedMetric, metrics::AugmentedMetrics, self_stop::analyze_io_result,
+    delta::{CpuDeltaCounters, IoDeltaCounters},
+    metrics::AugmentedMetric,
+    metrics::AugmentedMetrics,
+    metrics::is_memory_stat_event,
+    self_stop::analyze_io_result,
 };
 
 pub struct CgroupV1Probe {
     consumer: ResourceConsumer,
     delta_counters: CpuDeltaCounters,
+    /// Counters of `cpuacct.usage_percpu`, by CPU id.
+    percpu_counters: Vec<CounterDiff>,
+    /// Counters of the additional keys of `memory.stat`, `None` for the keys that are not event counters.
+    memory_stat_counters: Vec<Option<CounterDiff>>,
+    /// Counters of the `blkio` throttling files, per block device (major, minor).
+    io_counters: HashMap<(u32, u32), IoDeltaCounters>,
+    device_names: BlockDeviceNames,
     metrics: AugmentedMetrics,
     collector: V1Collector,
     io_buf: Vec<u8>,
     last_timestamp: Option<Timestamp>,
+    n_cores: usize,
 }
 
 impl CgroupV1Probe {
@@ -24,15 +45,32 @@ impl CgroupV1Probe {
         let consumer = ResourceConsumer::ControlGroup {
             path: cgroup_canon_path.clone().into(),
         };
-        let io_buf = Vec::new();
-        let collector = V1Collector::in_single_hierarchy(cgroup)?;
+        let mut io_buf = Vec::new();
+        let settings = V1CollectorSettings {
+            memory_stat_keys: metrics.memory_stat_keys.clone(),
+        };
+        let collector = V1Collector::in_single_hierarchy(cgroup, &settings, &mut io_buf)?;
+        let memory_stat_counters = metrics
+            .memory_stat_keys
+            .iter()
+            .map(|key| is_memory_stat_event(key).then(|| CounterDiff::with_max_value(u64::MAX)))
+            .collect();
+
+        // Same as cgroup v2: we want the number of cores of the machine, not the ones available to Alumet.
+        let n_cores = crate::cpus::online_cpus()?.len();
+
         Ok(Self {
             consumer,
             delta_counters: Default::default(),
+            percpu_counters: Vec::new(),
+            memory_stat_counters,
+            io_counters: HashMap::new(),
+            device_names: BlockDeviceNames::new(),
             metrics,
             collector,
             io_buf,
             last_timestamp: None,
+            n_cores,
         })
     }
 
@@ -47,6 +85,33 @@ impl CgroupV1Probe {
             .with_attr_slice(&metric.attributes)
             .with_attr_slice(&self.metrics.common_attrs)
     }
+
+    /// Pushes the CPU time of the given kind, and the corresponding CPU usage if the poll interval is known.
+    fn push_cpu(
+        &self,
+        measurements: &mut MeasurementAccumulator,
+        t: Timestamp,
+        kind: &'static str,
+        value: u64,
+        poll_interval_nano: Option<u128>,
+    ) {
+        let resource = Resource::LocalMachine;
+        measurements.push(
+            self.new_point(&self.metrics.cpu_time_delta, t, &resource, value)
+                .with_attr("kind", kind),
+        );
+        if let Some(poll_interval) = poll_interval_nano {
+            measurements.push(
+                self.new_point(
+                    &self.metrics.cpu_percent,
+                    t,
+                    &resource,
+                    (value as f64 / poll_interval as f64 / self.n_cores as f64) * 100.0,
+                )
+                .with_attr("kind", kind),
+            );
+        }
+    }
 }
 
 impl Source for CgroupV1Probe {
@@ -66,20 +131,30 @@ impl Source for CgroupV1Probe {
             .map(|v| self.delta_counters.usage.update(v).difference())
             .flatten()
         {
-            measurements.push(
-                self.new_point(&self.metrics.cpu_time_delta, t, &resource, value)
-                    .with_attr("kind", "total"),
-            );
-            if let Some(poll_interval) = poll_interval_nano {
-                measurements.push(
-                    self.new_point(
-                        &self.metrics.cpu_percent,
-                        t,
-                        &resource,
-                        (value as f64 / poll_interval as f64) * 100.0,
-                    )
-                    .with_attr("kind", "total"),
-                );
+            self.push_cpu(measurements, t, "total", value, poll_interval_nano);
+        }
+        if let Some(stat) = data.cpuacct_stat {
+            if let Some(value) = self.delta_counters.user.update(stat.user).difference() {
+                self.push_cpu(measurements, t, "user", value, poll_interval_nano);
+            }
+            if let Some(value) = self.delta_counters.system.update(stat.system).difference() {
+                self.push_cpu(measurements, t, "system", value, poll_interval_nano);
+            }
+        }
+        if let Some(percpu) = data.cpuacct_usage_percpu {
+            if self.percpu_counters.len() < percpu.len() {
+                self.percpu_counters
+                    .resize_with(percpu.len(), || CounterDiff::with_max_value(u64::MAX));
+            }
+            // The per-core values have their own kind, so that they are not added to the total of the cgroup.
+            for (cpu, usage) in percpu.into_iter().enumerate() {
+                if let Some(value) = self.percpu_counters[cpu].update(usage).difference() {
+                    let core = Resource::CpuCore { id: cpu as u32 };
+                    measurements.push(
+                        self.new_point(&self.metrics.cpu_time_delta, t, &core, value)
+                            .with_attr("kind", "per_core"),
+                    );
+                }
             }
         }
 
@@ -87,11 +162,83 @@ impl Source for CgroupV1Probe {
         if let Some(mem) = data.memory_usage {
             measurements.push(self.new_point(&self.metrics.memory_usage, t, &resource, mem));
         }
+        if let Some(mem_stat) = data.memory_stat {
+            if let Some(value) = mem_stat.anon {
+                measurements.push(self.new_point(&self.metrics.memory_anonymous, t, &resource, value));
+            }
+            if let Some(value) = mem_stat.file {
+                measurements.push(self.new_point(&self.metrics.memory_file, t, &resource, value));
+            }
+            for (i, value) in mem_stat.additional.into_iter().enumerate() {
+                let Some(value) = value else {
+                    continue;
+                };
+                let key = &self.metrics.memory_stat_keys[i];
+                match &mut self.memory_stat_counters[i] {
+                    Some(counter) => {
+                        if let Some(delta) = counter.update(value).difference() {
+                            measurements.push(
+                                self.new_point(&self.metrics.memory_stat_events, t, &resource, delta)
+                                    .with_attr("key", key.clone()),
+                            );
+                        }
+                    }
+                    None => {
+                        measurements.push(
+                            self.new_point(&self.metrics.memory_stat, t, &resource, value)
+                                .with_attr("key", key.clone()),
+                        );
+                    }
+                }
+            }
+        }
+
+        // I/O statistics, per block device
+        if let Some(blkio) = data.blkio_throttle {
+            for dev in blkio {
+                let counters = self.io_counters.entry((dev.major, dev.minor)).or_default();
+                let bytes = [
+                    ("read", counters.rbytes.update(dev.rbytes).difference()),
+                    ("write", counters.wbytes.update(dev.wbytes).difference()),
+                    ("discard", counters.dbytes.update(dev.dbytes).difference()),
+                ];
+                let operations = [
+                    ("read", counters.rios.update(dev.rios).difference()),
+                    ("write", counters.wios.update(dev.wios).difference()),
+                    ("discard", counters.dios.update(dev.dios).difference()),
+                ];
+                let device = Resource::BlockDevice {
+                    name: self.device_names.get(dev.major, dev.minor).to_owned().into(),
+                };
+                for (direction, value) in bytes {
+                    if let Some(value) = value {
+                        measurements.push(
+                            self.new_point(&self.metrics.disk_bytes, t, &device, value)
+                                .with_attr("direction", direction),
+                        );
+                    }
+                }
+                for (direction, value) in operations {
+                    if let Some(value) = value {
+                        measurements.push(
+                            self.new_point(&self.metrics.disk_operations, t, &device, value)
+                                .with_attr("direction", direction),
+                        );
+                    }
+                }
+            }
+        }
         Ok(())
     }
 
     fn reset(&mut self) -> anyhow::Result<()> {
         self.delta_counters.reset();
+        self.percpu_counters.clear();
+        self.memory_stat_counters
+            .iter_mut()
+            .flatten()
+            .for_each(CounterDiff::reset);
+        self.io_counters.clear();
         self.last_timestamp = None;
         Ok(())
     }
@@ -1,20 +1,38 @@
+use std::collections::HashMap;
+
 use alumet::{
     measurement::{MeasurementAccumulator, MeasurementPoint, MeasurementType, Timestamp},
     pipeline::{Source, elements::error::PollError},
+    plugin::util::CounterDiff,
     resources::{Resource, ResourceConsumer},
 };
 use util_cgroups::{
     Cgroup,
-    measure::v2::{V2Collector, cpu::CpuStatCollectorSettings, memory::MemoryStatCollectorSettings},
+    measure::v2::{
+        V2Collector, cpu::CpuStatCollectorSettings, io::BlockDeviceNames, memory::MemoryStatCollectorSettings,
+        pressure::PressureRecord,
+    },
 };
 
 use super::{
-    delta::CpuDeltaCounters, metrics::AugmentedMetric, metrics::AugmentedMetrics, self_stop::analyze_io_result,
+    delta::{CpuDeltaCounters, IoDeltaCounters, MemoryEventsDeltaCounters, PressureDeltaCounters},
+    metrics::AugmentedMetric,
+    metrics::AugmentedMetrics,
+    metrics::is_memory_stat_event,
+    self_stop::analyze_io_result,
 };
 
 pub struct CgroupV2Probe {
     consumer: ResourceConsumer,
     delta_counters: CpuDeltaCounters,
+    memory_events_counters: MemoryEventsDeltaCounters,
+    /// Counters of the additional keys of `memory.stat`, `None` for the keys that are not event counters.
+    memory_stat_counters: Vec<Option<CounterDiff>>,
+    /// Counters of the PSI files, in this order: cpu, memory, io.
+    pressure_counters: [PressureDeltaCounters; 3],
+    /// Counters of `io.stat`, per block device (major, minor).
+    io_counters: HashMap<(u32, u32), IoDeltaCounters>,
+    device_names: BlockDeviceNames,
     metrics: AugmentedMetrics,
     collector: V2Collector,
     io_buf: Vec<u8>,
@@ -28,12 +46,21 @@ impl CgroupV2Probe {
             path: cgroup.canonical_path().to_owned().into(),
         };
         let mut io_buf = Vec::new();
+        let memory_stat_settings = MemoryStatCollectorSettings {
+            additional_keys: metrics.memory_stat_keys.clone(),
+            ..Default::default()
+        };
         let collector = V2Collector::new(
             cgroup,
-            MemoryStatCollectorSettings::default(),
+            memory_stat_settings,
             CpuStatCollectorSettings::default(),
             &mut io_buf,
         )?;
+        let memory_stat_counters = metrics
+            .memory_stat_keys
+            .iter()
+            .map(|key| is_memory_stat_event(key).then(|| CounterDiff::with_max_value(u64::MAX)))
+            .collect();
 
         // To get the number of logical core, one could think about calling num_cpus::get().
         // However, this is affected by the constraints set on the Alumet process (sched affinity, cgroups cpuset), which is not what we want.
@@ -42,6 +69,11 @@ impl CgroupV2Probe {
         Ok(Self {
             consumer,
             delta_counters: Default::default(),
+            memory_events_counters: Default::default(),
+            memory_stat_counters,
+            pressure_counters: Default::default(),
+            io_counters: HashMap::new(),
+            device_names: BlockDeviceNames::new(),
             metrics,
             collector,
             io_buf,
@@ -61,6 +93,34 @@ impl CgroupV2Probe {
             .with_attr_slice(&metric.attributes)
             .with_attr_slice(&self.metrics.common_attrs)
     }
+
+    /// Pushes the measurements of a line of a PSI file.
+    fn push_pressure(
+        &self,
+        measurements: &mut MeasurementAccumulator,
+        t: Timestamp,
+        pressure: &'static str,
+        stall: &'static str,
+        record: PressureRecord,
+        stall_time_delta: Option<u64>,
+    ) {
+        let resource = Resource::LocalMachine;
+        for (window, avg) in [("10s", record.avg10), ("60s", record.avg60), ("300s", record.avg300)] {
+            measurements.push(
+                self.new_point(&self.metrics.pressure_stall_avg, t, &resource, avg)
+                    .with_attr("pressure", pressure)
+                    .with_attr("stall", stall)
+                    .with_attr("window", window),
+            );
+        }
+        if let Some(value) = stall_time_delta {
+            measurements.push(
+                self.new_point(&self.metrics.pressure_stall_time_delta, t, &resource, value)
+                    .with_attr("pressure", pressure)
+                    .with_attr("stall", stall),
+            );
+        }
+    }
 }
 
 impl Source for CgroupV2Probe {
@@ -147,6 +207,31 @@ impl Source for CgroupV2Probe {
                     );
                 }
             }
+
+            // CPU throttling (only if the cpu controller is enabled)
+            let periods = cpu_stat
+                .nr_periods
+                .and_then(|v| self.delta_counters.nr_periods.update(v).difference());
+            let throttled_periods = cpu_stat
+                .nr_throttled
+                .and_then(|v| self.delta_counters.nr_throttled.update(v).difference());
+            let throttled_time = cpu_stat
+                .throttled
+                .and_then(|v| self.delta_counters.throttled.update(v).difference());
+            if let Some(value) = periods {
+                measurements.push(self.new_point(&self.metrics.cpu_periods, t, &resource, value));
+            }
+            if let Some(value) = throttled_periods {
+                measurements.push(self.new_point(&self.metrics.cpu_throttled_periods, t, &resource, value));
+            }
+            if let Some(value) = throttled_time {
+                measurements.push(self.new_point(&self.metrics.cpu_throttled_time, t, &resource, value));
+            }
+        }
+
+        // Number of processes
+        if let Some(pids) = data.pids_current {
+            measurements.push(self.new_point(&self.metrics.pids, t, &resource, pids));
         }
 
         // Memory statistics
@@ -166,12 +251,117 @@ impl Source for CgroupV2Probe {
             if let Some(value) = mem_stat.page_tables {
                 measurements.push(self.new_point(&self.metrics.memory_pagetables, t, &resource, value));
             }
+            for (i, value) in mem_stat.additional.into_iter().enumerate() {
+                let Some(value) = value else {
+                    continue;
+                };
+                let key = &self.metrics.memory_stat_keys[i];
+                match &mut self.memory_stat_counters[i] {
+                    Some(counter) => {
+                        if let Some(delta) = counter.update(value).difference() {
+                            measurements.push(
+                                self.new_point(&self.metrics.memory_stat_events, t, &resource, delta)
+                                    .with_attr("key", key.clone()),
+                            );
+                        }
+                    }
+                    None => {
+                        measurements.push(
+                            self.new_point(&self.metrics.memory_stat, t, &resource, value)
+                                .with_attr("key", key.clone()),
+                        );
+                    }
+                }
+            }
+        }
+        if let Some(events) = data.memory_events {
+            let counters = &mut self.memory_events_counters;
+            let deltas = [
+                ("high", events.high.and_then(|v| counters.high.update(v).difference())),
+                ("max", events.max.and_then(|v| counters.max.update(v).difference())),
+                ("oom", events.oom.and_then(|v| counters.oom.update(v).difference())),
+                (
+                    "oom_kill",
+                    events.oom_kill.and_then(|v| counters.oom_kill.update(v).difference()),
+                ),
+            ];
+            for (event, value) in deltas {
+                if let Some(value) = value {
+                    measurements.push(
+                        self.new_point(&self.metrics.memory_events, t, &resource, value)
+                            .with_attr("event", event),
+                    );
+                }
+            }
+        }
+
+        // I/O statistics, per block device
+        if let Some(io_stat) = data.io_stat {
+            for dev in io_stat {
+                let counters = self.io_counters.entry((dev.major, dev.minor)).or_default();
+                let bytes = [
+                    ("read", counters.rbytes.update(dev.rbytes).difference()),
+                    ("write", counters.wbytes.update(dev.wbytes).difference()),
+                    ("discard", counters.dbytes.update(dev.dbytes).difference()),
+                ];
+                let operations = [
+                    ("read", counters.rios.update(dev.rios).difference()),
+                    ("write", counters.wios.update(dev.wios).difference()),
+                    ("discard", counters.dios.update(dev.dios).difference()),
+                ];
+                let device = Resource::BlockDevice {
+                    name: self.device_names.get(dev.major, dev.minor).to_owned().into(),
+                };
+                for (direction, value) in bytes {
+                    if let Some(value) = value {
+                        measurements.push(
+                            self.new_point(&self.metrics.disk_bytes, t, &device, value)
+                                .with_attr("direction", direction),
+                        );
+                    }
+                }
+                for (direction, value) in operations {
+                    if let Some(value) = value {
+                        measurements.push(
+                            self.new_point(&self.metrics.disk_operations, t, &device, value)
+                                .with_attr("direction", direction),
+                        );
+                    }
+                }
+            }
+        }
+
+        // Pressure Stall Information
+        let pressures = [
+            ("cpu", data.cpu_pressure),
+            ("memory", data.memory_pressure),
+            ("io", data.io_pressure),
+        ];
+        for (i, (pressure, stats)) in pressures.into_iter().enumerate() {
+            let Some(stats) = stats else {
+                continue;
+            };
+            if let Some(record) = stats.some {
+                let delta = self.pressure_counters[i].some.update(record.total).difference();
+                self.push_pressure(measurements, t, pressure, "some", record, delta);
+            }
+            if let Some(record) = stats.full {
+                let delta = self.pressure_counters[i].full.update(record.total).difference();
+                self.push_pressure(measurements, t, pressure, "full", record, delta);
+            }
         }
         Ok(())
     }
 
     fn reset(&mut self) -> anyhow::Result<()> {
         self.delta_counters.reset();
+        self.memory_events_counters.reset();
+        self.memory_stat_counters
+            .iter_mut()
+            .flatten()
+            .for_each(CounterDiff::reset);
+        self.pressure_counters.iter_mut().for_each(PressureDeltaCounters::reset);
+        self.io_counters.clear();
         self.last_timestamp = None;
         Ok(())
     }
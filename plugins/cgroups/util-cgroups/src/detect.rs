@@ -56,9 +56,10 @@ pub struct CgroupDetector {
 
 #[derive(Debug, Clone)]
 pub struct Config {
-    /// Time between each refresh of the filesystem watcher.
+    /// Time between each refresh of the filesystem watcher, when polling is used.
     ///
-    /// Only applies to cgroup v1 hierarchies (cgroupv2 supports inotify).
+    /// Polling is used when [`force_polling`](Self::force_polling) is `true`, or as a fallback
+    /// when a cgroup v1 hierarchy cannot be watched with `inotify`.
     pub v1_refresh_interval: Duration,
 
     /// If `true`, always use a poll-based approach instead of `inotify`.
@@ -182,19 +183,35 @@ impl CgroupDetector {
         };
 
         let paths_to_watch = vec![hierarchy.root().to_owned()];
-        let use_polling = hierarchy.version() == CgroupVersion::V1 || config.force_polling;
-
-        let watcher: Box<dyn file_watch::Watcher + Send> = match use_polling {
+        let watcher: Box<dyn file_watch::Watcher + Send> = match config.force_polling {
             true => {
-                // inotify is not supported, use polling
                 handler.initial_scan().context("error during initial scan")?;
                 let watcher = PollingRefresh::start(config.v1_refresh_interval, handler);
                 Box::new(watcher)
             }
             false => {
-                // inotify is supported
+                // inotify works on cgroup v1 and v2 hierarchies.
                 // First, start the watcher. Then, do the initial scan. This way, we will not miss events.
-                let watcher = file_watch::inotify::InotifyWatcher::new(handler.clone(), paths_to_watch)?;
+                let watcher = match file_watch::inotify::InotifyWatcher::new(handler.clone(), paths_to_watch) {
+                    Ok(watcher) => watcher,
+                    Err(e) if hierarchy.version() == CgroupVersion::V1 => {
+                        // There is one detector per v1 hierarchy, which can exceed the limit of inotify instances.
+                        // Polling is slower to detect new cgroups, but it still works.
+                        log::warn!(
+                            "could not watch {} with inotify, falling back to polling every {:?}: {e:#}",
+                            hierarchy.root().display(),
+                            config.v1_refresh_interval
+                        );
+                        handler.initial_scan().context("error during initial scan")?;
+                        let watcher = PollingRefresh::start(config.v1_refresh_interval, handler);
+                        return Ok(Self {
+                            hierarchy,
+                            watcher: Box::new(watcher),
+                            state,
+                        });
+                    }
+                    Err(e) => return Err(e),
+                };
 
                 // We need to manually do the initial scan.
                 let res = handler.initial_scan().context("error during initial scan");
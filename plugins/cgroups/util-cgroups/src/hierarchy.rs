@@ -408,6 +408,61 @@ pub fn find_user_app_slice(v2_hierarchy_root: &Path) -> anyhow::Result<PathBuf>
     }
 }
 
+/// Returns `true` if the cgroup `descendant` is `ancestor` or one of its descendants.
+///
+/// Both paths must be canonical cgroup paths, such as `/system.slice/my.service`.
+pub fn is_in_subtree(ancestor: &str, descendant: &str) -> bool {
+    let ancestor = ancestor.trim_end_matches('/');
+    match descendant.strip_prefix(ancestor) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+/// Returns the canonical path of the parent of a cgroup, or `None` for the root `/`.
+pub fn parent_path(path: &str) -> Option<&str> {
+    let path = path.trim_end_matches('/');
+    match path.rfind('/') {
+        None => None,
+        Some(0) => Some("/"),
+        Some(i) => Some(&path[..i]),
+    }
+}
+
+/// Returns the depth of a cgroup in its hierarchy: `0` for the root `/`, `1` for its children, etc.
+pub fn path_depth(path: &str) -> usize {
+    path.split('/').filter(|s| !s.is_empty()).count()
+}
+
+/// Returns the ancestor of the cgroup that is at the given depth, or the cgroup itself if it is not deeper than `depth`.
+pub fn ancestor_at_depth(path: &str, depth: usize) -> &str {
+    if depth == 0 {
+        return "/";
+    }
+    let mut seen = 0;
+    for (i, c) in path.char_indices().skip(1) {
+        if c == '/' {
+            seen += 1;
+            if seen == depth {
+                return &path[..i];
+            }
+        }
+    }
+    path.trim_end_matches('/')
+}
+
+/// Returns the deepest cgroup that is an ancestor (or self) of both `a` and `b`.
+pub fn common_ancestor<'a>(a: &'a str, b: &str) -> &'a str {
+    let mut ancestor = a.trim_end_matches('/');
+    while !ancestor.is_empty() && !is_in_subtree(ancestor, b) {
+        ancestor = match parent_path(ancestor) {
+            Some("/") | None => "",
+            Some(parent) => parent,
+        };
+    }
+    if ancestor.is_empty() { "/" } else { ancestor }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{fs, os::unix::fs::PermissionsExt, path::PathBuf};
@@ -416,7 +471,10 @@ mod tests {
 
     use crate::{
         Cgroup,
-        hierarchy::{CgroupHierarchy, CgroupVersion, HierarchyError},
+        hierarchy::{
+            CgroupHierarchy, CgroupVersion, HierarchyError, ancestor_at_depth, common_ancestor, is_in_subtree,
+            parent_path, path_depth,
+        },
     };
 
     #[test]
@@ -562,4 +620,34 @@ mod tests {
         let cgroup = Cgroup::from_cgroup_path(&cgrp_hier, "/".to_string());
         assert_eq!(cgroup.unique_name(), format!("{}", cgroup));
     }
+
+    #[test]
+    fn path_relationships() {
+        assert!(is_in_subtree("/", "/a.slice"));
+        assert!(is_in_subtree("/a.slice", "/a.slice"));
+        assert!(is_in_subtree("/a.slice", "/a.slice/b"));
+        assert!(!is_in_subtree("/a.slice", "/a.slice2"));
+        assert!(!is_in_subtree("/a.slice/b", "/a.slice"));
+
+        assert_eq!(parent_path("/"), None);
+        assert_eq!(parent_path("/a.slice"), Some("/"));
+        assert_eq!(parent_path("/a.slice/b/c"), Some("/a.slice/b"));
+
+        assert_eq!(path_depth("/"), 0);
+        assert_eq!(path_depth("/a.slice"), 1);
+        assert_eq!(path_depth("/a.slice/b/c"), 3);
+
+        assert_eq!(ancestor_at_depth("/a.slice/b/c", 0), "/");
+        assert_eq!(ancestor_at_depth("/a.slice/b/c", 1), "/a.slice");
+        assert_eq!(ancestor_at_depth("/a.slice/b/c", 2), "/a.slice/b");
+        assert_eq!(ancestor_at_depth("/a.slice/b/c", 3), "/a.slice/b/c");
+        assert_eq!(ancestor_at_depth("/a.slice/b/c", 5), "/a.slice/b/c");
+
+        assert_eq!(common_ancestor("/a.slice/b/c", "/a.slice/b/d"), "/a.slice/b");
+        assert_eq!(common_ancestor("/a.slice/b", "/a.slice/b/d"), "/a.slice/b");
+        assert_eq!(common_ancestor("/a.slice/b/d", "/a.slice/b"), "/a.slice/b");
+        assert_eq!(common_ancestor("/a.slice/b", "/a.slice/bb"), "/a.slice");
+        assert_eq!(common_ancestor("/a.slice", "/b.slice"), "/");
+        assert_eq!(common_ancestor("/", "/b.slice"), "/");
+    }
 }
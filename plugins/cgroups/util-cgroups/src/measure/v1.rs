@@ -1,20 +1,92 @@
 //! Measure cgroup v1 things.
 
 use anyhow::Context;
-use std::io::{self};
+use std::{
+    fs::File,
+    io::{self, ErrorKind},
+    path::Path,
+};
 
-use crate::{Cgroup, CgroupHierarchy, measure::parse::U64File};
+use crate::{
+    Cgroup, CgroupHierarchy,
+    measure::{
+        parse::{SelectiveStatFile, StatFileBuilder, U64File, parse_space_kv, read_fully},
+        v2::{io::DeviceIoStats, memory::MemoryStats},
+    },
+};
+
+/// Number of clock ticks per second, in which `cpuacct.stat` is expressed.
+///
+/// The kernel always exposes the times in `USER_HZ`, which is 100 on all the supported architectures,
+/// regardless of its internal tick rate.
+const USER_HZ: u64 = 100;
+
+/// Key of `memory.stat` that contains the anonymous memory of the cgroup and its descendants.
+const MEMORY_STAT_ANON: &str = "total_rss";
+/// Key of `memory.stat` that contains the page cache of the cgroup and its descendants.
+const MEMORY_STAT_FILE: &str = "total_cache";
 
 /// Collects cgroup v1 measurements.
 pub struct V1Collector {
     cpuacct_usage: Option<U64File>,
+    cpuacct_usage_percpu: Option<File>,
+    cpuacct_stat: Option<File>,
     memory_usage: Option<U64File>,
+    memory_stat: Option<MemoryStatV1Collector>,
+    blkio_bytes: Option<File>,
+    blkio_operations: Option<File>,
 }
 
 /// The result of a cgroupv1 measurement operation.
 pub struct V1Stats {
+    /// Total CPU time consumed by the cgroup, in nanoseconds.
     pub cpuacct_usage: Option<u64>,
+    /// CPU time consumed by the cgroup on each CPU, in nanoseconds.
+    ///
+    /// The index of the value is the id of the CPU.
+    pub cpuacct_usage_percpu: Option<Vec<u64>>,
+    /// CPU time consumed by the cgroup in user and system mode.
+    pub cpuacct_stat: Option<CpuacctStats>,
     pub memory_usage: Option<u64>,
+    /// Measurements of `memory.stat`, with the same meaning as in cgroup v2.
+    ///
+    /// The kernel stack and the page tables are not reported by cgroup v1.
+    pub memory_stat: Option<MemoryStats>,
+    /// I/O statistics of each block device, from the throttling policy of the `blkio` controller.
+    pub blkio_throttle: Option<Vec<DeviceIoStats>>,
+}
+
+/// Represents the measurements extracted from the `cpuacct.stat` file.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CpuacctStats {
+    /// Time spent in user mode, in nanoseconds.
+    pub user: u64,
+    /// Time spent in kernel mode, in nanoseconds.
+    pub system: u64,
+}
+
+/// Settings of the [`V1Collector`].
+#[derive(Debug, Default, Clone)]
+pub struct V1CollectorSettings {
+    /// Other keys of `memory.stat` to collect, like `mapped_file` or `total_pgmajfault`.
+    pub memory_stat_keys: Vec<String>,
+}
+
+/// Collects measurements from the `memory.stat` file of cgroup v1.
+struct MemoryStatV1Collector {
+    stat_file: SelectiveStatFile,
+    anon: Option<u8>,
+    file: Option<u8>,
+    additional: Vec<Option<u8>>,
+}
+
+/// The counters of [`DeviceIoStats`] that are provided by a `blkio` file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlkioCounters {
+    /// `blkio.throttle.io_service_bytes`: number of bytes.
+    Bytes,
+    /// `blkio.throttle.io_serviced`: number of operations.
+    Operations,
 }
 
 impl V1Collector {
@@ -24,34 +96,76 @@ impl V1Collector {
     /// The probe can only measure what is provided by the cgroup controllers attached to the given hierarchies.
     /// For instance, if one hierarchy has the `cpuacct` controller, and another one has the `memory` controller,
     /// the probe reads both `cpuacct.usage` and `memory.stat` (in their respective hierarchies).
-    pub fn across_hierarchies(cgroup_path: &str, hierarchies: &[&CgroupHierarchy]) -> anyhow::Result<Self> {
-        let mut cpuacct_usage = None;
-        let mut memory_usage = None;
+    pub fn across_hierarchies(
+        cgroup_path: &str,
+        hierarchies: &[&CgroupHierarchy],
+        settings: &V1CollectorSettings,
+        io_buf: &mut Vec<u8>,
+    ) -> anyhow::Result<Self> {
+        let mut res = Self {
+            cpuacct_usage: None,
+            cpuacct_usage_percpu: None,
+            cpuacct_stat: None,
+            memory_usage: None,
+            memory_stat: None,
+            blkio_bytes: None,
+            blkio_operations: None,
+        };
         for h in hierarchies {
             let cgroup_in_sysfs = h.cgroup_fs_path(cgroup_path);
             if h.available_controllers().iter().any(|c| c == "cpuacct") {
                 let data_path = cgroup_in_sysfs.join("cpuacct.usage");
                 let file = U64File::open(&data_path).with_context(|| format!("failed to open {data_path:?}"))?;
-                cpuacct_usage = Some(file)
+                res.cpuacct_usage = Some(file);
+                res.cpuacct_usage_percpu = open_optional(&cgroup_in_sysfs.join("cpuacct.usage_percpu"))?;
+                res.cpuacct_stat = open_optional(&cgroup_in_sysfs.join("cpuacct.stat"))?;
             }
             if h.available_controllers().iter().any(|c| c == "memory") {
                 let data_path = cgroup_in_sysfs.join("memory.usage_in_bytes");
                 let file = U64File::open(&data_path).with_context(|| format!("failed to open {data_path:?}"))?;
-                memory_usage = Some(file)
+                res.memory_usage = Some(file);
+
+                let data_path = cgroup_in_sysfs.join("memory.stat");
+                res.memory_stat = match MemoryStatV1Collector::new(&data_path, &settings.memory_stat_keys, io_buf) {
+                    Ok(c) => Some(c),
+                    Err(e) if e.kind() == ErrorKind::NotFound => {
+                        log::warn!(
+                            "{} does not exist, some metrics will not be available",
+                            data_path.display()
+                        );
+                        None
+                    }
+                    Err(e) => return Err(e).with_context(|| format!("failed to open {data_path:?}")),
+                };
+            }
+            if h.available_controllers().iter().any(|c| c == "blkio") {
+                // The recursive files include the I/O of the descendants, like io.stat in cgroup v2,
+                // but they are missing from old kernels.
+                res.blkio_bytes =
+                    match open_optional(&cgroup_in_sysfs.join("blkio.throttle.io_service_bytes_recursive"))? {
+                        Some(f) => Some(f),
+                        None => open_optional(&cgroup_in_sysfs.join("blkio.throttle.io_service_bytes"))?,
+                    };
+                res.blkio_operations =
+                    match open_optional(&cgroup_in_sysfs.join("blkio.throttle.io_serviced_recursive"))? {
+                        Some(f) => Some(f),
+                        None => open_optional(&cgroup_in_sysfs.join("blkio.throttle.io_serviced"))?,
+                    };
             }
         }
-        Ok(Self {
-            cpuacct_usage,
-            memory_usage,
-        })
+        Ok(res)
     }
 
     /// Creates a new `V1Collector` in a single cgroup v1 hierarchy.
     ///
     /// # Available metrics
     /// The collector can only measure what is provided by the cgroup controllers attached to the given hierarchy.
-    pub fn in_single_hierarchy(cgroup: Cgroup) -> anyhow::Result<Self> {
-        Self::across_hierarchies(cgroup.canonical_path(), &[cgroup.hierarchy()])
+    pub fn in_single_hierarchy(
+        cgroup: Cgroup,
+        settings: &V1CollectorSettings,
+        io_buf: &mut Vec<u8>,
+    ) -> anyhow::Result<Self> {
+        Self::across_hierarchies(cgroup.canonical_path(), &[cgroup.hierarchy()], settings, io_buf)
             .with_context(|| format!("collector creation failed for cgroup {}", cgroup.unique_name()))
     }
 
@@ -64,25 +178,229 @@ impl V1Collector {
             .map(|f| unsafe { f.read(io_buf) })
             .transpose()?;
 
+        let cpuacct_usage_percpu = self
+            .cpuacct_usage_percpu
+            .as_mut()
+            .map(|f| {
+                read_fully(f, io_buf)?;
+                parse_usage_percpu(utf8(io_buf)?)
+            })
+            .transpose()?;
+
+        let cpuacct_stat = self
+            .cpuacct_stat
+            .as_mut()
+            .map(|f| {
+                read_fully(f, io_buf)?;
+                // SAFETY: the content is generated by the kernel and is always valid ASCII (hence valid UTF-8)
+                unsafe { parse_cpuacct_stat(io_buf) }
+            })
+            .transpose()?;
+
         let memory_usage = self
             .memory_usage
             .as_mut()
             .map(|f| unsafe { f.read(io_buf) })
             .transpose()?;
 
+        let memory_stat = self.memory_stat.as_mut().map(|c| c.measure(io_buf)).transpose()?;
+
+        let mut blkio_throttle = None;
+        for (file, counters) in [
+            (&mut self.blkio_bytes, BlkioCounters::Bytes),
+            (&mut self.blkio_operations, BlkioCounters::Operations),
+        ] {
+            if let Some(f) = file {
+                read_fully(f, io_buf)?;
+                let stats = blkio_throttle.get_or_insert_with(Vec::new);
+                parse_blkio_throttle(utf8(io_buf)?, counters, stats)?;
+            }
+        }
+
         Ok(V1Stats {
             cpuacct_usage,
+            cpuacct_usage_percpu,
+            cpuacct_stat,
             memory_usage,
+            memory_stat,
+            blkio_throttle,
+        })
+    }
+}
+
+impl MemoryStatV1Collector {
+    fn new(path: &Path, additional_keys: &[String], io_buf: &mut Vec<u8>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let mut keys = vec![MEMORY_STAT_ANON.to_owned(), MEMORY_STAT_FILE.to_owned()];
+        for key in additional_keys {
+            // the same key cannot be looked for twice
+            if !keys.contains(key) {
+                keys.push(key.clone());
+            }
+        }
+        let (stat_file, mapping) = StatFileBuilder::new(file, &keys).build(io_buf)?;
+        if !mapping.keys_not_found().is_empty() {
+            log::warn!(
+                "keys not found in {}: {}",
+                path.display(),
+                mapping.keys_not_found().join(", ")
+            )
+        }
+        Ok(Self {
+            stat_file,
+            anon: mapping.line_index(MEMORY_STAT_ANON),
+            file: mapping.line_index(MEMORY_STAT_FILE),
+            additional: additional_keys.iter().map(|k| mapping.line_index(k)).collect(),
+        })
+    }
+
+    fn measure(&mut self, io_buf: &mut Vec<u8>) -> io::Result<MemoryStats> {
+        let mut res = MemoryStats {
+            additional: vec![None; self.additional.len()],
+            ..Default::default()
+        };
+        // SAFETY: the content is generated by the kernel and is always valid ASCII (hence valid UTF-8)
+        unsafe {
+            self.stat_file.read(io_buf, |i, _k, v| {
+                // an additional key can also be one of the keys above
+                for (j, index) in self.additional.iter().enumerate() {
+                    if *index == Some(i) {
+                        res.additional[j] = Some(v);
+                    }
+                }
+                if self.anon == Some(i) {
+                    res.anon = Some(v);
+                } else if self.file == Some(i) {
+                    res.file = Some(v);
+                }
+            })
+        }?;
+        Ok(res)
+    }
+}
+
+/// Opens a file that may not exist, depending on the version and the configuration of the kernel.
+fn open_optional(path: &Path) -> anyhow::Result<Option<File>> {
+    match File::open(path) {
+        Ok(f) => Ok(Some(f)),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            log::debug!("{} does not exist, some metrics will not be available", path.display());
+            Ok(None)
+        }
+        Err(e) => Err(e).with_context(|| format!("failed to open {path:?}")),
+    }
+}
+
+fn utf8(io_buf: &[u8]) -> io::Result<&str> {
+    std::str::from_utf8(io_buf).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+/// Parses the content of the `cpuacct.usage_percpu` file.
+///
+/// # Input format
+/// ```text
+/// 2385712921 1862372045 2039281739 1967226911
+/// ```
+pub fn parse_usage_percpu(content: &str) -> io::Result<Vec<u64>> {
+    content
+        .split_ascii_whitespace()
+        .map(|v| v.parse().map_err(|_| io::Error::from(ErrorKind::InvalidData)))
+        .collect()
+}
+
+/// Parses the content of the `cpuacct.stat` file, and converts the times to nanoseconds.
+///
+/// # Input format
+/// ```text
+/// user 79463
+/// system 20118
+/// ```
+///
+/// # Safety
+/// The bytes passed in must be valid UTF-8.
+pub unsafe fn parse_cpuacct_stat(io_buf: &[u8]) -> io::Result<CpuacctStats> {
+    const NANOS_PER_TICK: u64 = 1_000_000_000 / USER_HZ;
+
+    let mut res = CpuacctStats::default();
+    unsafe {
+        parse_space_kv(io_buf, |_, k, v| match k {
+            "user" => res.user = v * NANOS_PER_TICK,
+            "system" => res.system = v * NANOS_PER_TICK,
+            _ => (),
         })
+    }?;
+    Ok(res)
+}
+
+/// Parses the content of a throttling file of the `blkio` controller, and adds the counters to `stats`.
+///
+/// The devices that are not in `stats` yet are added to it, with their other counters set to zero.
+///
+/// # Input format
+/// ```text
+/// 8:0 Read 90430464
+/// 8:0 Write 299008000
+/// 8:0 Sync 389438464
+/// 8:0 Async 0
+/// 8:0 Discard 50331648
+/// 8:0 Total 439769112
+/// Total 439769112
+/// ```
+///
+/// `Discard` is missing on old kernels.
+pub fn parse_blkio_throttle(content: &str, counters: BlkioCounters, stats: &mut Vec<DeviceIoStats>) -> io::Result<()> {
+    fn invalid() -> io::Error {
+        io::Error::from(ErrorKind::InvalidData)
+    }
+
+    for line in content.lines() {
+        let mut fields = line.split_ascii_whitespace();
+        let (Some(device), Some(op), Some(value)) = (fields.next(), fields.next(), fields.next()) else {
+            // empty line or total of all the devices
+            continue;
+        };
+        let (major, minor) = device.split_once(':').ok_or_else(invalid)?;
+        let major: u32 = major.parse().map_err(|_| invalid())?;
+        let minor: u32 = minor.parse().map_err(|_| invalid())?;
+        let value: u64 = value.parse().map_err(|_| invalid())?;
+
+        let dev = match stats.iter().position(|s| s.major == major && s.minor == minor) {
+            Some(i) => &mut stats[i],
+            None => {
+                stats.push(DeviceIoStats {
+                    major,
+                    minor,
+                    ..Default::default()
+                });
+                stats.last_mut().unwrap()
+            }
+        };
+        let counter = match (counters, op) {
+            (BlkioCounters::Bytes, "Read") => &mut dev.rbytes,
+            (BlkioCounters::Bytes, "Write") => &mut dev.wbytes,
+            (BlkioCounters::Bytes, "Discard") => &mut dev.dbytes,
+            (BlkioCounters::Operations, "Read") => &mut dev.rios,
+            (BlkioCounters::Operations, "Write") => &mut dev.wios,
+            (BlkioCounters::Operations, "Discard") => &mut dev.dios,
+            // Sync, Async and Total overlap with the other operations
+            _ => continue,
+        };
+        *counter = value;
     }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{fs::File, io::Write};
+    use std::{
+        fs::{self, File},
+        io::Write,
+    };
 
+    use pretty_assertions::assert_eq;
     use tempfile::tempdir;
 
+    use super::*;
     use crate::{CgroupHierarchy, CgroupVersion, measure::v1::V1Collector};
 
     #[test]
@@ -104,9 +422,14 @@ mod tests {
         let hierarchy1 = CgroupHierarchy::manually_unchecked(root.path(), CgroupVersion::V2, vec!["cpuacct"]);
         let hierarchy2 = CgroupHierarchy::manually_unchecked(root.path(), CgroupVersion::V2, vec!["memory"]);
 
-        let res = V1Collector::across_hierarchies("/", &[&hierarchy1, &hierarchy2]);
-        let mut v1_collector = res.unwrap();
         let mut buf: Vec<u8> = vec![];
+        let res = V1Collector::across_hierarchies(
+            "/",
+            &[&hierarchy1, &hierarchy2],
+            &V1CollectorSettings::default(),
+            &mut buf,
+        );
+        let mut v1_collector = res.unwrap();
 
         assert!(v1_collector.cpuacct_usage.is_some());
         assert!(v1_collector.memory_usage.is_some());
@@ -118,4 +441,184 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn all_files() -> anyhow::Result<()> {
+        let root = tempdir()?;
+        let cpuacct = root.path().join("cpuacct");
+        let memory = root.path().join("memory");
+        let blkio = root.path().join("blkio");
+        for dir in [&cpuacct, &memory, &blkio] {
+            fs::create_dir_all(dir.join("job"))?;
+        }
+        fs::write(cpuacct.join("job/cpuacct.usage"), "1000\n")?;
+        fs::write(cpuacct.join("job/cpuacct.usage_percpu"), "400 0 600 \n")?;
+        fs::write(cpuacct.join("job/cpuacct.stat"), "user 7\nsystem 2\n")?;
+        fs::write(memory.join("job/memory.usage_in_bytes"), "4096\n")?;
+        fs::write(
+            memory.join("job/memory.stat"),
+            "cache 10\nrss 20\nmapped_file 30\npgmajfault 1\ntotal_cache 100\ntotal_rss 200\ntotal_mapped_file 300\ntotal_pgmajfault 5\n",
+        )?;
+        fs::write(
+            blkio.join("job/blkio.throttle.io_service_bytes"),
+            "8:0 Read 4096\n8:0 Write 8192\n8:0 Sync 12288\n8:0 Async 0\n8:0 Total 12288\nTotal 12288\n",
+        )?;
+        fs::write(
+            blkio.join("job/blkio.throttle.io_serviced"),
+            "8:0 Read 1\n8:0 Write 2\n8:0 Sync 3\n8:0 Async 0\n8:0 Total 3\nTotal 3\n",
+        )?;
+
+        let hierarchies = [
+            CgroupHierarchy::manually_unchecked(cpuacct, CgroupVersion::V1, vec!["cpu", "cpuacct"]),
+            CgroupHierarchy::manually_unchecked(memory, CgroupVersion::V1, vec!["memory"]),
+            CgroupHierarchy::manually_unchecked(blkio, CgroupVersion::V1, vec!["blkio"]),
+        ];
+        let settings = V1CollectorSettings {
+            memory_stat_keys: vec![String::from("total_mapped_file"), String::from("total_pgmajfault")],
+        };
+        let mut buf = Vec::new();
+        let mut collector =
+            V1Collector::across_hierarchies("/job", &hierarchies.iter().collect::<Vec<_>>(), &settings, &mut buf)?;
+        let stats = collector.measure(&mut buf)?;
+
+        assert_eq!(stats.cpuacct_usage, Some(1000));
+        assert_eq!(stats.cpuacct_usage_percpu, Some(vec![400, 0, 600]));
+        assert_eq!(
+            stats.cpuacct_stat,
+            Some(CpuacctStats {
+                user: 70_000_000,
+                system: 20_000_000,
+            })
+        );
+        assert_eq!(stats.memory_usage, Some(4096));
+        let memory_stat = stats.memory_stat.unwrap();
+        assert_eq!(memory_stat.anon, Some(200));
+        assert_eq!(memory_stat.file, Some(100));
+        assert_eq!(memory_stat.kernel_stack, None);
+        assert_eq!(memory_stat.page_tables, None);
+        assert_eq!(memory_stat.additional, vec![Some(300), Some(5)]);
+        assert_eq!(
+            stats.blkio_throttle,
+            Some(vec![DeviceIoStats {
+                major: 8,
+                minor: 0,
+                rbytes: 4096,
+                wbytes: 8192,
+                rios: 1,
+                wios: 2,
+                ..Default::default()
+            }])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn missing_optional_files() -> anyhow::Result<()> {
+        let root = tempdir()?;
+        fs::write(root.path().join("cpuacct.usage"), "15\n")?;
+        let hierarchy = CgroupHierarchy::manually_unchecked(root.path(), CgroupVersion::V1, vec!["cpuacct", "blkio"]);
+
+        let mut buf = Vec::new();
+        let mut collector =
+            V1Collector::across_hierarchies("/", &[&hierarchy], &V1CollectorSettings::default(), &mut buf)?;
+        let stats = collector.measure(&mut buf)?;
+        assert_eq!(stats.cpuacct_usage, Some(15));
+        assert_eq!(stats.cpuacct_usage_percpu, None);
+        assert_eq!(stats.cpuacct_stat, None);
+        assert_eq!(stats.blkio_throttle, None);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_percpu() {
+        assert_eq!(
+            parse_usage_percpu("2385712921 1862372045 2039281739 \n").unwrap(),
+            vec![2385712921, 1862372045, 2039281739]
+        );
+        assert_eq!(parse_usage_percpu("").unwrap(), vec![]);
+        let err = parse_usage_percpu("12 abc\n").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn parse_blkio() {
+        const BYTES: &str = "8:16 Read 1459200
+8:16 Write 314773504
+8:16 Sync 316232704
+8:16 Async 0
+8:16 Discard 0
+8:16 Total 316232704
+8:0 Read 90430464
+8:0 Write 299008000
+8:0 Sync 339338464
+8:0 Async 0
+8:0 Discard 50331648
+8:0 Total 389438464
+Total 705671168
+";
+        const OPERATIONS: &str = "8:0 Read 8950
+8:0 Write 1252
+8:0 Sync 10202
+8:0 Async 0
+8:0 Discard 3021
+8:0 Total 13223
+8:16 Read 192
+8:16 Write 353
+8:16 Sync 545
+8:16 Async 0
+8:16 Discard 0
+8:16 Total 545
+Total 13768
+";
+        let mut stats = Vec::new();
+        parse_blkio_throttle(BYTES, BlkioCounters::Bytes, &mut stats).unwrap();
+        parse_blkio_throttle(OPERATIONS, BlkioCounters::Operations, &mut stats).unwrap();
+        assert_eq!(
+            stats,
+            vec![
+                DeviceIoStats {
+                    major: 8,
+                    minor: 16,
+                    rbytes: 1459200,
+                    wbytes: 314773504,
+                    rios: 192,
+                    wios: 353,
+                    dbytes: 0,
+                    dios: 0,
+                },
+                DeviceIoStats {
+                    major: 8,
+                    minor: 0,
+                    rbytes: 90430464,
+                    wbytes: 299008000,
+                    rios: 8950,
+                    wios: 1252,
+                    dbytes: 50331648,
+                    dios: 3021,
+                },
+            ]
+        );
+
+        // the file only contains the total when the cgroup has not done any I/O
+        let mut stats = Vec::new();
+        parse_blkio_throttle("Total 0\n", BlkioCounters::Bytes, &mut stats).unwrap();
+        assert_eq!(stats, vec![]);
+
+        let err = parse_blkio_throttle("sda Read 0\n", BlkioCounters::Bytes, &mut stats).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        let err = parse_blkio_throttle("8:0 Read abc\n", BlkioCounters::Bytes, &mut stats).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn parse_stat() {
+        let stats = unsafe { parse_cpuacct_stat(b"user 79463\nsystem 20118\n") }.unwrap();
+        assert_eq!(
+            stats,
+            CpuacctStats {
+                user: 794_630_000_000,
+                system: 201_180_000_000,
+            }
+        );
+    }
 }
@@ -6,6 +6,15 @@ pub mod cpu;
 /// Memory statistics for cgroup v2.
 pub mod memory;
 
+/// I/O statistics for cgroup v2.
+pub mod io;
+
+/// Number of processes for cgroup v2.
+pub mod pids;
+
+/// Pressure Stall Information (PSI) for cgroup v2.
+pub mod pressure;
+
 /// Small zero-cost wrapper around line index.
 mod line_index;
 
@@ -36,20 +45,37 @@ mod common {
 
     use super::{
         cpu::{CpuStatCollector, CpuStats},
-        memory::{MemoryCurrentCollector, MemoryStatCollector, MemoryStats},
+        io::{DeviceIoStats, IoStatCollector},
+        memory::{MemoryCurrentCollector, MemoryEvents, MemoryEventsCollector, MemoryStatCollector, MemoryStats},
+        pids::PidsCurrentCollector,
+        pressure::{PressureCollector, PressureStats},
     };
 
     /// Collects cgroup v2 measurements.
     pub struct V2Collector {
         memory_current: Option<MemoryCurrentCollector>,
         memory_stat: Option<MemoryStatCollector>,
+        memory_events: Option<MemoryEventsCollector>,
         cpu_stat: Option<CpuStatCollector>,
+        pids_current: Option<PidsCurrentCollector>,
+        io_stat: Option<IoStatCollector>,
+        cpu_pressure: Option<PressureCollector>,
+        memory_pressure: Option<PressureCollector>,
+        io_pressure: Option<PressureCollector>,
     }
 
     pub struct V2Stats {
         pub memory_current: Option<u64>,
         pub memory_stat: Option<MemoryStats>,
+        pub memory_events: Option<MemoryEvents>,
         pub cpu_stat: Option<CpuStats>,
+        /// Number of processes in the cgroup and its descendants.
+        pub pids_current: Option<u64>,
+        /// I/O statistics of each block device.
+        pub io_stat: Option<Vec<DeviceIoStats>>,
+        pub cpu_pressure: Option<PressureStats>,
+        pub memory_pressure: Option<PressureStats>,
+        pub io_pressure: Option<PressureStats>,
     }
 
     impl V2Collector {
@@ -71,6 +97,7 @@ mod common {
             let memory_current_file = cgroup_path.join("memory.current");
             let memory_stat_file = cgroup_path.join("memory.stat");
             let cpu_stat_file = cgroup_path.join("cpu.stat");
+            let memory_events_file = cgroup_path.join("memory.events");
 
             let prepare_memory_current = || -> anyhow::Result<Option<MemoryCurrentCollector>> {
                 match MemoryCurrentCollector::new(&memory_current_file) {
@@ -102,6 +129,21 @@ mod common {
                 }
             };
 
+            let prepare_memory_events = |io_buf: &mut Vec<u8>| -> anyhow::Result<Option<MemoryEventsCollector>> {
+                match MemoryEventsCollector::new(&memory_events_file, io_buf) {
+                    Ok(res) => Ok(Some(res)),
+                    Err(memory::CollectorCreationError::Io(e, _)) if e.kind() == ErrorKind::NotFound => {
+                        // the file does not exist, ignore
+                        log::warn!(
+                            "{} does not exist, some metrics will not be available",
+                            memory_events_file.display()
+                        );
+                        Ok(None)
+                    }
+                    Err(e) => Err(e.into()),
+                }
+            };
+
             let prepare_cpu_stat = |io_buf: &mut Vec<u8>| -> anyhow::Result<Option<CpuStatCollector>> {
                 match CpuStatCollector::new(&cpu_stat_file, cpu_stat_settings, io_buf) {
                     Ok(res) => Ok(Some(res)),
@@ -117,12 +159,57 @@ mod common {
                 }
             };
 
+            let prepare_pids_current = || -> anyhow::Result<Option<PidsCurrentCollector>> {
+                let path = cgroup_path.join("pids.current");
+                match PidsCurrentCollector::new(&path) {
+                    Ok(res) => Ok(Some(res)),
+                    Err(e) if e.kind() == ErrorKind::NotFound => {
+                        // the pids controller is not always enabled, and the file never exists in the root cgroup
+                        log::debug!("{} does not exist, some metrics will not be available", path.display());
+                        Ok(None)
+                    }
+                    Err(e) => Err(e.into()),
+                }
+            };
+
+            let prepare_io_stat = || -> anyhow::Result<Option<IoStatCollector>> {
+                let path = cgroup_path.join("io.stat");
+                match IoStatCollector::new(&path) {
+                    Ok(res) => Ok(Some(res)),
+                    Err(e) if e.kind() == ErrorKind::NotFound => {
+                        // the io controller is often not enabled, don't flood the logs
+                        log::debug!("{} does not exist, some metrics will not be available", path.display());
+                        Ok(None)
+                    }
+                    Err(e) => Err(e.into()),
+                }
+            };
+
+            let prepare_pressure = |file_name: &str| -> anyhow::Result<Option<PressureCollector>> {
+                let path = cgroup_path.join(file_name);
+                match PressureCollector::new(&path) {
+                    Ok(res) => Ok(Some(res)),
+                    Err(e) if e.kind() == ErrorKind::NotFound => {
+                        // PSI can be disabled in the kernel, don't flood the logs
+                        log::debug!("{} does not exist, some metrics will not be available", path.display());
+                        Ok(None)
+                    }
+                    Err(e) => Err(e.into()),
+                }
+            };
+
             let error_msg = || format!("collector creation failed for cgroup {}", cgroup.unique_name());
 
             Ok(Self {
                 memory_current: prepare_memory_current().with_context(error_msg)?,
                 memory_stat: prepare_memory_stat(io_buf).with_context(error_msg)?,
+                memory_events: prepare_memory_events(io_buf).with_context(error_msg)?,
                 cpu_stat: prepare_cpu_stat(io_buf).with_context(error_msg)?,
+                pids_current: prepare_pids_current().with_context(error_msg)?,
+                io_stat: prepare_io_stat().with_context(error_msg)?,
+                cpu_pressure: prepare_pressure("cpu.pressure").with_context(error_msg)?,
+                memory_pressure: prepare_pressure("memory.pressure").with_context(error_msg)?,
+                io_pressure: prepare_pressure("io.pressure").with_context(error_msg)?,
             })
         }
 
@@ -132,12 +219,24 @@ mod common {
 
             let memory_current = self.memory_current.as_mut().map(|c| c.measure(io_buf)).transpose()?;
             let memory_stat = self.memory_stat.as_mut().map(|c| c.measure(io_buf)).transpose()?;
+            let memory_events = self.memory_events.as_mut().map(|c| c.measure(io_buf)).transpose()?;
             let cpu_stat = self.cpu_stat.as_mut().map(|c| c.measure(io_buf)).transpose()?;
+            let pids_current = self.pids_current.as_mut().map(|c| c.measure(io_buf)).transpose()?;
+            let io_stat = self.io_stat.as_mut().map(|c| c.measure(io_buf)).transpose()?;
+            let cpu_pressure = self.cpu_pressure.as_mut().map(|c| c.measure(io_buf)).transpose()?;
+            let memory_pressure = self.memory_pressure.as_mut().map(|c| c.measure(io_buf)).transpose()?;
+            let io_pressure = self.io_pressure.as_mut().map(|c| c.measure(io_buf)).transpose()?;
 
             Ok(V2Stats {
                 memory_current,
                 memory_stat,
+                memory_events,
                 cpu_stat,
+                pids_current,
+                io_stat,
+                cpu_pressure,
+                memory_pressure,
+                io_pressure,
             })
         }
     }
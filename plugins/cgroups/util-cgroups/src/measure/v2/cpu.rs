@@ -19,6 +19,9 @@ struct CpuStatMapping {
     usage: LineIndex,
     user: LineIndex,
     system: LineIndex,
+    nr_periods: LineIndex,
+    nr_throttled: LineIndex,
+    throttled: LineIndex,
 }
 
 #[derive(Debug, Serialize)]
@@ -26,6 +29,9 @@ pub struct CpuStatCollectorSettings {
     pub usage_usec: bool,
     pub user_usec: bool,
     pub system_usec: bool,
+    pub nr_periods: bool,
+    pub nr_throttled: bool,
+    pub throttled_usec: bool,
 }
 
 impl EnabledKeys for CpuStatCollectorSettings {}
@@ -36,6 +42,9 @@ impl Default for CpuStatCollectorSettings {
             usage_usec: true,
             user_usec: true,
             system_usec: true,
+            nr_periods: true,
+            nr_throttled: true,
+            throttled_usec: true,
         }
     }
 }
@@ -46,9 +55,17 @@ pub struct CpuStats {
     pub usage: Option<u64>,
     pub user: Option<u64>,
     pub system: Option<u64>,
-    // could be extended to manage other measurements
+    /// Number of enforcement periods of the CPU bandwidth limit (`cpu.max`) that have elapsed.
+    pub nr_periods: Option<u64>,
+    /// Number of periods in which the cgroup has been throttled because it used its whole quota.
+    pub nr_throttled: Option<u64>,
+    /// Total time during which the cgroup has been throttled, in microseconds.
+    pub throttled: Option<u64>,
 }
 
+/// Keys that only exist when the `cpu` controller is enabled.
+const THROTTLING_KEYS: [&str; 3] = ["nr_periods", "nr_throttled", "throttled_usec"];
+
 pub type CollectorCreationError = super::memory::CollectorCreationError;
 
 impl CpuStatCollector {
@@ -76,12 +93,37 @@ impl CpuStatCollector {
         if let Some(i) = stat_mapping.line_index("system_usec") {
             mapping.system = i.into();
         }
+        if let Some(i) = stat_mapping.line_index("nr_periods") {
+            mapping.nr_periods = i.into();
+        }
+        if let Some(i) = stat_mapping.line_index("nr_throttled") {
+            mapping.nr_throttled = i.into();
+        }
+        if let Some(i) = stat_mapping.line_index("throttled_usec") {
+            mapping.throttled = i.into();
+        }
 
-        if !stat_mapping.keys_not_found().is_empty() {
+        // The throttling statistics are missing when the cpu controller is not enabled, which is common: don't flood the logs.
+        let (missing_throttling, missing_others): (Vec<&String>, Vec<&String>) = stat_mapping
+            .keys_not_found()
+            .iter()
+            .partition(|k| THROTTLING_KEYS.contains(&k.as_str()));
+        if !missing_others.is_empty() {
             log::warn!(
                 "keys not found in {}: {}",
                 path.display(),
-                stat_mapping.keys_not_found().join(", ")
+                missing_others.iter().map(|k| k.as_str()).collect::<Vec<_>>().join(", ")
+            )
+        }
+        if !missing_throttling.is_empty() {
+            log::debug!(
+                "keys not found in {} (is the cpu controller enabled?): {}",
+                path.display(),
+                missing_throttling
+                    .iter()
+                    .map(|k| k.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
             )
         }
 
@@ -102,6 +144,15 @@ impl CpuStatCollector {
                 i if i == self.mapping.system.0 => {
                     res.system = Some(v);
                 }
+                i if i == self.mapping.nr_periods.0 => {
+                    res.nr_periods = Some(v);
+                }
+                i if i == self.mapping.nr_throttled.0 => {
+                    res.nr_throttled = Some(v);
+                }
+                i if i == self.mapping.throttled.0 => {
+                    res.throttled = Some(v);
+                }
                 _ => (),
             })
         }?;
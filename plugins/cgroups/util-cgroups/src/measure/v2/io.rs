@@ -0,0 +1,244 @@
+use std::{
+    fs::File,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use rustc_hash::FxHashMap;
+
+use crate::measure::parse::read_fully;
+
+/// Directory that contains one entry `MAJOR:MINOR` per block device.
+pub const SYSFS_DEV_BLOCK_DIR: &str = "/sys/dev/block";
+
+/// Collects measurements from the `io.stat` file.
+///
+/// See <https://docs.kernel.org/admin-guide/cgroup-v2.html#io-interface-files>.
+pub struct IoStatCollector {
+    file: File,
+}
+
+/// The I/O statistics of a cgroup on a block device: a line of `io.stat`.
+///
+/// The keys that are missing from the line are set to zero, for instance
+/// `dbytes` and `dios` on kernels that don't report the discards.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DeviceIoStats {
+    /// Major number of the block device.
+    pub major: u32,
+    /// Minor number of the block device.
+    pub minor: u32,
+    /// Number of bytes read.
+    pub rbytes: u64,
+    /// Number of bytes written.
+    pub wbytes: u64,
+    /// Number of read operations.
+    pub rios: u64,
+    /// Number of write operations.
+    pub wios: u64,
+    /// Number of bytes discarded.
+    pub dbytes: u64,
+    /// Number of discard operations.
+    pub dios: u64,
+}
+
+impl IoStatCollector {
+    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        Ok(Self { file })
+    }
+
+    /// Collects measurements from the underlying "file", using `io_buf` as an intermediary I/O buffer.
+    pub fn measure(&mut self, io_buf: &mut Vec<u8>) -> io::Result<Vec<DeviceIoStats>> {
+        read_fully(&mut self.file, io_buf)?;
+        let content = std::str::from_utf8(io_buf).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        parse_io_stat(content)
+    }
+}
+
+/// Parses the content of the `io.stat` file.
+///
+/// # Input format
+/// ```text
+/// 8:16 rbytes=1459200 wbytes=314773504 rios=192 wios=353 dbytes=0 dios=0
+/// 8:0 rbytes=90430464 wbytes=299008000 rios=8950 wios=1252 dbytes=50331648 dios=3021
+/// ```
+///
+/// Some controllers add other keys to the lines (like `cost.usage` for `io.cost`), they are ignored.
+pub fn parse_io_stat(content: &str) -> io::Result<Vec<DeviceIoStats>> {
+    fn invalid() -> io::Error {
+        io::Error::from(ErrorKind::InvalidData)
+    }
+
+    let mut res = Vec::new();
+    for line in content.lines() {
+        let mut fields = line.split_ascii_whitespace();
+        let Some(device) = fields.next() else {
+            continue;
+        };
+        let (major, minor) = device.split_once(':').ok_or_else(invalid)?;
+        let mut stats = DeviceIoStats {
+            major: major.parse().map_err(|_| invalid())?,
+            minor: minor.parse().map_err(|_| invalid())?,
+            ..Default::default()
+        };
+        for field in fields {
+            let (key, value) = field.split_once('=').ok_or_else(invalid)?;
+            let counter = match key {
+                "rbytes" => &mut stats.rbytes,
+                "wbytes" => &mut stats.wbytes,
+                "rios" => &mut stats.rios,
+                "wios" => &mut stats.wios,
+                "dbytes" => &mut stats.dbytes,
+                "dios" => &mut stats.dios,
+                _ => continue,
+            };
+            *counter = value.parse().map_err(|_| invalid())?;
+        }
+        res.push(stats);
+    }
+    Ok(res)
+}
+
+/// Finds the names of the block devices from their major and minor numbers, and caches them.
+pub struct BlockDeviceNames {
+    sysfs_dev_block: PathBuf,
+    cache: FxHashMap<(u32, u32), String>,
+}
+
+impl BlockDeviceNames {
+    /// Looks for the devices in `/sys/dev/block`.
+    pub fn new() -> Self {
+        Self::with_sysfs_dir(SYSFS_DEV_BLOCK_DIR)
+    }
+
+    /// Looks for the devices in the given directory instead of `/sys/dev/block`.
+    pub fn with_sysfs_dir(sysfs_dev_block: impl Into<PathBuf>) -> Self {
+        Self {
+            sysfs_dev_block: sysfs_dev_block.into(),
+            cache: FxHashMap::default(),
+        }
+    }
+
+    /// Returns the kernel name of the device, like `sda` or `nvme0n1`.
+    ///
+    /// If the name cannot be found, returns `MAJOR:MINOR`.
+    pub fn get(&mut self, major: u32, minor: u32) -> &str {
+        let dir = &self.sysfs_dev_block;
+        self.cache.entry((major, minor)).or_insert_with(|| {
+            let uevent = dir.join(format!("{major}:{minor}")).join("uevent");
+            match std::fs::read_to_string(&uevent) {
+                Ok(content) => match parse_devname(&content) {
+                    Some(name) => name.to_owned(),
+                    None => {
+                        log::warn!("no DEVNAME in {}", uevent.display());
+                        format!("{major}:{minor}")
+                    }
+                },
+                Err(e) => {
+                    log::warn!("could not read {}: {e}", uevent.display());
+                    format!("{major}:{minor}")
+                }
+            }
+        })
+    }
+}
+
+impl Default for BlockDeviceNames {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Extracts the value of `DEVNAME` from the content of a `uevent` file.
+fn parse_devname(uevent: &str) -> Option<&str> {
+    uevent.lines().find_map(|line| line.strip_prefix("DEVNAME="))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parse() {
+        const IO_STAT: &str = "8:16 rbytes=1459200 wbytes=314773504 rios=192 wios=353 dbytes=0 dios=0
+8:0 rbytes=90430464 wbytes=299008000 rios=8950 wios=1252 dbytes=50331648 dios=3021
+";
+        let stats = parse_io_stat(IO_STAT).unwrap();
+        assert_eq!(
+            stats,
+            vec![
+                DeviceIoStats {
+                    major: 8,
+                    minor: 16,
+                    rbytes: 1459200,
+                    wbytes: 314773504,
+                    rios: 192,
+                    wios: 353,
+                    dbytes: 0,
+                    dios: 0,
+                },
+                DeviceIoStats {
+                    major: 8,
+                    minor: 0,
+                    rbytes: 90430464,
+                    wbytes: 299008000,
+                    rios: 8950,
+                    wios: 1252,
+                    dbytes: 50331648,
+                    dios: 3021,
+                },
+            ]
+        );
+
+        // old kernels don't have the discards, io.cost adds other keys
+        let stats = parse_io_stat("259:0 rbytes=10 wbytes=20 rios=1 wios=2 cost.vrate=100.00 cost.usage=5\n").unwrap();
+        assert_eq!(
+            stats,
+            vec![DeviceIoStats {
+                major: 259,
+                minor: 0,
+                rbytes: 10,
+                wbytes: 20,
+                rios: 1,
+                wios: 2,
+                dbytes: 0,
+                dios: 0,
+            }]
+        );
+
+        // the file is empty when the cgroup has not done any I/O
+        assert_eq!(parse_io_stat("").unwrap(), vec![]);
+    }
+
+    #[test]
+    fn parse_invalid() {
+        let err = parse_io_stat("8:0 rbytes=abc wbytes=0\n").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        let err = parse_io_stat("sda rbytes=0 wbytes=0\n").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        let err = parse_io_stat("8:0 rbytes\n").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn device_names() -> anyhow::Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let dev = tmp_dir.path().join("259:0");
+        std::fs::create_dir(&dev)?;
+        std::fs::write(
+            dev.join("uevent"),
+            "MAJOR=259\nMINOR=0\nDEVNAME=nvme0n1\nDEVTYPE=disk\n",
+        )?;
+
+        let mut names = BlockDeviceNames::with_sysfs_dir(tmp_dir.path());
+        assert_eq!(names.get(259, 0), "nvme0n1");
+        // unknown device
+        assert_eq!(names.get(8, 0), "8:0");
+        // the names are cached
+        std::fs::remove_dir_all(&dev)?;
+        assert_eq!(names.get(259, 0), "nvme0n1");
+        Ok(())
+    }
+}
@@ -26,6 +26,12 @@ pub struct MemoryStatCollector {
     mapping: MemoryStatMapping,
 }
 
+/// Collects measurements from `memory.events`.
+pub struct MemoryEventsCollector {
+    stat_file: SelectiveStatFile,
+    mapping: MemoryEventsMapping,
+}
+
 /// Represents the measurements extracted from the `memory.stat` file.
 #[derive(Default)]
 pub struct MemoryStats {
@@ -33,7 +39,23 @@ pub struct MemoryStats {
     pub file: Option<u64>,
     pub kernel_stack: Option<u64>,
     pub page_tables: Option<u64>,
-    // could be extended to manage other memory.stat measurements
+    /// Values of the [additional keys](MemoryStatCollectorSettings::additional_keys), in the same order.
+    pub additional: Vec<Option<u64>>,
+}
+
+/// Represents the measurements extracted from the `memory.events` file.
+///
+/// The values are counters: they only increase during the lifetime of the cgroup.
+#[derive(Debug, Default)]
+pub struct MemoryEvents {
+    /// Number of times the cgroup was throttled and reclaimed because it exceeded `memory.high`.
+    pub high: Option<u64>,
+    /// Number of times the cgroup reached `memory.max`.
+    pub max: Option<u64>,
+    /// Number of times the cgroup reached its limit and failed to allocate memory.
+    pub oom: Option<u64>,
+    /// Number of processes of the cgroup killed by the OOM killer.
+    pub oom_kill: Option<u64>,
 }
 
 #[derive(Default)]
@@ -42,6 +64,15 @@ struct MemoryStatMapping {
     file: LineIndex,
     kernel_stack: LineIndex,
     page_tables: LineIndex,
+    additional: Vec<LineIndex>,
+}
+
+#[derive(Default)]
+struct MemoryEventsMapping {
+    high: LineIndex,
+    max: LineIndex,
+    oom: LineIndex,
+    oom_kill: LineIndex,
 }
 
 #[derive(Debug, Serialize)]
@@ -51,6 +82,9 @@ pub struct MemoryStatCollectorSettings {
     pub kernel_stack: bool,
     #[serde(rename = "pagetables")]
     pub page_tables: bool,
+    /// Other keys of `memory.stat` to collect, like `shmem` or `pgmajfault`.
+    #[serde(skip)]
+    pub additional_keys: Vec<String>,
 }
 
 impl EnabledKeys for MemoryStatCollectorSettings {}
@@ -62,6 +96,7 @@ impl Default for MemoryStatCollectorSettings {
             file: true,
             kernel_stack: true,
             page_tables: true,
+            additional_keys: Vec::new(),
         }
     }
 }
@@ -96,7 +131,13 @@ impl MemoryStatCollector {
         let path = path.as_ref();
         let file = File::open(path).map_err(|e| CollectorCreationError::Io(e, path.into()))?;
 
-        let keys = settings.enabled_keys()?;
+        let mut keys = settings.enabled_keys()?;
+        for key in &settings.additional_keys {
+            // the same key cannot be looked for twice
+            if !keys.contains(key) {
+                keys.push(key.clone());
+            }
+        }
         let (stat_file, stat_mapping) = StatFileBuilder::new(file, &keys)
             .build(io_buf.as_mut())
             .map_err(|e| CollectorCreationError::Io(e, path.into()))?;
@@ -114,6 +155,11 @@ impl MemoryStatCollector {
         if let Some(i) = stat_mapping.line_index("pagetables") {
             mapping.page_tables = i.into();
         }
+        mapping.additional = settings
+            .additional_keys
+            .iter()
+            .map(|k| stat_mapping.line_index(k).map(LineIndex::from).unwrap_or_default())
+            .collect();
 
         if !stat_mapping.keys_not_found().is_empty() {
             log::warn!(
@@ -128,20 +174,90 @@ impl MemoryStatCollector {
 
     /// Collects measurements from the underlying "file", using `io_buf` as an intermediary I/O buffer.
     pub fn measure(&mut self, io_buf: &mut Vec<u8>) -> io::Result<MemoryStats> {
-        let mut res = MemoryStats::default();
+        let mut res = MemoryStats {
+            additional: vec![None; self.mapping.additional.len()],
+            ..Default::default()
+        };
+        unsafe {
+            self.stat_file.read(io_buf, |i, _k, v| {
+                // an additional key can also be one of the keys above
+                for (j, index) in self.mapping.additional.iter().enumerate() {
+                    if index.0 == i {
+                        res.additional[j] = Some(v);
+                    }
+                }
+                match i {
+                    i if i == self.mapping.anon.0 => {
+                        res.anon = Some(v);
+                    }
+                    i if i == self.mapping.file.0 => {
+                        res.file = Some(v);
+                    }
+                    i if i == self.mapping.kernel_stack.0 => {
+                        res.kernel_stack = Some(v);
+                    }
+                    i if i == self.mapping.page_tables.0 => {
+                        res.page_tables = Some(v);
+                    }
+                    _ => (),
+                }
+            })
+        }?;
+        Ok(res)
+    }
+}
+
+impl MemoryEventsCollector {
+    pub fn new<P: AsRef<Path>>(path: P, io_buf: &mut Vec<u8>) -> Result<Self, CollectorCreationError> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| CollectorCreationError::Io(e, path.into()))?;
+
+        let keys = ["high", "max", "oom", "oom_kill"];
+        let (stat_file, stat_mapping) = StatFileBuilder::new(file, &keys)
+            .build(io_buf.as_mut())
+            .map_err(|e| CollectorCreationError::Io(e, path.into()))?;
+
+        let mut mapping = MemoryEventsMapping::default();
+        if let Some(i) = stat_mapping.line_index("high") {
+            mapping.high = i.into();
+        }
+        if let Some(i) = stat_mapping.line_index("max") {
+            mapping.max = i.into();
+        }
+        if let Some(i) = stat_mapping.line_index("oom") {
+            mapping.oom = i.into();
+        }
+        if let Some(i) = stat_mapping.line_index("oom_kill") {
+            mapping.oom_kill = i.into();
+        }
+
+        if !stat_mapping.keys_not_found().is_empty() {
+            log::warn!(
+                "keys not found in {}: {}",
+                path.display(),
+                stat_mapping.keys_not_found().join(", ")
+            )
+        }
+
+        Ok(Self { stat_file, mapping })
+    }
+
+    /// Collects measurements from the underlying "file", using `io_buf` as an intermediary I/O buffer.
+    pub fn measure(&mut self, io_buf: &mut Vec<u8>) -> io::Result<MemoryEvents> {
+        let mut res = MemoryEvents::default();
         unsafe {
             self.stat_file.read(io_buf, |i, _k, v| match i {
-                i if i == self.mapping.anon.0 => {
-                    res.anon = Some(v);
+                i if i == self.mapping.high.0 => {
+                    res.high = Some(v);
                 }
-                i if i == self.mapping.file.0 => {
-                    res.file = Some(v);
+                i if i == self.mapping.max.0 => {
+                    res.max = Some(v);
                 }
-                i if i == self.mapping.kernel_stack.0 => {
-                    res.kernel_stack = Some(v);
+                i if i == self.mapping.oom.0 => {
+                    res.oom = Some(v);
                 }
-                i if i == self.mapping.page_tables.0 => {
-                    res.page_tables = Some(v);
+                i if i == self.mapping.oom_kill.0 => {
+                    res.oom_kill = Some(v);
                 }
                 _ => (),
             })
@@ -155,7 +271,7 @@ mod tests {
     use std::io::ErrorKind;
 
     use crate::measure::v2::{
-        memory::{MemoryCurrentCollector, MemoryStatCollector, MemoryStatCollectorSettings},
+        memory::{MemoryCurrentCollector, MemoryEventsCollector, MemoryStatCollector, MemoryStatCollectorSettings},
         mock::{MemoryStatMock, MockFileCgroupKV},
     };
 
@@ -180,6 +296,7 @@ mod tests {
                 file: false,
                 kernel_stack: true,
                 page_tables: true,
+                additional_keys: Vec::new(),
             },
             io_buf.as_mut(),
         )?;
@@ -192,6 +309,65 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn collect_memory_stat_additional_keys() -> anyhow::Result<()> {
+        let mut tmp = tempfile::NamedTempFile::new()?;
+
+        let mock = MemoryStatMock {
+            anon: 63,
+            shmem: 7,
+            pgmajfault: 1000,
+            ..Default::default()
+        };
+        mock.write_to_file(tmp.as_file_mut())?;
+
+        let mut io_buf = Vec::new();
+        let mut collector = MemoryStatCollector::new(
+            tmp.path(),
+            MemoryStatCollectorSettings {
+                additional_keys: vec![
+                    String::from("pgmajfault"),
+                    String::from("anon"),
+                    String::from("does_not_exist"),
+                    String::from("shmem"),
+                ],
+                ..Default::default()
+            },
+            io_buf.as_mut(),
+        )?;
+
+        let memory_stats = collector.measure(io_buf.as_mut())?;
+        assert_eq!(memory_stats.anon, Some(63));
+        assert_eq!(memory_stats.additional, vec![Some(1000), Some(63), None, Some(7)]);
+        Ok(())
+    }
+
+    #[test]
+    fn collect_memory_events() -> anyhow::Result<()> {
+        let tmp = tempfile::NamedTempFile::new()?;
+        std::fs::write(
+            tmp.path(),
+            "low 0\nhigh 12\nmax 3\noom 1\noom_kill 1\noom_group_kill 0\n",
+        )?;
+
+        let mut io_buf = Vec::new();
+        let mut collector = MemoryEventsCollector::new(tmp.path(), io_buf.as_mut())?;
+        let events = collector.measure(io_buf.as_mut())?;
+        assert_eq!(events.high, Some(12));
+        assert_eq!(events.max, Some(3));
+        assert_eq!(events.oom, Some(1));
+        assert_eq!(events.oom_kill, Some(1));
+
+        std::fs::write(
+            tmp.path(),
+            "low 0\nhigh 15\nmax 3\noom 2\noom_kill 2\noom_group_kill 0\n",
+        )?;
+        let events = collector.measure(io_buf.as_mut())?;
+        assert_eq!(events.high, Some(15));
+        assert_eq!(events.oom_kill, Some(2));
+        Ok(())
+    }
+
     #[test]
     fn collect_memory_stat_empty() -> anyhow::Result<()> {
         let tmp = tempfile::NamedTempFile::new()?;
@@ -206,6 +382,7 @@ mod tests {
                 file: true,
                 kernel_stack: true,
                 page_tables: true,
+                additional_keys: Vec::new(),
             },
             io_buf.as_mut(),
         )?;
@@ -0,0 +1,152 @@
+use std::{
+    fs::File,
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+use crate::measure::parse::read_fully;
+
+/// Collects measurements from a PSI file: `cpu.pressure`, `memory.pressure` or `io.pressure`.
+///
+/// See <https://docs.kernel.org/accounting/psi.html>.
+pub struct PressureCollector {
+    file: File,
+}
+
+/// Represents the measurements extracted from a PSI file.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PressureStats {
+    /// Share of time in which at least some tasks were stalled on the resource.
+    pub some: Option<PressureRecord>,
+    /// Share of time in which all non-idle tasks were stalled on the resource simultaneously.
+    /// Not available for the CPU of the root cgroup on old kernels.
+    pub full: Option<PressureRecord>,
+}
+
+/// A line of a PSI file.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PressureRecord {
+    /// Average percentage of stall time over the last 10 seconds.
+    pub avg10: f64,
+    /// Average percentage of stall time over the last 60 seconds.
+    pub avg60: f64,
+    /// Average percentage of stall time over the last 300 seconds.
+    pub avg300: f64,
+    /// Total stall time, in microseconds.
+    pub total: u64,
+}
+
+impl PressureCollector {
+    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        Ok(Self { file })
+    }
+
+    /// Collects measurements from the underlying "file", using `io_buf` as an intermediary I/O buffer.
+    pub fn measure(&mut self, io_buf: &mut Vec<u8>) -> io::Result<PressureStats> {
+        read_fully(&mut self.file, io_buf)?;
+        let content = std::str::from_utf8(io_buf).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        parse_pressure(content)
+    }
+}
+
+/// Parses the content of a PSI file.
+///
+/// # Input format
+/// ```text
+/// some avg10=0.00 avg60=0.00 avg300=0.00 total=0
+/// full avg10=0.00 avg60=0.00 avg300=0.00 total=0
+/// ```
+pub fn parse_pressure(content: &str) -> io::Result<PressureStats> {
+    fn invalid() -> io::Error {
+        io::Error::from(ErrorKind::InvalidData)
+    }
+
+    let mut res = PressureStats::default();
+    for line in content.lines() {
+        let mut fields = line.split_ascii_whitespace();
+        let Some(kind) = fields.next() else {
+            continue;
+        };
+        let mut record = PressureRecord::default();
+        for field in fields {
+            let (key, value) = field.split_once('=').ok_or_else(invalid)?;
+            match key {
+                "avg10" => record.avg10 = value.parse().map_err(|_| invalid())?,
+                "avg60" => record.avg60 = value.parse().map_err(|_| invalid())?,
+                "avg300" => record.avg300 = value.parse().map_err(|_| invalid())?,
+                "total" => record.total = value.parse().map_err(|_| invalid())?,
+                _ => (),
+            }
+        }
+        match kind {
+            "some" => res.some = Some(record),
+            "full" => res.full = Some(record),
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parse() {
+        const MEMORY_PRESSURE: &str = "some avg10=1.53 avg60=0.87 avg300=0.22 total=58761459
+full avg10=0.00 avg60=0.13 avg300=0.05 total=29612012
+";
+        let stats = parse_pressure(MEMORY_PRESSURE).unwrap();
+        assert_eq!(
+            stats,
+            PressureStats {
+                some: Some(PressureRecord {
+                    avg10: 1.53,
+                    avg60: 0.87,
+                    avg300: 0.22,
+                    total: 58761459
+                }),
+                full: Some(PressureRecord {
+                    avg10: 0.0,
+                    avg60: 0.13,
+                    avg300: 0.05,
+                    total: 29612012
+                }),
+            }
+        );
+
+        // old kernels only have the "some" line for the CPU
+        let stats = parse_pressure("some avg10=0.00 avg60=0.00 avg300=0.00 total=0\n").unwrap();
+        assert_eq!(stats.some, Some(PressureRecord::default()));
+        assert_eq!(stats.full, None);
+    }
+
+    #[test]
+    fn parse_invalid() {
+        let err = parse_pressure("some avg10=abc avg60=0.00 avg300=0.00 total=0\n").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        let err = parse_pressure("other avg10=0.00 avg60=0.00 avg300=0.00 total=0\n").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        let err = parse_pressure("some avg10\n").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn measure() -> anyhow::Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let path = tmp_dir.path().join("io.pressure");
+        std::fs::write(&path, "some avg10=0.00 avg60=0.00 avg300=0.00 total=10\n")?;
+
+        let mut collector = PressureCollector::new(&path)?;
+        let mut io_buf = Vec::new();
+        assert_eq!(collector.measure(&mut io_buf)?.some.unwrap().total, 10);
+
+        std::fs::write(&path, "some avg10=2.00 avg60=1.00 avg300=0.50 total=25\n")?;
+        let stats = collector.measure(&mut io_buf)?;
+        assert_eq!(stats.some.unwrap().total, 25);
+        assert_eq!(stats.some.unwrap().avg10, 2.0);
+        Ok(())
+    }
+}
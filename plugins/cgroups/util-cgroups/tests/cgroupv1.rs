@@ -382,3 +382,47 @@ fn creation_of_cgroup_before_and_after_v1() {
     thread::sleep(tools::WAITING);
     assert_eq!(4, cpt.load(Ordering::SeqCst));
 }
+
+#[test]
+fn short_cgroup_detected_without_rescan_v1() {
+    let root_unwraped = tempdir().unwrap();
+    let root = root_unwraped.path().to_path_buf();
+    let version = CgroupVersion::V1;
+    let path_cpu = root.join("cpuacct");
+    let hierarchy_cpu = CgroupHierarchy::manually_unchecked(path_cpu.clone(), version, vec!["cpuacct"]);
+    assert!(tools::create_folder(&root, "cpuacct", hierarchy_cpu.clone()).is_ok());
+
+    let created: Arc<AtomicU8> = Arc::new(AtomicU8::new(0));
+    let removed: Arc<AtomicU8> = Arc::new(AtomicU8::new(0));
+    let ac_created = Arc::clone(&created);
+    let ac_removed = Arc::clone(&removed);
+    // The rescan never happens during the test: the cgroups must be detected with inotify.
+    let config = Config {
+        v1_refresh_interval: Duration::from_secs(3600),
+        force_polling: false,
+        add_source_in_pause_state: false,
+    };
+    let handler = ClosureCallbacks {
+        on_cgroups_created: callback(move |cgroups| {
+            ac_created.fetch_add(cgroups.len() as u8, Ordering::SeqCst);
+            Ok(())
+        }),
+        on_cgroups_removed: callback(move |cgroups| {
+            ac_removed.fetch_add(cgroups.len() as u8, Ordering::SeqCst);
+            Ok(())
+        }),
+    };
+    let detector = CgroupDetector::new(hierarchy_cpu.clone(), config, handler);
+    assert!(detector.is_ok());
+    // the root cgroup
+    assert_eq!(1, created.load(Ordering::SeqCst));
+
+    // a job that ends quickly
+    let job = tools::create_folder(&path_cpu, "Bill_Potts", hierarchy_cpu.clone()).unwrap();
+    thread::sleep(Duration::from_millis(500));
+    fs::remove_dir_all(&job).unwrap();
+
+    thread::sleep(tools::WAITING);
+    assert_eq!(2, created.load(Ordering::SeqCst));
+    assert_eq!(1, removed.load(Ordering::SeqCst));
+}
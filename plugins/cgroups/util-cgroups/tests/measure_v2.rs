@@ -58,6 +58,9 @@ pub fn test_new_and_measure() -> anyhow::Result<()> {
     assert_eq!(cpu_stat.system.unwrap_or(0), 456);
     assert_eq!(cpu_stat.user.unwrap_or(0), 123);
     assert_eq!(cpu_stat.usage.unwrap_or(0), 579);
+    assert_eq!(cpu_stat.nr_periods, Some(1));
+    assert_eq!(cpu_stat.nr_throttled, Some(2));
+    assert_eq!(cpu_stat.throttled, Some(3));
 
     assert_eq!(mem_stat.anon.unwrap_or(0), 321);
     assert_eq!(mem_stat.file.unwrap_or(0), 654);
@@ -139,6 +142,168 @@ pub fn test_new_files_dont_exist() -> anyhow::Result<()> {
     assert!(v2stat.cpu_stat.is_none());
     assert!(v2stat.memory_stat.is_none());
     assert!(v2stat.memory_current.is_none());
+    assert!(v2stat.memory_events.is_none());
+    assert!(v2stat.pids_current.is_none());
+    assert!(v2stat.io_stat.is_none());
+    assert!(v2stat.cpu_pressure.is_none());
+    assert!(v2stat.memory_pressure.is_none());
+    assert!(v2stat.io_pressure.is_none());
+    Ok(())
+}
+
+#[test]
+pub fn test_new_and_measure_pressure() -> anyhow::Result<()> {
+    let root = tempdir().expect("Failed to create a temporary directory");
+    std::fs::write(
+        root.path().join("cpu.pressure"),
+        "some avg10=12.50 avg60=6.00 avg300=1.25 total=4000\n\
+        full avg10=0.00 avg60=0.00 avg300=0.00 total=0\n",
+    )?;
+    std::fs::write(
+        root.path().join("memory.pressure"),
+        "some avg10=0.10 avg60=0.20 avg300=0.30 total=500\n\
+        full avg10=0.05 avg60=0.10 avg300=0.15 total=250\n",
+    )?;
+    // io.pressure does not exist
+
+    let hierarchy = CgroupHierarchy::manually_unchecked(root.path(), CgroupVersion::V2, vec!["cpu", "memory"]);
+    let cgroup = Cgroup::from_fs_path(&hierarchy, root.path().to_path_buf());
+
+    let mut io_buf = Vec::new();
+    let mut collector = V2Collector::new(
+        cgroup,
+        MemoryStatCollectorSettings::default(),
+        CpuStatCollectorSettings::default(),
+        &mut io_buf,
+    )?;
+    let v2stat = collector.measure(&mut io_buf)?;
+    assert!(v2stat.io_pressure.is_none());
+
+    let cpu = v2stat.cpu_pressure.expect("cpu.pressure should be measured");
+    let cpu_some = cpu.some.unwrap();
+    assert_eq!(cpu_some.avg10, 12.5);
+    assert_eq!(cpu_some.avg60, 6.0);
+    assert_eq!(cpu_some.avg300, 1.25);
+    assert_eq!(cpu_some.total, 4000);
+    assert_eq!(cpu.full.unwrap().total, 0);
+
+    let memory = v2stat.memory_pressure.expect("memory.pressure should be measured");
+    assert_eq!(memory.some.unwrap().total, 500);
+    assert_eq!(memory.full.unwrap().avg300, 0.15);
+    assert_eq!(memory.full.unwrap().total, 250);
+    Ok(())
+}
+
+#[test]
+pub fn test_new_and_measure_events_and_pids() -> anyhow::Result<()> {
+    let root = tempdir().expect("Failed to create a temporary directory");
+    std::fs::write(
+        root.path().join("memory.events"),
+        "low 0\n\
+        high 4\n\
+        max 2\n\
+        oom 1\n\
+        oom_kill 1\n\
+        oom_group_kill 0\n",
+    )?;
+    std::fs::write(root.path().join("pids.current"), "17\n")?;
+
+    let hierarchy = CgroupHierarchy::manually_unchecked(root.path(), CgroupVersion::V2, vec!["memory", "pids"]);
+    let cgroup = Cgroup::from_fs_path(&hierarchy, root.path().to_path_buf());
+
+    let mut io_buf = Vec::new();
+    let mut collector = V2Collector::new(
+        cgroup,
+        MemoryStatCollectorSettings::default(),
+        CpuStatCollectorSettings::default(),
+        &mut io_buf,
+    )?;
+    let v2stat = collector.measure(&mut io_buf)?;
+    assert_eq!(v2stat.pids_current, Some(17));
+    let events = v2stat.memory_events.expect("memory.events should be measured");
+    assert_eq!(events.high, Some(4));
+    assert_eq!(events.max, Some(2));
+    assert_eq!(events.oom, Some(1));
+    assert_eq!(events.oom_kill, Some(1));
+    Ok(())
+}
+
+#[test]
+pub fn test_new_and_measure_memory_stat_additional_keys() -> anyhow::Result<()> {
+    let root = tempdir().expect("Failed to create a temporary directory");
+    std::fs::write(
+        root.path().join("memory.stat"),
+        "anon 321\n\
+        file 654\n\
+        kernel_stack 987\n\
+        pagetables 741\n\
+        shmem 12\n\
+        pgfault 5000\n",
+    )?;
+
+    let hierarchy = CgroupHierarchy::manually_unchecked(root.path(), CgroupVersion::V2, vec!["memory"]);
+    let cgroup = Cgroup::from_fs_path(&hierarchy, root.path().to_path_buf());
+
+    let mut io_buf = Vec::new();
+    let memory_settings = MemoryStatCollectorSettings {
+        additional_keys: vec![String::from("shmem"), String::from("pgfault")],
+        ..Default::default()
+    };
+    let mut collector = V2Collector::new(
+        cgroup,
+        memory_settings,
+        CpuStatCollectorSettings::default(),
+        &mut io_buf,
+    )?;
+    let mem_stat = collector.measure(&mut io_buf)?.memory_stat.unwrap();
+    assert_eq!(mem_stat.anon, Some(321));
+    assert_eq!(mem_stat.additional, vec![Some(12), Some(5000)]);
+    Ok(())
+}
+
+#[test]
+pub fn test_new_and_measure_io_stat() -> anyhow::Result<()> {
+    let root = tempdir().expect("Failed to create a temporary directory");
+    let io_stat_path = root.path().join("io.stat");
+    std::fs::write(
+        &io_stat_path,
+        "8:0 rbytes=4096 wbytes=8192 rios=1 wios=2 dbytes=0 dios=0\n\
+        259:0 rbytes=1048576 wbytes=0 rios=256 wios=0 dbytes=0 dios=0\n",
+    )?;
+
+    let hierarchy = CgroupHierarchy::manually_unchecked(root.path(), CgroupVersion::V2, vec!["cpu", "memory", "io"]);
+    let cgroup = Cgroup::from_fs_path(&hierarchy, root.path().to_path_buf());
+
+    let mut io_buf = Vec::new();
+    let mut collector = V2Collector::new(
+        cgroup,
+        MemoryStatCollectorSettings::default(),
+        CpuStatCollectorSettings::default(),
+        &mut io_buf,
+    )?;
+    let io_stat = collector
+        .measure(&mut io_buf)?
+        .io_stat
+        .expect("io.stat should be measured");
+    assert_eq!(io_stat.len(), 2);
+    assert_eq!((io_stat[0].major, io_stat[0].minor), (8, 0));
+    assert_eq!(io_stat[0].rbytes, 4096);
+    assert_eq!(io_stat[0].wbytes, 8192);
+    assert_eq!(io_stat[0].wios, 2);
+    assert_eq!((io_stat[1].major, io_stat[1].minor), (259, 0));
+    assert_eq!(io_stat[1].rios, 256);
+
+    // a new device appears
+    std::fs::write(
+        &io_stat_path,
+        "8:0 rbytes=4096 wbytes=16384 rios=1 wios=4 dbytes=0 dios=0\n\
+        8:16 rbytes=512 wbytes=0 rios=1 wios=0 dbytes=0 dios=0\n\
+        259:0 rbytes=1048576 wbytes=0 rios=256 wios=0 dbytes=0 dios=0\n",
+    )?;
+    let io_stat = collector.measure(&mut io_buf)?.io_stat.unwrap();
+    assert_eq!(io_stat.len(), 3);
+    assert_eq!(io_stat[0].wbytes, 16384);
+    assert_eq!((io_stat[1].major, io_stat[1].minor), (8, 16));
     Ok(())
 }
 
@@ -186,6 +351,8 @@ pub fn test_new_missing_lines() -> anyhow::Result<()> {
     assert!(cpu_stat.system.is_none());
     assert!(cpu_stat.user.is_none());
     assert_eq!(cpu_stat.usage.unwrap_or(0), 579);
+    assert!(cpu_stat.nr_periods.is_none());
+    assert!(cpu_stat.throttled.is_none());
 
     assert!(mem_stat.anon.is_none());
     assert!(mem_stat.file.is_none());
@@ -0,0 +1,309 @@
+use std::{path::Path, time::Duration};
+
+use alumet::{
+    pipeline::elements::source::trigger::TriggerSpec,
+    plugin::{
+        AlumetPluginStart, ConfigTable,
+        rust::{AlumetPlugin, deserialize_config, serialize_config},
+    },
+};
+use anyhow::{Context, anyhow};
+use serde::{Deserialize, Serialize};
+
+use source::{CpuMetrics, CpuSource};
+
+#[cfg(test)]
+use std::path::PathBuf;
+
+mod source;
+mod sysfs;
+
+pub struct CpufreqPlugin {
+    config: Config,
+}
+
+impl CpufreqPlugin {
+    #[cfg(not(test))]
+    fn sysfs_root(&self) -> &Path {
+        Path::new(sysfs::CPU_SYSFS_DIR)
+    }
+
+    #[cfg(test)]
+    fn sysfs_root(&self) -> &Path {
+        &self.config.sysfs_test_path
+    }
+}
+
+impl AlumetPlugin for CpufreqPlugin {
+    fn name() -> &'static str {
+        "cpufreq"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(CpufreqPlugin { config }))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let config = &self.config;
+        let root = self.sysfs_root();
+        let cpus =
+            sysfs::explore(root, config.frequency, config.idle_states).context("could not explore the CPU cores")?;
+        let cpuidle = sysfs::cpuidle_info(root);
+
+        let metrics = CpuMetrics::new(alumet)?;
+        let source = CpuSource::new(metrics, cpus, cpuidle);
+        let (n_freq, n_idle) = (source.n_freq_probes(), source.n_idle_probes());
+        if config.frequency && n_freq == 0 {
+            log::warn!("cpufreq is not available on this machine, the frequency of the cores will not be measured");
+        }
+        if config.idle_states && n_idle == 0 {
+            log::warn!("cpuidle is not available on this machine, the idle states of the cores will not be measured");
+        }
+        if n_freq == 0 && n_idle == 0 {
+            return Err(anyhow!(
+                "nothing to measure in {root:?}, check the configuration of the plugin"
+            ));
+        }
+        log::info!("Measuring the frequency of {n_freq} core(s) and {n_idle} idle state(s).");
+
+        let trigger = TriggerSpec::builder(config.poll_interval)
+            .flush_interval(config.flush_interval)
+            .build()?;
+        alumet.add_source("cpu", Box::new(source), trigger)?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Initial interval between two measurements.
+    #[serde(with = "humantime_serde")]
+    pub poll_interval: Duration,
+
+    /// Initial interval between two measurement flushes.
+    #[serde(with = "humantime_serde")]
+    pub flush_interval: Duration,
+
+    /// Measure the frequency of the cores (cpufreq).
+    pub frequency: bool,
+
+    /// Measure the time spent in each idle state (cpuidle).
+    pub idle_states: bool,
+
+    #[cfg(test)]
+    pub sysfs_test_path: PathBuf,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(1), // 1Hz
+            flush_interval: Duration::from_secs(5),
+            frequency: true,
+            idle_states: true,
+
+            #[cfg(test)]
+            sysfs_test_path: PathBuf::from(sysfs::CPU_SYSFS_DIR),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, time::Duration};
+
+    use alumet::{
+        agent::{
+            self,
+            plugin::{PluginInfo, PluginSet},
+        },
+        measurement::{MeasurementPoint, WrappedMeasurementValue},
+        pipeline::naming::SourceName,
+        plugin::PluginMetadata,
+        resources::Resource,
+        test::{RuntimeExpectations, StartupExpectations},
+        units::{PrefixedUnit, Unit},
+    };
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+
+    use crate::{Config, CpufreqPlugin};
+
+    fn config_to_toml_table(config: &Config) -> toml::Table {
+        toml::Value::try_from(config).unwrap().as_table().unwrap().clone()
+    }
+
+    fn write(root: &Path, path: &str, content: &str) {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    fn attr(p: &MeasurementPoint, key: &str) -> String {
+        p.attributes()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| v.to_string())
+            .unwrap_or_default()
+    }
+
+    /// Creates two cores with two idle states each.
+    fn create_mock_layout(root: &Path) {
+        for cpu in ["cpu0", "cpu1"] {
+            write(root, &format!("{cpu}/cpufreq/scaling_cur_freq"), "2400000\n");
+            write(root, &format!("{cpu}/cpufreq/scaling_governor"), "powersave\n");
+            write(root, &format!("{cpu}/cpufreq/scaling_driver"), "intel_pstate\n");
+            for (i, name) in ["POLL", "C6"].iter().enumerate() {
+                write(root, &format!("{cpu}/cpuidle/state{i}/name"), &format!("{name}\n"));
+                write(root, &format!("{cpu}/cpuidle/state{i}/time"), "1000\n");
+                write(root, &format!("{cpu}/cpuidle/state{i}/usage"), "10\n");
+            }
+        }
+        write(root, "cpuidle/current_driver", "intel_idle\n");
+        write(root, "cpuidle/current_governor_ro", "menu\n");
+    }
+
+    #[test]
+    fn startup() {
+        let tmp = tempdir().unwrap();
+        create_mock_layout(tmp.path());
+
+        let config = Config {
+            sysfs_test_path: tmp.path().to_owned(),
+            ..Default::default()
+        };
+        let mut plugins = PluginSet::new();
+        plugins.add_plugin(PluginInfo {
+            metadata: PluginMetadata::from_static::<CpufreqPlugin>(),
+            enabled: true,
+            config: Some(config_to_toml_table(&config)),
+        });
+
+        let startup_expectations = StartupExpectations::new()
+            .expect_metric::<u64>("cpu_frequency", PrefixedUnit::kilo(Unit::Hertz))
+            .expect_metric::<u64>("cpu_idle_residency", PrefixedUnit::micro(Unit::Second))
+            .expect_metric::<u64>("cpu_idle_entries", Unit::Unity)
+            .expect_source("cpufreq", "cpu");
+
+        let agent = agent::Builder::new(plugins)
+            .with_expectations(startup_expectations)
+            .build_and_start()
+            .unwrap();
+        agent.pipeline.control_handle().shutdown();
+        agent.wait_for_shutdown(Duration::from_secs(5)).unwrap();
+    }
+
+    #[test]
+    fn startup_without_cpufreq_nor_cpuidle() {
+        let tmp = tempdir().unwrap();
+        write(tmp.path(), "cpu0/online", "1\n");
+
+        let config = Config {
+            sysfs_test_path: tmp.path().to_owned(),
+            ..Default::default()
+        };
+        let mut plugins = PluginSet::new();
+        plugins.add_plugin(PluginInfo {
+            metadata: PluginMetadata::from_static::<CpufreqPlugin>(),
+            enabled: true,
+            config: Some(config_to_toml_table(&config)),
+        });
+        let agent = agent::Builder::new(plugins).build_and_start();
+        assert!(agent.is_err(), "the plugin should fail to start");
+    }
+
+    #[test]
+    fn runtime() {
+        let tmp = tempdir().unwrap();
+        let root = tmp.path().to_owned();
+        create_mock_layout(&root);
+
+        let config = Config {
+            poll_interval: Duration::from_secs(1),
+            flush_interval: Duration::from_secs(1),
+            sysfs_test_path: root.clone(),
+            ..Default::default()
+        };
+        let mut plugins = PluginSet::new();
+        plugins.add_plugin(PluginInfo {
+            metadata: PluginMetadata::from_static::<CpufreqPlugin>(),
+            enabled: true,
+            config: Some(config_to_toml_table(&config)),
+        });
+
+        let source = SourceName::from_str("cpufreq", "cpu");
+        let root2 = root.clone();
+        let runtime_expectations = RuntimeExpectations::new()
+            .test_source(
+                source.clone(),
+                || (),
+                |ctx| {
+                    // the idle states are counters: no measurement at the first poll, only the frequencies
+                    let freq = ctx.metrics().by_name("cpu_frequency").unwrap().0;
+                    let m = ctx.measurements();
+                    assert_eq!(m.len(), 2);
+                    for p in m.iter() {
+                        assert_eq!(p.metric, freq);
+                        assert_eq!(p.value, WrappedMeasurementValue::U64(2400000));
+                        assert_eq!(attr(p, "governor"), "powersave");
+                        assert_eq!(attr(p, "driver"), "intel_pstate");
+                    }
+                },
+            )
+            .test_source(
+                source,
+                move || {
+                    write(&root2, "cpu1/cpufreq/scaling_cur_freq", "800000\n");
+                    write(&root2, "cpu1/cpufreq/scaling_governor", "performance\n");
+                    write(&root2, "cpu1/cpuidle/state1/time", "6000\n");
+                    write(&root2, "cpu1/cpuidle/state1/usage", "12\n");
+                },
+                |ctx| {
+                    let freq = ctx.metrics().by_name("cpu_frequency").unwrap().0;
+                    let residency = ctx.metrics().by_name("cpu_idle_residency").unwrap().0;
+                    let entries = ctx.metrics().by_name("cpu_idle_entries").unwrap().0;
+                    let m = ctx.measurements();
+                    assert_eq!(m.len(), 2 + 2 * 2 * 2);
+
+                    let cpu1 = Resource::CpuCore { id: 1 };
+                    let cpu1_freq = m
+                        .iter()
+                        .find(|p| p.metric == freq && p.resource == cpu1)
+                        .expect("missing frequency of cpu1");
+                    assert_eq!(cpu1_freq.value, WrappedMeasurementValue::U64(800000));
+                    assert_eq!(attr(cpu1_freq, "governor"), "performance");
+
+                    for p in m.iter().filter(|p| p.metric == residency || p.metric == entries) {
+                        assert_eq!(attr(p, "driver"), "intel_idle");
+                        assert_eq!(attr(p, "governor"), "menu");
+                        let expected = match (p.resource == cpu1, attr(p, "state").as_str()) {
+                            (true, "C6") if p.metric == residency => 5000,
+                            (true, "C6") => 2,
+                            (_, "POLL" | "C6") => 0,
+                            (_, state) => panic!("unexpected state {state}"),
+                        };
+                        assert_eq!(p.value, WrappedMeasurementValue::U64(expected), "wrong value for {p:?}");
+                    }
+                },
+            );
+
+        let agent = agent::Builder::new(plugins)
+            .with_expectations(runtime_expectations)
+            .build_and_start()
+            .unwrap();
+        agent.wait_for_shutdown(Duration::from_secs(5)).unwrap();
+    }
+}
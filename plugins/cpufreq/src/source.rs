@@ -0,0 +1,237 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek},
+    path::PathBuf,
+};
+
+use alumet::{
+    measurement::{AttributeValue, MeasurementAccumulator, MeasurementPoint, Timestamp},
+    metrics::{TypedMetricId, error::MetricCreationError},
+    pipeline::{Source, elements::error::PollError},
+    plugin::AlumetPluginStart,
+    resources::{Resource, ResourceConsumer},
+    units::{PrefixedUnit, Unit},
+};
+
+use crate::sysfs::{Cpu, CpuIdle};
+
+pub struct CpuMetrics {
+    frequency: TypedMetricId<u64>,
+    idle_time: TypedMetricId<u64>,
+    idle_usage: TypedMetricId<u64>,
+}
+
+impl CpuMetrics {
+    pub fn new(alumet: &mut AlumetPluginStart) -> Result<Self, MetricCreationError> {
+        Ok(Self {
+            frequency: alumet.create_metric(
+                "cpu_frequency",
+                PrefixedUnit::kilo(Unit::Hertz),
+                "current frequency of the CPU core, according to cpufreq",
+            )?,
+            idle_time: alumet.create_metric(
+                "cpu_idle_residency",
+                PrefixedUnit::micro(Unit::Second),
+                "time spent in the idle state since the previous measurement",
+            )?,
+            idle_usage: alumet.create_metric(
+                "cpu_idle_entries",
+                Unit::Unity,
+                "number of times the idle state was entered since the previous measurement",
+            )?,
+        })
+    }
+}
+
+/// A sysfs file that is read at each poll.
+///
+/// The file is kept open between two reads, and reopened after an error:
+/// the files of a core disappear when it goes offline, and come back when it goes online.
+struct SysfsFile {
+    path: PathBuf,
+    file: Option<File>,
+    /// Did the last read fail? Used to avoid logging the same error at each poll.
+    failing: bool,
+}
+
+impl SysfsFile {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            file: None,
+            failing: false,
+        }
+    }
+
+    fn read_to<'b>(&mut self, buf: &'b mut String) -> io::Result<&'b str> {
+        buf.clear();
+        let mut file = match self.file.take() {
+            Some(file) => file,
+            None => File::open(&self.path)?,
+        };
+        file.rewind()?;
+        file.read_to_string(buf)?;
+        self.file = Some(file);
+        Ok(buf.trim_ascii())
+    }
+
+    /// Reads the content of the file, logs the errors.
+    fn read<'b>(&mut self, buf: &'b mut String) -> Option<&'b str> {
+        match self.read_to(buf) {
+            Ok(content) => {
+                if self.failing {
+                    log::info!("{:?} can be read again", self.path);
+                    self.failing = false;
+                }
+                Some(content)
+            }
+            Err(e) => {
+                self.file = None;
+                if !self.failing {
+                    log::warn!("failed to read {:?}: {e}", self.path);
+                    self.failing = true;
+                }
+                None
+            }
+        }
+    }
+
+    fn read_u64(&mut self, buf: &mut String) -> Option<u64> {
+        let content = self.read(buf)?;
+        match content.parse() {
+            Ok(value) => Some(value),
+            Err(e) => {
+                log::warn!("failed to parse {:?}: {e} (content: {content:?})", self.path);
+                None
+            }
+        }
+    }
+}
+
+/// Measures the frequency of a core.
+struct FreqProbe {
+    cpu: u32,
+    cur_freq: SysfsFile,
+    governor: SysfsFile,
+    driver: String,
+}
+
+/// Measures an idle state of a core.
+struct IdleProbe {
+    cpu: u32,
+    state: String,
+    time: SysfsFile,
+    usage: SysfsFile,
+    /// Previous values of the time and usage counters.
+    previous: Option<(u64, u64)>,
+}
+
+impl IdleProbe {
+    /// Returns the increase of the counters since the previous call, if any.
+    fn measure(&mut self, buf: &mut String) -> Option<(u64, u64)> {
+        let time = self.time.read_u64(buf)?;
+        let usage = self.usage.read_u64(buf)?;
+        // If the counters have been reset (this happens when a core goes offline), wait for the next values.
+        let (prev_time, prev_usage) = self.previous.replace((time, usage))?;
+        Some((time.checked_sub(prev_time)?, usage.checked_sub(prev_usage)?))
+    }
+}
+
+pub struct CpuSource {
+    metrics: CpuMetrics,
+    freq_probes: Vec<FreqProbe>,
+    idle_probes: Vec<IdleProbe>,
+    /// Attributes common to all the idle states.
+    idle_attributes: Vec<(String, AttributeValue)>,
+    buf: String,
+}
+
+impl CpuSource {
+    pub fn new(metrics: CpuMetrics, cpus: Vec<Cpu>, cpuidle: CpuIdle) -> Self {
+        let mut freq_probes = Vec::new();
+        let mut idle_probes = Vec::new();
+        for cpu in cpus {
+            if let Some(freq) = cpu.freq {
+                freq_probes.push(FreqProbe {
+                    cpu: cpu.id,
+                    cur_freq: SysfsFile::new(freq.cur_freq_path),
+                    governor: SysfsFile::new(freq.governor_path),
+                    driver: freq.driver,
+                });
+            }
+            for state in cpu.idle_states {
+                idle_probes.push(IdleProbe {
+                    cpu: cpu.id,
+                    state: state.name,
+                    time: SysfsFile::new(state.time_path),
+                    usage: SysfsFile::new(state.usage_path),
+                    previous: None,
+                });
+            }
+        }
+
+        let mut idle_attributes = Vec::new();
+        if let Some(driver) = cpuidle.driver {
+            idle_attributes.push((String::from("driver"), AttributeValue::String(driver)));
+        }
+        if let Some(governor) = cpuidle.governor {
+            idle_attributes.push((String::from("governor"), AttributeValue::String(governor)));
+        }
+
+        Self {
+            metrics,
+            freq_probes,
+            idle_probes,
+            idle_attributes,
+            buf: String::with_capacity(32),
+        }
+    }
+
+    pub fn n_freq_probes(&self) -> usize {
+        self.freq_probes.len()
+    }
+
+    pub fn n_idle_probes(&self) -> usize {
+        self.idle_probes.len()
+    }
+}
+
+impl Source for CpuSource {
+    fn poll(&mut self, measurements: &mut MeasurementAccumulator, t: Timestamp) -> Result<(), PollError> {
+        for probe in &mut self.freq_probes {
+            let Some(freq) = probe.cur_freq.read_u64(&mut self.buf) else {
+                continue;
+            };
+            // The governor can be changed at any time, for instance by power-profiles-daemon.
+            let governor = probe.governor.read(&mut self.buf).unwrap_or_default().to_owned();
+            measurements.push(
+                MeasurementPoint::new(
+                    t,
+                    self.metrics.frequency,
+                    Resource::CpuCore { id: probe.cpu },
+                    ResourceConsumer::LocalMachine,
+                    freq,
+                )
+                .with_attr("governor", governor)
+                .with_attr("driver", probe.driver.clone()),
+            );
+        }
+
+        for probe in &mut self.idle_probes {
+            let Some((time, usage)) = probe.measure(&mut self.buf) else {
+                continue;
+            };
+            let resource = Resource::CpuCore { id: probe.cpu };
+            let consumer = ResourceConsumer::LocalMachine;
+            let time = MeasurementPoint::new(t, self.metrics.idle_time, resource.clone(), consumer.clone(), time)
+                .with_attr("state", probe.state.clone())
+                .with_attr_slice(&self.idle_attributes);
+            let usage = MeasurementPoint::new(t, self.metrics.idle_usage, resource, consumer, usage)
+                .with_attr("state", probe.state.clone())
+                .with_attr_slice(&self.idle_attributes);
+            measurements.push(time);
+            measurements.push(usage);
+        }
+        Ok(())
+    }
+}
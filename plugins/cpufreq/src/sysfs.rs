@@ -0,0 +1,227 @@
+//! Discovery of the cpufreq and cpuidle files of the CPU cores.
+//!
+//! See <https://docs.kernel.org/admin-guide/pm/cpufreq.html>
+//! and <https://docs.kernel.org/admin-guide/pm/cpuidle.html>.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+/// Directory that contains the CPU cores.
+pub const CPU_SYSFS_DIR: &str = "/sys/devices/system/cpu";
+
+/// A CPU core, i.e. a directory `cpuN`.
+#[derive(Debug)]
+pub struct Cpu {
+    pub id: u32,
+    /// Frequency scaling of the core, if the core has a cpufreq policy.
+    pub freq: Option<CpuFreq>,
+    /// Idle states of the core, empty if cpuidle is disabled.
+    pub idle_states: Vec<IdleState>,
+}
+
+/// The cpufreq files of a core.
+#[derive(Debug)]
+pub struct CpuFreq {
+    /// Current frequency of the core, in kHz.
+    pub cur_freq_path: PathBuf,
+    /// Current scaling governor, like `powersave` or `performance`. It can change at runtime.
+    pub governor_path: PathBuf,
+    /// Name of the cpufreq driver, like `intel_pstate` or `acpi-cpufreq`.
+    pub driver: String,
+}
+
+/// An idle state (C-state) of a core, i.e. a directory `cpuidle/stateN`.
+#[derive(Debug)]
+pub struct IdleState {
+    pub index: u32,
+    /// Name of the state, like `POLL` or `C1E`.
+    pub name: String,
+    /// Total time spent in the state, in microseconds.
+    pub time_path: PathBuf,
+    /// Number of times the state was entered.
+    pub usage_path: PathBuf,
+}
+
+/// The cpuidle subsystem, common to all the cores.
+#[derive(Debug, Default)]
+pub struct CpuIdle {
+    /// Name of the cpuidle driver, like `intel_idle` or `acpi_idle`.
+    pub driver: Option<String>,
+    /// Name of the cpuidle governor, like `menu` or `teo`.
+    pub governor: Option<String>,
+}
+
+fn read_trimmed(path: &Path) -> anyhow::Result<String> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("failed to read {path:?}"))?;
+    Ok(content.trim_ascii().to_owned())
+}
+
+/// Returns the id of the core if `name` is like `cpuN`.
+fn parse_cpu_dir_name(name: &str) -> Option<u32> {
+    name.strip_prefix("cpu")?.parse().ok()
+}
+
+/// Explores the cores of a tree like `/sys/devices/system/cpu`.
+///
+/// ## Expected file layout
+///
+/// ```txt
+/// /sys/devices/system/cpu/
+/// |− cpu0
+///     |− cpufreq
+///         |− scaling_cur_freq
+///         |− scaling_governor
+///         |− scaling_driver
+///     |− cpuidle
+///         |− state0
+///             |− name
+///             |− time
+///             |− usage
+///         |− …
+/// |− cpu1
+///     |− …
+/// |− cpuidle
+///     |− current_driver
+///     |− current_governor_ro
+/// ```
+///
+/// The offline cores have no `cpufreq` nor `cpuidle` directory.
+pub fn explore(root: &Path, with_freq: bool, with_idle: bool) -> anyhow::Result<Vec<Cpu>> {
+    let mut cpus = Vec::new();
+    for entry in std::fs::read_dir(root).with_context(|| format!("failed to read dir {root:?}"))? {
+        let entry = entry?;
+        let Some(id) = parse_cpu_dir_name(&entry.file_name().to_string_lossy()) else {
+            continue;
+        };
+        let cpu_path = entry.path();
+        let freq = if with_freq { find_freq(&cpu_path)? } else { None };
+        let idle_states = if with_idle {
+            find_idle_states(&cpu_path)?
+        } else {
+            Vec::new()
+        };
+        cpus.push(Cpu { id, freq, idle_states });
+    }
+    cpus.sort_by_key(|cpu| cpu.id);
+    Ok(cpus)
+}
+
+fn find_freq(cpu_path: &Path) -> anyhow::Result<Option<CpuFreq>> {
+    let dir = cpu_path.join("cpufreq");
+    let cur_freq_path = dir.join("scaling_cur_freq");
+    if !cur_freq_path.is_file() {
+        log::debug!("no cpufreq in {cpu_path:?}");
+        return Ok(None);
+    }
+    let driver = read_trimmed(&dir.join("scaling_driver"))?;
+    Ok(Some(CpuFreq {
+        cur_freq_path,
+        governor_path: dir.join("scaling_governor"),
+        driver,
+    }))
+}
+
+fn find_idle_states(cpu_path: &Path) -> anyhow::Result<Vec<IdleState>> {
+    let dir = cpu_path.join("cpuidle");
+    if !dir.is_dir() {
+        log::debug!("no cpuidle in {cpu_path:?}");
+        return Ok(Vec::new());
+    }
+    let mut states = Vec::new();
+    for entry in std::fs::read_dir(&dir).with_context(|| format!("failed to read dir {dir:?}"))? {
+        let entry = entry?;
+        let Some(index) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.strip_prefix("state"))
+            .and_then(|index| index.parse().ok())
+        else {
+            continue;
+        };
+        let state_path = entry.path();
+        let name = read_trimmed(&state_path.join("name"))?;
+        states.push(IdleState {
+            index,
+            name,
+            time_path: state_path.join("time"),
+            usage_path: state_path.join("usage"),
+        });
+    }
+    states.sort_by_key(|s| s.index);
+    Ok(states)
+}
+
+/// Reads the driver and the governor of cpuidle, if available.
+pub fn cpuidle_info(root: &Path) -> CpuIdle {
+    let dir = root.join("cpuidle");
+    // current_governor is only readable by root on some kernels, current_governor_ro is readable by all
+    let governor = ["current_governor_ro", "current_governor"]
+        .into_iter()
+        .find_map(|file| read_trimmed(&dir.join(file)).ok());
+    CpuIdle {
+        driver: read_trimmed(&dir.join("current_driver")).ok(),
+        governor,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(root: &Path, path: &str, content: &str) {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn cpu_dir_names() {
+        assert_eq!(parse_cpu_dir_name("cpu0"), Some(0));
+        assert_eq!(parse_cpu_dir_name("cpu127"), Some(127));
+        assert_eq!(parse_cpu_dir_name("cpufreq"), None);
+        assert_eq!(parse_cpu_dir_name("cpuidle"), None);
+        assert_eq!(parse_cpu_dir_name("cpu"), None);
+    }
+
+    #[test]
+    fn explore_fake_sysfs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        for cpu in ["cpu0", "cpu10", "cpu2"] {
+            write(root, &format!("{cpu}/cpufreq/scaling_cur_freq"), "2400000\n");
+            write(root, &format!("{cpu}/cpufreq/scaling_governor"), "powersave\n");
+            write(root, &format!("{cpu}/cpufreq/scaling_driver"), "intel_pstate\n");
+            for (i, name) in ["POLL", "C1", "C1E"].iter().enumerate() {
+                write(root, &format!("{cpu}/cpuidle/state{i}/name"), &format!("{name}\n"));
+                write(root, &format!("{cpu}/cpuidle/state{i}/time"), "0\n");
+                write(root, &format!("{cpu}/cpuidle/state{i}/usage"), "0\n");
+            }
+        }
+        // offline core
+        write(root, "cpu3/online", "0\n");
+        write(root, "cpufreq/policy0/scaling_cur_freq", "2400000\n");
+        write(root, "cpuidle/current_driver", "intel_idle\n");
+        write(root, "cpuidle/current_governor_ro", "menu\n");
+
+        let cpus = explore(root, true, true).unwrap();
+        let ids: Vec<u32> = cpus.iter().map(|cpu| cpu.id).collect();
+        assert_eq!(ids, vec![0, 2, 3, 10]);
+
+        let freq = cpus[0].freq.as_ref().unwrap();
+        assert_eq!(freq.driver, "intel_pstate");
+        assert_eq!(freq.cur_freq_path, root.join("cpu0/cpufreq/scaling_cur_freq"));
+        let states: Vec<&str> = cpus[0].idle_states.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(states, vec!["POLL", "C1", "C1E"]);
+
+        assert!(cpus[2].freq.is_none());
+        assert!(cpus[2].idle_states.is_empty());
+
+        let cpus = explore(root, false, true).unwrap();
+        assert!(cpus.iter().all(|cpu| cpu.freq.is_none()));
+
+        let idle = cpuidle_info(root);
+        assert_eq!(idle.driver.as_deref(), Some("intel_idle"));
+        assert_eq!(idle.governor.as_deref(), Some("menu"));
+    }
+}
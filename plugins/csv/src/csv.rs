@@ -1,10 +1,10 @@
-use std::{borrow::Cow, fs::File, io::Write};
+use std::{borrow::Cow, io::Write};
 
 use rustc_hash::FxHashMap;
 
-pub struct CsvWriter {
+pub struct CsvWriter<W: Write> {
     /// File, opened for writing.
-    file: File,
+    file: W,
 
     /// Columns of the header.
     /// If empty, the header has not been written yet.
@@ -14,9 +14,12 @@ pub struct CsvWriter {
     params: CsvParams,
 }
 
+#[derive(Clone)]
 pub struct CsvParams {
     pub delimiter: char,
     pub late_delimiter: char,
+    /// Do we add the `__late_attributes` column?
+    pub late_attributes: bool,
 }
 
 impl Default for CsvParams {
@@ -24,12 +27,13 @@ impl Default for CsvParams {
         Self {
             delimiter: ';',
             late_delimiter: ',',
+            late_attributes: true,
         }
     }
 }
 
-impl CsvWriter {
-    pub fn new(file: File, params: CsvParams) -> Self {
+impl<W: Write> CsvWriter<W> {
+    pub fn new(file: W, params: CsvParams) -> Self {
         Self {
             file,
             header: Vec::new(),
@@ -41,12 +45,35 @@ impl CsvWriter {
         !self.header.is_empty()
     }
 
+    /// Returns the columns of the header (empty if the header has not been written yet).
+    pub fn header(&self) -> &[String] {
+        &self.header
+    }
+
+    /// Forgets the header, so that a new one can be written (for instance, after a rotation of the file).
+    pub fn reset_header(&mut self) {
+        self.header.clear();
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.file
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.file
+    }
+
     pub fn write_header(&mut self, header: Vec<String>) -> anyhow::Result<()> {
         assert!(self.header.is_empty());
-        for column in &header {
-            write!(&mut self.file, "{column}{}", self.params.delimiter)?;
+        if self.params.late_attributes {
+            for column in &header {
+                write!(&mut self.file, "{column}{}", self.params.delimiter)?;
+            }
+            writeln!(&mut self.file, "__late_attributes")?;
+        } else {
+            let delimiter = self.params.delimiter.to_string();
+            writeln!(&mut self.file, "{}", header.join(&delimiter))?;
         }
-        writeln!(&mut self.file, "__late_attributes")?;
         self.header = header;
         Ok(())
     }
@@ -57,12 +84,22 @@ impl CsvWriter {
         assert!(!self.header.is_empty());
 
         // Write the data in the columns that we know
-        for column in &self.header {
+        let last_column = self.header.len() - 1;
+        for (i, column) in self.header.iter().enumerate() {
             if let Some(value) = data.remove(column) {
                 let value = self.params.escape_string(&value);
                 write!(&mut self.file, "{value}")?;
             }
-            write!(&mut self.file, "{}", self.params.delimiter)?;
+            if i != last_column || self.params.late_attributes {
+                write!(&mut self.file, "{}", self.params.delimiter)?;
+            }
+        }
+        if !self.params.late_attributes {
+            if !data.is_empty() {
+                log::warn!("attributes without a column are ignored: {:?}", data.keys());
+            }
+            writeln!(&mut self.file)?;
+            return Ok(());
         }
 
         // Write the data in the "late attributes" column.
@@ -172,11 +209,42 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn csv_writer_without_late_attributes() -> anyhow::Result<()> {
+        let params = CsvParams {
+            late_attributes: false,
+            ..Default::default()
+        };
+        let mut writer = CsvWriter::new(Vec::new(), params);
+        writer.write_header(vec!["metric".to_owned(), "value".to_owned(), "sensor".to_owned()])?;
+        writer.write_line(&mut FxHashMap::from_iter(vec![
+            ("metric".to_owned(), "test".to_owned()),
+            ("value".to_owned(), "25".to_owned()),
+            ("sensor".to_owned(), "petits;pois".to_owned()),
+        ]))?;
+        writer.write_line(&mut FxHashMap::from_iter(vec![
+            ("metric".to_owned(), "test".to_owned()),
+            ("value".to_owned(), "7".to_owned()),
+        ]))?;
+
+        let output = String::from_utf8(writer.file)?;
+        assert_eq!(
+            output,
+            indoc! {"
+            metric;value;sensor
+            test;25;\"petits;pois\"
+            test;7;
+        "}
+        );
+        Ok(())
+    }
+
     #[test]
     fn csv_escape() {
         let helper = CsvParams {
             delimiter: ',',
             late_delimiter: ':',
+            ..Default::default()
         };
         assert_eq!("abcdefg", helper.escape_string("abcdefg"));
         assert_eq!("\"abcd\"\"efg\"", helper.escape_string("abcd\"efg"));
@@ -187,6 +255,7 @@ mod tests {
         let helper = CsvParams {
             delimiter: ';',
             late_delimiter: ',',
+            ..Default::default()
         };
         assert_eq!("abcdefg", helper.escape_string("abcdefg"));
         assert_eq!("\"abcd\"\"efg\"", helper.escape_string("abcd\"efg"));
@@ -0,0 +1,458 @@
+//! Output files, with optional rotation and compression.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+/// Compression algorithm of the output files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Extension that is appended to the name of the compressed files.
+    fn extension(&self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some("gz"),
+            Compression::Zstd => Some("zst"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FileSettings {
+    /// Rotate the file when it exceeds this size (in bytes, before compression).
+    pub rotation_max_size: Option<u64>,
+    /// Rotate the file when it is older than this duration.
+    pub rotation_max_age: Option<Duration>,
+    /// Compression of the files.
+    pub compression: Compression,
+    /// If true, the live file is compressed while it is written.
+    /// Otherwise, only the rotated files are compressed.
+    pub compress_live_file: bool,
+}
+
+impl FileSettings {
+    /// Compression applied while writing the live file.
+    fn live_compression(&self) -> Compression {
+        if self.compress_live_file {
+            self.compression
+        } else {
+            Compression::None
+        }
+    }
+
+    /// Compression applied to the files after their rotation.
+    fn rotated_compression(&self) -> Compression {
+        if self.compress_live_file {
+            Compression::None
+        } else {
+            self.compression
+        }
+    }
+}
+
+/// An output file that can be rotated.
+///
+/// On rotation, the file is renamed with the time at which it was opened,
+/// for instance `alumet-output-20250101T120000Z.csv`, and a new file is created.
+pub struct RotatingFile {
+    /// Path of the (uncompressed) file, as configured.
+    base_path: PathBuf,
+    /// Path of the file that is currently written.
+    live_path: PathBuf,
+    settings: FileSettings,
+    /// `None` only after a failed rotation, in which case the file is opened again on the next write.
+    encoder: Option<Encoder>,
+    opened_at: SystemTime,
+    /// Number of bytes written to the live file, before compression.
+    written: u64,
+}
+
+impl RotatingFile {
+    /// Creates (or truncates) the file and opens it for writing.
+    pub fn create(path: impl Into<PathBuf>, settings: FileSettings) -> anyhow::Result<Self> {
+        let base_path = path.into();
+        let live_path = with_compression_extension(&base_path, settings.live_compression());
+        let encoder = Encoder::create(&live_path, settings.live_compression())?;
+        Ok(Self {
+            base_path,
+            live_path,
+            settings,
+            encoder: Some(encoder),
+            opened_at: SystemTime::now(),
+            written: 0,
+        })
+    }
+
+    /// Returns the path of the file that is currently written.
+    pub fn live_path(&self) -> &Path {
+        &self.live_path
+    }
+
+    /// Returns true if the file has reached its maximum size or age.
+    pub fn should_rotate(&self, now: SystemTime) -> bool {
+        if self.written == 0 {
+            return false;
+        }
+        let too_big = self.settings.rotation_max_size.is_some_and(|max| self.written >= max);
+        let too_old = self
+            .settings
+            .rotation_max_age
+            .is_some_and(|max| now.duration_since(self.opened_at).is_ok_and(|age| age >= max));
+        too_big || too_old
+    }
+
+    /// Closes the current file, renames it and opens a new file.
+    ///
+    /// If the rotated files must be compressed, the compression happens here, after the opening of the new file.
+    /// A compression failure is logged, the rotated file is then left uncompressed.
+    /// Returns the final path of the rotated file.
+    pub fn rotate(&mut self) -> anyhow::Result<PathBuf> {
+        if let Some(Err(e)) = self.encoder.take().map(Encoder::finish) {
+            log::error!("failed to close {:?}, its end may be lost: {e}", self.live_path);
+        }
+
+        // Find a name that is not used yet, even after the compression of the rotated file.
+        let live_compression = self.settings.live_compression();
+        let rotated_compression = self.settings.rotated_compression();
+        let candidate = |n| {
+            let path = rotated_path(&self.base_path, self.opened_at, n);
+            with_compression_extension(&path, live_compression)
+        };
+        let mut n = 0;
+        let mut rotated = candidate(n);
+        while rotated.exists() || with_compression_extension(&rotated, rotated_compression).exists() {
+            n += 1;
+            rotated = candidate(n);
+        }
+        if let Err(e) = fs::rename(&self.live_path, &rotated) {
+            // keep writing to the same file
+            self.encoder = Some(Encoder::append(&self.live_path, live_compression)?);
+            return Err(e).with_context(|| format!("failed to rename {:?} to {rotated:?}", self.live_path));
+        }
+
+        self.opened_at = SystemTime::now();
+        self.written = 0;
+        self.encoder = Some(Encoder::create(&self.live_path, live_compression)?);
+
+        if rotated_compression != Compression::None {
+            match compress_file(&rotated, rotated_compression) {
+                Ok(compressed) => rotated = compressed,
+                Err(e) => log::error!("failed to compress the rotated file {rotated:?}: {e:#}"),
+            }
+        }
+        log::debug!("{:?} rotated to {rotated:?}", self.live_path);
+        Ok(rotated)
+    }
+
+    fn encoder(&mut self) -> io::Result<&mut Encoder> {
+        let encoder = match self.encoder.take() {
+            Some(encoder) => encoder,
+            None => {
+                // The last rotation could not open the new file, try again.
+                let encoder =
+                    Encoder::create(&self.live_path, self.settings.live_compression()).map_err(io::Error::other)?;
+                self.opened_at = SystemTime::now();
+                self.written = 0;
+                encoder
+            }
+        };
+        Ok(self.encoder.insert(encoder))
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.encoder()?.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder()?.flush()
+    }
+}
+
+impl Drop for RotatingFile {
+    fn drop(&mut self) {
+        if let Some(Err(e)) = self.encoder.take().map(Encoder::finish) {
+            log::error!("failed to close {:?}: {e}", self.live_path);
+        }
+    }
+}
+
+/// Writes to a file, with optional compression.
+enum Encoder {
+    Plain(BufWriter<File>),
+    Gzip(flate2::write::GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Encoder {
+    fn create(path: &Path, compression: Compression) -> anyhow::Result<Self> {
+        let file = File::create(path).with_context(|| format!("failed to open file for writing {path:?}"))?;
+        Self::new(file, compression)
+    }
+
+    /// Opens the file for writing at its end.
+    ///
+    /// Compressed data is written in a new gzip member or zstd frame, which is still a valid compressed file.
+    fn append(path: &Path, compression: Compression) -> anyhow::Result<Self> {
+        let file = File::options()
+            .append(true)
+            .create(true)
+            .open(path)
+            .with_context(|| format!("failed to open file for appending {path:?}"))?;
+        Self::new(file, compression)
+    }
+
+    fn new(file: File, compression: Compression) -> anyhow::Result<Self> {
+        let writer = BufWriter::new(file);
+        let encoder = match compression {
+            Compression::None => Encoder::Plain(writer),
+            Compression::Gzip => Encoder::Gzip(flate2::write::GzEncoder::new(writer, flate2::Compression::default())),
+            Compression::Zstd => Encoder::Zstd(zstd::Encoder::new(writer, 0)?),
+        };
+        Ok(encoder)
+    }
+
+    /// Writes the end of the compressed stream, if any, and flushes the file.
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Encoder::Plain(mut w) => w.flush(),
+            Encoder::Gzip(e) => e.finish()?.flush(),
+            Encoder::Zstd(e) => e.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for Encoder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Plain(w) => w.write(buf),
+            Encoder::Gzip(e) => e.write(buf),
+            Encoder::Zstd(e) => e.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Plain(w) => w.flush(),
+            Encoder::Gzip(e) => e.flush(),
+            Encoder::Zstd(e) => e.flush(),
+        }
+    }
+}
+
+/// Compresses a file and removes the uncompressed file.
+fn compress_file(path: &Path, compression: Compression) -> anyhow::Result<PathBuf> {
+    let target = with_compression_extension(path, compression);
+    let mut input = File::open(path).with_context(|| format!("failed to open {path:?}"))?;
+    let mut encoder = Encoder::create(&target, compression)?;
+    let res = io::copy(&mut input, &mut encoder)
+        .with_context(|| format!("failed to compress {path:?}"))
+        .and_then(|_| encoder.finish().with_context(|| format!("failed to close {target:?}")));
+    if let Err(e) = res {
+        // don't leave an incomplete compressed file
+        let _ = fs::remove_file(&target);
+        return Err(e);
+    }
+    fs::remove_file(path).with_context(|| format!("failed to remove {path:?}"))?;
+    Ok(target)
+}
+
+/// Appends the extension of the compression algorithm (if any) to the path, for instance `.gz`.
+fn with_compression_extension(path: &Path, compression: Compression) -> PathBuf {
+    match compression.extension() {
+        Some(ext) => {
+            let mut s = path.as_os_str().to_owned();
+            s.push(".");
+            s.push(ext);
+            PathBuf::from(s)
+        }
+        None => path.to_path_buf(),
+    }
+}
+
+/// Returns the path `{stem}-{suffix}.{extension}`, in the same directory as `path`.
+pub fn path_with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(ext) => format!("{stem}-{suffix}.{}", ext.to_string_lossy()),
+        None => format!("{stem}-{suffix}"),
+    };
+    path.with_file_name(file_name)
+}
+
+/// Returns the path of a rotated file, such as `alumet-output-20250101T120000Z.csv`.
+///
+/// If `n` is not zero, it is appended to the timestamp to make the name unique.
+/// This naming scheme is also used by the other file outputs, like parquet.
+pub fn rotated_path(base: &Path, opened_at: SystemTime, n: u32) -> PathBuf {
+    let t = OffsetDateTime::from(opened_at);
+    let mut suffix = format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        t.year(),
+        u8::from(t.month()),
+        t.day(),
+        t.hour(),
+        t.minute(),
+        t.second()
+    );
+    if n != 0 {
+        suffix.push_str(&format!("-{n}"));
+    }
+    path_with_suffix(base, &suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, File},
+        io::{Read, Write},
+        path::Path,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use pretty_assertions::assert_eq;
+
+    use super::{Compression, FileSettings, RotatingFile, path_with_suffix, rotated_path};
+
+    fn list_dir(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn paths() {
+        assert_eq!(
+            path_with_suffix(Path::new("out/alumet-output.csv"), "rapl_consumed_energy_J"),
+            Path::new("out/alumet-output-rapl_consumed_energy_J.csv")
+        );
+        assert_eq!(
+            path_with_suffix(Path::new("alumet-output"), "abc"),
+            Path::new("alumet-output-abc")
+        );
+
+        let t = UNIX_EPOCH + Duration::from_secs(1735732800); // 2025-01-01 12:00:00 UTC
+        assert_eq!(
+            rotated_path(Path::new("alumet-output.csv"), t, 0),
+            Path::new("alumet-output-20250101T120000Z.csv")
+        );
+        assert_eq!(
+            rotated_path(Path::new("alumet-output.csv"), t, 2),
+            Path::new("alumet-output-20250101T120000Z-2.csv")
+        );
+    }
+
+    #[test]
+    fn should_rotate() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let settings = FileSettings {
+            rotation_max_size: Some(10),
+            rotation_max_age: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+        let mut file = RotatingFile::create(tmp.path().join("test.csv"), settings)?;
+        let now = SystemTime::now();
+
+        // empty files are never rotated
+        assert!(!file.should_rotate(now + Duration::from_secs(3600)));
+
+        file.write_all(b"12345")?;
+        assert!(!file.should_rotate(now));
+        assert!(file.should_rotate(now + Duration::from_secs(3600)));
+
+        file.write_all(b"67890")?;
+        assert!(file.should_rotate(now));
+        Ok(())
+    }
+
+    #[test]
+    fn rotate_and_compress() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let settings = FileSettings {
+            rotation_max_size: Some(10),
+            compression: Compression::Gzip,
+            ..Default::default()
+        };
+        let mut file = RotatingFile::create(tmp.path().join("test.csv"), settings)?;
+        file.write_all(b"first file\n")?;
+        let rotated_1 = file.rotate()?;
+        file.write_all(b"second file\n")?;
+        let rotated_2 = file.rotate()?;
+        file.write_all(b"live\n")?;
+        file.flush()?;
+
+        // The rotated files have the same timestamp (most probably), but different names.
+        assert_ne!(rotated_1, rotated_2);
+        assert_eq!(list_dir(tmp.path()).len(), 3);
+        assert_eq!(fs::read_to_string(file.live_path())?, "live\n");
+
+        let mut content = String::new();
+        flate2::read::GzDecoder::new(File::open(&rotated_1)?).read_to_string(&mut content)?;
+        assert_eq!(content, "first file\n");
+        Ok(())
+    }
+
+    #[test]
+    fn compress_live_file() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let settings = FileSettings {
+            compression: Compression::Zstd,
+            compress_live_file: true,
+            ..Default::default()
+        };
+        let mut file = RotatingFile::create(tmp.path().join("test.csv"), settings)?;
+        assert_eq!(file.live_path(), tmp.path().join("test.csv.zst"));
+        file.write_all(b"abc\n")?;
+        let rotated = file.rotate()?;
+        assert!(rotated.to_string_lossy().ends_with("Z.csv.zst"));
+        drop(file);
+
+        let content = zstd::decode_all(File::open(&rotated)?)?;
+        assert_eq!(content, b"abc\n");
+        let content = zstd::decode_all(File::open(tmp.path().join("test.csv.zst"))?)?;
+        assert_eq!(content, b"");
+        Ok(())
+    }
+
+    #[test]
+    fn failed_rotation() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let mut file = RotatingFile::create(tmp.path().join("test.csv"), FileSettings::default())?;
+        file.write_all(b"removed\n")?;
+
+        // the live file cannot be renamed, but it is still possible to write
+        fs::remove_file(file.live_path())?;
+        assert!(file.rotate().is_err());
+        file.write_all(b"still written\n")?;
+        file.flush()?;
+        assert_eq!(fs::read_to_string(file.live_path())?, "still written\n");
+
+        // the encoder is opened again if the last rotation could not do it
+        file.encoder = None;
+        file.write_all(b"new file\n")?;
+        file.flush()?;
+        assert_eq!(fs::read_to_string(file.live_path())?, "new file\n");
+        Ok(())
+    }
+}
@@ -1,8 +1,9 @@
 mod csv;
+mod file;
 mod output;
 // TODO mod input
 
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
 use alumet::plugin::{
     ConfigTable,
@@ -11,7 +12,9 @@ use alumet::plugin::{
 use output::CsvOutput;
 use serde::{Deserialize, Serialize};
 
-use crate::{csv::CsvParams, output::CsvOutputSettings};
+use crate::{csv::CsvParams, file::FileSettings, output::CsvOutputSettings};
+
+pub use file::{Compression, rotated_path};
 
 pub struct CsvPlugin {
     config: Config,
@@ -40,9 +43,18 @@ impl AlumetPlugin for CsvPlugin {
             force_flush: self.config.force_flush,
             append_unit_to_metric_name: self.config.append_unit_to_metric_name,
             use_unit_display_name: self.config.use_unit_display_name,
+            file_per_metric: self.config.file_per_metric,
+            attributes_as_columns: self.config.attributes_as_columns,
             params: CsvParams {
                 delimiter: self.config.csv_delimiter,
                 late_delimiter: self.config.csv_late_delimiter,
+                late_attributes: !self.config.attributes_as_columns,
+            },
+            file: FileSettings {
+                rotation_max_size: self.config.rotation.max_size,
+                rotation_max_age: self.config.rotation.max_age,
+                compression: self.config.compression,
+                compress_live_file: self.config.compress_live_file,
             },
         };
         let output = Box::new(CsvOutput::new(&self.config.output_path, settings)?);
@@ -70,6 +82,32 @@ pub struct Config {
     pub csv_delimiter: char,
     /// The delimiter between the entries in `__late_attributes`.
     pub csv_late_delimiter: char,
+    /// Do we write one file per metric (format `{output_path stem}-{metric}.{extension}`)?
+    #[serde(default)]
+    pub file_per_metric: bool,
+    /// Do we write every attribute in its own column, instead of packing the late attributes in `__late_attributes`?
+    /// When new attributes appear, the file is rotated and the new file has an extended header.
+    #[serde(default)]
+    pub attributes_as_columns: bool,
+    /// Compression of the files: `none`, `gzip` or `zstd`.
+    #[serde(default)]
+    pub compression: Compression,
+    /// Do we compress the file that is being written? If false, only the rotated files are compressed.
+    #[serde(default)]
+    pub compress_live_file: bool,
+    /// Rotation of the output file(s).
+    #[serde(default)]
+    pub rotation: RotationConfig,
+}
+
+#[derive(Deserialize, Serialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RotationConfig {
+    /// Rotate the file when it exceeds this size, in bytes (before compression).
+    pub max_size: Option<u64>,
+    /// Rotate the file when it is older than this duration.
+    #[serde(with = "humantime_serde", default)]
+    pub max_age: Option<Duration>,
 }
 
 impl Default for Config {
@@ -81,6 +119,11 @@ impl Default for Config {
             append_unit_to_metric_name: true,
             csv_delimiter: ';',
             csv_late_delimiter: ',',
+            file_per_metric: false,
+            attributes_as_columns: false,
+            compression: Compression::None,
+            compress_live_file: false,
+            rotation: RotationConfig::default(),
         }
     }
 }
@@ -1,16 +1,33 @@
-use std::{collections::HashSet, fs::File, path::Path, time::SystemTime};
+use std::{
+    collections::{BTreeSet, HashSet},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
-use crate::csv::{CsvParams, CsvWriter};
+use crate::{
+    csv::{CsvParams, CsvWriter},
+    file::{FileSettings, RotatingFile, path_with_suffix},
+};
+use alumet::{measurement::WrappedMeasurementValue, pipeline::Output};
 use alumet::{
-    measurement::MeasurementBuffer,
+    measurement::{MeasurementBuffer, MeasurementPoint},
     pipeline::elements::{error::WriteError, output::OutputContext},
 };
-use alumet::{measurement::WrappedMeasurementValue, pipeline::Output};
-use anyhow::Context;
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 use time::OffsetDateTime;
 use time::format_description::well_known::Rfc3339;
 
+/// Columns that are always present, before the attributes.
+const BASE_COLUMNS: [&str; 7] = [
+    "metric",
+    "timestamp",
+    "value",
+    "resource_kind",
+    "resource_id",
+    "consumer_kind",
+    "consumer_id",
+];
+
 pub struct CsvOutput {
     /// parameter: do we flush after each write(measurements)?
     force_flush: bool,
@@ -19,119 +36,241 @@ pub struct CsvOutput {
     append_unit_to_metric_name: bool,
     use_unit_display_name: bool,
 
-    /// CSV writer
-    writer: CsvWriter,
+    /// parameter: do we write one file per metric?
+    file_per_metric: bool,
+    /// parameter: do we write every attribute in its own column?
+    attributes_as_columns: bool,
+
+    output_path: PathBuf,
+    params: CsvParams,
+    file_settings: FileSettings,
+
+    /// CSV writers, by metric if `file_per_metric` is true (otherwise, the only key is the empty string).
+    writers: FxHashMap<String, CsvWriter<RotatingFile>>,
+    /// The suffixes of the files that have been created for the metrics, to avoid collisions.
+    file_suffixes: FxHashSet<String>,
 }
 
 pub struct CsvOutputSettings {
     pub force_flush: bool,
     pub append_unit_to_metric_name: bool,
     pub use_unit_display_name: bool,
+    pub file_per_metric: bool,
+    pub attributes_as_columns: bool,
     pub params: CsvParams,
+    pub file: FileSettings,
 }
 
 impl CsvOutput {
     pub fn new(output_file: impl AsRef<Path>, settings: CsvOutputSettings) -> anyhow::Result<Self> {
-        let path = output_file.as_ref();
-        let file = File::create(path).with_context(|| format!("failed to open file for writing {path:?}"))?;
-        let writer = CsvWriter::new(file, settings.params);
-        Ok(Self {
+        let mut res = Self {
             force_flush: settings.force_flush,
             append_unit_to_metric_name: settings.append_unit_to_metric_name,
             use_unit_display_name: settings.use_unit_display_name,
-            writer,
-        })
+            file_per_metric: settings.file_per_metric,
+            attributes_as_columns: settings.attributes_as_columns,
+            output_path: output_file.as_ref().to_path_buf(),
+            params: settings.params,
+            file_settings: settings.file,
+            writers: FxHashMap::default(),
+            file_suffixes: FxHashSet::default(),
+        };
+        if !res.file_per_metric {
+            // Open the file now, to detect errors early.
+            res.writer(String::new())?;
+        }
+        Ok(res)
+    }
+
+    /// Returns the writer of the file with the given key, creating the file if needed.
+    fn writer(&mut self, key: String) -> anyhow::Result<&mut CsvWriter<RotatingFile>> {
+        use std::collections::hash_map::Entry;
+
+        match self.writers.entry(key) {
+            Entry::Occupied(e) => Ok(e.into_mut()),
+            Entry::Vacant(e) => {
+                let path = if e.key().is_empty() {
+                    self.output_path.clone()
+                } else {
+                    let suffix = unique_file_suffix(&mut self.file_suffixes, e.key());
+                    path_with_suffix(&self.output_path, &suffix)
+                };
+                log::debug!("creating csv file {path:?}");
+                let file = RotatingFile::create(path, self.file_settings.clone())?;
+                Ok(e.insert(CsvWriter::new(file, self.params.clone())))
+            }
+        }
+    }
+
+    /// Returns the name of the metric, with its unit if configured so.
+    fn metric_string(&self, m: &MeasurementPoint, ctx: &OutputContext) -> String {
+        let metric = ctx.metrics.by_id(&m.metric).expect("unknown metric");
+        let metric_name = metric.name.clone();
+        let unit = &metric.unit;
+
+        let unit_string = if self.append_unit_to_metric_name {
+            if self.use_unit_display_name {
+                unit.display_name()
+            } else {
+                unit.unique_name()
+            }
+        } else {
+            String::new()
+        };
+        if unit_string.is_empty() {
+            metric_name
+        } else {
+            format!("{metric_name}_{unit_string}")
+        }
     }
 }
 
-fn collect_attribute_keys(buf: &MeasurementBuffer) -> HashSet<String> {
+fn collect_attribute_keys<'a>(points: impl IntoIterator<Item = &'a MeasurementPoint>) -> HashSet<String> {
     let mut res = HashSet::new();
-    for m in buf.iter() {
+    for m in points {
         res.extend(m.attributes_keys().map(|k| k.to_owned()));
     }
     res
 }
 
+/// Builds the header of a CSV file, with the base columns followed by the attributes.
+fn build_header(attributes: BTreeSet<String>) -> Vec<String> {
+    let mut header = Vec::with_capacity(BASE_COLUMNS.len() + attributes.len());
+    header.extend(BASE_COLUMNS.map(String::from));
+    header.extend(attributes);
+    header
+}
+
+/// Replaces the characters that are not safe in a file name.
+fn sanitize_file_name(s: &str) -> String {
+    s.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Returns the sanitized name of the metric, with a number if it is already used by another metric.
+///
+/// For instance, `memory_usage_B/s` and `memory_usage_B_s` would otherwise be written to the same file.
+fn unique_file_suffix(used: &mut FxHashSet<String>, metric: &str) -> String {
+    let base = sanitize_file_name(metric);
+    let mut suffix = base.clone();
+    let mut n = 1;
+    while used.contains(&suffix) {
+        n += 1;
+        suffix = format!("{base}-{n}");
+    }
+    if n > 1 {
+        log::warn!("the file name of metric {metric} is already used by another metric, using {suffix} instead");
+    }
+    used.insert(suffix.clone());
+    suffix
+}
+
+/// Writes some measurements to a CSV file, rotating it if needed.
+fn write_points(
+    writer: &mut CsvWriter<RotatingFile>,
+    points: &[(&MeasurementPoint, String)],
+    attributes_as_columns: bool,
+) -> anyhow::Result<()> {
+    let attr_keys = collect_attribute_keys(points.iter().map(|(m, _)| *m));
+
+    if writer.is_initialized() {
+        let header = writer.header();
+        let new_columns = attributes_as_columns && attr_keys.iter().any(|k| !header.contains(k));
+        if new_columns {
+            // Start a new file with a header that contains the previous and the new attributes.
+            let mut attributes: BTreeSet<String> = header[BASE_COLUMNS.len()..].iter().cloned().collect();
+            attributes.extend(attr_keys.iter().cloned());
+            log::debug!(
+                "new attributes in the measurements, rotating {:?}",
+                writer.get_ref().live_path()
+            );
+            writer.get_mut().rotate()?;
+            writer.reset_header();
+            writer.write_header(build_header(attributes))?;
+        } else if writer.get_ref().should_rotate(SystemTime::now()) {
+            writer.get_mut().rotate()?;
+            writer.reset_header();
+        }
+    }
+
+    if !writer.is_initialized() {
+        log::trace!("initializing csv header");
+        // Sort the attributes to ensure a consistent order between calls to `CsvOutput::write`.
+        let header = build_header(attr_keys.into_iter().collect());
+        log::trace!("writing header {header:?}");
+        writer.write_header(header)?;
+    }
+
+    for (m, metric_string) in points {
+        log::trace!("writing {m:?}");
+        let mut data = FxHashMap::default();
+
+        let datetime: OffsetDateTime = SystemTime::from(m.timestamp).into();
+        let datetime = datetime.format(&Rfc3339)?;
+
+        let value = match m.value {
+            WrappedMeasurementValue::F64(x) => x.to_string(),
+            WrappedMeasurementValue::U64(x) => x.to_string(),
+        };
+        let resource_kind = m.resource.kind().to_owned();
+        let resource_id = m.resource.id_display().to_string();
+        let consumer_kind = m.consumer.kind().to_owned();
+        let consumer_id = m.consumer.id_display().to_string();
+
+        data.insert("metric".to_owned(), metric_string.clone());
+        data.insert("timestamp".to_owned(), datetime);
+        data.insert("value".to_owned(), value);
+        data.insert("resource_kind".to_owned(), resource_kind);
+        data.insert("resource_id".to_owned(), resource_id);
+        data.insert("consumer_kind".to_owned(), consumer_kind);
+        data.insert("consumer_id".to_owned(), consumer_id);
+
+        for (k, v) in m.attributes() {
+            data.insert(k.to_owned(), v.to_string());
+        }
+
+        writer.write_line(&mut data)?;
+    }
+    Ok(())
+}
+
 impl Output for CsvOutput {
     fn write(&mut self, measurements: &MeasurementBuffer, ctx: &OutputContext) -> Result<(), WriteError> {
         log::trace!("writing csv measurements {measurements:?}");
-        if !self.writer.is_initialized() {
-            log::trace!("initializing csv header");
-            // Collect the attributes that are present in the measurements.
-            // Then, sort the keys to ensure a consistent order between calls to `CsvOutput::write`.
-            let attr_keys: HashSet<String> = collect_attribute_keys(measurements).into_iter().collect();
-            let mut attr_sorted: Vec<&str> = attr_keys.iter().map(|k| k.as_str()).collect();
-            attr_sorted.sort();
-
-            let mut header = Vec::with_capacity(8 + attr_sorted.len());
-            header.extend(&[
-                "metric",
-                "timestamp",
-                "value",
-                "resource_kind",
-                "resource_id",
-                "consumer_kind",
-                "consumer_id",
-            ]);
-            header.extend(attr_sorted);
-            let header = header.into_iter().map(String::from).collect();
-            log::trace!("writing header {header:?}");
-            self.writer.write_header(header)?;
-        }
 
+        // Group the measurements by file, keeping their order.
+        let mut groups: Vec<(String, Vec<(&MeasurementPoint, String)>)> = Vec::new();
+        let mut group_indices: FxHashMap<String, usize> = FxHashMap::default();
         for m in measurements {
-            log::trace!("writing {m:?}");
-            let mut data = FxHashMap::default();
-
-            let metric = ctx.metrics.by_id(&m.metric).expect("unknown metric");
-            let metric_name = metric.name.clone();
-            let unit = &metric.unit;
-
-            let unit_string = if self.append_unit_to_metric_name {
-                if self.use_unit_display_name {
-                    unit.display_name()
-                } else {
-                    unit.unique_name()
-                }
+            let metric_string = self.metric_string(m, ctx);
+            let key = if self.file_per_metric {
+                metric_string.clone()
             } else {
                 String::new()
             };
-            let metric_string = if unit_string.is_empty() {
-                metric_name
-            } else {
-                format!("{metric_name}_{unit_string}")
-            };
-
-            let datetime: OffsetDateTime = SystemTime::from(m.timestamp).into();
-            let datetime = datetime.format(&Rfc3339)?;
-
-            let value = match m.value {
-                WrappedMeasurementValue::F64(x) => x.to_string(),
-                WrappedMeasurementValue::U64(x) => x.to_string(),
-            };
-            let resource_kind = m.resource.kind().to_owned();
-            let resource_id = m.resource.id_display().to_string();
-            let consumer_kind = m.consumer.kind().to_owned();
-            let consumer_id = m.consumer.id_display().to_string();
-
-            data.insert("metric".to_owned(), metric_string);
-            data.insert("timestamp".to_owned(), datetime);
-            data.insert("value".to_owned(), value);
-            data.insert("resource_kind".to_owned(), resource_kind);
-            data.insert("resource_id".to_owned(), resource_id);
-            data.insert("consumer_kind".to_owned(), consumer_kind);
-            data.insert("consumer_id".to_owned(), consumer_id);
-
-            for (k, v) in m.attributes() {
-                data.insert(k.to_owned(), v.to_string());
-            }
-
-            self.writer.write_line(&mut data)?;
+            let i = *group_indices.entry(key.clone()).or_insert_with(|| {
+                groups.push((key, Vec::new()));
+                groups.len() - 1
+            });
+            groups[i].1.push((m, metric_string));
         }
 
-        if self.force_flush {
-            log::trace!("flushing BufWriter");
-            self.writer.flush()?;
+        let attributes_as_columns = self.attributes_as_columns;
+        let force_flush = self.force_flush;
+        for (key, points) in groups {
+            let writer = self.writer(key)?;
+            write_points(writer, &points, attributes_as_columns)?;
+            if force_flush {
+                log::trace!("flushing BufWriter");
+                writer.flush()?;
+            }
         }
         Ok(())
     }
@@ -151,7 +290,9 @@ mod tests {
     };
     use std::{collections::HashSet, time::UNIX_EPOCH};
 
-    use super::{collect_attribute_keys, escape_late_attribute};
+    use rustc_hash::FxHashSet;
+
+    use super::{collect_attribute_keys, escape_late_attribute, sanitize_file_name, unique_file_suffix};
 
     fn simple_point(metric: RawMetricId, value: WrappedMeasurementValue) -> MeasurementPoint {
         MeasurementPoint::new_untyped(
@@ -193,4 +334,20 @@ mod tests {
         let expected = HashSet::from_iter(["k1".to_string(), "k2".to_string()]);
         assert_eq!(result, expected)
     }
+
+    #[test]
+    fn sanitize_file_names() {
+        assert_eq!(sanitize_file_name("rapl_consumed_energy_J"), "rapl_consumed_energy_J");
+        assert_eq!(sanitize_file_name("memory_usage_B/s"), "memory_usage_B_s");
+        assert_eq!(sanitize_file_name("temp_°C"), "temp__C");
+    }
+
+    #[test]
+    fn unique_file_suffixes() {
+        let mut used = FxHashSet::default();
+        assert_eq!(unique_file_suffix(&mut used, "memory_usage_B/s"), "memory_usage_B_s");
+        assert_eq!(unique_file_suffix(&mut used, "memory_usage_B_s"), "memory_usage_B_s-2");
+        assert_eq!(unique_file_suffix(&mut used, "memory_usage_B?s"), "memory_usage_B_s-3");
+        assert_eq!(unique_file_suffix(&mut used, "temp_°C"), "temp__C");
+    }
 }
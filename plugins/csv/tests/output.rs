@@ -266,3 +266,98 @@ fn write_output_config_no_unit_in_metric_name() {
 
     agent.wait_for_shutdown(TIMEOUT).unwrap();
 }
+
+#[test]
+fn write_output_file_per_metric_attributes_as_columns() {
+    let _ = env_logger::Builder::from_default_env().try_init();
+
+    // Prepare the plugin
+
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path().to_path_buf();
+    let dir_2 = dir.clone();
+
+    let config = Config {
+        output_path: dir.join("alumet-output.csv"),
+        file_per_metric: true,
+        attributes_as_columns: true,
+        ..Config::default()
+    };
+
+    let mut plugins = PluginSet::new();
+    plugins.add_plugin(PluginInfo {
+        metadata: PluginMetadata::from_static::<CsvPlugin>(),
+        enabled: true,
+        config: Some(config_to_toml_table(&config)),
+    });
+
+    // Prepare the scenarios
+
+    let expected_u64 = indoc! {
+        r#"metric;timestamp;value;resource_kind;resource_id;consumer_kind;consumer_id;attributes_1
+           test_metric_u64;1970-01-01T00:00:00Z;0;local_machine;;local_machine;;value1
+        "#
+    };
+    let expected_f64 = indoc! {
+        r#"metric;timestamp;value;resource_kind;resource_id;consumer_kind;consumer_id
+           test_metric_f64;1970-01-01T00:00:00Z;0.5;local_machine;;local_machine;
+        "#
+    };
+    // new attribute: the file is rotated and the new header contains all the attributes
+    let expected_u64_rotated = indoc! {
+        r#"metric;timestamp;value;resource_kind;resource_id;consumer_kind;consumer_id;attributes_1;attributes_2
+           test_metric_u64;1970-01-01T00:00:00Z;1;local_machine;;local_machine;;;value2
+        "#
+    };
+
+    let csv_output = OutputName::from_str("csv", "out");
+    let runtime_expectations = RuntimeExpectations::new()
+        .create_metric::<u64>("test_metric_u64", Unit::Unity)
+        .create_metric::<f64>("test_metric_f64", Unit::Unity)
+        .test_output(
+            csv_output.clone(),
+            |ctx| {
+                let metrics = TestMetrics::get(ctx);
+                let point1 = simple_point(metrics.metric_u64, WrappedMeasurementValue::U64(0))
+                    .with_attr("attributes_1", "value1");
+                let point2 = simple_point(metrics.metric_f64, WrappedMeasurementValue::F64(0.5));
+                MeasurementBuffer::from(vec![point1, point2])
+            },
+            move || {
+                let u64_file = dir.join("alumet-output-test_metric_u64.csv");
+                let f64_file = dir.join("alumet-output-test_metric_f64.csv");
+                assert_eq!(fs::read_to_string(u64_file).unwrap(), expected_u64);
+                assert_eq!(fs::read_to_string(f64_file).unwrap(), expected_f64);
+                assert!(!dir.join("alumet-output.csv").exists());
+            },
+        )
+        .test_output(
+            csv_output.clone(),
+            |ctx| {
+                let metrics = TestMetrics::get(ctx);
+                let point = simple_point(metrics.metric_u64, WrappedMeasurementValue::U64(1))
+                    .with_attr("attributes_2", "value2");
+                MeasurementBuffer::from(vec![point])
+            },
+            move || {
+                let u64_file = dir_2.join("alumet-output-test_metric_u64.csv");
+                assert_eq!(fs::read_to_string(u64_file).unwrap(), expected_u64_rotated);
+
+                let rotated: Vec<String> = fs::read_dir(&dir_2)
+                    .unwrap()
+                    .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+                    .filter(|name| name.starts_with("alumet-output-test_metric_u64-"))
+                    .collect();
+                assert_eq!(rotated.len(), 1, "there should be one rotated file, got {rotated:?}");
+                let content = fs::read_to_string(dir_2.join(&rotated[0])).unwrap();
+                assert_eq!(content, expected_u64);
+            },
+        );
+
+    let agent = agent::Builder::new(plugins)
+        .with_expectations(runtime_expectations)
+        .build_and_start()
+        .unwrap();
+
+    agent.wait_for_shutdown(TIMEOUT).unwrap();
+}
@@ -2,4 +2,4 @@ mod client;
 mod de;
 mod ser;
 
-pub use client::{ApiAuthentication, Client, ConnectionSettings, DataSettings};
+pub use client::{ApiAuthentication, Client, ConnectionSettings, DataSettings, LifecycleSettings};
@@ -7,13 +7,17 @@ use reqwest::{
     header::{HeaderMap, HeaderValue, InvalidHeaderValue},
 };
 
-use super::ser::{CreateIndexTemplate, IndexTemplate, Serializer};
+use super::ser::{
+    CreateIndexTemplate, DataStreamTemplate, IndexTemplate, LifecyclePolicy, PutLifecyclePolicy, Serializer,
+};
 
 // OpenSearch/ElasticSearch API client.
 pub struct Client {
     serializer: Serializer,
     client: reqwest::blocking::Client,
     server_url: Url,
+    data_stream: bool,
+    lifecycle: Option<LifecycleSettings>,
 }
 
 pub struct ConnectionSettings {
@@ -25,6 +29,20 @@ pub struct ConnectionSettings {
 pub struct DataSettings {
     pub index_prefix: String,
     pub metric_unit_as_index_suffix: bool,
+    /// Write the measurements into data streams instead of regular indices.
+    pub data_stream: bool,
+    /// ILM policy to attach to the indices, if any.
+    pub lifecycle: Option<LifecycleSettings>,
+}
+
+/// Settings of the ILM (Index Lifecycle Management) policy.
+///
+/// The durations and sizes use the format of ElasticSearch, for instance `30d` or `50gb`.
+pub struct LifecycleSettings {
+    pub policy_name: String,
+    pub delete_after: Option<String>,
+    pub rollover_max_age: Option<String>,
+    pub rollover_max_primary_shard_size: Option<String>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -67,21 +85,58 @@ impl Client {
                 index_prefix: data.index_prefix,
                 metric_unit_as_index_suffix: data.metric_unit_as_index_suffix,
             },
+            data_stream: data.data_stream,
+            lifecycle: data.lifecycle,
         })
     }
 
+    /// Creates the ILM policy (or updates it if it exists), if a policy has been configured.
+    pub fn put_lifecycle_policy(&self) -> anyhow::Result<()> {
+        let Some(lifecycle) = &self.lifecycle else {
+            return Ok(());
+        };
+
+        let mut rollover = serde_json::Map::new();
+        if let Some(max_age) = &lifecycle.rollover_max_age {
+            rollover.insert(String::from("max_age"), max_age.clone().into());
+        }
+        if let Some(max_size) = &lifecycle.rollover_max_primary_shard_size {
+            rollover.insert(String::from("max_primary_shard_size"), max_size.clone().into());
+        }
+        let rollover = (!rollover.is_empty()).then_some(serde_json::Value::Object(rollover));
+        let body = PutLifecyclePolicy {
+            policy: LifecyclePolicy::new(rollover, lifecycle.delete_after.as_deref()),
+        };
+
+        let url = format!("{}_ilm/policy/{}", self.server_url, lifecycle.policy_name);
+        let request = self.client.put(url).json(&body);
+        log::trace!("sending {request:?}");
+
+        let response = request.send().context("could not send request")?;
+        log::trace!("got response {response:?}");
+
+        Self::handle_response(response)?;
+        Ok(())
+    }
+
     pub fn create_index_template(&self) -> anyhow::Result<()> {
         const TEMPLATE_NAME: &str = "alumet_index_template";
 
+        let settings = self
+            .lifecycle
+            .as_ref()
+            .map(|l| serde_json::json!({ "index.lifecycle.name": l.policy_name }));
         let template = IndexTemplate {
+            settings,
             mappings: self.serializer.common_index_mappings(),
         };
         let index_pattern = format!("{}-*", self.serializer.index_prefix);
         let create = CreateIndexTemplate {
             index_patterns: vec![index_pattern],
             template,
+            data_stream: self.data_stream.then_some(DataStreamTemplate {}),
             priority: 80,
-            version: 3,
+            version: 4,
             meta: HashMap::from_iter([("origin".to_string(), "Alumet measurements".to_string())]),
         };
 
@@ -20,9 +20,13 @@ pub struct Serializer {
 
 impl Serializer {
     /// Generates the mappings for an index.
+    ///
+    /// The fields are explicitly typed, including the attributes (through dynamic templates),
+    /// in order to avoid mapping conflicts between measurements of different types.
     pub fn common_index_mappings(&self) -> serde_json::Value {
         json!({
-            "properties": DocMeasurement::properties_definitions()
+            "properties": DocMeasurement::properties_definitions(),
+            "dynamic_templates": DocMeasurement::attributes_dynamic_templates(),
         })
     }
 
@@ -172,6 +176,19 @@ impl<'a> DocMeasurement<'a> {
             field_with_type("resource_id", "keyword"),
             field_with_type("consumer_kind", "keyword"),
             field_with_type("consumer_id", "keyword"),
+            // U64 and F64 values are stored in the same field, which must be able to hold both.
+            field_with_type("value", "double"),
+        ])
+    }
+
+    /// Generates the mappings of the attributes, which are not known in advance.
+    ///
+    /// Strings become keywords and all numbers become doubles, regardless of the first value seen.
+    pub fn attributes_dynamic_templates() -> serde_json::Value {
+        json!([
+            { "attributes_strings": { "match_mapping_type": "string", "mapping": { "type": "keyword" } } },
+            { "attributes_integers": { "match_mapping_type": "long", "mapping": { "type": "double" } } },
+            { "attributes_floats": { "match_mapping_type": "double", "mapping": { "type": "double" } } },
         ])
     }
 }
@@ -180,6 +197,9 @@ impl<'a> DocMeasurement<'a> {
 pub struct CreateIndexTemplate {
     pub index_patterns: Vec<String>,
     pub template: IndexTemplate,
+    /// If set, the indices that match the template are data streams.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data_stream: Option<DataStreamTemplate>,
     pub priority: u32,
     pub version: u32,
     #[serde(rename = "_meta")]
@@ -188,9 +208,48 @@ pub struct CreateIndexTemplate {
 
 #[derive(Debug, Serialize)]
 pub struct IndexTemplate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub settings: Option<serde_json::Value>,
     pub mappings: serde_json::Value,
 }
 
+/// Data stream options of an index template (empty: use the default options).
+#[derive(Debug, Serialize)]
+pub struct DataStreamTemplate {}
+
+/// Body of a request that creates or updates an ILM policy.
+#[derive(Debug, Serialize)]
+pub struct PutLifecyclePolicy {
+    pub policy: LifecyclePolicy,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LifecyclePolicy {
+    pub phases: serde_json::Map<String, serde_json::Value>,
+    #[serde(rename = "_meta")]
+    pub meta: HashMap<String, String>,
+}
+
+impl LifecyclePolicy {
+    /// Builds a policy with an optional rollover in the hot phase, and an optional delete phase.
+    pub fn new(rollover: Option<serde_json::Value>, delete_after: Option<&str>) -> Self {
+        let mut phases = serde_json::Map::new();
+        if let Some(rollover) = rollover {
+            phases.insert(String::from("hot"), json!({ "actions": { "rollover": rollover } }));
+        }
+        if let Some(min_age) = delete_after {
+            phases.insert(
+                String::from("delete"),
+                json!({ "min_age": min_age, "actions": { "delete": {} } }),
+            );
+        }
+        Self {
+            phases,
+            meta: HashMap::from_iter([("origin".to_string(), "Alumet measurements".to_string())]),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use alumet::{
@@ -242,4 +301,68 @@ mod tests {
 }";
         assert_eq!(result, expected_result)
     }
+
+    #[test]
+    fn test_serialize_template() {
+        let serializer = Serializer {
+            index_prefix: String::from("alumet"),
+            metric_unit_as_index_suffix: false,
+        };
+        let create = CreateIndexTemplate {
+            index_patterns: vec![String::from("alumet-*")],
+            template: IndexTemplate {
+                settings: Some(json!({ "index.lifecycle.name": "alumet" })),
+                mappings: serializer.common_index_mappings(),
+            },
+            data_stream: Some(DataStreamTemplate {}),
+            priority: 80,
+            version: 4,
+            meta: HashMap::new(),
+        };
+        let result = serde_json::to_value(&create).unwrap();
+        assert_eq!(result["data_stream"], json!({}));
+        assert_eq!(result["template"]["settings"]["index.lifecycle.name"], "alumet");
+        assert_eq!(
+            result["template"]["mappings"]["properties"]["value"],
+            json!({ "type": "double" })
+        );
+        assert_eq!(
+            result["template"]["mappings"]["properties"]["@timestamp"],
+            json!({ "type": "date_nanos" })
+        );
+        assert_eq!(
+            result["template"]["mappings"]["dynamic_templates"][0]["attributes_strings"]["mapping"],
+            json!({ "type": "keyword" })
+        );
+
+        // without data stream nor ILM
+        let create = CreateIndexTemplate {
+            data_stream: None,
+            template: IndexTemplate {
+                settings: None,
+                mappings: serializer.common_index_mappings(),
+            },
+            ..create
+        };
+        let result = serde_json::to_value(&create).unwrap();
+        assert!(result.get("data_stream").is_none());
+        assert!(result["template"].get("settings").is_none());
+    }
+
+    #[test]
+    fn test_serialize_lifecycle_policy() {
+        let policy = LifecyclePolicy::new(Some(json!({ "max_age": "1d" })), Some("30d"));
+        let result = serde_json::to_value(PutLifecyclePolicy { policy }).unwrap();
+        assert_eq!(
+            result["policy"]["phases"],
+            json!({
+                "hot": { "actions": { "rollover": { "max_age": "1d" } } },
+                "delete": { "min_age": "30d", "actions": { "delete": {} } },
+            })
+        );
+
+        let policy = LifecyclePolicy::new(None, None);
+        let result = serde_json::to_value(PutLifecyclePolicy { policy }).unwrap();
+        assert_eq!(result["policy"]["phases"], json!({}));
+    }
 }
@@ -46,6 +46,13 @@ impl AlumetPlugin for ElasticSearchPlugin {
         // Parse auth settings
         let auth = api::ApiAuthentication::try_from(config.auth).context("invalid auth config")?;
 
+        // Parse lifecycle settings
+        let lifecycle = config
+            .lifecycle
+            .map(|l| l.into_settings(config.data_stream))
+            .transpose()
+            .context("invalid lifecycle config")?;
+
         // Create the client
         let client = api::Client::new(
             api::ConnectionSettings {
@@ -56,10 +63,17 @@ impl AlumetPlugin for ElasticSearchPlugin {
             api::DataSettings {
                 index_prefix: config.index_prefix,
                 metric_unit_as_index_suffix: config.metric_unit_as_index_suffix,
+                data_stream: config.data_stream,
+                lifecycle,
             },
         )
         .context("failed to initialize api client")?;
 
+        // Create the ILM policy before the template that references it.
+        client
+            .put_lifecycle_policy()
+            .context("failed to create the lifecycle policy")?;
+
         // Create a template for elastic indices that will be populated by the output.
         log::info!("Creating template for Alumet indices...");
         client
@@ -115,6 +129,49 @@ pub mod config {
         pub index_prefix: String,
         /// Controls the use of an optional suffix for each index (format `{index_prefix}-{metric_name}-{metric_unit_unique_name}`).
         pub metric_unit_as_index_suffix: bool,
+        /// Write the measurements into data streams (named like the indices) instead of regular indices.
+        #[serde(default)]
+        pub data_stream: bool,
+        /// Index lifecycle management (ILM), for the retention of the measurements.
+        #[serde(default)]
+        pub lifecycle: Option<LifecycleConfig>,
+    }
+
+    /// ILM policy attached to the indices or data streams.
+    ///
+    /// The durations and sizes use the format of ElasticSearch, for instance `30d` or `50gb`.
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct LifecycleConfig {
+        /// Name of the ILM policy, which is created or updated at startup.
+        #[serde(default = "default_policy_name")]
+        pub policy_name: String,
+        /// Delete the data after this duration.
+        pub delete_after: Option<String>,
+        /// Roll over the backing index of the data streams after this duration.
+        pub rollover_max_age: Option<String>,
+        /// Roll over the backing index of the data streams when its primary shard reaches this size.
+        pub rollover_max_primary_shard_size: Option<String>,
+    }
+
+    fn default_policy_name() -> String {
+        String::from("alumet-measurements")
+    }
+
+    impl LifecycleConfig {
+        pub fn into_settings(self, data_stream: bool) -> anyhow::Result<api::LifecycleSettings> {
+            let rollover = self.rollover_max_age.is_some() || self.rollover_max_primary_shard_size.is_some();
+            if rollover && !data_stream {
+                return Err(anyhow::anyhow!(
+                    "rollover is only supported with data streams, please set data_stream = true"
+                ));
+            }
+            Ok(api::LifecycleSettings {
+                policy_name: self.policy_name,
+                delete_after: self.delete_after,
+                rollover_max_age: self.rollover_max_age,
+                rollover_max_primary_shard_size: self.rollover_max_primary_shard_size,
+            })
+        }
     }
 
     #[derive(Debug, Serialize, Deserialize)]
@@ -137,6 +194,8 @@ pub mod config {
                 allow_insecure: false,
                 index_prefix: String::from("alumet"),
                 metric_unit_as_index_suffix: false,
+                data_stream: false,
+                lifecycle: None,
             }
         }
     }
@@ -183,7 +242,10 @@ mod tests {
         time::Duration,
     };
 
-    use crate::{ElasticSearchPlugin, api, plugin::config::AuthConfig};
+    use crate::{
+        api,
+        plugin::config::{AuthConfig, LifecycleConfig},
+    };
 
     use super::config::Config;
 
@@ -229,6 +291,45 @@ mod tests {
         assert_eq!(parsed.allow_insecure, false);
         assert_eq!(parsed.index_prefix, "alumet");
         assert_eq!(parsed.metric_unit_as_index_suffix, true);
+        assert_eq!(parsed.data_stream, false);
+        assert!(parsed.lifecycle.is_none());
+    }
+
+    #[test]
+    fn parse_lifecycle_config() {
+        let config = r#"
+            server_url = "http://localhost:9200"
+            allow_insecure = false
+            index_prefix = "alumet"
+            metric_unit_as_index_suffix = false
+            data_stream = true
+
+            [auth.api_key]
+            key = "abcd"
+
+            [lifecycle]
+            delete_after = "30d"
+            rollover_max_age = "1d"
+        "#;
+        let parsed: Config = toml::from_str(config).expect("config should be valid");
+        assert!(parsed.data_stream);
+        let lifecycle = parsed.lifecycle.unwrap();
+        assert_eq!(lifecycle.policy_name, "alumet-measurements");
+        assert_eq!(lifecycle.delete_after.as_deref(), Some("30d"));
+        assert_eq!(lifecycle.rollover_max_age.as_deref(), Some("1d"));
+        assert_eq!(lifecycle.rollover_max_primary_shard_size, None);
+
+        let settings = lifecycle.into_settings(true).unwrap();
+        assert_eq!(settings.policy_name, "alumet-measurements");
+
+        // rollover requires data streams
+        let lifecycle = LifecycleConfig {
+            policy_name: String::from("policy"),
+            delete_after: None,
+            rollover_max_age: None,
+            rollover_max_primary_shard_size: Some(String::from("50gb")),
+        };
+        assert!(lifecycle.into_settings(false).is_err());
     }
 
     #[test]
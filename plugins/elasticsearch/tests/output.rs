@@ -18,8 +18,11 @@ use alumet::{
     units::Unit,
 };
 
-use mockito::Mock;
-use plugin_elasticsearch::{ElasticSearchPlugin, plugin::config::Config};
+use mockito::{Matcher, Mock};
+use plugin_elasticsearch::{
+    ElasticSearchPlugin,
+    plugin::config::{Config, LifecycleConfig},
+};
 
 use indoc::indoc;
 
@@ -222,3 +225,78 @@ fn elastic_output() {
 
     agent.wait_for_shutdown(TIMEOUT).unwrap();
 }
+
+#[test]
+fn elastic_output_data_stream_lifecycle() {
+    let _ = env_logger::Builder::from_default_env().try_init();
+
+    let mut server = mockito::Server::new_with_opts(mockito::ServerOpts {
+        host: "127.0.0.1",
+        ..Default::default()
+    });
+
+    // The ILM policy and the template are expected once on startup
+    let put_policy = server
+        .mock("PUT", "/_ilm/policy/alumet-measurements")
+        .match_body(Matcher::PartialJsonString(
+            r#"{"policy":{"phases":{"hot":{"actions":{"rollover":{"max_age":"1d"}}},"delete":{"min_age":"30d","actions":{"delete":{}}}}}}"#.to_string(),
+        ))
+        .with_status(200)
+        .create();
+    let create_index_template = server
+        .mock("PUT", "/_index_template/alumet_index_template")
+        .match_body(Matcher::PartialJsonString(
+            r#"{"index_patterns":["alumet-*"],"data_stream":{},"template":{"settings":{"index.lifecycle.name":"alumet-measurements"},"mappings":{"properties":{"value":{"type":"double"}}}}}"#.to_string(),
+        ))
+        .with_status(200)
+        .create();
+    let bulk = server
+        .mock("PUT", "/_bulk")
+        .match_body(indoc! {
+            r#"{"create":{"_index":"alumet-test_metric_u64"}}
+            {"@timestamp":"1970-01-01T00:00:00Z","resource_kind":"local_machine","resource_id":"","consumer_kind":"local_machine","consumer_id":"","value":0}
+            "#})
+        .with_status(200)
+        .create();
+
+    let config = Config {
+        server_url: server.url(),
+        data_stream: true,
+        lifecycle: Some(LifecycleConfig {
+            policy_name: String::from("alumet-measurements"),
+            delete_after: Some(String::from("30d")),
+            rollover_max_age: Some(String::from("1d")),
+            rollover_max_primary_shard_size: None,
+        }),
+        ..Config::default()
+    };
+
+    let mut plugins = PluginSet::new();
+    plugins.add_plugin(PluginInfo {
+        metadata: PluginMetadata::from_static::<ElasticSearchPlugin>(),
+        enabled: true,
+        config: Some(config_to_toml_table(&config)),
+    });
+
+    let elastic_output = OutputName::from_str("elasticsearch", "api");
+    let runtime_expectations = RuntimeExpectations::new()
+        .create_metric::<u64>("test_metric_u64", Unit::Unity)
+        .test_output(
+            elastic_output,
+            |ctx| {
+                let metric = ctx.metrics().by_name("test_metric_u64").expect("metric should exist").0;
+                MeasurementBuffer::from(vec![simple_point(metric, WrappedMeasurementValue::U64(0))])
+            },
+            move || {
+                put_policy.assert();
+                create_index_template.assert();
+                bulk.assert();
+            },
+        );
+    let agent = agent::Builder::new(plugins)
+        .with_expectations(runtime_expectations)
+        .build_and_start()
+        .unwrap();
+
+    agent.wait_for_shutdown(TIMEOUT).unwrap();
+}
@@ -0,0 +1,117 @@
+mod source;
+mod sysfs;
+
+use std::{path::PathBuf, time::Duration};
+
+use alumet::{
+    pipeline::elements::source::trigger::TriggerSpec,
+    plugin::{
+        AlumetPluginStart, ConfigTable,
+        rust::{AlumetPlugin, deserialize_config, serialize_config},
+    },
+};
+use anyhow::{Context, anyhow};
+use serde::{Deserialize, Serialize};
+
+use source::HwmonSource;
+use sysfs::ChipFilter;
+pub use sysfs::SensorKind;
+
+pub struct HwmonPlugin {
+    config: Config,
+}
+
+impl AlumetPlugin for HwmonPlugin {
+    fn name() -> &'static str {
+        "hwmon"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(HwmonPlugin { config }))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let config = &self.config;
+        let filter = ChipFilter::new(&config.include_chips, &config.exclude_chips)?;
+        let chips = sysfs::explore(&config.root_path, &config.sensor_kinds, &filter)
+            .context("could not explore the hwmon devices")?;
+
+        for chip in &chips {
+            log::info!(
+                "Found hwmon chip {} ({}) with {} sensor(s)",
+                chip.name,
+                chip.dir_name,
+                chip.sensors.len()
+            );
+            for sensor in &chip.sensors {
+                let label = sensor.label.as_deref().unwrap_or("-");
+                log::debug!("  - {}: {label}", sensor.id());
+            }
+        }
+
+        let metrics = source::create_metrics(alumet, &config.sensor_kinds)?;
+        let source = HwmonSource::new(chips, &metrics);
+        if source.is_empty() {
+            return Err(anyhow!(
+                "no hwmon sensor found in {:?}, check the configuration of the plugin",
+                config.root_path
+            ));
+        }
+
+        let trigger = TriggerSpec::builder(config.poll_interval)
+            .flush_interval(config.flush_interval)
+            .build()?;
+        alumet.add_source("sensors", Box::new(source), trigger)?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Initial interval between two measurements.
+    #[serde(with = "humantime_serde")]
+    pub poll_interval: Duration,
+
+    /// Initial interval between two measurement flushes.
+    #[serde(with = "humantime_serde")]
+    pub flush_interval: Duration,
+
+    /// Directory that contains the hwmon chips.
+    pub root_path: PathBuf,
+
+    /// Kinds of sensors to measure.
+    pub sensor_kinds: Vec<SensorKind>,
+
+    /// Regexes that select the chips by name. If empty, all the chips are selected.
+    pub include_chips: Vec<String>,
+
+    /// Regexes that exclude some chips by name, applied after `include_chips`.
+    pub exclude_chips: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(1), // 1Hz
+            flush_interval: Duration::from_secs(5),
+            root_path: PathBuf::from("/sys/class/hwmon"),
+            sensor_kinds: SensorKind::ALL.to_vec(),
+            include_chips: Vec::new(),
+            exclude_chips: Vec::new(),
+        }
+    }
+}
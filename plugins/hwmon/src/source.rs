@@ -0,0 +1,184 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Read, Seek},
+    path::PathBuf,
+};
+
+use alumet::{
+    measurement::{
+        AttributeValue, MeasurementAccumulator, MeasurementPoint, Timestamp, WrappedMeasurementType,
+        WrappedMeasurementValue,
+    },
+    metrics::RawMetricId,
+    pipeline::{Source, elements::error::PollError},
+    plugin::AlumetPluginStart,
+    resources::{Resource, ResourceConsumer},
+};
+use anyhow::{Context, anyhow};
+
+use crate::sysfs::{Chip, Sensor, SensorKind};
+
+/// Returns the name and the description of the metric of a kind of sensor.
+fn metric_info(kind: SensorKind) -> (&'static str, &'static str) {
+    match kind {
+        SensorKind::Temp => ("hwmon_temperature", "temperature reported by a hwmon sensor"),
+        SensorKind::Fan => ("hwmon_fan_speed", "fan speed reported by a hwmon sensor"),
+        SensorKind::In => ("hwmon_voltage", "voltage reported by a hwmon sensor"),
+        SensorKind::Curr => ("hwmon_current", "current reported by a hwmon sensor"),
+        SensorKind::Power => ("hwmon_power", "power reported by a hwmon sensor"),
+        SensorKind::Energy => (
+            "hwmon_energy_consumed",
+            "energy consumed since the previous measurement, according to a hwmon sensor",
+        ),
+    }
+}
+
+/// Creates the metric of each kind of sensor.
+pub fn create_metrics(
+    alumet: &mut AlumetPluginStart,
+    kinds: &[SensorKind],
+) -> anyhow::Result<HashMap<SensorKind, RawMetricId>> {
+    let mut metrics = HashMap::with_capacity(kinds.len());
+    for kind in kinds {
+        let (name, description) = metric_info(*kind);
+        let id = alumet.create_metric_untyped(name, kind.value_type(), kind.unit(), description)?;
+        metrics.insert(*kind, id);
+    }
+    Ok(metrics)
+}
+
+/// Reads the value of a sensor.
+struct Probe {
+    kind: SensorKind,
+    metric: RawMetricId,
+    path: PathBuf,
+    file: File,
+    attributes: Vec<(String, AttributeValue)>,
+    /// Previous value of the energy counter.
+    previous_energy: Option<u64>,
+    /// Did the last read fail? Used to avoid logging the same error at each poll.
+    failing: bool,
+}
+
+impl Probe {
+    fn new(chip: &Chip, sensor: Sensor, metric: RawMetricId) -> anyhow::Result<Self> {
+        let file = File::open(&sensor.value_path).with_context(|| format!("failed to open {:?}", sensor.value_path))?;
+        let id = sensor.id();
+        let attributes = vec![
+            (String::from("chip"), AttributeValue::String(chip.name.clone())),
+            (String::from("hwmon"), AttributeValue::String(chip.dir_name.clone())),
+            (
+                String::from("label"),
+                AttributeValue::String(sensor.label.unwrap_or_else(|| id.clone())),
+            ),
+            (String::from("sensor"), AttributeValue::String(id)),
+        ];
+        Ok(Self {
+            kind: sensor.kind,
+            metric,
+            path: sensor.value_path,
+            file,
+            attributes,
+            previous_energy: None,
+            failing: false,
+        })
+    }
+
+    fn read(&mut self, buf: &mut String) -> anyhow::Result<i64> {
+        buf.clear();
+        self.file.rewind()?;
+        self.file.read_to_string(buf)?;
+        let value = buf
+            .trim_ascii_end()
+            .parse()
+            .with_context(|| format!("invalid content {buf:?}"))?;
+        Ok(value)
+    }
+
+    /// Returns the value to push, if any.
+    fn measure(&mut self, buf: &mut String) -> anyhow::Result<Option<WrappedMeasurementValue>> {
+        let raw = self.read(buf)?;
+        let value = match self.kind.value_type() {
+            WrappedMeasurementType::F64 => WrappedMeasurementValue::F64(raw as f64),
+            WrappedMeasurementType::U64 => {
+                let raw = u64::try_from(raw).map_err(|_| anyhow!("unexpected negative value {raw}"))?;
+                if self.kind == SensorKind::Energy {
+                    // The counter is cumulative: compute the difference with the previous value.
+                    // If the counter has been reset, wait for the next value.
+                    let previous = self.previous_energy.replace(raw);
+                    match previous.and_then(|prev| raw.checked_sub(prev)) {
+                        Some(delta) => WrappedMeasurementValue::U64(delta),
+                        None => return Ok(None),
+                    }
+                } else {
+                    WrappedMeasurementValue::U64(raw)
+                }
+            }
+        };
+        Ok(Some(value))
+    }
+}
+
+pub struct HwmonSource {
+    probes: Vec<Probe>,
+    buf: String,
+}
+
+impl HwmonSource {
+    /// Opens the sensors of the chips. The sensors that cannot be opened are ignored.
+    pub fn new(chips: Vec<Chip>, metrics: &HashMap<SensorKind, RawMetricId>) -> Self {
+        let mut probes = Vec::new();
+        for mut chip in chips {
+            for sensor in std::mem::take(&mut chip.sensors) {
+                let metric = metrics[&sensor.kind];
+                match Probe::new(&chip, sensor, metric) {
+                    Ok(probe) => probes.push(probe),
+                    Err(e) => log::warn!("{e:#}, the sensor is ignored"),
+                }
+            }
+        }
+        Self {
+            probes,
+            buf: String::with_capacity(16),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.probes.is_empty()
+    }
+}
+
+impl Source for HwmonSource {
+    fn poll(&mut self, measurements: &mut MeasurementAccumulator, t: Timestamp) -> Result<(), PollError> {
+        for probe in &mut self.probes {
+            match probe.measure(&mut self.buf) {
+                Ok(value) => {
+                    if probe.failing {
+                        log::info!("sensor {:?} can be read again", probe.path);
+                        probe.failing = false;
+                    }
+                    if let Some(value) = value {
+                        let point = MeasurementPoint::new_untyped(
+                            t,
+                            probe.metric,
+                            Resource::LocalMachine,
+                            ResourceConsumer::LocalMachine,
+                            value,
+                        )
+                        .with_attr_slice(&probe.attributes);
+                        measurements.push(point);
+                    }
+                }
+                Err(e) => {
+                    // Some sensors are temporarily unavailable, for instance when a device is asleep.
+                    if !probe.failing {
+                        log::warn!("failed to read sensor {:?}: {e:#}", probe.path);
+                        probe.failing = true;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,361 @@
+//! Discovery of the hwmon chips and sensors.
+//!
+//! See <https://docs.kernel.org/hwmon/sysfs-interface.html>.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use alumet::{
+    measurement::WrappedMeasurementType,
+    units::{PrefixedUnit, Unit},
+};
+use anyhow::Context;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Kind of hwmon sensor, named after the prefix of its sysfs files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SensorKind {
+    /// Temperature, in millidegree Celsius.
+    Temp,
+    /// Fan speed, in revolutions per minute.
+    Fan,
+    /// Voltage, in millivolt.
+    In,
+    /// Current, in milliampere.
+    Curr,
+    /// Power, in microwatt.
+    Power,
+    /// Cumulative energy, in microjoule.
+    Energy,
+}
+
+impl SensorKind {
+    pub const ALL: [SensorKind; 6] = [
+        SensorKind::Temp,
+        SensorKind::Fan,
+        SensorKind::In,
+        SensorKind::Curr,
+        SensorKind::Power,
+        SensorKind::Energy,
+    ];
+
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            SensorKind::Temp => "temp",
+            SensorKind::Fan => "fan",
+            SensorKind::In => "in",
+            SensorKind::Curr => "curr",
+            SensorKind::Power => "power",
+            SensorKind::Energy => "energy",
+        }
+    }
+
+    fn from_prefix(prefix: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|k| k.prefix() == prefix)
+    }
+
+    /// Unit of the values, as defined by the sysfs interface.
+    pub fn unit(&self) -> PrefixedUnit {
+        match self {
+            SensorKind::Temp => PrefixedUnit::milli(Unit::DegreeCelsius),
+            SensorKind::Fan => PrefixedUnit::from(Unit::Custom {
+                unique_name: String::from("{rpm}"),
+                display_name: String::from("rpm"),
+            }),
+            SensorKind::In => PrefixedUnit::milli(Unit::Volt),
+            SensorKind::Curr => PrefixedUnit::milli(Unit::Ampere),
+            SensorKind::Power => PrefixedUnit::micro(Unit::Watt),
+            SensorKind::Energy => PrefixedUnit::micro(Unit::Joule),
+        }
+    }
+
+    /// Type of the values. The temperatures, voltages and currents can be negative.
+    pub fn value_type(&self) -> WrappedMeasurementType {
+        match self {
+            SensorKind::Temp | SensorKind::In | SensorKind::Curr => WrappedMeasurementType::F64,
+            SensorKind::Fan | SensorKind::Power | SensorKind::Energy => WrappedMeasurementType::U64,
+        }
+    }
+}
+
+/// A hwmon chip, i.e. a directory `hwmonN`.
+#[derive(Debug)]
+pub struct Chip {
+    /// Name of the directory, like `hwmon3`. Not stable across reboots.
+    pub dir_name: String,
+    /// Name of the chip, like `coretemp` or `nvme`.
+    pub name: String,
+    pub sensors: Vec<Sensor>,
+}
+
+/// A sensor of a chip, like `temp1`.
+#[derive(Debug, PartialEq)]
+pub struct Sensor {
+    pub kind: SensorKind,
+    pub index: u32,
+    /// Content of the `_label` file, if any.
+    pub label: Option<String>,
+    /// File that contains the value.
+    pub value_path: PathBuf,
+}
+
+impl Sensor {
+    /// Returns the name of the sensor, like `temp1`.
+    pub fn id(&self) -> String {
+        format!("{}{}", self.kind.prefix(), self.index)
+    }
+}
+
+/// Selects the chips by name.
+pub struct ChipFilter {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl ChipFilter {
+    /// Creates a filter. The regexes must match the whole name of the chip.
+    /// An empty `include` list accepts all the chips that are not excluded.
+    pub fn new(include: &[String], exclude: &[String]) -> anyhow::Result<Self> {
+        fn compile(patterns: &[String]) -> anyhow::Result<Vec<Regex>> {
+            patterns
+                .iter()
+                .map(|p| Regex::new(&format!("^(?:{p})$")).with_context(|| format!("invalid chip regex {p:?}")))
+                .collect()
+        }
+        Ok(Self {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+        })
+    }
+
+    pub fn accepts(&self, chip_name: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|r| r.is_match(chip_name)))
+            && !self.exclude.iter().any(|r| r.is_match(chip_name))
+    }
+}
+
+/// Explores a tree of hwmon chips, like `/sys/class/hwmon`.
+///
+/// ## Expected file layout
+///
+/// ```txt
+/// /sys/class/hwmon/
+/// |− hwmon0
+///     |− name
+///     |− temp1_input
+///     |− temp1_label
+///     |− …
+/// |− hwmon1
+///     |− device
+///         |− name
+///         |− in0_input
+///         |− …
+/// ```
+///
+/// Old drivers put the files in the `device` subdirectory.
+pub fn explore(root: &Path, kinds: &[SensorKind], filter: &ChipFilter) -> anyhow::Result<Vec<Chip>> {
+    let mut entries: Vec<_> = std::fs::read_dir(root)
+        .with_context(|| format!("failed to read dir {root:?}"))?
+        .collect::<Result<_, _>>()?;
+    entries.sort_by_key(|e| e.file_name());
+
+    let mut chips = Vec::new();
+    for entry in entries {
+        let chip_path = entry.path();
+        let dir_name = entry.file_name().to_string_lossy().into_owned();
+        let attr_path = if chip_path.join("name").is_file() {
+            chip_path
+        } else if chip_path.join("device/name").is_file() {
+            chip_path.join("device")
+        } else {
+            log::debug!("ignoring {chip_path:?}: no name file");
+            continue;
+        };
+        let name_path = attr_path.join("name");
+        let name = std::fs::read_to_string(&name_path)
+            .with_context(|| format!("failed to read {name_path:?}"))?
+            .trim_ascii()
+            .to_owned();
+        if !filter.accepts(&name) {
+            log::debug!("ignoring chip {name} ({dir_name}): excluded by the configuration");
+            continue;
+        }
+        let sensors = find_sensors(&attr_path, kinds)?;
+        chips.push(Chip {
+            dir_name,
+            name,
+            sensors,
+        });
+    }
+    Ok(chips)
+}
+
+#[derive(Default)]
+struct SensorFiles {
+    input: Option<PathBuf>,
+    average: Option<PathBuf>,
+    label: Option<PathBuf>,
+}
+
+fn find_sensors(dir: &Path, kinds: &[SensorKind]) -> anyhow::Result<Vec<Sensor>> {
+    let mut files: BTreeMap<(SensorKind, u32), SensorFiles> = BTreeMap::new();
+    for entry in std::fs::read_dir(dir).with_context(|| format!("failed to read dir {dir:?}"))? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let name = file_name.to_string_lossy();
+        let Some((kind, index, item)) = parse_file_name(&name) else {
+            continue;
+        };
+        if !kinds.contains(&kind) {
+            continue;
+        }
+        let sensor = files.entry((kind, index)).or_default();
+        match item {
+            "input" => sensor.input = Some(entry.path()),
+            "average" => sensor.average = Some(entry.path()),
+            "label" => sensor.label = Some(entry.path()),
+            _ => (),
+        }
+    }
+
+    let mut sensors = Vec::with_capacity(files.len());
+    for ((kind, index), files) in files {
+        // Some power sensors only provide an average.
+        let value_path = match (files.input, files.average) {
+            (Some(input), _) => input,
+            (None, Some(average)) if kind == SensorKind::Power => average,
+            _ => continue,
+        };
+        let label = match files.label {
+            Some(path) => {
+                let label = std::fs::read_to_string(&path).with_context(|| format!("failed to read {path:?}"))?;
+                Some(label.trim_ascii().to_owned())
+            }
+            None => None,
+        };
+        sensors.push(Sensor {
+            kind,
+            index,
+            label,
+            value_path,
+        });
+    }
+    Ok(sensors)
+}
+
+/// Parses a file name like `temp1_input` into `(Temp, 1, "input")`.
+fn parse_file_name(file_name: &str) -> Option<(SensorKind, u32, &str)> {
+    let (sensor, item) = file_name.split_once('_')?;
+    let digits = sensor.find(|c: char| c.is_ascii_digit())?;
+    let kind = SensorKind::from_prefix(&sensor[..digits])?;
+    let index = sensor[digits..].parse().ok()?;
+    Some((kind, index, item))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use pretty_assertions::assert_eq;
+
+    use super::{ChipFilter, SensorKind, explore, parse_file_name};
+
+    #[test]
+    fn file_names() {
+        assert_eq!(parse_file_name("temp1_input"), Some((SensorKind::Temp, 1, "input")));
+        assert_eq!(parse_file_name("in0_label"), Some((SensorKind::In, 0, "label")));
+        assert_eq!(
+            parse_file_name("power12_average"),
+            Some((SensorKind::Power, 12, "average"))
+        );
+        assert_eq!(parse_file_name("energy1_input"), Some((SensorKind::Energy, 1, "input")));
+        assert_eq!(parse_file_name("intrusion0_alarm"), None);
+        assert_eq!(parse_file_name("pwm1_enable"), None);
+        assert_eq!(parse_file_name("name"), None);
+        assert_eq!(parse_file_name("temp_input"), None);
+    }
+
+    #[test]
+    fn filter() {
+        let all = ChipFilter::new(&[], &[]).unwrap();
+        assert!(all.accepts("coretemp"));
+
+        let filter = ChipFilter::new(&[String::from("nvme"), String::from("k10temp|coretemp")], &[]).unwrap();
+        assert!(filter.accepts("nvme"));
+        assert!(filter.accepts("coretemp"));
+        assert!(!filter.accepts("nvme2"), "the regex must match the whole name");
+        assert!(!filter.accepts("acpitz"));
+
+        let filter = ChipFilter::new(&[], &[String::from("acpi.*")]).unwrap();
+        assert!(filter.accepts("nvme"));
+        assert!(!filter.accepts("acpitz"));
+
+        assert!(ChipFilter::new(&[String::from("(")], &[]).is_err());
+    }
+
+    fn write(root: &Path, path: &str, content: &str) {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn explore_fake_sysfs() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let root = tmp.path();
+        write(root, "hwmon0/name", "coretemp\n");
+        write(root, "hwmon0/temp1_input", "45000\n");
+        write(root, "hwmon0/temp1_label", "Package id 0\n");
+        write(root, "hwmon0/temp2_input", "43000\n");
+        write(root, "hwmon0/temp2_crit", "100000\n");
+        // old layout
+        write(root, "hwmon1/device/name", "it8728\n");
+        write(root, "hwmon1/device/fan1_input", "1200\n");
+        write(root, "hwmon1/device/in0_input", "1104\n");
+        write(root, "hwmon1/device/power1_average", "15000000\n");
+        write(root, "hwmon1/device/curr1_max", "1000\n");
+        // no name: ignored
+        write(root, "hwmon2/temp1_input", "1\n");
+        // excluded
+        write(root, "hwmon3/name", "acpitz\n");
+        write(root, "hwmon3/temp1_input", "27800\n");
+
+        let filter = ChipFilter::new(&[], &[String::from("acpitz")])?;
+        let chips = explore(root, &SensorKind::ALL, &filter)?;
+        assert_eq!(chips.len(), 2);
+
+        assert_eq!(chips[0].dir_name, "hwmon0");
+        assert_eq!(chips[0].name, "coretemp");
+        let sensors: Vec<_> = chips[0].sensors.iter().map(|s| (s.id(), s.label.as_deref())).collect();
+        assert_eq!(
+            sensors,
+            vec![
+                (String::from("temp1"), Some("Package id 0")),
+                (String::from("temp2"), None)
+            ]
+        );
+
+        assert_eq!(chips[1].name, "it8728");
+        let sensors: Vec<_> = chips[1].sensors.iter().map(|s| s.id()).collect();
+        assert_eq!(sensors, vec!["fan1", "in0", "power1"], "curr1 has no input");
+        assert_eq!(
+            chips[1].sensors[2].value_path,
+            root.join("hwmon1/device/power1_average")
+        );
+
+        let chips = explore(root, &[SensorKind::Fan], &filter)?;
+        assert!(chips[0].sensors.is_empty());
+        assert_eq!(chips[1].sensors.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn explore_missing_root() {
+        let filter = ChipFilter::new(&[], &[]).unwrap();
+        assert!(explore(Path::new("/nonexistent/hwmon"), &SensorKind::ALL, &filter).is_err());
+    }
+}
@@ -0,0 +1,162 @@
+use std::{path::Path, time::Duration};
+
+use alumet::{
+    agent::{
+        self,
+        plugin::{PluginInfo, PluginSet},
+    },
+    measurement::{MeasurementPoint, WrappedMeasurementValue},
+    pipeline::naming::SourceName,
+    plugin::PluginMetadata,
+    test::{RuntimeExpectations, StartupExpectations},
+    units::{PrefixedUnit, Unit},
+};
+use plugin_hwmon::{Config, HwmonPlugin, SensorKind};
+use pretty_assertions::assert_eq;
+use tempfile::tempdir;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn config_to_toml_table(config: &Config) -> toml::Table {
+    toml::Value::try_from(config).unwrap().as_table().unwrap().clone()
+}
+
+fn write(root: &Path, path: &str, content: &str) {
+    let path = root.join(path);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, content).unwrap();
+}
+
+fn attr(p: &MeasurementPoint, key: &str) -> String {
+    p.attributes()
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| v.to_string())
+        .unwrap_or_default()
+}
+
+/// Returns the value of the point that comes from the sensor `sensor` of the chip `chip`.
+fn find_value(points: &[&MeasurementPoint], chip: &str, sensor: &str) -> Option<WrappedMeasurementValue> {
+    points
+        .iter()
+        .find(|p| attr(p, "chip") == chip && attr(p, "sensor") == sensor)
+        .map(|p| p.value.clone())
+}
+
+#[test]
+fn plugin_with_fake_sysfs() {
+    let _ = env_logger::Builder::from_default_env().try_init();
+
+    let tmp = tempdir().unwrap();
+    let root = tmp.path().to_owned();
+    write(&root, "hwmon0/name", "coretemp\n");
+    write(&root, "hwmon0/temp1_input", "45000\n");
+    write(&root, "hwmon0/temp1_label", "Package id 0\n");
+    write(&root, "hwmon1/name", "acpitz\n");
+    write(&root, "hwmon1/temp1_input", "27800\n");
+    write(&root, "hwmon2/device/name", "it8728\n");
+    write(&root, "hwmon2/device/fan1_input", "1200\n");
+    write(&root, "hwmon2/device/in0_input", "1104\n");
+    write(&root, "hwmon2/device/power1_average", "15000000\n");
+    write(&root, "hwmon2/device/energy1_input", "1000000\n");
+
+    let config = Config {
+        poll_interval: Duration::from_secs(1),
+        flush_interval: Duration::from_secs(1),
+        root_path: root.clone(),
+        exclude_chips: vec![String::from("acpi.*")],
+        ..Config::default()
+    };
+    let mut plugins = PluginSet::new();
+    plugins.add_plugin(PluginInfo {
+        metadata: PluginMetadata::from_static::<HwmonPlugin>(),
+        enabled: true,
+        config: Some(config_to_toml_table(&config)),
+    });
+
+    let startup = StartupExpectations::new()
+        .expect_metric::<f64>("hwmon_temperature", PrefixedUnit::milli(Unit::DegreeCelsius))
+        .expect_metric::<f64>("hwmon_voltage", PrefixedUnit::milli(Unit::Volt))
+        .expect_metric::<u64>("hwmon_power", PrefixedUnit::micro(Unit::Watt))
+        .expect_metric::<u64>("hwmon_energy_consumed", PrefixedUnit::micro(Unit::Joule))
+        .expect_source("hwmon", "sensors");
+
+    let source = SourceName::from_str("hwmon", "sensors");
+    let runtime = RuntimeExpectations::new()
+        .test_source(
+            source.clone(),
+            || {},
+            |ctx| {
+                let m = ctx.measurements();
+                let points: Vec<_> = m.iter().collect();
+                let temp = ctx.metrics().by_name("hwmon_temperature").unwrap().0;
+                let energy = ctx.metrics().by_name("hwmon_energy_consumed").unwrap().0;
+
+                let package = points
+                    .iter()
+                    .find(|p| p.metric == temp && attr(p, "chip") == "coretemp")
+                    .expect("missing coretemp temperature");
+                assert_eq!(package.value, WrappedMeasurementValue::F64(45000.0));
+                assert_eq!(attr(package, "label"), "Package id 0");
+                assert_eq!(attr(package, "hwmon"), "hwmon0");
+
+                assert_eq!(find_value(&points, "acpitz", "temp1"), None, "acpitz is excluded");
+                assert_eq!(
+                    find_value(&points, "it8728", "fan1"),
+                    Some(WrappedMeasurementValue::U64(1200))
+                );
+                assert_eq!(
+                    find_value(&points, "it8728", "in0"),
+                    Some(WrappedMeasurementValue::F64(1104.0))
+                );
+                assert_eq!(
+                    find_value(&points, "it8728", "power1"),
+                    Some(WrappedMeasurementValue::U64(15000000))
+                );
+                assert!(points.iter().all(|p| p.metric != energy), "no energy on the first poll");
+            },
+        )
+        .test_source(
+            source,
+            move || {
+                // the energy counter increases between the two polls
+                write(&root, "hwmon2/device/energy1_input", "1500000\n");
+            },
+            |ctx| {
+                let m = ctx.measurements();
+                let energy = ctx.metrics().by_name("hwmon_energy_consumed").unwrap().0;
+                let points: Vec<_> = m.iter().filter(|p| p.metric == energy).collect();
+                assert_eq!(
+                    find_value(&points, "it8728", "energy1"),
+                    Some(WrappedMeasurementValue::U64(500000))
+                );
+            },
+        );
+
+    let agent = agent::Builder::new(plugins)
+        .with_expectations(startup)
+        .with_expectations(runtime)
+        .build_and_start()
+        .unwrap();
+    agent.wait_for_shutdown(TIMEOUT).unwrap();
+}
+
+#[test]
+fn plugin_without_sensor() {
+    let tmp = tempdir().unwrap();
+    write(tmp.path(), "hwmon0/name", "coretemp\n");
+    write(tmp.path(), "hwmon0/temp1_input", "45000\n");
+
+    let config = Config {
+        root_path: tmp.path().to_owned(),
+        sensor_kinds: vec![SensorKind::Fan],
+        ..Config::default()
+    };
+    let mut plugins = PluginSet::new();
+    plugins.add_plugin(PluginInfo {
+        metadata: PluginMetadata::from_static::<HwmonPlugin>(),
+        enabled: true,
+        config: Some(config_to_toml_table(&config)),
+    });
+    let agent = agent::Builder::new(plugins).build_and_start();
+    assert!(agent.is_err(), "the plugin should fail to start (no fan sensor)");
+}
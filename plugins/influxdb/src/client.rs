@@ -0,0 +1,730 @@
+//! InfluxDB HTTP API (v1, v2 and v3) and line protocol.
+
+use alumet::measurement::Timestamp;
+use anyhow::{Context, anyhow};
+use flate2::{Compression, write::GzEncoder};
+use reqwest::{RequestBuilder, Url, header};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    fmt::Write,
+    io::Write as _,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Precision of the timestamps sent to InfluxDB.
+///
+/// A coarser precision reduces the storage used by InfluxDB.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Precision {
+    #[default]
+    #[serde(rename = "ns")]
+    Nanoseconds,
+    #[serde(rename = "us")]
+    Microseconds,
+    #[serde(rename = "ms")]
+    Milliseconds,
+    #[serde(rename = "s")]
+    Seconds,
+}
+
+impl Precision {
+    /// Number of nanoseconds in one unit of this precision.
+    fn nanos_per_unit(self) -> u128 {
+        match self {
+            Precision::Nanoseconds => 1,
+            Precision::Microseconds => 1_000,
+            Precision::Milliseconds => 1_000_000,
+            Precision::Seconds => 1_000_000_000,
+        }
+    }
+
+    /// Value of the `precision` query parameter, which differs between the API versions.
+    fn query_param(self, api: &Api) -> &'static str {
+        match (api, self) {
+            (Api::V1 { .. }, Precision::Nanoseconds) => "n",
+            (Api::V1 { .. }, Precision::Microseconds) => "u",
+            (Api::V1 { .. }, Precision::Milliseconds) => "ms",
+            (Api::V1 { .. }, Precision::Seconds) => "s",
+            (Api::V2 { .. }, Precision::Nanoseconds) => "ns",
+            (Api::V2 { .. }, Precision::Microseconds) => "us",
+            (Api::V2 { .. }, Precision::Milliseconds) => "ms",
+            (Api::V2 { .. }, Precision::Seconds) => "s",
+            (Api::V3 { .. }, Precision::Nanoseconds) => "nanosecond",
+            (Api::V3 { .. }, Precision::Microseconds) => "microsecond",
+            (Api::V3 { .. }, Precision::Milliseconds) => "millisecond",
+            (Api::V3 { .. }, Precision::Seconds) => "second",
+        }
+    }
+}
+
+/// Write API of InfluxDB, with the information that is specific to each version.
+#[derive(Debug, Clone)]
+pub enum Api {
+    /// InfluxDB 1.x: `/write` endpoint, authentication with username and password.
+    V1 {
+        database: String,
+        retention_policy: Option<String>,
+        username: Option<String>,
+        password: Option<String>,
+    },
+    /// InfluxDB 2.x: `/api/v2/write` endpoint, authentication with a token.
+    V2 { org: String, bucket: String, token: String },
+    /// InfluxDB 3: `/api/v3/write_lp` endpoint, optional authentication with a token.
+    V3 { database: String, token: Option<String> },
+}
+
+impl Api {
+    /// Returns `true` if the fields can be unsigned integers, which InfluxDB 1.x does not support.
+    pub fn supports_unsigned_integers(&self) -> bool {
+        !matches!(self, Api::V1 { .. })
+    }
+}
+
+/// Authentication method.
+#[derive(Clone)]
+enum Auth {
+    None,
+    /// HTTP basic authentication, used by InfluxDB v1.
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    /// Value of the `Authorization` header, of the form `Token <api_token>` or `Bearer <api_token>`.
+    Header(String),
+}
+
+/// Client for the write API of InfluxDB.
+#[derive(Clone)]
+pub struct Client {
+    client: reqwest::Client,
+    /// Url of the write endpoint, with all its query parameters.
+    write_url: Url,
+    /// Url used to test the connection when the write endpoint cannot be used for that.
+    health_url: Option<Url>,
+    auth: Auth,
+    /// Compress the request bodies with gzip.
+    gzip: bool,
+}
+
+impl Client {
+    pub fn new(host: &str, api: Api, precision: Precision, gzip: bool) -> anyhow::Result<Self> {
+        let host = host.trim_end_matches('/');
+        let precision_param = precision.query_param(&api);
+        let (write_url, health_url, auth) = match api {
+            Api::V1 {
+                database,
+                retention_policy,
+                username,
+                password,
+            } => {
+                let mut params = vec![("db", database), ("precision", precision_param.to_owned())];
+                if let Some(rp) = retention_policy {
+                    params.push(("rp", rp));
+                }
+                let url = Url::parse_with_params(&format!("{host}/write"), &params)?;
+                let auth = match username {
+                    Some(username) => Auth::Basic { username, password },
+                    None => Auth::None,
+                };
+                (url, None, auth)
+            }
+            Api::V2 { org, bucket, token } => {
+                let url = Url::parse_with_params(
+                    &format!("{host}/api/v2/write"),
+                    &[
+                        ("org", org.as_str()),
+                        ("bucket", &bucket),
+                        ("precision", precision_param),
+                    ],
+                )?;
+                (url, None, Auth::Header(format!("Token {token}")))
+            }
+            Api::V3 { database, token } => {
+                let url = Url::parse_with_params(
+                    &format!("{host}/api/v3/write_lp"),
+                    &[("db", database.as_str()), ("precision", precision_param)],
+                )?;
+                let health_url = Url::parse(&format!("{host}/health"))?;
+                let auth = match token {
+                    Some(token) => Auth::Header(format!("Bearer {token}")),
+                    None => Auth::None,
+                };
+                (url, Some(health_url), auth)
+            }
+        };
+        Ok(Self {
+            client: reqwest::Client::new(),
+            write_url,
+            health_url,
+            auth,
+            gzip,
+        })
+    }
+
+    fn with_auth(&self, req: RequestBuilder) -> RequestBuilder {
+        match &self.auth {
+            Auth::None => req,
+            Auth::Basic { username, password } => req.basic_auth(username, password.as_ref()),
+            Auth::Header(value) => req.header(header::AUTHORIZATION, value),
+        }
+    }
+
+    /// Writes measurements to InfluxDB.
+    pub async fn write(&self, data: &LineProtocolData) -> anyhow::Result<()> {
+        let mut req = self
+            .with_auth(self.client.post(self.write_url.clone()))
+            .header(header::ACCEPT, "application/json")
+            .header(header::CONTENT_TYPE, "text/plain; charset=utf-8");
+        req = if self.gzip {
+            let mut encoder = GzEncoder::new(Vec::with_capacity(data.0.len() / 4), Compression::default());
+            encoder.write_all(data.0.as_bytes())?;
+            let body = encoder.finish().context("gzip compression failed")?;
+            req.header(header::CONTENT_ENCODING, "gzip").body(body)
+        } else {
+            req.body(data.0.clone())
+        };
+        let res = req.send().await?;
+        check_response(res).await
+    }
+
+    /// Tests whether it is possible to write to InfluxDB with the client.
+    ///
+    /// Returns `Ok(())` if all goes well.
+    pub async fn test_write(&self) -> anyhow::Result<()> {
+        match &self.health_url {
+            // InfluxDB 3 rejects empty writes, check its health instead
+            Some(url) => {
+                let res = self.with_auth(self.client.get(url.clone())).send().await?;
+                check_response(res).await
+            }
+            // send empty data
+            None => self.write(&LineProtocolData::default()).await,
+        }
+    }
+}
+
+async fn check_response(res: reqwest::Response) -> anyhow::Result<()> {
+    match res.error_for_status_ref() {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            let response = res.text().await.context("failed to get a response from the server")?;
+            log::error!("InfluxDB client error: {err}\nServer response: {response}");
+            Err(anyhow!(err))
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LineProtocolData(String);
+
+impl LineProtocolData {
+    pub fn builder() -> LineProtocolBuilder {
+        LineProtocolBuilder::new()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Appends the lines of `other` to this data.
+    pub fn append(&mut self, other: LineProtocolData) {
+        if self.0.is_empty() {
+            self.0 = other.0;
+        } else if !other.0.is_empty() {
+            self.0.push('\n');
+            self.0.push_str(&other.0);
+        }
+    }
+
+    /// Removes the first `n` lines of the data.
+    pub fn remove_first_lines(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        match self.0.match_indices('\n').nth(n - 1) {
+            Some((i, _)) => {
+                self.0.drain(..=i);
+            }
+            None => self.0.clear(),
+        }
+    }
+}
+
+pub struct LineProtocolBuilder {
+    buf: String,
+    after_first_field: bool,
+    precision: Precision,
+    unsigned_integers: bool,
+}
+
+#[allow(unused)]
+impl LineProtocolBuilder {
+    pub fn new() -> Self {
+        Self {
+            buf: String::new(),
+            after_first_field: false,
+            precision: Precision::Nanoseconds,
+            unsigned_integers: true,
+        }
+    }
+
+    #[allow(unused)]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buf: String::with_capacity(capacity),
+            after_first_field: false,
+            precision: Precision::Nanoseconds,
+            unsigned_integers: true,
+        }
+    }
+
+    /// Sets the precision of the timestamps. The default is nanoseconds.
+    pub fn precision(&mut self, precision: Precision) -> &mut Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Sets whether the `u64` fields are written as unsigned integers. The default is `true`.
+    ///
+    /// If `false`, they are written as signed integers, or as floats if they are greater than `i64::MAX`.
+    pub fn unsigned_integers(&mut self, enabled: bool) -> &mut Self {
+        self.unsigned_integers = enabled;
+        self
+    }
+
+    /// Writes the measurement to the current line.
+    ///
+    /// Must be called first in a line. Required.
+    pub fn measurement(&mut self, name: &str) -> &mut Self {
+        if self.after_first_field {
+            self.after_first_field = false;
+            self.buf.push('\n'); // new measurement
+        }
+        self.buf.push_str(&escape_string(name, &[',', ' ']));
+        self
+    }
+
+    /// Writes a tag to the current line.
+    ///
+    /// Must be called after `measurement`. Optional.
+    pub fn tag(&mut self, key: &str, value: &str) -> &mut Self {
+        // tag values cannot be empty!
+        if !value.is_empty() {
+            let key = escape_string(key, &[',', '=', ' ']);
+            let value = escape_string(value, &[',', '=', ' ']);
+            write!(self.buf, ",{key}={value}").unwrap();
+        }
+        self
+    }
+
+    /// Writes a field to the current line.
+    ///
+    /// Must be called after `tag` (or `measurement` if there's no tag).
+    /// Required (there must be at least one field).
+    fn field(&mut self, key: &str, serialized_value: &str) -> &mut Self {
+        let key = escape_string(key, &[',', '=', ' ']);
+        if self.after_first_field {
+            write!(self.buf, ",{key}={serialized_value}").unwrap();
+        } else {
+            write!(self.buf, " {key}={serialized_value}").unwrap();
+            self.after_first_field = true;
+        }
+        self
+    }
+
+    /// Writes a field to the current line.
+    ///
+    /// Must be called after `tag` (or `measurement` if there's no tag).
+    /// Required (there must be at least one field).
+    pub fn field_float(&mut self, key: &str, value: f64) -> &mut Self {
+        self.field(key, &value.to_string())
+    }
+
+    /// Writes a field to the current line.
+    ///
+    /// Must be called after `tag` (or `measurement` if there's no tag).
+    /// Required (there must be at least one field).
+    pub fn field_int(&mut self, key: &str, value: i64) -> &mut Self {
+        self.field(key, &format!("{value}i"))
+    }
+
+    /// Writes a field to the current line.
+    ///
+    /// Must be called after `tag` (or `measurement` if there's no tag).
+    /// Required (there must be at least one field).
+    pub fn field_uint(&mut self, key: &str, value: u64) -> &mut Self {
+        if self.unsigned_integers {
+            self.field(key, &format!("{value}u"))
+        } else {
+            match i64::try_from(value) {
+                Ok(value) => self.field_int(key, value),
+                Err(_) => self.field_float(key, value as f64),
+            }
+        }
+    }
+
+    /// Writes a field to the current line.
+    ///
+    /// Must be called after `tag` (or `measurement` if there's no tag).
+    /// Required (there must be at least one field).
+    pub fn field_string(&mut self, key: &str, value: &str) -> &mut Self {
+        let escaped = escape_string(value, &['"', '\\']);
+        self.field(key, &format!("\"{escaped}\""))
+    }
+
+    /// Writes a field to the current line.
+    ///
+    /// Must be called after `tag` (or `measurement` if there's no tag).
+    /// Required (there must be at least one field).
+    pub fn field_bool(&mut self, key: &str, value: bool) -> &mut Self {
+        self.field(key, if value { "T" } else { "F" })
+    }
+
+    /// Writes the timestamp of the current line, in the precision of the builder.
+    ///
+    /// Must be called after `field`. Required.
+    pub fn timestamp(&mut self, timestamp: Timestamp) -> &mut Self {
+        let nanoseconds = SystemTime::from(timestamp)
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let t = nanoseconds / self.precision.nanos_per_unit();
+        write!(self.buf, " {t}").unwrap();
+        self
+    }
+
+    pub fn build(self) -> LineProtocolData {
+        assert!(
+            self.after_first_field,
+            "wrong use of the LineProtocolBuilder: at least one field is required"
+        );
+        LineProtocolData(self.buf)
+    }
+}
+
+/// Escape a String to make it suitable for the line protocol.
+///
+/// See https://docs.influxdata.com/influxdb/cloud/reference/syntax/line-protocol/#special-characters.
+fn escape_string<'a>(s: &'a str, chars_to_escape: &[char]) -> Cow<'a, str> {
+    if s.contains(chars_to_escape) {
+        // escape required, allocate a new string
+        let mut escaped = String::with_capacity(s.len() + 2);
+        for c in s.chars() {
+            if chars_to_escape.contains(&c) {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        Cow::Owned(escaped)
+    } else {
+        // nothing to escape, return the same string without allocating
+        Cow::Borrowed(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::{Matcher, Mock, Server, ServerGuard};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{Api, Auth, Client, LineProtocolBuilder, LineProtocolData, Precision};
+    use crate::client::escape_string;
+    use alumet::measurement::Timestamp;
+
+    fn api_v2(org: &str, bucket: &str, token: &str) -> Api {
+        Api::V2 {
+            org: org.to_owned(),
+            bucket: bucket.to_owned(),
+            token: token.to_owned(),
+        }
+    }
+
+    async fn mock_influx_write(server: &mut ServerGuard, org: &str, bucket: &str, token: &str, body: &str) -> Mock {
+        server
+            .mock("POST", "/api/v2/write")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("org".into(), org.into()),
+                Matcher::UrlEncoded("bucket".into(), bucket.into()),
+                Matcher::UrlEncoded("precision".into(), "ns".into()),
+            ]))
+            .match_header("authorization", format!("Token {token}").as_str())
+            .match_header("accept", "application/json")
+            .match_header("Content-Type", "text/plain; charset=utf-8")
+            .match_body(body)
+            .with_status(204)
+            .create_async()
+            .await
+    }
+
+    struct TestedLineProtocolData {
+        line: LineProtocolData,
+        expected_str: &'static str,
+    }
+
+    fn get_tested_lines() -> Vec<TestedLineProtocolData> {
+        let mut tested_lines = Vec::new();
+
+        let mut builder = LineProtocolData::builder();
+        builder
+            .measurement("myMeasurement")
+            .tag("tag1", "value1")
+            .tag("tag2", "value2")
+            .field_string("fieldKey", "fieldValue")
+            .timestamp(Timestamp::from(UNIX_EPOCH + Duration::from_nanos(1556813561098000000)));
+        let line = builder.build();
+
+        tested_lines.push(TestedLineProtocolData {
+            line,
+            expected_str: r#"myMeasurement,tag1=value1,tag2=value2 fieldKey="fieldValue" 1556813561098000000"#,
+        });
+
+        let mut builder = LineProtocolData::builder();
+        builder
+            .measurement("myMeasurement")
+            .tag("tag1", "value1")
+            .tag("tag2", "value2")
+            .field_string("fieldKey", "fieldValue")
+            .timestamp(Timestamp::from(UNIX_EPOCH + Duration::from_nanos(1556813561098000000)));
+        builder
+            .measurement("measurement_without_tags")
+            .field_string("fieldKey", "fieldValue")
+            .field_bool("bool", true)
+            .field_float("float", 123.0)
+            .field_int("int", -123)
+            .field_uint("uint", 123)
+            .timestamp(Timestamp::from(UNIX_EPOCH + Duration::from_nanos(1556813561098000000)));
+        let line = builder.build();
+        tested_lines.push(TestedLineProtocolData {
+            line,
+            expected_str: r#"myMeasurement,tag1=value1,tag2=value2 fieldKey="fieldValue" 1556813561098000000
+measurement_without_tags fieldKey="fieldValue",bool=T,float=123,int=-123i,uint=123u 1556813561098000000"#,
+        });
+        tested_lines
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!("myMeasurement", escape_string("myMeasurement", &['\\', ' ', '=']));
+        assert_eq!("with\\ space", escape_string("with space", &['\\', ' ', '=']));
+        assert_eq!(
+            "with\\ space\\ and\\ backslash\\\\",
+            escape_string("with space and backslash\\", &['\\', ' ', '='])
+        );
+    }
+
+    #[test]
+    fn build_line() {
+        for tested_line in get_tested_lines() {
+            assert_eq!(tested_line.line.0, tested_line.expected_str);
+        }
+    }
+
+    #[tokio::test]
+    async fn write() {
+        let mut server = Server::new_async().await;
+
+        let token = "sometoken";
+        let org = "someorg";
+        let bucket = "somebucket";
+
+        let influx_client =
+            Client::new(&server.url(), api_v2(org, bucket, token), Precision::Nanoseconds, false).unwrap();
+
+        for tested_line in get_tested_lines() {
+            let tested_line_write_mock =
+                mock_influx_write(&mut server, org, bucket, token, tested_line.expected_str).await;
+
+            let _ = influx_client.write(&tested_line.line).await;
+            tested_line_write_mock.assert();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write() {
+        let mut server = Server::new_async().await;
+
+        let token = "sometoken";
+        let org = "someorg";
+        let bucket = "somebucket";
+
+        let influx_client =
+            Client::new(&server.url(), api_v2(org, bucket, token), Precision::Nanoseconds, false).unwrap();
+
+        let test_write_line = mock_influx_write(&mut server, org, bucket, token, "").await;
+
+        let _ = influx_client.test_write().await;
+        test_write_line.assert();
+    }
+
+    #[test]
+    fn verify_client() {
+        let url = "http://127.0.0.1:8086";
+        let token = "sometoken";
+        let token_header = format!("Token {}", token);
+
+        let influx_client = Client::new(url, api_v2("org", "bucket", token), Precision::Nanoseconds, false).unwrap();
+
+        assert_eq!(
+            influx_client.write_url.as_str(),
+            format!("{}/api/v2/write?org=org&bucket=bucket&precision=ns", url),
+            "influx write_url doesn't have the expected format when Client is created"
+        );
+        assert!(
+            matches!(influx_client.auth, Auth::Header(ref h) if h == &token_header),
+            "influx token header doesn't have the expected format when Client is created"
+        );
+    }
+
+    #[test]
+    fn verify_client_v1_v3() {
+        let url = "http://127.0.0.1:8086/";
+        let v1 = Api::V1 {
+            database: String::from("db"),
+            retention_policy: Some(String::from("autogen")),
+            username: None,
+            password: None,
+        };
+        let client = Client::new(url, v1, Precision::Microseconds, false).unwrap();
+        assert_eq!(
+            client.write_url.as_str(),
+            "http://127.0.0.1:8086/write?db=db&precision=u&rp=autogen"
+        );
+        assert!(matches!(client.auth, Auth::None));
+        assert!(client.health_url.is_none());
+
+        let v3 = Api::V3 {
+            database: String::from("db"),
+            token: Some(String::from("tok")),
+        };
+        let client = Client::new(url, v3, Precision::Seconds, false).unwrap();
+        assert_eq!(
+            client.write_url.as_str(),
+            "http://127.0.0.1:8086/api/v3/write_lp?db=db&precision=second"
+        );
+        assert!(matches!(client.auth, Auth::Header(ref h) if h == "Bearer tok"));
+        assert_eq!(client.health_url.unwrap().as_str(), "http://127.0.0.1:8086/health");
+    }
+
+    #[tokio::test]
+    async fn write_v1_basic_auth() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/write")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("db".into(), "mydb".into()),
+                Matcher::UrlEncoded("precision".into(), "ms".into()),
+            ]))
+            // "user:pass" in base64
+            .match_header("authorization", "Basic dXNlcjpwYXNz")
+            .match_body("m value=1i,big=18446744073709552000 1556813561098")
+            .with_status(204)
+            .create_async()
+            .await;
+
+        let api = Api::V1 {
+            database: String::from("mydb"),
+            retention_policy: None,
+            username: Some(String::from("user")),
+            password: Some(String::from("pass")),
+        };
+        let client = Client::new(&server.url(), api, Precision::Milliseconds, false).unwrap();
+        let mut builder = LineProtocolData::builder();
+        builder
+            .precision(Precision::Milliseconds)
+            .unsigned_integers(false)
+            .measurement("m")
+            .field_uint("value", 1)
+            .field_uint("big", u64::MAX)
+            .timestamp(Timestamp::from(UNIX_EPOCH + Duration::from_nanos(1556813561098000000)));
+        client.write(&builder.build()).await.unwrap();
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn remove_first_lines() {
+        let mut data = LineProtocolData(String::from("a value=1u\nb value=2u\nc value=3u"));
+        data.remove_first_lines(0);
+        assert_eq!(data.0, "a value=1u\nb value=2u\nc value=3u");
+        data.remove_first_lines(2);
+        assert_eq!(data.0, "c value=3u");
+        data.remove_first_lines(5);
+        assert!(data.is_empty());
+    }
+
+    #[tokio::test]
+    async fn write_gzip() {
+        use flate2::read::GzDecoder;
+        use std::io::Read;
+
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/v2/write")
+            .match_query(Matcher::Any)
+            .match_header("content-encoding", "gzip")
+            .match_request(|req| {
+                let mut decoded = String::new();
+                let body = req.body().unwrap();
+                GzDecoder::new(body.as_slice()).read_to_string(&mut decoded).is_ok()
+                    && decoded == "m value=1u 1556813561"
+            })
+            .with_status(204)
+            .create_async()
+            .await;
+
+        let client = Client::new(&server.url(), api_v2("o", "b", "t"), Precision::Seconds, true).unwrap();
+        let mut builder = LineProtocolData::builder();
+        builder
+            .precision(Precision::Seconds)
+            .measurement("m")
+            .field_uint("value", 1)
+            .timestamp(Timestamp::from(UNIX_EPOCH + Duration::from_nanos(1556813561098000000)));
+        client.write(&builder.build()).await.unwrap();
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_write_v3_health() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("GET", "/health")
+            .match_header("authorization", "Bearer tok")
+            .with_status(200)
+            .create_async()
+            .await;
+
+        let api = Api::V3 {
+            database: String::from("db"),
+            token: Some(String::from("tok")),
+        };
+        let client = Client::new(&server.url(), api, Precision::Nanoseconds, false).unwrap();
+        client.test_write().await.unwrap();
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn append() {
+        let mut data = LineProtocolData::default();
+        for line in get_tested_lines() {
+            data.append(line.line);
+        }
+        data.append(LineProtocolData::default());
+        let expected: Vec<&str> = get_tested_lines().iter().map(|l| l.expected_str).collect();
+        assert_eq!(data.0, expected.join("\n"));
+    }
+
+    #[test]
+    fn test_with_capacity() {
+        let capacity = 100;
+        let builder = LineProtocolBuilder::with_capacity(capacity);
+
+        assert!(
+            builder.buf.capacity() >= capacity,
+            "Buffer capacity is less than requested"
+        );
+        assert!(
+            !builder.after_first_field,
+            "after_first_field should be false on initialization"
+        );
+    }
+}
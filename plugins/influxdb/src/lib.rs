@@ -1,4 +1,10 @@
-use std::collections::HashSet;
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::{task::AbortHandle, time::MissedTickBehavior};
 
 use alumet::{
     measurement::{AttributeValue, MeasurementBuffer, WrappedMeasurementValue},
@@ -14,12 +20,18 @@ use alumet::{
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
 
-use crate::influxdb2::LineProtocolData;
+use crate::client::{Api, Client, LineProtocolData};
+
+pub use client::Precision;
 
-mod influxdb2;
+mod client;
 
 pub struct InfluxDbPlugin {
     config: Option<Config>,
+    /// Batch of the output, used to flush the last measurements on stop.
+    batch: Option<SharedBatch>,
+    /// Task that flushes the batch when it gets too old, even if the output receives no measurements.
+    flush_task: Arc<Mutex<Option<AbortHandle>>>,
 }
 
 impl AlumetPlugin for InfluxDbPlugin {
@@ -37,32 +49,68 @@ impl AlumetPlugin for InfluxDbPlugin {
 
     fn init(config: alumet::plugin::ConfigTable) -> anyhow::Result<Box<Self>> {
         let config = deserialize_config(config)?;
-        Ok(Box::new(InfluxDbPlugin { config: Some(config) }))
+        Ok(Box::new(InfluxDbPlugin {
+            config: Some(config),
+            batch: None,
+            flush_task: Arc::new(Mutex::new(None)),
+        }))
     }
 
     fn start(&mut self, alumet: &mut alumet::plugin::AlumetPluginStart) -> anyhow::Result<()> {
         let config = self.config.take().unwrap();
 
         // Connect to InfluxDB to detect configuration errors early.
-        let influx_client = influxdb2::Client::new(config.host.clone(), config.token.clone());
+        let api = config.api()?;
+        let destination = describe_destination(&api);
+        let unsigned_integers = api.supports_unsigned_integers();
+        let influx_client = Client::new(&config.host, api, config.precision, config.gzip)?;
         let rt = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
         log::info!("Testing connection to InfluxDB...");
-        rt.block_on(influx_client.test_write(&config.org, &config.bucket))
-            .with_context(|| {
-                format!(
-                    "Cannot write to InfluxDB host {} in org {} and bucket {}. Please check your configuration.",
-                    &config.host, &config.org, &config.bucket
-                )
-            })?;
+        rt.block_on(influx_client.test_write()).with_context(|| {
+            format!(
+                "Cannot write to InfluxDB host {} in {destination}. Please check your configuration.",
+                &config.host
+            )
+        })?;
         log::info!("Test successful.");
 
         // Create the output.
+        let batch = SharedBatch {
+            client: influx_client,
+            batch: Arc::new(tokio::sync::Mutex::new(Batch::new(
+                config.batch_max_size,
+                config.batch_max_delay,
+            ))),
+        };
+        self.batch = Some(batch.clone());
+
+        // Without new measurements, the output is not called: use a timer to flush the batch when it gets too old.
+        if !config.batch_max_delay.is_zero() {
+            let period = (config.batch_max_delay / 4).max(MIN_FLUSH_PERIOD);
+            let timer_batch = batch.clone();
+            let flush_task = self.flush_task.clone();
+            alumet.on_pipeline_start(move |ctx| {
+                let task = ctx.async_runtime().spawn(async move {
+                    let mut interval = tokio::time::interval(period);
+                    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                    loop {
+                        interval.tick().await;
+                        if let Err(e) = timer_batch.flush(false).await {
+                            log::error!("failed to write measurements to InfluxDB: {e:#}");
+                        }
+                    }
+                });
+                *flush_task.lock().unwrap() = Some(task.abort_handle());
+                Ok(())
+            });
+        }
+
         alumet.add_blocking_output(
             "out",
             Box::new(InfluxDbOutput {
-                client: influx_client,
-                org: config.org,
-                bucket: config.bucket,
+                batch,
+                precision: config.precision,
+                unsigned_integers,
                 attributes_as: config.attributes_as,
                 attributes_as_tags: config.attributes_as_tags.unwrap_or_default(),
                 attributes_as_fields: config.attributes_as_fields.unwrap_or_default(),
@@ -72,19 +120,119 @@ impl AlumetPlugin for InfluxDbPlugin {
     }
 
     fn stop(&mut self) -> anyhow::Result<()> {
+        if let Some(task) = self.flush_task.lock().unwrap().take() {
+            task.abort();
+        }
+        // The output has been stopped, write the measurements that remain in the batch.
+        if let Some(batch) = self.batch.take() {
+            let rt = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+            rt.block_on(batch.flush(true))
+                .context("failed to write the last measurements to InfluxDB")?;
+        }
         Ok(())
     }
 }
 
+/// Minimum period of the timer that flushes the batch.
+const MIN_FLUSH_PERIOD: Duration = Duration::from_millis(10);
+
+/// If the writes keep failing, the batch is limited to this number of times `batch_max_size`.
+/// Older lines are dropped.
+const MAX_PENDING_BATCHES: usize = 10;
+
 struct InfluxDbOutput {
-    client: influxdb2::Client,
-    org: String,
-    bucket: String,
+    batch: SharedBatch,
+    precision: Precision,
+    /// Writes the `u64` values as unsigned integers (not supported by InfluxDB 1.x).
+    unsigned_integers: bool,
     attributes_as: AttributeAs,
     attributes_as_tags: HashSet<String>,
     attributes_as_fields: HashSet<String>,
 }
 
+/// Line protocol data that has not been written yet.
+///
+/// The batch is flushed when it contains enough lines, or when its first line is old enough.
+struct Batch {
+    data: LineProtocolData,
+    n_lines: usize,
+    first_line_at: Option<Instant>,
+    max_lines: usize,
+    max_delay: Duration,
+}
+
+impl Batch {
+    fn new(max_lines: usize, max_delay: Duration) -> Self {
+        Self {
+            data: LineProtocolData::default(),
+            n_lines: 0,
+            first_line_at: None,
+            max_lines,
+            max_delay,
+        }
+    }
+
+    fn push(&mut self, data: LineProtocolData, n_lines: usize, now: Instant) {
+        self.data.append(data);
+        self.n_lines += n_lines;
+        self.first_line_at.get_or_insert(now);
+
+        let max_pending = self.max_lines.saturating_mul(MAX_PENDING_BATCHES);
+        if self.n_lines > max_pending {
+            let excess = self.n_lines - max_pending;
+            self.data.remove_first_lines(excess);
+            self.n_lines = max_pending;
+            log::error!(
+                "The measurements could not be written to InfluxDB for a while, {excess} lines have been dropped"
+            );
+        }
+    }
+
+    fn should_flush(&self, now: Instant) -> bool {
+        match self.first_line_at {
+            Some(t) => self.n_lines >= self.max_lines || now.duration_since(t) >= self.max_delay,
+            None => false,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.n_lines = 0;
+        self.first_line_at = None;
+        self.data = LineProtocolData::default();
+    }
+}
+
+/// Batch shared by the output, the flush timer and the plugin.
+#[derive(Clone)]
+struct SharedBatch {
+    client: Client,
+    batch: Arc<tokio::sync::Mutex<Batch>>,
+}
+
+impl SharedBatch {
+    async fn push(&self, data: LineProtocolData, n_lines: usize) -> anyhow::Result<()> {
+        let mut batch = self.batch.lock().await;
+        batch.push(data, n_lines, Instant::now());
+        self.write(&mut batch, false).await
+    }
+
+    /// Writes the batch if it is full enough or old enough, or in any case if `force` is true.
+    async fn flush(&self, force: bool) -> anyhow::Result<()> {
+        let mut batch = self.batch.lock().await;
+        self.write(&mut batch, force).await
+    }
+
+    async fn write(&self, batch: &mut Batch, force: bool) -> anyhow::Result<()> {
+        if batch.data.is_empty() || !(force || batch.should_flush(Instant::now())) {
+            return Ok(());
+        }
+        // If the write fails, the lines stay in the batch and will be written with the next ones.
+        self.client.write(&batch.data).await?;
+        batch.clear();
+        Ok(())
+    }
+}
+
 impl Output for InfluxDbOutput {
     fn write(&mut self, measurements: &MeasurementBuffer, ctx: &OutputContext) -> Result<(), WriteError> {
         // Cannot write anything with an empty buffer.
@@ -95,6 +243,9 @@ impl Output for InfluxDbOutput {
 
         // Build the data to send to InfluxDB.
         let mut builder = LineProtocolData::builder();
+        builder
+            .precision(self.precision)
+            .unsigned_integers(self.unsigned_integers);
         for m in measurements {
             let metric = ctx.metrics.by_id(&m.metric).unwrap();
             builder.measurement(&metric.name);
@@ -158,10 +309,11 @@ impl Output for InfluxDbOutput {
         let data = builder.build();
         log::debug!("Line protocol data: {data:?}");
 
+        // Add the data to the batch and write it if it's full enough, or old enough.
         // Do the writing on the tokio Runtime.
         let handle = tokio::runtime::Handle::current();
         handle
-            .block_on(self.client.write(&self.org, &self.bucket, data))
+            .block_on(self.batch.push(data, measurements.len()))
             .context("failed to write measurements to InfluxDB")
             .retry_write()?;
         Ok(())
@@ -206,17 +358,56 @@ fn ensure_valid_field_key<'a>(reserved_field: &'a str, field_key: &'a str) -> &'
     }
 }
 
+fn describe_destination(api: &Api) -> String {
+    match api {
+        Api::V1 {
+            database,
+            retention_policy,
+            ..
+        } => match retention_policy {
+            Some(rp) => format!("database {database} with retention policy {rp}"),
+            None => format!("database {database}"),
+        },
+        Api::V2 { org, bucket, .. } => format!("org {org} and bucket {bucket}"),
+        Api::V3 { database, .. } => format!("database {database}"),
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct Config {
     /// Address of the host where InfluxDB is running
     pub host: String,
-    /// Token to write on the database
-    pub token: String,
-    /// Organisation where to write data
-    pub org: String,
-    /// Bucket where to write data   
-    pub bucket: String,
+    /// Version of the InfluxDB API: `"v1"`, `"v2"` or `"v3"`.
+    #[serde(default)]
+    pub api_version: ApiVersion,
+    /// Token to write on the database (v2 and v3)
+    pub token: Option<String>,
+    /// Organisation where to write data (v2)
+    pub org: Option<String>,
+    /// Bucket where to write data (v2)
+    pub bucket: Option<String>,
+    /// Database where to write data (v1 and v3)
+    pub database: Option<String>,
+    /// Retention policy of the database (v1, optional)
+    pub retention_policy: Option<String>,
+    /// Username used to authenticate (v1, optional)
+    pub username: Option<String>,
+    /// Password used to authenticate (v1, optional)
+    pub password: Option<String>,
+    /// Precision of the timestamps: `"ns"`, `"us"`, `"ms"` or `"s"`.
+    #[serde(default)]
+    pub precision: Precision,
+    /// Compress the requests with gzip.
+    #[serde(default)]
+    pub gzip: bool,
+    /// Maximum number of lines to buffer before writing them to InfluxDB.
+    #[serde(default = "default_batch_max_size")]
+    pub batch_max_size: usize,
+    /// Maximum time to keep the measurements in the buffer before writing them to InfluxDB.
+    /// With the default value of `0s`, the measurements are written as soon as they are received.
+    #[serde(default, with = "humantime_serde")]
+    pub batch_max_delay: Duration,
     /// By default, serialize all Alumet attributes as fields. This can be either `"field"` or `"tag".
     pub attributes_as: AttributeAs,
     /// Always serialize the given list of attributes as InfluxDB tags
@@ -225,6 +416,51 @@ pub struct Config {
     pub attributes_as_fields: Option<HashSet<String>>,
 }
 
+/// Version of the InfluxDB API to use.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiVersion {
+    V1,
+    #[default]
+    V2,
+    V3,
+}
+
+fn default_batch_max_size() -> usize {
+    // recommended by https://docs.influxdata.com/influxdb/v2/write-data/best-practices/optimize-writes/
+    5000
+}
+
+impl Config {
+    /// Checks that the options required by the API version are set.
+    fn api(&self) -> anyhow::Result<Api> {
+        fn required(value: &Option<String>, name: &str, version: &str) -> anyhow::Result<String> {
+            value
+                .clone()
+                .with_context(|| format!("option `{name}` is required for InfluxDB {version}"))
+        }
+
+        let api = match self.api_version {
+            ApiVersion::V1 => Api::V1 {
+                database: required(&self.database, "database", "v1")?,
+                retention_policy: self.retention_policy.clone(),
+                username: self.username.clone(),
+                password: self.password.clone(),
+            },
+            ApiVersion::V2 => Api::V2 {
+                org: required(&self.org, "org", "v2")?,
+                bucket: required(&self.bucket, "bucket", "v2")?,
+                token: required(&self.token, "token", "v2")?,
+            },
+            ApiVersion::V3 => Api::V3 {
+                database: required(&self.database, "database", "v3")?,
+                token: self.token.clone(),
+            },
+        };
+        Ok(api)
+    }
+}
+
 /// How to serialize Alumet attributes by default?
 #[derive(Serialize, Deserialize, Clone, Copy)]
 #[serde(rename_all = "lowercase")]
@@ -241,9 +477,18 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             host: String::from("http://localhost:8086"),
-            token: String::from("FILL ME"),
-            org: String::from("FILL ME"),
-            bucket: String::from("FILL ME"),
+            api_version: ApiVersion::V2,
+            token: Some(String::from("FILL ME")),
+            org: Some(String::from("FILL ME")),
+            bucket: Some(String::from("FILL ME")),
+            database: None,
+            retention_policy: None,
+            username: None,
+            password: None,
+            precision: Precision::Nanoseconds,
+            gzip: false,
+            batch_max_size: default_batch_max_size(),
+            batch_max_delay: Duration::ZERO,
             attributes_as: AttributeAs::Field,
             attributes_as_tags: None,
             attributes_as_fields: None,
@@ -253,8 +498,16 @@ impl Default for Config {
 
 #[cfg(test)]
 mod tests {
-    use crate::{AttributeAs, ensure_valid_field_key, ensure_valid_tag_key, partition_tag};
-    use std::collections::HashSet;
+    use crate::{
+        ApiVersion, AttributeAs, Batch, Config, MAX_PENDING_BATCHES, Precision, SharedBatch,
+        client::{Api, Client, LineProtocolData},
+        ensure_valid_field_key, ensure_valid_tag_key, partition_tag,
+    };
+    use std::{
+        collections::HashSet,
+        sync::Arc,
+        time::{Duration, Instant},
+    };
     #[test]
     fn test_partition_tag() {
         let mut tags: HashSet<String> = HashSet::new();
@@ -305,4 +558,116 @@ mod tests {
             "some_random_field"
         );
     }
+
+    fn line(name: &str) -> LineProtocolData {
+        let mut builder = LineProtocolData::builder();
+        builder.measurement(name).field_uint("value", 1);
+        builder.build()
+    }
+
+    #[test]
+    fn batch_size() {
+        let now = Instant::now();
+        let mut batch = Batch::new(3, Duration::from_secs(3600));
+        assert!(!batch.should_flush(now));
+        batch.push(line("a"), 1, now);
+        batch.push(line("b"), 1, now);
+        assert!(!batch.should_flush(now));
+        batch.push(line("c"), 1, now);
+        assert!(batch.should_flush(now));
+        assert_eq!(
+            format!("{:?}", batch.data),
+            r#"LineProtocolData("a value=1u\nb value=1u\nc value=1u")"#
+        );
+
+        batch.clear();
+        assert!(!batch.should_flush(now));
+        assert!(batch.data.is_empty());
+    }
+
+    #[test]
+    fn batch_max_pending() {
+        let now = Instant::now();
+        let mut batch = Batch::new(1, Duration::ZERO);
+        for i in 0..MAX_PENDING_BATCHES + 2 {
+            batch.push(line(&format!("m{i}")), 1, now);
+        }
+        // the oldest lines have been dropped
+        assert_eq!(batch.n_lines, MAX_PENDING_BATCHES);
+        let data = format!("{:?}", batch.data);
+        assert!(data.starts_with(r#"LineProtocolData("m2 value=1u\n"#), "{data}");
+    }
+
+    #[test]
+    fn batch_delay() {
+        let start = Instant::now();
+        let mut batch = Batch::new(1000, Duration::from_secs(10));
+        batch.push(line("a"), 1, start);
+        assert!(!batch.should_flush(start + Duration::from_secs(5)));
+        batch.push(line("b"), 1, start + Duration::from_secs(5));
+        assert!(batch.should_flush(start + Duration::from_secs(10)));
+
+        // with no delay, the batch is flushed immediately
+        let mut batch = Batch::new(1000, Duration::ZERO);
+        batch.push(line("a"), 1, start);
+        assert!(batch.should_flush(start));
+    }
+
+    #[tokio::test]
+    async fn batch_kept_on_failure() {
+        let mut server = mockito::Server::new_async().await;
+        let failure = server
+            .mock("POST", "/api/v2/write")
+            .match_query(mockito::Matcher::Any)
+            .with_status(503)
+            .create_async()
+            .await;
+
+        let api = Api::V2 {
+            org: String::from("o"),
+            bucket: String::from("b"),
+            token: String::from("t"),
+        };
+        let client = Client::new(&server.url(), api, Precision::Nanoseconds, false).unwrap();
+        let batch = SharedBatch {
+            client,
+            batch: Arc::new(tokio::sync::Mutex::new(Batch::new(1, Duration::ZERO))),
+        };
+        assert!(batch.push(line("a"), 1).await.is_err());
+        assert_eq!(batch.batch.lock().await.n_lines, 1);
+        failure.assert_async().await;
+
+        // the lines that could not be written are written with the next ones
+        failure.remove_async().await;
+        let success = server
+            .mock("POST", "/api/v2/write")
+            .match_query(mockito::Matcher::Any)
+            .match_body("a value=1u\nb value=1u")
+            .with_status(204)
+            .create_async()
+            .await;
+        batch.push(line("b"), 1).await.unwrap();
+        assert!(batch.batch.lock().await.data.is_empty());
+        success.assert_async().await;
+    }
+
+    #[test]
+    fn config_api() {
+        let config = Config::default();
+        assert!(matches!(config.api().unwrap(), Api::V2 { .. }));
+
+        let config = Config {
+            api_version: ApiVersion::V1,
+            ..Default::default()
+        };
+        assert!(config.api().is_err(), "database should be required for v1");
+
+        let config = Config {
+            api_version: ApiVersion::V3,
+            token: None,
+            database: Some(String::from("db")),
+            ..Default::default()
+        };
+        assert!(matches!(config.api().unwrap(), Api::V3 { token: None, .. }));
+    }
 }
@@ -67,12 +67,13 @@ mod tests {
 
         let source_config = Config {
             host: server.url(),
-            token: String::from(token),
-            org: String::from(org),
-            bucket: String::from(bucket),
+            token: Some(String::from(token)),
+            org: Some(String::from(org)),
+            bucket: Some(String::from(bucket)),
             attributes_as: AttributeAs::Field,
             attributes_as_tags: None,
             attributes_as_fields: None,
+            ..Default::default()
         };
         plugins.add_plugin(PluginInfo {
             metadata: PluginMetadata::from_static::<InfluxDbPlugin>(),
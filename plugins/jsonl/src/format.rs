@@ -0,0 +1,234 @@
+//! Serialization of the measurements to JSON objects.
+
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use alumet::{
+    measurement::{MeasurementPoint, WrappedMeasurementValue},
+    metrics::Metric,
+};
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize, Serializer, ser::SerializeMap};
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+
+/// Naming scheme of the fields of the JSON objects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldNaming {
+    /// `resource_kind`, `timestamp_ns`, ...
+    SnakeCase,
+    /// `resourceKind`, `timestampNs`, ...
+    CamelCase,
+}
+
+/// How the timestamp of the measurements is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampFormat {
+    /// A RFC 3339 string, like `2025-01-01T12:00:00.5Z`.
+    Rfc3339,
+    /// A number of nanoseconds since the UNIX epoch.
+    EpochNs,
+    /// Both, in two fields.
+    Both,
+}
+
+/// Names of the fields of the JSON objects.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldNames {
+    pub timestamp: String,
+    pub timestamp_ns: String,
+    pub metric: String,
+    pub unit: String,
+    pub value: String,
+    pub resource_kind: String,
+    pub resource_id: String,
+    pub consumer_kind: String,
+    pub consumer_id: String,
+    pub attributes: String,
+}
+
+impl FieldNames {
+    /// Returns the names of the fields according to the naming scheme, with some names overridden.
+    ///
+    /// The keys of `overrides` are the snake_case names of the fields.
+    pub fn new(naming: FieldNaming, overrides: &HashMap<String, String>) -> anyhow::Result<Self> {
+        let name = |snake: &str, camel: &str| match naming {
+            FieldNaming::SnakeCase => snake.to_owned(),
+            FieldNaming::CamelCase => camel.to_owned(),
+        };
+        let mut names = Self {
+            timestamp: name("timestamp", "timestamp"),
+            timestamp_ns: name("timestamp_ns", "timestampNs"),
+            metric: name("metric", "metric"),
+            unit: name("unit", "unit"),
+            value: name("value", "value"),
+            resource_kind: name("resource_kind", "resourceKind"),
+            resource_id: name("resource_id", "resourceId"),
+            consumer_kind: name("consumer_kind", "consumerKind"),
+            consumer_id: name("consumer_id", "consumerId"),
+            attributes: name("attributes", "attributes"),
+        };
+        for (field, new_name) in overrides {
+            let target = match field.as_str() {
+                "timestamp" => &mut names.timestamp,
+                "timestamp_ns" => &mut names.timestamp_ns,
+                "metric" => &mut names.metric,
+                "unit" => &mut names.unit,
+                "value" => &mut names.value,
+                "resource_kind" => &mut names.resource_kind,
+                "resource_id" => &mut names.resource_id,
+                "consumer_kind" => &mut names.consumer_kind,
+                "consumer_id" => &mut names.consumer_id,
+                "attributes" => &mut names.attributes,
+                _ => return Err(anyhow!("unknown field {field:?} in field_names")),
+            };
+            *target = new_name.to_owned();
+        }
+        Ok(names)
+    }
+}
+
+pub struct FormatSettings {
+    pub names: FieldNames,
+    pub timestamp_format: TimestampFormat,
+}
+
+/// A measurement point, serialized as a JSON object.
+pub struct JsonPoint<'a> {
+    pub point: &'a MeasurementPoint,
+    pub metric: &'a Metric,
+    pub settings: &'a FormatSettings,
+}
+
+impl Serialize for JsonPoint<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::Error;
+
+        let names = &self.settings.names;
+        let m = self.point;
+        let mut map = serializer.serialize_map(None)?;
+
+        let timestamp = SystemTime::from(m.timestamp);
+        if let TimestampFormat::Rfc3339 | TimestampFormat::Both = self.settings.timestamp_format {
+            let datetime = OffsetDateTime::from(timestamp)
+                .format(&Rfc3339)
+                .map_err(S::Error::custom)?;
+            map.serialize_entry(&names.timestamp, &datetime)?;
+        }
+        if let TimestampFormat::EpochNs | TimestampFormat::Both = self.settings.timestamp_format {
+            let nanos = timestamp
+                .duration_since(UNIX_EPOCH)
+                .map_err(|_| S::Error::custom("timestamp before the UNIX epoch"))?
+                .as_nanos();
+            let nanos = u64::try_from(nanos).map_err(|_| S::Error::custom("timestamp too large"))?;
+            map.serialize_entry(&names.timestamp_ns, &nanos)?;
+        }
+        map.serialize_entry(&names.metric, &self.metric.name)?;
+        map.serialize_entry(&names.unit, &self.metric.unit.unique_name())?;
+        match m.value {
+            WrappedMeasurementValue::F64(v) => map.serialize_entry(&names.value, &v)?,
+            WrappedMeasurementValue::U64(v) => map.serialize_entry(&names.value, &v)?,
+        }
+        map.serialize_entry(&names.resource_kind, m.resource.kind())?;
+        map.serialize_entry(&names.resource_id, &m.resource.id_display().to_string())?;
+        map.serialize_entry(&names.consumer_kind, m.consumer.kind())?;
+        map.serialize_entry(&names.consumer_id, &m.consumer.id_display().to_string())?;
+        map.serialize_entry(&names.attributes, &JsonAttributes(m))?;
+        map.end()
+    }
+}
+
+struct JsonAttributes<'a>(&'a MeasurementPoint);
+
+impl Serialize for JsonAttributes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.attributes_len()))?;
+        for (key, value) in self.0.attributes() {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use alumet::{
+        measurement::{AttributeValue, MeasurementPoint, Timestamp, WrappedMeasurementType, WrappedMeasurementValue},
+        metrics::{Metric, RawMetricId},
+        resources::{Resource, ResourceConsumer},
+        units::{PrefixedUnit, Unit},
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::{FieldNames, FieldNaming, FormatSettings, JsonPoint, TimestampFormat};
+
+    fn test_metric() -> Metric {
+        Metric {
+            name: String::from("rapl_consumed_energy"),
+            description: String::new(),
+            value_type: WrappedMeasurementType::F64,
+            unit: PrefixedUnit::milli(Unit::Joule),
+        }
+    }
+
+    fn test_point() -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::from(UNIX_EPOCH + Duration::from_nanos(1_500_000_001)),
+            RawMetricId::from_u64(0),
+            Resource::CpuPackage { id: 0 },
+            ResourceConsumer::Process { pid: 42 },
+            WrappedMeasurementValue::F64(12.5),
+        )
+        .with_attr("domain", "package")
+        .with_attr("cpus", AttributeValue::ListU64(vec![0, 1]))
+    }
+
+    fn to_json(naming: FieldNaming, overrides: &HashMap<String, String>, format: TimestampFormat) -> String {
+        let settings = FormatSettings {
+            names: FieldNames::new(naming, overrides).unwrap(),
+            timestamp_format: format,
+        };
+        let point = test_point();
+        let metric = test_metric();
+        serde_json::to_string(&JsonPoint {
+            point: &point,
+            metric: &metric,
+            settings: &settings,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn snake_case() {
+        assert_eq!(
+            to_json(FieldNaming::SnakeCase, &HashMap::new(), TimestampFormat::Both),
+            r#"{"timestamp":"1970-01-01T00:00:01.500000001Z","timestamp_ns":1500000001,"metric":"rapl_consumed_energy","unit":"milliJ","value":12.5,"resource_kind":"cpu_package","resource_id":"0","consumer_kind":"process","consumer_id":"42","attributes":{"domain":"package","cpus":[0,1]}}"#
+        );
+    }
+
+    #[test]
+    fn camel_case_with_overrides() {
+        let overrides = HashMap::from([
+            (String::from("timestamp_ns"), String::from("@timestamp")),
+            (String::from("attributes"), String::from("labels")),
+        ]);
+        assert_eq!(
+            to_json(FieldNaming::CamelCase, &overrides, TimestampFormat::EpochNs),
+            r#"{"@timestamp":1500000001,"metric":"rapl_consumed_energy","unit":"milliJ","value":12.5,"resourceKind":"cpu_package","resourceId":"0","consumerKind":"process","consumerId":"42","labels":{"domain":"package","cpus":[0,1]}}"#
+        );
+    }
+
+    #[test]
+    fn unknown_field() {
+        let overrides = HashMap::from([(String::from("time"), String::from("t"))]);
+        assert!(FieldNames::new(FieldNaming::SnakeCase, &overrides).is_err());
+    }
+}
@@ -0,0 +1,235 @@
+mod format;
+mod sink;
+
+use std::{collections::HashMap, path::PathBuf};
+
+use alumet::{
+    measurement::MeasurementBuffer,
+    pipeline::{
+        Output,
+        elements::{error::WriteError, output::OutputContext},
+    },
+    plugin::{
+        AlumetPluginStart, ConfigTable,
+        rust::{AlumetPlugin, deserialize_config, serialize_config},
+    },
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use format::{FieldNames, FormatSettings, JsonPoint};
+pub use format::{FieldNaming, TimestampFormat};
+use sink::{Sink, Target};
+
+pub struct JsonLinesPlugin {
+    config: Option<Config>,
+}
+
+impl AlumetPlugin for JsonLinesPlugin {
+    fn name() -> &'static str {
+        "jsonl"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(JsonLinesPlugin { config: Some(config) }))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let config = self.config.take().unwrap();
+        let settings = FormatSettings {
+            names: FieldNames::new(config.field_naming, &config.field_names)?,
+            timestamp_format: config.timestamp_format,
+        };
+        let sink = Sink::open(config.target()?)?;
+        let output = JsonLinesOutput {
+            sink,
+            settings,
+            force_flush: config.force_flush,
+            buf: Vec::new(),
+        };
+        alumet.add_blocking_output("out", Box::new(output))?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+struct JsonLinesOutput {
+    sink: Sink,
+    settings: FormatSettings,
+    force_flush: bool,
+    /// Lines of the current buffer, reused between the calls to `write`.
+    buf: Vec<u8>,
+}
+
+impl Output for JsonLinesOutput {
+    fn write(&mut self, measurements: &MeasurementBuffer, ctx: &OutputContext) -> Result<(), WriteError> {
+        self.buf.clear();
+        for m in measurements {
+            let metric = ctx.metrics.by_id(&m.metric).expect("unknown metric");
+            let point = JsonPoint {
+                point: m,
+                metric,
+                settings: &self.settings,
+            };
+            serde_json::to_writer(&mut self.buf, &point).context("failed to serialize a measurement")?;
+            self.buf.push(b'\n');
+        }
+        if let Err(e) = self.sink.write(&self.buf, self.force_flush) {
+            // The socket will be reconnected on the next write.
+            return Err(if self.sink.is_socket() {
+                WriteError::CanRetry(e)
+            } else {
+                WriteError::Fatal(e)
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Where to write the measurements.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Destination {
+    /// The standard output of the agent.
+    Stdout,
+    /// A file.
+    File,
+    /// A Unix socket, which must be listening (stream socket).
+    Unix,
+    /// A TCP server.
+    Tcp,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Where to write the measurements: `"stdout"`, `"file"`, `"unix"` or `"tcp"`.
+    pub destination: Destination,
+    /// Path of the file (`file`) or of the socket (`unix`).
+    pub path: Option<PathBuf>,
+    /// Address of the server, like `"localhost:9000"` (`tcp`).
+    pub address: Option<String>,
+    /// Append the measurements to the existing file instead of overwriting it (`file`).
+    #[serde(default)]
+    pub append: bool,
+    /// Do we flush after each write? The sockets are always flushed.
+    pub force_flush: bool,
+    /// Format of the timestamps: `"rfc3339"`, `"epoch_ns"` or `"both"`.
+    pub timestamp_format: TimestampFormat,
+    /// Naming scheme of the fields: `"snake_case"` or `"camel_case"`.
+    pub field_naming: FieldNaming,
+    /// Custom names of some fields, by snake_case name of the field.
+    /// For instance, `timestamp = "@timestamp"`.
+    #[serde(default)]
+    pub field_names: HashMap<String, String>,
+}
+
+impl Config {
+    /// Checks that the options required by the destination are set.
+    fn target(&self) -> anyhow::Result<Target> {
+        let path = || {
+            self.path
+                .clone()
+                .context("option `path` is required for this destination")
+        };
+        let target = match self.destination {
+            Destination::Stdout => Target::Stdout,
+            Destination::File => Target::File {
+                path: path()?,
+                append: self.append,
+            },
+            #[cfg(unix)]
+            Destination::Unix => Target::Unix { path: path()? },
+            #[cfg(not(unix))]
+            Destination::Unix => return Err(anyhow::anyhow!("Unix sockets are not supported on this platform")),
+            Destination::Tcp => Target::Tcp {
+                address: self
+                    .address
+                    .clone()
+                    .context("option `address` is required for the tcp destination")?,
+            },
+        };
+        Ok(target)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            destination: Destination::Stdout,
+            path: None,
+            address: None,
+            append: false,
+            force_flush: true,
+            timestamp_format: TimestampFormat::Both,
+            field_naming: FieldNaming::SnakeCase,
+            field_names: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{Config, Destination, FieldNaming, Target, TimestampFormat};
+
+    #[test]
+    fn parse_config() {
+        let config: Config = toml::from_str(
+            r#"
+            destination = "file"
+            path = "out.jsonl"
+            append = true
+            force_flush = false
+            timestamp_format = "epoch_ns"
+            field_naming = "camel_case"
+
+            [field_names]
+            timestamp_ns = "@timestamp"
+        "#,
+        )
+        .unwrap();
+        assert_eq!(config.destination, Destination::File);
+        assert_eq!(config.timestamp_format, TimestampFormat::EpochNs);
+        assert_eq!(config.field_naming, FieldNaming::CamelCase);
+        assert_eq!(config.field_names["timestamp_ns"], "@timestamp");
+        assert_eq!(
+            config.target().unwrap(),
+            Target::File {
+                path: PathBuf::from("out.jsonl"),
+                append: true
+            }
+        );
+
+        let default = toml::to_string(&Config::default()).unwrap();
+        let config: Config = toml::from_str(&default).unwrap();
+        assert_eq!(config.target().unwrap(), Target::Stdout);
+    }
+
+    #[test]
+    fn missing_options() {
+        let config = Config {
+            destination: Destination::Tcp,
+            ..Default::default()
+        };
+        assert!(config.target().is_err());
+        let config = Config {
+            destination: Destination::Unix,
+            ..Default::default()
+        };
+        assert!(config.target().is_err());
+    }
+}
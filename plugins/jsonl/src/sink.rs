@@ -0,0 +1,212 @@
+//! Destinations of the JSON lines.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    net::TcpStream,
+    path::PathBuf,
+};
+
+use anyhow::Context;
+
+/// Where to write the JSON lines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Stdout,
+    File {
+        path: PathBuf,
+        append: bool,
+    },
+    #[cfg(unix)]
+    Unix {
+        path: PathBuf,
+    },
+    Tcp {
+        address: String,
+    },
+}
+
+impl Target {
+    fn is_socket(&self) -> bool {
+        match self {
+            Target::Stdout | Target::File { .. } => false,
+            #[cfg(unix)]
+            Target::Unix { .. } => true,
+            Target::Tcp { .. } => true,
+        }
+    }
+
+    fn open(&self) -> anyhow::Result<Box<dyn Write + Send>> {
+        let writer: Box<dyn Write + Send> = match self {
+            Target::Stdout => Box::new(io::stdout()),
+            Target::File { path, append } => {
+                let file = if *append {
+                    OpenOptions::new().create(true).append(true).open(path)
+                } else {
+                    File::create(path)
+                };
+                Box::new(file.with_context(|| format!("failed to open {path:?}"))?)
+            }
+            #[cfg(unix)]
+            Target::Unix { path } => Box::new(
+                std::os::unix::net::UnixStream::connect(path)
+                    .with_context(|| format!("failed to connect to the Unix socket {path:?}"))?,
+            ),
+            Target::Tcp { address } => {
+                Box::new(TcpStream::connect(address).with_context(|| format!("failed to connect to {address}"))?)
+            }
+        };
+        Ok(writer)
+    }
+}
+
+/// Writes lines to a [`Target`].
+///
+/// The sockets are (re)connected when needed: if the peer closes the connection,
+/// the next write opens a new one.
+pub struct Sink {
+    target: Target,
+    writer: Option<BufWriter<Box<dyn Write + Send>>>,
+}
+
+impl Sink {
+    /// Creates a sink and opens its target.
+    ///
+    /// If the target is a socket that is not available yet, the connection is attempted again on the next write.
+    pub fn open(target: Target) -> anyhow::Result<Self> {
+        let mut sink = Self { target, writer: None };
+        if let Err(e) = sink.writer() {
+            if sink.target.is_socket() {
+                log::warn!("{e:#}, retrying later");
+            } else {
+                return Err(e);
+            }
+        }
+        Ok(sink)
+    }
+
+    fn writer(&mut self) -> anyhow::Result<&mut BufWriter<Box<dyn Write + Send>>> {
+        if self.writer.is_none() {
+            self.writer = Some(BufWriter::new(self.target.open()?));
+        }
+        Ok(self.writer.as_mut().unwrap())
+    }
+
+    /// Writes some data, and flushes it if `flush` is true.
+    ///
+    /// Sockets are always flushed, and are closed if an error occurs.
+    pub fn write(&mut self, data: &[u8], flush: bool) -> anyhow::Result<()> {
+        let flush = flush || self.target.is_socket();
+        let writer = self.writer()?;
+        let res = writer
+            .write_all(data)
+            .and_then(|_| if flush { writer.flush() } else { Ok(()) });
+        if let Err(e) = res {
+            if self.target.is_socket() {
+                // Discard the connection, and the data that has not been sent.
+                if let Some(w) = self.writer.take() {
+                    let _ = w.into_parts();
+                }
+            }
+            return Err(e).context("failed to write the measurements");
+        }
+        Ok(())
+    }
+
+    pub fn is_socket(&self) -> bool {
+        self.target.is_socket()
+    }
+}
+
+impl Drop for Sink {
+    fn drop(&mut self) {
+        if let Some(w) = &mut self.writer
+            && let Err(e) = w.flush()
+        {
+            log::error!("failed to flush the measurements: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        net::TcpListener,
+    };
+
+    use super::{Sink, Target};
+
+    #[test]
+    fn file() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("out.jsonl");
+
+        let mut sink = Sink::open(Target::File {
+            path: path.clone(),
+            append: false,
+        })?;
+        sink.write(b"{\"a\":1}\n", false)?;
+        drop(sink);
+
+        let mut sink = Sink::open(Target::File {
+            path: path.clone(),
+            append: true,
+        })?;
+        sink.write(b"{\"a\":2}\n", true)?;
+        assert_eq!(std::fs::read_to_string(&path)?, "{\"a\":1}\n{\"a\":2}\n");
+        drop(sink);
+
+        let mut sink = Sink::open(Target::File {
+            path: path.clone(),
+            append: false,
+        })?;
+        sink.write(b"{\"a\":3}\n", true)?;
+        assert_eq!(std::fs::read_to_string(&path)?, "{\"a\":3}\n");
+        Ok(())
+    }
+
+    #[test]
+    fn tcp() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?.to_string();
+        let mut sink = Sink::open(Target::Tcp { address })?;
+        sink.write(b"{\"a\":1}\n", false)?;
+
+        let (stream, _) = listener.accept()?;
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line)?;
+        assert_eq!(line, "{\"a\":1}\n");
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_reconnect() -> anyhow::Result<()> {
+        use std::os::unix::net::UnixListener;
+
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("out.sock");
+
+        // the socket does not exist yet: the sink is created, but writing fails
+        let mut sink = Sink::open(Target::Unix { path: path.clone() })?;
+        assert!(sink.write(b"lost\n", false).is_err());
+
+        let listener = UnixListener::bind(&path)?;
+        sink.write(b"{\"a\":1}\n", false)?;
+        let (stream, _) = listener.accept()?;
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line)?;
+        assert_eq!(line, "{\"a\":1}\n");
+        Ok(())
+    }
+
+    #[test]
+    fn missing_directory() {
+        let target = Target::File {
+            path: "/nonexistent/dir/out.jsonl".into(),
+            append: false,
+        };
+        assert!(Sink::open(target).is_err());
+    }
+}
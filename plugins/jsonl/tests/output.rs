@@ -0,0 +1,96 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use alumet::{
+    agent::{
+        self,
+        plugin::{PluginInfo, PluginSet},
+    },
+    measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+    pipeline::naming::OutputName,
+    plugin::PluginMetadata,
+    resources::{Resource, ResourceConsumer},
+    test::RuntimeExpectations,
+    units::Unit,
+};
+use plugin_jsonl::{Config, Destination, JsonLinesPlugin, TimestampFormat};
+use pretty_assertions::assert_eq;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn config_to_toml_table(config: &Config) -> toml::Table {
+    toml::Value::try_from(config).unwrap().as_table().unwrap().clone()
+}
+
+#[test]
+fn jsonl_output_to_file() {
+    let _ = env_logger::Builder::from_default_env().try_init();
+
+    // Prepare the plugin
+
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("alumet-output.jsonl");
+    let result_path = path.clone();
+
+    let config = Config {
+        destination: Destination::File,
+        path: Some(path),
+        timestamp_format: TimestampFormat::Rfc3339,
+        field_names: [(String::from("timestamp"), String::from("@timestamp"))].into(),
+        ..Config::default()
+    };
+
+    let mut plugins = PluginSet::new();
+    plugins.add_plugin(PluginInfo {
+        metadata: PluginMetadata::from_static::<JsonLinesPlugin>(),
+        enabled: true,
+        config: Some(config_to_toml_table(&config)),
+    });
+
+    // Prepare the scenario
+
+    let output = OutputName::from_str("jsonl", "out");
+    let runtime_expectations = RuntimeExpectations::new()
+        .create_metric::<u64>("test_metric_u64", Unit::Joule)
+        .create_metric::<f64>("test_metric_f64", Unit::Watt)
+        .test_output(
+            output,
+            |ctx| {
+                let metric_u64 = ctx.metrics().by_name("test_metric_u64").unwrap().0;
+                let metric_f64 = ctx.metrics().by_name("test_metric_f64").unwrap().0;
+                let p1 = MeasurementPoint::new_untyped(
+                    Timestamp::from(UNIX_EPOCH + Duration::from_secs(1)),
+                    metric_u64,
+                    Resource::CpuPackage { id: 0 },
+                    ResourceConsumer::LocalMachine,
+                    WrappedMeasurementValue::U64(12),
+                )
+                .with_attr("domain", "package");
+                let p2 = MeasurementPoint::new_untyped(
+                    Timestamp::from(UNIX_EPOCH + Duration::from_secs(1)),
+                    metric_f64,
+                    Resource::CpuPackage { id: 0 },
+                    ResourceConsumer::Process { pid: 42 },
+                    WrappedMeasurementValue::F64(2.5),
+                );
+                MeasurementBuffer::from(vec![p1, p2])
+            },
+            move || {
+                let content = std::fs::read_to_string(&result_path).unwrap();
+                let lines: Vec<&str> = content.lines().collect();
+                assert_eq!(
+                    lines,
+                    vec![
+                        r#"{"@timestamp":"1970-01-01T00:00:01Z","metric":"test_metric_u64","unit":"J","value":12,"resource_kind":"cpu_package","resource_id":"0","consumer_kind":"local_machine","consumer_id":"","attributes":{"domain":"package"}}"#,
+                        r#"{"@timestamp":"1970-01-01T00:00:01Z","metric":"test_metric_f64","unit":"W","value":2.5,"resource_kind":"cpu_package","resource_id":"0","consumer_kind":"process","consumer_id":"42","attributes":{}}"#,
+                    ]
+                );
+            },
+        );
+
+    let agent = agent::Builder::new(plugins)
+        .with_expectations(runtime_expectations)
+        .build_and_start()
+        .unwrap();
+
+    agent.wait_for_shutdown(TIMEOUT).unwrap();
+}
@@ -0,0 +1,288 @@
+//! Encodings of the Kafka messages.
+
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use alumet::{
+    measurement::{MeasurementPoint, WrappedMeasurementValue},
+    metrics::Metric,
+};
+use anyhow::Context;
+use apache_avro::{Schema, types::Value as AvroValue};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value as JsonValue, json};
+
+/// Encoding of the messages.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    /// A JSON object.
+    Json,
+    /// An Avro datum, without header, that follows [`AVRO_SCHEMA`].
+    Avro,
+    /// A postcard-encoded [`PostcardRecord`].
+    Postcard,
+}
+
+/// Avro schema of the messages, when the encoding is `avro`.
+pub const AVRO_SCHEMA: &str = r#"{
+  "type": "record",
+  "name": "Measurement",
+  "namespace": "alumet",
+  "fields": [
+    { "name": "timestamp_ns", "type": "long" },
+    { "name": "metric", "type": "string" },
+    { "name": "unit", "type": "string" },
+    { "name": "value", "type": ["long", "double"] },
+    { "name": "resource_kind", "type": "string" },
+    { "name": "resource_id", "type": "string" },
+    { "name": "consumer_kind", "type": "string" },
+    { "name": "consumer_id", "type": "string" },
+    { "name": "attributes", "type": { "type": "map", "values": "string" } }
+  ]
+}"#;
+
+/// Measurement point encoded with postcard.
+///
+/// Consumers written in Rust can decode the messages by copying this struct:
+/// postcard does not store the names of the fields, only their order matters.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct PostcardRecord<'a> {
+    pub timestamp_ns: u64,
+    pub metric: &'a str,
+    pub unit: String,
+    pub value: PostcardValue,
+    pub resource_kind: &'a str,
+    pub resource_id: String,
+    pub consumer_kind: &'a str,
+    pub consumer_id: String,
+    pub attributes: Vec<(&'a str, String)>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub enum PostcardValue {
+    U64(u64),
+    F64(f64),
+}
+
+/// Encodes the measurement points.
+pub struct Encoder {
+    encoding: Encoding,
+    avro_schema: Option<Schema>,
+}
+
+impl Encoder {
+    pub fn new(encoding: Encoding) -> anyhow::Result<Self> {
+        let avro_schema = match encoding {
+            Encoding::Avro => Some(Schema::parse_str(AVRO_SCHEMA).context("invalid Avro schema")?),
+            _ => None,
+        };
+        Ok(Self { encoding, avro_schema })
+    }
+
+    pub fn encode(&self, m: &MeasurementPoint, metric: &Metric) -> anyhow::Result<Vec<u8>> {
+        match self.encoding {
+            Encoding::Json => Ok(serde_json::to_vec(&json_value(m, metric))?),
+            Encoding::Avro => {
+                let schema = self.avro_schema.as_ref().unwrap();
+                apache_avro::to_avro_datum(schema, avro_value(m, metric)).context("failed to encode in Avro")
+            }
+            Encoding::Postcard => {
+                postcard::to_allocvec(&postcard_record(m, metric)).context("failed to encode with postcard")
+            }
+        }
+    }
+}
+
+fn timestamp_ns(m: &MeasurementPoint) -> u64 {
+    let t = SystemTime::from(m.timestamp)
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    t.as_nanos() as u64
+}
+
+fn json_value(m: &MeasurementPoint, metric: &Metric) -> JsonValue {
+    let value = match m.value {
+        WrappedMeasurementValue::F64(v) => JsonValue::from(v),
+        WrappedMeasurementValue::U64(v) => JsonValue::from(v),
+    };
+    let attributes: Map<String, JsonValue> = m
+        .attributes()
+        .map(|(k, v)| (k.to_owned(), JsonValue::from(v)))
+        .collect();
+    json!({
+        "timestamp_ns": timestamp_ns(m),
+        "metric": metric.name,
+        "unit": metric.unit.unique_name(),
+        "value": value,
+        "resource_kind": m.resource.kind(),
+        "resource_id": m.resource.id_display().to_string(),
+        "consumer_kind": m.consumer.kind(),
+        "consumer_id": m.consumer.id_display().to_string(),
+        "attributes": attributes,
+    })
+}
+
+/// Avro has no unsigned integers: the u64 values are saturated to `i64::MAX`.
+fn avro_long(v: u64) -> AvroValue {
+    AvroValue::Long(i64::try_from(v).unwrap_or(i64::MAX))
+}
+
+fn avro_value(m: &MeasurementPoint, metric: &Metric) -> AvroValue {
+    let value = match m.value {
+        WrappedMeasurementValue::U64(v) => AvroValue::Union(0, Box::new(avro_long(v))),
+        WrappedMeasurementValue::F64(v) => AvroValue::Union(1, Box::new(AvroValue::Double(v))),
+    };
+    let attributes: HashMap<String, AvroValue> = m
+        .attributes()
+        .map(|(k, v)| (k.to_owned(), AvroValue::String(v.to_string())))
+        .collect();
+    AvroValue::Record(vec![
+        field("timestamp_ns", avro_long(timestamp_ns(m))),
+        field("metric", AvroValue::String(metric.name.clone())),
+        field("unit", AvroValue::String(metric.unit.unique_name())),
+        field("value", value),
+        field("resource_kind", AvroValue::String(m.resource.kind().to_owned())),
+        field("resource_id", AvroValue::String(m.resource.id_display().to_string())),
+        field("consumer_kind", AvroValue::String(m.consumer.kind().to_owned())),
+        field("consumer_id", AvroValue::String(m.consumer.id_display().to_string())),
+        field("attributes", AvroValue::Map(attributes)),
+    ])
+}
+
+fn field(name: &str, value: AvroValue) -> (String, AvroValue) {
+    (name.to_owned(), value)
+}
+
+fn postcard_record<'a>(m: &'a MeasurementPoint, metric: &'a Metric) -> PostcardRecord<'a> {
+    PostcardRecord {
+        timestamp_ns: timestamp_ns(m),
+        metric: &metric.name,
+        unit: metric.unit.unique_name(),
+        value: match m.value {
+            WrappedMeasurementValue::U64(v) => PostcardValue::U64(v),
+            WrappedMeasurementValue::F64(v) => PostcardValue::F64(v),
+        },
+        resource_kind: m.resource.kind(),
+        resource_id: m.resource.id_display().to_string(),
+        consumer_kind: m.consumer.kind(),
+        consumer_id: m.consumer.id_display().to_string(),
+        attributes: m.attributes().map(|(k, v)| (k, v.to_string())).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use alumet::{
+        measurement::{MeasurementPoint, Timestamp, WrappedMeasurementType, WrappedMeasurementValue},
+        metrics::{Metric, RawMetricId},
+        resources::{Resource, ResourceConsumer},
+        units::{PrefixedUnit, Unit},
+    };
+    use apache_avro::{Schema, types::Value as AvroValue};
+    use pretty_assertions::assert_eq;
+
+    use super::{AVRO_SCHEMA, Encoder, Encoding, PostcardRecord, PostcardValue};
+
+    fn point(value: WrappedMeasurementValue) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::from(UNIX_EPOCH + Duration::from_nanos(1_500_000_001)),
+            RawMetricId::from_u64(0),
+            Resource::CpuPackage { id: 0 },
+            ResourceConsumer::Process { pid: 42 },
+            value,
+        )
+        .with_attr("domain", "package")
+    }
+
+    fn metric() -> Metric {
+        Metric {
+            name: String::from("rapl_consumed_energy"),
+            description: String::new(),
+            value_type: WrappedMeasurementType::F64,
+            unit: PrefixedUnit::milli(Unit::Joule),
+        }
+    }
+
+    #[test]
+    fn json() {
+        let encoder = Encoder::new(Encoding::Json).unwrap();
+        let payload = encoder
+            .encode(&point(WrappedMeasurementValue::F64(12.5)), &metric())
+            .unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "timestamp_ns": 1_500_000_001u64,
+                "metric": "rapl_consumed_energy",
+                "unit": "milliJ",
+                "value": 12.5,
+                "resource_kind": "cpu_package",
+                "resource_id": "0",
+                "consumer_kind": "process",
+                "consumer_id": "42",
+                "attributes": { "domain": "package" },
+            })
+        );
+    }
+
+    #[test]
+    fn avro() {
+        let encoder = Encoder::new(Encoding::Avro).unwrap();
+        let payload = encoder
+            .encode(&point(WrappedMeasurementValue::U64(12)), &metric())
+            .unwrap();
+
+        let schema = Schema::parse_str(AVRO_SCHEMA).unwrap();
+        let value = apache_avro::from_avro_datum(&schema, &mut payload.as_slice(), None).unwrap();
+        let AvroValue::Record(fields) = value else {
+            panic!("expected a record, got {value:?}");
+        };
+        assert_eq!(
+            fields[0],
+            (String::from("timestamp_ns"), AvroValue::Long(1_500_000_001))
+        );
+        assert_eq!(
+            fields[3],
+            (
+                String::from("value"),
+                AvroValue::Union(0, Box::new(AvroValue::Long(12)))
+            )
+        );
+        assert_eq!(
+            fields[8],
+            (
+                String::from("attributes"),
+                AvroValue::Map([(String::from("domain"), AvroValue::String(String::from("package")))].into())
+            )
+        );
+    }
+
+    #[test]
+    fn postcard() {
+        let encoder = Encoder::new(Encoding::Postcard).unwrap();
+        let payload = encoder
+            .encode(&point(WrappedMeasurementValue::F64(0.5)), &metric())
+            .unwrap();
+        let record: PostcardRecord = postcard::from_bytes(&payload).unwrap();
+        assert_eq!(
+            record,
+            PostcardRecord {
+                timestamp_ns: 1_500_000_001,
+                metric: "rapl_consumed_energy",
+                unit: String::from("milliJ"),
+                value: PostcardValue::F64(0.5),
+                resource_kind: "cpu_package",
+                resource_id: String::from("0"),
+                consumer_kind: "process",
+                consumer_id: String::from("42"),
+                attributes: vec![("domain", String::from("package"))],
+            }
+        );
+    }
+}
@@ -0,0 +1,241 @@
+mod encoding;
+mod producer;
+mod routing;
+
+use std::{collections::HashMap, time::Duration};
+
+use alumet::{
+    measurement::MeasurementBuffer,
+    metrics::RawMetricId,
+    pipeline::{
+        Output,
+        elements::{error::WriteError, output::OutputContext},
+    },
+    plugin::{
+        AlumetPluginStart, ConfigTable,
+        rust::{AlumetPlugin, deserialize_config, serialize_config},
+    },
+};
+use anyhow::Context;
+use rdkafka::ClientConfig;
+use serde::{Deserialize, Serialize};
+
+use encoding::Encoder;
+pub use encoding::{AVRO_SCHEMA, Encoding, PostcardRecord, PostcardValue};
+use producer::{DeliveryContext, Failures, KafkaProducer, Record};
+pub use routing::KeyMode;
+use routing::TopicTemplate;
+
+pub struct KafkaPlugin {
+    config: Option<Config>,
+}
+
+impl AlumetPlugin for KafkaPlugin {
+    fn name() -> &'static str {
+        "kafka"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(KafkaPlugin { config: Some(config) }))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let config = self.config.take().unwrap();
+        let topic = TopicTemplate::parse(&config.topic)?;
+        let encoder = Encoder::new(config.encoding)?;
+        let producer = config
+            .client_config()
+            .create_with_context(DeliveryContext::default())
+            .context("failed to create the Kafka producer")?;
+        let output = KafkaOutput {
+            producer: KafkaProducer::new(producer, config.delivery_timeout),
+            topic,
+            topics: HashMap::new(),
+            key: config.key,
+            encoder,
+        };
+        alumet.add_blocking_output("out", Box::new(output))?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+struct KafkaOutput {
+    producer: KafkaProducer,
+    topic: TopicTemplate,
+    /// Topic of each metric, computed on first use.
+    topics: HashMap<RawMetricId, String>,
+    key: KeyMode,
+    encoder: Encoder,
+}
+
+impl Output for KafkaOutput {
+    fn write(&mut self, measurements: &MeasurementBuffer, ctx: &OutputContext) -> Result<(), WriteError> {
+        // Failed deliveries of the previous writes are reported after queuing the new messages,
+        // so that they are not lost.
+        let mut failures = Failures::default();
+        self.producer.poll(&mut failures);
+
+        for m in measurements {
+            let metric = ctx.metrics.by_id(&m.metric).expect("unknown metric");
+            let topic = self
+                .topics
+                .entry(m.metric)
+                .or_insert_with(|| self.topic.render(&metric.name));
+            let key = self.key.key(m);
+            // A message that cannot be encoded or sent is skipped: it must not prevent the others from being sent.
+            let sent = self.encoder.encode(m, metric).and_then(|payload| {
+                self.producer
+                    .send(Record {
+                        topic: topic.as_str(),
+                        key: key.as_deref(),
+                        payload: &payload,
+                    })
+                    .map_err(anyhow::Error::from)
+            });
+            if let Err(e) = sent {
+                failures.add(e, topic);
+            }
+        }
+        self.producer.poll(&mut failures);
+        self.producer.check(failures)
+    }
+}
+
+/// Which acknowledgements the producer waits for, before considering that a message is delivered.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Acks {
+    /// Don't wait for the broker (`acks=0`).
+    None,
+    /// Wait for the leader of the partition (`acks=1`).
+    Leader,
+    /// Wait for all the in-sync replicas (`acks=all`).
+    All,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Initial list of brokers, like `"host1:9092,host2:9092"`.
+    pub brokers: String,
+    /// Topic of the messages. Use `{metric}` to send each metric to its own topic.
+    pub topic: String,
+    /// What the key of the messages is made of: `"none"`, `"resource"`, `"consumer"` or `"resource_and_consumer"`.
+    pub key: KeyMode,
+    /// Encoding of the messages: `"json"`, `"avro"` or `"postcard"`.
+    pub encoding: Encoding,
+    /// How long to wait for more messages before sending a batch.
+    #[serde(with = "humantime_serde")]
+    pub linger: Duration,
+    /// Maximum size of a batch, in bytes.
+    pub batch_size: usize,
+    /// Maximum number of messages in a batch.
+    pub batch_max_messages: usize,
+    /// Acknowledgements to wait for: `"none"`, `"leader"` or `"all"`.
+    pub acks: Acks,
+    /// Maximum time to deliver a message, including the retries.
+    #[serde(with = "humantime_serde")]
+    pub delivery_timeout: Duration,
+    /// Additional librdkafka settings, like `"security.protocol"`. They override the other options.
+    #[serde(default)]
+    pub properties: HashMap<String, String>,
+}
+
+impl Config {
+    fn client_config(&self) -> ClientConfig {
+        let acks = match self.acks {
+            Acks::None => "0",
+            Acks::Leader => "1",
+            Acks::All => "all",
+        };
+        let mut config = ClientConfig::new();
+        config
+            .set("bootstrap.servers", &self.brokers)
+            .set("linger.ms", self.linger.as_millis().to_string())
+            .set("batch.size", self.batch_size.to_string())
+            .set("batch.num.messages", self.batch_max_messages.to_string())
+            .set("acks", acks)
+            .set("message.timeout.ms", self.delivery_timeout.as_millis().to_string());
+        for (key, value) in &self.properties {
+            config.set(key, value);
+        }
+        config
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            brokers: String::from("localhost:9092"),
+            topic: String::from("alumet"),
+            key: KeyMode::Consumer,
+            encoding: Encoding::Json,
+            linger: Duration::from_millis(100),
+            batch_size: 1_000_000,
+            batch_max_messages: 10_000,
+            acks: Acks::All,
+            delivery_timeout: Duration::from_secs(30),
+            properties: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{Acks, Config, Encoding, KeyMode};
+
+    #[test]
+    fn parse_config() {
+        let config: Config = toml::from_str(
+            r#"
+            brokers = "kafka-1:9092,kafka-2:9092"
+            topic = "alumet.{metric}"
+            key = "resource_and_consumer"
+            encoding = "avro"
+            linger = "20ms"
+            batch_size = 65536
+            batch_max_messages = 500
+            acks = "leader"
+            delivery_timeout = "10s"
+
+            [properties]
+            "compression.type" = "zstd"
+        "#,
+        )
+        .unwrap();
+        assert_eq!(config.key, KeyMode::ResourceAndConsumer);
+        assert_eq!(config.encoding, Encoding::Avro);
+        assert_eq!(config.acks, Acks::Leader);
+
+        let client_config = config.client_config();
+        assert_eq!(
+            client_config.get("bootstrap.servers"),
+            Some("kafka-1:9092,kafka-2:9092")
+        );
+        assert_eq!(client_config.get("linger.ms"), Some("20"));
+        assert_eq!(client_config.get("batch.size"), Some("65536"));
+        assert_eq!(client_config.get("batch.num.messages"), Some("500"));
+        assert_eq!(client_config.get("acks"), Some("1"));
+        assert_eq!(client_config.get("message.timeout.ms"), Some("10000"));
+        assert_eq!(client_config.get("compression.type"), Some("zstd"));
+
+        let default = toml::to_string(&Config::default()).unwrap();
+        let config: Config = toml::from_str(&default).unwrap();
+        assert_eq!(config.client_config().get("acks"), Some("all"));
+    }
+}
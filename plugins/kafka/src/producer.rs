@@ -0,0 +1,177 @@
+//! Kafka producer and delivery reports.
+
+use std::{sync::Mutex, time::Duration};
+
+use alumet::pipeline::elements::error::WriteError;
+use anyhow::anyhow;
+use rdkafka::{
+    ClientContext,
+    error::{KafkaError, RDKafkaErrorCode},
+    message::Message,
+    producer::{BaseProducer, BaseRecord, DeliveryResult, Producer, ProducerContext},
+};
+
+/// How long to wait for some room in the queue of the producer, when it is full.
+const QUEUE_FULL_WAIT: Duration = Duration::from_millis(100);
+
+/// Messages that could not be sent or delivered.
+#[derive(Debug, Default)]
+pub struct Failures {
+    count: usize,
+    /// The last error, with the topic of the message.
+    last: Option<(anyhow::Error, String)>,
+}
+
+impl Failures {
+    pub fn add(&mut self, error: anyhow::Error, topic: &str) {
+        self.count += 1;
+        self.last = Some((error, topic.to_owned()));
+    }
+
+    fn merge(&mut self, other: Failures) {
+        self.count += other.count;
+        if other.last.is_some() {
+            self.last = other.last;
+        }
+    }
+
+    /// Returns an error if some messages have failed.
+    ///
+    /// The error is always [`WriteError::CanRetry`]: a message can be rejected (e.g. because it is too large)
+    /// without preventing the next ones from being delivered.
+    fn into_result(self) -> Result<(), WriteError> {
+        match self.last {
+            None => Ok(()),
+            Some((e, topic)) => Err(WriteError::CanRetry(anyhow!(
+                "{} message(s) could not be sent or delivered, last error on topic {topic}: {e:#}",
+                self.count
+            ))),
+        }
+    }
+}
+
+/// Receives the delivery reports of the producer.
+#[derive(Default)]
+pub struct DeliveryContext {
+    failures: Mutex<Failures>,
+}
+
+impl ClientContext for DeliveryContext {}
+
+impl ProducerContext for DeliveryContext {
+    type DeliveryOpaque = ();
+
+    fn delivery(&self, result: &DeliveryResult<'_>, _: Self::DeliveryOpaque) {
+        if let Err((e, message)) = result {
+            let mut failures = self.failures.lock().unwrap();
+            failures.add(e.clone().into(), message.topic());
+        }
+    }
+}
+
+impl DeliveryContext {
+    /// Moves the failed deliveries since the last call to `failures`.
+    fn take_failures(&self, failures: &mut Failures) {
+        failures.merge(std::mem::take(&mut *self.failures.lock().unwrap()));
+    }
+}
+
+/// A message to send.
+pub struct Record<'a> {
+    pub topic: &'a str,
+    pub key: Option<&'a str>,
+    pub payload: &'a [u8],
+}
+
+/// Sends messages to Kafka.
+///
+/// The messages are sent in the background by librdkafka, which groups them in batches.
+/// The delivery reports are processed by [`Self::poll`]: the failed deliveries are reported on the next call.
+pub struct KafkaProducer {
+    producer: BaseProducer<DeliveryContext>,
+    flush_timeout: Duration,
+}
+
+impl KafkaProducer {
+    pub fn new(producer: BaseProducer<DeliveryContext>, flush_timeout: Duration) -> Self {
+        Self {
+            producer,
+            flush_timeout,
+        }
+    }
+
+    /// Queues a message. If the queue is full, waits for some messages to be sent.
+    pub fn send(&self, record: Record) -> Result<(), KafkaError> {
+        let mut base = BaseRecord::to(record.topic).payload(record.payload);
+        if let Some(key) = record.key {
+            base = base.key(key);
+        }
+        loop {
+            match self.producer.send(base) {
+                Ok(()) => return Ok(()),
+                Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), rejected)) => {
+                    base = rejected;
+                    self.producer.poll(QUEUE_FULL_WAIT);
+                }
+                Err((e, _)) => return Err(e),
+            }
+        }
+    }
+
+    /// Processes the delivery reports, and adds the messages that could not be delivered to `failures`.
+    pub fn poll(&self, failures: &mut Failures) {
+        self.producer.poll(Duration::ZERO);
+        self.producer.context().take_failures(failures);
+    }
+
+    /// Returns an error if some messages have failed, or if the producer cannot be used anymore.
+    pub fn check(&self, failures: Failures) -> Result<(), WriteError> {
+        if let Some((code, reason)) = self.producer.client().fatal_error() {
+            return Err(WriteError::Fatal(anyhow!(
+                "the Kafka producer has encountered a fatal error: {reason} ({code})"
+            )));
+        }
+        failures.into_result()
+    }
+}
+
+impl Drop for KafkaProducer {
+    fn drop(&mut self) {
+        // Send the messages that are still in the queue.
+        if let Err(e) = self.producer.flush(self.flush_timeout) {
+            log::error!("failed to flush the Kafka producer: {e}");
+        }
+        let mut failures = Failures::default();
+        self.producer.context().take_failures(&mut failures);
+        if let Err(e) = failures.into_result() {
+            log::error!("{e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alumet::pipeline::elements::error::WriteError;
+    use anyhow::anyhow;
+
+    use super::Failures;
+
+    #[test]
+    fn failures() {
+        assert!(Failures::default().into_result().is_ok());
+
+        let mut failures = Failures::default();
+        failures.add(anyhow!("Message production error: MessageSizeTooLarge"), "alumet");
+        let mut delivery = Failures::default();
+        delivery.add(anyhow!("Message production error: MessageTimedOut"), "alumet.cpu");
+        failures.merge(delivery);
+        failures.merge(Failures::default());
+        match failures.into_result() {
+            Err(WriteError::CanRetry(e)) => assert_eq!(
+                e.to_string(),
+                "2 message(s) could not be sent or delivered, last error on topic alumet.cpu: Message production error: MessageTimedOut"
+            ),
+            res => panic!("unexpected result {res:?}"),
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Topics and keys of the Kafka messages.
+
+use std::fmt::Write;
+
+use alumet::measurement::MeasurementPoint;
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+/// Maximum length of a Kafka topic name.
+const MAX_TOPIC_LEN: usize = 249;
+
+/// What the key of the messages is made of.
+///
+/// Kafka sends the messages that have the same key to the same partition, which preserves their order.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyMode {
+    /// No key: the messages are spread over the partitions.
+    None,
+    /// `{resource_kind}:{resource_id}`
+    Resource,
+    /// `{consumer_kind}:{consumer_id}`
+    Consumer,
+    /// `{resource_kind}:{resource_id}/{consumer_kind}:{consumer_id}`
+    ResourceAndConsumer,
+}
+
+impl KeyMode {
+    /// Returns the key of a measurement point, if any.
+    pub fn key(&self, m: &MeasurementPoint) -> Option<String> {
+        let mut key = String::new();
+        match self {
+            KeyMode::None => return None,
+            KeyMode::Resource => write!(key, "{}:{}", m.resource.kind(), m.resource.id_display()),
+            KeyMode::Consumer => write!(key, "{}:{}", m.consumer.kind(), m.consumer.id_display()),
+            KeyMode::ResourceAndConsumer => write!(
+                key,
+                "{}:{}/{}:{}",
+                m.resource.kind(),
+                m.resource.id_display(),
+                m.consumer.kind(),
+                m.consumer.id_display()
+            ),
+        }
+        .expect("writing to a String should not fail");
+        Some(key)
+    }
+}
+
+/// Topic of the messages: a single topic, or one topic per metric if the name contains `{metric}`.
+#[derive(Debug)]
+pub struct TopicTemplate {
+    template: String,
+}
+
+impl TopicTemplate {
+    pub fn parse(template: &str) -> anyhow::Result<Self> {
+        let literal = template.replace("{metric}", "");
+        if template.is_empty() {
+            return Err(anyhow!("the topic cannot be empty"));
+        }
+        if let Some(c) = literal.chars().find(|c| !is_valid_topic_char(*c)) {
+            return Err(anyhow!(
+                "invalid character {c:?} in topic {template:?}: only ASCII letters, digits, '.', '_' and '-' are allowed"
+            ));
+        }
+        Ok(Self {
+            template: template.to_owned(),
+        })
+    }
+
+    /// Returns the topic of a metric.
+    ///
+    /// The characters that are not allowed in a topic name are replaced by `_`.
+    pub fn render(&self, metric_name: &str) -> String {
+        let metric: String = metric_name
+            .chars()
+            .map(|c| if is_valid_topic_char(c) { c } else { '_' })
+            .collect();
+        let mut topic = self.template.replace("{metric}", &metric);
+        if topic.len() > MAX_TOPIC_LEN {
+            log::warn!("topic {topic:?} is longer than {MAX_TOPIC_LEN} characters, it will be truncated");
+            topic.truncate(MAX_TOPIC_LEN);
+        }
+        topic
+    }
+}
+
+fn is_valid_topic_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use alumet::{
+        measurement::{MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        resources::{Resource, ResourceConsumer},
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::{KeyMode, TopicTemplate};
+
+    #[test]
+    fn keys() {
+        let m = MeasurementPoint::new_untyped(
+            Timestamp::from(UNIX_EPOCH),
+            RawMetricId::from_u64(0),
+            Resource::CpuPackage { id: 1 },
+            ResourceConsumer::ControlGroup {
+                path: "/kubepods/pod1".into(),
+            },
+            WrappedMeasurementValue::U64(1),
+        );
+        assert_eq!(KeyMode::None.key(&m), None);
+        assert_eq!(KeyMode::Resource.key(&m).unwrap(), "cpu_package:1");
+        assert_eq!(KeyMode::Consumer.key(&m).unwrap(), "cgroup:/kubepods/pod1");
+        assert_eq!(
+            KeyMode::ResourceAndConsumer.key(&m).unwrap(),
+            "cpu_package:1/cgroup:/kubepods/pod1"
+        );
+    }
+
+    #[test]
+    fn topics() {
+        let single = TopicTemplate::parse("alumet-measurements").unwrap();
+        assert_eq!(single.render("cpu_time_delta"), "alumet-measurements");
+
+        let per_metric = TopicTemplate::parse("alumet.{metric}").unwrap();
+        assert_eq!(per_metric.render("cpu_time_delta"), "alumet.cpu_time_delta");
+        assert_eq!(per_metric.render("kernel/cpu time"), "alumet.kernel_cpu_time");
+        assert_eq!(per_metric.render(&"m".repeat(300)).len(), 249);
+
+        assert!(TopicTemplate::parse("").is_err());
+        assert!(TopicTemplate::parse("alumet/{metric}").is_err());
+        assert!(TopicTemplate::parse("alumet.{resource}").is_err());
+    }
+}
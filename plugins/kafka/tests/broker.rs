@@ -0,0 +1,127 @@
+//! Tests that require a Kafka broker listening on localhost:9092, for instance:
+//!
+//! ```sh
+//! docker run --rm -p 9092:9092 apache/kafka:3.9.0
+//! cargo test -p plugin-kafka -- --ignored
+//! ```
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use alumet::{
+    agent::{
+        self,
+        plugin::{PluginInfo, PluginSet},
+    },
+    measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+    pipeline::naming::OutputName,
+    plugin::PluginMetadata,
+    resources::{Resource, ResourceConsumer},
+    test::RuntimeExpectations,
+    units::Unit,
+};
+use plugin_kafka::{Config, Encoding, KafkaPlugin, KeyMode, PostcardRecord, PostcardValue};
+use pretty_assertions::assert_eq;
+use rdkafka::{
+    ClientConfig, Message,
+    consumer::{BaseConsumer, Consumer},
+};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+fn config_to_toml_table(config: &Config) -> toml::Table {
+    toml::Value::try_from(config).unwrap().as_table().unwrap().clone()
+}
+
+/// Reads the messages of `topic` from the beginning, as `(key, payload)`.
+fn consume(topic: &str, count: usize) -> Vec<(Option<String>, Vec<u8>)> {
+    let consumer: BaseConsumer = ClientConfig::new()
+        .set("bootstrap.servers", "localhost:9092")
+        .set("group.id", format!("{topic}-test"))
+        .set("auto.offset.reset", "earliest")
+        .create()
+        .unwrap();
+    consumer.subscribe(&[topic]).unwrap();
+
+    let mut messages = Vec::new();
+    while messages.len() < count {
+        let message = consumer
+            .poll(TIMEOUT)
+            .expect("no message received")
+            .expect("failed to consume");
+        let key = message.key().map(|k| String::from_utf8(k.to_vec()).unwrap());
+        messages.push((key, message.payload().unwrap_or_default().to_vec()));
+    }
+    messages
+}
+
+fn run_agent(config: Config, check: impl Fn() + Send + 'static) {
+    let mut plugins = PluginSet::new();
+    plugins.add_plugin(PluginInfo {
+        metadata: PluginMetadata::from_static::<KafkaPlugin>(),
+        enabled: true,
+        config: Some(config_to_toml_table(&config)),
+    });
+
+    let runtime_expectations = RuntimeExpectations::new()
+        .create_metric::<u64>("test_metric", Unit::Joule)
+        .test_output(
+            OutputName::from_str("kafka", "out"),
+            |ctx| {
+                let metric = ctx.metrics().by_name("test_metric").unwrap().0;
+                let point = |pid, value| {
+                    MeasurementPoint::new_untyped(
+                        Timestamp::from(UNIX_EPOCH + Duration::from_secs(1)),
+                        metric,
+                        Resource::CpuPackage { id: 0 },
+                        ResourceConsumer::Process { pid },
+                        WrappedMeasurementValue::U64(value),
+                    )
+                };
+                MeasurementBuffer::from(vec![point(1, 10), point(2, 20)])
+            },
+            check,
+        );
+
+    let agent = agent::Builder::new(plugins)
+        .with_expectations(runtime_expectations)
+        .build_and_start()
+        .unwrap();
+    agent.wait_for_shutdown(TIMEOUT).unwrap();
+}
+
+#[test]
+#[ignore = "requires a Kafka broker on localhost:9092"]
+fn produce_postcard() {
+    let _ = env_logger::Builder::from_default_env().try_init();
+
+    let config = Config {
+        topic: String::from("alumet-test-{metric}"),
+        key: KeyMode::Consumer,
+        encoding: Encoding::Postcard,
+        linger: Duration::from_millis(5),
+        ..Config::default()
+    };
+    // The messages are sent in the background: consume them after the shutdown of the agent,
+    // which flushes the producer.
+    run_agent(config, || ());
+
+    let messages = consume("alumet-test-test_metric", 2);
+    let mut decoded: Vec<(Option<String>, u64)> = messages
+        .iter()
+        .map(|(key, payload)| {
+            let record: PostcardRecord = postcard::from_bytes(payload).unwrap();
+            let PostcardValue::U64(value) = record.value else {
+                panic!("unexpected value {:?}", record.value);
+            };
+            (key.clone(), value)
+        })
+        .collect();
+    decoded.sort();
+    assert_eq!(
+        decoded,
+        vec![
+            (Some(String::from("process:1")), 10),
+            (Some(String::from("process:2")), 20),
+        ]
+    );
+}
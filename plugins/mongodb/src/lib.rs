@@ -1,17 +1,18 @@
+use std::time::Duration;
+
 use alumet::{
-    measurement::{AttributeValue, MeasurementBuffer, WrappedMeasurementValue},
+    measurement::MeasurementBuffer,
     pipeline::{
         Output,
-        elements::{error::WriteError, output::OutputContext},
+        elements::{
+            error::WriteError,
+            output::{OutputContext, error::WriteRetry},
+        },
     },
     plugin::rust::{AlumetPlugin, deserialize_config, serialize_config},
 };
-
-use mongodb::{
-    bson::{Document, doc},
-    sync::Client,
-};
-use mongodb2::convert_timestamp;
+use anyhow::Context;
+use mongodb::{bson::Document, sync::Collection};
 use serde::{Deserialize, Serialize};
 
 mod mongodb2;
@@ -40,25 +41,34 @@ impl AlumetPlugin for MongoDbPlugin {
 
     fn start(&mut self, alumet: &mut alumet::plugin::AlumetPluginStart) -> anyhow::Result<()> {
         let config = self.config.take().unwrap();
+        if config.batch_size == 0 {
+            return Err(anyhow::anyhow!("batch_size must be greater than zero"));
+        }
+
+        if config.host.is_some() || config.port.is_some() {
+            log::warn!("The options `host` and `port` of the mongodb plugin are deprecated, please use `uri` instead.");
+        }
 
         // Test connection to MongoDb.
-        let uri = mongodb2::build_mongo_uri(&config);
-        let client = Client::with_uri_str(uri)?;
-        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        let uri = mongodb2::redact_uri(&config.connection_uri()?);
+        let options = mongodb2::client_options(&config)?;
         log::info!("Testing connection to MongoDb...");
-        rt.block_on(async {
-            let collection = client.database(&config.database).collection(&config.collection);
-            collection.insert_one(doc! {}).await.unwrap();
-        });
+        let client = mongodb2::connect(options, &config.database)
+            .with_context(|| format!("Cannot connect to MongoDB with URI {uri}"))?;
         log::info!("Test successful.");
 
+        // Create the time series collection if needed.
+        let database = client.database(&config.database);
+        if let Some(time_series) = &config.time_series {
+            mongodb2::ensure_time_series_collection(&database, &config.collection, time_series)?;
+        }
+
         // Create the output.
         alumet.add_blocking_output(
             "out",
             Box::new(MongoDbOutput {
-                client: Client::with_uri_str(mongodb2::build_mongo_uri(&config)).unwrap(),
-                database: config.database,
-                collection: config.collection,
+                collection: database.collection(&config.collection),
+                batch_size: config.batch_size,
             }),
         )?;
         Ok(())
@@ -70,9 +80,8 @@ impl AlumetPlugin for MongoDbPlugin {
 }
 
 struct MongoDbOutput {
-    client: Client,
-    database: String,
-    collection: String,
+    collection: Collection<Document>,
+    batch_size: usize,
 }
 
 impl Output for MongoDbOutput {
@@ -84,112 +93,143 @@ impl Output for MongoDbOutput {
         }
 
         // Build the data to send to MongoDb
-        let mut docs = Vec::with_capacity(measurements.len());
-        for m in measurements {
-            let mut doc = Document::new();
-            let metric = ctx.metrics.by_id(&m.metric).unwrap();
-            doc.insert("measurement", &metric.name);
-
-            // Resources and consumers are translated as fields
-            doc.insert("resource_kind", m.resource.kind());
-            doc.insert("resource_id", m.resource.id_string().unwrap_or_default());
-            doc.insert("resource_consumer_kind", m.consumer.kind());
-            doc.insert("resource_consumer_id", m.consumer.id_string().unwrap_or_default());
-
-            // Alumet attributes are translated to fields
-            // Some field keys are reserved by Alumet and will trigger a renaming
-            let attributes = m.attributes();
-            const RESERVED_FIELDS: [&str; 6] = [
-                "measurement",
-                "resource_kind",
-                "resource_id",
-                "resource_consumer_kind",
-                "resource_consumer_id",
-                "value",
-            ];
-
-            for (field_key, field_value) in attributes {
-                let field_key = if RESERVED_FIELDS.contains(&field_key) {
-                    format!("{}_field", field_key)
-                } else {
-                    String::from(field_key)
-                };
-                match field_value {
-                    AttributeValue::F64(v) => {
-                        doc.insert(field_key, v);
-                    }
-                    AttributeValue::U64(v) => {
-                        doc.insert(field_key, u64_to_bson(*v));
-                    }
-                    AttributeValue::Bool(v) => {
-                        doc.insert(field_key, v);
-                    }
-                    AttributeValue::Str(v) => {
-                        doc.insert(field_key, v);
-                    }
-                    AttributeValue::String(v) => {
-                        doc.insert(field_key, v);
-                    }
-                    AttributeValue::ListU64(items) => {
-                        doc.insert(
-                            field_key,
-                            items.into_iter().map(|v| u64_to_bson(*v)).collect::<Vec<_>>(),
-                        );
-                    }
-                }
-            }
-
-            // Append alumet value
-            match m.value {
-                WrappedMeasurementValue::F64(v) => {
-                    doc.insert("value", v);
-                }
-                WrappedMeasurementValue::U64(v) => {
-                    doc.insert("value", u64_to_bson(v));
-                }
-            }
-
-            // Add the timestamp
-            doc.insert("timestamp", convert_timestamp(m.timestamp));
-
-            docs.push(doc);
+        let docs: Vec<Document> = measurements
+            .iter()
+            .map(|m| mongodb2::convert_point(m, ctx.metrics))
+            .collect();
+
+        // Send the data to MongoDb, with ordered bulk inserts
+        for batch in docs.chunks(self.batch_size) {
+            self.collection
+                .insert_many(batch)
+                .ordered(true)
+                .run()
+                .context("failed to write measurements to MongoDB")
+                .retry_write()?;
         }
-
-        // Send the data to MongoDb
-        let collection = self.client.database(&self.database).collection(&self.collection);
-        let rt = tokio::runtime::Handle::current();
-        rt.block_on(async {
-            collection.insert_many(docs).await.unwrap();
-        });
-
         Ok(())
     }
 }
 
-fn u64_to_bson(v: u64) -> String {
-    format!("{v}u")
-}
-
 #[derive(Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 struct Config {
-    host: String,
-    port: u16,
-    database: String,
-    collection: String,
+    /// Connection string, for instance `mongodb://host1:27017,host2:27017/?replicaSet=rs0&tls=true`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    uri: Option<String>,
+    /// Deprecated: use `uri` instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    host: Option<String>,
+    /// Deprecated: use `uri` instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    port: Option<u16>,
+    /// Username for authentication purpose, inserted in the URI.
     username: Option<String>,
+    /// Password for authentication purpose, inserted in the URI.
     password: Option<String>,
+    database: String,
+    collection: String,
+    /// Maximum number of documents per bulk insert.
+    #[serde(default = "default_batch_size")]
+    batch_size: usize,
+    /// Create the collection as a time series collection, if it does not exist.
+    /// Remove this section to use a regular collection.
+    time_series: Option<TimeSeriesConfig>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TimeSeriesConfig {
+    /// Expected interval between subsequent measurements of the same series.
+    granularity: Granularity,
+    /// Delete the measurements after this duration.
+    #[serde(default, with = "humantime_serde")]
+    expire_after: Option<Duration>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum Granularity {
+    Seconds,
+    Minutes,
+    Hours,
+}
+
+#[cfg_attr(tarpaulin, ignore)]
+fn default_batch_size() -> usize {
+    1000
+}
+
+impl Config {
+    /// Returns the connection URI, built from the deprecated `host` and `port` options if `uri` is not set.
+    fn connection_uri(&self) -> anyhow::Result<String> {
+        match (&self.uri, &self.host, self.port) {
+            (Some(uri), None, None) => Ok(uri.clone()),
+            (Some(_), _, _) => Err(anyhow::anyhow!(
+                "the options `uri` and `host`/`port` are mutually exclusive, please only set `uri`"
+            )),
+            (None, None, None) => Err(anyhow::anyhow!("the option `uri` is required")),
+            (None, host, port) => Ok(format!(
+                "mongodb://{}:{}/",
+                host.as_deref().unwrap_or("localhost"),
+                port.unwrap_or(27017)
+            )),
+        }
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
-            host: String::from("localhost"),
-            port: 27017,
+            uri: Some(String::from("mongodb://localhost:27017/")),
+            host: None,
+            port: None,
+            username: None,
+            password: None,
             database: String::from("FILL ME"),
             collection: String::from("FILL ME"),
-            username: Some(String::from("FILL ME")),
-            password: Some(String::from("FILL ME")),
+            batch_size: default_batch_size(),
+            time_series: Some(TimeSeriesConfig {
+                granularity: Granularity::Seconds,
+                expire_after: None,
+            }),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alumet::plugin::{ConfigTable, rust::deserialize_config};
+
+    use super::Config;
+
+    #[test]
+    fn deprecated_host_and_port() {
+        // config of the previous versions of the plugin
+        let config: toml::Table = toml::from_str(
+            r#"
+            host = "db.example.com"
+            port = 27018
+            database = "db"
+            collection = "measurements"
+            "#,
+        )
+        .unwrap();
+        let config: Config = deserialize_config(ConfigTable(config)).unwrap();
+        assert_eq!(config.connection_uri().unwrap(), "mongodb://db.example.com:27018/");
+        assert_eq!(config.batch_size, 1000);
+        assert!(config.time_series.is_none());
+
+        let config = Config {
+            host: Some(String::from("localhost")),
+            ..Default::default()
+        };
+        assert!(config.connection_uri().is_err(), "uri and host are mutually exclusive");
+
+        let config = Config {
+            uri: None,
+            ..Default::default()
+        };
+        assert!(config.connection_uri().is_err(), "uri is required");
+    }
+}
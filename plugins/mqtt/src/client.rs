@@ -0,0 +1,178 @@
+//! Connection to the MQTT broker.
+
+use std::{
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use anyhow::{Context, anyhow};
+use rumqttc::{Client, ClientError, Connection, Event, MqttOptions, Outgoing, Packet, QoS, Transport};
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of messages waiting to be sent. When the queue is full, publishing blocks.
+const REQUEST_CAPACITY: usize = 10_000;
+
+/// How long to wait before reconnecting to the broker.
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// TLS settings.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// Certificate of the authority that signed the certificate of the broker (PEM).
+    /// If not set, the root certificates of the system are used.
+    pub ca_cert: Option<PathBuf>,
+    /// Certificate of the client (PEM), for mutual authentication.
+    pub client_cert: Option<PathBuf>,
+    /// Private key of the client (PEM), for mutual authentication.
+    pub client_key: Option<PathBuf>,
+}
+
+impl TlsConfig {
+    fn transport(&self) -> anyhow::Result<Transport> {
+        let read = |path: &PathBuf| std::fs::read(path).with_context(|| format!("failed to read {path:?}"));
+        let client_auth = match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => Some((read(cert)?, read(key)?)),
+            (None, None) => None,
+            _ => return Err(anyhow!("`client_cert` and `client_key` must be set together")),
+        };
+        match &self.ca_cert {
+            Some(ca) => Ok(Transport::tls(read(ca)?, client_auth, None)),
+            None if client_auth.is_none() => Ok(Transport::tls_with_default_config()),
+            None => Err(anyhow!("`ca_cert` is required for the client authentication")),
+        }
+    }
+}
+
+/// Parameters of the connection.
+pub struct ConnectionSettings<'a> {
+    pub host: &'a str,
+    pub port: u16,
+    pub client_id: &'a str,
+    pub credentials: Option<(&'a str, &'a str)>,
+    pub keep_alive: Duration,
+    pub tls: Option<&'a TlsConfig>,
+}
+
+/// Converts a QoS level (0, 1 or 2) to a [`QoS`].
+pub fn qos(level: u8) -> anyhow::Result<QoS> {
+    match level {
+        0 => Ok(QoS::AtMostOnce),
+        1 => Ok(QoS::AtLeastOnce),
+        2 => Ok(QoS::ExactlyOnce),
+        _ => Err(anyhow!("invalid QoS level {level}, it must be 0, 1 or 2")),
+    }
+}
+
+/// A client connected to the broker.
+///
+/// The network is handled by a background thread, which reconnects to the broker when needed.
+/// The messages are queued until they can be sent.
+pub struct MqttClient {
+    client: Client,
+    stopping: Arc<AtomicBool>,
+    event_loop: Option<JoinHandle<()>>,
+}
+
+impl MqttClient {
+    pub fn connect(settings: ConnectionSettings) -> anyhow::Result<Self> {
+        let mut options = MqttOptions::new(settings.client_id, settings.host, settings.port);
+        options.set_keep_alive(settings.keep_alive);
+        if let Some((username, password)) = settings.credentials {
+            options.set_credentials(username, password);
+        }
+        if let Some(tls) = settings.tls {
+            options.set_transport(tls.transport()?);
+        }
+
+        let (client, connection) = Client::new(options, REQUEST_CAPACITY);
+        let stopping = Arc::new(AtomicBool::new(false));
+        let event_loop = std::thread::Builder::new()
+            .name(String::from("mqtt-event-loop"))
+            .spawn({
+                let stopping = stopping.clone();
+                let broker = format!("{}:{}", settings.host, settings.port);
+                move || run_event_loop(connection, broker, stopping)
+            })
+            .context("failed to spawn the MQTT thread")?;
+        Ok(Self {
+            client,
+            stopping,
+            event_loop: Some(event_loop),
+        })
+    }
+
+    /// Queues a message. Blocks if the queue is full.
+    ///
+    /// Fails only if the event loop has stopped.
+    pub fn publish(&self, topic: String, qos: QoS, retain: bool, payload: Vec<u8>) -> Result<(), ClientError> {
+        self.client.publish(topic, qos, retain, payload)
+    }
+}
+
+impl Drop for MqttClient {
+    fn drop(&mut self) {
+        self.stopping.store(true, Ordering::Relaxed);
+        // The disconnection is queued after the pending messages, so they are sent before it.
+        if let Err(e) = self.client.disconnect() {
+            log::debug!("failed to disconnect from the MQTT broker: {e}");
+        }
+        if let Some(handle) = self.event_loop.take()
+            && handle.join().is_err()
+        {
+            log::error!("the MQTT thread panicked");
+        }
+    }
+}
+
+fn run_event_loop(mut connection: Connection, broker: String, stopping: Arc<AtomicBool>) {
+    for event in connection.iter() {
+        match event {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => log::info!("Connected to the MQTT broker {broker}"),
+            Ok(Event::Outgoing(Outgoing::Disconnect)) => break,
+            Ok(_) => (),
+            Err(e) => {
+                if stopping.load(Ordering::Relaxed) {
+                    break;
+                }
+                log::warn!("MQTT connection error with {broker}: {e}, reconnecting in {RECONNECT_DELAY:?}");
+                std::thread::sleep(RECONNECT_DELAY);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rumqttc::QoS;
+
+    use super::{TlsConfig, qos};
+
+    #[test]
+    fn qos_levels() {
+        assert_eq!(qos(0).unwrap(), QoS::AtMostOnce);
+        assert_eq!(qos(1).unwrap(), QoS::AtLeastOnce);
+        assert_eq!(qos(2).unwrap(), QoS::ExactlyOnce);
+        assert!(qos(3).is_err());
+    }
+
+    #[test]
+    fn tls_options() {
+        let tls = TlsConfig {
+            client_cert: Some("client.pem".into()),
+            ..Default::default()
+        };
+        assert!(tls.transport().is_err());
+
+        let tls = TlsConfig {
+            ca_cert: Some("/nonexistent/ca.pem".into()),
+            ..Default::default()
+        };
+        assert!(tls.transport().is_err());
+    }
+}
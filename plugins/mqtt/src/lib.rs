@@ -0,0 +1,199 @@
+mod client;
+mod payload;
+mod topic;
+
+use std::time::Duration;
+
+use alumet::{
+    measurement::MeasurementBuffer,
+    pipeline::{
+        Output,
+        elements::{error::WriteError, output::OutputContext},
+    },
+    plugin::{
+        AlumetPluginStart, ConfigTable,
+        rust::{AlumetPlugin, deserialize_config, serialize_config},
+    },
+};
+use anyhow::{Context, anyhow};
+use rumqttc::QoS;
+use serde::{Deserialize, Serialize};
+
+pub use client::TlsConfig;
+use client::{ConnectionSettings, MqttClient};
+pub use payload::{BINARY_PAYLOAD_LEN, PayloadFormat};
+use topic::TopicTemplate;
+
+pub struct MqttPlugin {
+    config: Option<Config>,
+}
+
+impl AlumetPlugin for MqttPlugin {
+    fn name() -> &'static str {
+        "mqtt"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(MqttPlugin { config: Some(config) }))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let config = self.config.take().unwrap();
+        let hostname = hostname::get()
+            .context("failed to get the hostname")?
+            .to_string_lossy()
+            .to_string();
+        let topic = TopicTemplate::parse(&config.topic_template, &hostname)?;
+        let qos = client::qos(config.qos)?;
+        let client_id = config.client_id.unwrap_or_else(|| format!("alumet-{hostname}"));
+        let credentials = match (&config.username, &config.password) {
+            (Some(username), password) => Some((username.as_str(), password.as_deref().unwrap_or_default())),
+            (None, None) => None,
+            (None, Some(_)) => return Err(anyhow!("`password` requires a `username`")),
+        };
+
+        let client = MqttClient::connect(ConnectionSettings {
+            host: &config.host,
+            port: config.port,
+            client_id: &client_id,
+            credentials,
+            keep_alive: config.keep_alive,
+            tls: config.tls.as_ref(),
+        })?;
+        let output = MqttOutput {
+            client,
+            topic,
+            qos,
+            retain: config.retain,
+            payload_format: config.payload_format,
+        };
+        alumet.add_blocking_output("out", Box::new(output))?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+struct MqttOutput {
+    client: MqttClient,
+    topic: TopicTemplate,
+    qos: QoS,
+    retain: bool,
+    payload_format: PayloadFormat,
+}
+
+impl Output for MqttOutput {
+    fn write(&mut self, measurements: &MeasurementBuffer, ctx: &OutputContext) -> Result<(), WriteError> {
+        for m in measurements {
+            let metric = ctx.metrics.by_id(&m.metric).expect("unknown metric");
+            let topic = self.topic.render(m, metric);
+            let payload = payload::encode(self.payload_format, m, metric);
+            // The messages are queued while the broker is unreachable: an error here means
+            // that the event loop has stopped, which cannot be fixed by retrying.
+            self.client
+                .publish(topic, self.qos, self.retain, payload)
+                .context("failed to publish a measurement")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Host name or IP address of the broker.
+    pub host: String,
+    /// Port of the broker, usually 1883 without TLS and 8883 with TLS.
+    pub port: u16,
+    /// Identifier of the client. Defaults to `alumet-{hostname}`.
+    pub client_id: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Maximum time between two messages exchanged with the broker.
+    #[serde(with = "humantime_serde")]
+    pub keep_alive: Duration,
+    /// Topic of the messages. Available placeholders: `{hostname}`, `{metric}`, `{unit}`,
+    /// `{resource_kind}`, `{resource_id}`, `{consumer_kind}`, `{consumer_id}` and `{attr:NAME}`.
+    pub topic_template: String,
+    /// Quality of service: 0 (at most once), 1 (at least once) or 2 (exactly once).
+    pub qos: u8,
+    /// Ask the broker to retain the last message of each topic, for the new subscribers.
+    pub retain: bool,
+    /// Format of the payloads: `"json"` or `"binary"`.
+    pub payload_format: PayloadFormat,
+    /// Enables TLS, if set.
+    pub tls: Option<TlsConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            host: String::from("localhost"),
+            port: 1883,
+            client_id: None,
+            username: None,
+            password: None,
+            keep_alive: Duration::from_secs(30),
+            topic_template: String::from("alumet/{hostname}/{metric}/{resource_kind}/{resource_id}"),
+            qos: 0,
+            retain: false,
+            payload_format: PayloadFormat::Json,
+            tls: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Config, PayloadFormat, TlsConfig};
+
+    #[test]
+    fn parse_config() {
+        let config: Config = toml::from_str(
+            r#"
+            host = "broker.local"
+            port = 8883
+            client_id = "edge-1"
+            username = "alumet"
+            password = "secret"
+            keep_alive = "1m"
+            topic_template = "site/{hostname}/{metric}"
+            qos = 1
+            retain = true
+            payload_format = "binary"
+
+            [tls]
+            ca_cert = "ca.pem"
+        "#,
+        )
+        .unwrap();
+        assert_eq!(config.port, 8883);
+        assert_eq!(config.keep_alive, Duration::from_secs(60));
+        assert_eq!(config.payload_format, PayloadFormat::Binary);
+        assert_eq!(
+            config.tls,
+            Some(TlsConfig {
+                ca_cert: Some("ca.pem".into()),
+                ..Default::default()
+            })
+        );
+
+        let default = toml::to_string(&Config::default()).unwrap();
+        let config: Config = toml::from_str(&default).unwrap();
+        assert_eq!(config.host, "localhost");
+        assert_eq!(config.tls, None);
+    }
+}
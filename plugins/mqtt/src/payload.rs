@@ -0,0 +1,160 @@
+//! Payloads of the MQTT messages.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use alumet::{
+    measurement::{MeasurementPoint, WrappedMeasurementValue},
+    metrics::Metric,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+
+/// Format of the payload of each message.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadFormat {
+    /// A JSON object with the value, the timestamp and the context of the measurement.
+    Json,
+    /// 17 bytes: the timestamp, the type of the value and the value.
+    Binary,
+}
+
+/// Size of a binary payload, in bytes.
+pub const BINARY_PAYLOAD_LEN: usize = 17;
+
+/// Encodes a measurement point.
+pub fn encode(format: PayloadFormat, m: &MeasurementPoint, metric: &Metric) -> Vec<u8> {
+    match format {
+        PayloadFormat::Json => json(m, metric),
+        PayloadFormat::Binary => binary(m).to_vec(),
+    }
+}
+
+fn timestamp_ns(m: &MeasurementPoint) -> u64 {
+    let t = SystemTime::from(m.timestamp)
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    t.as_nanos() as u64
+}
+
+/// Encodes a point in JSON.
+///
+/// The topic usually contains the metric and the resource, but they are repeated in the payload
+/// so that each message can be interpreted on its own.
+fn json(m: &MeasurementPoint, metric: &Metric) -> Vec<u8> {
+    let value = match m.value {
+        WrappedMeasurementValue::F64(v) => Value::from(v),
+        WrappedMeasurementValue::U64(v) => Value::from(v),
+    };
+    let attributes: Map<String, Value> = m.attributes().map(|(k, v)| (k.to_owned(), Value::from(v))).collect();
+    let payload = json!({
+        "timestamp_ns": timestamp_ns(m),
+        "metric": metric.name,
+        "unit": metric.unit.unique_name(),
+        "value": value,
+        "resource_kind": m.resource.kind(),
+        "resource_id": m.resource.id_display().to_string(),
+        "consumer_kind": m.consumer.kind(),
+        "consumer_id": m.consumer.id_display().to_string(),
+        "attributes": attributes,
+    });
+    serde_json::to_vec(&payload).expect("a JSON value should always be serializable")
+}
+
+/// Encodes a point in a compact binary form, for constrained devices and links.
+///
+/// Layout, all integers in little-endian:
+/// - bytes 0..8: timestamp, in nanoseconds since the Unix epoch (u64)
+/// - byte 8: type of the value, `0` for u64 and `1` for f64
+/// - bytes 9..17: value (u64 or IEEE 754 f64)
+///
+/// The attributes are not included: use the topic template to distinguish the points.
+fn binary(m: &MeasurementPoint) -> [u8; BINARY_PAYLOAD_LEN] {
+    let mut payload = [0u8; BINARY_PAYLOAD_LEN];
+    payload[0..8].copy_from_slice(&timestamp_ns(m).to_le_bytes());
+    let (tag, value) = match m.value {
+        WrappedMeasurementValue::U64(v) => (0, v.to_le_bytes()),
+        WrappedMeasurementValue::F64(v) => (1, v.to_le_bytes()),
+    };
+    payload[8] = tag;
+    payload[9..17].copy_from_slice(&value);
+    payload
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use alumet::{
+        measurement::{MeasurementPoint, Timestamp, WrappedMeasurementType, WrappedMeasurementValue},
+        metrics::{Metric, RawMetricId},
+        resources::{Resource, ResourceConsumer},
+        units::{PrefixedUnit, Unit},
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::{PayloadFormat, encode};
+
+    fn point(value: WrappedMeasurementValue) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::from(UNIX_EPOCH + Duration::from_nanos(1_500_000_001)),
+            RawMetricId::from_u64(0),
+            Resource::CpuPackage { id: 0 },
+            ResourceConsumer::Process { pid: 42 },
+            value,
+        )
+    }
+
+    fn metric() -> Metric {
+        Metric {
+            name: String::from("rapl_consumed_energy"),
+            description: String::new(),
+            value_type: WrappedMeasurementType::F64,
+            unit: PrefixedUnit::milli(Unit::Joule),
+        }
+    }
+
+    #[test]
+    fn json() {
+        let m = point(WrappedMeasurementValue::F64(12.5))
+            .with_attr("domain", "package")
+            .with_attr("core", 3u64);
+        let payload = encode(PayloadFormat::Json, &m, &metric());
+        let value: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "timestamp_ns": 1_500_000_001u64,
+                "metric": "rapl_consumed_energy",
+                "unit": "milliJ",
+                "value": 12.5,
+                "resource_kind": "cpu_package",
+                "resource_id": "0",
+                "consumer_kind": "process",
+                "consumer_id": "42",
+                "attributes": { "domain": "package", "core": 3 },
+            })
+        );
+    }
+
+    #[test]
+    fn binary() {
+        let payload = encode(
+            PayloadFormat::Binary,
+            &point(WrappedMeasurementValue::U64(7)),
+            &metric(),
+        );
+        assert_eq!(payload.len(), 17);
+        assert_eq!(u64::from_le_bytes(payload[0..8].try_into().unwrap()), 1_500_000_001);
+        assert_eq!(payload[8], 0);
+        assert_eq!(u64::from_le_bytes(payload[9..17].try_into().unwrap()), 7);
+
+        let payload = encode(
+            PayloadFormat::Binary,
+            &point(WrappedMeasurementValue::F64(-0.25)),
+            &metric(),
+        );
+        assert_eq!(payload[8], 1);
+        assert_eq!(f64::from_le_bytes(payload[9..17].try_into().unwrap()), -0.25);
+    }
+}
@@ -0,0 +1,188 @@
+//! Topic templates, like `alumet/{hostname}/{metric}/{resource_kind}/{resource_id}`.
+
+use alumet::{measurement::MeasurementPoint, metrics::Metric};
+use anyhow::anyhow;
+
+#[derive(Debug, PartialEq)]
+enum Part {
+    Literal(String),
+    Metric,
+    Unit,
+    ResourceKind,
+    ResourceId,
+    ConsumerKind,
+    ConsumerId,
+    Attribute(String),
+}
+
+/// A parsed topic template.
+#[derive(Debug)]
+pub struct TopicTemplate {
+    parts: Vec<Part>,
+}
+
+impl TopicTemplate {
+    /// Parses a template. The `{hostname}` placeholder is replaced immediately.
+    pub fn parse(template: &str, hostname: &str) -> anyhow::Result<Self> {
+        if template.is_empty() {
+            return Err(anyhow!("the topic template is empty"));
+        }
+        if template.contains(['+', '#']) {
+            return Err(anyhow!("the topic template cannot contain the wildcards '+' and '#'"));
+        }
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            literal.push_str(&rest[..start]);
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| anyhow!("unclosed '{{' in the topic template {template:?}"))?;
+            let name = &rest[start + 1..start + end];
+            let part = match name {
+                "hostname" => {
+                    literal.push_str(&topic_level(hostname));
+                    None
+                }
+                "metric" => Some(Part::Metric),
+                "unit" => Some(Part::Unit),
+                "resource_kind" => Some(Part::ResourceKind),
+                "resource_id" => Some(Part::ResourceId),
+                "consumer_kind" => Some(Part::ConsumerKind),
+                "consumer_id" => Some(Part::ConsumerId),
+                _ => match name.strip_prefix("attr:") {
+                    Some(key) if !key.is_empty() => Some(Part::Attribute(key.to_owned())),
+                    _ => return Err(anyhow!("unknown placeholder {{{name}}} in the topic template")),
+                },
+            };
+            if let Some(part) = part {
+                if !literal.is_empty() {
+                    parts.push(Part::Literal(std::mem::take(&mut literal)));
+                }
+                parts.push(part);
+            }
+            rest = &rest[start + end + 1..];
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Self { parts })
+    }
+
+    /// Returns the topic of a measurement point.
+    pub fn render(&self, m: &MeasurementPoint, metric: &Metric) -> String {
+        let mut topic = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(s) => topic.push_str(s),
+                Part::Metric => topic.push_str(&topic_level(&metric.name)),
+                Part::Unit => topic.push_str(&topic_level(&metric.unit.unique_name())),
+                Part::ResourceKind => topic.push_str(&topic_level(m.resource.kind())),
+                Part::ResourceId => topic.push_str(&topic_level(&m.resource.id_display().to_string())),
+                Part::ConsumerKind => topic.push_str(&topic_level(m.consumer.kind())),
+                Part::ConsumerId => topic.push_str(&topic_level(&m.consumer.id_display().to_string())),
+                Part::Attribute(key) => {
+                    let value = m
+                        .attributes()
+                        .find(|(k, _)| *k == key.as_str())
+                        .map(|(_, v)| v.to_string())
+                        .unwrap_or_default();
+                    topic.push_str(&topic_level(&value));
+                }
+            }
+        }
+        topic
+    }
+}
+
+/// Makes a value usable in a topic, by replacing the topic separator and the wildcards with `_`.
+///
+/// Empty values are replaced by `_`, to avoid empty topic levels.
+fn topic_level(value: &str) -> String {
+    if value.is_empty() {
+        return String::from("_");
+    }
+    value.replace(['/', '+', '#', '\0'], "_")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use alumet::{
+        measurement::{MeasurementPoint, Timestamp, WrappedMeasurementType, WrappedMeasurementValue},
+        metrics::{Metric, RawMetricId},
+        resources::{Resource, ResourceConsumer},
+        units::{PrefixedUnit, Unit},
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::{Part, TopicTemplate};
+
+    fn render(template: &str, consumer: ResourceConsumer) -> String {
+        let metric = Metric {
+            name: String::from("rapl_consumed_energy"),
+            description: String::new(),
+            value_type: WrappedMeasurementType::F64,
+            unit: PrefixedUnit::milli(Unit::Joule),
+        };
+        let point = MeasurementPoint::new_untyped(
+            Timestamp::from(UNIX_EPOCH),
+            RawMetricId::from_u64(0),
+            Resource::CpuPackage { id: 0 },
+            consumer,
+            WrappedMeasurementValue::F64(1.0),
+        )
+        .with_attr("domain", "package");
+        TopicTemplate::parse(template, "node-1")
+            .unwrap()
+            .render(&point, &metric)
+    }
+
+    #[test]
+    fn parse() {
+        let template = TopicTemplate::parse("alumet/{hostname}/{metric}_{unit}", "node-1").unwrap();
+        assert_eq!(
+            template.parts,
+            vec![
+                Part::Literal(String::from("alumet/node-1/")),
+                Part::Metric,
+                Part::Literal(String::from("_")),
+                Part::Unit,
+            ]
+        );
+        assert!(TopicTemplate::parse("", "h").is_err());
+        assert!(TopicTemplate::parse("alumet/#", "h").is_err());
+        assert!(TopicTemplate::parse("alumet/{metric", "h").is_err());
+        assert!(TopicTemplate::parse("alumet/{unknown}", "h").is_err());
+        assert!(TopicTemplate::parse("alumet/{attr:}", "h").is_err());
+    }
+
+    #[test]
+    fn render_topics() {
+        assert_eq!(
+            render(
+                "alumet/{hostname}/{metric}/{resource_kind}/{resource_id}",
+                ResourceConsumer::LocalMachine
+            ),
+            "alumet/node-1/rapl_consumed_energy/cpu_package/0"
+        );
+        assert_eq!(
+            render(
+                "alumet/{metric}/{unit}/{consumer_kind}/{consumer_id}",
+                ResourceConsumer::LocalMachine
+            ),
+            "alumet/rapl_consumed_energy/milliJ/local_machine/_"
+        );
+        assert_eq!(
+            render(
+                "{consumer_kind}/{consumer_id}/{attr:domain}/{attr:missing}",
+                ResourceConsumer::ControlGroup {
+                    path: "/system.slice/foo+bar.service".into()
+                }
+            ),
+            "cgroup/_system.slice_foo_bar.service/package/_"
+        );
+    }
+}
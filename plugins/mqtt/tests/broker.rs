@@ -0,0 +1,147 @@
+//! Tests that require an MQTT broker listening on localhost:1883, for instance:
+//!
+//! ```sh
+//! docker run --rm -p 1883:1883 eclipse-mosquitto:2 mosquitto -c /mosquitto-no-auth.conf
+//! cargo test -p plugin-mqtt -- --ignored
+//! ```
+
+use std::{
+    sync::mpsc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use alumet::{
+    agent::{
+        self,
+        plugin::{PluginInfo, PluginSet},
+    },
+    measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+    pipeline::naming::OutputName,
+    plugin::PluginMetadata,
+    resources::{Resource, ResourceConsumer},
+    test::RuntimeExpectations,
+    units::Unit,
+};
+use plugin_mqtt::{BINARY_PAYLOAD_LEN, Config, MqttPlugin, PayloadFormat};
+use pretty_assertions::assert_eq;
+use rumqttc::{Client, Event, MqttOptions, Packet, QoS};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn config_to_toml_table(config: &Config) -> toml::Table {
+    toml::Value::try_from(config).unwrap().as_table().unwrap().clone()
+}
+
+/// Subscribes to `filter` and returns the received messages, as `(topic, payload)`.
+fn subscribe(client_id: &str, filter: &str) -> (Client, mpsc::Receiver<(String, Vec<u8>)>) {
+    let options = MqttOptions::new(client_id, "localhost", 1883);
+    let (client, mut connection) = Client::new(options, 100);
+    client.subscribe(filter, QoS::AtLeastOnce).unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let (subscribed_tx, subscribed_rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        for event in connection.iter() {
+            match event {
+                Ok(Event::Incoming(Packet::SubAck(_))) => subscribed_tx.send(()).unwrap(),
+                Ok(Event::Incoming(Packet::Publish(p))) => {
+                    if tx.send((p.topic, p.payload.to_vec())).is_err() {
+                        break;
+                    }
+                }
+                Ok(_) => (),
+                Err(_) => break,
+            }
+        }
+    });
+    subscribed_rx.recv_timeout(TIMEOUT).expect("subscription timeout");
+    (client, rx)
+}
+
+fn run_agent(config: Config, check: impl Fn() + Send + 'static) {
+    let mut plugins = PluginSet::new();
+    plugins.add_plugin(PluginInfo {
+        metadata: PluginMetadata::from_static::<MqttPlugin>(),
+        enabled: true,
+        config: Some(config_to_toml_table(&config)),
+    });
+
+    let runtime_expectations = RuntimeExpectations::new()
+        .create_metric::<u64>("test_metric", Unit::Joule)
+        .test_output(
+            OutputName::from_str("mqtt", "out"),
+            |ctx| {
+                let metric = ctx.metrics().by_name("test_metric").unwrap().0;
+                let point = MeasurementPoint::new_untyped(
+                    Timestamp::from(UNIX_EPOCH + Duration::from_secs(1)),
+                    metric,
+                    Resource::CpuPackage { id: 0 },
+                    ResourceConsumer::LocalMachine,
+                    WrappedMeasurementValue::U64(12),
+                )
+                .with_attr("domain", "package");
+                MeasurementBuffer::from(vec![point])
+            },
+            check,
+        );
+
+    let agent = agent::Builder::new(plugins)
+        .with_expectations(runtime_expectations)
+        .build_and_start()
+        .unwrap();
+    agent.wait_for_shutdown(TIMEOUT).unwrap();
+}
+
+#[test]
+#[ignore = "requires an MQTT broker on localhost:1883"]
+fn publish_json() {
+    let _ = env_logger::Builder::from_default_env().try_init();
+    let (_subscriber, rx) = subscribe("alumet-test-json-sub", "alumet-test-json/#");
+
+    let config = Config {
+        client_id: Some(String::from("alumet-test-json")),
+        topic_template: String::from("alumet-test-json/{metric}/{resource_kind}/{resource_id}"),
+        qos: 1,
+        ..Config::default()
+    };
+    run_agent(config, move || {
+        let (topic, payload) = rx.recv_timeout(TIMEOUT).expect("no message received");
+        assert_eq!(topic, "alumet-test-json/test_metric/cpu_package/0");
+        let payload: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(
+            payload,
+            serde_json::json!({
+                "timestamp_ns": 1_000_000_000u64,
+                "metric": "test_metric",
+                "unit": "J",
+                "value": 12,
+                "resource_kind": "cpu_package",
+                "resource_id": "0",
+                "consumer_kind": "local_machine",
+                "consumer_id": "",
+                "attributes": { "domain": "package" },
+            })
+        );
+    });
+}
+
+#[test]
+#[ignore = "requires an MQTT broker on localhost:1883"]
+fn publish_binary() {
+    let _ = env_logger::Builder::from_default_env().try_init();
+    let (_subscriber, rx) = subscribe("alumet-test-binary-sub", "alumet-test-binary/#");
+
+    let config = Config {
+        client_id: Some(String::from("alumet-test-binary")),
+        topic_template: String::from("alumet-test-binary/{metric}/{attr:domain}"),
+        qos: 1,
+        payload_format: PayloadFormat::Binary,
+        ..Config::default()
+    };
+    run_agent(config, move || {
+        let (topic, payload) = rx.recv_timeout(TIMEOUT).expect("no message received");
+        assert_eq!(topic, "alumet-test-binary/test_metric/package");
+        assert_eq!(payload.len(), BINARY_PAYLOAD_LEN);
+        assert_eq!(u64::from_le_bytes(payload[9..17].try_into().unwrap()), 12);
+    });
+}
@@ -0,0 +1,581 @@
+//! Conversion of OTLP metrics to Alumet metrics and measurements.
+
+use std::{collections::HashMap, fmt::Write, str::FromStr};
+
+use alumet::{
+    measurement::{AttributeValue, Timestamp, WrappedMeasurementType, WrappedMeasurementValue},
+    metrics::Metric,
+    resources::{Resource, ResourceConsumer},
+    units::{PrefixedUnit, Unit},
+};
+use opentelemetry_proto::tonic::{
+    collector::metrics::v1::ExportMetricsServiceRequest,
+    common::v1::{AnyValue, KeyValue, any_value},
+    metrics::v1::{DataPointFlags, NumberDataPoint, metric::Data, number_data_point},
+};
+
+/// Converts the content of OTLP requests to Alumet types.
+pub struct Converter {
+    /// Prefix added to the name of every received metric.
+    pub metric_prefix: String,
+    /// Keys of the OpenTelemetry resource attributes that are copied to every measurement point.
+    pub resource_attributes: Vec<String>,
+}
+
+/// Metrics and data points extracted from an OTLP request.
+///
+/// The metrics are not registered yet: each point refers to its metric
+/// by its index in `metrics`.
+#[derive(Debug, Default)]
+pub struct ConvertedMetrics {
+    pub metrics: Vec<Metric>,
+    pub points: Vec<ConvertedPoint>,
+}
+
+/// A data point extracted from an OTLP request.
+#[derive(Debug, PartialEq)]
+pub struct ConvertedPoint {
+    /// Index of the metric in [`ConvertedMetrics::metrics`].
+    pub metric_index: usize,
+    pub timestamp: Timestamp,
+    pub value: WrappedMeasurementValue,
+    pub resource: Resource,
+    pub consumer: ResourceConsumer,
+    pub attributes: Vec<(String, AttributeValue)>,
+}
+
+/// Information shared by all the points of an OpenTelemetry resource.
+struct PointContext {
+    consumer: ResourceConsumer,
+    attributes: Vec<(String, AttributeValue)>,
+    default_timestamp: Timestamp,
+}
+
+impl ConvertedMetrics {
+    /// Returns the index of the metric, adding it to the list if it is not already there.
+    fn metric_index(&mut self, indices: &mut HashMap<String, usize>, metric: Metric) -> usize {
+        *indices.entry(metric.name.clone()).or_insert_with(|| {
+            self.metrics.push(metric);
+            self.metrics.len() - 1
+        })
+    }
+
+    fn push_point(
+        &mut self,
+        ctx: &PointContext,
+        metric_index: usize,
+        time_unix_nano: u64,
+        value: WrappedMeasurementValue,
+        attributes: &[KeyValue],
+    ) {
+        let mut point_attributes = ctx.attributes.clone();
+        point_attributes.extend(attributes.iter().filter_map(convert_attribute));
+        self.points.push(ConvertedPoint {
+            metric_index,
+            timestamp: convert_timestamp(time_unix_nano, ctx.default_timestamp),
+            value,
+            resource: Resource::LocalMachine,
+            consumer: ctx.consumer.clone(),
+            attributes: point_attributes,
+        });
+    }
+}
+
+impl Converter {
+    /// Converts an OTLP export request.
+    ///
+    /// Histograms and summaries are converted to two metrics: `<name>_count` and `<name>_sum`.
+    pub fn convert(&self, request: ExportMetricsServiceRequest) -> ConvertedMetrics {
+        let mut res = ConvertedMetrics::default();
+        let mut indices = HashMap::new();
+        let now = Timestamp::now();
+
+        for resource_metrics in request.resource_metrics {
+            let resource_attributes = resource_metrics.resource.map(|r| r.attributes).unwrap_or_default();
+            let ctx = PointContext {
+                consumer: consumer_from_resource(&resource_attributes),
+                attributes: resource_attributes
+                    .iter()
+                    .filter(|kv| self.resource_attributes.contains(&kv.key))
+                    .filter_map(convert_attribute)
+                    .collect(),
+                default_timestamp: now,
+            };
+
+            for scope_metrics in resource_metrics.scope_metrics {
+                for metric in scope_metrics.metrics {
+                    let name = format!("{}{}", self.metric_prefix, metric.name);
+                    let unit = convert_unit(&metric.unit);
+                    let description = if metric.description.is_empty() {
+                        String::from("metric received via OTLP")
+                    } else {
+                        metric.description
+                    };
+                    let new_metric = |name: String, value_type: WrappedMeasurementType, unit: PrefixedUnit| Metric {
+                        name,
+                        description: description.clone(),
+                        value_type,
+                        unit,
+                    };
+
+                    match metric.data {
+                        Some(Data::Gauge(gauge)) => {
+                            let i = res.metric_index(&mut indices, new_metric(name, WrappedMeasurementType::F64, unit));
+                            for p in gauge.data_points.iter().filter(|p| has_value(p.flags)) {
+                                if let Some(value) = number_value(p, &WrappedMeasurementType::F64) {
+                                    res.push_point(&ctx, i, p.time_unix_nano, value, &p.attributes);
+                                }
+                            }
+                        }
+                        Some(Data::Sum(sum)) => {
+                            // Monotonic integer sums are counters: they fit in an u64.
+                            let value_type = if sum.is_monotonic && sum.data_points.iter().all(is_int) {
+                                WrappedMeasurementType::U64
+                            } else {
+                                WrappedMeasurementType::F64
+                            };
+                            let i = res.metric_index(&mut indices, new_metric(name, value_type.clone(), unit));
+                            for p in sum.data_points.iter().filter(|p| has_value(p.flags)) {
+                                if let Some(value) = number_value(p, &value_type) {
+                                    res.push_point(&ctx, i, p.time_unix_nano, value, &p.attributes);
+                                }
+                            }
+                        }
+                        Some(Data::Histogram(histogram)) => {
+                            let (i_count, i_sum) = res.count_and_sum(&mut indices, &name, unit, new_metric);
+                            for p in histogram.data_points.iter().filter(|p| has_value(p.flags)) {
+                                let count = WrappedMeasurementValue::U64(p.count);
+                                res.push_point(&ctx, i_count, p.time_unix_nano, count, &p.attributes);
+                                if let Some(sum) = p.sum {
+                                    let sum = WrappedMeasurementValue::F64(sum);
+                                    res.push_point(&ctx, i_sum, p.time_unix_nano, sum, &p.attributes);
+                                }
+                            }
+                        }
+                        Some(Data::ExponentialHistogram(histogram)) => {
+                            let (i_count, i_sum) = res.count_and_sum(&mut indices, &name, unit, new_metric);
+                            for p in histogram.data_points.iter().filter(|p| has_value(p.flags)) {
+                                let count = WrappedMeasurementValue::U64(p.count);
+                                res.push_point(&ctx, i_count, p.time_unix_nano, count, &p.attributes);
+                                if let Some(sum) = p.sum {
+                                    let sum = WrappedMeasurementValue::F64(sum);
+                                    res.push_point(&ctx, i_sum, p.time_unix_nano, sum, &p.attributes);
+                                }
+                            }
+                        }
+                        Some(Data::Summary(summary)) => {
+                            let (i_count, i_sum) = res.count_and_sum(&mut indices, &name, unit, new_metric);
+                            for p in summary.data_points.iter().filter(|p| has_value(p.flags)) {
+                                let count = WrappedMeasurementValue::U64(p.count);
+                                res.push_point(&ctx, i_count, p.time_unix_nano, count, &p.attributes);
+                                let sum = WrappedMeasurementValue::F64(p.sum);
+                                res.push_point(&ctx, i_sum, p.time_unix_nano, sum, &p.attributes);
+                            }
+                        }
+                        None => {
+                            log::debug!("Ignoring OTLP metric {name} because it has no data.");
+                        }
+                    }
+                }
+            }
+        }
+        res
+    }
+}
+
+impl ConvertedMetrics {
+    /// Adds the `_count` and `_sum` metrics that represent a histogram or a summary.
+    fn count_and_sum(
+        &mut self,
+        indices: &mut HashMap<String, usize>,
+        name: &str,
+        unit: PrefixedUnit,
+        new_metric: impl Fn(String, WrappedMeasurementType, PrefixedUnit) -> Metric,
+    ) -> (usize, usize) {
+        let count = new_metric(format!("{name}_count"), WrappedMeasurementType::U64, Unit::Unity.into());
+        let sum = new_metric(format!("{name}_sum"), WrappedMeasurementType::F64, unit);
+        (self.metric_index(indices, count), self.metric_index(indices, sum))
+    }
+}
+
+/// Returns `false` if the flags indicate that the point has no recorded value.
+fn has_value(flags: u32) -> bool {
+    flags & (DataPointFlags::NoRecordedValueMask as u32) == 0
+}
+
+fn is_int(p: &NumberDataPoint) -> bool {
+    matches!(p.value, Some(number_data_point::Value::AsInt(_)))
+}
+
+/// Converts the value of a data point to the given type.
+fn number_value(p: &NumberDataPoint, value_type: &WrappedMeasurementType) -> Option<WrappedMeasurementValue> {
+    use number_data_point::Value;
+
+    let value = match (value_type, p.value?) {
+        (WrappedMeasurementType::U64, Value::AsInt(v)) => WrappedMeasurementValue::U64(u64::try_from(v).ok()?),
+        (WrappedMeasurementType::U64, Value::AsDouble(v)) => WrappedMeasurementValue::U64(v as u64),
+        (WrappedMeasurementType::F64, Value::AsInt(v)) => WrappedMeasurementValue::F64(v as f64),
+        (WrappedMeasurementType::F64, Value::AsDouble(v)) => WrappedMeasurementValue::F64(v),
+    };
+    Some(value)
+}
+
+/// Converts an OTLP timestamp, in nanoseconds since the UNIX epoch.
+///
+/// The timestamp is optional in OTLP: `0` means that it is missing.
+fn convert_timestamp(time_unix_nano: u64, default: Timestamp) -> Timestamp {
+    if time_unix_nano == 0 {
+        default
+    } else {
+        Timestamp::from_unix_timestamp(time_unix_nano / 1_000_000_000, (time_unix_nano % 1_000_000_000) as u32)
+    }
+}
+
+/// Converts an OTLP unit, which is a UCUM string, to an Alumet unit.
+///
+/// Units that are unknown to Alumet, such as annotations like `{request}`, become custom units.
+fn convert_unit(unit: &str) -> PrefixedUnit {
+    // "1" is the UCUM code of dimensionless values
+    if unit.is_empty() || unit == "1" {
+        return Unit::Unity.into();
+    }
+    PrefixedUnit::from_str(unit).unwrap_or_else(|_| {
+        PrefixedUnit::from(Unit::Custom {
+            unique_name: unit.to_owned(),
+            display_name: unit.to_owned(),
+        })
+    })
+}
+
+/// Finds the consumer that corresponds to an OpenTelemetry resource, based on its semantic attributes.
+///
+/// See <https://opentelemetry.io/docs/specs/semconv/resource/>.
+fn consumer_from_resource(attributes: &[KeyValue]) -> ResourceConsumer {
+    let find = |key: &str| {
+        attributes
+            .iter()
+            .find(|kv| kv.key == key)
+            .and_then(|kv| kv.value.as_ref())
+            .and_then(|v| v.value.as_ref())
+    };
+
+    let pid = match find("process.pid") {
+        Some(any_value::Value::IntValue(pid)) => u32::try_from(*pid).ok(),
+        Some(any_value::Value::StringValue(pid)) => pid.parse().ok(),
+        _ => None,
+    };
+    if let Some(pid) = pid {
+        return ResourceConsumer::Process { pid };
+    }
+    if let Some(any_value::Value::StringValue(id)) = find("container.id") {
+        return ResourceConsumer::custom("container", id.to_owned());
+    }
+    ResourceConsumer::LocalMachine
+}
+
+/// Converts an OpenTelemetry attribute to an Alumet attribute.
+///
+/// Attributes without a value are ignored.
+fn convert_attribute(kv: &KeyValue) -> Option<(String, AttributeValue)> {
+    let value = match kv.value.as_ref()?.value.as_ref()? {
+        any_value::Value::StringValue(s) => AttributeValue::String(s.to_owned()),
+        any_value::Value::BoolValue(b) => AttributeValue::Bool(*b),
+        any_value::Value::IntValue(i) => match u64::try_from(*i) {
+            Ok(u) => AttributeValue::U64(u),
+            Err(_) => AttributeValue::F64(*i as f64),
+        },
+        any_value::Value::DoubleValue(d) => AttributeValue::F64(*d),
+        complex => {
+            let mut buf = String::new();
+            format_any_value(&mut buf, complex);
+            AttributeValue::String(buf)
+        }
+    };
+    Some((kv.key.to_owned(), value))
+}
+
+/// Formats an OpenTelemetry value that has no equivalent in Alumet attributes.
+fn format_any_value(buf: &mut String, value: &any_value::Value) {
+    fn format_opt(buf: &mut String, value: &Option<AnyValue>) {
+        if let Some(v) = value.as_ref().and_then(|v| v.value.as_ref()) {
+            format_any_value(buf, v);
+        }
+    }
+
+    match value {
+        any_value::Value::StringValue(s) => buf.push_str(s),
+        any_value::Value::BoolValue(b) => write!(buf, "{b}").unwrap(),
+        any_value::Value::IntValue(i) => write!(buf, "{i}").unwrap(),
+        any_value::Value::DoubleValue(d) => write!(buf, "{d}").unwrap(),
+        any_value::Value::ArrayValue(array) => {
+            buf.push('[');
+            for (i, v) in array.values.iter().enumerate() {
+                if i > 0 {
+                    buf.push_str(", ");
+                }
+                format_opt(buf, &Some(v.clone()));
+            }
+            buf.push(']');
+        }
+        any_value::Value::KvlistValue(list) => {
+            buf.push('{');
+            for (i, kv) in list.values.iter().enumerate() {
+                if i > 0 {
+                    buf.push_str(", ");
+                }
+                write!(buf, "{}=", kv.key).unwrap();
+                format_opt(buf, &kv.value);
+            }
+            buf.push('}');
+        }
+        any_value::Value::BytesValue(bytes) => {
+            for b in bytes {
+                write!(buf, "{b:02x}").unwrap();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alumet::{
+        measurement::{AttributeValue, Timestamp, WrappedMeasurementType, WrappedMeasurementValue},
+        resources::{Resource, ResourceConsumer},
+        units::{PrefixedUnit, Unit},
+    };
+    use opentelemetry_proto::tonic::{
+        collector::metrics::v1::ExportMetricsServiceRequest,
+        common::v1::{AnyValue, KeyValue, any_value},
+        metrics::v1::{
+            Gauge, Histogram, HistogramDataPoint, Metric, NumberDataPoint, ResourceMetrics, ScopeMetrics, Sum,
+            metric::Data, number_data_point,
+        },
+        resource::v1::Resource as OtelResource,
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::{ConvertedPoint, Converter, convert_unit};
+
+    fn attr(key: &str, value: any_value::Value) -> KeyValue {
+        KeyValue {
+            key: key.to_owned(),
+            value: Some(AnyValue { value: Some(value) }),
+        }
+    }
+
+    fn request(resource_attributes: Vec<KeyValue>, metrics: Vec<Metric>) -> ExportMetricsServiceRequest {
+        ExportMetricsServiceRequest {
+            resource_metrics: vec![ResourceMetrics {
+                resource: Some(OtelResource {
+                    attributes: resource_attributes,
+                    ..Default::default()
+                }),
+                scope_metrics: vec![ScopeMetrics {
+                    metrics,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        }
+    }
+
+    fn number_point(
+        time_unix_nano: u64,
+        value: number_data_point::Value,
+        attributes: Vec<KeyValue>,
+    ) -> NumberDataPoint {
+        NumberDataPoint {
+            attributes,
+            time_unix_nano,
+            value: Some(value),
+            ..Default::default()
+        }
+    }
+
+    fn converter() -> Converter {
+        Converter {
+            metric_prefix: String::from("app_"),
+            resource_attributes: vec![String::from("service.name")],
+        }
+    }
+
+    #[test]
+    fn gauge_and_resource() {
+        let req = request(
+            vec![
+                attr(
+                    "service.name",
+                    any_value::Value::StringValue(String::from("my-service")),
+                ),
+                attr("process.pid", any_value::Value::IntValue(1234)),
+                attr("host.name", any_value::Value::StringValue(String::from("ignored"))),
+            ],
+            vec![Metric {
+                name: String::from("phase"),
+                unit: String::from("1"),
+                data: Some(Data::Gauge(Gauge {
+                    data_points: vec![number_point(
+                        1_500_000_000,
+                        number_data_point::Value::AsInt(2),
+                        vec![attr("phase.name", any_value::Value::StringValue(String::from("init")))],
+                    )],
+                })),
+                ..Default::default()
+            }],
+        );
+        let res = converter().convert(req);
+
+        assert_eq!(res.metrics.len(), 1);
+        assert_eq!(res.metrics[0].name, "app_phase");
+        assert_eq!(res.metrics[0].value_type, WrappedMeasurementType::F64);
+        assert_eq!(res.metrics[0].unit, PrefixedUnit::from(Unit::Unity));
+        assert_eq!(
+            res.points,
+            vec![ConvertedPoint {
+                metric_index: 0,
+                timestamp: Timestamp::from_unix_timestamp(1, 500_000_000),
+                value: WrappedMeasurementValue::F64(2.0),
+                resource: Resource::LocalMachine,
+                consumer: ResourceConsumer::Process { pid: 1234 },
+                attributes: vec![
+                    (
+                        String::from("service.name"),
+                        AttributeValue::String(String::from("my-service"))
+                    ),
+                    (String::from("phase.name"), AttributeValue::String(String::from("init"))),
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn monotonic_sum() {
+        let metric = |name: &str, is_monotonic: bool, value: number_data_point::Value| Metric {
+            name: name.to_owned(),
+            unit: String::from("{request}"),
+            data: Some(Data::Sum(Sum {
+                data_points: vec![number_point(10, value, vec![])],
+                aggregation_temporality: 2,
+                is_monotonic,
+            })),
+            ..Default::default()
+        };
+        let req = request(
+            vec![attr(
+                "container.id",
+                any_value::Value::StringValue(String::from("abcd")),
+            )],
+            vec![
+                metric("requests", true, number_data_point::Value::AsInt(42)),
+                metric("up_down", false, number_data_point::Value::AsInt(-1)),
+                metric("float_sum", true, number_data_point::Value::AsDouble(0.5)),
+            ],
+        );
+        let res = converter().convert(req);
+
+        let types: Vec<_> = res.metrics.iter().map(|m| m.value_type.clone()).collect();
+        assert_eq!(
+            types,
+            vec![
+                WrappedMeasurementType::U64,
+                WrappedMeasurementType::F64,
+                WrappedMeasurementType::F64
+            ]
+        );
+        let values: Vec<_> = res.points.iter().map(|p| p.value.clone()).collect();
+        assert_eq!(
+            values,
+            vec![
+                WrappedMeasurementValue::U64(42),
+                WrappedMeasurementValue::F64(-1.0),
+                WrappedMeasurementValue::F64(0.5)
+            ]
+        );
+        assert!(
+            res.points
+                .iter()
+                .all(|p| p.consumer == ResourceConsumer::custom("container", "abcd"))
+        );
+        assert_eq!(
+            res.metrics[0].unit,
+            PrefixedUnit::from(Unit::Custom {
+                unique_name: String::from("{request}"),
+                display_name: String::from("{request}")
+            })
+        );
+    }
+
+    #[test]
+    fn histogram() {
+        let req = request(
+            vec![],
+            vec![Metric {
+                name: String::from("latency"),
+                unit: String::from("ms"),
+                data: Some(Data::Histogram(Histogram {
+                    data_points: vec![
+                        HistogramDataPoint {
+                            time_unix_nano: 10,
+                            count: 3,
+                            sum: Some(12.5),
+                            ..Default::default()
+                        },
+                        HistogramDataPoint {
+                            time_unix_nano: 20,
+                            count: 4,
+                            sum: Some(13.0),
+                            flags: 1, // no recorded value
+                            ..Default::default()
+                        },
+                    ],
+                    aggregation_temporality: 1,
+                })),
+                ..Default::default()
+            }],
+        );
+        let res = converter().convert(req);
+
+        let names: Vec<_> = res.metrics.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, vec!["app_latency_count", "app_latency_sum"]);
+        assert_eq!(res.metrics[1].unit, PrefixedUnit::milli(Unit::Second));
+        assert_eq!(res.points.len(), 2);
+        assert_eq!(res.points[0].value, WrappedMeasurementValue::U64(3));
+        assert_eq!(res.points[1].value, WrappedMeasurementValue::F64(12.5));
+        assert_eq!(res.points[0].consumer, ResourceConsumer::LocalMachine);
+    }
+
+    #[test]
+    fn same_metric_in_several_resources() {
+        let metric = || Metric {
+            name: String::from("temperature"),
+            unit: String::from("Cel"),
+            data: Some(Data::Gauge(Gauge {
+                data_points: vec![number_point(1, number_data_point::Value::AsDouble(20.0), vec![])],
+            })),
+            ..Default::default()
+        };
+        let mut req = request(vec![], vec![metric()]);
+        req.resource_metrics
+            .extend(request(vec![], vec![metric()]).resource_metrics);
+        let res = converter().convert(req);
+        assert_eq!(res.metrics.len(), 1);
+        assert_eq!(res.points.len(), 2);
+        assert!(res.points.iter().all(|p| p.metric_index == 0));
+    }
+
+    #[test]
+    fn units() {
+        assert_eq!(convert_unit(""), PrefixedUnit::from(Unit::Unity));
+        assert_eq!(convert_unit("1"), PrefixedUnit::from(Unit::Unity));
+        assert_eq!(convert_unit("By"), PrefixedUnit::from(Unit::Byte));
+        assert_eq!(convert_unit("kBy"), PrefixedUnit::kilo(Unit::Byte));
+        assert_eq!(convert_unit("W"), PrefixedUnit::from(Unit::Watt));
+        assert_eq!(
+            convert_unit("min"),
+            PrefixedUnit::from(Unit::Custom {
+                unique_name: String::from("min"),
+                display_name: String::from("min")
+            })
+        );
+    }
+}
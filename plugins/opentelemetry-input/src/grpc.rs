@@ -0,0 +1,51 @@
+//! OTLP/gRPC receiver.
+
+use std::{net::SocketAddr, sync::Arc};
+
+use anyhow::Context;
+use opentelemetry_proto::tonic::collector::metrics::v1::{
+    ExportMetricsServiceRequest, ExportMetricsServiceResponse,
+    metrics_service_server::{MetricsService, MetricsServiceServer},
+};
+use tokio_util::sync::CancellationToken;
+use tonic::{Request, Response, Status, codec::CompressionEncoding};
+
+use crate::receiver::Receiver;
+
+struct GrpcMetricsService {
+    receiver: Arc<Receiver>,
+}
+
+#[tonic::async_trait]
+impl MetricsService for GrpcMetricsService {
+    async fn export(
+        &self,
+        request: Request<ExportMetricsServiceRequest>,
+    ) -> Result<Response<ExportMetricsServiceResponse>, Status> {
+        self.receiver.receive(request.into_inner()).await.map_err(|e| {
+            log::error!("Error while handling an OTLP/gRPC request: {e:?}");
+            Status::unavailable(format!("{e:#}"))
+        })?;
+        Ok(Response::new(ExportMetricsServiceResponse { partial_success: None }))
+    }
+}
+
+/// Runs the gRPC server until the token is cancelled.
+///
+/// Messages larger than `max_message_size` (after decompression) are rejected.
+pub async fn serve(
+    addr: SocketAddr,
+    receiver: Arc<Receiver>,
+    max_message_size: usize,
+    cancel_token: CancellationToken,
+) -> anyhow::Result<()> {
+    log::info!("Starting OTLP/gRPC receiver on {addr}");
+    let service = MetricsServiceServer::new(GrpcMetricsService { receiver })
+        .accept_compressed(CompressionEncoding::Gzip)
+        .max_decoding_message_size(max_message_size);
+    tonic::transport::Server::builder()
+        .add_service(service)
+        .serve_with_shutdown(addr, cancel_token.cancelled_owned())
+        .await
+        .with_context(|| format!("OTLP/gRPC server failed on {addr}"))
+}
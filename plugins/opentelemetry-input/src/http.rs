@@ -0,0 +1,310 @@
+//! OTLP/HTTP receiver.
+//!
+//! Both binary protobuf and JSON payloads are supported, optionally compressed with gzip.
+//! See <https://opentelemetry.io/docs/specs/otlp/#otlphttp>.
+
+use std::{convert::Infallible, fmt, io::Read, net::SocketAddr, sync::Arc};
+
+use anyhow::Context;
+use flate2::read::GzDecoder;
+use hyper::{
+    Body, Method, Request, Response, Server, StatusCode,
+    body::HttpBody,
+    header::{CONTENT_ENCODING, CONTENT_TYPE, HeaderValue},
+    service::{make_service_fn, service_fn},
+};
+use opentelemetry_proto::tonic::collector::metrics::v1::{ExportMetricsServiceRequest, ExportMetricsServiceResponse};
+use prost::Message;
+use tokio_util::sync::CancellationToken;
+
+use crate::receiver::Receiver;
+
+/// Path of the metrics endpoint, as defined by the OTLP specification.
+const METRICS_PATH: &str = "/v1/metrics";
+
+const CONTENT_TYPE_PROTOBUF: &str = "application/x-protobuf";
+const CONTENT_TYPE_JSON: &str = "application/json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Protobuf,
+    Json,
+}
+
+/// The request is larger than the maximum size allowed by the configuration.
+#[derive(Debug)]
+struct PayloadTooLarge {
+    limit: usize,
+}
+
+impl fmt::Display for PayloadTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "payload too large, the limit is {} bytes", self.limit)
+    }
+}
+
+impl std::error::Error for PayloadTooLarge {}
+
+/// Runs the HTTP server until the token is cancelled.
+///
+/// Requests larger than `max_body_size` (after decompression) are rejected.
+pub async fn serve(
+    addr: SocketAddr,
+    receiver: Arc<Receiver>,
+    max_body_size: usize,
+    cancel_token: CancellationToken,
+) -> anyhow::Result<()> {
+    log::info!("Starting OTLP/HTTP receiver on {addr}");
+    let make_svc = make_service_fn(move |_conn| {
+        let receiver = receiver.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let receiver = receiver.clone();
+                async move { Ok::<_, Infallible>(handle(req, &receiver, max_body_size).await) }
+            }))
+        }
+    });
+    Server::try_bind(&addr)
+        .with_context(|| format!("could not bind the OTLP/HTTP server to {addr}"))?
+        .serve(make_svc)
+        .with_graceful_shutdown(cancel_token.cancelled_owned())
+        .await
+        .with_context(|| format!("OTLP/HTTP server failed on {addr}"))
+}
+
+async fn handle(req: Request<Body>, receiver: &Receiver, max_body_size: usize) -> Response<Body> {
+    if req.uri().path() != METRICS_PATH {
+        return error_response(StatusCode::NOT_FOUND, "not found", Encoding::Json);
+    }
+    if req.method() != Method::POST {
+        return error_response(StatusCode::METHOD_NOT_ALLOWED, "only POST is supported", Encoding::Json);
+    }
+
+    let encoding = match req.headers().get(CONTENT_TYPE).map(parse_content_type) {
+        Some(Some(encoding)) => encoding,
+        _ => {
+            return error_response(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "unsupported content type, expected application/x-protobuf or application/json",
+                Encoding::Json,
+            );
+        }
+    };
+    let gzip = match req.headers().get(CONTENT_ENCODING).map(HeaderValue::as_bytes) {
+        None | Some(b"identity") => false,
+        Some(b"gzip") => true,
+        Some(_) => {
+            return error_response(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "unsupported content encoding",
+                encoding,
+            );
+        }
+    };
+
+    let request = match read_body(req.into_body(), max_body_size).await {
+        Ok(body) => decode_request(&body, encoding, gzip, max_body_size),
+        Err(e) => Err(e),
+    };
+    let request = match request {
+        Ok(request) => request,
+        Err(e) if e.is::<PayloadTooLarge>() => {
+            return error_response(StatusCode::PAYLOAD_TOO_LARGE, &format!("{e:#}"), encoding);
+        }
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, &format!("{e:#}"), encoding),
+    };
+
+    if let Err(e) = receiver.receive(request).await {
+        log::error!("Error while handling an OTLP/HTTP request: {e:?}");
+        return error_response(StatusCode::SERVICE_UNAVAILABLE, &format!("{e:#}"), encoding);
+    }
+
+    let response = ExportMetricsServiceResponse { partial_success: None };
+    let body = match encoding {
+        Encoding::Protobuf => response.encode_to_vec(),
+        Encoding::Json => serde_json::to_vec(&response).unwrap_or_else(|_| b"{}".to_vec()),
+    };
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, content_type(encoding))
+        .body(Body::from(body))
+        .unwrap()
+}
+
+fn parse_content_type(value: &HeaderValue) -> Option<Encoding> {
+    let value = value.to_str().ok()?;
+    // ignore parameters such as "; charset=utf-8"
+    let mime = value.split(';').next()?.trim();
+    match mime {
+        CONTENT_TYPE_PROTOBUF => Some(Encoding::Protobuf),
+        CONTENT_TYPE_JSON => Some(Encoding::Json),
+        _ => None,
+    }
+}
+
+fn content_type(encoding: Encoding) -> &'static str {
+    match encoding {
+        Encoding::Protobuf => CONTENT_TYPE_PROTOBUF,
+        Encoding::Json => CONTENT_TYPE_JSON,
+    }
+}
+
+/// Reads the body of a request, without exceeding `limit` bytes.
+async fn read_body(mut body: Body, limit: usize) -> anyhow::Result<Vec<u8>> {
+    // fail early if the Content-Length is already too large
+    if body.size_hint().lower() > limit as u64 {
+        return Err(PayloadTooLarge { limit }.into());
+    }
+    let mut buf = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.context("invalid body")?;
+        if buf.len() + chunk.len() > limit {
+            return Err(PayloadTooLarge { limit }.into());
+        }
+        buf.extend_from_slice(&chunk);
+    }
+    Ok(buf)
+}
+
+fn decode_request(
+    body: &[u8],
+    encoding: Encoding,
+    gzip: bool,
+    limit: usize,
+) -> anyhow::Result<ExportMetricsServiceRequest> {
+    let decompressed;
+    let body = if gzip {
+        // Read one byte more than the limit to detect payloads that are too large (e.g. gzip bombs).
+        let mut buf = Vec::with_capacity(body.len().saturating_mul(4).min(limit));
+        GzDecoder::new(body)
+            .take(limit as u64 + 1)
+            .read_to_end(&mut buf)
+            .context("invalid gzip payload")?;
+        if buf.len() > limit {
+            return Err(PayloadTooLarge { limit }.into());
+        }
+        decompressed = buf;
+        &decompressed
+    } else {
+        body
+    };
+    match encoding {
+        Encoding::Protobuf => ExportMetricsServiceRequest::decode(body).context("invalid protobuf payload"),
+        Encoding::Json => serde_json::from_slice(body).context("invalid json payload"),
+    }
+}
+
+/// Builds an error response.
+///
+/// The OTLP specification requires the body to be a `google.rpc.Status` message,
+/// in which we only fill the `message` field.
+fn error_response(status: StatusCode, message: &str, encoding: Encoding) -> Response<Body> {
+    let body = match encoding {
+        Encoding::Protobuf => {
+            // google.rpc.Status { message: string = 2 }
+            #[derive(Clone, PartialEq, prost::Message)]
+            struct RpcStatus {
+                #[prost(string, tag = "2")]
+                message: String,
+            }
+            RpcStatus {
+                message: message.to_owned(),
+            }
+            .encode_to_vec()
+        }
+        Encoding::Json => serde_json::json!({ "message": message }).to_string().into_bytes(),
+    };
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, content_type(encoding))
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{Compression, write::GzEncoder};
+    use hyper::header::HeaderValue;
+    use opentelemetry_proto::tonic::{
+        collector::metrics::v1::ExportMetricsServiceRequest,
+        metrics::v1::{Metric, ResourceMetrics, ScopeMetrics},
+    };
+    use prost::Message;
+
+    use super::{Encoding, PayloadTooLarge, decode_request, parse_content_type, read_body};
+
+    const LIMIT: usize = 1024 * 1024;
+
+    fn sample_request() -> ExportMetricsServiceRequest {
+        ExportMetricsServiceRequest {
+            resource_metrics: vec![ResourceMetrics {
+                scope_metrics: vec![ScopeMetrics {
+                    metrics: vec![Metric {
+                        name: String::from("test"),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        }
+    }
+
+    #[test]
+    fn content_type() {
+        let parse = |s: &'static str| parse_content_type(&HeaderValue::from_static(s));
+        assert_eq!(parse("application/x-protobuf"), Some(Encoding::Protobuf));
+        assert_eq!(parse("application/json; charset=utf-8"), Some(Encoding::Json));
+        assert_eq!(parse("text/plain"), None);
+    }
+
+    #[test]
+    fn decode_protobuf() {
+        let req = sample_request();
+        let body = req.encode_to_vec();
+        assert_eq!(decode_request(&body, Encoding::Protobuf, false, LIMIT).unwrap(), req);
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&body).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(
+            decode_request(&compressed, Encoding::Protobuf, true, LIMIT).unwrap(),
+            req
+        );
+        assert!(decode_request(&body, Encoding::Protobuf, true, LIMIT).is_err());
+    }
+
+    #[test]
+    fn decode_json() {
+        let body = r#"{"resourceMetrics":[{"scopeMetrics":[{"metrics":[{"name":"test"}]}]}]}"#;
+        assert_eq!(
+            decode_request(body.as_bytes(), Encoding::Json, false, LIMIT).unwrap(),
+            sample_request()
+        );
+        assert!(decode_request(b"{", Encoding::Json, false, LIMIT).is_err());
+    }
+
+    #[test]
+    fn gzip_bomb() {
+        // a few KiB that decompress to more than the limit
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&vec![0; LIMIT + 1]).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert!(compressed.len() < LIMIT / 100);
+
+        let err = decode_request(&compressed, Encoding::Protobuf, true, LIMIT).unwrap_err();
+        assert!(err.is::<PayloadTooLarge>());
+    }
+
+    #[tokio::test]
+    async fn body_too_large() {
+        let body = hyper::Body::from(vec![0; LIMIT + 1]);
+        let err = read_body(body, LIMIT).await.unwrap_err();
+        assert!(err.is::<PayloadTooLarge>());
+
+        let body = hyper::Body::from(vec![0; LIMIT]);
+        assert_eq!(read_body(body, LIMIT).await.unwrap().len(), LIMIT);
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    net::{SocketAddr, ToSocketAddrs},
+    sync::Arc,
+};
+
+use alumet::plugin::{
+    AlumetPluginStart, ConfigTable,
+    rust::{AlumetPlugin, deserialize_config, serialize_config},
+};
+use anyhow::{Context, anyhow};
+use serde::{Deserialize, Serialize};
+
+mod convert;
+mod grpc;
+mod http;
+mod receiver;
+
+use convert::Converter;
+use receiver::Receiver;
+
+pub struct OpenTelemetryInputPlugin {
+    config: Option<Config>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// Address of the OTLP/gRPC receiver.
+    /// Remove this option to disable the gRPC receiver.
+    grpc_address: Option<String>,
+    /// Address of the OTLP/HTTP receiver, which accepts protobuf and JSON payloads on `/v1/metrics`.
+    /// Remove this option to disable the HTTP receiver.
+    http_address: Option<String>,
+    /// Prefix added to the name of the received metrics.
+    metric_prefix: String,
+    /// OpenTelemetry resource attributes to copy to every measurement point.
+    resource_attributes: Vec<String>,
+    /// Maximum size of a request, in bytes, after decompression.
+    /// Larger requests are rejected.
+    #[serde(default = "default_max_body_size")]
+    max_body_size: usize,
+}
+
+#[cfg_attr(tarpaulin, ignore)]
+fn default_max_body_size() -> usize {
+    4 * 1024 * 1024 // 4 MiB, like the default limit of gRPC
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            grpc_address: Some(String::from("[::]:4317")), // standard OTLP/gRPC port
+            http_address: Some(String::from("[::]:4318")), // standard OTLP/HTTP port
+            metric_prefix: String::new(),
+            resource_attributes: vec![String::from("service.name")],
+            max_body_size: default_max_body_size(),
+        }
+    }
+}
+
+impl AlumetPlugin for OpenTelemetryInputPlugin {
+    fn name() -> &'static str {
+        "opentelemetry-input"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(OpenTelemetryInputPlugin { config: Some(config) }))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let config = self.config.take().unwrap();
+
+        // Resolve the addresses from the config right now (fail fast).
+        let grpc_addr = config.grpc_address.as_deref().map(resolve_address).transpose()?;
+        let http_addr = config.http_address.as_deref().map(resolve_address).transpose()?;
+        if grpc_addr.is_none() && http_addr.is_none() {
+            return Err(anyhow!(
+                "at least one receiver must be enabled: please set grpc_address or http_address"
+            ));
+        }
+
+        let max_body_size = config.max_body_size;
+        let converter = Converter {
+            metric_prefix: config.metric_prefix,
+            resource_attributes: config.resource_attributes,
+        };
+
+        alumet.add_autonomous_source_builder("receiver", move |ctx, cancel_token, out_tx| {
+            let receiver = Arc::new(Receiver::new(converter, ctx.metrics_sender(), out_tx));
+            let source = Box::pin(async move {
+                let grpc = async {
+                    match grpc_addr {
+                        Some(addr) => grpc::serve(addr, receiver.clone(), max_body_size, cancel_token.clone()).await,
+                        None => Ok(()),
+                    }
+                };
+                let http = async {
+                    match http_addr {
+                        Some(addr) => http::serve(addr, receiver.clone(), max_body_size, cancel_token.clone()).await,
+                        None => Ok(()),
+                    }
+                };
+                tokio::try_join!(grpc, http)?;
+                Ok(())
+            });
+            Ok(source)
+        })?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        // The autonomous source has already been stopped at this point.
+        Ok(())
+    }
+}
+
+fn resolve_address(addr: &str) -> anyhow::Result<SocketAddr> {
+    addr.to_socket_addrs()
+        .with_context(|| format!("invalid socket address: {addr}"))?
+        .next()
+        .with_context(|| format!("no socket address found for {addr}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use alumet::plugin::{
+        ConfigTable,
+        rust::{deserialize_config, serialize_config},
+    };
+
+    use super::{Config, default_max_body_size};
+
+    #[test]
+    fn default_config() {
+        let config = serialize_config(Config::default()).unwrap();
+        let config: Config = deserialize_config(config).unwrap();
+        assert_eq!(config.grpc_address.as_deref(), Some("[::]:4317"));
+        assert_eq!(config.http_address.as_deref(), Some("[::]:4318"));
+        assert_eq!(config.resource_attributes, vec!["service.name"]);
+        assert_eq!(config.max_body_size, 4 * 1024 * 1024);
+    }
+
+    #[test]
+    fn disabled_receiver() {
+        let config: toml::Table = toml::from_str(
+            r#"
+            http_address = "127.0.0.1:4318"
+            metric_prefix = "otel_"
+            resource_attributes = []
+            "#,
+        )
+        .unwrap();
+        let config: Config = deserialize_config(ConfigTable(config)).unwrap();
+        assert_eq!(config.grpc_address, None);
+        assert_eq!(config.metric_prefix, "otel_");
+        assert_eq!(config.max_body_size, default_max_body_size());
+    }
+}
@@ -0,0 +1,124 @@
+//! Common logic of the gRPC and HTTP receivers.
+
+use std::collections::HashMap;
+
+use alumet::{
+    measurement::{MeasurementBuffer, MeasurementPoint, WrappedMeasurementType, WrappedMeasurementValue},
+    metrics::{Metric, RawMetricId, duplicate::DuplicateReaction, online::MetricSender},
+};
+use anyhow::{Context, anyhow};
+use opentelemetry_proto::tonic::collector::metrics::v1::ExportMetricsServiceRequest;
+use tokio::sync::{Mutex, mpsc};
+
+use crate::convert::Converter;
+
+/// Registers the metrics received via OTLP and remembers their ids.
+pub struct MetricRegistrar {
+    sender: MetricSender,
+    ids: HashMap<String, (RawMetricId, WrappedMeasurementType)>,
+}
+
+impl MetricRegistrar {
+    pub fn new(sender: MetricSender) -> Self {
+        Self {
+            sender,
+            ids: HashMap::new(),
+        }
+    }
+
+    /// Returns the id and type of each metric, registering the metrics that are unknown.
+    ///
+    /// Metrics that cannot be registered are mapped to `None`.
+    async fn resolve(
+        &mut self,
+        metrics: Vec<Metric>,
+    ) -> anyhow::Result<Vec<Option<(RawMetricId, WrappedMeasurementType)>>> {
+        let unknown: Vec<Metric> = metrics
+            .iter()
+            .filter(|m| !self.ids.contains_key(&m.name))
+            .cloned()
+            .collect();
+
+        if !unknown.is_empty() {
+            let results = self
+                .sender
+                .create_metrics(
+                    unknown.clone(),
+                    DuplicateReaction::Rename {
+                        suffix: String::from("otlp"),
+                    },
+                )
+                .await
+                .map_err(|e| anyhow!("create_metrics returned an error: {e:?}"))?;
+
+            for (res, metric) in results.into_iter().zip(unknown) {
+                match res {
+                    Ok(id) => {
+                        log::debug!("New OTLP metric registered: {} (id {})", metric.name, id.as_u64());
+                        self.ids.insert(metric.name, (id, metric.value_type));
+                    }
+                    Err(e) => {
+                        log::error!("metric registration failed: name='{}'; {e:?}", metric.name);
+                    }
+                }
+            }
+        }
+
+        Ok(metrics.iter().map(|m| self.ids.get(&m.name).cloned()).collect())
+    }
+}
+
+/// Converts OTLP requests to measurements and sends them to the Alumet pipeline.
+pub struct Receiver {
+    converter: Converter,
+    registrar: Mutex<MetricRegistrar>,
+    out_tx: mpsc::Sender<MeasurementBuffer>,
+}
+
+impl Receiver {
+    pub fn new(converter: Converter, metrics_tx: MetricSender, out_tx: mpsc::Sender<MeasurementBuffer>) -> Self {
+        Self {
+            converter,
+            registrar: Mutex::new(MetricRegistrar::new(metrics_tx)),
+            out_tx,
+        }
+    }
+
+    /// Handles an export request.
+    pub async fn receive(&self, request: ExportMetricsServiceRequest) -> anyhow::Result<()> {
+        let converted = self.converter.convert(request);
+        if converted.points.is_empty() {
+            return Ok(());
+        }
+
+        let ids = self.registrar.lock().await.resolve(converted.metrics).await?;
+
+        let mut buffer = MeasurementBuffer::with_capacity(converted.points.len());
+        for p in converted.points {
+            let Some((id, value_type)) = &ids[p.metric_index] else {
+                continue;
+            };
+            // The metric may have been registered by a previous request with another type.
+            let value = match (value_type, p.value) {
+                (WrappedMeasurementType::F64, WrappedMeasurementValue::U64(v)) => {
+                    WrappedMeasurementValue::F64(v as f64)
+                }
+                (WrappedMeasurementType::U64, WrappedMeasurementValue::F64(v)) => {
+                    WrappedMeasurementValue::U64(v as u64)
+                }
+                (_, v) => v,
+            };
+            let point = MeasurementPoint::new_untyped(p.timestamp, *id, p.resource, p.consumer, value)
+                .with_attr_vec(p.attributes);
+            buffer.push(point);
+        }
+
+        if !buffer.is_empty() {
+            self.out_tx
+                .send(buffer)
+                .await
+                .context("failed to send the OTLP measurements to the pipeline")?;
+        }
+        Ok(())
+    }
+}
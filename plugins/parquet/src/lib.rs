@@ -0,0 +1,159 @@
+mod schema;
+mod writer;
+
+use std::{path::PathBuf, time::Duration, time::SystemTime};
+
+use alumet::{
+    measurement::MeasurementBuffer,
+    pipeline::{
+        Output,
+        elements::{error::WriteError, output::OutputContext},
+    },
+    plugin::{
+        AlumetPluginStart, ConfigTable,
+        rust::{AlumetPlugin, deserialize_config, serialize_config},
+    },
+};
+use serde::{Deserialize, Serialize};
+
+pub use writer::{Compression, Format};
+use writer::{FileSettings, MeasurementFile};
+
+pub struct ParquetPlugin {
+    config: Option<Config>,
+}
+
+impl AlumetPlugin for ParquetPlugin {
+    fn name() -> &'static str {
+        "parquet"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(ParquetPlugin { config: Some(config) }))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let config = self.config.take().unwrap();
+        if config.row_group_size == 0 {
+            return Err(anyhow::anyhow!("row_group_size must be greater than zero"));
+        }
+        let settings = FileSettings {
+            format: config.format,
+            compression: config.compression,
+            row_group_size: config.row_group_size,
+            rotation_max_size: config.rotation.max_size,
+            rotation_max_age: config.rotation.max_age,
+        };
+        let file = MeasurementFile::create(&config.output_path, settings)?;
+        alumet.add_blocking_output("out", Box::new(ParquetOutput { file }))?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+struct ParquetOutput {
+    file: MeasurementFile,
+}
+
+impl Output for ParquetOutput {
+    fn write(&mut self, measurements: &MeasurementBuffer, ctx: &OutputContext) -> Result<(), WriteError> {
+        for m in measurements {
+            let metric = ctx.metrics.by_id(&m.metric).expect("unknown metric");
+            self.file.push(m, metric)?;
+        }
+        if self.file.should_rotate(SystemTime::now()) {
+            // the file is still usable if the rotation fails, it will be retried on the next write
+            match self.file.rotate() {
+                Ok(rotated) => log::info!("Measurements file rotated: {rotated:?}"),
+                Err(e) => log::error!("failed to rotate the measurements file: {e:?}"),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Absolute or relative path to the output file.
+    pub output_path: PathBuf,
+    /// Format of the file: `parquet` or `arrow_ipc`.
+    pub format: Format,
+    /// Compression of the columns: `none`, `snappy`, `gzip`, `lz4` or `zstd`.
+    /// The Arrow IPC format only supports `none`, `lz4` and `zstd`.
+    pub compression: Compression,
+    /// Number of measurements per row group (Parquet) or record batch (Arrow IPC).
+    /// The measurements are kept in memory until a row group is complete.
+    pub row_group_size: usize,
+    /// Rotation of the output file.
+    #[serde(default)]
+    pub rotation: RotationConfig,
+}
+
+#[derive(Deserialize, Serialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RotationConfig {
+    /// Rotate the file when it exceeds this size, in bytes.
+    pub max_size: Option<u64>,
+    /// Rotate the file when it is older than this duration.
+    #[serde(with = "humantime_serde", default)]
+    pub max_age: Option<Duration>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            output_path: PathBuf::from("alumet-output.parquet"),
+            format: Format::Parquet,
+            compression: Compression::Zstd,
+            row_group_size: 100_000,
+            rotation: RotationConfig::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Compression, Config, Format};
+
+    #[test]
+    fn parse_config() {
+        let config: Config = toml::from_str(
+            r#"
+            output_path = "out.arrow"
+            format = "arrow_ipc"
+            compression = "lz4"
+            row_group_size = 1000
+
+            [rotation]
+            max_size = 1000000
+            max_age = "1h"
+        "#,
+        )
+        .unwrap();
+        assert_eq!(config.format, Format::ArrowIpc);
+        assert_eq!(config.compression, Compression::Lz4);
+        assert_eq!(config.row_group_size, 1000);
+        assert_eq!(config.rotation.max_size, Some(1000000));
+        assert_eq!(config.rotation.max_age, Some(Duration::from_secs(3600)));
+
+        let default = toml::to_string(&Config::default()).unwrap();
+        let config: Config = toml::from_str(&default).unwrap();
+        assert_eq!(config.format, Format::Parquet);
+        assert!(config.rotation.max_age.is_none());
+    }
+}
@@ -0,0 +1,377 @@
+//! Arrow schema of the measurements.
+
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use alumet::{
+    measurement::{AttributeValue, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+    metrics::Metric,
+};
+use anyhow::Context;
+use arrow::{
+    array::{
+        ArrayBuilder, ArrayRef, BooleanBuilder, Float64Builder, ListBuilder, MapBuilder, MapFieldNames, StringBuilder,
+        StructBuilder, TimestampNanosecondBuilder, UInt64Builder,
+    },
+    datatypes::{DataType, Field, Fields, Schema, SchemaRef, TimeUnit},
+    record_batch::RecordBatch,
+};
+
+/// Key of the file metadata that describes the metrics, in JSON.
+pub const METRICS_METADATA_KEY: &str = "alumet.metrics";
+
+const TIMEZONE: &str = "UTC";
+
+/// Returns the schema of the files.
+///
+/// The value is stored in `value_u64` or `value_f64`, depending on the type of the metric,
+/// so that the type of the measurements is preserved. The other value column is null.
+/// Likewise, the value of an attribute is a struct with one field per type, see [`attribute_fields`].
+pub fn measurement_schema() -> SchemaRef {
+    let field_names = map_field_names();
+    Arc::new(Schema::new(vec![
+        Field::new(
+            "timestamp",
+            DataType::Timestamp(TimeUnit::Nanosecond, Some(TIMEZONE.into())),
+            false,
+        ),
+        Field::new("metric", DataType::Utf8, false),
+        Field::new("unit", DataType::Utf8, false),
+        Field::new("value_u64", DataType::UInt64, true),
+        Field::new("value_f64", DataType::Float64, true),
+        Field::new("resource_kind", DataType::Utf8, false),
+        Field::new("resource_id", DataType::Utf8, false),
+        Field::new("consumer_kind", DataType::Utf8, false),
+        Field::new("consumer_id", DataType::Utf8, false),
+        Field::new_map(
+            "attributes",
+            field_names.entry,
+            Field::new(field_names.key, DataType::Utf8, false),
+            Field::new(field_names.value, DataType::Struct(attribute_fields()), true),
+            false,
+            false,
+        ),
+    ]))
+}
+
+/// Fields of the attribute values: only the field that corresponds to the type of the value is not null.
+///
+/// Parquet does not support unions, hence the struct.
+pub fn attribute_fields() -> Fields {
+    Fields::from(vec![
+        Field::new("u64", DataType::UInt64, true),
+        Field::new("f64", DataType::Float64, true),
+        Field::new("bool", DataType::Boolean, true),
+        Field::new("string", DataType::Utf8, true),
+        Field::new_list("list_u64", Field::new_list_field(DataType::UInt64, true), true),
+    ])
+}
+
+fn attribute_values_builder() -> StructBuilder {
+    let builders: Vec<Box<dyn ArrayBuilder>> = vec![
+        Box::new(UInt64Builder::new()),
+        Box::new(Float64Builder::new()),
+        Box::new(BooleanBuilder::new()),
+        Box::new(StringBuilder::new()),
+        Box::new(ListBuilder::new(UInt64Builder::new())),
+    ];
+    StructBuilder::new(attribute_fields(), builders)
+}
+
+fn append_attribute_value(builder: &mut StructBuilder, value: &AttributeValue) {
+    builder
+        .field_builder::<UInt64Builder>(0)
+        .unwrap()
+        .append_option(match value {
+            AttributeValue::U64(v) => Some(*v),
+            _ => None,
+        });
+    builder
+        .field_builder::<Float64Builder>(1)
+        .unwrap()
+        .append_option(match value {
+            AttributeValue::F64(v) => Some(*v),
+            _ => None,
+        });
+    builder
+        .field_builder::<BooleanBuilder>(2)
+        .unwrap()
+        .append_option(match value {
+            AttributeValue::Bool(v) => Some(*v),
+            _ => None,
+        });
+    builder
+        .field_builder::<StringBuilder>(3)
+        .unwrap()
+        .append_option(match value {
+            AttributeValue::Str(v) => Some(*v),
+            AttributeValue::String(v) => Some(v.as_str()),
+            _ => None,
+        });
+    let list = builder.field_builder::<ListBuilder<UInt64Builder>>(4).unwrap();
+    match value {
+        AttributeValue::ListU64(items) => {
+            list.values().append_slice(items);
+            list.append(true);
+        }
+        _ => list.append(false),
+    }
+    builder.append(true);
+}
+
+/// Converts a timestamp to a number of nanoseconds since the UNIX epoch, which is negative before the epoch.
+///
+/// Returns `None` if the timestamp is out of the range of `i64` (about 292 years around 1970).
+fn timestamp_nanos(timestamp: Timestamp) -> Option<i64> {
+    match SystemTime::from(timestamp).duration_since(UNIX_EPOCH) {
+        Ok(d) => i64::try_from(d.as_nanos()).ok(),
+        Err(e) => i64::try_from(e.duration().as_nanos()).ok().map(|n| -n),
+    }
+}
+
+fn map_field_names() -> MapFieldNames {
+    MapFieldNames {
+        entry: String::from("entries"),
+        key: String::from("key"),
+        value: String::from("value"),
+    }
+}
+
+/// Accumulates measurements in Arrow arrays, to build record batches.
+pub struct BatchBuilder {
+    schema: SchemaRef,
+    timestamp: TimestampNanosecondBuilder,
+    metric: StringBuilder,
+    unit: StringBuilder,
+    value_u64: UInt64Builder,
+    value_f64: Float64Builder,
+    resource_kind: StringBuilder,
+    resource_id: StringBuilder,
+    consumer_kind: StringBuilder,
+    consumer_id: StringBuilder,
+    attributes: MapBuilder<StringBuilder, StructBuilder>,
+    len: usize,
+}
+
+impl BatchBuilder {
+    pub fn new(schema: SchemaRef) -> Self {
+        Self {
+            schema,
+            timestamp: TimestampNanosecondBuilder::new().with_timezone(TIMEZONE),
+            metric: StringBuilder::new(),
+            unit: StringBuilder::new(),
+            value_u64: UInt64Builder::new(),
+            value_f64: Float64Builder::new(),
+            resource_kind: StringBuilder::new(),
+            resource_id: StringBuilder::new(),
+            consumer_kind: StringBuilder::new(),
+            consumer_id: StringBuilder::new(),
+            attributes: MapBuilder::new(
+                Some(map_field_names()),
+                StringBuilder::new(),
+                attribute_values_builder(),
+            ),
+            len: 0,
+        }
+    }
+
+    /// Returns the number of measurements in the batch.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Adds a measurement point to the batch.
+    ///
+    /// If the point cannot be stored, it is not added and an error is returned.
+    pub fn push(&mut self, m: &MeasurementPoint, metric: &Metric) -> anyhow::Result<()> {
+        // check everything before appending to the columns, which must keep the same length
+        let timestamp = timestamp_nanos(m.timestamp)
+            .with_context(|| format!("timestamp out of range: {:?}", SystemTime::from(m.timestamp)))?;
+        self.timestamp.append_value(timestamp);
+        self.metric.append_value(&metric.name);
+        self.unit.append_value(metric.unit.unique_name());
+        match m.value {
+            WrappedMeasurementValue::F64(v) => {
+                self.value_u64.append_null();
+                self.value_f64.append_value(v);
+            }
+            WrappedMeasurementValue::U64(v) => {
+                self.value_u64.append_value(v);
+                self.value_f64.append_null();
+            }
+        }
+        self.resource_kind.append_value(m.resource.kind());
+        self.resource_id.append_value(m.resource.id_display().to_string());
+        self.consumer_kind.append_value(m.consumer.kind());
+        self.consumer_id.append_value(m.consumer.id_display().to_string());
+        for (key, value) in m.attributes() {
+            self.attributes.keys().append_value(key);
+            append_attribute_value(self.attributes.values(), value);
+        }
+        self.attributes.append(true)?;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Builds a record batch with the measurements, and clears the builder.
+    pub fn finish(&mut self) -> anyhow::Result<RecordBatch> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.timestamp.finish()),
+            Arc::new(self.metric.finish()),
+            Arc::new(self.unit.finish()),
+            Arc::new(self.value_u64.finish()),
+            Arc::new(self.value_f64.finish()),
+            Arc::new(self.resource_kind.finish()),
+            Arc::new(self.resource_id.finish()),
+            Arc::new(self.consumer_kind.finish()),
+            Arc::new(self.consumer_id.finish()),
+            Arc::new(self.attributes.finish()),
+        ];
+        self.len = 0;
+        let batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+        Ok(batch)
+    }
+}
+
+/// Describes a metric, for the metadata of the files.
+pub fn metric_metadata(id: u64, metric: &Metric) -> serde_json::Value {
+    serde_json::json!({
+        "id": id,
+        "name": metric.name,
+        "description": metric.description,
+        "value_type": metric.value_type.to_string(),
+        "unit": metric.unit.unique_name(),
+        "unit_display_name": metric.unit.display_name(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use alumet::{
+        measurement::{MeasurementPoint, Timestamp, WrappedMeasurementType, WrappedMeasurementValue},
+        metrics::{Metric, RawMetricId},
+        resources::{Resource, ResourceConsumer},
+        units::{PrefixedUnit, Unit},
+    };
+    use arrow::{
+        array::{Array, AsArray, MapArray},
+        datatypes::{Float64Type, TimestampNanosecondType, UInt64Type},
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::{BatchBuilder, measurement_schema, metric_metadata, timestamp_nanos};
+
+    #[test]
+    fn build_batch() -> anyhow::Result<()> {
+        let energy = Metric {
+            name: String::from("rapl_consumed_energy"),
+            description: String::from("energy"),
+            value_type: WrappedMeasurementType::F64,
+            unit: PrefixedUnit::milli(Unit::Joule),
+        };
+        let count = Metric {
+            name: String::from("count"),
+            description: String::new(),
+            value_type: WrappedMeasurementType::U64,
+            unit: PrefixedUnit::from(Unit::Unity),
+        };
+        let timestamp = Timestamp::from(UNIX_EPOCH + Duration::from_nanos(1_500_000_123));
+        let p1 = MeasurementPoint::new_untyped(
+            timestamp,
+            RawMetricId::from_u64(0),
+            Resource::CpuPackage { id: 0 },
+            ResourceConsumer::LocalMachine,
+            WrappedMeasurementValue::F64(12.5),
+        )
+        .with_attr("domain", "package")
+        .with_attr("core", 3_u64);
+        let p2 = MeasurementPoint::new_untyped(
+            timestamp,
+            RawMetricId::from_u64(1),
+            Resource::LocalMachine,
+            ResourceConsumer::Process { pid: 42 },
+            WrappedMeasurementValue::U64(7),
+        );
+
+        let mut builder = BatchBuilder::new(measurement_schema());
+        builder.push(&p1, &energy)?;
+        builder.push(&p2, &count)?;
+        assert_eq!(builder.len(), 2);
+
+        let batch = builder.finish()?;
+        assert!(builder.is_empty());
+        assert_eq!(batch.num_rows(), 2);
+
+        let timestamps = batch.column(0).as_primitive::<TimestampNanosecondType>();
+        assert_eq!(timestamps.value(0), 1_500_000_123);
+        let metrics = batch.column(1).as_string::<i32>();
+        assert_eq!(metrics.value(0), "rapl_consumed_energy");
+        let units = batch.column(2).as_string::<i32>();
+        assert_eq!(units.value(0), "milliJ");
+        let values_u64 = batch.column(3).as_primitive::<UInt64Type>();
+        assert!(values_u64.is_null(0));
+        assert_eq!(values_u64.value(1), 7);
+        let values_f64 = batch.column(4).as_primitive::<Float64Type>();
+        assert_eq!(values_f64.value(0), 12.5);
+        assert!(values_f64.is_null(1));
+        assert_eq!(batch.column(5).as_string::<i32>().value(0), "cpu_package");
+        assert_eq!(batch.column(6).as_string::<i32>().value(0), "0");
+        assert_eq!(batch.column(7).as_string::<i32>().value(1), "process");
+        assert_eq!(batch.column(8).as_string::<i32>().value(1), "42");
+
+        let attributes = batch.column(9).as_any().downcast_ref::<MapArray>().unwrap();
+        assert_eq!(attributes.value_length(0), 2);
+        assert_eq!(attributes.value_length(1), 0);
+        let keys = attributes.keys().as_string::<i32>();
+        let values = attributes.values().as_struct();
+        assert_eq!(keys.value(0), "domain");
+        assert_eq!(
+            values.column_by_name("string").unwrap().as_string::<i32>().value(0),
+            "package"
+        );
+        assert!(values.column_by_name("u64").unwrap().is_null(0));
+        assert_eq!(keys.value(1), "core");
+        assert_eq!(
+            values
+                .column_by_name("u64")
+                .unwrap()
+                .as_primitive::<UInt64Type>()
+                .value(1),
+            3
+        );
+        assert!(values.column_by_name("string").unwrap().is_null(1));
+
+        let meta = metric_metadata(0, &energy);
+        assert_eq!(meta["name"], "rapl_consumed_energy");
+        assert_eq!(meta["unit"], "milliJ");
+        assert_eq!(meta["value_type"], "F64");
+        Ok(())
+    }
+
+    #[test]
+    fn timestamps() {
+        let t = |d: i64| {
+            let epoch = UNIX_EPOCH;
+            let t = if d >= 0 {
+                epoch + Duration::from_nanos(d as u64)
+            } else {
+                epoch - Duration::from_nanos(d.unsigned_abs())
+            };
+            Timestamp::from(t)
+        };
+        assert_eq!(timestamp_nanos(t(1_500)), Some(1_500));
+        assert_eq!(timestamp_nanos(t(-1_500)), Some(-1_500));
+        assert_eq!(
+            timestamp_nanos(Timestamp::from(UNIX_EPOCH + Duration::from_secs(u64::MAX / 1000))),
+            None
+        );
+    }
+}
@@ -0,0 +1,510 @@
+//! Columnar files, with optional rotation.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime},
+};
+
+use alumet::{measurement::MeasurementPoint, metrics::Metric};
+use anyhow::{Context, anyhow};
+use arrow::{
+    datatypes::SchemaRef,
+    ipc::{
+        CompressionType,
+        writer::{FileWriter as IpcFileWriter, IpcWriteOptions},
+    },
+};
+use parquet::{
+    arrow::ArrowWriter,
+    basic::{GzipLevel, ZstdLevel},
+    file::{metadata::KeyValue, properties::WriterProperties},
+};
+use plugin_csv::rotated_path;
+use serde::{Deserialize, Serialize};
+
+use crate::schema::{BatchBuilder, METRICS_METADATA_KEY, measurement_schema, metric_metadata};
+
+/// Format of the output files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    /// Apache Parquet.
+    Parquet,
+    /// Arrow IPC file format, also known as Feather v2.
+    ArrowIpc,
+}
+
+/// Compression of the columns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    None,
+    Snappy,
+    Gzip,
+    Lz4,
+    Zstd,
+}
+
+#[derive(Debug, Clone)]
+pub struct FileSettings {
+    pub format: Format,
+    pub compression: Compression,
+    /// Number of measurements per row group (Parquet) or record batch (Arrow IPC).
+    pub row_group_size: usize,
+    /// Rotate the file when it exceeds this size (in bytes).
+    pub rotation_max_size: Option<u64>,
+    /// Rotate the file when it is older than this duration.
+    pub rotation_max_age: Option<Duration>,
+}
+
+/// Counts the bytes that are written to the file.
+struct CountingWriter {
+    inner: BufWriter<File>,
+    written: Arc<AtomicU64>,
+}
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.written.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+enum Writer {
+    Parquet(ArrowWriter<CountingWriter>),
+    Ipc(IpcFileWriter<CountingWriter>),
+}
+
+impl Writer {
+    fn create(
+        path: &Path,
+        schema: &SchemaRef,
+        settings: &FileSettings,
+        written: Arc<AtomicU64>,
+    ) -> anyhow::Result<Self> {
+        let file = File::create(path).with_context(|| format!("failed to open file for writing {path:?}"))?;
+        let file = CountingWriter {
+            inner: BufWriter::new(file),
+            written,
+        };
+        let writer = match settings.format {
+            Format::Parquet => {
+                let compression = match settings.compression {
+                    Compression::None => parquet::basic::Compression::UNCOMPRESSED,
+                    Compression::Snappy => parquet::basic::Compression::SNAPPY,
+                    Compression::Gzip => parquet::basic::Compression::GZIP(GzipLevel::default()),
+                    Compression::Lz4 => parquet::basic::Compression::LZ4_RAW,
+                    Compression::Zstd => parquet::basic::Compression::ZSTD(ZstdLevel::default()),
+                };
+                let props = WriterProperties::builder()
+                    .set_compression(compression)
+                    .set_max_row_group_size(settings.row_group_size)
+                    .build();
+                Writer::Parquet(ArrowWriter::try_new(file, schema.clone(), Some(props))?)
+            }
+            Format::ArrowIpc => {
+                let compression = match settings.compression {
+                    Compression::None => None,
+                    Compression::Lz4 => Some(CompressionType::LZ4_FRAME),
+                    Compression::Zstd => Some(CompressionType::ZSTD),
+                    other => {
+                        return Err(anyhow!(
+                            "compression {other:?} is not supported by the Arrow IPC format"
+                        ));
+                    }
+                };
+                let options = IpcWriteOptions::default().try_with_compression(compression)?;
+                Writer::Ipc(IpcFileWriter::try_new_with_options(file, schema, options)?)
+            }
+        };
+        Ok(writer)
+    }
+
+    /// Writes the metadata in the footer and closes the file.
+    fn close(self, metadata: String) -> anyhow::Result<()> {
+        let mut file = match self {
+            Writer::Parquet(mut w) => {
+                w.append_key_value_metadata(KeyValue::new(METRICS_METADATA_KEY.to_string(), metadata));
+                w.into_inner()?
+            }
+            Writer::Ipc(mut w) => {
+                w.write_metadata(METRICS_METADATA_KEY, metadata);
+                w.into_inner()?
+            }
+        };
+        file.flush()?;
+        Ok(())
+    }
+}
+
+/// A Parquet or Arrow IPC file that contains measurements.
+///
+/// The measurements are buffered in memory until `row_group_size` measurements have been pushed.
+/// The file is only valid once it has been closed, because the footer is written at the end.
+pub struct MeasurementFile {
+    path: PathBuf,
+    settings: FileSettings,
+    schema: SchemaRef,
+    /// `None` after a failed rotation, until the file is re-created by [`MeasurementFile::write_batch`].
+    writer: Option<Writer>,
+    opened_at: SystemTime,
+    batch: BatchBuilder,
+    /// Number of measurements in the file, including the buffered ones.
+    rows: u64,
+    /// Number of bytes written by the format writer.
+    written: Arc<AtomicU64>,
+    /// Metadata of the metrics that appear in the file, by metric id.
+    metrics: BTreeMap<u64, serde_json::Value>,
+}
+
+impl MeasurementFile {
+    /// Creates (or truncates) the file and opens it for writing.
+    pub fn create(path: impl Into<PathBuf>, settings: FileSettings) -> anyhow::Result<Self> {
+        let path = path.into();
+        let schema = measurement_schema();
+        let written = Arc::new(AtomicU64::new(0));
+        let writer = Writer::create(&path, &schema, &settings, written.clone())?;
+        Ok(Self {
+            path,
+            settings,
+            batch: BatchBuilder::new(schema.clone()),
+            schema,
+            writer: Some(writer),
+            opened_at: SystemTime::now(),
+            rows: 0,
+            written,
+            metrics: BTreeMap::new(),
+        })
+    }
+
+    /// Adds a measurement to the file.
+    ///
+    /// If the measurement cannot be stored (e.g. its timestamp is out of range), it is skipped with a warning.
+    pub fn push(&mut self, m: &MeasurementPoint, metric: &Metric) -> anyhow::Result<()> {
+        if let Err(e) = self.batch.push(m, metric) {
+            log::warn!("measurement of metric {} skipped: {e:#}", metric.name);
+            return Ok(());
+        }
+        let id = m.metric.as_u64();
+        self.metrics.entry(id).or_insert_with(|| metric_metadata(id, metric));
+        self.rows += 1;
+        if self.batch.len() >= self.settings.row_group_size {
+            self.write_batch()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the buffered measurements to the file.
+    pub fn write_batch(&mut self) -> anyhow::Result<()> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        let batch = self.batch.finish()?;
+        match self.writer()? {
+            Writer::Parquet(w) => {
+                w.write(&batch)?;
+                // end the row group here
+                w.flush()?;
+            }
+            Writer::Ipc(w) => w.write(&batch)?,
+        }
+        Ok(())
+    }
+
+    /// Returns the format writer, and re-creates the file if a previous rotation failed to do it.
+    fn writer(&mut self) -> anyhow::Result<&mut Writer> {
+        if self.writer.is_none() {
+            let writer = Writer::create(&self.path, &self.schema, &self.settings, self.written.clone())?;
+            self.opened_at = SystemTime::now();
+            self.writer = Some(writer);
+        }
+        Ok(self.writer.as_mut().unwrap())
+    }
+
+    /// Returns true if the file has reached its maximum size or age.
+    ///
+    /// The size does not include the measurements that are still buffered in memory.
+    pub fn should_rotate(&self, now: SystemTime) -> bool {
+        if self.rows == 0 {
+            return false;
+        }
+        let too_big = self
+            .settings
+            .rotation_max_size
+            .is_some_and(|max| self.written.load(Ordering::Relaxed) >= max);
+        let too_old = self
+            .settings
+            .rotation_max_age
+            .is_some_and(|max| now.duration_since(self.opened_at).is_ok_and(|age| age >= max));
+        too_big || too_old
+    }
+
+    /// Renames the current file, closes it and opens a new file.
+    ///
+    /// Returns the path of the rotated file.
+    /// If the file cannot be renamed, it is left open and the measurements keep being written to it.
+    pub fn rotate(&mut self) -> anyhow::Result<PathBuf> {
+        let mut n = 0;
+        let mut rotated = rotated_path(&self.path, self.opened_at, n);
+        while rotated.exists() {
+            n += 1;
+            rotated = rotated_path(&self.path, self.opened_at, n);
+        }
+        // The file is renamed while it is still open, so that a failure does not lose the writer.
+        fs::rename(&self.path, &rotated).with_context(|| format!("failed to rename {:?} to {rotated:?}", self.path))?;
+        log::debug!("{:?} rotated to {rotated:?}", self.path);
+
+        if let Err(e) = self.close() {
+            log::error!("failed to close the rotated file {rotated:?}: {e:?}");
+        }
+        self.written.store(0, Ordering::Relaxed);
+        self.rows = 0;
+        self.metrics.clear();
+        self.writer()?;
+        Ok(rotated)
+    }
+
+    /// Writes the buffered measurements and the footer, and closes the file.
+    pub fn close(&mut self) -> anyhow::Result<()> {
+        let res = self.write_batch();
+        if let Some(writer) = self.writer.take() {
+            let metadata = serde_json::Value::Array(self.metrics.values().cloned().collect()).to_string();
+            writer
+                .close(metadata)
+                .with_context(|| format!("failed to close {:?}", self.path))?;
+        }
+        res
+    }
+}
+
+impl Drop for MeasurementFile {
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            log::error!("{e:?}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, File},
+        path::Path,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use alumet::{
+        measurement::{MeasurementPoint, Timestamp, WrappedMeasurementType, WrappedMeasurementValue},
+        metrics::{Metric, RawMetricId},
+        resources::{Resource, ResourceConsumer},
+        units::Unit,
+    };
+    use arrow::{
+        array::AsArray,
+        datatypes::{TimestampNanosecondType, UInt64Type},
+        ipc::reader::FileReader,
+    };
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use pretty_assertions::assert_eq;
+
+    use super::{Compression, FileSettings, Format, MeasurementFile};
+    use crate::schema::METRICS_METADATA_KEY;
+
+    fn test_metric() -> Metric {
+        Metric {
+            name: String::from("test_metric"),
+            description: String::from("a test metric"),
+            value_type: WrappedMeasurementType::U64,
+            unit: Unit::Second.into(),
+        }
+    }
+
+    fn test_point(value: u64) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::from(UNIX_EPOCH + Duration::from_secs(value)),
+            RawMetricId::from_u64(3),
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            WrappedMeasurementValue::U64(value),
+        )
+        .with_attr("attr", value)
+    }
+
+    fn settings(format: Format, compression: Compression) -> FileSettings {
+        FileSettings {
+            format,
+            compression,
+            row_group_size: 2,
+            rotation_max_size: None,
+            rotation_max_age: None,
+        }
+    }
+
+    #[test]
+    fn write_parquet() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("test.parquet");
+        let metric = test_metric();
+
+        let mut file = MeasurementFile::create(&path, settings(Format::Parquet, Compression::Zstd))?;
+        for i in 0..5 {
+            file.push(&test_point(i), &metric)?;
+        }
+        drop(file);
+
+        let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(&path)?)?;
+        let parquet_metadata = builder.metadata().clone();
+        // 5 measurements with 2 measurements per row group
+        assert_eq!(parquet_metadata.num_row_groups(), 3);
+        let key_values = parquet_metadata.file_metadata().key_value_metadata().unwrap();
+        let metrics = key_values
+            .iter()
+            .find(|kv| kv.key == METRICS_METADATA_KEY)
+            .and_then(|kv| kv.value.clone())
+            .unwrap();
+        let metrics: serde_json::Value = serde_json::from_str(&metrics)?;
+        assert_eq!(metrics[0]["id"], 3);
+        assert_eq!(metrics[0]["name"], "test_metric");
+        assert_eq!(metrics[0]["unit"], "s");
+
+        let mut values = Vec::new();
+        for batch in builder.build()? {
+            let batch = batch?;
+            let column = batch.column_by_name("value_u64").unwrap();
+            values.extend(column.as_primitive::<UInt64Type>().values().iter().copied());
+        }
+        assert_eq!(values, vec![0, 1, 2, 3, 4]);
+        Ok(())
+    }
+
+    #[test]
+    fn write_arrow_ipc() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("test.arrow");
+        let metric = test_metric();
+
+        let mut file = MeasurementFile::create(&path, settings(Format::ArrowIpc, Compression::Lz4))?;
+        for i in 0..3 {
+            file.push(&test_point(i), &metric)?;
+        }
+        drop(file);
+
+        let reader = FileReader::try_new(File::open(&path)?, None)?;
+        let metrics: serde_json::Value = serde_json::from_str(&reader.custom_metadata()[METRICS_METADATA_KEY])?;
+        assert_eq!(metrics[0]["name"], "test_metric");
+        let rows: usize = reader.map(|batch| batch.unwrap().num_rows()).sum();
+        assert_eq!(rows, 3);
+
+        // snappy is not supported by Arrow IPC
+        let path = tmp.path().join("invalid.arrow");
+        assert!(MeasurementFile::create(&path, settings(Format::ArrowIpc, Compression::Snappy)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn rotate() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("test.parquet");
+        let metric = test_metric();
+        let settings = FileSettings {
+            rotation_max_age: Some(Duration::from_secs(60)),
+            ..settings(Format::Parquet, Compression::Snappy)
+        };
+
+        let mut file = MeasurementFile::create(&path, settings)?;
+        let later = SystemTime::now() + Duration::from_secs(3600);
+        // empty files are never rotated
+        assert!(!file.should_rotate(later));
+        file.push(&test_point(1), &metric)?;
+        file.push(&test_point(2), &metric)?;
+        assert!(!file.should_rotate(SystemTime::now()));
+        assert!(file.should_rotate(later));
+        let rotated = file.rotate()?;
+        file.push(&test_point(3), &metric)?;
+        drop(file);
+
+        let mut names: Vec<_> = fs::read_dir(tmp.path())?
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names.len(), 2);
+
+        let rows = |p: &Path| -> anyhow::Result<i64> {
+            let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(p)?)?;
+            Ok(builder.metadata().file_metadata().num_rows())
+        };
+        assert_eq!(rows(&rotated)?, 2);
+        assert_eq!(rows(&path)?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn failed_rotation() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("test.parquet");
+        let metric = test_metric();
+
+        let mut file = MeasurementFile::create(&path, settings(Format::Parquet, Compression::None))?;
+        file.push(&test_point(1), &metric)?;
+        // the rename fails, but the file stays open
+        fs::remove_file(&path)?;
+        assert!(file.rotate().is_err());
+        file.push(&test_point(2), &metric)?;
+        file.push(&test_point(3), &metric)?;
+
+        // the new file could not be created: it is re-created on the next write
+        file.writer = None;
+        file.push(&test_point(4), &metric)?;
+        file.push(&test_point(5), &metric)?;
+        drop(file);
+
+        // 3 was still buffered, 1 and 2 were written to the removed file
+        let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(&path)?)?;
+        assert_eq!(builder.metadata().file_metadata().num_rows(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn timestamp_before_epoch() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("test.parquet");
+        let metric = test_metric();
+
+        let mut file = MeasurementFile::create(&path, settings(Format::Parquet, Compression::None))?;
+        let mut point = test_point(1);
+        point.timestamp = Timestamp::from(UNIX_EPOCH - Duration::from_secs(10));
+        file.push(&point, &metric)?;
+        // out of the range of the timestamp column: skipped
+        point.timestamp = Timestamp::from(UNIX_EPOCH + Duration::from_secs(u64::MAX / 1000));
+        file.push(&point, &metric)?;
+        file.push(&test_point(2), &metric)?;
+        drop(file);
+
+        let mut timestamps = Vec::new();
+        for batch in ParquetRecordBatchReaderBuilder::try_new(File::open(&path)?)?.build()? {
+            let batch = batch?;
+            let column = batch.column_by_name("timestamp").unwrap();
+            timestamps.extend(
+                column
+                    .as_primitive::<TimestampNanosecondType>()
+                    .values()
+                    .iter()
+                    .copied(),
+            );
+        }
+        assert_eq!(timestamps, vec![-10_000_000_000, 2_000_000_000]);
+        Ok(())
+    }
+}
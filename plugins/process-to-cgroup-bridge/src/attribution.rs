@@ -0,0 +1,370 @@
+//! Attribution of the measurements of a cgroup to its processes, in proportion to their CPU time.
+
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+use alumet::{
+    measurement::{AttributeValue, MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+    metrics::RawMetricId,
+    pipeline::{
+        Transform,
+        elements::{error::TransformError, transform::TransformContext},
+    },
+    resources::ResourceConsumer,
+};
+use serde::{Deserialize, Serialize};
+use util_cgroups::hierarchy::is_in_subtree;
+
+use crate::transform::find_cgroup_path;
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct AttributionConfig {
+    /// The metrics names, measured on cgroups, to distribute to the processes of the cgroups.
+    pub metrics: Vec<String>,
+    /// The metric that measures the CPU time of the processes, used to compute their share.
+    #[serde(default = "default_cpu_time_metric")]
+    pub cpu_time_metric: String,
+    /// The CPU time of a process is only used if it has been measured at most `max_age` before or after the cgroup.
+    #[serde(with = "humantime_serde", default = "default_max_age")]
+    pub max_age: Duration,
+    /// Will also distribute the measurements to the processes of the descendants of the cgroup.
+    #[serde(default = "default_true")]
+    pub include_descendants: bool,
+}
+
+#[cfg_attr(tarpaulin, ignore)]
+fn default_cpu_time_metric() -> String {
+    String::from("cpu_time_delta")
+}
+
+#[cfg_attr(tarpaulin, ignore)]
+fn default_max_age() -> Duration {
+    Duration::from_secs(5)
+}
+
+#[cfg_attr(tarpaulin, ignore)]
+fn default_true() -> bool {
+    true
+}
+
+/// Last CPU time of a process.
+struct ProcessCpuTime {
+    cgroup: String,
+    timestamp: Timestamp,
+    cpu_time: u64,
+}
+
+pub struct AttributionTransform {
+    metrics: Vec<RawMetricId>,
+    cpu_time_metric: RawMetricId,
+    max_age: Duration,
+    include_descendants: bool,
+    proc_path: PathBuf,
+    processes: HashMap<u32, ProcessCpuTime>,
+    /// The processes whose cgroup could not be read, and when. They are not looked for again before `max_age`.
+    failures: HashMap<u32, Timestamp>,
+}
+
+impl Transform for AttributionTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, _ctx: &TransformContext) -> Result<(), TransformError> {
+        self.attribute(measurements);
+        Ok(())
+    }
+}
+
+impl AttributionTransform {
+    pub fn new(
+        metrics: Vec<RawMetricId>,
+        cpu_time_metric: RawMetricId,
+        max_age: Duration,
+        include_descendants: bool,
+        proc_path: PathBuf,
+    ) -> Self {
+        Self {
+            metrics,
+            cpu_time_metric,
+            max_age,
+            include_descendants,
+            proc_path,
+            processes: HashMap::new(),
+            failures: HashMap::new(),
+        }
+    }
+
+    fn attribute(&mut self, measurements: &mut MeasurementBuffer) {
+        // Update the shares first, because the cgroup and its processes can be measured at the same time.
+        let mut latest: Option<Timestamp> = None;
+        for m in measurements.iter() {
+            if m.metric != self.cpu_time_metric || !is_process_cpu_time(m) {
+                continue;
+            }
+            if let ResourceConsumer::Process { pid } = m.consumer {
+                self.update_cpu_time(pid, m.timestamp, m.value.as_u64());
+                latest = latest.max(Some(m.timestamp));
+            }
+        }
+
+        let mut attributed = Vec::new();
+        for m in measurements.iter() {
+            if !self.metrics.contains(&m.metric) {
+                continue;
+            }
+            if let ResourceConsumer::ControlGroup { path } = &m.consumer {
+                attributed.extend(self.distribute(m, path));
+            }
+        }
+        for point in attributed {
+            measurements.push(point);
+        }
+
+        if let Some(latest) = latest {
+            let max_age = self.max_age;
+            self.processes.retain(|_, p| abs_diff(latest, p.timestamp) <= max_age);
+            self.failures.retain(|_, t| abs_diff(latest, *t) <= max_age);
+        }
+    }
+
+    fn update_cpu_time(&mut self, pid: u32, t: Timestamp, cpu_time: u64) {
+        match self.processes.get_mut(&pid) {
+            // user and system time are measured separately, at the same time
+            Some(p) if p.timestamp == t => p.cpu_time += cpu_time,
+            Some(p) if p.timestamp < t => {
+                p.timestamp = t;
+                p.cpu_time = cpu_time;
+            }
+            Some(_) => (), // out of order, ignore
+            None => {
+                if self.failures.get(&pid).is_some_and(|f| abs_diff(t, *f) <= self.max_age) {
+                    return;
+                }
+                match find_cgroup_path(&self.proc_path, pid) {
+                    Ok(cgroup) => {
+                        let p = ProcessCpuTime {
+                            cgroup,
+                            timestamp: t,
+                            cpu_time,
+                        };
+                        self.failures.remove(&pid);
+                        self.processes.insert(pid, p);
+                    }
+                    Err(e) => {
+                        log::debug!("cannot attribute measurements to process {pid}: {e:#}");
+                        self.failures.insert(pid, t);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Splits a measurement of a cgroup between its processes, in proportion to their CPU time.
+    fn distribute(&self, m: &MeasurementPoint, cgroup_path: &str) -> Vec<MeasurementPoint> {
+        let shares: Vec<(u32, u64)> = self
+            .processes
+            .iter()
+            .filter(|(_, p)| {
+                let in_cgroup = if self.include_descendants {
+                    is_in_subtree(cgroup_path, &p.cgroup)
+                } else {
+                    p.cgroup == cgroup_path
+                };
+                in_cgroup && p.cpu_time > 0 && abs_diff(m.timestamp, p.timestamp) <= self.max_age
+            })
+            .map(|(pid, p)| (*pid, p.cpu_time))
+            .collect();
+        let total: u64 = shares.iter().map(|(_, cpu_time)| cpu_time).sum();
+
+        shares
+            .into_iter()
+            .map(|(pid, cpu_time)| {
+                let share = cpu_time as f64 / total as f64;
+                let mut point = m.clone();
+                point.consumer = ResourceConsumer::Process { pid };
+                point.value = match m.value {
+                    WrappedMeasurementValue::F64(v) => WrappedMeasurementValue::F64(v * share),
+                    WrappedMeasurementValue::U64(v) => WrappedMeasurementValue::U64((v as f64 * share).round() as u64),
+                };
+                point.add_attr("cgroup", cgroup_path.to_owned());
+                point.add_attr("cpu_share", share);
+                point
+            })
+            .collect()
+    }
+}
+
+/// The guest time is already included in the user time: ignore it.
+fn is_process_cpu_time(m: &MeasurementPoint) -> bool {
+    !m.attributes().any(|(k, v)| {
+        k == "kind"
+            && match v {
+                AttributeValue::Str(s) => *s == "guest",
+                AttributeValue::String(s) => s == "guest",
+                _ => false,
+            }
+    })
+}
+
+fn abs_diff(a: Timestamp, b: Timestamp) -> Duration {
+    match a.duration_since(b) {
+        Ok(d) => d,
+        Err(_) => b.duration_since(a).unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use alumet::resources::Resource;
+
+    use super::*;
+
+    const ENERGY: u64 = 0;
+    const CPU_TIME: u64 = 1;
+
+    fn point(
+        metric: u64,
+        t: Timestamp,
+        consumer: ResourceConsumer,
+        value: WrappedMeasurementValue,
+    ) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            t,
+            RawMetricId::from_u64(metric),
+            Resource::LocalMachine,
+            consumer,
+            value,
+        )
+    }
+
+    fn process_cpu(t: Timestamp, pid: u32, value: u64) -> MeasurementPoint {
+        point(
+            CPU_TIME,
+            t,
+            ResourceConsumer::Process { pid },
+            WrappedMeasurementValue::U64(value),
+        )
+    }
+
+    fn cgroup(path: &str) -> ResourceConsumer {
+        ResourceConsumer::ControlGroup {
+            path: path.to_owned().into(),
+        }
+    }
+
+    fn mock_proc(processes: &[(u32, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (pid, cgroup) in processes {
+            let pid_dir = dir.path().join(pid.to_string());
+            fs::create_dir(&pid_dir).unwrap();
+            fs::write(pid_dir.join("cgroup"), format!("0::{cgroup}\n")).unwrap();
+        }
+        dir
+    }
+
+    fn transform(proc_dir: &tempfile::TempDir, include_descendants: bool) -> AttributionTransform {
+        AttributionTransform::new(
+            vec![RawMetricId::from_u64(ENERGY)],
+            RawMetricId::from_u64(CPU_TIME),
+            Duration::from_secs(5),
+            include_descendants,
+            proc_dir.path().to_owned(),
+        )
+    }
+
+    fn attributed(buf: &MeasurementBuffer) -> Vec<(u32, f64)> {
+        let mut res: Vec<(u32, f64)> = buf
+            .iter()
+            .filter_map(|m| match m.consumer {
+                ResourceConsumer::Process { pid } if m.metric == RawMetricId::from_u64(ENERGY) => {
+                    Some((pid, m.value.as_f64()))
+                }
+                _ => None,
+            })
+            .collect();
+        res.sort_by_key(|(pid, _)| *pid);
+        res
+    }
+
+    #[test]
+    fn share_of_cpu_time() {
+        let proc_dir = mock_proc(&[(1, "/job"), (2, "/job"), (3, "/job/step"), (4, "/other")]);
+        let t = Timestamp::from_unix_timestamp(1000, 0);
+        let mut transform = transform(&proc_dir, true);
+
+        let mut buf = MeasurementBuffer::new();
+        let cpu = |pid, value, kind: &'static str| process_cpu(t, pid, value).with_attr("kind", kind);
+        buf.push(cpu(1, 20, "user"));
+        buf.push(cpu(1, 10, "system"));
+        buf.push(cpu(1, 1000, "guest"));
+        buf.push(cpu(2, 0, "user"));
+        buf.push(cpu(3, 10, "user"));
+        buf.push(cpu(4, 500, "user"));
+        buf.push(point(ENERGY, t, cgroup("/job"), WrappedMeasurementValue::F64(100.0)));
+        transform.attribute(&mut buf);
+
+        assert_eq!(attributed(&buf), vec![(1, 75.0), (3, 25.0)]);
+        let attributed_point = buf
+            .iter()
+            .find(|m| m.consumer == ResourceConsumer::Process { pid: 3 } && m.metric == RawMetricId::from_u64(ENERGY))
+            .unwrap();
+        assert!(
+            attributed_point
+                .attributes()
+                .any(|(k, v)| k == "cgroup" && v == &AttributeValue::String("/job".into()))
+        );
+    }
+
+    #[test]
+    fn exact_cgroup_and_age() {
+        let proc_dir = mock_proc(&[(1, "/job"), (3, "/job/step")]);
+        let t = Timestamp::from_unix_timestamp(1000, 0);
+        let mut transform = transform(&proc_dir, false);
+
+        let mut buf = MeasurementBuffer::new();
+        buf.push(process_cpu(t, 1, 10));
+        buf.push(process_cpu(t, 3, 10));
+        // unknown process: ignored
+        buf.push(process_cpu(t, 9, 10));
+        buf.push(point(ENERGY, t, cgroup("/job"), WrappedMeasurementValue::U64(7)));
+        transform.attribute(&mut buf);
+        assert_eq!(attributed(&buf), vec![(1, 7.0)]);
+
+        // the CPU time of the processes is too old
+        let mut buf = MeasurementBuffer::new();
+        let later = t + Duration::from_secs(60);
+        buf.push(point(ENERGY, later, cgroup("/job"), WrappedMeasurementValue::U64(7)));
+        transform.attribute(&mut buf);
+        assert_eq!(attributed(&buf), vec![]);
+    }
+
+    #[test]
+    fn failures_are_cached() {
+        let proc_dir = mock_proc(&[]);
+        let t = Timestamp::from_unix_timestamp(1000, 0);
+        let mut transform = transform(&proc_dir, false);
+
+        // the process cannot be found in /proc
+        let mut buf = MeasurementBuffer::new();
+        buf.push(process_cpu(t, 1, 10));
+        transform.attribute(&mut buf);
+        assert!(transform.failures.contains_key(&1));
+
+        // not looked for again before max_age
+        fs::create_dir(proc_dir.path().join("1")).unwrap();
+        fs::write(proc_dir.path().join("1/cgroup"), "0::/job\n").unwrap();
+        let mut buf = MeasurementBuffer::new();
+        let soon = t + Duration::from_secs(1);
+        buf.push(process_cpu(soon, 1, 10));
+        buf.push(point(ENERGY, soon, cgroup("/job"), WrappedMeasurementValue::U64(7)));
+        transform.attribute(&mut buf);
+        assert_eq!(attributed(&buf), vec![]);
+
+        // looked for again after max_age
+        let mut buf = MeasurementBuffer::new();
+        let later = t + Duration::from_secs(10);
+        buf.push(process_cpu(later, 1, 10));
+        buf.push(point(ENERGY, later, cgroup("/job"), WrappedMeasurementValue::U64(7)));
+        transform.attribute(&mut buf);
+        assert_eq!(attributed(&buf), vec![(1, 7.0)]);
+        assert!(transform.failures.is_empty());
+    }
+}
@@ -6,15 +6,17 @@ use alumet::{
     },
 };
 use anyhow::Context;
+use attribution::{AttributionConfig, AttributionTransform};
+use process_tree::{ProcessTreeConfig, setup_process_tree};
 use serde::{Deserialize, Serialize};
-use transform::ProcessToCgroupBridgeTransform;
-
-#[cfg(test)]
 use std::path::PathBuf;
+use transform::ProcessToCgroupBridgeTransform;
 
 #[cfg(test)]
 mod tests;
 
+mod attribution;
+mod process_tree;
 mod transform;
 
 pub struct ProcessToCgroupBridgePlugin {
@@ -40,13 +42,67 @@ impl AlumetPlugin for ProcessToCgroupBridgePlugin {
     }
 
     fn start(&mut self, alumet: &mut alumet::plugin::AlumetPluginStart) -> anyhow::Result<()> {
+        #[cfg(test)]
+        let proc_path = self.config.proc_path.clone();
+        #[cfg(not(test))]
+        let proc_path = PathBuf::from("/proc");
+
+        // The bridge is optional if the attribution or the process tree aggregation is enabled.
+        let bridge_enabled = !self.config.processes_metrics.is_empty()
+            || (self.config.attribution.is_none() && self.config.process_tree.is_none());
+        if bridge_enabled {
+            self.add_bridge_transform(
+                alumet,
+                #[cfg(test)]
+                proc_path.clone(),
+            )?;
+        }
+
+        if let Some(attribution) = self.config.attribution.clone() {
+            let proc_path = proc_path.clone();
+            alumet.add_transform_builder("attribution", move |ctx| {
+                let mut metrics = Vec::with_capacity(attribution.metrics.len());
+                for metric_name in &attribution.metrics {
+                    let (id, _) = ctx
+                        .metric_by_name(metric_name)
+                        .with_context(|| format!("Metric not found : {}", metric_name))?;
+                    metrics.push(id);
+                }
+                let (cpu_time_metric, _) = ctx
+                    .metric_by_name(&attribution.cpu_time_metric)
+                    .with_context(|| format!("Metric not found : {}", attribution.cpu_time_metric))?;
+                let transform = Box::new(AttributionTransform::new(
+                    metrics,
+                    cpu_time_metric,
+                    attribution.max_age,
+                    attribution.include_descendants,
+                    proc_path,
+                ));
+                Ok(transform)
+            })?;
+        }
+
+        if let Some(process_tree) = self.config.process_tree.clone() {
+            setup_process_tree(alumet, process_tree, proc_path)?;
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+impl ProcessToCgroupBridgePlugin {
+    fn add_bridge_transform(
+        &self,
+        alumet: &mut alumet::plugin::AlumetPluginStart,
+        #[cfg(test)] proc_path: PathBuf,
+    ) -> anyhow::Result<()> {
         let processes_metrics = self.config.processes_metrics.clone();
         let merge_similar_cgroups = self.config.merge_similar_cgroups;
         let keep_processed_measurements = self.config.keep_processed_measurements;
 
-        #[cfg(test)]
-        let proc_path = self.config.proc_path.clone();
-
         alumet.add_transform_builder("transform", move |ctx| {
             let mut processes_metrics_ids: Vec<RawMetricId> = Vec::new();
             for metric_name in &processes_metrics {
@@ -71,10 +127,6 @@ impl AlumetPlugin for ProcessToCgroupBridgePlugin {
         })?;
         Ok(())
     }
-
-    fn stop(&mut self) -> anyhow::Result<()> {
-        Ok(())
-    }
 }
 
 #[derive(Deserialize, Serialize)]
@@ -85,6 +137,12 @@ pub struct Config {
     pub merge_similar_cgroups: bool,
     /// Will keep all the measurements that have been processed by the transformer. In case it's false only the measurements with a cgroup resource consumer will be kept.
     pub keep_processed_measurements: bool,
+    /// Will distribute the measurements of the cgroups to their processes, in proportion to their CPU time.
+    #[serde(default)]
+    pub attribution: Option<AttributionConfig>,
+    /// Will aggregate the measurements of the processes to their session leader or to their parent.
+    #[serde(default)]
+    pub process_tree: Option<ProcessTreeConfig>,
 
     #[cfg(test)]
     pub proc_path: PathBuf,
@@ -99,6 +157,8 @@ impl Default for Config {
             ],
             merge_similar_cgroups: true,
             keep_processed_measurements: true,
+            attribution: None,
+            process_tree: None,
 
             #[cfg(test)]
             proc_path: PathBuf::from("/proc"),
@@ -0,0 +1,514 @@
+//! Aggregation of the measurements of the processes up the process tree, to their session leader or to their ancestors.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use alumet::{
+    measurement::{
+        AttributeValue, MeasurementAccumulator, MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue,
+    },
+    metrics::RawMetricId,
+    pipeline::{
+        Source, Transform,
+        elements::{
+            error::{PollError, TransformError},
+            source::trigger::TriggerSpec,
+            transform::TransformContext,
+        },
+    },
+    plugin::AlumetPluginStart,
+    resources::{Resource, ResourceConsumer},
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// Attribute added to the aggregated measurements, its value is the [`ProcessGroup`].
+const AGGREGATED_BY_ATTRIBUTE: &str = "aggregated_by";
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ProcessTreeConfig {
+    /// The process that receives the aggregated measurements.
+    #[serde(default)]
+    pub group_by: ProcessGroup,
+    /// The metrics names to aggregate, and how to aggregate them.
+    pub metrics: BTreeMap<String, MetricAggregation>,
+    /// Interval between two aggregated measurements.
+    #[serde(with = "humantime_serde", default = "default_interval")]
+    pub interval: Duration,
+}
+
+#[cfg_attr(tarpaulin, ignore)]
+fn default_interval() -> Duration {
+    Duration::from_secs(5)
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProcessGroup {
+    /// The measurements of the processes are aggregated to the leader of their session.
+    #[default]
+    Session,
+    /// The measurements of the processes are aggregated to the process itself and to all its ancestors,
+    /// up the chain of parents: each process receives the measurements of its subtree.
+    Parent,
+}
+
+impl ProcessGroup {
+    fn as_str(self) -> &'static str {
+        match self {
+            ProcessGroup::Session => "session",
+            ProcessGroup::Parent => "parent",
+        }
+    }
+}
+
+/// How the values of a metric are aggregated.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricAggregation {
+    /// Additive increments, like `cpu_time_delta`: summed over the interval and across the processes.
+    Delta,
+    /// Additive values, like `memory_usage`: the last values of the processes are summed.
+    Gauge,
+    /// Non-additive values: the last values of the processes are averaged.
+    Mean,
+}
+
+/// Creates the transform and the source that aggregate the measurements up the process tree.
+pub fn setup_process_tree(
+    alumet: &mut AlumetPluginStart,
+    config: ProcessTreeConfig,
+    proc_path: PathBuf,
+) -> anyhow::Result<()> {
+    let state = Arc::new(Mutex::new(TreeState::default()));
+
+    let transform_state = state.clone();
+    let group_by = config.group_by;
+    let metrics_config = config.metrics;
+    alumet.add_transform_builder("process-tree", move |ctx| {
+        let mut metrics = HashMap::with_capacity(metrics_config.len());
+        for (name, aggregation) in &metrics_config {
+            let (id, _) = ctx
+                .metric_by_name(name)
+                .with_context(|| format!("Metric not found : {}", name))?;
+            metrics.insert(id, *aggregation);
+        }
+        let transform = ProcessTreeTransform {
+            state: transform_state,
+            metrics,
+            group_by,
+            proc_path,
+        };
+        Ok(Box::new(transform))
+    })?;
+
+    let source = ProcessTreeSource { state, group_by };
+    alumet.add_source(
+        "process-tree",
+        Box::new(source),
+        TriggerSpec::at_interval(config.interval),
+    )?;
+    Ok(())
+}
+
+/// Identifies the series of aggregated measurements.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct SeriesKey {
+    metric: RawMetricId,
+    /// The process that receives the aggregated measurements.
+    target: u32,
+    resource: Resource,
+    attributes: Vec<(String, AttributeValue)>,
+}
+
+struct SeriesValues {
+    aggregation: MetricAggregation,
+    /// Values of the interval, by pid.
+    values: HashMap<u32, WrappedMeasurementValue>,
+}
+
+/// Position of a process in the process tree.
+#[derive(Clone, Copy)]
+struct ProcessNode {
+    parent: u32,
+    session: u32,
+}
+
+#[derive(Default)]
+struct TreeState {
+    /// Parent and session of each process, and whether the process has been used during the interval.
+    /// `None` if `/proc` could not be read: the process is not looked for again before the next interval.
+    processes: HashMap<u32, (Option<ProcessNode>, bool)>,
+    series: HashMap<SeriesKey, SeriesValues>,
+}
+
+impl TreeState {
+    fn node(&mut self, pid: u32, proc_path: &Path) -> Option<ProcessNode> {
+        let (node, seen) = self.processes.entry(pid).or_insert_with(|| {
+            let node = match read_parent_and_session(proc_path, pid) {
+                Ok((parent, session)) => Some(ProcessNode { parent, session }),
+                Err(e) => {
+                    log::debug!("cannot aggregate the measurements of process {pid}: {e:#}");
+                    None
+                }
+            };
+            (node, false)
+        });
+        *seen = true;
+        *node
+    }
+
+    /// Returns the processes that receive the measurements of the given process.
+    fn targets_of(&mut self, pid: u32, group_by: ProcessGroup, proc_path: &Path) -> Vec<u32> {
+        let Some(node) = self.node(pid, proc_path) else {
+            return Vec::new();
+        };
+        match group_by {
+            ProcessGroup::Session => vec![node.session],
+            ProcessGroup::Parent => {
+                let mut targets = vec![pid];
+                let mut parent = node.parent;
+                // the parent of the root of the tree is 0
+                while parent != 0 && !targets.contains(&parent) {
+                    targets.push(parent);
+                    match self.node(parent, proc_path) {
+                        Some(node) => parent = node.parent,
+                        None => break,
+                    }
+                }
+                targets
+            }
+        }
+    }
+
+    /// Starts a new interval: forgets the values, the processes that have not been used and the failures.
+    fn end_interval(&mut self) {
+        self.series.clear();
+        self.processes
+            .retain(|_, (node, seen)| std::mem::take(seen) && node.is_some());
+    }
+}
+
+pub struct ProcessTreeTransform {
+    state: Arc<Mutex<TreeState>>,
+    metrics: HashMap<RawMetricId, MetricAggregation>,
+    group_by: ProcessGroup,
+    proc_path: PathBuf,
+}
+
+impl Transform for ProcessTreeTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, _ctx: &TransformContext) -> Result<(), TransformError> {
+        self.accumulate(measurements);
+        Ok(())
+    }
+}
+
+impl ProcessTreeTransform {
+    fn accumulate(&self, measurements: &MeasurementBuffer) {
+        let mut state = self.state.lock().unwrap();
+        for m in measurements.iter() {
+            let Some(aggregation) = self.metrics.get(&m.metric) else {
+                continue;
+            };
+            let ResourceConsumer::Process { pid } = m.consumer else {
+                continue;
+            };
+            // Skip the measurements that have already been aggregated.
+            if m.attributes().any(|(k, _)| k == AGGREGATED_BY_ATTRIBUTE) {
+                continue;
+            }
+            let attributes: Vec<(String, AttributeValue)> =
+                m.attributes().map(|(k, v)| (k.to_owned(), v.clone())).collect();
+            for target in state.targets_of(pid, self.group_by, &self.proc_path) {
+                let key = SeriesKey {
+                    metric: m.metric,
+                    target,
+                    resource: m.resource.clone(),
+                    attributes: attributes.clone(),
+                };
+                let series = state.series.entry(key).or_insert_with(|| SeriesValues {
+                    aggregation: *aggregation,
+                    values: HashMap::new(),
+                });
+                match (aggregation, series.values.get_mut(&pid)) {
+                    (MetricAggregation::Delta, Some(value)) => *value = add(value, &m.value),
+                    _ => {
+                        series.values.insert(pid, m.value.clone());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Emits the aggregated measurements at regular intervals.
+pub struct ProcessTreeSource {
+    state: Arc<Mutex<TreeState>>,
+    group_by: ProcessGroup,
+}
+
+impl Source for ProcessTreeSource {
+    fn poll(&mut self, measurements: &mut MeasurementAccumulator, t: Timestamp) -> Result<(), PollError> {
+        let mut state = self.state.lock().unwrap();
+        for (key, series) in &state.series {
+            let values: Vec<&WrappedMeasurementValue> = series.values.values().collect();
+            let Some(value) = aggregate(series.aggregation, &values) else {
+                continue;
+            };
+            let point = MeasurementPoint::new_untyped(
+                t,
+                key.metric,
+                key.resource.clone(),
+                ResourceConsumer::Process { pid: key.target },
+                value,
+            )
+            .with_attr_slice(&key.attributes)
+            .with_attr(AGGREGATED_BY_ATTRIBUTE, self.group_by.as_str());
+            measurements.push(point);
+        }
+        state.end_interval();
+        Ok(())
+    }
+}
+
+fn add(a: &WrappedMeasurementValue, b: &WrappedMeasurementValue) -> WrappedMeasurementValue {
+    match a {
+        WrappedMeasurementValue::F64(a) => WrappedMeasurementValue::F64(a + b.as_f64()),
+        WrappedMeasurementValue::U64(a) => WrappedMeasurementValue::U64(a + b.as_u64()),
+    }
+}
+
+/// Aggregates the values of the processes, returns `None` if there is no value.
+fn aggregate(aggregation: MetricAggregation, values: &[&WrappedMeasurementValue]) -> Option<WrappedMeasurementValue> {
+    let (first, rest) = values.split_first()?;
+    let sum = rest.iter().fold((*first).clone(), |acc, v| add(&acc, v));
+    match aggregation {
+        MetricAggregation::Delta | MetricAggregation::Gauge => Some(sum),
+        MetricAggregation::Mean => {
+            let n = values.len() as f64;
+            Some(match sum {
+                WrappedMeasurementValue::F64(sum) => WrappedMeasurementValue::F64(sum / n),
+                WrappedMeasurementValue::U64(sum) => WrappedMeasurementValue::U64((sum as f64 / n).round() as u64),
+            })
+        }
+    }
+}
+
+/// Returns the parent pid and the session id of a process, read from `/proc/<pid>/stat`.
+fn read_parent_and_session(proc_path: &Path, pid: u32) -> anyhow::Result<(u32, u32)> {
+    let path = proc_path.join(pid.to_string()).join("stat");
+    let content = fs::read_to_string(&path).with_context(|| format!("failed to read {path:?}"))?;
+    parse_parent_and_session(&content).with_context(|| format!("invalid content in {path:?}"))
+}
+
+fn parse_parent_and_session(stat: &str) -> Option<(u32, u32)> {
+    // The name of the process is between parentheses and can contain spaces or parentheses: skip it.
+    let (_, rest) = stat.rsplit_once(')')?;
+    let mut fields = rest.split_whitespace();
+    let _state = fields.next()?;
+    let ppid = fields.next()?.parse().ok()?;
+    let _pgrp = fields.next()?;
+    let session = fields.next()?.parse().ok()?;
+    Some((ppid, session))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CPU_TIME: u64 = 0;
+    const MEMORY: u64 = 1;
+    const PERCENT: u64 = 2;
+
+    fn mock_proc(processes: &[(u32, u32, u32)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (pid, ppid, session) in processes {
+            let pid_dir = dir.path().join(pid.to_string());
+            fs::create_dir(&pid_dir).unwrap();
+            let stat =
+                format!("{pid} (my (weird) name) S {ppid} {pid} {session} 0 -1 4194560 105 0 0 0 0 0 0 0 20 0 1");
+            fs::write(pid_dir.join("stat"), stat).unwrap();
+        }
+        dir
+    }
+
+    fn point(metric: u64, pid: u32, value: WrappedMeasurementValue) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::from_unix_timestamp(1000, 0),
+            RawMetricId::from_u64(metric),
+            Resource::LocalMachine,
+            ResourceConsumer::Process { pid },
+            value,
+        )
+    }
+
+    fn transform(proc_dir: &tempfile::TempDir, group_by: ProcessGroup) -> ProcessTreeTransform {
+        ProcessTreeTransform {
+            state: Default::default(),
+            metrics: HashMap::from([
+                (RawMetricId::from_u64(CPU_TIME), MetricAggregation::Delta),
+                (RawMetricId::from_u64(MEMORY), MetricAggregation::Gauge),
+                (RawMetricId::from_u64(PERCENT), MetricAggregation::Mean),
+            ]),
+            group_by,
+            proc_path: proc_dir.path().to_owned(),
+        }
+    }
+
+    /// Returns the aggregated value of the given metric and target process.
+    fn aggregated(transform: &ProcessTreeTransform, metric: u64, target: u32) -> Option<WrappedMeasurementValue> {
+        let state = transform.state.lock().unwrap();
+        let (_, series) = state
+            .series
+            .iter()
+            .find(|(k, _)| k.metric == RawMetricId::from_u64(metric) && k.target == target)?;
+        let values: Vec<&WrappedMeasurementValue> = series.values.values().collect();
+        aggregate(series.aggregation, &values)
+    }
+
+    #[test]
+    fn parse_stat() {
+        let stat = "1234 (bash (1)) S 1000 1234 999 34816 1234 4194560 105 0 0 0";
+        assert_eq!(parse_parent_and_session(stat), Some((1000, 999)));
+        assert_eq!(parse_parent_and_session("1234 (bash) S"), None);
+        assert_eq!(parse_parent_and_session(""), None);
+    }
+
+    #[test]
+    fn session() {
+        let proc_dir = mock_proc(&[(10, 1, 10), (11, 10, 10), (12, 11, 10), (20, 1, 20)]);
+        let transform = transform(&proc_dir, ProcessGroup::Session);
+
+        let mut buf = MeasurementBuffer::new();
+        buf.push(point(CPU_TIME, 10, WrappedMeasurementValue::U64(5)));
+        buf.push(point(CPU_TIME, 11, WrappedMeasurementValue::U64(10)));
+        buf.push(point(CPU_TIME, 11, WrappedMeasurementValue::U64(10)));
+        buf.push(point(CPU_TIME, 12, WrappedMeasurementValue::U64(1)));
+        buf.push(point(CPU_TIME, 20, WrappedMeasurementValue::U64(100)));
+        buf.push(point(MEMORY, 11, WrappedMeasurementValue::U64(1000)));
+        buf.push(point(MEMORY, 11, WrappedMeasurementValue::U64(3000)));
+        buf.push(point(MEMORY, 12, WrappedMeasurementValue::U64(500)));
+        buf.push(point(PERCENT, 11, WrappedMeasurementValue::F64(10.0)));
+        buf.push(point(PERCENT, 12, WrappedMeasurementValue::F64(20.0)));
+        // unknown process
+        buf.push(point(CPU_TIME, 99, WrappedMeasurementValue::U64(1)));
+        // already aggregated
+        buf.push(point(CPU_TIME, 10, WrappedMeasurementValue::U64(1)).with_attr(AGGREGATED_BY_ATTRIBUTE, "session"));
+        transform.accumulate(&buf);
+
+        assert_eq!(
+            aggregated(&transform, CPU_TIME, 10),
+            Some(WrappedMeasurementValue::U64(26))
+        );
+        assert_eq!(
+            aggregated(&transform, CPU_TIME, 20),
+            Some(WrappedMeasurementValue::U64(100))
+        );
+        assert_eq!(
+            aggregated(&transform, MEMORY, 10),
+            Some(WrappedMeasurementValue::U64(3500))
+        );
+        assert_eq!(
+            aggregated(&transform, PERCENT, 10),
+            Some(WrappedMeasurementValue::F64(15.0))
+        );
+        assert_eq!(aggregated(&transform, CPU_TIME, 99), None);
+
+        // next interval: only the processes that are still measured are kept
+        transform.state.lock().unwrap().end_interval();
+        assert_eq!(aggregated(&transform, CPU_TIME, 10), None);
+        let mut buf = MeasurementBuffer::new();
+        buf.push(point(MEMORY, 12, WrappedMeasurementValue::U64(600)));
+        transform.accumulate(&buf);
+        transform.state.lock().unwrap().end_interval();
+        let state = transform.state.lock().unwrap();
+        assert_eq!(state.processes.keys().copied().collect::<Vec<_>>(), vec![12]);
+    }
+
+    #[test]
+    fn parent() {
+        let proc_dir = mock_proc(&[(1, 0, 1), (10, 1, 10), (11, 10, 10), (12, 11, 10), (20, 1, 20)]);
+        let transform = transform(&proc_dir, ProcessGroup::Parent);
+
+        let mut buf = MeasurementBuffer::new();
+        buf.push(point(CPU_TIME, 11, WrappedMeasurementValue::U64(10)).with_attr("kind", "user"));
+        buf.push(point(CPU_TIME, 11, WrappedMeasurementValue::U64(3)).with_attr("kind", "system"));
+        buf.push(point(CPU_TIME, 12, WrappedMeasurementValue::U64(1)).with_attr("kind", "user"));
+        buf.push(point(MEMORY, 10, WrappedMeasurementValue::U64(100)));
+        buf.push(point(MEMORY, 11, WrappedMeasurementValue::U64(20)));
+        buf.push(point(MEMORY, 12, WrappedMeasurementValue::U64(3)));
+        buf.push(point(MEMORY, 20, WrappedMeasurementValue::U64(1000)));
+        transform.accumulate(&buf);
+
+        // each process receives its own measurements and the measurements of its descendants
+        assert_eq!(
+            aggregated(&transform, MEMORY, 1),
+            Some(WrappedMeasurementValue::U64(1123))
+        );
+        assert_eq!(
+            aggregated(&transform, MEMORY, 10),
+            Some(WrappedMeasurementValue::U64(123))
+        );
+        assert_eq!(
+            aggregated(&transform, MEMORY, 11),
+            Some(WrappedMeasurementValue::U64(23))
+        );
+        assert_eq!(
+            aggregated(&transform, MEMORY, 12),
+            Some(WrappedMeasurementValue::U64(3))
+        );
+        assert_eq!(
+            aggregated(&transform, MEMORY, 20),
+            Some(WrappedMeasurementValue::U64(1000))
+        );
+
+        let state = transform.state.lock().unwrap();
+        let mut series: Vec<(u32, String)> = state
+            .series
+            .keys()
+            .filter(|k| k.metric == RawMetricId::from_u64(CPU_TIME))
+            .map(|k| (k.target, k.attributes[0].1.to_string()))
+            .collect();
+        series.sort();
+        let expected = vec![
+            (1, String::from("system")),
+            (1, String::from("user")),
+            (10, String::from("system")),
+            (10, String::from("user")),
+            (11, String::from("system")),
+            (11, String::from("user")),
+            (12, String::from("user")),
+        ];
+        assert_eq!(series, expected);
+    }
+
+    #[test]
+    fn failures_are_cached() {
+        let proc_dir = mock_proc(&[]);
+        let transform = transform(&proc_dir, ProcessGroup::Session);
+
+        let mut buf = MeasurementBuffer::new();
+        buf.push(point(CPU_TIME, 10, WrappedMeasurementValue::U64(5)));
+        transform.accumulate(&buf);
+        assert_eq!(aggregated(&transform, CPU_TIME, 10), None);
+
+        // the failure is kept until the end of the interval
+        let proc_dir_path = proc_dir.path().join("10");
+        fs::create_dir(&proc_dir_path).unwrap();
+        fs::write(proc_dir_path.join("stat"), "10 (sh) S 1 10 10 0 -1").unwrap();
+        transform.accumulate(&buf);
+        assert_eq!(aggregated(&transform, CPU_TIME, 10), None);
+
+        transform.state.lock().unwrap().end_interval();
+        transform.accumulate(&buf);
+        assert_eq!(
+            aggregated(&transform, CPU_TIME, 10),
+            Some(WrappedMeasurementValue::U64(5))
+        );
+    }
+}
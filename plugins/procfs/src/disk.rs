@@ -0,0 +1,343 @@
+//! Block device I/O statistics read from `/proc/diskstats`.
+//!
+//! See <https://docs.kernel.org/admin-guide/iostats.html>.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, Seek},
+    path::{Path, PathBuf},
+};
+
+use alumet::{
+    measurement::{MeasurementAccumulator, MeasurementPoint, Timestamp},
+    metrics::{TypedMetricId, error::MetricCreationError},
+    pipeline::{Source, elements::error::PollError},
+    plugin::AlumetPluginStart,
+    resources::{Resource, ResourceConsumer},
+    units::{PrefixedUnit, Unit},
+};
+use anyhow::{Context, anyhow};
+use regex::Regex;
+
+pub const PROC_DISKSTATS_PATH: &str = "/proc/diskstats";
+pub const SYS_BLOCK_PATH: &str = "/sys/block";
+
+/// The sectors of /proc/diskstats are always 512 bytes long, regardless of the actual sector size of the device.
+const SECTOR_SIZE: u64 = 512;
+
+/// Reads the I/O statistics of each block device from /proc/diskstats.
+pub struct DiskStatsProbe {
+    /// A reader opened to /proc/diskstats.
+    reader: BufReader<File>,
+    /// The devices whose name matches this regex are ignored.
+    exclude: Option<Regex>,
+    /// If set, the devices that are not listed in this directory (normally /sys/block) are ignored.
+    /// It contains the whole disks but not their partitions.
+    whole_disks_dir: Option<PathBuf>,
+    /// The previously measured stats of each device, to compute the difference.
+    previous: HashMap<String, DiskStats>,
+    metrics: DiskMetrics,
+}
+
+pub struct DiskMetrics {
+    bytes: TypedMetricId<u64>,
+    operations: TypedMetricId<u64>,
+    io_time: TypedMetricId<u64>,
+    busy_time: TypedMetricId<u64>,
+    queue_time: TypedMetricId<u64>,
+}
+
+/// The counters of a line of /proc/diskstats.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct DiskStats {
+    read_ios: u64,
+    read_sectors: u64,
+    read_ms: u64,
+    write_ios: u64,
+    write_sectors: u64,
+    write_ms: u64,
+    /// Time during which the device had I/O requests queued.
+    io_ms: u64,
+    /// Time spent by all the requests in the queue, weighted by the number of requests in flight.
+    weighted_io_ms: u64,
+}
+
+impl DiskMetrics {
+    pub fn new(alumet: &mut AlumetPluginStart) -> Result<Self, MetricCreationError> {
+        Ok(Self {
+            bytes: alumet.create_metric(
+                "disk_bytes",
+                Unit::Byte,
+                "Number of bytes read or written on a block device since the previous measurement",
+            )?,
+            operations: alumet.create_metric(
+                "disk_operations",
+                Unit::Unity,
+                "Number of read or write operations completed on a block device since the previous measurement",
+            )?,
+            io_time: alumet.create_metric(
+                "disk_io_time",
+                PrefixedUnit::milli(Unit::Second),
+                "Time spent by the read or write operations of a block device since the previous measurement",
+            )?,
+            busy_time: alumet.create_metric(
+                "disk_busy_time",
+                PrefixedUnit::milli(Unit::Second),
+                "Time during which a block device had I/O requests in flight since the previous measurement",
+            )?,
+            queue_time: alumet.create_metric(
+                "disk_queue_time",
+                PrefixedUnit::milli(Unit::Second),
+                "Time spent by the I/O requests in the queue of a block device since the previous measurement, weighted by the number of requests in flight",
+            )?,
+        })
+    }
+}
+
+impl DiskStatsProbe {
+    pub fn new(
+        metrics: DiskMetrics,
+        proc_diskstats_path: &str,
+        exclude: Option<Regex>,
+        sys_block_path: Option<&str>,
+    ) -> anyhow::Result<Self> {
+        let file = File::open(proc_diskstats_path).with_context(|| format!("could not open {proc_diskstats_path}"))?;
+        Ok(Self {
+            reader: BufReader::new(file),
+            exclude,
+            whole_disks_dir: sys_block_path.map(PathBuf::from),
+            previous: HashMap::new(),
+            metrics,
+        })
+    }
+}
+
+impl Source for DiskStatsProbe {
+    fn poll(&mut self, acc: &mut MeasurementAccumulator, timestamp: Timestamp) -> Result<(), PollError> {
+        self.reader.rewind()?;
+        // Devices can be added and removed at runtime: only keep the devices that still exist.
+        let mut now = HashMap::with_capacity(self.previous.len());
+        for line in (&mut self.reader).lines() {
+            let line = line.context("could not read line from /proc/diskstats")?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (name, stats) =
+                parse_diskstats_line(&line).ok_or_else(|| anyhow!("invalid line in /proc/diskstats: {line}"))?;
+            if self.exclude.as_ref().is_some_and(|regex| regex.is_match(name)) {
+                continue;
+            }
+            if self
+                .whole_disks_dir
+                .as_ref()
+                .is_some_and(|dir| !is_whole_disk(dir, name))
+            {
+                continue;
+            }
+            // Only push deltas, not the baseline value before the plugin starts
+            if let Some(prev) = self.previous.get(name) {
+                // The counters are reset when a device is removed and added again between two measurements.
+                if let Some(delta) = stats.delta(prev) {
+                    delta.push_measurements(&self.metrics, name, acc, timestamp);
+                }
+            }
+            now.insert(name.to_owned(), stats);
+        }
+        self.previous = now;
+        Ok(())
+    }
+}
+
+impl DiskStats {
+    /// Computes the increase of the counters since `prev`, or `None` if a counter has decreased.
+    fn delta(&self, prev: &DiskStats) -> Option<DiskStats> {
+        Some(DiskStats {
+            read_ios: self.read_ios.checked_sub(prev.read_ios)?,
+            read_sectors: self.read_sectors.checked_sub(prev.read_sectors)?,
+            read_ms: self.read_ms.checked_sub(prev.read_ms)?,
+            write_ios: self.write_ios.checked_sub(prev.write_ios)?,
+            write_sectors: self.write_sectors.checked_sub(prev.write_sectors)?,
+            write_ms: self.write_ms.checked_sub(prev.write_ms)?,
+            io_ms: self.io_ms.checked_sub(prev.io_ms)?,
+            weighted_io_ms: self.weighted_io_ms.checked_sub(prev.weighted_io_ms)?,
+        })
+    }
+
+    fn push_measurements(
+        &self,
+        metrics: &DiskMetrics,
+        device: &str,
+        acc: &mut MeasurementAccumulator,
+        timestamp: Timestamp,
+    ) {
+        let res = Resource::BlockDevice {
+            name: device.to_owned().into(),
+        };
+        let cons = ResourceConsumer::LocalMachine;
+        let per_direction = [
+            (
+                metrics.bytes,
+                self.read_sectors * SECTOR_SIZE,
+                self.write_sectors * SECTOR_SIZE,
+            ),
+            (metrics.operations, self.read_ios, self.write_ios),
+            (metrics.io_time, self.read_ms, self.write_ms),
+        ];
+        for (metric, read, write) in per_direction {
+            acc.push(
+                MeasurementPoint::new(timestamp, metric, res.clone(), cons.clone(), read)
+                    .with_attr("direction", "read"),
+            );
+            acc.push(
+                MeasurementPoint::new(timestamp, metric, res.clone(), cons.clone(), write)
+                    .with_attr("direction", "write"),
+            );
+        }
+        acc.push(MeasurementPoint::new(
+            timestamp,
+            metrics.busy_time,
+            res.clone(),
+            cons.clone(),
+            self.io_ms,
+        ));
+        acc.push(MeasurementPoint::new(
+            timestamp,
+            metrics.queue_time,
+            res,
+            cons,
+            self.weighted_io_ms,
+        ));
+    }
+}
+
+/// Checks whether the block device `name` is a whole disk, i.e. not a partition, by looking for it in `sys_block_dir`.
+///
+/// In /sys/block, the slashes of the device names are replaced by `!` (e.g. `cciss/c0d0` becomes `cciss!c0d0`).
+fn is_whole_disk(sys_block_dir: &Path, name: &str) -> bool {
+    sys_block_dir.join(name.replace('/', "!")).exists()
+}
+
+/// Parses a line of /proc/diskstats and returns the name of the device and its counters.
+///
+/// # Input format
+/// ```text
+///    8       0 sda 4781 1569 472446 2052 7563 3456 294408 9432 0 7400 12164 0 0 0 0 1038 680
+/// ```
+///
+/// The first 14 fields have been there since Linux 2.6, the others (discards and flushes) are ignored.
+fn parse_diskstats_line(line: &str) -> Option<(&str, DiskStats)> {
+    let mut fields = line.split_ascii_whitespace();
+    let _major = fields.next()?;
+    let _minor = fields.next()?;
+    let name = fields.next()?;
+    let mut next = || -> Option<u64> { fields.next()?.parse().ok() };
+    let read_ios = next()?;
+    let _read_merges = next()?;
+    let read_sectors = next()?;
+    let read_ms = next()?;
+    let write_ios = next()?;
+    let _write_merges = next()?;
+    let write_sectors = next()?;
+    let write_ms = next()?;
+    let _in_flight = next()?;
+    let io_ms = next()?;
+    let weighted_io_ms = next()?;
+    Some((
+        name,
+        DiskStats {
+            read_ios,
+            read_sectors,
+            read_ms,
+            write_ios,
+            write_sectors,
+            write_ms,
+            io_ms,
+            weighted_io_ms,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parse_line() {
+        let expected = DiskStats {
+            read_ios: 4781,
+            read_sectors: 472446,
+            read_ms: 2052,
+            write_ios: 7563,
+            write_sectors: 294408,
+            write_ms: 9432,
+            io_ms: 7400,
+            weighted_io_ms: 12164,
+        };
+        // Linux 5.5+, with discards and flushes
+        assert_eq!(
+            parse_diskstats_line(
+                " 259       0 nvme0n1 4781 1569 472446 2052 7563 3456 294408 9432 0 7400 12164 0 0 0 0 1038 680"
+            ),
+            Some(("nvme0n1", expected.clone()))
+        );
+        // old kernels
+        assert_eq!(
+            parse_diskstats_line("   8       1 sda1 4781 1569 472446 2052 7563 3456 294408 9432 0 7400 12164"),
+            Some(("sda1", expected))
+        );
+        assert_eq!(parse_diskstats_line("   8       1 sda1 4781 1569 472446 2052"), None);
+        assert_eq!(
+            parse_diskstats_line("   8       1 sda1 abc 1569 472446 2052 7563 3456 294408 9432 0 7400 12164"),
+            None
+        );
+    }
+
+    #[test]
+    fn whole_disks() -> anyhow::Result<()> {
+        let sys_block = tempfile::tempdir()?;
+        std::fs::create_dir(sys_block.path().join("sda"))?;
+        std::fs::create_dir(sys_block.path().join("nvme0n1"))?;
+        std::fs::create_dir(sys_block.path().join("cciss!c0d0"))?;
+
+        assert!(is_whole_disk(sys_block.path(), "sda"));
+        assert!(is_whole_disk(sys_block.path(), "nvme0n1"));
+        assert!(is_whole_disk(sys_block.path(), "cciss/c0d0"));
+        assert!(!is_whole_disk(sys_block.path(), "sda1"));
+        assert!(!is_whole_disk(sys_block.path(), "nvme0n1p1"));
+        assert!(!is_whole_disk(sys_block.path(), "cciss/c0d0p1"));
+        Ok(())
+    }
+
+    #[test]
+    fn delta() {
+        let prev = DiskStats {
+            read_ios: 10,
+            read_sectors: 80,
+            write_ios: 5,
+            write_sectors: 40,
+            io_ms: 100,
+            ..Default::default()
+        };
+        let now = DiskStats {
+            read_ios: 12,
+            read_sectors: 96,
+            write_ios: 5,
+            write_sectors: 40,
+            io_ms: 150,
+            ..Default::default()
+        };
+        assert_eq!(
+            now.delta(&prev),
+            Some(DiskStats {
+                read_ios: 2,
+                read_sectors: 16,
+                io_ms: 50,
+                ..Default::default()
+            })
+        );
+        // the counters have been reset
+        assert_eq!(prev.delta(&now), None);
+    }
+}
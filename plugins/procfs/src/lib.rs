@@ -11,9 +11,11 @@ use anyhow::Context;
 use procfs::{Current, CurrentSI};
 use rlimit::{Resource, getrlimit, setrlimit};
 
+mod disk;
 mod kernel;
 mod memory;
 mod network;
+mod pressure;
 mod process;
 mod serde_regex;
 
@@ -48,12 +50,18 @@ impl AlumetPlugin for ProcfsPlugin {
         if config.kernel.enabled {
             start_kernel_probe(config.kernel, alumet)?;
         }
+        if config.disk.enabled {
+            start_disk_probe(config.disk, alumet)?;
+        }
         if config.memory.enabled {
             start_memory_probe(config.memory, alumet)?;
         }
         if config.network.enabled {
             start_network_probe(config.network, alumet)?;
         }
+        if config.pressure.enabled {
+            start_pressure_probe(config.pressure, alumet)?;
+        }
         if config.processes.enabled {
             let metrics = process::ProcessMetrics {
                 metric_cpu_time_delta: alumet
@@ -101,6 +109,24 @@ fn start_kernel_probe(
     Ok(())
 }
 
+fn start_disk_probe(
+    config_disk: config::DiskMonitoring,
+    alumet: &mut alumet::plugin::AlumetPluginStart<'_>,
+) -> Result<(), anyhow::Error> {
+    let trigger = TriggerSpec::at_interval(config_disk.poll_interval);
+    let metrics = disk::DiskMetrics::new(alumet).context("unable to register metrics for disk probe")?;
+    let sys_block_path = (!config_disk.include_partitions).then_some(disk::SYS_BLOCK_PATH);
+    let source = disk::DiskStatsProbe::new(
+        metrics,
+        disk::PROC_DISKSTATS_PATH,
+        config_disk.exclude_devices,
+        sys_block_path,
+    )
+    .context("unable to create disk probe")?;
+    alumet.add_source("disk", Box::new(source), trigger)?;
+    Ok(())
+}
+
 fn start_network_probe(
     config_network: config::NetworkMonitoring,
     alumet: &mut alumet::plugin::AlumetPluginStart<'_>,
@@ -113,6 +139,25 @@ fn start_network_probe(
     Ok(())
 }
 
+fn start_pressure_probe(
+    config_pressure: config::PressureMonitoring,
+    alumet: &mut alumet::plugin::AlumetPluginStart<'_>,
+) -> Result<(), anyhow::Error> {
+    if let Ok(false) = std::path::Path::new(pressure::PROC_PRESSURE_DIR).try_exists() {
+        log::warn!(
+            "{} does not exist, PSI may be disabled in the kernel (boot parameter psi=1). The pressure will not be measured.",
+            pressure::PROC_PRESSURE_DIR
+        );
+        return Ok(());
+    }
+    let trigger = TriggerSpec::at_interval(config_pressure.poll_interval);
+    let metrics = pressure::PressureMetrics::new(alumet).context("unable to register metrics for pressure probe")?;
+    let source = pressure::PressureProbe::new(metrics, pressure::PROC_PRESSURE_DIR)
+        .context("unable to create pressure probe")?;
+    alumet.add_source("pressure", Box::new(source), trigger)?;
+    Ok(())
+}
+
 fn start_memory_probe(
     config_memory: config::MeminfoMonitoring,
     alumet: &mut alumet::plugin::AlumetPluginStart<'_>,
@@ -228,8 +273,12 @@ mod config {
     #[serde(deny_unknown_fields)]
     pub struct Config {
         pub kernel: KernelStatsMonitoring,
+        #[serde(default)]
+        pub disk: DiskMonitoring,
         pub memory: MeminfoMonitoring,
         pub network: NetworkMonitoring,
+        #[serde(default)]
+        pub pressure: PressureMonitoring,
         pub processes: ProcessMonitoring,
     }
 
@@ -241,6 +290,20 @@ mod config {
         pub poll_interval: Duration,
     }
 
+    #[derive(Serialize, Deserialize)]
+    pub struct DiskMonitoring {
+        #[serde(default = "default_enabled")]
+        pub enabled: bool,
+        #[serde(with = "humantime_serde")]
+        pub poll_interval: Duration,
+        /// Ignore the block devices whose name matches this regex.
+        #[serde(with = "serde_regex::option", default)]
+        pub exclude_devices: Option<Regex>,
+        /// Also measure the partitions, whose I/O is already counted in their disk.
+        #[serde(default)]
+        pub include_partitions: bool,
+    }
+
     #[derive(Serialize, Deserialize)]
     pub struct NetworkMonitoring {
         #[serde(default = "default_enabled")]
@@ -249,6 +312,14 @@ mod config {
         pub poll_interval: Duration,
     }
 
+    #[derive(Serialize, Deserialize)]
+    pub struct PressureMonitoring {
+        #[serde(default = "default_enabled")]
+        pub enabled: bool,
+        #[serde(with = "humantime_serde")]
+        pub poll_interval: Duration,
+    }
+
     #[derive(Serialize, Deserialize)]
     pub struct MeminfoMonitoring {
         #[serde(default = "default_enabled")]
@@ -328,6 +399,18 @@ mod config {
         }
     }
 
+    impl Default for DiskMonitoring {
+        fn default() -> Self {
+            Self {
+                enabled: true,
+                poll_interval: Duration::from_secs(5),
+                // loop and ram devices are not real disks
+                exclude_devices: Some(Regex::new(r"^(loop|ram)\d+$").unwrap()),
+                include_partitions: false,
+            }
+        }
+    }
+
     impl Default for NetworkMonitoring {
         fn default() -> Self {
             Self {
@@ -337,6 +420,15 @@ mod config {
         }
     }
 
+    impl Default for PressureMonitoring {
+        fn default() -> Self {
+            Self {
+                enabled: true,
+                poll_interval: Duration::from_secs(5),
+            }
+        }
+    }
+
     impl Default for MeminfoMonitoring {
         fn default() -> Self {
             Self {
@@ -0,0 +1,200 @@
+//! System-level Pressure Stall Information (PSI) read from `/proc/pressure`.
+//!
+//! See <https://docs.kernel.org/accounting/psi.html>.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Seek},
+    path::Path,
+};
+
+use alumet::{
+    measurement::{MeasurementAccumulator, MeasurementPoint, Timestamp},
+    metrics::{TypedMetricId, error::MetricCreationError},
+    pipeline::{Source, elements::error::PollError},
+    plugin::{AlumetPluginStart, util::CounterDiff},
+    resources::{Resource, ResourceConsumer},
+    units::{PrefixedUnit, Unit},
+};
+use anyhow::{Context, anyhow};
+
+pub const PROC_PRESSURE_DIR: &str = "/proc/pressure";
+
+/// Reads the PSI files `cpu`, `memory` and `io`.
+pub struct PressureProbe {
+    files: Vec<PressureFile>,
+    metrics: PressureMetrics,
+}
+
+pub struct PressureMetrics {
+    pub stall_avg: TypedMetricId<f64>,
+    pub stall_time_delta: TypedMetricId<u64>,
+}
+
+/// A PSI file, like `/proc/pressure/cpu`.
+struct PressureFile {
+    /// Name of the resource, like `cpu`.
+    pressure: &'static str,
+    reader: BufReader<File>,
+    /// Total stall time of the line `some`.
+    some: CounterDiff,
+    /// Total stall time of the line `full`.
+    full: CounterDiff,
+}
+
+/// A line of a PSI file.
+#[derive(Debug, PartialEq)]
+struct PressureLine<'a> {
+    /// `some` or `full`.
+    stall: &'a str,
+    avg10: f64,
+    avg60: f64,
+    avg300: f64,
+    /// Total stall time, in microseconds.
+    total: u64,
+}
+
+impl PressureMetrics {
+    pub fn new(alumet: &mut AlumetPluginStart) -> Result<Self, MetricCreationError> {
+        Ok(Self {
+            stall_avg: alumet.create_metric(
+                "pressure_stall_avg",
+                Unit::Percent,
+                "Share of time in which some (or all) tasks were stalled on a resource, averaged over a time window",
+            )?,
+            stall_time_delta: alumet.create_metric(
+                "pressure_stall_time_delta",
+                PrefixedUnit::micro(Unit::Second),
+                "Time in which some (or all) tasks were stalled on a resource since the previous measurement",
+            )?,
+        })
+    }
+}
+
+impl PressureProbe {
+    pub fn new(metrics: PressureMetrics, proc_pressure_dir: &str) -> anyhow::Result<Self> {
+        let dir = Path::new(proc_pressure_dir);
+        let files = ["cpu", "memory", "io"]
+            .into_iter()
+            .map(|pressure| {
+                let path = dir.join(pressure);
+                let file = File::open(&path).with_context(|| format!("could not open {}", path.display()))?;
+                Ok(PressureFile {
+                    pressure,
+                    reader: BufReader::new(file),
+                    some: CounterDiff::with_max_value(u64::MAX),
+                    full: CounterDiff::with_max_value(u64::MAX),
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self { files, metrics })
+    }
+}
+
+impl Source for PressureProbe {
+    fn poll(&mut self, measurements: &mut MeasurementAccumulator, timestamp: Timestamp) -> Result<(), PollError> {
+        for file in &mut self.files {
+            file.reader.rewind()?;
+            for line in (&mut file.reader).lines() {
+                let line =
+                    line.with_context(|| format!("could not read line from /proc/pressure/{}", file.pressure))?;
+                if line.is_empty() {
+                    continue;
+                }
+                let parsed = parse_pressure_line(&line)
+                    .with_context(|| format!("invalid line in /proc/pressure/{}: {line}", file.pressure))?;
+                let (stall, counter) = match parsed.stall {
+                    "some" => ("some", &mut file.some),
+                    "full" => ("full", &mut file.full),
+                    other => return Err(anyhow!("unknown kind of stall {other}").into()),
+                };
+                let stall_time_delta = counter.update(parsed.total).difference();
+
+                for (window, avg) in [("10s", parsed.avg10), ("60s", parsed.avg60), ("300s", parsed.avg300)] {
+                    measurements.push(
+                        MeasurementPoint::new(
+                            timestamp,
+                            self.metrics.stall_avg,
+                            Resource::LocalMachine,
+                            ResourceConsumer::LocalMachine,
+                            avg,
+                        )
+                        .with_attr("pressure", file.pressure)
+                        .with_attr("stall", stall)
+                        .with_attr("window", window),
+                    );
+                }
+                if let Some(delta) = stall_time_delta {
+                    measurements.push(
+                        MeasurementPoint::new(
+                            timestamp,
+                            self.metrics.stall_time_delta,
+                            Resource::LocalMachine,
+                            ResourceConsumer::LocalMachine,
+                            delta,
+                        )
+                        .with_attr("pressure", file.pressure)
+                        .with_attr("stall", stall),
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses a line like `some avg10=0.00 avg60=0.00 avg300=0.00 total=0`.
+fn parse_pressure_line(line: &str) -> Option<PressureLine<'_>> {
+    let mut fields = line.split_ascii_whitespace();
+    let stall = fields.next()?;
+    let mut value = |key: &str| {
+        let (k, v) = fields.next()?.split_once('=')?;
+        (k == key).then_some(v)
+    };
+    Some(PressureLine {
+        avg10: value("avg10")?.parse().ok()?,
+        avg60: value("avg60")?.parse().ok()?,
+        avg300: value("avg300")?.parse().ok()?,
+        total: value("total")?.parse().ok()?,
+        stall,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parse_line() {
+        assert_eq!(
+            parse_pressure_line("some avg10=1.53 avg60=0.87 avg300=0.22 total=58761459"),
+            Some(PressureLine {
+                stall: "some",
+                avg10: 1.53,
+                avg60: 0.87,
+                avg300: 0.22,
+                total: 58761459,
+            })
+        );
+        assert_eq!(
+            parse_pressure_line("full avg10=0.00 avg60=0.00 avg300=0.00 total=0"),
+            Some(PressureLine {
+                stall: "full",
+                avg10: 0.0,
+                avg60: 0.0,
+                avg300: 0.0,
+                total: 0,
+            })
+        );
+        assert_eq!(parse_pressure_line("some avg10=1.53 avg60=0.87 avg300=0.22"), None);
+        assert_eq!(
+            parse_pressure_line("some avg60=0.87 avg10=1.53 avg300=0.22 total=0"),
+            None
+        );
+        assert_eq!(
+            parse_pressure_line("some avg10=abc avg60=0.87 avg300=0.22 total=0"),
+            None
+        );
+    }
+}
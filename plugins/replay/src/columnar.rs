@@ -0,0 +1,371 @@
+//! Reads the Parquet and Arrow IPC files produced by the `parquet` output.
+
+use std::{
+    fs::File,
+    path::Path,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use alumet::{
+    measurement::{AttributeValue, WrappedMeasurementValue},
+    resources::Resource,
+};
+use anyhow::{Context, anyhow};
+use arrow::{
+    array::{
+        Array, AsArray, BooleanArray, Float64Array, ListArray, RecordBatch, StringArray, StructArray, UInt64Array,
+    },
+    datatypes::{Float64Type, TimestampNanosecondType, UInt64Type},
+    ipc::reader::FileReader,
+};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use rustc_hash::FxHashMap;
+use serde::Deserialize;
+
+use crate::record::{RecordedPoint, Recording, parse_consumer, parse_unit_or_custom};
+
+/// Key of the file metadata that describes the metrics, in JSON (see the `parquet` output).
+const METRICS_METADATA_KEY: &str = "alumet.metrics";
+
+/// Description of the metrics, by name and unit.
+type Descriptions = FxHashMap<(String, String), String>;
+
+#[derive(Deserialize)]
+struct MetricMetadata {
+    name: String,
+    unit: String,
+    description: String,
+}
+
+pub fn read_parquet(path: &Path, recording: &mut Recording) -> anyhow::Result<()> {
+    let file = File::open(path).with_context(|| format!("failed to open {path:?}"))?;
+    let builder = ParquetRecordBatchReaderBuilder::try_new(file).with_context(|| format!("invalid file {path:?}"))?;
+    let metadata = builder
+        .metadata()
+        .file_metadata()
+        .key_value_metadata()
+        .and_then(|kv| kv.iter().find(|kv| kv.key == METRICS_METADATA_KEY))
+        .and_then(|kv| kv.value.clone());
+    let descriptions = parse_descriptions(metadata.as_deref())?;
+    for batch in builder.build()? {
+        read_batch(&batch?, &descriptions, recording).with_context(|| format!("invalid file {path:?}"))?;
+    }
+    Ok(())
+}
+
+pub fn read_arrow_ipc(path: &Path, recording: &mut Recording) -> anyhow::Result<()> {
+    let file = File::open(path).with_context(|| format!("failed to open {path:?}"))?;
+    let reader = FileReader::try_new(file, None).with_context(|| format!("invalid file {path:?}"))?;
+    let metadata = reader.custom_metadata().get(METRICS_METADATA_KEY);
+    let descriptions = parse_descriptions(metadata.map(String::as_str))?;
+    for batch in reader {
+        read_batch(&batch?, &descriptions, recording).with_context(|| format!("invalid file {path:?}"))?;
+    }
+    Ok(())
+}
+
+fn parse_descriptions(metadata: Option<&str>) -> anyhow::Result<Descriptions> {
+    let Some(metadata) = metadata else {
+        // The metadata is written when the file is closed: it is missing if the agent has been killed.
+        log::warn!("The file contains no description of the metrics.");
+        return Ok(Descriptions::default());
+    };
+    let metrics: Vec<MetricMetadata> =
+        serde_json::from_str(metadata).context("invalid description of the metrics in the file metadata")?;
+    Ok(metrics.into_iter().map(|m| ((m.name, m.unit), m.description)).collect())
+}
+
+fn string_column<'a>(batch: &'a RecordBatch, name: &str) -> anyhow::Result<&'a StringArray> {
+    batch
+        .column_by_name(name)
+        .and_then(|c| c.as_string_opt::<i32>())
+        .with_context(|| format!("missing or invalid column {name:?}"))
+}
+
+/// Values of the attributes: a struct with one nullable field per type (see the `parquet` output).
+struct AttributeValues<'a> {
+    u64: &'a UInt64Array,
+    f64: &'a Float64Array,
+    bool: &'a BooleanArray,
+    string: &'a StringArray,
+    list_u64: &'a ListArray,
+}
+
+impl<'a> AttributeValues<'a> {
+    fn new(values: &'a StructArray) -> anyhow::Result<Self> {
+        let field = |name: &str| {
+            values
+                .column_by_name(name)
+                .with_context(|| format!("missing attribute field \"{name}\""))
+        };
+        let invalid = |name: &str| format!("invalid attribute field \"{name}\"");
+        Ok(Self {
+            u64: field("u64")?
+                .as_primitive_opt::<UInt64Type>()
+                .with_context(|| invalid("u64"))?,
+            f64: field("f64")?
+                .as_primitive_opt::<Float64Type>()
+                .with_context(|| invalid("f64"))?,
+            bool: field("bool")?.as_boolean_opt().with_context(|| invalid("bool"))?,
+            string: field("string")?
+                .as_string_opt::<i32>()
+                .with_context(|| invalid("string"))?,
+            list_u64: field("list_u64")?
+                .as_list_opt::<i32>()
+                .with_context(|| invalid("list_u64"))?,
+        })
+    }
+
+    /// Returns the value at index `j`, or `None` if all the fields are null.
+    fn value(&self, j: usize) -> Option<AttributeValue> {
+        if self.u64.is_valid(j) {
+            Some(AttributeValue::U64(self.u64.value(j)))
+        } else if self.f64.is_valid(j) {
+            Some(AttributeValue::F64(self.f64.value(j)))
+        } else if self.bool.is_valid(j) {
+            Some(AttributeValue::Bool(self.bool.value(j)))
+        } else if self.string.is_valid(j) {
+            Some(AttributeValue::String(self.string.value(j).to_owned()))
+        } else if self.list_u64.is_valid(j) {
+            let list = self.list_u64.value(j);
+            let items = list.as_primitive_opt::<UInt64Type>()?;
+            Some(AttributeValue::ListU64(items.iter().flatten().collect()))
+        } else {
+            None
+        }
+    }
+}
+
+fn read_batch(batch: &RecordBatch, descriptions: &Descriptions, recording: &mut Recording) -> anyhow::Result<()> {
+    let timestamps = batch
+        .column_by_name("timestamp")
+        .and_then(|c| c.as_primitive_opt::<TimestampNanosecondType>())
+        .context("missing or invalid column \"timestamp\"")?;
+    let metrics = string_column(batch, "metric")?;
+    let units = string_column(batch, "unit")?;
+    let values_u64 = batch
+        .column_by_name("value_u64")
+        .and_then(|c| c.as_primitive_opt::<UInt64Type>())
+        .context("missing or invalid column \"value_u64\"")?;
+    let values_f64 = batch
+        .column_by_name("value_f64")
+        .and_then(|c| c.as_primitive_opt::<Float64Type>())
+        .context("missing or invalid column \"value_f64\"")?;
+    let resource_kinds = string_column(batch, "resource_kind")?;
+    let resource_ids = string_column(batch, "resource_id")?;
+    let consumer_kinds = string_column(batch, "consumer_kind")?;
+    let consumer_ids = string_column(batch, "consumer_id")?;
+    let attributes = match batch.column_by_name("attributes") {
+        Some(c) => {
+            let map = c.as_map_opt().context("invalid column \"attributes\"")?;
+            let keys = map.keys().as_string_opt::<i32>().context("invalid attribute keys")?;
+            let values = map.values().as_struct_opt().context("invalid attribute values")?;
+            Some((map.value_offsets(), keys, AttributeValues::new(values)?))
+        }
+        None => None,
+    };
+
+    for i in 0..batch.num_rows() {
+        // negative before the UNIX epoch
+        let timestamp = timestamps.value(i);
+        let timestamp = if timestamp >= 0 {
+            UNIX_EPOCH + Duration::from_nanos(timestamp as u64)
+        } else {
+            UNIX_EPOCH - Duration::from_nanos(timestamp.unsigned_abs())
+        };
+        let value = if values_u64.is_valid(i) {
+            WrappedMeasurementValue::U64(values_u64.value(i))
+        } else if values_f64.is_valid(i) {
+            WrappedMeasurementValue::F64(values_f64.value(i))
+        } else {
+            return Err(anyhow!("row {i}: no value"));
+        };
+        let name = metrics.value(i);
+        let unit = units.value(i);
+        let description = descriptions.get(&(name.to_owned(), unit.to_owned()));
+        let metric = recording.metric_index(name, parse_unit_or_custom(unit), description.map(String::as_str));
+        let resource = Resource::parse(resource_kinds.value(i).to_owned(), resource_ids.value(i).to_owned())?;
+        let consumer = parse_consumer(consumer_kinds.value(i).to_owned(), consumer_ids.value(i).to_owned())?;
+
+        let mut point_attributes = Vec::new();
+        if let Some((offsets, keys, values)) = &attributes {
+            for j in offsets[i] as usize..offsets[i + 1] as usize {
+                if let Some(value) = values.value(j) {
+                    point_attributes.push((keys.value(j).to_owned(), value));
+                }
+            }
+        }
+
+        recording.push(RecordedPoint {
+            timestamp,
+            metric,
+            value,
+            resource,
+            consumer,
+            attributes: point_attributes,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        sync::Arc,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use alumet::{
+        measurement::{AttributeValue, WrappedMeasurementType, WrappedMeasurementValue},
+        resources::{Resource, ResourceConsumer},
+        units::{PrefixedUnit, Unit},
+    };
+    use arrow::{
+        array::{
+            ArrayBuilder, ArrayRef, BooleanBuilder, Float64Array, Float64Builder, ListBuilder, MapBuilder, RecordBatch,
+            StringArray, StringBuilder, StructBuilder, TimestampNanosecondArray, UInt64Array, UInt64Builder,
+        },
+        datatypes::{DataType, Field, Fields},
+        ipc::writer::FileWriter,
+    };
+    use parquet::arrow::ArrowWriter;
+    use pretty_assertions::assert_eq;
+
+    use super::{METRICS_METADATA_KEY, read_arrow_ipc, read_parquet};
+    use crate::record::Recording;
+
+    const METADATA: &str = r#"[{"id":0,"name":"rapl_consumed_energy","description":"energy consumed","value_type":"F64","unit":"milliJ","unit_display_name":"mJ"}]"#;
+
+    /// Builds a batch like the ones of the `parquet` output.
+    fn test_batch() -> RecordBatch {
+        let fields = Fields::from(vec![
+            Field::new("u64", DataType::UInt64, true),
+            Field::new("f64", DataType::Float64, true),
+            Field::new("bool", DataType::Boolean, true),
+            Field::new("string", DataType::Utf8, true),
+            Field::new_list("list_u64", Field::new_list_field(DataType::UInt64, true), true),
+        ]);
+        let builders: Vec<Box<dyn ArrayBuilder>> = vec![
+            Box::new(UInt64Builder::new()),
+            Box::new(Float64Builder::new()),
+            Box::new(BooleanBuilder::new()),
+            Box::new(StringBuilder::new()),
+            Box::new(ListBuilder::new(UInt64Builder::new())),
+        ];
+        let mut attributes = MapBuilder::new(None, StringBuilder::new(), StructBuilder::new(fields, builders));
+        // "domain": "package", "core": 3
+        for (key, u64_value, string_value) in [("domain", None, Some("package")), ("core", Some(3), None)] {
+            attributes.keys().append_value(key);
+            let values = attributes.values();
+            values
+                .field_builder::<UInt64Builder>(0)
+                .unwrap()
+                .append_option(u64_value);
+            values.field_builder::<Float64Builder>(1).unwrap().append_null();
+            values.field_builder::<BooleanBuilder>(2).unwrap().append_null();
+            values
+                .field_builder::<StringBuilder>(3)
+                .unwrap()
+                .append_option(string_value);
+            values
+                .field_builder::<ListBuilder<UInt64Builder>>(4)
+                .unwrap()
+                .append(false);
+            values.append(true);
+        }
+        attributes.append(true).unwrap();
+        attributes.append(true).unwrap();
+
+        let columns: Vec<(&str, ArrayRef)> = vec![
+            (
+                "timestamp",
+                Arc::new(TimestampNanosecondArray::from(vec![2_000_000_000, 1_000_000_000]).with_timezone("UTC")),
+            ),
+            (
+                "metric",
+                Arc::new(StringArray::from(vec!["rapl_consumed_energy", "count"])),
+            ),
+            ("unit", Arc::new(StringArray::from(vec!["milliJ", "1"]))),
+            ("value_u64", Arc::new(UInt64Array::from(vec![None, Some(7)]))),
+            ("value_f64", Arc::new(Float64Array::from(vec![Some(12.5), None]))),
+            (
+                "resource_kind",
+                Arc::new(StringArray::from(vec!["cpu_package", "local_machine"])),
+            ),
+            ("resource_id", Arc::new(StringArray::from(vec!["0", ""]))),
+            (
+                "consumer_kind",
+                Arc::new(StringArray::from(vec!["local_machine", "process"])),
+            ),
+            ("consumer_id", Arc::new(StringArray::from(vec!["", "42"]))),
+            ("attributes", Arc::new(attributes.finish())),
+        ];
+        RecordBatch::try_from_iter(columns).unwrap()
+    }
+
+    fn check_recording(mut rec: Recording) {
+        rec.finish();
+        assert_eq!(rec.metrics.len(), 2);
+        let energy = &rec.metrics[0];
+        assert_eq!(energy.name, "rapl_consumed_energy");
+        assert_eq!(energy.unit, PrefixedUnit::milli(Unit::Joule));
+        assert_eq!(energy.value_type, WrappedMeasurementType::F64);
+        assert_eq!(energy.description, "energy consumed");
+        let count = &rec.metrics[1];
+        assert_eq!(count.unit, PrefixedUnit::from(Unit::Unity));
+        assert_eq!(count.value_type, WrappedMeasurementType::U64);
+
+        // sorted by timestamp
+        let p = &rec.points[0];
+        assert_eq!(p.timestamp, UNIX_EPOCH + Duration::from_secs(1));
+        assert_eq!(p.value, WrappedMeasurementValue::U64(7));
+        assert_eq!(p.consumer, ResourceConsumer::Process { pid: 42 });
+        assert!(p.attributes.is_empty());
+        let p = &rec.points[1];
+        assert_eq!(p.value, WrappedMeasurementValue::F64(12.5));
+        assert_eq!(p.resource, Resource::CpuPackage { id: 0 });
+        assert_eq!(
+            p.attributes,
+            vec![
+                (String::from("domain"), AttributeValue::String(String::from("package"))),
+                (String::from("core"), AttributeValue::U64(3)),
+            ]
+        );
+    }
+
+    #[test]
+    fn parquet() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("test.parquet");
+        let batch = test_batch();
+        let mut writer = ArrowWriter::try_new(File::create(&path)?, batch.schema(), None)?;
+        writer.write(&batch)?;
+        writer.append_key_value_metadata(parquet::file::metadata::KeyValue::new(
+            METRICS_METADATA_KEY.to_string(),
+            METADATA.to_string(),
+        ));
+        writer.close()?;
+
+        let mut rec = Recording::default();
+        read_parquet(&path, &mut rec)?;
+        check_recording(rec);
+        Ok(())
+    }
+
+    #[test]
+    fn arrow_ipc() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("test.arrow");
+        let batch = test_batch();
+        let mut writer = FileWriter::try_new(File::create(&path)?, &batch.schema())?;
+        writer.write(&batch)?;
+        writer.write_metadata(METRICS_METADATA_KEY, METADATA);
+        writer.finish()?;
+
+        let mut rec = Recording::default();
+        read_arrow_ipc(&path, &mut rec)?;
+        check_recording(rec);
+        Ok(())
+    }
+}
@@ -0,0 +1,426 @@
+//! Reads the files produced by the `csv` output.
+
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+    time::SystemTime,
+};
+
+use alumet::{
+    resources::Resource,
+    units::{PrefixedUnit, Unit},
+};
+use anyhow::{Context, anyhow};
+use rustc_hash::FxHashMap;
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+
+use crate::record::{RecordedPoint, Recording, parse_attribute, parse_consumer, parse_value, split_metric_unit};
+
+const LATE_ATTRIBUTES_COLUMN: &str = "__late_attributes";
+
+#[derive(Clone)]
+pub struct CsvSettings {
+    pub delimiter: char,
+    pub late_delimiter: char,
+    /// Does the metric column contain the unit, like `rapl_consumed_energy_J`?
+    pub unit_in_metric_name: bool,
+}
+
+/// Reads a CSV file, which can be compressed with gzip (`.gz`) or zstd (`.zst`).
+pub fn read_file(path: &Path, settings: &CsvSettings, recording: &mut Recording) -> anyhow::Result<()> {
+    let file = BufReader::new(File::open(path).with_context(|| format!("failed to open {path:?}"))?);
+    let mut reader: Box<dyn Read> = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("gz") => Box::new(flate2::read::MultiGzDecoder::new(file)),
+        Some("zst") => Box::new(zstd::Decoder::with_buffer(file)?),
+        _ => Box::new(file),
+    };
+    let mut content = String::new();
+    reader
+        .read_to_string(&mut content)
+        .with_context(|| format!("failed to read {path:?}"))?;
+    read_str(&content, settings, recording).with_context(|| format!("invalid CSV file {path:?}"))
+}
+
+/// Columns of a CSV file.
+struct Columns {
+    metric: usize,
+    timestamp: usize,
+    value: usize,
+    resource_kind: usize,
+    resource_id: usize,
+    consumer_kind: usize,
+    consumer_id: usize,
+    late_attributes: Option<usize>,
+    /// Columns that contain one attribute each: (index, attribute key)
+    attributes: Vec<(usize, String)>,
+}
+
+impl Columns {
+    fn from_header(header: Vec<String>) -> anyhow::Result<Self> {
+        let find = |name: &str| {
+            header
+                .iter()
+                .position(|c| c == name)
+                .with_context(|| format!("missing column {name:?}"))
+        };
+        let metric = find("metric")?;
+        let timestamp = find("timestamp")?;
+        let value = find("value")?;
+        let resource_kind = find("resource_kind")?;
+        let resource_id = find("resource_id")?;
+        let consumer_kind = find("consumer_kind")?;
+        let consumer_id = find("consumer_id")?;
+        let base = [
+            metric,
+            timestamp,
+            value,
+            resource_kind,
+            resource_id,
+            consumer_kind,
+            consumer_id,
+        ];
+        let late_attributes = header.iter().position(|c| c == LATE_ATTRIBUTES_COLUMN);
+        let attributes = header
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| !base.contains(i) && Some(*i) != late_attributes)
+            .collect();
+        Ok(Self {
+            metric,
+            timestamp,
+            value,
+            resource_kind,
+            resource_id,
+            consumer_kind,
+            consumer_id,
+            late_attributes,
+            attributes,
+        })
+    }
+}
+
+fn read_str(content: &str, settings: &CsvSettings, recording: &mut Recording) -> anyhow::Result<()> {
+    // A file can contain several headers, for instance if the csv output has been restarted with other settings.
+    let mut header: Option<(Columns, usize)> = None;
+    // Index of each metric, by content of the metric column.
+    let mut metrics: FxHashMap<String, usize> = FxHashMap::default();
+
+    for record in Records::new(content, settings.delimiter) {
+        let (line, fields) = record?;
+        let is_header = fields.iter().any(|f| f == "metric") && fields.iter().any(|f| f == "timestamp");
+        if is_header {
+            let n = fields.len();
+            header = Some((Columns::from_header(fields).with_context(|| format!("line {line}"))?, n));
+            continue;
+        }
+        let (columns, n_columns) = header.as_ref().context("missing header")?;
+        if fields.len() != *n_columns {
+            return Err(anyhow!(
+                "line {line}: expected {n_columns} fields, found {}",
+                fields.len()
+            ));
+        }
+        let point =
+            parse_point(fields, columns, settings, &mut metrics, recording).with_context(|| format!("line {line}"))?;
+        recording.push(point);
+    }
+    Ok(())
+}
+
+fn parse_point(
+    mut fields: Vec<String>,
+    columns: &Columns,
+    settings: &CsvSettings,
+    metrics: &mut FxHashMap<String, usize>,
+    recording: &mut Recording,
+) -> anyhow::Result<RecordedPoint> {
+    let metric_column = &fields[columns.metric];
+    let metric = match metrics.get(metric_column) {
+        Some(i) => *i,
+        None => {
+            let (name, unit) = if settings.unit_in_metric_name {
+                split_metric_unit(metric_column)
+            } else {
+                (metric_column.as_str(), PrefixedUnit::from(Unit::Unity))
+            };
+            let i = recording.metric_index(name, unit, None);
+            metrics.insert(metric_column.to_owned(), i);
+            i
+        }
+    };
+
+    let timestamp = OffsetDateTime::parse(&fields[columns.timestamp], &Rfc3339)
+        .with_context(|| format!("invalid timestamp {:?}", fields[columns.timestamp]))?;
+    let value = parse_value(&fields[columns.value])?;
+    let resource = Resource::parse(
+        std::mem::take(&mut fields[columns.resource_kind]),
+        std::mem::take(&mut fields[columns.resource_id]),
+    )?;
+    let consumer = parse_consumer(
+        std::mem::take(&mut fields[columns.consumer_kind]),
+        std::mem::take(&mut fields[columns.consumer_id]),
+    )?;
+
+    let mut attributes = Vec::new();
+    for (i, key) in &columns.attributes {
+        let value = &fields[*i];
+        if !value.is_empty() {
+            attributes.push((key.to_owned(), parse_attribute(value)));
+        }
+    }
+    if let Some(i) = columns.late_attributes {
+        for (key, value) in parse_late_attributes(&fields[i], settings.late_delimiter)? {
+            attributes.push((key, parse_attribute(&value)));
+        }
+    }
+
+    Ok(RecordedPoint {
+        timestamp: SystemTime::from(timestamp),
+        metric,
+        value,
+        resource,
+        consumer,
+        attributes,
+    })
+}
+
+/// Parses the content of the `__late_attributes` column, like `key1=value1,key2=value2`.
+///
+/// The delimiter is escaped with a backslash when it appears in a value.
+fn parse_late_attributes(s: &str, delimiter: char) -> anyhow::Result<Vec<(String, String)>> {
+    let mut res = Vec::new();
+    if s.is_empty() {
+        return Ok(res);
+    }
+    let mut entry = String::new();
+    let mut chars = s.chars().peekable();
+    loop {
+        match chars.next() {
+            Some('\\') if chars.peek().is_some_and(|c| *c == delimiter || *c == '=') => {
+                // Keep the escaped '=' as is, so that it is not used to split the entry.
+                let c = chars.next().unwrap();
+                if c == '=' {
+                    entry.push('\\');
+                }
+                entry.push(c);
+            }
+            Some(c) if c != delimiter => entry.push(c),
+            next => {
+                let (key, value) =
+                    split_late_entry(&entry).with_context(|| format!("invalid late attribute {entry:?}"))?;
+                res.push((key, value));
+                entry.clear();
+                if next.is_none() {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(res)
+}
+
+/// Splits `key=value` at the first unescaped `=`.
+fn split_late_entry(entry: &str) -> Option<(String, String)> {
+    let mut escaped = false;
+    for (i, c) in entry.char_indices() {
+        match c {
+            '\\' => escaped = !escaped,
+            '=' if !escaped => {
+                let key = entry[..i].replace("\\=", "=");
+                let value = entry[i + 1..].replace("\\=", "=");
+                return Some((key, value));
+            }
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+/// Iterator on the records of a CSV file, following RFC 4180.
+///
+/// Each record is returned with the number of its first line.
+struct Records<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    delimiter: char,
+    line: usize,
+}
+
+impl<'a> Records<'a> {
+    fn new(content: &'a str, delimiter: char) -> Self {
+        Self {
+            chars: content.chars().peekable(),
+            delimiter,
+            line: 0,
+        }
+    }
+}
+
+impl Iterator for Records<'_> {
+    type Item = anyhow::Result<(usize, Vec<String>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // skip the empty lines
+        while let Some(c) = self.chars.peek() {
+            match c {
+                '\n' => self.line += 1,
+                '\r' => (),
+                _ => break,
+            }
+            self.chars.next();
+        }
+        self.chars.peek()?;
+
+        self.line += 1;
+        let first_line = self.line;
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        loop {
+            let c = self.chars.next();
+            if quoted {
+                match c {
+                    Some('"') if self.chars.peek() == Some(&'"') => {
+                        self.chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => quoted = false,
+                    Some(c) => {
+                        if c == '\n' {
+                            self.line += 1;
+                        }
+                        field.push(c);
+                    }
+                    None => return Some(Err(anyhow!("line {first_line}: unterminated quoted field"))),
+                }
+            } else {
+                match c {
+                    Some('"') if field.is_empty() => quoted = true,
+                    Some('\r') if self.chars.peek() == Some(&'\n') => (),
+                    Some('\n') | None => {
+                        fields.push(field);
+                        return Some(Ok((first_line, fields)));
+                    }
+                    Some(c) if c == self.delimiter => fields.push(std::mem::take(&mut field)),
+                    Some(c) => field.push(c),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use alumet::{
+        measurement::{AttributeValue, WrappedMeasurementType, WrappedMeasurementValue},
+        resources::{Resource, ResourceConsumer},
+        units::{PrefixedUnit, Unit},
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::{CsvSettings, Records, parse_late_attributes, read_str};
+    use crate::record::Recording;
+
+    fn settings() -> CsvSettings {
+        CsvSettings {
+            delimiter: ';',
+            late_delimiter: ',',
+            unit_in_metric_name: true,
+        }
+    }
+
+    #[test]
+    fn records() {
+        let content = "a;b;c\n1;\"x;y\";\"say \"\"hi\"\"\"\r\n\n2;\"multi\nline\";\n";
+        let records: Vec<_> = Records::new(content, ';').map(|r| r.unwrap()).collect();
+        assert_eq!(
+            records,
+            vec![
+                (1, vec![String::from("a"), String::from("b"), String::from("c")]),
+                (
+                    2,
+                    vec![String::from("1"), String::from("x;y"), String::from("say \"hi\"")]
+                ),
+                (4, vec![String::from("2"), String::from("multi\nline"), String::new()]),
+            ]
+        );
+        assert!(Records::new("a;\"b", ';').next().unwrap().is_err());
+    }
+
+    #[test]
+    fn late_attributes() {
+        assert_eq!(parse_late_attributes("", ',').unwrap(), vec![]);
+        assert_eq!(
+            parse_late_attributes("a=1,b=x\\,y,c=k\\=v", ',').unwrap(),
+            vec![
+                (String::from("a"), String::from("1")),
+                (String::from("b"), String::from("x,y")),
+                (String::from("c"), String::from("k=v")),
+            ]
+        );
+        assert!(parse_late_attributes("novalue", ',').is_err());
+    }
+
+    #[test]
+    fn read_csv_output() -> anyhow::Result<()> {
+        let content = "\
+metric;timestamp;value;resource_kind;resource_id;consumer_kind;consumer_id;domain;__late_attributes
+rapl_consumed_energy_mJ;1970-01-01T00:00:01Z;12.5;cpu_package;0;local_machine;;package;
+kernel_new_forks;1970-01-01T00:00:01.5Z;3;local_machine;;process;42;;job_id=7,name=a\\,b
+rapl_consumed_energy_mJ;1970-01-01T00:00:02Z;13;cpu_package;0;local_machine;;package;
+";
+        let mut rec = Recording::default();
+        read_str(content, &settings(), &mut rec)?;
+        rec.finish();
+
+        assert_eq!(rec.metrics.len(), 2);
+        assert_eq!(rec.metrics[0].name, "rapl_consumed_energy");
+        assert_eq!(rec.metrics[0].unit, PrefixedUnit::milli(Unit::Joule));
+        assert_eq!(rec.metrics[0].value_type, WrappedMeasurementType::F64);
+        assert_eq!(rec.metrics[1].name, "kernel_new_forks");
+        assert_eq!(rec.metrics[1].unit, PrefixedUnit::from(Unit::Unity));
+        assert_eq!(rec.metrics[1].value_type, WrappedMeasurementType::U64);
+
+        assert_eq!(rec.points.len(), 3);
+        let p = &rec.points[0];
+        assert_eq!(p.timestamp, UNIX_EPOCH + Duration::from_secs(1));
+        assert_eq!(p.value, WrappedMeasurementValue::F64(12.5));
+        assert_eq!(p.resource, Resource::CpuPackage { id: 0 });
+        assert_eq!(p.consumer, ResourceConsumer::LocalMachine);
+        assert_eq!(
+            p.attributes,
+            vec![(String::from("domain"), AttributeValue::String(String::from("package")))]
+        );
+
+        let p = &rec.points[1];
+        assert_eq!(p.timestamp, UNIX_EPOCH + Duration::from_millis(1500));
+        assert_eq!(p.consumer, ResourceConsumer::Process { pid: 42 });
+        assert_eq!(
+            p.attributes,
+            vec![
+                (String::from("job_id"), AttributeValue::U64(7)),
+                (String::from("name"), AttributeValue::String(String::from("a,b"))),
+            ]
+        );
+
+        // integer value of a F64 metric
+        assert_eq!(rec.points[2].value, WrappedMeasurementValue::F64(13.0));
+        Ok(())
+    }
+
+    #[test]
+    fn read_invalid_csv() {
+        let mut rec = Recording::default();
+        let missing_column = "metric;timestamp;value\na;1970-01-01T00:00:00Z;1\n";
+        assert!(read_str(missing_column, &settings(), &mut rec).is_err());
+
+        let wrong_length = "\
+metric;timestamp;value;resource_kind;resource_id;consumer_kind;consumer_id
+a;1970-01-01T00:00:00Z;1;local_machine;
+";
+        let err = read_str(wrong_length, &settings(), &mut rec).unwrap_err();
+        assert!(err.to_string().contains("line 2"), "{err}");
+    }
+}
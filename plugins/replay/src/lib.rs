@@ -0,0 +1,270 @@
+mod columnar;
+mod csv;
+mod record;
+mod replay;
+
+use std::path::{Path, PathBuf};
+
+use alumet::{
+    metrics::RawMetricId,
+    pipeline::control::request,
+    plugin::{
+        AlumetPluginStart, ConfigTable,
+        rust::{AlumetPlugin, deserialize_config, serialize_config},
+    },
+};
+use anyhow::{Context, anyhow};
+use serde::{Deserialize, Serialize};
+
+use csv::CsvSettings;
+use record::Recording;
+pub use replay::Pace;
+use replay::ReplaySettings;
+
+pub struct ReplayPlugin {
+    config: Option<Config>,
+}
+
+impl AlumetPlugin for ReplayPlugin {
+    fn name() -> &'static str {
+        "replay"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(ReplayPlugin { config: Some(config) }))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let config = self.config.take().unwrap();
+        if config.files.is_empty() {
+            return Err(anyhow!("no file to replay, please set `files`"));
+        }
+        if !(config.speed.is_finite() && config.speed > 0.0) {
+            return Err(anyhow!("speed must be a positive number"));
+        }
+        if config.batch_size == 0 {
+            return Err(anyhow!("batch_size must be greater than zero"));
+        }
+
+        // Load the files and register their metrics, so that the other plugins can find them.
+        let mut recording = Recording::default();
+        let csv_settings = CsvSettings {
+            delimiter: config.csv.delimiter,
+            late_delimiter: config.csv.late_delimiter,
+            unit_in_metric_name: config.csv.unit_in_metric_name,
+        };
+        for path in &config.files {
+            let format = match config.format {
+                FileFormat::Auto => FileFormat::detect(path)?,
+                f => f,
+            };
+            let n_points = recording.points.len();
+            match format {
+                FileFormat::Csv => csv::read_file(path, &csv_settings, &mut recording)?,
+                FileFormat::Parquet => columnar::read_parquet(path, &mut recording)?,
+                FileFormat::ArrowIpc => columnar::read_arrow_ipc(path, &mut recording)?,
+                FileFormat::Auto => unreachable!(),
+            }
+            log::info!(
+                "Loaded {} measurements from {path:?}",
+                recording.points.len() - n_points
+            );
+        }
+        recording.finish();
+
+        let mut metric_ids: Vec<RawMetricId> = Vec::with_capacity(recording.metrics.len());
+        for m in &recording.metrics {
+            let id = alumet
+                .create_metric_untyped(&m.name, m.value_type.clone(), m.unit.clone(), &m.description)
+                .with_context(|| format!("failed to register the metric {}", m.name))?;
+            metric_ids.push(id);
+        }
+
+        let settings = ReplaySettings {
+            pace: config.pace,
+            speed: config.speed,
+            batch_size: config.batch_size,
+            rebase_timestamps: config.rebase_timestamps,
+        };
+        let shutdown_when_done = config.shutdown_when_done;
+        let points = recording.points;
+
+        // The source is created after the pipeline startup, because it needs a control handle
+        // to shut the pipeline down at the end of the replay.
+        alumet.on_pipeline_start(move |ctx| {
+            let control_handle = ctx.pipeline_control();
+            let shutdown_handle = control_handle.clone();
+            let create_source =
+                request::create_one().add_autonomous_source_builder("replay", move |_ctx, cancel_token, tx| {
+                    let source = Box::pin(async move {
+                        let n_points = points.len();
+                        replay::replay(points, metric_ids, settings, cancel_token, tx).await?;
+                        log::info!("Replay finished: {n_points} measurements.");
+                        if shutdown_when_done {
+                            shutdown_handle.shutdown();
+                        }
+                        Ok(())
+                    });
+                    Ok(source)
+                });
+            ctx.block_on(control_handle.send_wait(create_source, None))
+                .context("failed to add the replay source to the pipeline")
+        });
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Format of the recorded files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileFormat {
+    /// Detect the format from the extension of each file.
+    Auto,
+    /// CSV file written by the `csv` output, possibly compressed (`.csv.gz` or `.csv.zst`).
+    Csv,
+    /// Parquet file written by the `parquet` output.
+    Parquet,
+    /// Arrow IPC file written by the `parquet` output.
+    ArrowIpc,
+}
+
+impl FileFormat {
+    fn detect(path: &Path) -> anyhow::Result<Self> {
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        let name = name
+            .strip_suffix(".gz")
+            .or_else(|| name.strip_suffix(".zst"))
+            .unwrap_or(name);
+        match Path::new(name).extension().and_then(|ext| ext.to_str()) {
+            Some("csv") => Ok(FileFormat::Csv),
+            Some("parquet") => Ok(FileFormat::Parquet),
+            Some("arrow" | "ipc" | "feather") => Ok(FileFormat::ArrowIpc),
+            _ => Err(anyhow!(
+                "cannot detect the format of {path:?} from its extension, please set `format`"
+            )),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Files to replay, in any order: the measurements of all the files are sorted by timestamp.
+    pub files: Vec<PathBuf>,
+    /// Format of the files: `auto`, `csv`, `parquet` or `arrow_ipc`.
+    pub format: FileFormat,
+    /// `fast` to replay the measurements as fast as possible,
+    /// `original` to keep the delays between the measurements.
+    pub pace: Pace,
+    /// Speed factor of the `original` pace. For instance, `10.0` replays one hour of measurements in six minutes.
+    pub speed: f64,
+    /// Maximum number of measurements per buffer.
+    pub batch_size: usize,
+    /// Shift the timestamps so that the first measurement is timestamped with the start of the replay.
+    /// If false, the measurements keep their original timestamps.
+    pub rebase_timestamps: bool,
+    /// Do we stop Alumet when all the measurements have been replayed?
+    pub shutdown_when_done: bool,
+    /// Options of the CSV files.
+    #[serde(default)]
+    pub csv: CsvConfig,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CsvConfig {
+    pub delimiter: char,
+    /// Delimiter of the `__late_attributes` column.
+    pub late_delimiter: char,
+    /// Does the metric column end with the unit, like `rapl_consumed_energy_J`?
+    /// This is the case when the `csv` output has `append_unit_to_metric_name = true`.
+    pub unit_in_metric_name: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            files: vec![PathBuf::from("alumet-output.csv")],
+            format: FileFormat::Auto,
+            pace: Pace::Fast,
+            speed: 1.0,
+            batch_size: 1000,
+            rebase_timestamps: false,
+            shutdown_when_done: true,
+            csv: CsvConfig::default(),
+        }
+    }
+}
+
+impl Default for CsvConfig {
+    fn default() -> Self {
+        Self {
+            delimiter: ';',
+            late_delimiter: ',',
+            unit_in_metric_name: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{Config, FileFormat, Pace};
+
+    #[test]
+    fn detect_format() {
+        let detect = |p: &str| FileFormat::detect(Path::new(p)).ok();
+        assert_eq!(detect("out.csv"), Some(FileFormat::Csv));
+        assert_eq!(detect("dir/out-20250101T120000Z.csv.gz"), Some(FileFormat::Csv));
+        assert_eq!(detect("out.csv.zst"), Some(FileFormat::Csv));
+        assert_eq!(detect("out.parquet"), Some(FileFormat::Parquet));
+        assert_eq!(detect("out.arrow"), Some(FileFormat::ArrowIpc));
+        assert_eq!(detect("out.txt"), None);
+        assert_eq!(detect("out"), None);
+    }
+
+    #[test]
+    fn parse_config() {
+        let config: Config = toml::from_str(
+            r#"
+            files = ["a.csv", "b.parquet"]
+            format = "auto"
+            pace = "original"
+            speed = 10.0
+            batch_size = 100
+            rebase_timestamps = true
+            shutdown_when_done = false
+
+            [csv]
+            delimiter = ","
+            late_delimiter = "|"
+            unit_in_metric_name = false
+        "#,
+        )
+        .unwrap();
+        assert_eq!(config.files.len(), 2);
+        assert_eq!(config.pace, Pace::Original);
+        assert_eq!(config.speed, 10.0);
+        assert_eq!(config.csv.delimiter, ',');
+        assert!(!config.csv.unit_in_metric_name);
+
+        let default = toml::to_string(&Config::default()).unwrap();
+        let config: Config = toml::from_str(&default).unwrap();
+        assert_eq!(config.pace, Pace::Fast);
+        assert!(config.shutdown_when_done);
+    }
+}
@@ -0,0 +1,307 @@
+//! Measurements loaded from recorded files, before their replay.
+
+use std::time::SystemTime;
+
+use alumet::{
+    measurement::{AttributeValue, WrappedMeasurementType, WrappedMeasurementValue},
+    resources::{Resource, ResourceConsumer},
+    units::{PrefixedUnit, Unit, UnitPrefix},
+};
+use rustc_hash::FxHashMap;
+
+/// A metric found in the recorded files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedMetric {
+    pub name: String,
+    pub unit: PrefixedUnit,
+    pub value_type: WrappedMeasurementType,
+    pub description: String,
+}
+
+/// A measurement point found in the recorded files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedPoint {
+    pub timestamp: SystemTime,
+    /// Index of the metric in [`Recording::metrics`].
+    pub metric: usize,
+    pub value: WrappedMeasurementValue,
+    pub resource: Resource,
+    pub consumer: ResourceConsumer,
+    pub attributes: Vec<(String, AttributeValue)>,
+}
+
+/// The content of one or several recorded files.
+#[derive(Debug, Default)]
+pub struct Recording {
+    pub metrics: Vec<RecordedMetric>,
+    pub points: Vec<RecordedPoint>,
+    /// Index of each metric, by name and unit.
+    metric_indices: FxHashMap<(String, String), usize>,
+}
+
+impl Recording {
+    /// Returns the index of the metric with the given name and unit, adding it to the recording if needed.
+    ///
+    /// The type of the metric is `U64` until a floating-point value is pushed (see [`Recording::finish`]).
+    pub fn metric_index(&mut self, name: &str, unit: PrefixedUnit, description: Option<&str>) -> usize {
+        let key = (name.to_owned(), unit.unique_name());
+        *self.metric_indices.entry(key).or_insert_with(|| {
+            self.metrics.push(RecordedMetric {
+                name: name.to_owned(),
+                unit,
+                value_type: WrappedMeasurementType::U64,
+                description: description.unwrap_or_default().to_owned(),
+            });
+            self.metrics.len() - 1
+        })
+    }
+
+    pub fn push(&mut self, point: RecordedPoint) {
+        if let WrappedMeasurementValue::F64(_) = point.value {
+            self.metrics[point.metric].value_type = WrappedMeasurementType::F64;
+        }
+        self.points.push(point);
+    }
+
+    /// Sorts the points by timestamp and makes their values match the type of their metric.
+    ///
+    /// The sort is stable: the points that have the same timestamp stay in the order of the files.
+    pub fn finish(&mut self) {
+        for p in &mut self.points {
+            if let (WrappedMeasurementValue::U64(v), WrappedMeasurementType::F64) =
+                (&p.value, &self.metrics[p.metric].value_type)
+            {
+                p.value = WrappedMeasurementValue::F64(*v as f64);
+            }
+        }
+        self.points.sort_by_key(|p| p.timestamp);
+    }
+}
+
+/// Units that can be recognized, with any prefix.
+const UNITS: [Unit; 12] = [
+    Unit::Unity,
+    Unit::Second,
+    Unit::Watt,
+    Unit::Joule,
+    Unit::Volt,
+    Unit::Ampere,
+    Unit::Hertz,
+    Unit::DegreeCelsius,
+    Unit::DegreeFahrenheit,
+    Unit::WattHour,
+    Unit::Byte,
+    Unit::Percent,
+];
+
+const PREFIXES: [UnitPrefix; 7] = [
+    UnitPrefix::Plain,
+    UnitPrefix::Nano,
+    UnitPrefix::Micro,
+    UnitPrefix::Milli,
+    UnitPrefix::Kilo,
+    UnitPrefix::Mega,
+    UnitPrefix::Giga,
+];
+
+/// Parses a unit from its unique name (e.g. `milliJ`) or its display name (e.g. `mJ`).
+///
+/// Returns `None` if the unit is not a known (prefixed) unit.
+pub fn parse_unit(s: &str) -> Option<PrefixedUnit> {
+    if s.is_empty() {
+        return None;
+    }
+    for base_unit in UNITS {
+        for prefix in PREFIXES {
+            if base_unit == Unit::Unity && prefix != UnitPrefix::Plain {
+                continue;
+            }
+            let unit = PrefixedUnit {
+                base_unit: base_unit.clone(),
+                prefix,
+            };
+            if unit.unique_name() == s || unit.display_name() == s {
+                return Some(unit);
+            }
+        }
+    }
+    None
+}
+
+/// Parses a unit, and falls back to a custom unit if it is not known.
+pub fn parse_unit_or_custom(s: &str) -> PrefixedUnit {
+    parse_unit(s).unwrap_or_else(|| {
+        PrefixedUnit::from(Unit::Custom {
+            unique_name: s.to_owned(),
+            display_name: s.to_owned(),
+        })
+    })
+}
+
+/// Splits a metric name that ends with its unit, like `rapl_consumed_energy_J`.
+///
+/// If the name does not end with a known unit, the whole name is returned, with the unit `Unity`.
+pub fn split_metric_unit(s: &str) -> (&str, PrefixedUnit) {
+    if let Some((name, unit)) = s.rsplit_once('_')
+        && let Some(unit) = parse_unit(unit)
+    {
+        return (name, unit);
+    }
+    (s, PrefixedUnit::from(Unit::Unity))
+}
+
+/// Parses an attribute value, which has been written as a string.
+///
+/// Numbers and booleans are recognized only if they are written exactly as Alumet formats them,
+/// so that identifiers like `007` stay strings.
+pub fn parse_attribute(s: &str) -> AttributeValue {
+    if let Ok(v) = s.parse::<u64>()
+        && v.to_string() == s
+    {
+        return AttributeValue::U64(v);
+    }
+    if let Ok(v) = s.parse::<f64>()
+        && v.is_finite()
+        && v.to_string() == s
+    {
+        return AttributeValue::F64(v);
+    }
+    match s {
+        "true" => AttributeValue::Bool(true),
+        "false" => AttributeValue::Bool(false),
+        _ => AttributeValue::String(s.to_owned()),
+    }
+}
+
+/// Parses a consumer from its kind and id, as written by the outputs.
+///
+/// Unlike [`ResourceConsumer::parse`], this recognizes the `local_machine` kind.
+pub fn parse_consumer(kind: String, id: String) -> anyhow::Result<ResourceConsumer> {
+    if kind == "local_machine" {
+        return Ok(ResourceConsumer::LocalMachine);
+    }
+    Ok(ResourceConsumer::parse(kind, id)?)
+}
+
+/// Parses a measured value: an integer if possible, a floating-point number otherwise.
+pub fn parse_value(s: &str) -> anyhow::Result<WrappedMeasurementValue> {
+    if let Ok(v) = s.parse::<u64>() {
+        return Ok(WrappedMeasurementValue::U64(v));
+    }
+    let v = s
+        .parse::<f64>()
+        .map_err(|_| anyhow::anyhow!("invalid measurement value: {s:?}"))?;
+    Ok(WrappedMeasurementValue::F64(v))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use alumet::{
+        measurement::{AttributeValue, WrappedMeasurementType, WrappedMeasurementValue},
+        resources::{Resource, ResourceConsumer},
+        units::{PrefixedUnit, Unit},
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::{
+        RecordedPoint, Recording, parse_attribute, parse_consumer, parse_unit, parse_value, split_metric_unit,
+    };
+
+    #[test]
+    fn units() {
+        assert_eq!(parse_unit("J"), Some(PrefixedUnit::from(Unit::Joule)));
+        assert_eq!(parse_unit("mJ"), Some(PrefixedUnit::milli(Unit::Joule)));
+        assert_eq!(parse_unit("milliJ"), Some(PrefixedUnit::milli(Unit::Joule)));
+        assert_eq!(parse_unit("nanos"), Some(PrefixedUnit::nano(Unit::Second)));
+        assert_eq!(parse_unit("1"), Some(PrefixedUnit::from(Unit::Unity)));
+        assert_eq!(parse_unit("energy"), None);
+        assert_eq!(parse_unit(""), None);
+    }
+
+    #[test]
+    fn metric_names() {
+        assert_eq!(
+            split_metric_unit("rapl_consumed_energy_J"),
+            ("rapl_consumed_energy", PrefixedUnit::from(Unit::Joule))
+        );
+        assert_eq!(
+            split_metric_unit("cpu_time_delta_ms"),
+            ("cpu_time_delta", PrefixedUnit::milli(Unit::Second))
+        );
+        assert_eq!(
+            split_metric_unit("kernel_new_forks"),
+            ("kernel_new_forks", PrefixedUnit::from(Unit::Unity))
+        );
+        assert_eq!(split_metric_unit("count_1"), ("count", PrefixedUnit::from(Unit::Unity)));
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(parse_value("12").unwrap(), WrappedMeasurementValue::U64(12));
+        assert_eq!(parse_value("12.5").unwrap(), WrappedMeasurementValue::F64(12.5));
+        assert_eq!(parse_value("-1").unwrap(), WrappedMeasurementValue::F64(-1.0));
+        assert!(parse_value("abc").is_err());
+
+        assert_eq!(parse_attribute("42"), AttributeValue::U64(42));
+        assert_eq!(parse_attribute("0.5"), AttributeValue::F64(0.5));
+        assert_eq!(parse_attribute("true"), AttributeValue::Bool(true));
+        assert_eq!(parse_attribute("007"), AttributeValue::String(String::from("007")));
+        assert_eq!(
+            parse_attribute("package"),
+            AttributeValue::String(String::from("package"))
+        );
+    }
+
+    #[test]
+    fn consumers() {
+        let parse = |kind: &str, id: &str| parse_consumer(kind.to_owned(), id.to_owned()).unwrap();
+        assert_eq!(parse("local_machine", ""), ResourceConsumer::LocalMachine);
+        assert_eq!(parse("process", "42"), ResourceConsumer::Process { pid: 42 });
+        assert_eq!(
+            parse("job", "7"),
+            ResourceConsumer::Custom {
+                kind: "job".into(),
+                id: "7".into()
+            }
+        );
+        assert!(parse_consumer(String::from("process"), String::from("abc")).is_err());
+    }
+
+    #[test]
+    fn recording() {
+        let mut rec = Recording::default();
+        let energy = rec.metric_index("energy", PrefixedUnit::from(Unit::Joule), None);
+        let count = rec.metric_index("count", PrefixedUnit::from(Unit::Unity), None);
+        assert_eq!(
+            rec.metric_index("energy", PrefixedUnit::from(Unit::Joule), None),
+            energy
+        );
+
+        let point = |t: u64, metric: usize, value: WrappedMeasurementValue| RecordedPoint {
+            timestamp: UNIX_EPOCH + Duration::from_secs(t),
+            metric,
+            value,
+            resource: Resource::LocalMachine,
+            consumer: ResourceConsumer::LocalMachine,
+            attributes: Vec::new(),
+        };
+        rec.push(point(2, energy, WrappedMeasurementValue::U64(10)));
+        rec.push(point(1, energy, WrappedMeasurementValue::F64(1.5)));
+        rec.push(point(1, count, WrappedMeasurementValue::U64(3)));
+        rec.finish();
+
+        assert_eq!(rec.metrics[energy].value_type, WrappedMeasurementType::F64);
+        assert_eq!(rec.metrics[count].value_type, WrappedMeasurementType::U64);
+        let values: Vec<_> = rec.points.iter().map(|p| p.value.clone()).collect();
+        assert_eq!(
+            values,
+            vec![
+                WrappedMeasurementValue::F64(1.5),
+                WrappedMeasurementValue::U64(3),
+                WrappedMeasurementValue::F64(10.0),
+            ]
+        );
+    }
+}
@@ -0,0 +1,220 @@
+//! Emission of the recorded measurements.
+
+use std::{
+    ops::Range,
+    time::{Duration, SystemTime},
+};
+
+use alumet::{
+    measurement::{MeasurementBuffer, MeasurementPoint, Timestamp},
+    metrics::RawMetricId,
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tokio::{sync::mpsc::Sender, time::Instant};
+use tokio_util::sync::CancellationToken;
+
+use crate::record::RecordedPoint;
+
+/// How fast the measurements are replayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pace {
+    /// As fast as possible.
+    Fast,
+    /// With the same delays as in the recording, divided by the speed factor.
+    Original,
+}
+
+pub struct ReplaySettings {
+    pub pace: Pace,
+    /// Speed factor of the `Original` pace.
+    pub speed: f64,
+    /// Maximum number of measurements per buffer.
+    pub batch_size: usize,
+    /// Shift the timestamps so that the first measurement is timestamped with the start of the replay.
+    pub rebase_timestamps: bool,
+}
+
+/// A buffer to send: the points in `points`, after `delay` since the beginning of the replay.
+#[derive(Debug, PartialEq)]
+pub struct Step {
+    pub delay: Duration,
+    pub points: Range<usize>,
+}
+
+/// Splits the points (sorted by timestamp) in buffers, and computes when to send each buffer.
+///
+/// With the `Fast` pace, the buffers are full and sent immediately.
+/// With the `Original` pace, a buffer only contains points that have the same timestamp.
+pub fn schedule(points: &[RecordedPoint], settings: &ReplaySettings) -> Vec<Step> {
+    let mut steps = Vec::new();
+    let Some(first) = points.first() else {
+        return steps;
+    };
+    let mut start = 0;
+    while start < points.len() {
+        let max_end = (start + settings.batch_size).min(points.len());
+        let (end, delay) = match settings.pace {
+            Pace::Fast => (max_end, Duration::ZERO),
+            Pace::Original => {
+                let t = points[start].timestamp;
+                let len = points[start..max_end].iter().take_while(|p| p.timestamp == t).count();
+                let elapsed = t.duration_since(first.timestamp).unwrap_or_default();
+                (start + len, elapsed.div_f64(settings.speed))
+            }
+        };
+        steps.push(Step {
+            delay,
+            points: start..end,
+        });
+        start = end;
+    }
+    steps
+}
+
+/// Sends the points to the pipeline, according to the schedule.
+///
+/// Stops early if `cancel_token` is cancelled.
+pub async fn replay(
+    points: Vec<RecordedPoint>,
+    metric_ids: Vec<RawMetricId>,
+    settings: ReplaySettings,
+    cancel_token: CancellationToken,
+    tx: Sender<MeasurementBuffer>,
+) -> anyhow::Result<()> {
+    let steps = schedule(&points, &settings);
+    let start = Instant::now();
+    let start_time = SystemTime::now();
+    let time_shift = match points.first() {
+        Some(first) if settings.rebase_timestamps => start_time.duration_since(first.timestamp).ok(),
+        _ => None,
+    };
+
+    for step in steps {
+        if !step.delay.is_zero() {
+            tokio::select! {
+                _ = tokio::time::sleep_until(start + step.delay) => (),
+                _ = cancel_token.cancelled() => break,
+            }
+        } else if cancel_token.is_cancelled() {
+            break;
+        }
+        let mut buffer = MeasurementBuffer::with_capacity(step.points.len());
+        for p in &points[step.points] {
+            let timestamp = match time_shift {
+                Some(shift) => p.timestamp + shift,
+                None => p.timestamp,
+            };
+            let point = MeasurementPoint::new_untyped(
+                Timestamp::from(timestamp),
+                metric_ids[p.metric],
+                p.resource.clone(),
+                p.consumer.clone(),
+                p.value.clone(),
+            )
+            .with_attr_slice(&p.attributes);
+            buffer.push(point);
+        }
+        tx.send(buffer)
+            .await
+            .context("failed to send the measurements to the pipeline")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use alumet::{
+        measurement::WrappedMeasurementValue,
+        resources::{Resource, ResourceConsumer},
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::{Pace, ReplaySettings, Step, schedule};
+    use crate::record::RecordedPoint;
+
+    fn points(timestamps_ms: &[u64]) -> Vec<RecordedPoint> {
+        timestamps_ms
+            .iter()
+            .map(|t| RecordedPoint {
+                timestamp: UNIX_EPOCH + Duration::from_millis(*t),
+                metric: 0,
+                value: WrappedMeasurementValue::U64(*t),
+                resource: Resource::LocalMachine,
+                consumer: ResourceConsumer::LocalMachine,
+                attributes: Vec::new(),
+            })
+            .collect()
+    }
+
+    fn settings(pace: Pace, speed: f64, batch_size: usize) -> ReplaySettings {
+        ReplaySettings {
+            pace,
+            speed,
+            batch_size,
+            rebase_timestamps: false,
+        }
+    }
+
+    #[test]
+    fn schedule_fast() {
+        let points = points(&[1000, 1000, 1500, 2000, 3000]);
+        let steps = schedule(&points, &settings(Pace::Fast, 1.0, 2));
+        assert_eq!(
+            steps,
+            vec![
+                Step {
+                    delay: Duration::ZERO,
+                    points: 0..2
+                },
+                Step {
+                    delay: Duration::ZERO,
+                    points: 2..4
+                },
+                Step {
+                    delay: Duration::ZERO,
+                    points: 4..5
+                },
+            ]
+        );
+        assert!(schedule(&[], &settings(Pace::Fast, 1.0, 2)).is_empty());
+    }
+
+    #[test]
+    fn schedule_original_pace() {
+        let points = points(&[1000, 1000, 1000, 1500, 3000]);
+        let steps = schedule(&points, &settings(Pace::Original, 1.0, 2));
+        assert_eq!(
+            steps,
+            vec![
+                Step {
+                    delay: Duration::ZERO,
+                    points: 0..2
+                },
+                Step {
+                    delay: Duration::ZERO,
+                    points: 2..3
+                },
+                Step {
+                    delay: Duration::from_millis(500),
+                    points: 3..4
+                },
+                Step {
+                    delay: Duration::from_millis(2000),
+                    points: 4..5
+                },
+            ]
+        );
+
+        // accelerated
+        let steps = schedule(&points, &settings(Pace::Original, 4.0, 10));
+        let delays: Vec<_> = steps.iter().map(|s| s.delay).collect();
+        assert_eq!(
+            delays,
+            vec![Duration::ZERO, Duration::from_millis(125), Duration::from_millis(500)]
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use alumet::{
+    agent::{
+        self,
+        plugin::{PluginInfo, PluginSet},
+    },
+    measurement::MeasurementBuffer,
+    pipeline::{
+        Output,
+        elements::{error::WriteError, output::OutputContext},
+    },
+    plugin::{AlumetPluginStart, ConfigTable, PluginMetadata, rust::AlumetPlugin},
+};
+use plugin_replay::{Config, Pace, ReplayPlugin};
+use pretty_assertions::assert_eq;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Measurements received by the output of the `CollectorPlugin`, formatted as strings.
+static RECEIVED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+const RECORDING: &str = "\
+metric;timestamp;value;resource_kind;resource_id;consumer_kind;consumer_id;__late_attributes
+rapl_consumed_energy_J;2025-01-01T12:00:00Z;12.5;cpu_package;0;local_machine;;domain=package
+kernel_new_forks;2025-01-01T12:00:00Z;3;local_machine;;local_machine;;
+rapl_consumed_energy_J;2025-01-01T12:00:01Z;13.5;cpu_package;0;local_machine;;domain=package
+cpu_time_delta_ms;2025-01-01T12:00:02Z;250;local_machine;;process;42;
+";
+
+struct CollectorPlugin;
+
+impl AlumetPlugin for CollectorPlugin {
+    fn name() -> &'static str {
+        "collector"
+    }
+
+    fn version() -> &'static str {
+        "0.0.1"
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(None)
+    }
+
+    fn init(_config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        Ok(Box::new(CollectorPlugin))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        alumet.add_blocking_output("out", Box::new(CollectorOutput))?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+struct CollectorOutput;
+
+impl Output for CollectorOutput {
+    fn write(&mut self, measurements: &MeasurementBuffer, ctx: &OutputContext) -> Result<(), WriteError> {
+        let mut received = RECEIVED.lock().unwrap();
+        for m in measurements {
+            let metric = ctx.metrics.by_id(&m.metric).unwrap();
+            let attributes: Vec<String> = m.attributes().map(|(k, v)| format!("{k}={v}")).collect();
+            let timestamp = SystemTime::from(m.timestamp).duration_since(UNIX_EPOCH).unwrap();
+            received.push(format!(
+                "{} [{}, {:?}] {} {:?} {}/{} {}",
+                metric.name,
+                metric.unit.unique_name(),
+                metric.value_type,
+                timestamp.as_secs(),
+                m.value,
+                m.consumer.kind(),
+                m.consumer.id_display(),
+                attributes.join(",")
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn config_to_toml_table(config: &Config) -> toml::Table {
+    toml::Value::try_from(config).unwrap().as_table().unwrap().clone()
+}
+
+#[test]
+fn replay_csv_at_original_pace() {
+    let _ = env_logger::Builder::from_default_env().try_init();
+
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("recording.csv");
+    std::fs::write(&path, RECORDING).unwrap();
+
+    let config = Config {
+        files: vec![path],
+        pace: Pace::Original,
+        speed: 10.0,
+        ..Config::default()
+    };
+
+    let mut plugins = PluginSet::new();
+    plugins.add_plugin(PluginInfo {
+        metadata: PluginMetadata::from_static::<ReplayPlugin>(),
+        enabled: true,
+        config: Some(config_to_toml_table(&config)),
+    });
+    plugins.add_plugin(PluginInfo {
+        metadata: PluginMetadata::from_static::<CollectorPlugin>(),
+        enabled: true,
+        config: None,
+    });
+
+    let start = Instant::now();
+    let agent = agent::Builder::new(plugins).build_and_start().unwrap();
+    // The replay stops the agent when it is done.
+    agent.wait_for_shutdown(TIMEOUT).unwrap();
+
+    // two seconds of measurements, replayed 10 times faster
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    let received = RECEIVED.lock().unwrap();
+    let expected: Vec<String> = vec![
+        "rapl_consumed_energy [J, F64] 1735732800 F64(12.5) local_machine/ domain=package",
+        "kernel_new_forks [1, U64] 1735732800 U64(3) local_machine/ ",
+        "rapl_consumed_energy [J, F64] 1735732801 F64(13.5) local_machine/ domain=package",
+        "cpu_time_delta [millis, U64] 1735732802 U64(250) process/42 ",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect();
+    assert_eq!(*received, expected);
+}
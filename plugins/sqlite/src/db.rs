@@ -0,0 +1,355 @@
+//! Normalized SQLite schema and insertion of the measurements.
+
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use alumet::{
+    measurement::{MeasurementBuffer, WrappedMeasurementValue},
+    metrics::{Metric, RawMetricId, registry::MetricRegistry},
+    resources::{Resource, ResourceConsumer},
+};
+use anyhow::Context;
+use rusqlite::{Connection, Transaction, params, types::Value};
+use rustc_hash::FxHashMap;
+
+/// Tables and views of the database.
+///
+/// Timestamps are stored as a number of nanoseconds since the UNIX epoch.
+/// The `value` column has no type affinity, so that F64 values that happen to be integers stay `REAL`.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS metrics (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    unit TEXT NOT NULL,
+    unit_display_name TEXT NOT NULL,
+    value_type TEXT NOT NULL,
+    description TEXT NOT NULL,
+    UNIQUE (name, unit, value_type)
+);
+CREATE TABLE IF NOT EXISTS resources (
+    id INTEGER PRIMARY KEY,
+    kind TEXT NOT NULL,
+    identifier TEXT NOT NULL,
+    UNIQUE (kind, identifier)
+);
+CREATE TABLE IF NOT EXISTS consumers (
+    id INTEGER PRIMARY KEY,
+    kind TEXT NOT NULL,
+    identifier TEXT NOT NULL,
+    UNIQUE (kind, identifier)
+);
+CREATE TABLE IF NOT EXISTS points (
+    id INTEGER PRIMARY KEY,
+    timestamp INTEGER NOT NULL,
+    metric_id INTEGER NOT NULL REFERENCES metrics (id),
+    resource_id INTEGER NOT NULL REFERENCES resources (id),
+    consumer_id INTEGER NOT NULL REFERENCES consumers (id),
+    value NOT NULL,
+    attributes TEXT
+);
+CREATE INDEX IF NOT EXISTS points_metric_timestamp ON points (metric_id, timestamp);
+CREATE VIEW IF NOT EXISTS points_view AS
+    SELECT
+        p.id,
+        p.timestamp,
+        strftime('%Y-%m-%dT%H:%M:%fZ', p.timestamp / 1e9, 'unixepoch') AS datetime,
+        m.name AS metric,
+        m.unit AS unit,
+        p.value,
+        r.kind AS resource_kind,
+        r.identifier AS resource_id,
+        c.kind AS consumer_kind,
+        c.identifier AS consumer_id,
+        p.attributes
+    FROM points p
+    JOIN metrics m ON p.metric_id = m.id
+    JOIN resources r ON p.resource_id = r.id
+    JOIN consumers c ON p.consumer_id = c.id;
+";
+
+/// Connection to the database, with caches of the rows of the dictionary tables.
+pub struct Database {
+    conn: Connection,
+    /// Row id of each metric, by Alumet metric id.
+    metrics: FxHashMap<RawMetricId, i64>,
+    /// Row id of each resource.
+    resources: DictionaryCache,
+    /// Row id of each consumer.
+    consumers: DictionaryCache,
+}
+
+/// Row ids of a dictionary table, by (kind, identifier).
+///
+/// The rows inserted by a transaction are staged until it is committed:
+/// if the transaction is rolled back, their ids do not exist in the table.
+#[derive(Default)]
+struct DictionaryCache {
+    committed: FxHashMap<(String, String), i64>,
+    staged: FxHashMap<(String, String), i64>,
+}
+
+impl DictionaryCache {
+    fn get(&self, key: &(String, String)) -> Option<i64> {
+        self.committed.get(key).or_else(|| self.staged.get(key)).copied()
+    }
+
+    /// Keeps the staged rows, once their transaction has been committed.
+    fn commit(&mut self) {
+        self.committed.extend(self.staged.drain());
+    }
+
+    /// Forgets the staged rows, whose transaction has been rolled back.
+    fn rollback(&mut self) {
+        self.staged.clear();
+    }
+}
+
+impl Database {
+    /// Opens (or creates) the database file and creates the tables that do not exist yet.
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let conn = Connection::open(path).with_context(|| format!("failed to open the SQLite database {path:?}"))?;
+        Self::init(conn)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> anyhow::Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> anyhow::Result<Self> {
+        conn.execute_batch("PRAGMA foreign_keys = ON; PRAGMA journal_mode = WAL;")?;
+        conn.execute_batch(SCHEMA).context("failed to create the tables")?;
+        Ok(Self {
+            conn,
+            metrics: FxHashMap::default(),
+            resources: DictionaryCache::default(),
+            consumers: DictionaryCache::default(),
+        })
+    }
+
+    /// Inserts the metrics of the registry that have not been inserted yet.
+    pub fn insert_metrics(&mut self, registry: &MetricRegistry) -> anyhow::Result<()> {
+        if self.metrics.len() == registry.len() {
+            return Ok(());
+        }
+        let tx = self.conn.transaction()?;
+        let mut inserted = Vec::new();
+        for (id, metric) in registry.iter() {
+            if !self.metrics.contains_key(id) {
+                inserted.push((*id, upsert_metric(&tx, metric)?));
+            }
+        }
+        tx.commit()?;
+        // only cache the rows once they are committed
+        self.metrics.extend(inserted);
+        Ok(())
+    }
+
+    /// Inserts the measurements in one transaction.
+    pub fn insert_measurements(&mut self, buffer: &MeasurementBuffer, registry: &MetricRegistry) -> anyhow::Result<()> {
+        self.insert_metrics(registry)?;
+
+        // the rows staged by a previous transaction that failed have been rolled back
+        self.resources.rollback();
+        self.consumers.rollback();
+        let tx = self.conn.transaction()?;
+        {
+            let mut insert = tx.prepare_cached(
+                "INSERT INTO points (timestamp, metric_id, resource_id, consumer_id, value, attributes)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            for m in buffer {
+                let metric = *self
+                    .metrics
+                    .get(&m.metric)
+                    .with_context(|| format!("unknown metric {}", m.metric.as_u64()))?;
+                let resource = resource_row(&tx, &mut self.resources, &m.resource)?;
+                let consumer = consumer_row(&tx, &mut self.consumers, &m.consumer)?;
+                let timestamp = timestamp_nanos(SystemTime::from(m.timestamp))?;
+                let value = match m.value {
+                    WrappedMeasurementValue::F64(v) => Value::Real(v),
+                    WrappedMeasurementValue::U64(v) => u64_to_value(v),
+                };
+                let attributes = if m.attributes_len() == 0 {
+                    None
+                } else {
+                    let map: serde_json::Map<String, serde_json::Value> = m
+                        .attributes()
+                        .map(|(k, v)| (k.to_owned(), serde_json::Value::from(v)))
+                        .collect();
+                    Some(serde_json::Value::Object(map).to_string())
+                };
+                insert.execute(params![timestamp, metric, resource, consumer, value, attributes])?;
+            }
+        }
+        tx.commit()?;
+        self.resources.commit();
+        self.consumers.commit();
+        Ok(())
+    }
+}
+
+fn upsert_metric(tx: &Transaction, metric: &Metric) -> rusqlite::Result<i64> {
+    let mut stmt = tx.prepare_cached(
+        "INSERT INTO metrics (name, unit, unit_display_name, value_type, description)
+        VALUES (?1, ?2, ?3, ?4, ?5)
+        ON CONFLICT (name, unit, value_type) DO UPDATE SET description = excluded.description
+        RETURNING id",
+    )?;
+    stmt.query_row(
+        params![
+            metric.name,
+            metric.unit.unique_name(),
+            metric.unit.display_name(),
+            metric.value_type.to_string(),
+            metric.description
+        ],
+        |row| row.get(0),
+    )
+}
+
+/// Returns the row id of an entry of a dictionary table (`resources` or `consumers`), inserting it if needed.
+fn dictionary_row(
+    tx: &Transaction,
+    table: &str,
+    cache: &mut DictionaryCache,
+    kind: &str,
+    identifier: String,
+) -> rusqlite::Result<i64> {
+    let key = (kind.to_owned(), identifier);
+    if let Some(row) = cache.get(&key) {
+        return Ok(row);
+    }
+    // The no-op update ensures that RETURNING gives the id of the existing row.
+    let mut stmt = tx.prepare_cached(&format!(
+        "INSERT INTO {table} (kind, identifier) VALUES (?1, ?2)
+        ON CONFLICT (kind, identifier) DO UPDATE SET kind = excluded.kind
+        RETURNING id"
+    ))?;
+    let row = stmt.query_row(params![key.0, key.1], |row| row.get(0))?;
+    cache.staged.insert(key, row);
+    Ok(row)
+}
+
+fn resource_row(tx: &Transaction, cache: &mut DictionaryCache, resource: &Resource) -> rusqlite::Result<i64> {
+    dictionary_row(
+        tx,
+        "resources",
+        cache,
+        resource.kind(),
+        resource.id_display().to_string(),
+    )
+}
+
+fn consumer_row(tx: &Transaction, cache: &mut DictionaryCache, consumer: &ResourceConsumer) -> rusqlite::Result<i64> {
+    dictionary_row(
+        tx,
+        "consumers",
+        cache,
+        consumer.kind(),
+        consumer.id_display().to_string(),
+    )
+}
+
+fn timestamp_nanos(t: SystemTime) -> anyhow::Result<i64> {
+    let nanos = t
+        .duration_since(UNIX_EPOCH)
+        .context("timestamp before the UNIX epoch")?;
+    i64::try_from(nanos.as_nanos()).context("timestamp too large")
+}
+
+/// SQLite has no unsigned integers: use an `INTEGER` if possible, a `REAL` otherwise.
+fn u64_to_value(v: u64) -> Value {
+    match i64::try_from(v) {
+        Ok(v) => Value::Integer(v),
+        Err(_) => Value::Real(v as f64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alumet::{
+        measurement::WrappedMeasurementType,
+        metrics::Metric,
+        resources::{Resource, ResourceConsumer},
+        units::Unit,
+    };
+    use pretty_assertions::assert_eq;
+    use rusqlite::types::Value;
+
+    use super::{Database, DictionaryCache, consumer_row, resource_row, u64_to_value, upsert_metric};
+
+    fn count_rows(db: &Database, table: &str) -> i64 {
+        db.conn
+            .query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn dictionary_tables() -> anyhow::Result<()> {
+        let mut db = Database::open_in_memory()?;
+        let tx = db.conn.transaction()?;
+
+        let metric = Metric {
+            name: String::from("test_metric"),
+            description: String::from("test"),
+            value_type: WrappedMeasurementType::U64,
+            unit: Unit::Watt.into(),
+        };
+        let m1 = upsert_metric(&tx, &metric)?;
+        let m2 = upsert_metric(&tx, &metric)?;
+        assert_eq!(m1, m2);
+
+        let mut resources = DictionaryCache::default();
+        let r1 = resource_row(&tx, &mut resources, &Resource::CpuPackage { id: 0 })?;
+        let r2 = resource_row(&tx, &mut resources, &Resource::CpuPackage { id: 1 })?;
+        assert_ne!(r1, r2);
+        // not in the cache, but in the table
+        let r3 = resource_row(&tx, &mut Default::default(), &Resource::CpuPackage { id: 0 })?;
+        assert_eq!(r1, r3);
+
+        let mut consumers = DictionaryCache::default();
+        let c1 = consumer_row(&tx, &mut consumers, &ResourceConsumer::Process { pid: 42 })?;
+        let c2 = consumer_row(&tx, &mut consumers, &ResourceConsumer::Process { pid: 42 })?;
+        assert_eq!(c1, c2);
+        tx.commit()?;
+
+        assert_eq!(count_rows(&db, "metrics"), 1);
+        assert_eq!(count_rows(&db, "resources"), 2);
+        assert_eq!(count_rows(&db, "consumers"), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn rollback() -> anyhow::Result<()> {
+        let mut db = Database::open_in_memory()?;
+        let mut resources = DictionaryCache::default();
+
+        let tx = db.conn.transaction()?;
+        let r1 = resource_row(&tx, &mut resources, &Resource::CpuPackage { id: 0 })?;
+        drop(tx);
+        resources.rollback();
+
+        // the row of the rolled back transaction must not be reused
+        let tx = db.conn.transaction()?;
+        resource_row(&tx, &mut resources, &Resource::CpuPackage { id: 1 })?;
+        let r2 = resource_row(&tx, &mut resources, &Resource::CpuPackage { id: 0 })?;
+        tx.commit()?;
+        resources.commit();
+        assert_ne!(r1, r2);
+        assert_eq!(
+            resources.get(&(String::from("cpu_package"), String::from("0"))),
+            Some(r2)
+        );
+        assert_eq!(count_rows(&db, "resources"), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(u64_to_value(42), Value::Integer(42));
+        assert_eq!(u64_to_value(u64::MAX), Value::Real(u64::MAX as f64));
+    }
+}
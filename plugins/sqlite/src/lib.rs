@@ -0,0 +1,106 @@
+mod db;
+
+use std::path::{Path, PathBuf};
+
+use alumet::{
+    measurement::MeasurementBuffer,
+    pipeline::{
+        Output,
+        elements::{error::WriteError, output::OutputContext},
+    },
+    plugin::{
+        AlumetPluginStart, ConfigTable,
+        rust::{AlumetPlugin, deserialize_config, serialize_config},
+    },
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use db::Database;
+
+pub struct SqlitePlugin {
+    config: Option<Config>,
+}
+
+impl AlumetPlugin for SqlitePlugin {
+    fn name() -> &'static str {
+        "sqlite"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(SqlitePlugin { config: Some(config) }))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let config = self.config.take().unwrap();
+        let path = &config.database_path;
+        if config.overwrite && path.exists() {
+            log::info!("Removing the existing database {path:?}");
+            remove_database(path)?;
+        }
+        let db = Database::open(path)?;
+        alumet.add_blocking_output("out", Box::new(SqliteOutput { db }))?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Removes the database file, and its write-ahead log if any.
+fn remove_database(path: &Path) -> anyhow::Result<()> {
+    std::fs::remove_file(path).with_context(|| format!("failed to remove {path:?}"))?;
+    for suffix in ["-wal", "-shm"] {
+        let mut file = path.as_os_str().to_owned();
+        file.push(suffix);
+        let file = PathBuf::from(file);
+        if file.exists() {
+            std::fs::remove_file(&file).with_context(|| format!("failed to remove {file:?}"))?;
+        }
+    }
+    Ok(())
+}
+
+struct SqliteOutput {
+    db: Database,
+}
+
+impl Output for SqliteOutput {
+    fn write(&mut self, measurements: &MeasurementBuffer, ctx: &OutputContext) -> Result<(), WriteError> {
+        if !measurements.is_empty() {
+            self.db
+                .insert_measurements(measurements, ctx.metrics)
+                .context("failed to insert the measurements in the SQLite database")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Absolute or relative path to the database file.
+    pub database_path: PathBuf,
+    /// Do we delete the existing database on startup?
+    /// If false, the measurements are appended to the existing database.
+    pub overwrite: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            database_path: PathBuf::from("alumet-output.sqlite"),
+            overwrite: false,
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use alumet::{
+    agent::{
+        self,
+        plugin::{PluginInfo, PluginSet},
+    },
+    measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+    pipeline::naming::OutputName,
+    plugin::PluginMetadata,
+    resources::{Resource, ResourceConsumer},
+    test::RuntimeExpectations,
+    units::Unit,
+};
+use plugin_sqlite::{Config, SqlitePlugin};
+use pretty_assertions::assert_eq;
+use rusqlite::Connection;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn config_to_toml_table(config: &Config) -> toml::Table {
+    toml::Value::try_from(config).unwrap().as_table().unwrap().clone()
+}
+
+#[test]
+fn sqlite_output() {
+    let _ = env_logger::Builder::from_default_env().try_init();
+
+    // Prepare the plugin
+
+    let tmp = tempfile::tempdir().unwrap();
+    let db_path = tmp.path().join("alumet-output.sqlite");
+    let result_path = db_path.clone();
+
+    let config = Config {
+        database_path: db_path,
+        ..Config::default()
+    };
+
+    let mut plugins = PluginSet::new();
+    plugins.add_plugin(PluginInfo {
+        metadata: PluginMetadata::from_static::<SqlitePlugin>(),
+        enabled: true,
+        config: Some(config_to_toml_table(&config)),
+    });
+
+    // Prepare the scenario
+
+    let output = OutputName::from_str("sqlite", "out");
+    let runtime_expectations = RuntimeExpectations::new()
+        .create_metric::<u64>("test_metric_u64", Unit::Joule)
+        .create_metric::<f64>("test_metric_f64", Unit::Watt)
+        .test_output(
+            output,
+            |ctx| {
+                let metric_u64 = ctx.metrics().by_name("test_metric_u64").unwrap().0;
+                let metric_f64 = ctx.metrics().by_name("test_metric_f64").unwrap().0;
+                let p1 = MeasurementPoint::new_untyped(
+                    Timestamp::from(UNIX_EPOCH + Duration::from_secs(1)),
+                    metric_u64,
+                    Resource::CpuPackage { id: 0 },
+                    ResourceConsumer::LocalMachine,
+                    WrappedMeasurementValue::U64(12),
+                )
+                .with_attr("domain", "package");
+                let p2 = MeasurementPoint::new_untyped(
+                    Timestamp::from(UNIX_EPOCH + Duration::from_secs(1)),
+                    metric_f64,
+                    Resource::CpuPackage { id: 0 },
+                    ResourceConsumer::Process { pid: 42 },
+                    WrappedMeasurementValue::F64(2.5),
+                );
+                MeasurementBuffer::from(vec![p1, p2])
+            },
+            move || {
+                let conn = Connection::open(&result_path).unwrap();
+                let mut stmt = conn
+                    .prepare(
+                        "SELECT datetime, metric, unit, value, resource_kind, resource_id, consumer_kind, consumer_id, attributes
+                        FROM points_view ORDER BY id",
+                    )
+                    .unwrap();
+                let rows: Vec<String> = stmt
+                    .query_map([], |row| {
+                        let value: rusqlite::types::Value = row.get(3)?;
+                        Ok(format!(
+                            "{};{};{};{:?};{};{};{};{};{}",
+                            row.get::<_, String>(0)?,
+                            row.get::<_, String>(1)?,
+                            row.get::<_, String>(2)?,
+                            value,
+                            row.get::<_, String>(4)?,
+                            row.get::<_, String>(5)?,
+                            row.get::<_, String>(6)?,
+                            row.get::<_, String>(7)?,
+                            row.get::<_, Option<String>>(8)?.unwrap_or_default(),
+                        ))
+                    })
+                    .unwrap()
+                    .map(|r| r.unwrap())
+                    .collect();
+                assert_eq!(
+                    rows,
+                    vec![
+                        r#"1970-01-01T00:00:01.000Z;test_metric_u64;J;Integer(12);cpu_package;0;local_machine;;{"domain":"package"}"#,
+                        r#"1970-01-01T00:00:01.000Z;test_metric_f64;W;Real(2.5);cpu_package;0;process;42;"#,
+                    ]
+                );
+
+                let resources: i64 = conn
+                    .query_row("SELECT COUNT(*) FROM resources", [], |row| row.get(0))
+                    .unwrap();
+                assert_eq!(resources, 1);
+                let metrics: i64 = conn
+                    .query_row("SELECT COUNT(*) FROM metrics", [], |row| row.get(0))
+                    .unwrap();
+                assert_eq!(metrics, 2);
+            },
+        );
+
+    let agent = agent::Builder::new(plugins)
+        .with_expectations(runtime_expectations)
+        .build_and_start()
+        .unwrap();
+
+    agent.wait_for_shutdown(TIMEOUT).unwrap();
+}